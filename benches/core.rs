@@ -0,0 +1,60 @@
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+
+use cortex::bench::{self, Store};
+
+const SIZES: &[usize] = &[10_000, 100_000];
+
+fn save(c: &mut Criterion) {
+    let store = Store::new().unwrap();
+    let mut i = 0;
+    c.bench_function("save", |b| {
+        b.iter(|| {
+            i += 1;
+            bench::save(&store, i).unwrap()
+        })
+    });
+}
+
+fn recall_and_context(c: &mut Criterion) {
+    let mut recall = c.benchmark_group("recall");
+    let stores: Vec<(usize, Store)> = SIZES
+        .iter()
+        .map(|&n| (n, Store::seeded(n).unwrap()))
+        .collect();
+    for (n, store) in &stores {
+        let mut i = 0;
+        recall.bench_with_input(BenchmarkId::from_parameter(n), store, |b, store| {
+            b.iter(|| {
+                i += 1;
+                bench::recall(store, i).unwrap()
+            })
+        });
+    }
+    recall.finish();
+
+    let mut context = c.benchmark_group("context");
+    for (n, store) in &stores {
+        context.bench_with_input(BenchmarkId::from_parameter(n), store, |b, store| {
+            b.iter(|| bench::context(store).unwrap())
+        });
+    }
+    context.finish();
+}
+
+fn micro_sleep(c: &mut Criterion) {
+    let mut group = c.benchmark_group("micro_sleep");
+    group.sample_size(10);
+    for &n in SIZES {
+        group.bench_function(BenchmarkId::from_parameter(n), |b| {
+            b.iter_batched_ref(
+                || Store::seeded(n).unwrap(),
+                |store| bench::micro_sleep(store).unwrap(),
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, save, recall_and_context, micro_sleep);
+criterion_main!(benches);
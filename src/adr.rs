@@ -0,0 +1,243 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::db;
+use crate::sensitivity::Sensitivity;
+
+/// A parsed Architecture Decision Record file (`NNNN-title.md`).
+pub struct Adr {
+    pub number: u32,
+    pub title: String,
+    pub body: String,
+}
+
+/// Import ADR files as raw `decision` memories, skipping ones already imported or already
+/// holding a raw or consolidated decision (such as ADRs `export` wrote).
+/// Imported memories are tagged `adr` and `adr:NNNN` so repeated imports are no-ops.
+pub fn import(raw_conn: &Connection, cons_conn: &Connection, adr_dir: &Path, session_id: &str) -> Result<usize> {
+    let existing = db::get_memories_by_type(raw_conn, "decision")?;
+    let mut decisions: Vec<String> = existing.iter().map(|m| m.content.clone()).collect();
+    decisions.extend(db::get_all_consolidated(cons_conn)?.into_iter().filter(|c| c.r#type == "decision").map(|c| c.content));
+    let mut imported = 0;
+    for adr in read_adrs(adr_dir)? {
+        let tag = adr_tag(adr.number);
+        let body = [normalize(&adr.body)];
+        if existing.iter().any(|m| m.tags.contains(&tag)) || decisions.iter().any(|d| already_recorded(&body, d)) {
+            continue;
+        }
+        let content = format!("ADR {:04}: {}\n\n{}", adr.number, adr.title, adr.body);
+        let id = db::save_memory(raw_conn, &content, "decision", session_id)?;
+        db::update_memory_tags(raw_conn, id, &["adr".to_string(), tag])?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+/// Export decisions that have no ADR yet as new numbered ADR files, skipping any labeled
+/// more sensitive than `max_sensitivity`.
+/// Raw decisions are matched by their `adr:NNNN` tag; consolidated decisions by their
+/// sources (derived from an ADR-tagged memory) or by content.
+pub fn export(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    adr_dir: &Path,
+    max_sensitivity: Sensitivity,
+) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(adr_dir)?;
+    let mut written = Vec::new();
+
+    let decisions = db::get_memories_by_type(raw_conn, "decision")?;
+    let mut adr_sourced: HashSet<i64> = decisions
+        .iter()
+        .filter(|m| m.tags.iter().any(|t| t.starts_with("adr:")))
+        .map(|m| m.id)
+        .collect();
+    let mut recorded: Vec<String> = read_adrs(adr_dir)?.iter().map(|a| normalize(&a.body)).collect();
+
+    for m in decisions {
+        if m.sensitivity > max_sensitivity
+            || m.tags.iter().any(|t| t.starts_with("adr:"))
+            || already_recorded(&recorded, &m.content)
+        {
+            continue;
+        }
+        let (path, number) = write_adr(adr_dir, &m.content, &m.created_at)?;
+        let mut tags = m.tags.clone();
+        tags.push(adr_tag(number));
+        db::update_memory_tags(raw_conn, m.id, &tags)?;
+        // Consolidated decisions drawn from this one now have an ADR too
+        adr_sourced.insert(m.id);
+        recorded.push(normalize(&m.content));
+        written.push(path);
+    }
+
+    for c in db::get_all_consolidated(cons_conn)? {
+        if c.r#type != "decision"
+            || c.sensitivity > max_sensitivity
+            || c.source_ids.iter().any(|id| adr_sourced.contains(id))
+            || already_recorded(&recorded, &c.content)
+        {
+            continue;
+        }
+        let (path, _) = write_adr(adr_dir, &c.content, &c.created_at)?;
+        recorded.push(normalize(&c.content));
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// Write one decision memory as the next numbered ADR and add its tags (used by `[adr]
+/// auto_export`).
+pub fn export_memory(raw_conn: &Connection, adr_dir: &Path, id: i64, content: &str) -> Result<PathBuf> {
+    std::fs::create_dir_all(adr_dir)?;
+    let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    let (path, number) = write_adr(adr_dir, content, &date)?;
+    let mut tags = db::get_source_memories(raw_conn, &[id])?.into_iter().next().map(|(m, _)| m.tags).unwrap_or_default();
+    for tag in ["adr".to_string(), adr_tag(number)] {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    db::update_memory_tags(raw_conn, id, &tags)?;
+    Ok(path)
+}
+
+pub fn read_adrs(adr_dir: &Path) -> Result<Vec<Adr>> {
+    if !adr_dir.exists() {
+        anyhow::bail!("ADR directory {} does not exist.", adr_dir.display());
+    }
+    let mut adrs = Vec::new();
+    for entry in std::fs::read_dir(adr_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("md") {
+            continue;
+        }
+        let Some(number) = file_number(&path) else { continue };
+        let text = std::fs::read_to_string(&path)?;
+        let (title, body) = split_title(&text);
+        adrs.push(Adr { number, title, body });
+    }
+    adrs.sort_by_key(|a| a.number);
+    Ok(adrs)
+}
+
+fn write_adr(adr_dir: &Path, content: &str, created_at: &str) -> Result<(PathBuf, u32)> {
+    let number = next_number(adr_dir)?;
+    let title = derive_title(content);
+    let date = created_at.get(..10).unwrap_or(created_at);
+    let body = if content.contains("## ") {
+        content.to_string()
+    } else {
+        format!("## Decision\n\n{}", content)
+    };
+    let text = format!("# {}. {}\n\nDate: {}\n\n## Status\n\nAccepted\n\n{}\n", number, title, date, body);
+    let path = adr_dir.join(format!("{:04}-{}.md", number, slugify(&title)));
+    std::fs::write(&path, text)?;
+    Ok((path, number))
+}
+
+fn next_number(adr_dir: &Path) -> Result<u32> {
+    let mut max = 0;
+    for entry in std::fs::read_dir(adr_dir)? {
+        if let Some(n) = file_number(&entry?.path()) {
+            max = max.max(n);
+        }
+    }
+    Ok(max + 1)
+}
+
+/// True if one of the `recorded` ADR bodies (normalized) already contains this decision's text.
+fn already_recorded(recorded: &[String], content: &str) -> bool {
+    let needle = normalize(content);
+    recorded.iter().any(|body| body.contains(&needle))
+}
+
+fn file_number(path: &Path) -> Option<u32> {
+    let stem = path.file_stem()?.to_str()?;
+    let digits: String = stem.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+fn split_title(text: &str) -> (String, String) {
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or("").trim();
+    let title = first.trim_start_matches('#').trim();
+    // Strip a leading "12." number from "# 12. Use tokio"
+    let title = match title.split_once(". ") {
+        Some((n, rest)) if n.chars().all(|c| c.is_ascii_digit()) => rest,
+        _ => title,
+    };
+    (title.to_string(), lines.collect::<Vec<_>>().join("\n").trim().to_string())
+}
+
+fn derive_title(content: &str) -> String {
+    let line = content
+        .lines()
+        .map(|l| l.trim())
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+        .unwrap_or("Decision");
+    let title: String = line.chars().take(60).collect();
+    title.trim_end_matches(['.', ':']).trim().to_string()
+}
+
+fn slugify(title: &str) -> String {
+    let slug: String = title
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    slug.split('-').filter(|s| !s.is_empty()).collect::<Vec<_>>().join("-")
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+fn adr_tag(number: u32) -> String {
+    format!("adr:{:04}", number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_and_imports_each_decision_once() {
+        let dir = std::env::temp_dir().join(format!("cortex-adr-{}", uuid::Uuid::new_v4()));
+        let adr_dir = dir.join("adr");
+        std::fs::create_dir_all(&adr_dir).unwrap();
+        std::fs::write(adr_dir.join("0007-use-postgres.md"), "# 7. Use Postgres\n\nUse Postgres for storage.\n").unwrap();
+        let raw = db::open_raw_db(&dir.join("raw.db")).unwrap();
+        let cons = db::open_consolidated_db(&dir.join("consolidated.db")).unwrap();
+        db::save_memory(&raw, "Use Postgres for storage.", "decision", "s").unwrap();
+        let tokio = db::save_memory(&raw, "Adopt tokio as the async runtime", "decision", "s").unwrap();
+        // Consolidated from a decision that gets its ADR in the same export
+        db::insert_consolidated(&cons, "Adopt tokio for async code", "decision", &[tokio], 0.9).unwrap();
+        db::insert_consolidated(&cons, "Deploy through GitHub Actions", "decision", &[], 0.8).unwrap();
+
+        let written = export(&raw, &cons, &adr_dir, Sensitivity::Internal).unwrap();
+        let names: Vec<String> = written.iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        assert_eq!(names, ["0008-adopt-tokio-as-the-async-runtime.md", "0009-deploy-through-github-actions.md"]);
+        let tags = db::get_memories_by_type(&raw, "decision").unwrap().into_iter().find(|m| m.id == tokio).unwrap().tags;
+        assert!(tags.contains(&"adr:0008".to_string()));
+
+        // Exporting again writes nothing new, and importing what was exported adds nothing
+        assert!(export(&raw, &cons, &adr_dir, Sensitivity::Internal).unwrap().is_empty());
+        assert_eq!(read_adrs(&adr_dir).unwrap().len(), 3);
+        assert_eq!(import(&raw, &cons, &adr_dir, "s").unwrap(), 0);
+
+        // A decision saved with its own tags keeps them
+        let cache = db::save_memory(&raw, "Cache builds with sccache", "decision", "s").unwrap();
+        db::update_memory_tags(&raw, cache, &["ci".to_string()]).unwrap();
+        export_memory(&raw, &adr_dir, cache, "Cache builds with sccache").unwrap();
+        let tags = db::get_memories_by_type(&raw, "decision").unwrap().into_iter().find(|m| m.id == cache).unwrap().tags;
+        assert_eq!(tags, ["ci", "adr", "adr:0010"]);
+
+        std::fs::write(adr_dir.join("0011-pin-rust.md"), "# 11. Pin Rust\n\nPin the toolchain in rust-toolchain.toml.\n").unwrap();
+        assert_eq!(import(&raw, &cons, &adr_dir, "s").unwrap(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::db;
+use crate::models::Memory;
+use crate::similar::word_set;
+
+/// Memory type for "do NOT do X" rules. Sleep never merges or decays them, context shows
+/// them under "Avoid", and recall flags memories that go against them.
+pub const TYPE: &str = "antipattern";
+
+/// Words that turn a statement into a prohibition; ignored when matching an antipattern
+/// against other memories, and a memory that uses them is taken to agree with the rule.
+const NEGATIONS: &[&str] = &[
+    "not", "never", "don", "dont", "doesn", "avoid", "avoided", "without", "stop", "stopped", "instead", "shouldn",
+    "mustn", "must", "should", "anti", "antipattern",
+];
+/// Common words that say nothing about what a rule is about.
+const STOP_WORDS: &[&str] = &["the", "and", "for", "with", "this", "that", "are", "was", "from", "into", "any", "our"];
+/// Share of an antipattern's words a memory must contain to conflict with it.
+const CONFLICT_OVERLAP: f64 = 0.6;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Antipattern {
+    /// `#12` (raw, not yet consolidated), `consolidated #3`, or `global #5`
+    pub label: String,
+    pub content: String,
+}
+
+/// A recalled memory that looks like it does what an antipattern forbids.
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    pub memory_id: i64,
+    pub antipattern: String,
+    pub content: String,
+}
+
+/// Every antipattern known to the project: consolidated ones, raw ones not yet consolidated,
+/// and global ones.
+pub fn load(raw: &Connection, cons: &Connection, global: Option<&Connection>) -> Result<Vec<Antipattern>> {
+    let mut out: Vec<Antipattern> = db::get_consolidated_by_type(cons, TYPE)?
+        .into_iter()
+        .map(|c| Antipattern { label: format!("consolidated #{}", c.id), content: c.content })
+        .collect();
+    let untrusted: HashSet<i64> = db::get_untrusted_memories(raw)?.into_iter().map(|m| m.id).collect();
+    out.extend(
+        db::get_memories_by_type(raw, TYPE)?
+            .into_iter()
+            .filter(|m| !m.consolidated && !untrusted.contains(&m.id))
+            .map(|m| Antipattern { label: format!("#{}", m.id), content: m.content }),
+    );
+    if let Some(global) = global {
+        out.extend(
+            db::get_consolidated_by_type(global, TYPE)?
+                .into_iter()
+                .map(|c| Antipattern { label: format!("global #{}", c.id), content: c.content }),
+        );
+    }
+    Ok(out)
+}
+
+/// Memories that contain most of an antipattern's words (negations and stop words aside)
+/// without negating them themselves, e.g. "switched to unwrap in the parser" against "never
+/// unwrap in the parser". A word-overlap heuristic: it points at memories to double-check.
+pub fn conflicts(memories: &[Memory], antipatterns: &[Antipattern]) -> Vec<Conflict> {
+    let rules: Vec<(&Antipattern, HashSet<String>)> =
+        antipatterns.iter().map(|a| (a, key_words(&a.content))).filter(|(_, w)| w.len() >= 2).collect();
+    let mut out = Vec::new();
+    for m in memories.iter().filter(|m| m.r#type != TYPE) {
+        let words = word_set(&m.content);
+        if NEGATIONS.iter().any(|n| words.contains(*n)) || m.content.to_lowercase().contains("n't") {
+            continue;
+        }
+        for (a, rule) in &rules {
+            let shared = rule.iter().filter(|w| words.contains(*w)).count();
+            if shared as f64 / rule.len() as f64 >= CONFLICT_OVERLAP {
+                out.push(Conflict { memory_id: m.id, antipattern: a.label.clone(), content: a.content.clone() });
+            }
+        }
+    }
+    out
+}
+
+fn key_words(content: &str) -> HashSet<String> {
+    let mut words = word_set(content);
+    words.retain(|w| !NEGATIONS.contains(&w.as_str()) && !STOP_WORDS.contains(&w.as_str()));
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn flags_memories_that_do_what_an_antipattern_forbids() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        db::insert_consolidated(&cons, "Do NOT call unwrap in the parser", TYPE, &[], 0.9).unwrap();
+        db::save_memory(&raw, "Never mock the database in integration tests", TYPE, "s1").unwrap();
+        let rules = load(&raw, &cons, None).unwrap();
+        assert_eq!(rules.iter().map(|a| a.label.as_str()).collect::<Vec<_>>(), vec!["consolidated #1", "#1"]);
+
+        let bad = db::save_memory(&raw, "Parser now calls unwrap on every token", "pattern", "s1").unwrap();
+        let agrees = db::save_memory(&raw, "Removed unwrap from the parser; don't bring it back", "bugfix", "s1").unwrap();
+        let other = db::save_memory(&raw, "Integration tests take three minutes on CI", "pattern", "s1").unwrap();
+        let memories: Vec<Memory> =
+            db::get_memories_by_type(&raw, "pattern").unwrap().into_iter().chain(db::get_memories_by_type(&raw, "bugfix").unwrap()).collect();
+
+        let found = conflicts(&memories, &rules);
+        assert_eq!(found.len(), 1, "{:?}", found);
+        assert_eq!((found[0].memory_id, found[0].antipattern.as_str()), (bad, "consolidated #1"));
+        assert!(found.iter().all(|c| c.memory_id != agrees && c.memory_id != other));
+    }
+}
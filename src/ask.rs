@@ -0,0 +1,137 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::audit::Manifest;
+use crate::config::{Config, RecallConfig};
+use crate::db;
+use crate::llm;
+use crate::oversize;
+
+/// Longest excerpt of one memory put in the prompt.
+const EXCERPT_CHARS: usize = 2000;
+
+/// A memory retrieved for a question. `key` is how the prompt and answer refer to it:
+/// `c12` for consolidated #12, `m34` for raw memory #34, `g5` for global memory #5.
+#[derive(Debug, Clone, Serialize)]
+pub struct Source {
+    pub key: String,
+    pub id: i64,
+    pub kind: &'static str,
+    pub r#type: String,
+    pub date: String,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Answer {
+    pub answer: String,
+    /// Retrieved memories the answer cites, in retrieval order
+    pub citations: Vec<Source>,
+}
+
+/// Memories relevant to `question`: consolidated knowledge first, then raw observations,
+/// then global memory. Raw recall counts as an access, as with `cortex recall`. Sources are
+/// sent to the LLM, so memories marked `no_llm` are left out.
+pub fn retrieve(
+    raw: &Connection,
+    cons: &Connection,
+    global: Option<&Connection>,
+    question: &str,
+    limit: usize,
+    ranking: &RecallConfig,
+) -> Result<Vec<Source>> {
+    let mut sources = Vec::new();
+    for c in db::search_consolidated(cons, question, limit, ranking)?.into_iter().filter(|c| !c.no_llm) {
+        sources.push(Source {
+            key: format!("c{}", c.id),
+            id: c.id,
+            kind: "consolidated",
+            r#type: c.r#type,
+            date: c.updated_at,
+            content: c.content,
+        });
+    }
+    for m in db::recall_memories(raw, question, limit, ranking)?.into_iter().filter(|m| !m.no_llm) {
+        sources.push(Source {
+            key: format!("m{}", m.id),
+            id: m.id,
+            kind: "memory",
+            r#type: m.r#type,
+            date: m.created_at,
+            content: m.content,
+        });
+    }
+    if let Some(global) = global {
+        for g in db::search_consolidated(global, question, limit.div_ceil(2), ranking)?.into_iter().filter(|g| !g.no_llm) {
+            sources.push(Source {
+                key: format!("g{}", g.id),
+                id: g.id,
+                kind: "global",
+                r#type: g.r#type,
+                date: g.updated_at,
+                content: g.content,
+            });
+        }
+    }
+    Ok(sources)
+}
+
+/// Answer `question` from the project's memory with one LLM call. Citations the model
+/// makes up (keys that weren't retrieved) are dropped.
+pub async fn ask(
+    raw: &Connection,
+    cons: &Connection,
+    global: Option<&Connection>,
+    question: &str,
+    limit: usize,
+    config: &Config,
+) -> Result<Answer> {
+    let sources = retrieve(raw, cons, global, question, limit, &config.recall)?;
+    if sources.is_empty() {
+        return Ok(Answer { answer: "No memories match this question.".to_string(), citations: vec![] });
+    }
+    let manifest = Manifest::new(raw, "ask", sources.iter().map(|s| s.key.clone()).collect());
+    let (answer, keys) = llm::audited(manifest, llm::answer_question(question, &format_sources(&sources), config)).await?;
+    Ok(Answer { answer, citations: resolve(sources, &keys) })
+}
+
+/// Sources as prompt lines: `[key] (kind, type, date) content`.
+pub(crate) fn format_sources(sources: &[Source]) -> String {
+    sources
+        .iter()
+        .map(|s| {
+            let date = s.date.get(..10).unwrap_or(&s.date);
+            format!("[{}] ({}, {}, {}) {}", s.key, s.kind, s.r#type, date, oversize::truncate(&s.content, EXCERPT_CHARS))
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Sources named by `keys` (case-insensitive); unknown keys are ignored.
+pub(crate) fn resolve(sources: Vec<Source>, keys: &[String]) -> Vec<Source> {
+    sources.into_iter().filter(|s| keys.iter().any(|k| k.eq_ignore_ascii_case(&s.key))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn retrieves_keyed_sources_and_drops_unknown_citations() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let m = db::save_memory(&raw, "Switched to tokio because async-std was unmaintained", "decision", "s1").unwrap();
+        let c = db::insert_consolidated(&cons, "tokio is the async runtime", "pattern", &[m], 0.8).unwrap();
+
+        let sources = retrieve(&raw, &cons, None, "why did we switch to tokio?", 5, &Config::default().recall).unwrap();
+        let keys: Vec<String> = sources.iter().map(|s| s.key.clone()).collect();
+        assert_eq!(keys, vec![format!("c{}", c), format!("m{}", m)]);
+        assert!(format_sources(&sources).contains(&format!("[m{}] (memory, decision, ", m)));
+
+        let cited = resolve(sources, &["M1".to_string(), "c999".to_string()]);
+        assert_eq!(cited.len(), 1);
+        assert_eq!(cited[0].kind, "memory");
+    }
+}
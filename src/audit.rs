@@ -0,0 +1,179 @@
+use anyhow::Result;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// What an LLM call is for and which memories its prompt includes. Calls made inside
+/// `llm::audited` are recorded in the `llm_audit` table of this store's raw.db.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    /// raw.db that records the call (`None` for in-memory stores, which aren't audited)
+    pub raw_db: Option<PathBuf>,
+    /// e.g. `sleep`, `dream`, `ask`, `save`
+    pub purpose: &'static str,
+    /// Memories in the prompt: `m<id>` raw, `c<id>` consolidated, `g<id>` global
+    pub memories: Vec<String>,
+}
+
+impl Manifest {
+    /// A manifest recorded in the raw.db of the store that `conn` (either database) belongs to.
+    pub fn new(conn: &Connection, purpose: &'static str, memories: Vec<String>) -> Self {
+        let raw_db = conn
+            .path()
+            .filter(|p| !p.is_empty())
+            .and_then(|p| Path::new(p).parent().map(|dir| dir.join("raw.db")));
+        Self { raw_db, purpose, memories }
+    }
+}
+
+/// Manifest keys for raw memory IDs.
+pub fn raw_keys(ids: impl IntoIterator<Item = i64>) -> Vec<String> {
+    ids.into_iter().map(|id| format!("m{}", id)).collect()
+}
+
+/// Manifest keys for consolidated memory IDs.
+pub fn consolidated_keys(ids: impl IntoIterator<Item = i64>) -> Vec<String> {
+    ids.into_iter().map(|id| format!("c{}", id)).collect()
+}
+
+/// A finished call, as `llm` saw it.
+pub struct Call<'a> {
+    pub provider: &'a str,
+    pub model: &'a str,
+    pub system: &'a str,
+    pub prompt: &'a str,
+    /// Input and output tokens, when the provider reported them
+    pub usage: Option<(i64, i64)>,
+    pub ok: bool,
+}
+
+/// One row of `llm_audit`.
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    pub id: i64,
+    pub at: String,
+    pub provider: String,
+    pub model: String,
+    pub purpose: String,
+    pub memories: Vec<String>,
+    /// SHA-256 of the sorted memory keys, to compare manifests without listing them
+    pub manifest_hash: String,
+    /// SHA-256 of the system prompt and prompt as sent; the text itself is not kept
+    pub prompt_hash: String,
+    pub input_tokens: Option<i64>,
+    pub output_tokens: Option<i64>,
+    /// False if the provider returned an error (the prompt was still sent)
+    pub ok: bool,
+}
+
+/// `cortex audit` filters. Empty fields match everything.
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Only calls at or after this UTC timestamp (`%Y-%m-%dT%H:%M:%SZ`)
+    pub since: Option<String>,
+    /// Only calls whose manifest includes this key, e.g. `m12`
+    pub memory: Option<String>,
+    pub purpose: Option<String>,
+    pub limit: usize,
+}
+
+pub fn manifest_hash(memories: &[String]) -> String {
+    let mut sorted = memories.to_vec();
+    sorted.sort();
+    sorted.dedup();
+    sha256_hex(sorted.join("\n").as_bytes())
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Record `call` under `manifest`. Returns the new row's ID.
+pub fn record(conn: &Connection, manifest: &Manifest, call: &Call) -> Result<i64> {
+    let prompt_hash = sha256_hex(format!("{}\n\n{}", call.system, call.prompt).as_bytes());
+    conn.execute(
+        "INSERT INTO llm_audit (provider, model, purpose, memories, manifest_hash, prompt_hash, input_tokens, output_tokens, ok)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            call.provider,
+            call.model,
+            manifest.purpose,
+            serde_json::to_string(&manifest.memories)?,
+            manifest_hash(&manifest.memories),
+            prompt_hash,
+            call.usage.map(|u| u.0),
+            call.usage.map(|u| u.1),
+            call.ok,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Recorded calls matching `filter`, newest first.
+pub fn query(conn: &Connection, filter: &Filter) -> Result<Vec<Entry>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, at, provider, model, purpose, memories, manifest_hash, prompt_hash, input_tokens, output_tokens, ok
+         FROM llm_audit
+         WHERE (?1 IS NULL OR at >= ?1)
+           AND (?2 IS NULL OR instr(memories, ?2) > 0)
+           AND (?3 IS NULL OR purpose = ?3)
+         ORDER BY id DESC
+         LIMIT ?4",
+    )?;
+    // Keys are stored as a JSON array of strings, so match the quoted key
+    let memory = filter.memory.as_ref().map(|m| format!("\"{}\"", m));
+    let limit = if filter.limit == 0 { -1 } else { filter.limit as i64 };
+    let rows = stmt.query_map(params![filter.since, memory, filter.purpose, limit], |row| {
+        let memories: String = row.get(5)?;
+        Ok(Entry {
+            id: row.get(0)?,
+            at: row.get(1)?,
+            provider: row.get(2)?,
+            model: row.get(3)?,
+            purpose: row.get(4)?,
+            memories: serde_json::from_str(&memories).unwrap_or_default(),
+            manifest_hash: row.get(6)?,
+            prompt_hash: row.get(7)?,
+            input_tokens: row.get(8)?,
+            output_tokens: row.get(9)?,
+            ok: row.get(10)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// `12` -> `m12`; keys that already carry a prefix are kept.
+pub fn normalize_key(key: &str) -> String {
+    if key.starts_with(|c: char| c.is_ascii_digit()) { format!("m{}", key) } else { key.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn records_and_filters_calls() {
+        let conn = db::open_raw_db(std::path::Path::new(":memory:")).unwrap();
+        let sleep = Manifest { raw_db: None, purpose: "sleep", memories: vec!["m2".into(), "m1".into(), "c7".into()] };
+        let ask = Manifest { raw_db: None, purpose: "ask", memories: vec!["m12".into()] };
+        let call = |ok| Call { provider: "anthropic", model: "claude-haiku-4-5", system: "s", prompt: "p", usage: Some((120, 30)), ok };
+        record(&conn, &sleep, &call(true)).unwrap();
+        record(&conn, &ask, &call(false)).unwrap();
+
+        let all = query(&conn, &Filter::default()).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!((all[0].purpose.as_str(), all[0].ok), ("ask", false));
+        assert_eq!(all[1].input_tokens, Some(120));
+        assert_eq!(all[1].manifest_hash, manifest_hash(&["c7".into(), "m1".into(), "m2".into()]));
+
+        // `m1` must not match `m12`
+        let by_memory = |key: &str| query(&conn, &Filter { memory: Some(normalize_key(key)), ..Default::default() }).unwrap();
+        assert_eq!(by_memory("1").len(), 1);
+        assert_eq!(by_memory("m12")[0].purpose, "ask");
+        assert!(by_memory("c8").is_empty());
+        let sleeps = query(&conn, &Filter { purpose: Some("sleep".into()), limit: 5, ..Default::default() }).unwrap();
+        assert_eq!(sleeps.len(), 1);
+    }
+}
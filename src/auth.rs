@@ -0,0 +1,340 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Public OAuth client used for claude.ai subscription sign-in
+const CLIENT_ID: &str = "9d1c250a-e61b-44d9-88ed-5944d1962f5e";
+const AUTHORIZE_URL: &str = "https://claude.ai/oauth/authorize";
+const TOKEN_URL: &str = "https://console.anthropic.com/v1/oauth/token";
+const REDIRECT_URI: &str = "https://console.anthropic.com/oauth/code/callback";
+const SCOPES: &str = "org:create_api_key user:profile user:inference";
+/// Beta header required when calling the Messages API with an OAuth access token.
+pub const OAUTH_BETA: &str = "oauth-2025-04-20";
+
+const KEYCHAIN_SERVICE: &str = "cortex";
+const KEYCHAIN_ACCOUNT: &str = "anthropic-oauth";
+/// Refresh this many seconds before the access token actually expires.
+const EXPIRY_SKEW_SECS: i64 = 60;
+
+/// Stored OAuth tokens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Unix timestamp (seconds)
+    pub expires_at: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: i64,
+}
+
+/// Interactive PKCE login: print the authorize URL, read back the pasted code, store tokens.
+pub async fn login() -> Result<OAuthTokens> {
+    let verifier = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+    let challenge = base64_url(&Sha256::digest(verifier.as_bytes()));
+    let state = uuid::Uuid::new_v4().simple().to_string();
+
+    let url = format!(
+        "{}?code=true&client_id={}&response_type=code&redirect_uri={}&scope={}&code_challenge={}&code_challenge_method=S256&state={}",
+        AUTHORIZE_URL,
+        CLIENT_ID,
+        url_encode(REDIRECT_URI),
+        url_encode(SCOPES),
+        challenge,
+        state
+    );
+    eprintln!("Open this URL to sign in with your Claude account:\n\n{}\n", url);
+    open_browser(&url);
+    eprint!("Paste the authorization code: ");
+    std::io::stderr().flush()?;
+
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    let code = parse_code(&input, &state)?;
+
+    let tokens = request_tokens(serde_json::json!({
+        "grant_type": "authorization_code",
+        "code": code,
+        "state": state,
+        "client_id": CLIENT_ID,
+        "redirect_uri": REDIRECT_URI,
+        "code_verifier": verifier,
+    }), None)
+    .await?;
+    store_tokens(&tokens)?;
+    Ok(tokens)
+}
+
+/// The code from what the user pasted: the callback page shows `<code>#<state>`, and the state
+/// must be the one this login sent. Input without it is refused rather than trusted.
+fn parse_code<'a>(input: &'a str, state: &str) -> Result<&'a str> {
+    let input = input.trim();
+    if input.is_empty() {
+        anyhow::bail!("No authorization code entered.");
+    }
+    let Some((code, returned_state)) = input.split_once('#') else {
+        anyhow::bail!("Paste the whole code shown after sign-in, including the part after '#'.");
+    };
+    if code.is_empty() {
+        anyhow::bail!("No authorization code entered.");
+    }
+    if returned_state != state {
+        anyhow::bail!("OAuth state mismatch; please retry `cortex auth login`.");
+    }
+    Ok(code)
+}
+
+/// A usable access token, refreshing (and re-storing) it if it has expired.
+/// Returns None when the user hasn't logged in.
+pub async fn access_token() -> Result<Option<String>> {
+    let Some(tokens) = load_tokens() else {
+        return Ok(None);
+    };
+    if tokens.expires_at - EXPIRY_SKEW_SECS > chrono::Utc::now().timestamp() {
+        return Ok(Some(tokens.access_token));
+    }
+
+    let refreshed = request_tokens(
+        serde_json::json!({
+            "grant_type": "refresh_token",
+            "refresh_token": tokens.refresh_token,
+            "client_id": CLIENT_ID,
+        }),
+        Some(&tokens.refresh_token),
+    )
+    .await
+    .context("OAuth token refresh failed; run `cortex auth login` again")?;
+    store_tokens(&refreshed)?;
+    Ok(Some(refreshed.access_token))
+}
+
+async fn request_tokens(body: serde_json::Value, previous_refresh: Option<&str>) -> Result<OAuthTokens> {
+    let resp = reqwest::Client::new()
+        .post(TOKEN_URL)
+        .json(&body)
+        .send()
+        .await
+        .context("Failed to reach the Anthropic OAuth server")?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+        anyhow::bail!("OAuth token request failed ({}): {}", status, text);
+    }
+    let token: TokenResponse = resp.json().await.context("Failed to parse OAuth token response")?;
+    let refresh_token = token
+        .refresh_token
+        .or_else(|| previous_refresh.map(|s| s.to_string()))
+        .context("OAuth response did not include a refresh token")?;
+    Ok(OAuthTokens {
+        access_token: token.access_token,
+        refresh_token,
+        expires_at: chrono::Utc::now().timestamp() + token.expires_in,
+    })
+}
+
+// --- Token storage: OS keychain, falling back to a 0600 file ---
+
+pub fn load_tokens() -> Option<OAuthTokens> {
+    let raw = keychain_get().or_else(|| std::fs::read_to_string(fallback_path()?).ok())?;
+    serde_json::from_str(&raw).ok()
+}
+
+/// Where the tokens are stored, for `cortex auth status`.
+pub fn storage_location() -> String {
+    if keychain_get().is_some() {
+        "OS keychain".to_string()
+    } else {
+        fallback_path()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "none".to_string())
+    }
+}
+
+fn store_tokens(tokens: &OAuthTokens) -> Result<()> {
+    let raw = serde_json::to_string(tokens)?;
+    if keychain_set(&raw) {
+        // Don't leave a stale copy behind from an earlier keychain-less login
+        if let Some(path) = fallback_path() {
+            let _ = std::fs::remove_file(path);
+        }
+        return Ok(());
+    }
+
+    let path = fallback_path().context("Cannot determine home directory")?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(&path)?.write_all(raw.as_bytes())?;
+    eprintln!("No OS keychain available; stored tokens in {} (mode 0600).", path.display());
+    Ok(())
+}
+
+/// Remove stored tokens. Returns false if none were stored.
+pub fn logout() -> bool {
+    let mut removed = keychain_delete();
+    if let Some(path) = fallback_path() {
+        removed |= std::fs::remove_file(path).is_ok();
+    }
+    removed
+}
+
+fn fallback_path() -> Option<std::path::PathBuf> {
+    Some(crate::init::global_config_dir()?.join("credentials.json"))
+}
+
+#[cfg(target_os = "macos")]
+fn keychain_get() -> Option<String> {
+    let out = Command::new("security")
+        .args(["find-generic-password", "-s", KEYCHAIN_SERVICE, "-a", KEYCHAIN_ACCOUNT, "-w"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    out.status.success().then(|| String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// Run `command` with `input` on stdin, so a secret never shows up in its arguments, which any
+/// local user can read with `ps`.
+fn run_with_stdin(command: &mut Command, input: &str) -> bool {
+    let Ok(mut child) = command.stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null()).spawn() else {
+        return false;
+    };
+    let written = child.stdin.take().is_some_and(|mut stdin| stdin.write_all(input.as_bytes()).is_ok());
+    child.wait().is_ok_and(|s| s.success()) && written
+}
+
+#[cfg(target_os = "macos")]
+fn keychain_set(secret: &str) -> bool {
+    // With a bare `-w` last, security prompts for the password and its confirmation on stdin
+    let mut command = Command::new("security");
+    command.args(["add-generic-password", "-U", "-s", KEYCHAIN_SERVICE, "-a", KEYCHAIN_ACCOUNT, "-w"]);
+    // Read it back: anything else (say, a prompt answered elsewhere) falls back to the file
+    run_with_stdin(&mut command, &format!("{0}\n{0}\n", secret)) && keychain_get().as_deref() == Some(secret)
+}
+
+#[cfg(target_os = "macos")]
+fn keychain_delete() -> bool {
+    Command::new("security")
+        .args(["delete-generic-password", "-s", KEYCHAIN_SERVICE, "-a", KEYCHAIN_ACCOUNT])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+// Linux and other Unixes: the freedesktop Secret Service via `secret-tool` (libsecret)
+#[cfg(not(target_os = "macos"))]
+fn keychain_get() -> Option<String> {
+    let out = Command::new("secret-tool")
+        .args(["lookup", "service", KEYCHAIN_SERVICE, "account", KEYCHAIN_ACCOUNT])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let value = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (out.status.success() && !value.is_empty()).then_some(value)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn keychain_set(secret: &str) -> bool {
+    let mut command = Command::new("secret-tool");
+    command.args(["store", "--label=cortex Anthropic OAuth", "service", KEYCHAIN_SERVICE, "account", KEYCHAIN_ACCOUNT]);
+    run_with_stdin(&mut command, secret)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn keychain_delete() -> bool {
+    let existed = keychain_get().is_some();
+    let cleared = Command::new("secret-tool")
+        .args(["clear", "service", KEYCHAIN_SERVICE, "account", KEYCHAIN_ACCOUNT])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
+    existed && cleared
+}
+
+fn open_browser(url: &str) {
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    let _ = Command::new(opener)
+        .arg(url)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+}
+
+fn url_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Unpadded base64url, as PKCE requires.
+fn base64_url(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        let chars = chunk.len() + 1;
+        for i in 0..chars {
+            out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pkce_challenge_rfc7636() {
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        assert_eq!(
+            base64_url(&Sha256::digest(verifier.as_bytes())),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn authorization_code_needs_matching_state() {
+        assert_eq!(parse_code(" abc#s1\n", "s1").unwrap(), "abc");
+        assert!(parse_code("abc", "s1").unwrap_err().to_string().contains("after '#'"));
+        assert!(parse_code("abc#s2", "s1").unwrap_err().to_string().contains("mismatch"));
+        assert!(parse_code("#s1", "s1").is_err());
+        assert!(parse_code("", "s1").is_err());
+    }
+
+    #[test]
+    fn secrets_go_through_stdin() {
+        let out = std::env::temp_dir().join(format!("cortex-stdin-{}", uuid::Uuid::new_v4()));
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(format!("cat > '{}'", out.display()));
+        assert!(run_with_stdin(&mut command, "s3cret\n"));
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "s3cret\n");
+        std::fs::remove_file(&out).unwrap();
+        assert!(!run_with_stdin(Command::new("sh").arg("-c").arg("exit 1"), "s3cret"));
+    }
+
+    #[test]
+    fn base64_url_unpadded() {
+        assert_eq!(base64_url(b"f"), "Zg");
+        assert_eq!(base64_url(b"fo"), "Zm8");
+        assert_eq!(base64_url(b"foo"), "Zm9v");
+        assert_eq!(base64_url(&[0xfb, 0xff]), "-_8");
+    }
+}
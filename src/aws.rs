@@ -0,0 +1,525 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+
+/// AWS credential triple
+pub struct Credentials {
+    pub access_key: String,
+    pub secret_key: String,
+    pub session_token: Option<String>,
+}
+
+/// Nesting limit for `source_profile` chains.
+const MAX_PROFILE_DEPTH: usize = 5;
+
+/// Settings for one profile, merged from ~/.aws/config and ~/.aws/credentials.
+type Profile = HashMap<String, String>;
+
+/// True if some credential source is configured. Cheap: no network calls.
+pub fn credentials_configured() -> bool {
+    if env_credentials().is_some() {
+        return true;
+    }
+    let profile = load_profile(&profile_name()).unwrap_or_default();
+    ["aws_access_key_id", "role_arn", "sso_start_url", "sso_session"]
+        .iter()
+        .any(|k| profile.contains_key(*k))
+}
+
+/// Resolve AWS credentials in SDK order: env vars, then the active profile's static keys,
+/// assumed role (`role_arn` + `source_profile`/`credential_source`), or SSO token cache.
+pub async fn resolve_credentials() -> Result<Credentials> {
+    if let Some(creds) = env_credentials() {
+        return Ok(creds);
+    }
+    resolve_profile(&profile_name(), 0).await
+}
+
+fn profile_name() -> String {
+    std::env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string())
+}
+
+fn env_credentials() -> Option<Credentials> {
+    match (std::env::var("AWS_ACCESS_KEY_ID"), std::env::var("AWS_SECRET_ACCESS_KEY")) {
+        (Ok(ak), Ok(sk)) if !ak.is_empty() && !sk.is_empty() => Some(Credentials {
+            access_key: ak,
+            secret_key: sk,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok().filter(|s| !s.is_empty()),
+        }),
+        _ => None,
+    }
+}
+
+fn resolve_profile<'a>(name: &'a str, depth: usize) -> Pin<Box<dyn Future<Output = Result<Credentials>> + 'a>> {
+    Box::pin(async move {
+        if depth > MAX_PROFILE_DEPTH {
+            anyhow::bail!("AWS profile chain is too deep (source_profile loop at `{}`?)", name);
+        }
+        let profile = load_profile(name)?;
+
+        // A profile that assumes a role may also carry keys for its own use as a source
+        if let Some(role_arn) = profile.get("role_arn") {
+            let source = match (profile.get("source_profile"), profile.get("credential_source")) {
+                (Some(src), _) if src == name => static_credentials(&profile)
+                    .with_context(|| format!("profile `{}` is its own source_profile but has no keys", name))?,
+                (Some(src), _) => resolve_profile(src, depth + 1).await?,
+                (None, Some(cs)) if cs == "Environment" => {
+                    env_credentials().context("credential_source = Environment but AWS_ACCESS_KEY_ID is not set")?
+                }
+                (None, Some(cs)) => anyhow::bail!("credential_source `{}` is not supported", cs),
+                (None, None) => anyhow::bail!("profile `{}` has role_arn but no source_profile", name),
+            };
+            return assume_role(&source, role_arn, &profile).await;
+        }
+
+        if profile.contains_key("sso_start_url") || profile.contains_key("sso_session") {
+            return sso_credentials(name, &profile).await;
+        }
+
+        static_credentials(&profile).with_context(|| format!("No AWS credentials found for profile `{}`", name))
+    })
+}
+
+fn static_credentials(profile: &Profile) -> Option<Credentials> {
+    match (profile.get("aws_access_key_id"), profile.get("aws_secret_access_key")) {
+        (Some(ak), Some(sk)) if !ak.is_empty() && !sk.is_empty() => Some(Credentials {
+            access_key: ak.clone(),
+            secret_key: sk.clone(),
+            session_token: profile.get("aws_session_token").cloned(),
+        }),
+        _ => None,
+    }
+}
+
+/// Merge `[profile name]` from ~/.aws/config (plus its `[sso-session]`, if any) with
+/// `[name]` from ~/.aws/credentials; the credentials file wins on conflicts.
+fn load_profile(name: &str) -> Result<Profile> {
+    let aws_dir = dirs::home_dir().context("Cannot determine home directory")?.join(".aws");
+    let config = read_ini(&aws_dir.join("config"));
+    let credentials = read_ini(&aws_dir.join("credentials"));
+
+    let config_section = if name == "default" { "default".to_string() } else { format!("profile {}", name) };
+    let mut profile = config.get(&config_section).cloned().unwrap_or_default();
+    if let Some(session) = profile.get("sso_session").cloned()
+        && let Some(settings) = config.get(&format!("sso-session {}", session))
+    {
+        for (k, v) in settings {
+            profile.entry(k.clone()).or_insert_with(|| v.clone());
+        }
+    }
+    if let Some(creds) = credentials.get(name) {
+        profile.extend(creds.clone());
+    }
+    if profile.is_empty() {
+        anyhow::bail!("AWS profile `{}` not found in ~/.aws/config or ~/.aws/credentials", name);
+    }
+    Ok(profile)
+}
+
+/// Parse an AWS INI file into section → key → value. Missing files parse as empty.
+fn read_ini(path: &Path) -> HashMap<String, Profile> {
+    let mut sections: HashMap<String, Profile> = HashMap::new();
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return sections;
+    };
+    let mut current: Option<String> = None;
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+        if let Some(section) = trimmed.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            current = Some(section.trim().to_string());
+            continue;
+        }
+        let Some(section) = &current else { continue };
+        // Handle both "key = value" and "export KEY=value" formats
+        let trimmed = trimmed.strip_prefix("export ").unwrap_or(trimmed);
+        if let Some((k, v)) = trimmed.split_once('=') {
+            sections
+                .entry(section.clone())
+                .or_default()
+                .insert(k.trim().to_lowercase(), v.trim().to_string());
+        }
+    }
+    sections
+}
+
+/// Call STS AssumeRole with the source credentials.
+async fn assume_role(source: &Credentials, role_arn: &str, profile: &Profile) -> Result<Credentials> {
+    let region = profile
+        .get("region")
+        .cloned()
+        .or_else(|| std::env::var("AWS_REGION").ok())
+        .unwrap_or_else(|| "us-east-1".to_string());
+    let session_name = profile
+        .get("role_session_name")
+        .cloned()
+        .unwrap_or_else(|| format!("cortex-{}", chrono::Utc::now().timestamp()));
+
+    let mut params: Vec<(&str, String)> = vec![
+        ("Action", "AssumeRole".to_string()),
+        ("RoleArn", role_arn.to_string()),
+        ("RoleSessionName", session_name),
+        ("Version", "2011-06-15".to_string()),
+    ];
+    if let Some(external_id) = profile.get("external_id") {
+        params.push(("ExternalId", external_id.clone()));
+    }
+    if let Some(duration) = profile.get("duration_seconds") {
+        params.push(("DurationSeconds", duration.clone()));
+    }
+    params.sort();
+    let query = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, uri_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let host = format!("sts.{}.amazonaws.com", region);
+    let signature = sign(
+        source,
+        &region,
+        "sts",
+        &Request {
+            method: "GET",
+            canonical_uri: "/",
+            canonical_query: &query,
+            headers: &[("host", &host)],
+            payload: b"",
+        },
+        chrono::Utc::now(),
+    );
+
+    let mut req = reqwest::Client::new()
+        .get(format!("https://{}/?{}", host, query))
+        .header("x-amz-date", &signature.amz_date)
+        .header("authorization", &signature.authorization);
+    if let Some(token) = &signature.security_token {
+        req = req.header("x-amz-security-token", token);
+    }
+    let resp = req.send().await.context("Failed to call STS")?;
+    let status = resp.status();
+    let body = resp.text().await.unwrap_or_default();
+    if !status.is_success() {
+        anyhow::bail!("STS AssumeRole for {} failed ({}): {}", role_arn, status, body);
+    }
+
+    let field = |tag: &str| {
+        xml_text(&body, tag).with_context(|| format!("STS response is missing <{}>", tag))
+    };
+    Ok(Credentials {
+        access_key: field("AccessKeyId")?,
+        secret_key: field("SecretAccessKey")?,
+        session_token: Some(field("SessionToken")?),
+    })
+}
+
+/// Text of the first `<tag>...</tag>` in an XML document.
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(xml[start..end].trim().to_string())
+}
+
+/// Exchange the cached `aws sso login` token for role credentials.
+async fn sso_credentials(name: &str, profile: &Profile) -> Result<Credentials> {
+    let get = |k: &str| {
+        profile
+            .get(k)
+            .cloned()
+            .with_context(|| format!("SSO profile `{}` is missing {}", name, k))
+    };
+    let start_url = get("sso_start_url")?;
+    let sso_region = get("sso_region")?;
+    let account_id = get("sso_account_id")?;
+    let role_name = get("sso_role_name")?;
+
+    let token = sso_cached_token(&start_url)
+        .with_context(|| format!("No valid SSO token for {}. Run `aws sso login --profile {}`.", start_url, name))?;
+
+    let url = format!(
+        "https://portal.sso.{}.amazonaws.com/federation/credentials?account_id={}&role_name={}",
+        sso_region,
+        uri_encode(&account_id),
+        uri_encode(&role_name)
+    );
+    let resp = reqwest::Client::new()
+        .get(url)
+        .header("x-amz-sso_bearer_token", token)
+        .send()
+        .await
+        .context("Failed to call AWS SSO")?;
+    let status = resp.status();
+    if !status.is_success() {
+        let text = resp.text().await.unwrap_or_default();
+        anyhow::bail!(
+            "AWS SSO GetRoleCredentials failed ({}): {}. Try `aws sso login --profile {}`.",
+            status,
+            text,
+            name
+        );
+    }
+
+    let body: serde_json::Value = resp.json().await.context("Failed to parse AWS SSO response")?;
+    let creds = &body["roleCredentials"];
+    let field = |k: &str| {
+        creds[k]
+            .as_str()
+            .map(|s| s.to_string())
+            .with_context(|| format!("AWS SSO response is missing {}", k))
+    };
+    Ok(Credentials {
+        access_key: field("accessKeyId")?,
+        secret_key: field("secretAccessKey")?,
+        session_token: Some(field("sessionToken")?),
+    })
+}
+
+/// Find an unexpired access token for `start_url` in ~/.aws/sso/cache.
+fn sso_cached_token(start_url: &str) -> Option<String> {
+    let cache_dir = dirs::home_dir()?.join(".aws").join("sso").join("cache");
+    let now = chrono::Utc::now();
+    std::fs::read_dir(cache_dir)
+        .ok()?
+        .filter_map(|e| std::fs::read_to_string(e.ok()?.path()).ok())
+        .filter_map(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+        .filter(|entry| entry["startUrl"].as_str() == Some(start_url))
+        .filter(|entry| {
+            entry["expiresAt"]
+                .as_str()
+                .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                .is_some_and(|t| t > now)
+        })
+        .find_map(|entry| entry["accessToken"].as_str().map(|s| s.to_string()))
+}
+
+/// A request to sign. `headers` must include `host`; `x-amz-date` and, for temporary
+/// credentials, `x-amz-security-token` are added by [`sign`].
+pub struct Request<'a> {
+    pub method: &'a str,
+    /// Already URI-encoded path, e.g. `/model/us.anthropic.x%3A0/invoke`
+    pub canonical_uri: &'a str,
+    /// Already sorted and encoded query string (empty for none)
+    pub canonical_query: &'a str,
+    pub headers: &'a [(&'a str, &'a str)],
+    pub payload: &'a [u8],
+}
+
+/// Headers the caller must send alongside the ones it signed.
+pub struct Signature {
+    pub amz_date: String,
+    pub authorization: String,
+    pub security_token: Option<String>,
+}
+
+/// Sign a request with AWS Signature Version 4.
+pub fn sign(creds: &Credentials, region: &str, service: &str, req: &Request, now: DateTime<Utc>) -> Signature {
+    let date_stamp = now.format("%Y%m%d").to_string();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+
+    let mut headers: Vec<(String, String)> = req
+        .headers
+        .iter()
+        .map(|(k, v)| (k.to_lowercase(), v.trim().to_string()))
+        .collect();
+    headers.push(("x-amz-date".to_string(), amz_date.clone()));
+    if let Some(token) = &creds.session_token {
+        headers.push(("x-amz-security-token".to_string(), token.clone()));
+    }
+    headers.sort();
+
+    let canonical_headers: String = headers.iter().map(|(k, v)| format!("{}:{}\n", k, v)).collect();
+    let signed_headers = headers.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>().join(";");
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        req.method,
+        req.canonical_uri,
+        req.canonical_query,
+        canonical_headers,
+        signed_headers,
+        sha256_hex(req.payload)
+    );
+
+    let credential_scope = format!("{}/{}/{}/aws4_request", date_stamp, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        credential_scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let signing_key = signing_key(&creds.secret_key, &date_stamp, region, service);
+    let signature = to_hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+    Signature {
+        amz_date,
+        authorization: format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            creds.access_key, credential_scope, signed_headers, signature
+        ),
+        security_token: creds.session_token.clone(),
+    }
+}
+
+/// URI-encode a path segment per AWS SigV4 rules (encode everything except unreserved chars)
+pub fn uri_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len() * 2);
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'~' | b'.' => {
+                encoded.push(byte as char);
+            }
+            _ => {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    encoded
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+fn signing_key(secret_key: &str, date_stamp: &str, region: &str, service: &str) -> [u8; 32] {
+    let k_date = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date_stamp.as_bytes());
+    let k_region = hmac_sha256(&k_date, region.as_bytes());
+    let k_service = hmac_sha256(&k_region, service.as_bytes());
+    hmac_sha256(&k_service, b"aws4_request")
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // Credentials and timestamp shared by the AWS SigV4 test suite
+    fn example_creds() -> Credentials {
+        Credentials {
+            access_key: "AKIDEXAMPLE".to_string(),
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        }
+    }
+
+    fn example_time() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap()
+    }
+
+    #[test]
+    fn sha256_known_vectors() {
+        assert_eq!(sha256_hex(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn hmac_rfc4231_case_2() {
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(to_hex(&mac), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn signing_key_matches_aws_docs() {
+        let key = signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam");
+        assert_eq!(to_hex(&key), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+    }
+
+    #[test]
+    fn sigv4_get_vanilla() {
+        let req = Request {
+            method: "GET",
+            canonical_uri: "/",
+            canonical_query: "",
+            headers: &[("Host", "example.amazonaws.com")],
+            payload: b"",
+        };
+        let sig = sign(&example_creds(), "us-east-1", "service", &req, example_time());
+        assert_eq!(sig.amz_date, "20150830T123600Z");
+        assert_eq!(
+            sig.authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn sigv4_post_vanilla() {
+        let req = Request {
+            method: "POST",
+            canonical_uri: "/",
+            canonical_query: "",
+            headers: &[("Host", "example.amazonaws.com")],
+            payload: b"",
+        };
+        let sig = sign(&example_creds(), "us-east-1", "service", &req, example_time());
+        assert!(sig.authorization.ends_with(
+            "Signature=5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b"
+        ));
+    }
+
+    #[test]
+    fn sigv4_get_vanilla_query() {
+        let req = Request {
+            method: "GET",
+            canonical_uri: "/",
+            canonical_query: "Param1=value1",
+            headers: &[("Host", "example.amazonaws.com")],
+            payload: b"",
+        };
+        let sig = sign(&example_creds(), "us-east-1", "service", &req, example_time());
+        assert!(sig.authorization.ends_with(
+            "Signature=a67d582fa61cc504c4bae71f336f98b97f1ea3c7a6bfe1b6e45aec72011b9aeb"
+        ));
+    }
+
+    #[test]
+    fn xml_text_extracts_sts_fields() {
+        let xml = "<AssumeRoleResult><Credentials><AccessKeyId>ASIA123</AccessKeyId>\
+                   <SessionToken>tok</SessionToken></Credentials></AssumeRoleResult>";
+        assert_eq!(xml_text(xml, "AccessKeyId").as_deref(), Some("ASIA123"));
+        assert_eq!(xml_text(xml, "SessionToken").as_deref(), Some("tok"));
+        assert_eq!(xml_text(xml, "SecretAccessKey"), None);
+    }
+
+    #[test]
+    fn read_ini_sections() {
+        let path = std::env::temp_dir().join(format!("cortex-aws-{}.ini", std::process::id()));
+        std::fs::write(
+            &path,
+            "# comment\n[default]\nregion = us-west-2\n\n[profile dev]\nrole_arn = arn:aws:iam::1:role/x\nsource_profile = default\n",
+        )
+        .unwrap();
+        let ini = read_ini(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ini["default"]["region"], "us-west-2");
+        assert_eq!(ini["profile dev"]["role_arn"], "arn:aws:iam::1:role/x");
+        assert_eq!(ini["profile dev"]["source_profile"], "default");
+    }
+
+    #[test]
+    fn uri_encode_reserved_chars() {
+        assert_eq!(
+            uri_encode("us.anthropic.claude-haiku-4-5-20251001-v1:0"),
+            "us.anthropic.claude-haiku-4-5-20251001-v1%3A0"
+        );
+        assert_eq!(uri_encode("a b/c"), "a%20b%2Fc");
+    }
+}
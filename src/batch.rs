@@ -0,0 +1,176 @@
+use anyhow::Result;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+
+use crate::audit::{self, Manifest};
+use crate::config::{Config, LlmConfig};
+use crate::dream;
+use crate::llm::{self, BatchOutcome, OutputTool};
+use crate::lock::StoreLock;
+use crate::sleep;
+use crate::snapshot;
+
+/// Purposes of batched prompts; each is also its request's custom ID.
+pub const SLEEP: &str = "sleep";
+pub const DREAM: &str = "dream";
+
+/// A prompt submitted with `--batch-api` whose result hasn't been applied, kept in raw.db.
+#[derive(Debug, Clone, Serialize)]
+pub struct Pending {
+    /// Message Batches API batch ID
+    pub id: String,
+    /// `sleep` or `dream`
+    pub purpose: String,
+    pub submitted_at: String,
+    /// Raw memory IDs in the prompt
+    pub memories: Vec<i64>,
+    /// Consolidated memory IDs in the prompt
+    pub consolidated: Vec<i64>,
+}
+
+/// A prompt to submit, and the memories it includes.
+pub struct Request<'a> {
+    pub purpose: &'static str,
+    pub prompt: &'a str,
+    pub system: &'a str,
+    pub llm: &'a LlmConfig,
+    pub tool: &'a OutputTool,
+    pub memories: Vec<i64>,
+    pub consolidated: Vec<i64>,
+}
+
+/// What `cortex sleep --collect` did with the pending batches.
+#[derive(Debug, Default)]
+pub struct Collected {
+    pub applied: usize,
+    /// Still processing; collected on a later run
+    pub waiting: usize,
+    /// Errored, expired, or rejected on apply; their memories wait for the next sleep
+    pub failed: usize,
+}
+
+/// Submit `request` and record it as pending. Returns the batch ID.
+pub async fn submit(raw_conn: &Connection, config: &Config, request: Request<'_>) -> Result<String> {
+    let mut keys = audit::raw_keys(request.memories.iter().copied());
+    keys.extend(audit::consolidated_keys(request.consolidated.iter().copied()));
+    let call = llm::submit_batch(request.purpose, request.prompt, request.system, config, request.llm, request.tool);
+    let id = llm::audited(Manifest::new(raw_conn, request.purpose, keys), call).await?;
+    raw_conn.execute(
+        "INSERT INTO llm_batches (id, purpose, memories, consolidated) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            id,
+            request.purpose,
+            serde_json::to_string(&request.memories)?,
+            serde_json::to_string(&request.consolidated)?
+        ],
+    )?;
+    Ok(id)
+}
+
+/// Pending batches, oldest first, optionally only those for `purpose`.
+pub fn pending(conn: &Connection, purpose: Option<&str>) -> Result<Vec<Pending>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, purpose, submitted_at, memories, consolidated FROM llm_batches
+         WHERE ?1 IS NULL OR purpose = ?1
+         ORDER BY submitted_at, rowid",
+    )?;
+    let rows = stmt.query_map(params![purpose], |row| {
+        let (memories, consolidated): (String, String) = (row.get(3)?, row.get(4)?);
+        Ok(Pending {
+            id: row.get(0)?,
+            purpose: row.get(1)?,
+            submitted_at: row.get(2)?,
+            memories: serde_json::from_str(&memories).unwrap_or_default(),
+            consolidated: serde_json::from_str(&consolidated).unwrap_or_default(),
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+fn remove(conn: &Connection, id: &str) -> Result<()> {
+    conn.execute("DELETE FROM llm_batches WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+/// Fetch every pending batch that has finished and apply its result. A batch that failed, or
+/// whose result can't be applied, is dropped; its memories are picked up by the next sleep.
+pub async fn collect(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    config: &Config,
+    cortex_dir: &std::path::Path,
+) -> Result<Collected> {
+    let _lock = StoreLock::acquire(cortex_dir, "batch collect")?;
+    sleep::recover_pending_runs(raw_conn, cons_conn)?;
+    let tool = sleep::consolidation_tool();
+    let mut collected = Collected::default();
+    for batch in pending(raw_conn, None)? {
+        let response = match llm::batch_outcome(&batch.id, &batch.purpose, config, &tool).await? {
+            BatchOutcome::Pending => {
+                eprintln!("Batch {} ({}) is still processing.", batch.id, batch.purpose);
+                collected.waiting += 1;
+                continue;
+            }
+            BatchOutcome::Failed(e) => {
+                eprintln!("Warning: {} batch {} failed: {}", batch.purpose, batch.id, e);
+                remove(raw_conn, &batch.id)?;
+                collected.failed += 1;
+                continue;
+            }
+            BatchOutcome::Done(response) => response,
+        };
+        let applied = match batch.purpose.as_str() {
+            SLEEP => sleep::apply_batch(raw_conn, cons_conn, config, cortex_dir, &batch, response).await.map(|r| {
+                format!("{} consolidations, {} promotions", r.consolidations.len(), r.promotions.len())
+            }),
+            DREAM => snapshot::before(raw_conn, cons_conn, cortex_dir, &config.snapshots, "dream")
+                .and_then(|_| dream::apply_batch(raw_conn, cons_conn, config, cortex_dir, &batch, response)
+                .map(|r| format!(
+                    "{} insights, {} refined, {} skills updated, {} removed, {} decayed, {} merged",
+                    r.insights, r.insights_refined, r.skills_updated, r.skills_removed, r.decayed, r.merged
+                ))),
+            other => Err(anyhow::anyhow!("unknown batch purpose '{}'", other)),
+        };
+        remove(raw_conn, &batch.id)?;
+        match applied {
+            Ok(summary) => {
+                eprintln!("Applied {} batch {}: {}.", batch.purpose, batch.id, summary);
+                collected.applied += 1;
+            }
+            Err(e) => {
+                eprintln!("Warning: could not apply {} batch {}: {}", batch.purpose, batch.id, e);
+                collected.failed += 1;
+            }
+        }
+    }
+    Ok(collected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn lists_pending_batches_by_purpose() {
+        let conn = db::open_raw_db(std::path::Path::new(":memory:")).unwrap();
+        let insert = |id: &str, purpose: &str, memories: &str| {
+            conn.execute(
+                "INSERT INTO llm_batches (id, purpose, memories) VALUES (?1, ?2, ?3)",
+                params![id, purpose, memories],
+            )
+            .unwrap();
+        };
+        insert("msgbatch_1", SLEEP, "[3,4]");
+        insert("msgbatch_2", DREAM, "[]");
+
+        let all = pending(&conn, None).unwrap();
+        assert_eq!(all.iter().map(|b| b.id.as_str()).collect::<Vec<_>>(), ["msgbatch_1", "msgbatch_2"]);
+        assert_eq!(all[0].memories, [3, 4]);
+        let sleeps = pending(&conn, Some(SLEEP)).unwrap();
+        assert_eq!(sleeps.len(), 1);
+
+        remove(&conn, "msgbatch_1").unwrap();
+        assert!(pending(&conn, Some(SLEEP)).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::context;
+use crate::db;
+use crate::sleep;
+
+const TOPICS: &[&str] = &[
+    "tokio runtime blocking in async handlers",
+    "sqlite fts5 tokenizer and rank tuning",
+    "clippy lints on let chains",
+    "serde derive with default fields",
+    "cargo build caching in CI",
+    "error handling with anyhow context",
+    "MCP server JSON-RPC framing",
+];
+
+/// Deterministic synthetic observation; topics repeat so FTS queries have realistic hit counts.
+pub fn synthetic_content(i: usize) -> String {
+    format!("Observation {} about {} (variant {})", i, TOPICS[i % TOPICS.len()], i % 997)
+}
+
+/// A throwaway store in the system temp dir, removed on drop.
+pub struct Store {
+    pub dir: PathBuf,
+    pub raw: Connection,
+    pub cons: Connection,
+}
+
+impl Store {
+    pub fn new() -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("cortex-bench-{}-{}", std::process::id(), uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir)?;
+        let raw = db::open_raw_db(&dir.join("raw.db"))?;
+        let cons = db::open_consolidated_db(&dir.join("consolidated.db"))?;
+        Ok(Self { dir, raw, cons })
+    }
+
+    /// A store with `n` raw memories: every 50th unconsolidated, last access spread over
+    /// 400 days so micro sleep has a mix to dedup and decay. One consolidated memory per
+    /// 100 raw ones.
+    pub fn seeded(n: usize) -> Result<Self> {
+        let store = Self::new()?;
+        let tx = store.raw.unchecked_transaction()?;
+        for i in 0..n {
+            db::save_memory(&tx, &synthetic_content(i), "observation", &format!("bench-{}", i / 100))?;
+        }
+        tx.execute_batch(
+            "UPDATE memories SET consolidated = (id % 50 != 0),
+                accessed_at = datetime('now', '-' || (id % 400) || ' days');",
+        )?;
+        tx.commit()?;
+
+        let tx = store.cons.unchecked_transaction()?;
+        for i in (0..n).step_by(100) {
+            db::insert_consolidated(&tx, &synthetic_content(i), "pattern", &[i as i64 + 1], 0.7)?;
+        }
+        tx.commit()?;
+        Ok(store)
+    }
+}
+
+impl Drop for Store {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn save(store: &Store, i: usize) -> Result<i64> {
+    db::save_memory(&store.raw, &synthetic_content(i), "observation", "bench")
+}
+
+pub fn recall(store: &Store, i: usize) -> Result<usize> {
+    let query = TOPICS[i % TOPICS.len()].split(' ').next().unwrap_or("tokio");
+    Ok(db::recall_memories(&store.raw, query, 10, &Config::default().recall)?.len())
+}
+
+pub fn micro_sleep(store: &Store) -> Result<u64> {
+    sleep::micro_sleep(&store.raw, &Config::default(), &store.dir)
+}
+
+pub fn context(store: &Store) -> Result<usize> {
+    Ok(context::format_context(&store.cons, &store.raw, None, context::ContextFormat::Full, Some("tokio runtime"), 20, &Config::default().recall, None, &Config::default().context, None)?.len())
+}
+
+pub struct Timing {
+    pub name: String,
+    pub size: usize,
+    pub iterations: usize,
+    pub total: Duration,
+}
+
+impl Timing {
+    pub fn per_op(&self) -> Duration {
+        self.total / self.iterations.max(1) as u32
+    }
+}
+
+fn time(name: &str, size: usize, iterations: usize, mut f: impl FnMut(usize) -> Result<()>) -> Result<Timing> {
+    let start = Instant::now();
+    for i in 0..iterations {
+        f(i)?;
+    }
+    Ok(Timing { name: name.to_string(), size, iterations, total: start.elapsed() })
+}
+
+/// Quick timings of the core paths at each store size, for `cortex bench`. The criterion
+/// suite in `benches/` measures the same workloads with proper statistics.
+pub fn run(sizes: &[usize], iterations: usize) -> Result<Vec<Timing>> {
+    let mut timings = Vec::new();
+    for &size in sizes {
+        eprintln!("Seeding {} memories...", size);
+        let store = Store::seeded(size)?;
+        timings.push(time("save", size, iterations, |i| save(&store, size + i).map(drop))?);
+        timings.push(time("recall", size, iterations, |i| recall(&store, i).map(drop))?);
+        timings.push(time("context", size, iterations, |_| context(&store).map(drop))?);
+        timings.push(time("micro_sleep", size, 1, |_| micro_sleep(&store).map(drop))?);
+    }
+    Ok(timings)
+}
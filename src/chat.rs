@@ -0,0 +1,174 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use crate::ask;
+use crate::audit::Manifest;
+use crate::config::Config;
+use crate::contradiction;
+use crate::db;
+use crate::hooks::{self, HookEvent};
+use crate::llm;
+use crate::oversize;
+use crate::sleep;
+
+/// Memories retrieved from each store per message.
+const RETRIEVE_LIMIT: usize = 8;
+/// Earlier exchanges sent with each message; older ones are dropped.
+const HISTORY_TURNS: usize = 6;
+
+const HELP: &str = "Ask about the project's memory, or state something to remember.
+  /save <text>   save text as an observation
+  /help          show this help
+  /quit          leave (also Ctrl-D)";
+
+/// Everything `cortex chat` needs besides its input and output.
+pub struct Chat<'a> {
+    pub raw: &'a Connection,
+    pub cons: &'a Connection,
+    pub global: Option<&'a Connection>,
+    pub config: &'a Config,
+    /// The store's `.cortex/` directory
+    pub dir: &'a Path,
+    pub session_id: &'a str,
+    pub read_only: bool,
+}
+
+struct Turn {
+    user: String,
+    answer: String,
+}
+
+impl Chat<'_> {
+    /// Read messages from `input` until `/quit` or EOF. Each message is answered from memory
+    /// (as with `cortex ask`); new facts the user states are offered for saving, one by one.
+    /// The conversation itself is not stored.
+    pub async fn run(&self, input: &mut impl BufRead, out: &mut impl Write) -> Result<()> {
+        let mut history: Vec<Turn> = Vec::new();
+        writeln!(out, "cortex chat. /help for commands, /quit to leave.")?;
+        while let Some(line) = prompt(input, out, "> ")? {
+            let line = line.trim();
+            match line.split_once(' ').map_or((line, ""), |(c, rest)| (c, rest.trim())) {
+                ("", _) => continue,
+                ("/quit" | "/exit", _) => break,
+                ("/help", _) => writeln!(out, "{}", HELP)?,
+                ("/save", "") => writeln!(out, "Usage: /save <text>")?,
+                ("/save", text) => self.save(out, text, "observation").await?,
+                (cmd, _) if cmd.starts_with('/') => writeln!(out, "Unknown command {}. /help for commands.", cmd)?,
+                _ => {
+                    if let Some(answer) = self.reply(input, out, line, &history).await? {
+                        history.push(Turn { user: line.to_string(), answer });
+                        if history.len() > HISTORY_TURNS {
+                            history.remove(0);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer one message; returns the answer for the history, or None if the LLM call failed.
+    async fn reply(
+        &self,
+        input: &mut impl BufRead,
+        out: &mut impl Write,
+        message: &str,
+        history: &[Turn],
+    ) -> Result<Option<String>> {
+        let sources = ask::retrieve(self.raw, self.cons, self.global, message, RETRIEVE_LIMIT, &self.config.recall)?;
+        let transcript = history
+            .iter()
+            .map(|t| format!("Developer: {}\nYou: {}", t.user, t.answer))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let memories = if sources.is_empty() { "(none found)".to_string() } else { ask::format_sources(&sources) };
+        let manifest = Manifest::new(self.raw, "chat", sources.iter().map(|s| s.key.clone()).collect());
+        let reply = match llm::audited(manifest, llm::chat_reply(&transcript, message, &memories, self.config)).await {
+            Ok(r) => r,
+            Err(e) => {
+                writeln!(out, "Error: {}", e)?;
+                return Ok(None);
+            }
+        };
+
+        writeln!(out, "{}", reply.answer)?;
+        for s in ask::resolve(sources, &reply.citations) {
+            writeln!(out, "  [{}] {}", s.key, s.content.lines().next().unwrap_or(""))?;
+        }
+        if !self.read_only {
+            for fact in &reply.facts {
+                let question = format!("Save as {}? \"{}\" [y/N] ", fact.r#type, fact.content);
+                match prompt(input, out, &question)? {
+                    Some(a) if a.trim().eq_ignore_ascii_case("y") || a.trim().eq_ignore_ascii_case("yes") => {
+                        self.save(out, &fact.content, &fact.r#type).await?
+                    }
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+        }
+        Ok(Some(reply.answer))
+    }
+
+    async fn save(&self, out: &mut impl Write, content: &str, mem_type: &str) -> Result<()> {
+        if self.read_only {
+            writeln!(out, "Read-only mode: not saving.")?;
+            return Ok(());
+        }
+        let saved = oversize::save_memory(self.raw, content, mem_type, self.session_id, "chat", None, self.config).await?;
+        writeln!(out, "Saved memory #{} (type: {}{})", saved.id, mem_type, saved.note(self.config.content.max_chars))?;
+        for a in contradiction::check(self.raw, self.cons, saved.id, &saved.content, self.config).await? {
+            writeln!(out, "  This contradicts consolidated #{} ({}): {}", a.consolidated_id, a.reason, a.content)?;
+        }
+        hooks::fire(
+            self.config,
+            HookEvent::Save,
+            serde_json::json!({ "id": saved.id, "type": mem_type, "content": saved.content, "tags": [] }),
+        )
+        .await;
+        if db::get_unconsolidated_count(self.raw)? >= self.config.consolidation.auto_micro_threshold as i64 {
+            sleep::auto_micro_sleep(self.raw, self.config, self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Print `text` and read a line; None at EOF.
+pub(crate) fn prompt(input: &mut impl BufRead, out: &mut impl Write, text: &str) -> Result<Option<String>> {
+    write!(out, "{}", text)?;
+    out.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        writeln!(out)?;
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn slash_commands_save_and_quit() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let config = Config::default();
+        let mut chat = Chat { raw: &raw, cons: &cons, global: None, config: &config, dir: Path::new(":memory:"), session_id: "chat", read_only: false };
+
+        let mut out = Vec::new();
+        chat.run(&mut "/save CI caches ~/.cargo\n/bogus\n/quit\n/save never\n".as_bytes(), &mut out).await.unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Saved memory #1 (type: observation)"), "{}", out);
+        assert!(out.contains("Unknown command /bogus"));
+        assert_eq!(db::get_unconsolidated_count(&raw).unwrap(), 1);
+
+        chat.read_only = true;
+        let mut out = Vec::new();
+        chat.run(&mut "/save blocked".as_bytes(), &mut out).await.unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("Read-only mode"));
+        assert_eq!(db::get_unconsolidated_count(&raw).unwrap(), 1);
+    }
+}
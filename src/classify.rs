@@ -0,0 +1,91 @@
+use rusqlite::Connection;
+
+use crate::audit::Manifest;
+use crate::config::{ClassifyMode, Config};
+use crate::llm;
+use crate::similar::word_set;
+
+/// The type for saves that neither give one nor find a fit.
+pub const DEFAULT_TYPE: &str = "observation";
+
+/// Cue words and phrases per type. Antipattern is never guessed: it changes how sleep and
+/// context treat a memory, so it has to be asked for.
+const CUES: &[(&str, &[&str])] = &[
+    (
+        "bugfix",
+        &[
+            "fix", "fixed", "fixes", "bug", "crash", "crashes", "panic", "panics", "broke", "broken", "regression",
+            "workaround", "root cause", "caused by", "was failing", "error",
+        ],
+    ),
+    (
+        "decision",
+        &[
+            "decided", "decide", "decision", "chose", "choose", "going with", "switched to", "switch to", "migrate to",
+            "instead of", "rather than", "adopted", "we will", "agreed",
+        ],
+    ),
+    (
+        "preference",
+        &["prefer", "prefers", "preferred", "preference", "likes", "dislikes", "wants", "favor", "style", "i like"],
+    ),
+    (
+        "pattern",
+        &[
+            "convention", "pattern", "always", "every", "whenever", "usually", "typically", "each", "should", "must",
+            "lives in", "are stored",
+        ],
+    ),
+];
+
+/// The type whose cues `content` matches most, earlier types winning ties; `None` when no cue
+/// matches.
+pub fn heuristic(content: &str) -> Option<&'static str> {
+    let words = word_set(content);
+    // Phrases match against the words joined by single spaces
+    let text: Vec<String> = content.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_lowercase).collect();
+    let text = format!(" {} ", text.join(" "));
+    let mut best: Option<(&'static str, usize)> = None;
+    for (r#type, cues) in CUES {
+        let hits = cues
+            .iter()
+            .filter(|cue| if cue.contains(' ') { text.contains(&format!(" {} ", cue)) } else { words.contains(**cue) })
+            .count();
+        if hits > 0 && best.is_none_or(|(_, most)| hits > most) {
+            best = Some((r#type, hits));
+        }
+    }
+    best.map(|(r#type, _)| r#type)
+}
+
+/// A type for a save that didn't give one, by `[classify] mode`. The LLM falls back to the
+/// heuristic when it's unavailable or answers with something else.
+pub async fn classify(raw: &Connection, content: &str, config: &Config) -> String {
+    let guess = match config.classify.mode {
+        ClassifyMode::Off => None,
+        ClassifyMode::Heuristic => heuristic(content).map(str::to_string),
+        ClassifyMode::Llm => {
+            let manifest = Manifest::new(raw, "classify", vec![]);
+            match llm::audited(manifest, llm::classify_memory(content, config)).await {
+                Ok(r#type) => Some(r#type),
+                Err(_) => heuristic(content).map(str::to_string),
+            }
+        }
+    };
+    guess.unwrap_or_else(|| DEFAULT_TYPE.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guesses_type_from_cue_words() {
+        assert_eq!(heuristic("Fixed the crash on startup: root cause was a stale lockfile"), Some("bugfix"));
+        assert_eq!(heuristic("We decided to go with SQLite instead of Postgres"), Some("decision"));
+        assert_eq!(heuristic("The user prefers tabs over spaces"), Some("preference"));
+        assert_eq!(heuristic("Migrations always live in db/migrations"), Some("pattern"));
+        assert_eq!(heuristic("The staging cluster has 3 nodes"), None);
+        assert_eq!(heuristic("Never use unwrap in library code"), None, "antipatterns are never guessed");
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, Row, params};
+
+use crate::config::StorageConfig;
+
+/// zstd level: fast, and most of the gain on prose-sized text.
+const LEVEL: i32 = 3;
+
+/// What `compact` compressed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Compacted {
+    pub rows: usize,
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+}
+
+/// zstd-compress `text`.
+pub fn pack(text: &str) -> Result<Vec<u8>> {
+    Ok(zstd::encode_all(text.as_bytes(), LEVEL)?)
+}
+
+/// Read a content column that may hold compressed text. Compressed values are stored as BLOBs,
+/// plain text as TEXT, so both read back the same way.
+pub fn text(row: &Row, idx: usize) -> rusqlite::Result<String> {
+    match row.get_ref(idx)? {
+        ValueRef::Blob(bytes) => zstd::decode_all(bytes)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Blob, Box::new(e)))
+            .and_then(|raw| {
+                String::from_utf8(raw)
+                    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Blob, Box::new(e)))
+            }),
+        _ => row.get(idx),
+    }
+}
+
+/// Compress the uncompressed content `[storage]` asks for: archived memories, and attachments
+/// (full text of shortened memories) of at least `compress_attachments_over` bytes. Values that
+/// wouldn't shrink are left as text.
+pub fn compact(conn: &Connection, storage: &StorageConfig) -> Result<Compacted> {
+    let mut compacted = Compacted::default();
+    if storage.compress_archived {
+        compact_table(conn, "archived_memories", "id", 0, &mut compacted)?;
+    }
+    if storage.compress_attachments_over > 0 {
+        compact_table(conn, "attachments", "memory_id", storage.compress_attachments_over, &mut compacted)?;
+    }
+    Ok(compacted)
+}
+
+fn compact_table(conn: &Connection, table: &str, key: &str, min_bytes: usize, compacted: &mut Compacted) -> Result<()> {
+    let rows: Vec<(i64, String)> = {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {key}, content FROM {table} WHERE typeof(content) = 'text' AND length(CAST(content AS BLOB)) >= ?1"
+        ))?;
+        stmt.query_map(params![min_bytes as i64], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?
+    };
+    for (id, content) in rows {
+        let packed = pack(&content)?;
+        if packed.len() >= content.len() {
+            continue;
+        }
+        conn.execute(&format!("UPDATE {table} SET content = ?1 WHERE {key} = ?2"), params![packed, id])?;
+        compacted.rows += 1;
+        compacted.bytes_before += content.len();
+        compacted.bytes_after += packed.len();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn compresses_archived_content_transparently() {
+        let conn = db::open_raw_db(std::path::Path::new(":memory:")).unwrap();
+        let long = "The deploy script retries flaky uploads three times before failing. ".repeat(40);
+        let id = db::save_memory(&conn, &long, "observation", "s").unwrap();
+        let short = db::save_memory(&conn, "tiny", "observation", "s").unwrap();
+        db::mark_consolidated(&conn, &[id, short]).unwrap();
+        conn.execute(
+            "INSERT INTO archived_memories
+                 (id, content, type, created_at, accessed_at, access_count, consolidated, importance, session_id, entity_ids, tags, refs)
+             SELECT id, content, type, created_at, accessed_at, access_count, consolidated, importance, session_id, entity_ids, tags, refs
+             FROM memories",
+            [],
+        )
+        .unwrap();
+        conn.execute("DELETE FROM memories", []).unwrap();
+
+        let storage = StorageConfig::default();
+        let compacted = compact(&conn, &storage).unwrap();
+        assert_eq!(compacted.rows, 1);
+        assert!(compacted.bytes_after < compacted.bytes_before / 5);
+        // Already-compressed rows are skipped
+        assert_eq!(compact(&conn, &storage).unwrap(), Compacted::default());
+
+        let sources = db::get_source_memories(&conn, &[id, short]).unwrap();
+        assert_eq!(sources[0].0.content, long);
+        assert_eq!(sources[1].0.content, "tiny");
+    }
+}
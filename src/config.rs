@@ -1,54 +1,765 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::query::Tokenizer;
+use crate::sensitivity::Sensitivity;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default = "default_consolidation")]
     pub consolidation: ConsolidationConfig,
+    #[serde(default)]
+    pub mcp: McpConfig,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub templates: BTreeMap<String, TemplateConfig>,
+    #[serde(default)]
+    pub adr: AdrConfig,
+    #[serde(default)]
+    pub ci: CiConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub models: BTreeMap<String, ModelAlias>,
+    #[serde(default)]
+    pub llm: LlmConfig,
+    #[serde(default)]
+    pub quality: QualityConfig,
+    #[serde(default)]
+    pub skills: SkillsConfig,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub content: ContentConfig,
+    #[serde(default)]
+    pub recall: RecallConfig,
+    /// Project jargon: `[synonyms]` maps a term to comma-separated alternatives, e.g.
+    /// `db = "database, sqlite"`. Recall and context queries also search the alternatives.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub synonyms: BTreeMap<String, String>,
+    #[serde(default)]
+    pub context: ContextConfig,
+    #[serde(default)]
+    pub wake: WakeConfig,
+    #[serde(default)]
+    pub dream: DreamConfig,
+    #[serde(default)]
+    pub contradictions: ContradictionConfig,
+    #[serde(default)]
+    pub classify: ClassifyConfig,
+    #[serde(default)]
+    pub trust: TrustConfig,
+    #[serde(default)]
+    pub sensitivity: SensitivityConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub snapshots: SnapshotConfig,
+    #[serde(default)]
+    pub insights: InsightsConfig,
+    #[serde(default)]
+    pub db: DbConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConsolidationConfig {
     #[serde(default = "default_threshold")]
     pub auto_micro_threshold: u32,
+    /// Least time between automatic micro sleeps of one store in the MCP server (0 = no limit)
+    #[serde(default = "default_auto_micro_interval")]
+    pub auto_micro_interval_mins: u64,
     #[serde(default = "default_decay")]
     pub decay_threshold: f64,
     #[serde(default = "default_model")]
     pub model: String,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct McpConfig {
+    /// Reject save/sleep/dream/edit/delete so the server only serves reads
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// A structured entry template: `[templates.<name>]` with a memory type and markdown sections.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateConfig {
+    #[serde(default = "default_template_type")]
+    pub r#type: String,
+    pub sections: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdrConfig {
+    /// ADR directory, relative to the project root
+    #[serde(default = "default_adr_dir")]
+    pub dir: String,
+    /// Write a numbered ADR file whenever a decision is saved
+    #[serde(default)]
+    pub auto_export: bool,
+}
+
+impl Default for AdrConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_adr_dir(),
+            auto_export: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CiConfig {
+    /// Commit the updated consolidated.db and skills after `cortex sleep --from-ci`
+    #[serde(default)]
+    pub commit: bool,
+    #[serde(default = "default_ci_commit_message")]
+    pub commit_message: String,
+}
+
+impl Default for CiConfig {
+    fn default() -> Self {
+        Self {
+            commit: false,
+            commit_message: default_ci_commit_message(),
+        }
+    }
+}
+
+/// Commands or URLs run on memory events. Entries starting with `http://`/`https://` receive
+/// the JSON payload as a POST; anything else runs via `sh -c` with the payload on stdin.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HooksConfig {
+    #[serde(default)]
+    pub on_save: Vec<String>,
+    #[serde(default)]
+    pub on_sleep_complete: Vec<String>,
+    #[serde(default)]
+    pub on_global_promotion: Vec<String>,
+}
+
+/// Sampling and request settings for LLM calls. `[llm.dream]` overrides any of them for dream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmConfig {
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<String>,
+    /// Per-request timeout in seconds
+    #[serde(default = "default_llm_timeout")]
+    pub timeout_secs: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dream: Option<LlmOverrides>,
+}
+
+/// Fields of `[llm]` that `[llm.dream]` may override.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LlmOverrides {
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub stop_sequences: Option<Vec<String>>,
+    pub timeout_secs: Option<u64>,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            max_tokens: default_max_tokens(),
+            temperature: None,
+            top_p: None,
+            stop_sequences: vec![],
+            timeout_secs: default_llm_timeout(),
+            dream: None,
+        }
+    }
+}
+
+impl LlmConfig {
+    /// Current models reject requests that set both `temperature` and `top_p`, so catch that
+    /// when the config is read rather than on the first LLM call.
+    pub fn validate(&self) -> Result<()> {
+        if self.temperature.is_some() && self.top_p.is_some() {
+            anyhow::bail!("[llm] sets both temperature and top_p; set only one");
+        }
+        let dream = self.for_dream();
+        if dream.temperature.is_some() && dream.top_p.is_some() {
+            anyhow::bail!("[llm.dream] ends up with both temperature and top_p (with [llm]'s values); set only one");
+        }
+        Ok(())
+    }
+
+    /// Settings for dream calls: `[llm]` with `[llm.dream]` applied on top.
+    pub fn for_dream(&self) -> LlmConfig {
+        let Some(o) = &self.dream else {
+            return self.clone();
+        };
+        LlmConfig {
+            max_tokens: o.max_tokens.unwrap_or(self.max_tokens),
+            temperature: o.temperature.or(self.temperature),
+            top_p: o.top_p.or(self.top_p),
+            stop_sequences: o.stop_sequences.clone().unwrap_or_else(|| self.stop_sequences.clone()),
+            timeout_secs: o.timeout_secs.unwrap_or(self.timeout_secs),
+            dream: None,
+        }
+    }
+}
+
+/// Checks applied to a quick-sleep LLM result before it is written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QualityConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Fail the sleep if any item fails (observations stay unprocessed), instead of skipping that item
+    #[serde(default)]
+    pub strict: bool,
+    #[serde(default = "default_min_content_chars")]
+    pub min_content_chars: usize,
+    #[serde(default = "default_max_content_chars")]
+    pub max_content_chars: usize,
+    #[serde(default = "default_max_skill_chars")]
+    pub max_skill_chars: usize,
+    /// Send failed checks and unresolved contradictions back for one refinement turn before applying
+    #[serde(default)]
+    pub refine: bool,
+    /// After each quick sleep, have the LLM grade coverage and faithfulness (shown in `cortex stats`)
+    #[serde(default)]
+    pub evaluate: bool,
+    /// Share of a consolidation's words that must appear in the observations it cites (0 disables
+    /// the hallucination guard)
+    #[serde(default = "default_min_source_overlap")]
+    pub min_source_overlap: f64,
+    /// What the hallucination guard does with a consolidation under `min_source_overlap`
+    #[serde(default)]
+    pub on_unsupported: Unsupported,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Unsupported {
+    /// Drop it; its observations are still marked processed
+    #[default]
+    Reject,
+    /// Keep it at half its confidence, so it ranks below supported memories
+    Downrank,
+}
+
+impl Default for QualityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            strict: false,
+            min_content_chars: default_min_content_chars(),
+            max_content_chars: default_max_content_chars(),
+            max_skill_chars: default_max_skill_chars(),
+            refine: false,
+            evaluate: false,
+            min_source_overlap: default_min_source_overlap(),
+            on_unsupported: Unsupported::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkillsConfig {
+    /// Approximate token budget per skill; dream condenses larger skills and exports truncate them
+    #[serde(default = "default_skill_max_tokens")]
+    pub max_tokens: usize,
+}
+
+impl Default for SkillsConfig {
+    fn default() -> Self {
+        Self {
+            max_tokens: default_skill_max_tokens(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchConfig {
+    /// FTS5 tokenizer: `porter` (English stemming), `unicode61`, or `trigram` (substrings, CJK).
+    /// Existing stores are reindexed by `cortex reindex` or on the next `cortex wake`.
+    #[serde(default)]
+    pub tokenizer: Tokenizer,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentConfig {
+    /// Longest memory stored as-is; longer content is shortened and the full text kept as an
+    /// attachment. 0 disables the limit.
+    #[serde(default = "default_content_max_chars")]
+    pub max_chars: usize,
+    /// How to shorten oversized content
+    #[serde(default)]
+    pub oversize: Oversize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Oversize {
+    /// Keep the first `max_chars` characters
+    #[default]
+    Truncate,
+    /// Ask the LLM for a summary at save time; truncated instead when no LLM is available, and
+    /// summarized by the next sleep
+    Summarize,
+}
+
+impl Default for ContentConfig {
+    fn default() -> Self {
+        Self {
+            max_chars: default_content_max_chars(),
+            oversize: Oversize::default(),
+        }
+    }
+}
+
+/// Ranking for FTS recall (`[recall]`), used by `cortex recall`, `cortex context`, wake, ask, and
+/// the MCP tools. A result's score is
+/// `bm25^bm25_weight × importance^importance_weight × recency`, where importance is a raw
+/// memory's importance or a consolidated memory's confidence, and recency is
+/// `1 / (1 + age_days / half_life)`: it halves after `half_life` days.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecallConfig {
+    /// Exponent on BM25 text relevance; raise it to favor the closest text match
+    #[serde(default = "default_weight")]
+    pub bm25_weight: f64,
+    /// Exponent on importance/confidence; 0 ignores them
+    #[serde(default = "default_weight")]
+    pub importance_weight: f64,
+    /// Days since a consolidated memory was last updated at which its recency factor halves
+    /// (0 disables recency)
+    #[serde(default = "default_half_life_days")]
+    pub half_life_days: f64,
+    /// Days since a raw memory was last accessed at which its recency factor halves
+    /// (0 disables recency)
+    #[serde(default = "default_raw_half_life_days")]
+    pub raw_half_life_days: f64,
+    /// Score multiplier for consolidated (including global) memories when they are ranked
+    /// together with raw memories
+    #[serde(default = "default_weight")]
+    pub consolidated_boost: f64,
+    /// Also search paraphrases of the query and fuse the results (`cortex recall`, `cortex_recall`)
+    #[serde(default)]
+    pub expand: Expansion,
+    /// Importance added to a raw memory when a later save in the same MCP session reports that
+    /// it worked (0 disables)
+    #[serde(default = "default_confirm_boost")]
+    pub confirm_boost: f64,
+}
+
+/// Where `[recall] expand` gets query paraphrases from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Expansion {
+    /// Search the query as written
+    #[default]
+    Off,
+    /// Swap words for synonyms from a bundled table of developer vocabulary; no network
+    Synonyms,
+    /// Ask the LLM for paraphrases; falls back to synonyms when it isn't available
+    Llm,
+}
+
+impl Default for RecallConfig {
+    fn default() -> Self {
+        Self {
+            bm25_weight: default_weight(),
+            importance_weight: default_weight(),
+            half_life_days: default_half_life_days(),
+            raw_half_life_days: default_raw_half_life_days(),
+            consolidated_boost: default_weight(),
+            expand: Expansion::default(),
+            confirm_boost: default_confirm_boost(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextConfig {
+    /// Approximate token budget for `cortex context`, `cortex_context`, and wake; the least
+    /// relevant items are left out to fit (0 = no limit)
+    #[serde(default)]
+    pub max_tokens: usize,
+    /// Project patterns in compact context
+    #[serde(default = "default_compact_patterns")]
+    pub compact_patterns: usize,
+    /// Global patterns in compact context
+    #[serde(default = "default_compact_global")]
+    pub compact_global: usize,
+    /// Entity names in compact context
+    #[serde(default = "default_compact_patterns")]
+    pub compact_entities: usize,
+    #[serde(default)]
+    pub compact_order: CompactOrder,
+    /// Between the patterns (and avoid rules) of compact context
+    #[serde(default = "default_compact_separator")]
+    pub compact_separator: String,
+    /// Named views for `--profile`, overriding the built-in reviewer and implementer
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, ContextProfile>,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            max_tokens: 0,
+            compact_patterns: default_compact_patterns(),
+            compact_global: default_compact_global(),
+            compact_entities: default_compact_patterns(),
+            compact_order: CompactOrder::default(),
+            compact_separator: default_compact_separator(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+/// `[context.profiles.<name>]`: the context one kind of agent gets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContextProfile {
+    /// Consolidated memory types shown in patterns and global knowledge (empty = all)
+    #[serde(default)]
+    pub types: Vec<String>,
+    /// Sections shown (empty = all); stats are always shown
+    #[serde(default)]
+    pub sections: Vec<ContextSection>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextSection {
+    Entities,
+    Patterns,
+    Skills,
+    Global,
+    GlobalSkills,
+    Avoid,
+}
+
+/// Which patterns compact context picks, in what order. Ties always go to the lower ID, so the
+/// same store gives the same text every time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompactOrder {
+    /// Highest confidence first; changes only when a sleep does
+    #[default]
+    Confidence,
+    /// Most recently updated first
+    Recency,
+    /// Oldest first: new memories only ever append, which keeps a cached prompt prefix valid
+    Stable,
+}
+
+/// How `cortex wake` catches up on unconsolidated memories before serving context.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WakeConfig {
+    /// Unconsolidated memories needed before wake consolidates; a smaller backlog waits for
+    /// the next sleep
+    #[serde(default = "default_min_backlog")]
+    pub min_backlog: i64,
+    /// Seconds wake spends consolidating before serving context without it (0 = no limit)
+    #[serde(default)]
+    pub max_seconds: u64,
+}
+
+impl Default for WakeConfig {
+    fn default() -> Self {
+        Self { min_backlog: default_min_backlog(), max_seconds: 0 }
+    }
+}
+
+/// When a quick sleep starts a dream on its own (`dream::due`). The same policy runs the
+/// global store's dream after promotions, from the global store's config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DreamConfig {
+    /// Dream automatically when one is due
+    #[serde(default = "default_true")]
+    pub auto: bool,
+    /// Consolidated memories a store needs before it dreams
+    #[serde(default = "default_dream_min_entries")]
+    pub min_entries: i64,
+    /// Days between automatic dreams, at least
+    #[serde(default = "default_dream_min_days")]
+    pub min_days: i64,
+    /// Days after which a store dreams without churn, as long as something changed (0 = never)
+    #[serde(default = "default_dream_max_days")]
+    pub max_days: i64,
+    /// Consolidated memories added or updated since the last dream that make one due
+    #[serde(default = "default_dream_min_changes")]
+    pub min_changes: i64,
+    /// Contradictions flagged at save time since the last dream that make one due
+    #[serde(default = "default_dream_min_contradictions")]
+    pub min_contradictions: i64,
+}
+
+impl Default for DreamConfig {
+    fn default() -> Self {
+        Self {
+            auto: true,
+            min_entries: default_dream_min_entries(),
+            min_days: default_dream_min_days(),
+            max_days: default_dream_max_days(),
+            min_changes: default_dream_min_changes(),
+            min_contradictions: default_dream_min_contradictions(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContradictionConfig {
+    /// How a new save is checked against close consolidated matches that say the opposite
+    #[serde(default)]
+    pub check: ContradictionCheck,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContradictionCheck {
+    /// No check at save time; the next sleep still looks for contradictions
+    Off,
+    /// Negations and opposite terms ("enable"/"disable") in otherwise matching text
+    #[default]
+    Heuristic,
+    /// Ask the LLM about each close match; the heuristic decides when it isn't available
+    Llm,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClassifyConfig {
+    /// How saves without a type get one
+    #[serde(default)]
+    pub mode: ClassifyMode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClassifyMode {
+    /// Save them as observations
+    Off,
+    /// Cue words ("fixed", "decided", "prefers", "always") pick bugfix, decision, preference, or pattern
+    #[default]
+    Heuristic,
+    /// Ask the LLM; the heuristic decides when it isn't available
+    Llm,
+}
+
+/// Which saves are quarantined: memories from an untrusted source stay out of recall and
+/// context until a sleep consolidates them or `cortex trust` approves them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustConfig {
+    /// Save sources to distrust: `cli`, `mcp`, `chat`, `ci` (`--ci`), `unattended` (`--unattended`)
+    #[serde(default = "default_untrusted_sources")]
+    pub untrusted_sources: Vec<String>,
+}
+
+impl Default for TrustConfig {
+    fn default() -> Self {
+        Self { untrusted_sources: default_untrusted_sources() }
+    }
+}
+
+impl TrustConfig {
+    pub fn trusts(&self, source: &str) -> bool {
+        !self.untrusted_sources.iter().any(|s| s == source)
+    }
+}
+
+fn default_untrusted_sources() -> Vec<String> {
+    vec!["ci".to_string(), "unattended".to_string()]
+}
+
+/// Sensitivity labels: what new memories get, and the most sensitive level that may leave
+/// this project's raw.db.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SensitivityConfig {
+    /// Label for saves that don't pass `--sensitivity`
+    #[serde(default)]
+    pub default: Sensitivity,
+    /// Most sensitive level promoted to the global ~/.cortex/ store
+    #[serde(default)]
+    pub global_max: Sensitivity,
+    /// Most sensitive level written to shared files: consolidated.db, skills, and ADR exports
+    #[serde(default)]
+    pub share_max: Sensitivity,
+}
+
+/// Store size budget, enforced after each sleep by evicting the lowest-scoring memories.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LimitsConfig {
+    /// Most consolidated memories to keep (0 = no limit); antipatterns are never evicted
+    #[serde(default = "default_max_consolidated")]
+    pub max_consolidated: usize,
+    /// Most raw memories to keep (0 = no limit); evicted ones are archived, and only
+    /// already-consolidated memories are eligible
+    #[serde(default = "default_max_raw")]
+    pub max_raw: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self { max_consolidated: default_max_consolidated(), max_raw: default_max_raw() }
+    }
+}
+
+/// zstd compression of raw.db content that is rarely read (see `compress`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageConfig {
+    /// Compress memories as they are archived out of raw search
+    #[serde(default = "default_true")]
+    pub compress_archived: bool,
+    /// Compress attachments (full text of shortened memories) of at least this many bytes (0 = off)
+    #[serde(default)]
+    pub compress_attachments_over: usize,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self { compress_archived: true, compress_attachments_over: 0 }
+    }
+}
+
+/// Point-in-time copies of both databases in `.cortex/snapshots/` (see `snapshot`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotConfig {
+    /// Snapshot before each dream, `delete`, `edit`, `import`, `merge`, `reset`, and `doctor --fix`
+    #[serde(default = "default_true")]
+    pub auto: bool,
+    /// Automatic snapshots to keep (0 = all); manual ones are never pruned
+    #[serde(default = "default_snapshot_keep")]
+    pub keep: usize,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self { auto: true, keep: default_snapshot_keep() }
+    }
+}
+
+/// Local recall analytics for `cortex insights`. Off unless opted in; the log stays in raw.db.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InsightsConfig {
+    /// Log each recall's query and hits to raw.db's `query_log`
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Storage backend and SQLite connection options (see `storage` and `extensions`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DbConfig {
+    #[serde(default)]
+    pub backend: Backend,
+    /// Remote database URL for `backend = "libsql"`, e.g. `libsql://team-memory.turso.io`
+    #[serde(default)]
+    pub url: String,
+    /// Environment variable holding the remote's auth token
+    #[serde(default = "default_auth_token_env")]
+    pub auth_token_env: String,
+    /// SQLite extensions to load into every connection, e.g. sqlite-vec. Read only from
+    /// ~/.cortex/config.toml, since a project's config is committed and shouldn't run native code
+    #[serde(default)]
+    pub extensions: Vec<PathBuf>,
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        Self { backend: Backend::default(), url: String::new(), auth_token_env: default_auth_token_env(), extensions: vec![] }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Memories live only in the local databases
+    #[default]
+    Sqlite,
+    /// Consolidated memories live in a shared libsql server or Turso database; the local
+    /// consolidated.db is a replica of it
+    Libsql,
+}
+
+/// A model short name: `[models.<alias>]` with its Bedrock model ID. The ID is given the
+/// cross-region profile prefix for AWS_REGION (us/eu/apac) unless `regions` overrides it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelAlias {
+    /// Base Bedrock model ID, e.g. `anthropic.claude-haiku-4-5-20251001-v1:0`
+    #[serde(default)]
+    pub bedrock: String,
+    /// Full profile IDs per prefix, e.g. `apac = "apac.anthropic..."`
+    #[serde(default)]
+    pub regions: BTreeMap<String, String>,
+}
+
 fn default_consolidation() -> ConsolidationConfig {
     ConsolidationConfig::default()
 }
 fn default_threshold() -> u32 { 10 }
+fn default_auto_micro_interval() -> u64 { 5 }
 fn default_decay() -> f64 { 0.1 }
 fn default_model() -> String { "claude-haiku-4-5".to_string() }
+fn default_adr_dir() -> String { "docs/adr".to_string() }
+fn default_ci_commit_message() -> String { "Update cortex consolidated memories".to_string() }
+fn default_max_tokens() -> u32 { 8192 }
+fn default_llm_timeout() -> u64 { 120 }
+fn default_true() -> bool { true }
+fn default_auth_token_env() -> String { "CORTEX_DB_TOKEN".to_string() }
+fn default_min_content_chars() -> usize { 10 }
+fn default_max_content_chars() -> usize { 4000 }
+fn default_max_skill_chars() -> usize { 20000 }
+fn default_min_source_overlap() -> f64 { 0.25 }
+fn default_max_consolidated() -> usize { 500 }
+fn default_max_raw() -> usize { 5000 }
+fn default_snapshot_keep() -> usize { 10 }
+fn default_skill_max_tokens() -> usize { 2000 }
+fn default_content_max_chars() -> usize { 8000 }
+fn default_weight() -> f64 { 1.0 }
+fn default_half_life_days() -> f64 { 30.0 }
+fn default_raw_half_life_days() -> f64 { 1.0 }
+fn default_confirm_boost() -> f64 { 0.1 }
+fn default_compact_patterns() -> usize { 10 }
+fn default_compact_global() -> usize { 5 }
+fn default_compact_separator() -> String { "; ".to_string() }
+fn default_min_backlog() -> i64 { 1 }
+fn default_dream_min_entries() -> i64 { 5 }
+fn default_dream_min_days() -> i64 { 1 }
+fn default_dream_max_days() -> i64 { 7 }
+fn default_dream_min_changes() -> i64 { 10 }
+fn default_dream_min_contradictions() -> i64 { 3 }
+fn default_template_type() -> String { "observation".to_string() }
 
 impl Default for ConsolidationConfig {
     fn default() -> Self {
         Self {
             auto_micro_threshold: default_threshold(),
+            auto_micro_interval_mins: default_auto_micro_interval(),
             decay_threshold: default_decay(),
             model: default_model(),
         }
     }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            consolidation: ConsolidationConfig::default(),
-        }
-    }
-}
-
 pub fn load_config(cortex_dir: &Path) -> Result<Config> {
-    let config_path = cortex_dir.join("config.toml");
+    let config_path = crate::init::config_path(cortex_dir);
     if config_path.exists() {
         let content = std::fs::read_to_string(&config_path)?;
-        Ok(toml::from_str(&content)?)
+        let config: Config = toml::from_str(&content)?;
+        config.llm.validate().with_context(|| format!("Invalid {}", config_path.display()))?;
+        Ok(config)
     } else {
         Ok(Config::default())
     }
 }
+
+/// Fail with a clear error when a mutating operation is attempted in read-only mode.
+pub fn ensure_writable(read_only: bool, action: &str) -> Result<()> {
+    if read_only {
+        anyhow::bail!("Cannot {}: cortex is running in read-only mode.", action);
+    }
+    Ok(())
+}
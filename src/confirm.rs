@@ -0,0 +1,107 @@
+use anyhow::Result;
+use rusqlite::{Connection, params};
+
+use crate::similar::word_set;
+
+/// Words that mark a save as reporting that something worked.
+const SUCCESS: &[&str] = &[
+    "works", "worked", "working", "fixed", "resolved", "solved", "succeeded", "success", "successful", "passes",
+    "passed", "passing", "confirmed", "helped",
+];
+
+/// Words of 4+ characters a save must share with a recalled memory to be about it.
+const SHARED_WORDS: usize = 2;
+
+/// Session recalls kept; older ones can't be confirmed any more.
+const KEEP_DAYS: u32 = 7;
+
+/// Remember that `session` recalled these memories. Global memories (negative IDs) are skipped.
+pub fn record_recalls(raw: &Connection, session: &str, ids: &[i64]) -> Result<()> {
+    raw.execute(
+        "DELETE FROM session_recalls WHERE at < strftime('%Y-%m-%dT%H:%M:%SZ', 'now', ?1)",
+        params![format!("-{} days", KEEP_DAYS)],
+    )?;
+    let mut stmt = raw.prepare(
+        "INSERT INTO session_recalls (session_id, memory_id) VALUES (?1, ?2)
+         ON CONFLICT (session_id, memory_id) DO UPDATE SET at = excluded.at",
+    )?;
+    for id in ids.iter().filter(|id| **id > 0) {
+        stmt.execute(params![session, id])?;
+    }
+    Ok(())
+}
+
+/// Whether `content` reports a success.
+fn reports_success(content: &str) -> bool {
+    let words = word_set(content);
+    SUCCESS.iter().any(|w| words.contains(*w))
+}
+
+fn related(a: &str, b: &str) -> bool {
+    let b = word_set(b);
+    word_set(a).iter().filter(|w| w.len() >= 4 && !SUCCESS.contains(&w.as_str()) && b.contains(*w)).count() >= SHARED_WORDS
+}
+
+/// After `session` saved memory `saved_id`: if it reports a success and is about memories the
+/// session recalled, link each of them to it and raise its importance by `boost` (once per
+/// pair, capped at 1.0). Returns the confirmed memory IDs.
+pub fn confirm(raw: &Connection, session: &str, saved_id: i64, content: &str, boost: f64) -> Result<Vec<i64>> {
+    if boost <= 0.0 || !reports_success(content) {
+        return Ok(vec![]);
+    }
+    let mut stmt = raw.prepare(
+        "SELECT m.id, m.content FROM session_recalls r JOIN memories m ON m.id = r.memory_id
+         WHERE r.session_id = ?1 AND m.id != ?2 ORDER BY r.at DESC",
+    )?;
+    let recalled = stmt
+        .query_map(params![session, saved_id], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut confirmed = Vec::new();
+    for (id, recalled_content) in recalled {
+        if !related(content, &recalled_content) {
+            continue;
+        }
+        let linked =
+            raw.execute("INSERT OR IGNORE INTO confirmations (memory_id, confirmed_by) VALUES (?1, ?2)", params![id, saved_id])?;
+        if linked > 0 {
+            raw.execute("UPDATE memories SET importance = MIN(1.0, importance + ?1) WHERE id = ?2", params![boost, id])?;
+            confirmed.push(id);
+        }
+    }
+    Ok(confirmed)
+}
+
+/// The saves that confirmed memory `id`, oldest first.
+pub fn confirmed_by(raw: &Connection, id: i64) -> Result<Vec<i64>> {
+    let mut stmt = raw.prepare("SELECT confirmed_by FROM confirmations WHERE memory_id = ?1 ORDER BY at, confirmed_by")?;
+    let ids = stmt.query_map(params![id], |r| r.get(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use std::path::Path;
+
+    #[test]
+    fn success_saves_boost_related_recalls() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let hint = db::save_memory(&raw, "Use eager loading for UserList queries", "pattern", "s1").unwrap();
+        let other = db::save_memory(&raw, "Deploys go through staging", "decision", "s1").unwrap();
+        record_recalls(&raw, "mcp-1", &[hint, other, -4]).unwrap();
+
+        let unrelated = db::save_memory(&raw, "The release build passed", "observation", "mcp-1").unwrap();
+        assert!(confirm(&raw, "mcp-1", unrelated, "The release build passed", 0.1).unwrap().is_empty());
+        let elsewhere = db::save_memory(&raw, "Eager loading fixed UserList queries", "observation", "mcp-2").unwrap();
+        assert!(confirm(&raw, "mcp-2", elsewhere, "Eager loading fixed UserList queries", 0.1).unwrap().is_empty());
+
+        let content = "Eager loading worked: UserList queries dropped from 40 to 2";
+        let save = db::save_memory(&raw, content, "observation", "mcp-1").unwrap();
+        assert_eq!(confirm(&raw, "mcp-1", save, content, 0.1).unwrap(), vec![hint]);
+        assert!(confirm(&raw, "mcp-1", save, content, 0.1).unwrap().is_empty(), "one boost per pair");
+        let importance: f64 = raw.query_row("SELECT importance FROM memories WHERE id = ?1", [hint], |r| r.get(0)).unwrap();
+        assert!((importance - 0.6).abs() < 1e-9, "{}", importance);
+        assert_eq!(confirmed_by(&raw, hint).unwrap(), vec![save]);
+    }
+}
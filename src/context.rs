@@ -1,28 +1,150 @@
 use anyhow::Result;
 use rusqlite::Connection;
+use serde::Serialize;
 
+use crate::antipattern::{self, Antipattern};
+use std::collections::BTreeMap;
+
+use crate::config::{CompactOrder, ContextConfig, ContextProfile, ContextSection, RecallConfig};
 use crate::db;
-use crate::models::{ConsolidatedMemory, Entity, Relationship, Skill, Stats};
+use crate::expand;
+use crate::models::{ConsolidatedMemory, Entity, Memory, Relationship, Skill, Stats};
+use crate::sensitivity::Sensitivity;
+
+/// Recall for `cortex recall` and the `cortex_recall` MCP tool: memories about a matching
+/// entity (and its neighbors) if there is one, otherwise raw FTS results; then global
+/// consolidated memories, shown as `[global]` with negative IDs. FTS results from both
+/// stores are ranked together by their `[recall]` scores. Returned raw memories count as
+/// accessed.
+pub fn recall(
+    raw_conn: &Connection,
+    global_cons_conn: Option<&Connection>,
+    query: &str,
+    limit: usize,
+    ranking: &RecallConfig,
+) -> Result<Vec<Memory>> {
+    recall_fused(raw_conn, global_cons_conn, &[query.to_string()], limit, ranking)
+}
+
+/// `recall` for several phrasings of one query (see `expand`): each is recalled on its own
+/// and the lists are fused by reciprocal rank, so memories found by several phrasings rise.
+pub fn recall_fused(
+    raw_conn: &Connection,
+    global_cons_conn: Option<&Connection>,
+    queries: &[String],
+    limit: usize,
+    ranking: &RecallConfig,
+) -> Result<Vec<Memory>> {
+    let lists = queries
+        .iter()
+        .map(|q| ranked(raw_conn, global_cons_conn, q, limit, ranking))
+        .collect::<Result<Vec<_>>>()?;
+    let memories = expand::fuse(lists, limit.max(1));
+    let raw_ids: Vec<i64> = memories.iter().map(|m| m.id).filter(|id| *id > 0).collect();
+    db::touch_memories(raw_conn, &raw_ids)?;
+    Ok(memories)
+}
+
+fn ranked(
+    raw_conn: &Connection,
+    global_cons_conn: Option<&Connection>,
+    query: &str,
+    limit: usize,
+    ranking: &RecallConfig,
+) -> Result<Vec<Memory>> {
+    let by_entity = db::recall_by_entity(raw_conn, query, true, limit)?;
+    let mut scored: Vec<(Memory, f64)> = if by_entity.is_empty() {
+        db::search_memories_scored(raw_conn, query, limit, ranking)?
+    } else {
+        vec![]
+    };
+    if let Some(gc) = global_cons_conn {
+        for (m, score) in db::search_consolidated_scored(gc, query, limit, ranking).unwrap_or_default() {
+            scored.push((
+                Memory {
+                    id: -m.id,
+                    content: format!("[global] {}", m.content),
+                    r#type: m.r#type,
+                    created_at: m.created_at,
+                    accessed_at: m.updated_at,
+                    access_count: m.access_count,
+                    consolidated: true,
+                    importance: m.confidence,
+                    session_id: None,
+                    entity_ids: vec![],
+                    tags: vec![],
+                    refs: m.refs,
+                    sensitivity: m.sensitivity,
+                    no_llm: m.no_llm,
+                },
+                score,
+            ));
+        }
+    }
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let mut memories = by_entity;
+    memories.extend(scored.into_iter().map(|(m, _)| m));
+    memories.truncate(limit.max(1));
+    Ok(memories)
+}
 
+/// How `format_context` renders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextFormat {
+    /// Markdown sections
+    Full,
+    /// One line
+    Compact,
+    /// Sections in XML tags for a system prompt, with memory IDs an agent can cite or ask to
+    /// delete
+    Xml,
+}
+
+impl ContextFormat {
+    /// From the `compact` and `xml` options of `cortex context` and `cortex_context`.
+    pub fn from_flags(compact: bool, xml: bool) -> Self {
+        match (compact, xml) {
+            (_, true) => ContextFormat::Xml,
+            (true, false) => ContextFormat::Compact,
+            (false, false) => ContextFormat::Full,
+        }
+    }
+}
+
+/// Memory context for agents. With `max_sensitivity`, consolidated memories labeled above it
+/// are left out. `settings` shapes compact context, and with `max_tokens` above 0, items are
+/// dropped until the estimated size fits (see `Parts::drop_least_relevant`). A `profile` (see
+/// `find_profile`) narrows the memory types and sections before either.
+#[allow(clippy::too_many_arguments)]
 pub fn format_context(
     cons_conn: &Connection,
     raw_conn: &Connection,
     global_cons_conn: Option<&Connection>,
-    compact: bool,
+    format: ContextFormat,
     query: Option<&str>,
     limit: usize,
+    ranking: &RecallConfig,
+    max_sensitivity: Option<Sensitivity>,
+    settings: &ContextConfig,
+    profile: Option<&ContextProfile>,
 ) -> Result<String> {
+    let wanted = |m: &ConsolidatedMemory| profile.is_none_or(|p| p.types.is_empty() || p.types.contains(&m.r#type));
+
     // Load memories - either search-based (relevant) or all
     let consolidated = match query {
-        Some(q) if !q.trim().is_empty() => db::search_consolidated(cons_conn, q, limit)?,
+        Some(q) if !q.trim().is_empty() => db::search_consolidated(cons_conn, q, limit, ranking)?,
         _ => {
             // No query: load top N by recency
             let all = db::get_all_consolidated(cons_conn)?;
-            all.into_iter().take(limit).collect()
+            all.into_iter().filter(|m| wanted(m)).take(limit).collect()
         }
     };
 
-    let skills = db::get_all_skills(cons_conn)?;
+    // With a query, only the skills it matches, each with a snippet
+    let skills = match query {
+        Some(q) if !q.trim().is_empty() => db::search_skills(cons_conn, q, limit)?,
+        _ => db::get_all_skills(cons_conn)?,
+    };
     let stats = db::get_stats(raw_conn, cons_conn)?;
 
     // Load entities - either query-relevant or top by access
@@ -49,27 +171,277 @@ pub fn format_context(
     let global_consolidated = match global_cons_conn {
         Some(gc) => {
             match query {
-                Some(q) if !q.trim().is_empty() => db::search_consolidated(gc, q, limit / 2).unwrap_or_default(),
+                Some(q) if !q.trim().is_empty() => db::search_consolidated(gc, q, limit / 2, ranking).unwrap_or_default(),
                 _ => {
                     let all = db::get_all_consolidated(gc).unwrap_or_default();
-                    all.into_iter().take(limit / 3).collect()
+                    all.into_iter().filter(|m| wanted(m)).take(limit / 3).collect()
                 }
             }
         }
         None => vec![],
     };
-    let global_skills = match global_cons_conn {
-        Some(gc) => db::get_all_skills(gc).unwrap_or_default(),
-        None => vec![],
+    let global_skills = match (global_cons_conn, query) {
+        (Some(gc), Some(q)) if !q.trim().is_empty() => db::search_skills(gc, q, limit / 2).unwrap_or_default(),
+        (Some(gc), _) => db::get_all_skills(gc).unwrap_or_default(),
+        (None, _) => vec![],
+    };
+
+    // Antipatterns are always shown, in their own section, whatever the query
+    let mut avoid = antipattern::load(raw_conn, cons_conn, global_cons_conn)?;
+    let shown = |m: &ConsolidatedMemory| {
+        m.r#type != antipattern::TYPE && wanted(m) && max_sensitivity.is_none_or(|max| m.sensitivity <= max)
     };
+    let consolidated: Vec<_> = consolidated.into_iter().filter(shown).collect();
+    let global_consolidated: Vec<_> = global_consolidated.into_iter().filter(shown).collect();
 
+    let mut parts = Parts { consolidated, skills, global_consolidated, global_skills, entities };
+    if let Some(p) = profile.filter(|p| !p.sections.is_empty()) {
+        let hidden = |section| !p.sections.contains(&section);
+        if hidden(ContextSection::Entities) {
+            parts.entities.clear();
+        }
+        if hidden(ContextSection::Patterns) {
+            parts.consolidated.clear();
+        }
+        if hidden(ContextSection::Skills) {
+            parts.skills.clear();
+        }
+        if hidden(ContextSection::Global) {
+            parts.global_consolidated.clear();
+        }
+        if hidden(ContextSection::GlobalSkills) {
+            parts.global_skills.clear();
+        }
+        if hidden(ContextSection::Avoid) {
+            avoid.clear();
+        }
+    }
+    let compact = format == ContextFormat::Compact;
     if compact {
-        Ok(format_compact(&consolidated, &skills, &stats, &global_consolidated, &entities))
-    } else {
-        Ok(format_full(&consolidated, &skills, &stats, &global_consolidated, &global_skills, &entities, &relationships))
+        parts.consolidated = compact_order(parts.consolidated, settings.compact_order, settings.compact_patterns);
+        parts.global_consolidated =
+            compact_order(parts.global_consolidated, settings.compact_order, settings.compact_global);
+        parts.entities.truncate(settings.compact_entities);
+    }
+    let max_tokens = settings.max_tokens;
+    let render = |p: &Parts| match format {
+        ContextFormat::Compact => {
+            format_compact(&p.consolidated, &stats, &p.global_consolidated, &p.entities, &avoid, &settings.compact_separator)
+        }
+        ContextFormat::Full => {
+            format_full(&p.consolidated, &p.skills, &stats, &p.global_consolidated, &p.global_skills, &p.entities, &relationships, &avoid)
+        }
+        ContextFormat::Xml => format_xml(p, &stats, &relationships, &avoid),
+    };
+    let mut out = render(&parts);
+    let mut trimmed = 0;
+    while max_tokens > 0 && crate::skills::estimate_tokens(&out) > max_tokens && parts.drop_least_relevant() {
+        trimmed += 1;
+        out = render(&parts);
+    }
+    if trimmed > 0 {
+        match format {
+            ContextFormat::Compact => out.push_str(&format!(". {} more left out to fit [context] max_tokens", trimmed)),
+            ContextFormat::Full => out.push_str(&format!("{} items left out to fit [context] max_tokens\n", trimmed)),
+            ContextFormat::Xml => {
+                out.truncate(out.len() - XML_CLOSE.len());
+                out.push_str(&format!("<note>{} items left out to fit [context] max_tokens</note>\n{}", trimmed, XML_CLOSE));
+            }
+        }
     }
+    Ok(out)
+}
+
+/// Built-in profiles, overridable by `[context.profiles.<name>]` in config.toml.
+fn builtin_profiles() -> BTreeMap<String, ContextProfile> {
+    let mut profiles = BTreeMap::new();
+    profiles.insert(
+        "reviewer".to_string(),
+        ContextProfile {
+            types: vec!["decision".into()],
+            sections: vec![ContextSection::Patterns, ContextSection::Global, ContextSection::Avoid],
+        },
+    );
+    profiles.insert(
+        "implementer".to_string(),
+        ContextProfile {
+            types: vec![],
+            sections: vec![
+                ContextSection::Patterns,
+                ContextSection::Skills,
+                ContextSection::Global,
+                ContextSection::GlobalSkills,
+                ContextSection::Avoid,
+            ],
+        },
+    );
+    profiles
 }
 
+/// The context profile called `name`: configured ones first, then the built-ins.
+pub fn find_profile(settings: &ContextConfig, name: &str) -> Result<ContextProfile> {
+    let mut profiles = builtin_profiles();
+    profiles.extend(settings.profiles.clone());
+    profiles.get(name).cloned().ok_or_else(|| {
+        let known: Vec<&str> = profiles.keys().map(|k| k.as_str()).collect();
+        anyhow::anyhow!("Unknown context profile '{}'. Available: {}", name, known.join(", "))
+    })
+}
+
+/// Meta key holding `client`'s delta cursor.
+fn cursor_key(client: &str) -> String {
+    format!("context_cursor.{}", client)
+}
+
+/// Cursors untouched this long are forgotten, so per-session MCP clients don't pile up.
+const CURSOR_DAYS: u32 = 7;
+
+/// A cursor for everything up to now, without saving it.
+pub fn current_cursor(cons_conn: &Connection) -> Result<String> {
+    Ok(cons_conn.query_row("SELECT datetime('now')", [], |r| r.get(0))?)
+}
+
+/// Remember that `client` has everything up to now. Returns the cursor.
+pub fn save_cursor(cons_conn: &Connection, client: &str) -> Result<String> {
+    let now = current_cursor(cons_conn)?;
+    cons_conn.execute(
+        "DELETE FROM meta WHERE key LIKE 'context_cursor.%' AND value < datetime('now', ?1)",
+        [format!("-{} days", CURSOR_DAYS)],
+    )?;
+    db::set_meta(cons_conn, &cursor_key(client), &now)?;
+    Ok(now)
+}
+
+pub fn get_cursor(cons_conn: &Connection, client: &str) -> Result<Option<String>> {
+    db::get_meta(cons_conn, &cursor_key(client))
+}
+
+/// Delta context: consolidated memories and skills added or updated at or after `since` (a
+/// cursor from `save_cursor`), or all of them without one, for refreshing context mid-session
+/// without injecting it all again. Timestamps have one-second resolution, so an item updated in
+/// the second of the cursor is sent twice rather than missed. Deletions aren't reported. A
+/// `profile` narrows it like `format_context`.
+pub fn format_delta(
+    cons_conn: &Connection,
+    global_cons_conn: Option<&Connection>,
+    since: Option<&str>,
+    max_sensitivity: Option<Sensitivity>,
+    profile: Option<&ContextProfile>,
+) -> Result<String> {
+    let changed = |at: &str| since.is_none_or(|since| at >= since);
+    let section = |s| profile.is_none_or(|p| p.sections.is_empty() || p.sections.contains(&s));
+    let shown = |m: &ConsolidatedMemory| {
+        changed(&m.updated_at)
+            && profile.is_none_or(|p| p.types.is_empty() || p.types.contains(&m.r#type))
+            && max_sensitivity.is_none_or(|max| m.sensitivity <= max)
+    };
+    let mut consolidated: Vec<_> = db::get_all_consolidated(cons_conn)?.into_iter().filter(shown).collect();
+    if !section(ContextSection::Patterns) {
+        consolidated.clear();
+    }
+    let mut skills: Vec<_> = db::get_all_skills(cons_conn)?.into_iter().filter(|s| changed(&s.updated_at)).collect();
+    if !section(ContextSection::Skills) {
+        skills.clear();
+    }
+    let global_consolidated: Vec<_> = match global_cons_conn {
+        Some(gc) if section(ContextSection::Global) => {
+            db::get_all_consolidated(gc).unwrap_or_default().into_iter().filter(shown).collect()
+        }
+        _ => vec![],
+    };
+
+    let heading = match since {
+        Some(since) => format!("since {}", since),
+        None => "(no earlier fetch)".to_string(),
+    };
+    if consolidated.is_empty() && skills.is_empty() && global_consolidated.is_empty() {
+        return Ok(format!("No memory changes {}.\n", heading));
+    }
+    let mut out = format!("## Memory Updates {}\n\n", heading);
+    let sections = [("Learned Patterns", &consolidated), ("Global Knowledge", &global_consolidated)];
+    for (title, memories) in sections.iter().filter(|(_, m)| !m.is_empty()) {
+        out.push_str(&format!("### {}\n", title));
+        for m in memories.iter() {
+            out.push_str(&format!(
+                "- [{}] {} (confidence: {:.2}){}\n",
+                m.r#type, m.content, m.confidence, format_refs(&m.refs)
+            ));
+        }
+        out.push('\n');
+    }
+    if !skills.is_empty() {
+        out.push_str("### Skills\n");
+        for s in &skills {
+            out.push_str(&skill_line(s));
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// The first `n` of `memories` by `order`, ties going to the lower ID.
+fn compact_order(mut memories: Vec<ConsolidatedMemory>, order: CompactOrder, n: usize) -> Vec<ConsolidatedMemory> {
+    match order {
+        CompactOrder::Confidence => memories.sort_by(|a, b| b.confidence.total_cmp(&a.confidence).then(a.id.cmp(&b.id))),
+        CompactOrder::Recency => memories.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then(a.id.cmp(&b.id))),
+        CompactOrder::Stable => memories.sort_by_key(|m| m.id),
+    }
+    memories.truncate(n);
+    memories
+}
+
+/// What `format_context` shows besides the antipatterns and stats, which are always kept.
+struct Parts {
+    consolidated: Vec<ConsolidatedMemory>,
+    skills: Vec<Skill>,
+    global_consolidated: Vec<ConsolidatedMemory>,
+    global_skills: Vec<Skill>,
+    entities: Vec<Entity>,
+}
+
+impl Parts {
+    /// Drop one item: the last of the first non-empty list among global skills, skills,
+    /// entities, global knowledge, and project patterns. Lists are ordered most relevant (or
+    /// most recent) first, so the project's own best matches go last. False when nothing is left.
+    fn drop_least_relevant(&mut self) -> bool {
+        self.global_skills.pop().is_some()
+            || self.skills.pop().is_some()
+            || self.entities.pop().is_some()
+            || self.global_consolidated.pop().is_some()
+            || self.consolidated.pop().is_some()
+    }
+}
+
+/// Size of one part of a rendered context, for `cortex context --stats`.
+#[derive(Debug, Serialize)]
+pub struct SectionSize {
+    pub section: String,
+    /// List entries (`- ` lines) in the section
+    pub items: usize,
+    pub tokens: usize,
+}
+
+/// Estimated tokens per `### ` section of `ctx`, in order. Compact context is one section.
+pub fn section_sizes(ctx: &str) -> Vec<SectionSize> {
+    let mut sizes: Vec<SectionSize> = Vec::new();
+    let mut current = (if ctx.starts_with("## ") { "Header" } else { "Context" }.to_string(), String::new());
+    let flush = |(section, text): (String, String), sizes: &mut Vec<SectionSize>| {
+        if !text.trim().is_empty() {
+            let items = text.lines().filter(|l| l.starts_with("- ")).count();
+            sizes.push(SectionSize { section, items, tokens: crate::skills::estimate_tokens(&text) });
+        }
+    };
+    for line in ctx.split_inclusive('\n') {
+        if let Some(title) = line.strip_prefix("### ") {
+            flush(std::mem::replace(&mut current, (title.trim().to_string(), String::new())), &mut sizes);
+        }
+        current.1.push_str(line);
+    }
+    flush(current, &mut sizes);
+    sizes
+}
+
+#[allow(clippy::too_many_arguments)]
 fn format_full(
     consolidated: &[ConsolidatedMemory],
     skills: &[Skill],
@@ -78,9 +450,18 @@ fn format_full(
     global_skills: &[Skill],
     entities: &[Entity],
     relationships: &[Relationship],
+    avoid: &[Antipattern],
 ) -> String {
     let mut out = String::from("## Project Memory Context\n\n");
 
+    if !avoid.is_empty() {
+        out.push_str("### Avoid\n");
+        for a in avoid {
+            out.push_str(&format!("- {}\n", a.content));
+        }
+        out.push('\n');
+    }
+
     // Entity section
     if !entities.is_empty() {
         out.push_str("### Key Entities\n");
@@ -113,8 +494,8 @@ fn format_full(
         out.push_str("### Learned Patterns\n");
         for m in consolidated {
             out.push_str(&format!(
-                "- [{}] {} (confidence: {:.2})\n",
-                m.r#type, m.content, m.confidence
+                "- [{}] {} (confidence: {:.2}){}\n",
+                m.r#type, m.content, m.confidence, format_refs(&m.refs)
             ));
         }
         out.push('\n');
@@ -123,8 +504,7 @@ fn format_full(
     if !skills.is_empty() {
         out.push_str("### Skills\n");
         for s in skills {
-            let line_count = s.content.lines().count();
-            out.push_str(&format!("- {}: {} lines\n", s.name, line_count));
+            out.push_str(&skill_line(s));
         }
         out.push('\n');
     }
@@ -133,8 +513,8 @@ fn format_full(
         out.push_str("### Global Knowledge\n");
         for m in global_consolidated {
             out.push_str(&format!(
-                "- [{}] {} (confidence: {:.2})\n",
-                m.r#type, m.content, m.confidence
+                "- [{}] {} (confidence: {:.2}){}\n",
+                m.r#type, m.content, m.confidence, format_refs(&m.refs)
             ));
         }
         out.push('\n');
@@ -143,8 +523,7 @@ fn format_full(
     if !global_skills.is_empty() {
         out.push_str("### Global Skills\n");
         for s in global_skills {
-            let line_count = s.content.lines().count();
-            out.push_str(&format!("- {}: {} lines\n", s.name, line_count));
+            out.push_str(&skill_line(s));
         }
         out.push('\n');
     }
@@ -163,28 +542,25 @@ fn format_full(
     out
 }
 
+/// A skill's line under Skills: its path and size, and the matching excerpt when a query found it.
+fn skill_line(s: &Skill) -> String {
+    match &s.snippet {
+        Some(snippet) => format!("- {}: {} lines — {}\n", s.path(), s.content.lines().count(), snippet),
+        None => format!("- {}: {} lines\n", s.path(), s.content.lines().count()),
+    }
+}
+
 fn format_compact(
     consolidated: &[ConsolidatedMemory],
-    _skills: &[Skill],
     stats: &Stats,
     global_consolidated: &[ConsolidatedMemory],
     entities: &[Entity],
+    avoid: &[Antipattern],
+    separator: &str,
 ) -> String {
-    let patterns: Vec<String> = consolidated
-        .iter()
-        .map(|m| m.content.clone())
-        .collect();
-
-    let global_patterns: Vec<String> = global_consolidated
-        .iter()
-        .map(|m| m.content.clone())
-        .collect();
-
-    let entity_names: Vec<String> = entities
-        .iter()
-        .take(10)
-        .map(|e| e.name.clone())
-        .collect();
+    let patterns: Vec<&str> = consolidated.iter().map(|m| m.content.as_str()).collect();
+    let global_patterns: Vec<&str> = global_consolidated.iter().map(|m| m.content.as_str()).collect();
+    let entity_names: Vec<&str> = entities.iter().map(|e| e.name.as_str()).collect();
 
     let mut result = format!(
         "Project memory: {} memories, {} consolidated, {} entities. Key patterns: {}",
@@ -194,7 +570,7 @@ fn format_compact(
         if patterns.is_empty() {
             "none yet".to_string()
         } else {
-            patterns.join("; ")
+            patterns.join(separator)
         }
     );
 
@@ -203,8 +579,288 @@ fn format_compact(
     }
 
     if !global_patterns.is_empty() {
-        result.push_str(&format!(". Global: {}", global_patterns.join("; ")));
+        result.push_str(&format!(". Global: {}", global_patterns.join(separator)));
+    }
+
+    if !avoid.is_empty() {
+        let rules: Vec<&str> = avoid.iter().map(|a| a.content.as_str()).collect();
+        result.push_str(&format!(". Avoid: {}", rules.join(separator)));
     }
 
     result
 }
+
+const XML_CLOSE: &str = "</project_memory>\n";
+
+/// Add plugin `sections` (see `plugins::context_sections`) to `format_context` output, inside
+/// `<project_memory>` for XML.
+pub fn append_sections(ctx: &mut String, format: ContextFormat, sections: &str) {
+    match format {
+        ContextFormat::Xml if ctx.ends_with(XML_CLOSE) => ctx.insert_str(ctx.len() - XML_CLOSE.len(), sections),
+        _ => ctx.push_str(sections),
+    }
+}
+
+/// `settings` with plugin `sections` taken out of `max_tokens`, so context they're appended to
+/// still fits the budget.
+pub fn reserve(settings: &ContextConfig, sections: &str) -> ContextConfig {
+    let mut settings = settings.clone();
+    if settings.max_tokens > 0 && !sections.is_empty() {
+        // Never down to 0, which means no limit
+        settings.max_tokens = settings.max_tokens.saturating_sub(crate::skills::estimate_tokens(sections)).max(1);
+    }
+    settings
+}
+
+/// Text and attribute values in XML context.
+pub(crate) fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn xml_memories(out: &mut String, tag: &str, memories: &[ConsolidatedMemory]) {
+    if memories.is_empty() {
+        return;
+    }
+    out.push_str(&format!("<{}>\n", tag));
+    for m in memories {
+        let refs = if m.refs.is_empty() { String::new() } else { format!(" refs=\"{}\"", xml_escape(&m.refs.join(", "))) };
+        out.push_str(&format!(
+            "<memory id=\"{}\" type=\"{}\" confidence=\"{:.2}\"{}>{}</memory>\n",
+            m.id,
+            xml_escape(&m.r#type),
+            m.confidence,
+            refs,
+            xml_escape(&m.content)
+        ));
+    }
+    out.push_str(&format!("</{}>\n", tag));
+}
+
+fn xml_skills(out: &mut String, tag: &str, skills: &[Skill]) {
+    if skills.is_empty() {
+        return;
+    }
+    out.push_str(&format!("<{}>\n", tag));
+    for s in skills {
+        let (path, lines) = (xml_escape(&s.path()), s.content.lines().count());
+        match &s.snippet {
+            Some(snippet) => out.push_str(&format!("<skill path=\"{}\" lines=\"{}\">{}</skill>\n", path, lines, xml_escape(snippet))),
+            None => out.push_str(&format!("<skill path=\"{}\" lines=\"{}\"/>\n", path, lines)),
+        }
+    }
+    out.push_str(&format!("</{}>\n", tag));
+}
+
+/// The sections of `format_full` in XML tags. Memory IDs are the ones `cortex show`, `cortex
+/// outcome`, and `cortex delete` take; global knowledge IDs belong to the global store.
+fn format_xml(parts: &Parts, stats: &Stats, relationships: &[Relationship], avoid: &[Antipattern]) -> String {
+    let mut out = String::from("<project_memory>\n");
+    if !avoid.is_empty() {
+        out.push_str("<rules>\n");
+        for a in avoid {
+            out.push_str(&format!("<rule ref=\"{}\">{}</rule>\n", xml_escape(&a.label), xml_escape(&a.content)));
+        }
+        out.push_str("</rules>\n");
+    }
+    if !parts.entities.is_empty() {
+        let names: std::collections::HashMap<i64, &str> = parts.entities.iter().map(|e| (e.id, e.name.as_str())).collect();
+        out.push_str("<entities>\n");
+        for e in &parts.entities {
+            out.push_str(&format!(
+                "<entity name=\"{}\" type=\"{}\" confidence=\"{:.2}\">{}",
+                xml_escape(&e.name),
+                xml_escape(&e.entity_type),
+                e.confidence,
+                xml_escape(e.description.as_deref().unwrap_or(""))
+            ));
+            for r in relationships.iter().filter(|r| r.source_entity_id == e.id) {
+                if let Some(target) = names.get(&r.target_entity_id) {
+                    out.push_str(&format!(
+                        "<relation type=\"{}\" to=\"{}\"/>",
+                        xml_escape(&r.relation_type),
+                        xml_escape(target)
+                    ));
+                }
+            }
+            out.push_str("</entity>\n");
+        }
+        out.push_str("</entities>\n");
+    }
+    xml_memories(&mut out, "patterns", &parts.consolidated);
+    xml_skills(&mut out, "skills", &parts.skills);
+    xml_memories(&mut out, "global_knowledge", &parts.global_consolidated);
+    xml_skills(&mut out, "global_skills", &parts.global_skills);
+    out.push_str(&format!(
+        "<stats memories=\"{}\" consolidated=\"{}\" entities=\"{}\" skills=\"{}\"/>\n",
+        stats.raw_count, stats.consolidated_count, stats.entity_count, stats.skill_count
+    ));
+    out.push_str(XML_CLOSE);
+    out
+}
+
+/// Trailing " [refs: #12, JIRA-4]" for entries linked to the tracker.
+fn format_refs(refs: &[String]) -> String {
+    if refs.is_empty() {
+        String::new()
+    } else {
+        format!(" [refs: {}]", refs.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn recall_weights_change_ranking() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let global = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let low = db::save_memory(&raw, "cache key uses lockfile hash", "observation", "s1").unwrap();
+        let high = db::save_memory(&raw, "cache key includes toolchain", "observation", "s1").unwrap();
+        db::update_memory_importance(&raw, low, 0.1).unwrap();
+        db::update_memory_importance(&raw, high, 0.9).unwrap();
+        db::insert_consolidated(&global, "cache keys must include the toolchain version", "pattern", &[], 0.5).unwrap();
+
+        let ids = |ranking: &RecallConfig| -> Vec<i64> {
+            recall(&raw, Some(&global), "cache key", 10, ranking).unwrap().iter().map(|m| m.id).collect()
+        };
+        let flat = RecallConfig { importance_weight: 0.0, raw_half_life_days: 0.0, ..Default::default() };
+        assert_eq!(ids(&RecallConfig { consolidated_boost: 0.0, ..Default::default() })[..2], [high, low]);
+        assert_eq!(ids(&RecallConfig { consolidated_boost: 1000.0, ..flat.clone() })[0], -1);
+        assert_eq!(ids(&RecallConfig { consolidated_boost: 0.0, ..flat })[2], -1);
+    }
+
+    #[test]
+    fn trims_to_token_budget() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        for i in 0..20 {
+            let content = format!("Pattern {} about the upload pipeline and its retry behaviour", i);
+            db::insert_consolidated(&cons, &content, "pattern", &[], 0.8).unwrap();
+        }
+        let ranking = RecallConfig::default();
+        let full = format_context(&cons, &raw, None, ContextFormat::Full, None, 20, &ranking, None, &ContextConfig::default(), None).unwrap();
+        let budget = ContextConfig { max_tokens: 200, ..Default::default() };
+        let trimmed = format_context(&cons, &raw, None, ContextFormat::Full, None, 20, &ranking, None, &budget, None).unwrap();
+        assert!(crate::skills::estimate_tokens(&trimmed) <= 220, "{}", trimmed);
+        assert!(trimmed.contains("items left out to fit"));
+
+        let sizes = section_sizes(&full);
+        let patterns = sizes.iter().find(|s| s.section == "Learned Patterns").unwrap();
+        assert_eq!(patterns.items, 20);
+        let total: usize = sizes.iter().map(|s| s.tokens).sum();
+        assert!(total.abs_diff(crate::skills::estimate_tokens(&full)) <= sizes.len());
+    }
+
+    #[test]
+    fn compact_context_is_configurable_and_stable() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let a = db::insert_consolidated(&cons, "alpha", "pattern", &[], 0.6).unwrap();
+        db::insert_consolidated(&cons, "bravo", "pattern", &[], 0.9).unwrap();
+        db::insert_consolidated(&cons, "charlie", "pattern", &[], 0.6).unwrap();
+        cons.execute("UPDATE consolidated SET updated_at = '2026-01-01 00:00:00'", []).unwrap();
+        cons.execute("UPDATE consolidated SET updated_at = '2026-02-01 00:00:00' WHERE id = ?1", [a]).unwrap();
+        let ranking = RecallConfig::default();
+        let compact = |settings: &ContextConfig| {
+            let ctx = format_context(&cons, &raw, None, ContextFormat::Compact, None, 15, &ranking, None, settings, None).unwrap();
+            ctx.split("Key patterns: ").nth(1).unwrap().to_string()
+        };
+
+        assert_eq!(compact(&ContextConfig::default()), "bravo; alpha; charlie");
+        let stable = ContextConfig { compact_order: CompactOrder::Stable, compact_separator: " | ".into(), ..Default::default() };
+        assert_eq!(compact(&stable), "alpha | bravo | charlie");
+        let recent = ContextConfig { compact_order: CompactOrder::Recency, compact_patterns: 2, ..Default::default() };
+        assert_eq!(compact(&recent), "alpha; bravo");
+    }
+
+    #[test]
+    fn delta_context_follows_the_cursor() {
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        db::insert_consolidated(&cons, "Deploys go through staging", "decision", &[], 0.8).unwrap();
+        cons.execute("UPDATE consolidated SET updated_at = '2026-01-01 00:00:00'", []).unwrap();
+        assert!(format_delta(&cons, None, None, None, None).unwrap().contains("staging"));
+
+        assert_eq!(get_cursor(&cons, "hook").unwrap(), None);
+        let cursor = save_cursor(&cons, "hook").unwrap();
+        assert_eq!(get_cursor(&cons, "hook").unwrap().as_deref(), Some(cursor.as_str()));
+        let quiet = format_delta(&cons, None, Some(&cursor), None, None).unwrap();
+        assert!(quiet.starts_with("No memory changes since"), "{}", quiet);
+
+        db::insert_consolidated(&cons, "Tests run with nextest", "convention", &[], 0.7).unwrap();
+        let delta = format_delta(&cons, None, Some(&cursor), None, None).unwrap();
+        assert!(delta.contains("nextest") && !delta.contains("staging"), "{}", delta);
+    }
+
+    #[test]
+    fn profiles_narrow_types_and_sections() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        db::insert_consolidated(&cons, "Chose SQLite over Postgres", "decision", &[], 0.8).unwrap();
+        db::insert_consolidated(&cons, "Handlers live in src/api", "pattern", &[], 0.8).unwrap();
+        db::insert_consolidated(&cons, "Never call unwrap in handlers", antipattern::TYPE, &[], 0.8).unwrap();
+        raw.execute("INSERT INTO entities (name, entity_type) VALUES ('SQLite', 'tool')", []).unwrap();
+        let settings = ContextConfig::default();
+        let ranking = RecallConfig::default();
+        let render = |name: &str| {
+            let profile = find_profile(&settings, name).unwrap();
+            format_context(&cons, &raw, None, ContextFormat::Full, None, 20, &ranking, None, &settings, Some(&profile)).unwrap()
+        };
+
+        let all = format_context(&cons, &raw, None, ContextFormat::Full, None, 20, &ranking, None, &settings, None).unwrap();
+        assert!(all.contains("Key Entities") && all.contains("src/api"), "{}", all);
+        let reviewer = render("reviewer");
+        assert!(reviewer.contains("Chose SQLite") && reviewer.contains("Never call unwrap"), "{}", reviewer);
+        assert!(!reviewer.contains("src/api") && !reviewer.contains("Key Entities"), "{}", reviewer);
+        let implementer = render("implementer");
+        assert!(implementer.contains("Chose SQLite") && implementer.contains("src/api"), "{}", implementer);
+        assert!(!implementer.contains("Key Entities"), "{}", implementer);
+
+        let custom = ContextConfig {
+            profiles: [("reviewer".to_string(), ContextProfile { types: vec!["pattern".into()], sections: vec![] })].into(),
+            ..Default::default()
+        };
+        assert_eq!(find_profile(&custom, "reviewer").unwrap().types, ["pattern"]);
+        assert!(find_profile(&custom, "tester").unwrap_err().to_string().contains("Available: implementer, reviewer"));
+    }
+
+    #[test]
+    fn xml_context_tags_sections_with_ids() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let id = db::insert_consolidated(&cons, "Use Vec<u8> for \"raw\" bodies & headers", "pattern", &[], 0.8).unwrap();
+        db::insert_consolidated(&cons, "Never log tokens", antipattern::TYPE, &[], 0.9).unwrap();
+        let ranking = RecallConfig::default();
+        let xml = format_context(&cons, &raw, None, ContextFormat::Xml, None, 20, &ranking, None, &ContextConfig::default(), None)
+            .unwrap();
+        assert!(xml.starts_with("<project_memory>\n") && xml.ends_with(XML_CLOSE), "{}", xml);
+        assert!(xml.contains("<rules>\n<rule ref=\"consolidated #2\">Never log tokens</rule>"), "{}", xml);
+        let memory = format!(
+            "<memory id=\"{}\" type=\"pattern\" confidence=\"0.80\">Use Vec&lt;u8&gt; for &quot;raw&quot; bodies &amp; headers</memory>",
+            id
+        );
+        assert!(xml.contains(&memory), "{}", xml);
+
+        let budget = ContextConfig { max_tokens: 40, ..Default::default() };
+        let trimmed = format_context(&cons, &raw, None, ContextFormat::Xml, None, 20, &ranking, None, &budget, None).unwrap();
+        assert!(trimmed.ends_with(&format!("max_tokens</note>\n{}", XML_CLOSE)), "{}", trimmed);
+
+        let sections = "<plugin name=\"jira\" title=\"Open\">PROJ-1</plugin>\n";
+        let with_room = ContextConfig { max_tokens: 80, ..Default::default() };
+        let mut fitted = format_context(&cons, &raw, None, ContextFormat::Xml, None, 20, &ranking, None, &reserve(&with_room, sections), None)
+            .unwrap();
+        append_sections(&mut fitted, ContextFormat::Xml, sections);
+        assert!(crate::skills::estimate_tokens(&format!("{}{}", xml, sections)) > 80);
+        assert!(crate::skills::estimate_tokens(&fitted) <= 80, "{}", fitted);
+        assert_eq!(reserve(&ContextConfig::default(), sections).max_tokens, 0);
+        assert_eq!(reserve(&ContextConfig { max_tokens: 5, ..Default::default() }, sections).max_tokens, 1);
+
+        let mut with_plugins = xml.clone();
+        append_sections(&mut with_plugins, ContextFormat::Xml, "<plugin name=\"jira\" title=\"Open\">PROJ-1</plugin>\n");
+        assert!(with_plugins.ends_with(&format!("PROJ-1</plugin>\n{}", XML_CLOSE)), "{}", with_plugins);
+        let mut full = "## Project Memory\n".to_string();
+        append_sections(&mut full, ContextFormat::Full, "\n### Open\nPROJ-1\n");
+        assert!(full.ends_with("### Open\nPROJ-1\n"));
+    }
+}
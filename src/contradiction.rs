@@ -0,0 +1,152 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::audit::Manifest;
+use crate::config::{Config, ContradictionCheck};
+use crate::db;
+use crate::llm;
+use crate::similar::{self, Source, word_set};
+
+/// Consolidated memories considered per save.
+const CANDIDATES: usize = 20;
+/// Most close matches sent to the LLM per save.
+const LLM_CHECKS: usize = 3;
+/// Share of the shorter text's words the two must share to be about the same thing.
+const SAME_TOPIC: f64 = 0.6;
+
+const NEGATIONS: &[&str] = &[
+    "not", "never", "don", "dont", "doesn", "didn", "isn", "aren", "wasn", "won", "cannot", "without", "avoid",
+    "stop", "stopped", "longer",
+];
+const OPPOSITES: &[(&str, &str)] = &[
+    ("enable", "disable"),
+    ("enabled", "disabled"),
+    ("add", "remove"),
+    ("added", "removed"),
+    ("allow", "deny"),
+    ("allowed", "denied"),
+    ("always", "never"),
+    ("true", "false"),
+    ("required", "optional"),
+    ("increase", "decrease"),
+    ("sync", "async"),
+    ("works", "fails"),
+    ("safe", "unsafe"),
+];
+const STOP_WORDS: &[&str] = &["the", "and", "for", "with", "this", "that", "are", "was", "from", "into", "any", "our"];
+
+/// A consolidated memory a new save contradicts.
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub consolidated_id: i64,
+    pub content: String,
+    pub reason: String,
+}
+
+/// Check a just-saved raw memory against close consolidated matches (`[contradictions]
+/// check`) and link each contradiction in raw.db, where the next sleep picks it up.
+pub async fn check(raw: &Connection, cons: &Connection, memory_id: i64, content: &str, config: &Config) -> Result<Vec<Alert>> {
+    let mode = config.contradictions.check;
+    if mode == ContradictionCheck::Off {
+        return Ok(vec![]);
+    }
+    let mut alerts = Vec::new();
+    let mut asked = 0;
+    for m in similar::similar_to_text(raw, cons, content, Some((Source::Raw, memory_id)), CANDIDATES)? {
+        if m.source != Source::Consolidated || !same_topic(content, &m.content) {
+            continue;
+        }
+        let heuristic = opposite(content, &m.content);
+        // Consolidated memories built from --no-llm saves are only compared locally
+        let local_only = || -> Result<bool> { Ok(db::get_consolidated(cons, m.id)?.is_some_and(|c| c.no_llm)) };
+        let reason = if mode == ContradictionCheck::Llm && asked < LLM_CHECKS && !local_only()? {
+            asked += 1;
+            let manifest = Manifest::new(raw, "contradiction", vec![format!("m{}", memory_id), format!("c{}", m.id)]);
+            llm::audited(manifest, llm::check_contradiction(content, &m.content, config)).await.unwrap_or(heuristic)
+        } else {
+            heuristic
+        };
+        if let Some(reason) = reason {
+            db::link_contradiction(raw, memory_id, m.id, &reason)?;
+            alerts.push(Alert { consolidated_id: m.id, content: m.content, reason });
+        }
+    }
+    Ok(alerts)
+}
+
+/// Why `new` contradicts `old` by the heuristic alone: same topic, opposite claim.
+pub(crate) fn heuristic_conflict(new: &str, old: &str) -> Option<String> {
+    if same_topic(new, old) { opposite(new, old) } else { None }
+}
+
+/// Whether the two texts share most of their words, negations and opposite terms aside.
+fn same_topic(a: &str, b: &str) -> bool {
+    let (a, b) = (key_words(a), key_words(b));
+    let shared = a.intersection(&b).count();
+    shared >= 2 && shared as f64 >= SAME_TOPIC * a.len().min(b.len()) as f64
+}
+
+/// Why `new` asserts the opposite of `old`, if it seems to: one is negated and the other
+/// isn't, or one uses a word whose opposite the other uses.
+fn opposite(new: &str, old: &str) -> Option<String> {
+    let (a, b) = (word_set(new), word_set(old));
+    for (x, y) in OPPOSITES {
+        for (p, q) in [(x, y), (y, x)] {
+            if a.contains(*p) && !a.contains(*q) && b.contains(*q) && !b.contains(*p) {
+                return Some(format!("says \"{}\" where it said \"{}\"", p, q));
+            }
+        }
+    }
+    match (negated(new, &a), negated(old, &b)) {
+        (true, false) => Some("negates it".to_string()),
+        (false, true) => Some("drops its negation".to_string()),
+        _ => None,
+    }
+}
+
+fn negated(text: &str, words: &HashSet<String>) -> bool {
+    NEGATIONS.iter().any(|n| words.contains(*n)) || text.to_lowercase().contains("n't")
+}
+
+fn key_words(text: &str) -> HashSet<String> {
+    let mut words = word_set(text);
+    words.retain(|w| {
+        !NEGATIONS.contains(&w.as_str())
+            && !STOP_WORDS.contains(&w.as_str())
+            && !OPPOSITES.iter().any(|(x, y)| x == w || y == w)
+    });
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn links_saves_that_assert_the_opposite() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let c = db::insert_consolidated(&cons, "Incremental compilation is enabled in CI builds", "decision", &[], 0.8).unwrap();
+        db::insert_consolidated(&cons, "The release workflow tags from main", "pattern", &[], 0.8).unwrap();
+        let config = Config::default();
+
+        let id = db::save_memory(&raw, "Incremental compilation is disabled in CI builds", "decision", "s1").unwrap();
+        let alerts = check(&raw, &cons, id, "Incremental compilation is disabled in CI builds", &config).await.unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!((alerts[0].consolidated_id, alerts[0].reason.as_str()), (c, "says \"disabled\" where it said \"enabled\""));
+        assert_eq!(db::get_contradictions(&raw).unwrap(), vec![(id, c, alerts[0].reason.clone())]);
+
+        let agrees = db::save_memory(&raw, "CI builds keep incremental compilation enabled", "observation", "s1").unwrap();
+        assert!(check(&raw, &cons, agrees, "CI builds keep incremental compilation enabled", &config).await.unwrap().is_empty());
+        let negated = "Incremental compilation isn't used in CI builds anymore";
+        let n = db::save_memory(&raw, negated, "observation", "s1").unwrap();
+        assert_eq!(check(&raw, &cons, n, negated, &config).await.unwrap()[0].reason, "negates it");
+
+        // Deleting the memory drops its links
+        db::delete_memory(&raw, id).unwrap();
+        assert_eq!(db::get_contradictions(&raw).unwrap().len(), 1);
+    }
+}
@@ -1,12 +1,33 @@
 use anyhow::Result;
+use rusqlite::functions::FunctionFlags;
 use rusqlite::{params, Connection};
+use std::collections::BTreeMap;
 use std::path::Path;
 
+use crate::compress;
+use crate::config::RecallConfig;
+use crate::extensions;
 use crate::models::{ConsolidatedMemory, Entity, Memory, Relationship, Skill, Stats};
+use crate::query::{self, Tokenizer};
+use crate::refs;
+use crate::sensitivity::Sensitivity;
+
+/// How long a connection waits on another connection's write lock before failing. A store
+/// can have several connections open at once (see `Store::call`).
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Version of the store layout, recorded in `meta`. Bump it when a change would make older
+/// binaries misread a store, so they refuse to open it instead.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const BINARY_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub fn open_raw_db(path: &Path) -> Result<Connection> {
     let conn = Connection::open(path)?;
+    register_functions(&conn)?;
+    extensions::load(&conn);
     conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA foreign_keys=ON;")?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
     conn.execute_batch(
         "CREATE TABLE IF NOT EXISTS memories (
             id INTEGER PRIMARY KEY,
@@ -18,7 +39,12 @@ pub fn open_raw_db(path: &Path) -> Result<Connection> {
             consolidated INTEGER NOT NULL DEFAULT 0,
             importance REAL NOT NULL DEFAULT 0.5,
             session_id TEXT,
-            entity_ids TEXT NOT NULL DEFAULT '[]'
+            entity_ids TEXT NOT NULL DEFAULT '[]',
+            tags TEXT NOT NULL DEFAULT '[]',
+            refs TEXT NOT NULL DEFAULT '[]',
+            trusted INTEGER NOT NULL DEFAULT 1,
+            sensitivity TEXT NOT NULL DEFAULT 'internal',
+            no_llm INTEGER NOT NULL DEFAULT 0
         );
         CREATE VIRTUAL TABLE IF NOT EXISTS memories_fts USING fts5(content, type, content=memories, content_rowid=id, tokenize='porter unicode61');
         CREATE TRIGGER IF NOT EXISTS memories_ai AFTER INSERT ON memories BEGIN
@@ -41,6 +67,45 @@ pub fn open_raw_db(path: &Path) -> Result<Connection> {
         conn.execute_batch("ALTER TABLE memories ADD COLUMN entity_ids TEXT NOT NULL DEFAULT '[]';")?;
     }
 
+    // Migrate: add tags column if missing
+    let has_tags = conn.prepare("SELECT tags FROM memories LIMIT 0").is_ok();
+    if !has_tags {
+        conn.execute_batch("ALTER TABLE memories ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';")?;
+    }
+
+    // Migrate: add refs column if missing, backfilled from existing content
+    let has_refs = conn.prepare("SELECT refs FROM memories LIMIT 0").is_ok();
+    if !has_refs {
+        conn.execute_batch("ALTER TABLE memories ADD COLUMN refs TEXT NOT NULL DEFAULT '[]';")?;
+        backfill_refs(&conn, "memories")?;
+    }
+
+    // Migrate: add trusted column if missing (existing memories are trusted)
+    let has_trusted = conn.prepare("SELECT trusted FROM memories LIMIT 0").is_ok();
+    if !has_trusted {
+        conn.execute_batch("ALTER TABLE memories ADD COLUMN trusted INTEGER NOT NULL DEFAULT 1;")?;
+    }
+
+    // Migrate: add sensitivity column if missing
+    let has_sensitivity = conn.prepare("SELECT sensitivity FROM memories LIMIT 0").is_ok();
+    if !has_sensitivity {
+        conn.execute_batch("ALTER TABLE memories ADD COLUMN sensitivity TEXT NOT NULL DEFAULT 'internal';")?;
+    }
+
+    // Migrate: add no_llm column if missing
+    let has_no_llm = conn.prepare("SELECT no_llm FROM memories LIMIT 0").is_ok();
+    if !has_no_llm {
+        conn.execute_batch("ALTER TABLE memories ADD COLUMN no_llm INTEGER NOT NULL DEFAULT 0;")?;
+    }
+
+    // Indexes for the backlog, recency, session, and type queries on large stores
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_memories_unconsolidated ON memories(created_at) WHERE consolidated = 0;
+        CREATE INDEX IF NOT EXISTS idx_memories_accessed ON memories(accessed_at);
+        CREATE INDEX IF NOT EXISTS idx_memories_session ON memories(session_id);
+        CREATE INDEX IF NOT EXISTS idx_memories_type ON memories(type, created_at);",
+    )?;
+
     // Create entities table
     conn.execute_batch(
         "CREATE TABLE IF NOT EXISTS entities (
@@ -88,12 +153,154 @@ pub fn open_raw_db(path: &Path) -> Result<Connection> {
         CREATE INDEX IF NOT EXISTS idx_relationships_type ON relationships(relation_type);",
     )?;
 
+    // Full text of memories shortened at save time (see `oversize`)
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS attachments (
+            memory_id INTEGER PRIMARY KEY REFERENCES memories(id) ON DELETE CASCADE,
+            content TEXT NOT NULL,
+            summarized INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );",
+    )?;
+
+    // Consolidated memories removed by micro sleep's decay, kept (unindexed) so `cortex why`
+    // can still show where a consolidated memory came from
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS archived_memories (
+            id INTEGER PRIMARY KEY,
+            content TEXT NOT NULL,
+            type TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            accessed_at TEXT NOT NULL,
+            access_count INTEGER NOT NULL,
+            consolidated INTEGER NOT NULL,
+            importance REAL NOT NULL,
+            session_id TEXT,
+            entity_ids TEXT NOT NULL,
+            tags TEXT NOT NULL,
+            refs TEXT NOT NULL,
+            archived_at TEXT NOT NULL DEFAULT (datetime('now')),
+            sensitivity TEXT NOT NULL DEFAULT 'internal',
+            no_llm INTEGER NOT NULL DEFAULT 0
+        );",
+    )?;
+    let has_sensitivity = conn.prepare("SELECT sensitivity FROM archived_memories LIMIT 0").is_ok();
+    if !has_sensitivity {
+        conn.execute_batch("ALTER TABLE archived_memories ADD COLUMN sensitivity TEXT NOT NULL DEFAULT 'internal';")?;
+    }
+    let has_no_llm = conn.prepare("SELECT no_llm FROM archived_memories LIMIT 0").is_ok();
+    if !has_no_llm {
+        conn.execute_batch("ALTER TABLE archived_memories ADD COLUMN no_llm INTEGER NOT NULL DEFAULT 0;")?;
+    }
+
+    // Sleep runs whose raw.db side has been applied (see `sleep::apply_consolidation`)
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS applied_runs (
+            run_id TEXT PRIMARY KEY,
+            applied_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );",
+    )?;
+
+    // New memories that contradict a consolidated memory, found at save time (see
+    // `contradiction`) and handed to the next sleep
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS contradictions (
+            memory_id INTEGER NOT NULL REFERENCES memories(id) ON DELETE CASCADE,
+            consolidated_id INTEGER NOT NULL,
+            reason TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            PRIMARY KEY (memory_id, consolidated_id)
+        );",
+    )?;
+
+    // One row per LLM call whose prompt included memories (see `audit`). Kept in raw.db so
+    // the trail stays on the machine that made the calls.
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS llm_audit (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            provider TEXT NOT NULL,
+            model TEXT NOT NULL,
+            purpose TEXT NOT NULL,
+            memories TEXT NOT NULL DEFAULT '[]',
+            manifest_hash TEXT NOT NULL,
+            prompt_hash TEXT NOT NULL,
+            input_tokens INTEGER,
+            output_tokens INTEGER,
+            ok INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_llm_audit_at ON llm_audit(at);
+        CREATE TABLE IF NOT EXISTS llm_batches (
+            id TEXT PRIMARY KEY,
+            purpose TEXT NOT NULL,
+            submitted_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            memories TEXT NOT NULL DEFAULT '[]',
+            consolidated TEXT NOT NULL DEFAULT '[]'
+        );
+        CREATE TABLE IF NOT EXISTS sleep_evaluations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            inputs INTEGER NOT NULL,
+            outputs INTEGER NOT NULL,
+            coverage REAL NOT NULL,
+            faithfulness REAL NOT NULL,
+            score REAL NOT NULL,
+            unsupported TEXT NOT NULL DEFAULT '[]',
+            notes TEXT NOT NULL DEFAULT ''
+        );
+
+        CREATE TABLE IF NOT EXISTS query_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            source TEXT NOT NULL,
+            query TEXT NOT NULL,
+            hits INTEGER NOT NULL,
+            hit_ids TEXT NOT NULL DEFAULT '[]'
+        );
+        CREATE INDEX IF NOT EXISTS idx_query_log_at ON query_log(at);
+
+        CREATE TABLE IF NOT EXISTS session_recalls (
+            session_id TEXT NOT NULL,
+            memory_id INTEGER NOT NULL,
+            at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            PRIMARY KEY (session_id, memory_id)
+        );
+        CREATE TABLE IF NOT EXISTS confirmations (
+            memory_id INTEGER NOT NULL,
+            confirmed_by INTEGER NOT NULL,
+            at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            PRIMARY KEY (memory_id, confirmed_by)
+        );
+
+        CREATE TABLE IF NOT EXISTS guard_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            action TEXT NOT NULL,
+            type TEXT NOT NULL,
+            content TEXT NOT NULL,
+            source_ids TEXT NOT NULL DEFAULT '[]',
+            overlap REAL NOT NULL,
+            reason TEXT NOT NULL
+        );",
+    )?;
+
     Ok(conn)
 }
 
+/// SQL functions the bundled SQLite lacks: `pow(x, y)` for the `[recall]` ranking weights.
+fn register_functions(conn: &Connection) -> Result<()> {
+    conn.create_scalar_function("pow", 2, FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC, |ctx| {
+        Ok(ctx.get::<f64>(0)?.powf(ctx.get::<f64>(1)?))
+    })?;
+    Ok(())
+}
+
 pub fn open_consolidated_db(path: &Path) -> Result<Connection> {
     let conn = Connection::open(path)?;
+    register_functions(&conn)?;
+    extensions::load(&conn);
     conn.execute_batch("PRAGMA journal_mode=WAL;")?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
     conn.execute_batch(
         "CREATE TABLE IF NOT EXISTS consolidated (
             id INTEGER PRIMARY KEY,
@@ -104,7 +311,10 @@ pub fn open_consolidated_db(path: &Path) -> Result<Connection> {
             created_at TEXT NOT NULL DEFAULT (datetime('now')),
             updated_at TEXT NOT NULL DEFAULT (datetime('now')),
             access_count INTEGER NOT NULL DEFAULT 0,
-            entity_ids TEXT NOT NULL DEFAULT '[]'
+            entity_ids TEXT NOT NULL DEFAULT '[]',
+            refs TEXT NOT NULL DEFAULT '[]',
+            sensitivity TEXT NOT NULL DEFAULT 'internal',
+            no_llm INTEGER NOT NULL DEFAULT 0
         );
         CREATE VIRTUAL TABLE IF NOT EXISTS consolidated_fts USING fts5(content, type, content=consolidated, content_rowid=id, tokenize='porter unicode61');
         CREATE TRIGGER IF NOT EXISTS consolidated_ai AFTER INSERT ON consolidated BEGIN
@@ -124,11 +334,43 @@ pub fn open_consolidated_db(path: &Path) -> Result<Connection> {
             source_ids TEXT NOT NULL DEFAULT '[]',
             updated_at TEXT NOT NULL DEFAULT (datetime('now'))
         );
+        CREATE TABLE IF NOT EXISTS skill_aliases (
+            alias TEXT PRIMARY KEY,
+            name TEXT NOT NULL
+        );
         CREATE TABLE IF NOT EXISTS meta (
             key TEXT PRIMARY KEY,
             value TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS pending_runs (
+            run_id TEXT PRIMARY KEY,
+            raw_changes TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
         );",
     )?;
+    check_version(&conn)?;
+
+    // Sleeps and dreams, for `cortex timeline`
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS activity (
+            kind TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );",
+    )?;
+
+    // `cortex outcome` reports, committed with the memories they rate; `applied` once a sleep
+    // has folded them into confidence
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS outcomes (
+            id INTEGER PRIMARY KEY,
+            consolidated_id INTEGER NOT NULL,
+            worked INTEGER NOT NULL,
+            note TEXT NOT NULL DEFAULT '',
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            applied INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_outcomes_consolidated ON outcomes(consolidated_id);",
+    )?;
 
     // Migrate: add entity_ids column if missing
     let has_entity_ids = conn
@@ -138,84 +380,309 @@ pub fn open_consolidated_db(path: &Path) -> Result<Connection> {
         conn.execute_batch("ALTER TABLE consolidated ADD COLUMN entity_ids TEXT NOT NULL DEFAULT '[]';")?;
     }
 
+    // Migrate: add refs column if missing, backfilled from existing content
+    let has_refs = conn.prepare("SELECT refs FROM consolidated LIMIT 0").is_ok();
+    if !has_refs {
+        conn.execute_batch("ALTER TABLE consolidated ADD COLUMN refs TEXT NOT NULL DEFAULT '[]';")?;
+        backfill_refs(&conn, "consolidated")?;
+    }
+
+    // Migrate: add sensitivity column if missing
+    let has_sensitivity = conn.prepare("SELECT sensitivity FROM consolidated LIMIT 0").is_ok();
+    if !has_sensitivity {
+        conn.execute_batch("ALTER TABLE consolidated ADD COLUMN sensitivity TEXT NOT NULL DEFAULT 'internal';")?;
+    }
+
+    // Migrate: add no_llm column if missing
+    let has_no_llm = conn.prepare("SELECT no_llm FROM consolidated LIMIT 0").is_ok();
+    if !has_no_llm {
+        conn.execute_batch("ALTER TABLE consolidated ADD COLUMN no_llm INTEGER NOT NULL DEFAULT 0;")?;
+    }
+
+    // Migrate: add skills.category column if missing
+    let has_category = conn.prepare("SELECT category FROM skills LIMIT 0").is_ok();
+    if !has_category {
+        conn.execute_batch("ALTER TABLE skills ADD COLUMN category TEXT;")?;
+    }
+
+    // Migrate: index skill names and bodies for recall, including skills written before
+    let has_skills_fts = conn.prepare("SELECT 1 FROM skills_fts LIMIT 0").is_ok();
+    if !has_skills_fts {
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE skills_fts USING fts5(name, content, content=skills, content_rowid=id, tokenize='porter unicode61');
+             CREATE TRIGGER IF NOT EXISTS skills_ai AFTER INSERT ON skills BEGIN
+                 INSERT INTO skills_fts(rowid, name, content) VALUES (new.id, new.name, new.content);
+             END;
+             CREATE TRIGGER IF NOT EXISTS skills_ad AFTER DELETE ON skills BEGIN
+                 INSERT INTO skills_fts(skills_fts, rowid, name, content) VALUES('delete', old.id, old.name, old.content);
+             END;
+             CREATE TRIGGER IF NOT EXISTS skills_au AFTER UPDATE OF name, content ON skills BEGIN
+                 INSERT INTO skills_fts(skills_fts, rowid, name, content) VALUES('delete', old.id, old.name, old.content);
+                 INSERT INTO skills_fts(rowid, name, content) VALUES (new.id, new.name, new.content);
+             END;
+             INSERT INTO skills_fts(skills_fts) VALUES('rebuild');",
+        )?;
+    }
+
+    // Migrate: add skills usage columns if missing
+    let has_use_count = conn.prepare("SELECT use_count FROM skills LIMIT 0").is_ok();
+    if !has_use_count {
+        conn.execute_batch(
+            "ALTER TABLE skills ADD COLUMN use_count INTEGER NOT NULL DEFAULT 0;
+             ALTER TABLE skills ADD COLUMN last_used_at TEXT;",
+        )?;
+    }
+
     Ok(conn)
 }
 
+/// Columns added by the migrations in `open_raw_db` and `open_consolidated_db`.
+const MIGRATED_COLUMNS: [(&str, &str); 15] = [
+    ("memories", "entity_ids"),
+    ("memories", "tags"),
+    ("memories", "refs"),
+    ("memories", "trusted"),
+    ("memories", "sensitivity"),
+    ("memories", "no_llm"),
+    ("archived_memories", "sensitivity"),
+    ("archived_memories", "no_llm"),
+    ("consolidated", "entity_ids"),
+    ("consolidated", "refs"),
+    ("consolidated", "sensitivity"),
+    ("consolidated", "no_llm"),
+    ("skills", "category"),
+    ("skills", "use_count"),
+    ("skills", "last_used_at"),
+];
+
+/// Migrations the next open of this database would apply, as `table.column`, found without
+/// changing anything (e.g. on a read-only connection).
+pub fn pending_migrations(conn: &Connection) -> Vec<String> {
+    MIGRATED_COLUMNS
+        .iter()
+        .filter(|(table, column)| {
+            let exists = conn.prepare(&format!("SELECT 1 FROM {} LIMIT 0", table)).is_ok();
+            exists && conn.prepare(&format!("SELECT {} FROM {} LIMIT 0", column, table)).is_err()
+        })
+        .map(|(table, column)| format!("{}.{}", table, column))
+        .collect()
+}
+
+/// Binary versions that have opened this store, with the day (`YYYY-MM-DD`) each last did.
+pub fn binary_versions(conn: &Connection) -> Result<BTreeMap<String, String>> {
+    Ok(get_meta(conn, "binary_versions")?.and_then(|v| serde_json::from_str(&v).ok()).unwrap_or_default())
+}
+
+/// How long a binary's last use of a store counts as current, for mixed-version checks.
+const RECENT_VERSION_DAYS: i64 = 30;
+
+/// The versions in `versions` used within the last `RECENT_VERSION_DAYS`, oldest version first.
+pub fn recent_versions(versions: &BTreeMap<String, String>) -> Vec<(&str, &str)> {
+    let cutoff = (chrono::Utc::now() - chrono::Duration::days(RECENT_VERSION_DAYS)).format("%Y-%m-%d").to_string();
+    let mut recent: Vec<(&str, &str)> =
+        versions.iter().filter(|(_, day)| **day >= cutoff).map(|(v, day)| (v.as_str(), day.as_str())).collect();
+    recent.sort_by_key(|(v, _)| crate::update::parse_version(v));
+    recent
+}
+
+/// Refuse a store with a newer schema than this binary understands, warn when a newer binary
+/// has used it, and record this binary in `meta`. Recording writes at most once a day, and is
+/// skipped on read-only files.
+fn check_version(conn: &Connection) -> Result<()> {
+    let schema = get_meta(conn, "schema_version")?.and_then(|v| v.parse::<u32>().ok()).unwrap_or(0);
+    let mut versions = binary_versions(conn)?;
+    if schema > SCHEMA_VERSION {
+        let newest = versions.keys().max_by_key(|v| crate::update::parse_version(v));
+        anyhow::bail!(
+            "This store uses schema v{} (last opened by cortex {}), but cortex {} only understands v{}. Run `cortex self-update` or install a newer cortex.",
+            schema,
+            newest.map(String::as_str).unwrap_or("a newer version"),
+            BINARY_VERSION,
+            SCHEMA_VERSION
+        );
+    }
+    if let Some((newer, _)) = recent_versions(&versions).pop().filter(|(v, _)| crate::update::is_newer(v, BINARY_VERSION)) {
+        // Once per process: pooled stores open many connections
+        static WARNED: std::sync::Once = std::sync::Once::new();
+        WARNED.call_once(|| {
+            eprintln!(
+                "Warning: cortex {} has used this store; this binary is {}. Run `cortex self-update` to keep them in step.",
+                newer, BINARY_VERSION
+            )
+        });
+    }
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    if versions.get(BINARY_VERSION) != Some(&today) {
+        versions.insert(BINARY_VERSION.to_string(), today);
+        let _ = set_meta(conn, "binary_versions", &serde_json::to_string(&versions)?);
+    }
+    if schema < SCHEMA_VERSION {
+        let _ = set_meta(conn, "schema_version", &SCHEMA_VERSION.to_string());
+    }
+    if get_meta(conn, "created_by")?.is_none() {
+        let _ = set_meta(conn, "created_by", BINARY_VERSION);
+    }
+    Ok(())
+}
+
 // --- Memory CRUD ---
 
 pub fn save_memory(conn: &Connection, content: &str, mem_type: &str, session_id: &str) -> Result<i64> {
+    let refs_json = serde_json::to_string(&refs::extract_refs(content))?;
     conn.execute(
-        "INSERT INTO memories (content, type, session_id) VALUES (?1, ?2, ?3)",
-        params![content, mem_type, session_id],
+        "INSERT INTO memories (content, type, session_id, refs) VALUES (?1, ?2, ?3, ?4)",
+        params![content, mem_type, session_id, refs_json],
     )?;
     Ok(conn.last_insert_rowid())
 }
 
-pub fn save_memory_with_entities(
-    conn: &Connection,
-    content: &str,
-    mem_type: &str,
-    session_id: &str,
-    entity_ids: &[i64],
-) -> Result<i64> {
+pub fn update_memory_entities(conn: &Connection, id: i64, entity_ids: &[i64]) -> Result<()> {
     let entity_json = serde_json::to_string(entity_ids)?;
     conn.execute(
-        "INSERT INTO memories (content, type, session_id, entity_ids) VALUES (?1, ?2, ?3, ?4)",
-        params![content, mem_type, session_id, entity_json],
+        "UPDATE memories SET entity_ids = ?1 WHERE id = ?2",
+        params![entity_json, id],
     )?;
-    Ok(conn.last_insert_rowid())
+    Ok(())
 }
 
-pub fn update_memory_entities(conn: &Connection, id: i64, entity_ids: &[i64]) -> Result<()> {
-    let entity_json = serde_json::to_string(entity_ids)?;
+pub fn update_memory_tags(conn: &Connection, id: i64, tags: &[String]) -> Result<()> {
+    let tags_json = serde_json::to_string(tags)?;
     conn.execute(
-        "UPDATE memories SET entity_ids = ?1 WHERE id = ?2",
-        params![entity_json, id],
+        "UPDATE memories SET tags = ?1 WHERE id = ?2",
+        params![tags_json, id],
     )?;
     Ok(())
 }
 
-pub fn recall_memories(conn: &Connection, query: &str, limit: usize) -> Result<Vec<Memory>> {
-    let fts_query = build_fts_query(query);
+/// Label raw memories. Returns how many were found.
+pub fn set_memory_sensitivity(conn: &Connection, ids: &[i64], sensitivity: Sensitivity) -> Result<usize> {
+    let mut changed = 0;
+    for id in ids {
+        changed += conn.execute("UPDATE memories SET sensitivity = ?1 WHERE id = ?2", params![sensitivity.as_str(), id])?;
+    }
+    Ok(changed)
+}
+
+/// Mark raw memories that must never be sent to an LLM (`cortex save --no-llm`).
+pub fn set_memory_no_llm(conn: &Connection, ids: &[i64]) -> Result<()> {
+    for id in ids {
+        conn.execute("UPDATE memories SET no_llm = 1 WHERE id = ?1", params![id])?;
+    }
+    Ok(())
+}
+
+/// Mark consolidated memories built from `no_llm` sources.
+pub fn set_consolidated_no_llm(conn: &Connection, ids: &[i64]) -> Result<()> {
+    for id in ids {
+        conn.execute("UPDATE consolidated SET no_llm = 1 WHERE id = ?1", params![id])?;
+    }
+    Ok(())
+}
+
+/// Label consolidated memories. Returns how many were found.
+pub fn set_consolidated_sensitivity(conn: &Connection, ids: &[i64], sensitivity: Sensitivity) -> Result<usize> {
+    let mut changed = 0;
+    for id in ids {
+        changed +=
+            conn.execute("UPDATE consolidated SET sensitivity = ?1 WHERE id = ?2", params![sensitivity.as_str(), id])?;
+    }
+    Ok(changed)
+}
+
+/// Merge additional refs (e.g. the CI pull request) into a raw memory.
+pub fn add_memory_refs(conn: &Connection, id: i64, new_refs: &[String]) -> Result<()> {
+    if new_refs.is_empty() {
+        return Ok(());
+    }
+    let existing: String = conn.query_row("SELECT refs FROM memories WHERE id = ?1", params![id], |row| row.get(0))?;
+    let mut merged: Vec<String> = serde_json::from_str(&existing).unwrap_or_default();
+    for r in new_refs {
+        if !merged.contains(r) {
+            merged.push(r.clone());
+        }
+    }
+    conn.execute(
+        "UPDATE memories SET refs = ?1 WHERE id = ?2",
+        params![serde_json::to_string(&merged)?, id],
+    )?;
+    Ok(())
+}
+
+pub fn update_memory_importance(conn: &Connection, id: i64, importance: f64) -> Result<()> {
+    conn.execute(
+        "UPDATE memories SET importance = ?1 WHERE id = ?2",
+        params![importance, id],
+    )?;
+    Ok(())
+}
+
+pub fn recall_memories(conn: &Connection, query: &str, limit: usize, ranking: &RecallConfig) -> Result<Vec<Memory>> {
+    let memories: Vec<Memory> = search_memories_scored(conn, query, limit, ranking)?.into_iter().map(|(m, _)| m).collect();
+    touch_memories(conn, &memories.iter().map(|m| m.id).collect::<Vec<_>>())?;
+    Ok(memories)
+}
+
+/// FTS search with each memory's `[recall]` score, best first. Does not count as an access;
+/// see `touch_memories`.
+pub fn search_memories_scored(
+    conn: &Connection,
+    query: &str,
+    limit: usize,
+    ranking: &RecallConfig,
+) -> Result<Vec<(Memory, f64)>> {
+    let fts_query = query::fts_query(query, fts_tokenizer(conn, "memories_fts")?);
     if fts_query.is_empty() {
         return Ok(vec![]);
     }
 
     let mut stmt = conn.prepare(
         "SELECT m.id, m.content, m.type, m.created_at, m.accessed_at,
-                m.access_count, m.consolidated, m.importance, m.session_id, m.entity_ids
+                m.access_count, m.consolidated, m.importance, m.session_id, m.entity_ids, m.tags, m.refs, m.sensitivity, m.no_llm,
+                pow(-f.rank, ?3) * pow(m.importance, ?4)
+                    / (1.0 + CASE WHEN ?5 > 0 THEN (julianday('now') - julianday(m.accessed_at)) / ?5 ELSE 0 END) AS score
          FROM memories_fts f
          JOIN memories m ON f.rowid = m.id
-         WHERE memories_fts MATCH ?1
-         ORDER BY f.rank * (1.0 / (1.0 + (julianday('now') - julianday(m.accessed_at))))
+         WHERE memories_fts MATCH ?1 AND m.trusted = 1
+         ORDER BY score DESC
          LIMIT ?2",
     )?;
-    let rows = stmt.query_map(params![fts_query, limit as i64], |row| {
-        let entity_ids_str: String = row.get(9)?;
-        let entity_ids: Vec<i64> = serde_json::from_str(&entity_ids_str).unwrap_or_default();
-        Ok(Memory {
-            id: row.get(0)?,
-            content: row.get(1)?,
-            r#type: row.get(2)?,
-            created_at: row.get(3)?,
-            accessed_at: row.get(4)?,
-            access_count: row.get(5)?,
-            consolidated: row.get::<_, i64>(6)? != 0,
-            importance: row.get(7)?,
-            session_id: row.get(8)?,
-            entity_ids,
-        })
-    })?;
-    let mut memories = Vec::new();
-    for row in rows {
-        let m = row?;
-        conn.execute(
-            "UPDATE memories SET accessed_at = datetime('now'), access_count = access_count + 1 WHERE id = ?1",
-            params![m.id],
-        )?;
-        memories.push(m);
+    let rows = stmt.query_map(
+        params![fts_query, limit as i64, ranking.bm25_weight, ranking.importance_weight, ranking.raw_half_life_days],
+        |row| Ok((memory_from_row(row)?, row.get(14)?)),
+    )?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Trusted memories containing any of `words` anywhere, not only at a word start as full-text
+/// search requires. A linear scan, so only for the zero-hit fallback. Newest first.
+pub fn search_memories_substring(conn: &Connection, words: &[String], limit: usize) -> Result<Vec<Memory>> {
+    if words.is_empty() {
+        return Ok(vec![]);
     }
-    Ok(memories)
+    let patterns: Vec<String> = words
+        .iter()
+        .map(|w| format!("%{}%", w.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")))
+        .collect();
+    let mut stmt = conn.prepare(
+        "SELECT id, content, type, created_at, accessed_at, access_count, consolidated, importance,
+                session_id, entity_ids, tags, refs, sensitivity, no_llm
+         FROM memories
+         WHERE trusted = 1 AND EXISTS (SELECT 1 FROM json_each(?1) p WHERE content LIKE p.value ESCAPE '\\')
+         ORDER BY id DESC
+         LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![serde_json::to_string(&patterns)?, limit as i64], memory_from_row)?;
+    rows.into_iter().map(|r| Ok(r?)).collect()
+}
+
+/// Record an access to each memory: bumps `access_count` and `accessed_at`, which slows decay.
+pub fn touch_memories(conn: &Connection, ids: &[i64]) -> Result<()> {
+    let mut stmt =
+        conn.prepare("UPDATE memories SET accessed_at = datetime('now'), access_count = access_count + 1 WHERE id = ?1")?;
+    for id in ids {
+        stmt.execute(params![id])?;
+    }
+    Ok(())
 }
 
 /// Recall memories by entity: find all memories referencing an entity and optionally its neighbors.
@@ -260,9 +727,9 @@ pub fn recall_by_entity(conn: &Connection, entity_name: &str, include_neighbors:
     // We use json_each to check if entity_ids array contains any of our target IDs
     let query = format!(
         "SELECT DISTINCT m.id, m.content, m.type, m.created_at, m.accessed_at,
-                m.access_count, m.consolidated, m.importance, m.session_id, m.entity_ids
+                m.access_count, m.consolidated, m.importance, m.session_id, m.entity_ids, m.tags, m.refs, m.sensitivity, m.no_llm
          FROM memories m, json_each(m.entity_ids) e
-         WHERE e.value IN ({})
+         WHERE e.value IN ({}) AND m.trusted = 1
          ORDER BY m.accessed_at DESC
          LIMIT ?",
         placeholders.join(", ")
@@ -276,23 +743,57 @@ pub fn recall_by_entity(conn: &Connection, entity_name: &str, include_neighbors:
     param_values.push(Box::new(limit as i64));
 
     let params_refs: Vec<&dyn rusqlite::types::ToSql> = param_values.iter().map(|p| p.as_ref()).collect();
-    let rows = stmt.query_map(params_refs.as_slice(), |row| {
-        let entity_ids_str: String = row.get(9)?;
-        let entity_ids: Vec<i64> = serde_json::from_str(&entity_ids_str).unwrap_or_default();
-        Ok(Memory {
-            id: row.get(0)?,
-            content: row.get(1)?,
-            r#type: row.get(2)?,
-            created_at: row.get(3)?,
-            accessed_at: row.get(4)?,
-            access_count: row.get(5)?,
-            consolidated: row.get::<_, i64>(6)? != 0,
-            importance: row.get(7)?,
-            session_id: row.get(8)?,
-            entity_ids,
-        })
-    })?;
+    let rows = stmt.query_map(params_refs.as_slice(), memory_from_row)?;
+
+    rows.into_iter().map(|r| Ok(r?)).collect()
+}
+
+/// Record that raw memory `memory_id` contradicts consolidated memory `consolidated_id`.
+pub fn link_contradiction(conn: &Connection, memory_id: i64, consolidated_id: i64, reason: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO contradictions (memory_id, consolidated_id, reason) VALUES (?1, ?2, ?3)",
+        params![memory_id, consolidated_id, reason],
+    )?;
+    Ok(())
+}
+
+/// Contradictions linked at save time, as (memory ID, consolidated ID, reason).
+pub fn get_contradictions(conn: &Connection) -> Result<Vec<(i64, i64, String)>> {
+    let mut stmt = conn.prepare("SELECT memory_id, consolidated_id, reason FROM contradictions ORDER BY memory_id")?;
+    let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Raw memories referencing an issue/PR (case-insensitive exact match).
+pub fn recall_by_ref(conn: &Connection, reference: &str, limit: usize) -> Result<Vec<Memory>> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT m.id, m.content, m.type, m.created_at, m.accessed_at,
+                m.access_count, m.consolidated, m.importance, m.session_id, m.entity_ids, m.tags, m.refs, m.sensitivity, m.no_llm
+         FROM memories m, json_each(m.refs) r
+         WHERE r.value = ?1 COLLATE NOCASE
+         ORDER BY m.created_at DESC
+         LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![reference, limit as i64], memory_from_row)?;
+    rows.into_iter().map(|r| Ok(r?)).collect()
+}
 
+/// Raw memories with the given IDs, live or archived, in ID order. Archived ones carry their
+/// `archived_at` time; IDs found in neither table are left out.
+pub fn get_source_memories(conn: &Connection, ids: &[i64]) -> Result<Vec<(Memory, Option<String>)>> {
+    let ids_json = serde_json::to_string(ids)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, content, type, created_at, accessed_at, access_count, consolidated, importance,
+                session_id, entity_ids, tags, refs, sensitivity, no_llm, NULL
+         FROM memories WHERE id IN (SELECT value FROM json_each(?1))
+         UNION ALL
+         SELECT id, content, type, created_at, accessed_at, access_count, consolidated, importance,
+                session_id, entity_ids, tags, refs, sensitivity, no_llm, archived_at
+         FROM archived_memories
+         WHERE id IN (SELECT value FROM json_each(?1)) AND id NOT IN (SELECT id FROM memories)
+         ORDER BY 1",
+    )?;
+    let rows = stmt.query_map(params![ids_json], |row| Ok((memory_from_row(row)?, row.get(14)?)))?;
     rows.into_iter().map(|r| Ok(r?)).collect()
 }
 
@@ -306,40 +807,126 @@ pub fn get_unconsolidated_count(conn: &Connection) -> Result<i64> {
 
 pub fn get_unconsolidated_memories(conn: &Connection) -> Result<Vec<Memory>> {
     let mut stmt = conn.prepare(
-        "SELECT id, content, type, created_at, accessed_at, access_count, consolidated, importance, session_id, entity_ids
+        "SELECT id, content, type, created_at, accessed_at, access_count, consolidated, importance, session_id, entity_ids, tags, refs, sensitivity, no_llm
          FROM memories WHERE consolidated = 0 ORDER BY created_at ASC",
     )?;
-    let rows = stmt.query_map([], |row| {
-        let entity_ids_str: String = row.get(9)?;
-        let entity_ids: Vec<i64> = serde_json::from_str(&entity_ids_str).unwrap_or_default();
-        Ok(Memory {
-            id: row.get(0)?,
-            content: row.get(1)?,
-            r#type: row.get(2)?,
-            created_at: row.get(3)?,
-            accessed_at: row.get(4)?,
-            access_count: row.get(5)?,
-            consolidated: row.get::<_, i64>(6)? != 0,
-            importance: row.get(7)?,
-            session_id: row.get(8)?,
-            entity_ids,
-        })
-    })?;
+    let rows = stmt.query_map([], memory_from_row)?;
     rows.into_iter().map(|r| Ok(r?)).collect()
 }
 
+pub fn get_memories_by_type(conn: &Connection, mem_type: &str) -> Result<Vec<Memory>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content, type, created_at, accessed_at, access_count, consolidated, importance, session_id, entity_ids, tags, refs, sensitivity, no_llm
+         FROM memories WHERE type = ?1 ORDER BY created_at ASC",
+    )?;
+    let rows = stmt.query_map(params![mem_type], memory_from_row)?;
+    rows.into_iter().map(|r| Ok(r?)).collect()
+}
+
+/// Mark memories as consolidated. Consolidation also promotes untrusted memories: sleep has
+/// reviewed them, so they may show up in recall from now on.
 pub fn mark_consolidated(conn: &Connection, ids: &[i64]) -> Result<()> {
+    for id in ids {
+        conn.execute("UPDATE memories SET consolidated = 1, trusted = 1 WHERE id = ?1", params![id])?;
+    }
+    Ok(())
+}
+
+/// Mark memories kept out of consolidation as processed, without trusting them.
+pub fn mark_withheld(conn: &Connection, ids: &[i64]) -> Result<()> {
     for id in ids {
         conn.execute("UPDATE memories SET consolidated = 1 WHERE id = ?1", params![id])?;
     }
     Ok(())
 }
 
+/// Set whether raw memories may appear in recall and context. Returns how many were found.
+pub fn set_trusted(conn: &Connection, ids: &[i64], trusted: bool) -> Result<usize> {
+    let mut changed = 0;
+    for id in ids {
+        changed += conn.execute("UPDATE memories SET trusted = ?1 WHERE id = ?2", params![trusted, id])?;
+    }
+    Ok(changed)
+}
+
+/// Memories waiting for a sleep or `cortex trust` before they appear in recall and context.
+pub fn get_untrusted_memories(conn: &Connection) -> Result<Vec<Memory>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content, type, created_at, accessed_at, access_count, consolidated, importance, session_id, entity_ids, tags, refs, sensitivity, no_llm
+         FROM memories WHERE trusted = 0 ORDER BY created_at ASC",
+    )?;
+    let rows = stmt.query_map([], memory_from_row)?;
+    rows.into_iter().map(|r| Ok(r?)).collect()
+}
+
+pub fn get_trusted_memories(conn: &Connection) -> Result<Vec<Memory>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content, type, created_at, accessed_at, access_count, consolidated, importance, session_id, entity_ids, tags, refs, sensitivity, no_llm
+         FROM memories WHERE trusted = 1 ORDER BY id",
+    )?;
+    let rows = stmt.query_map([], memory_from_row)?;
+    rows.into_iter().map(|r| Ok(r?)).collect()
+}
+
+/// Call `f` with each trusted memory in ID order without loading them all at once.
+pub fn each_memory(conn: &Connection, mut f: impl FnMut(Memory) -> Result<()>) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content, type, created_at, accessed_at, access_count, consolidated, importance, session_id, entity_ids, tags, refs, sensitivity, no_llm
+         FROM memories WHERE trusted = 1 ORDER BY id",
+    )?;
+    for m in stmt.query_map([], memory_from_row)? {
+        f(m?)?;
+    }
+    Ok(())
+}
+
 pub fn delete_memory(conn: &Connection, id: i64) -> Result<()> {
     conn.execute("DELETE FROM memories WHERE id = ?1", params![id])?;
     Ok(())
 }
 
+/// Keep the full text of a memory whose stored content was shortened.
+pub fn insert_attachment(conn: &Connection, memory_id: i64, content: &str, summarized: bool) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO attachments (memory_id, content, summarized) VALUES (?1, ?2, ?3)",
+        params![memory_id, content, summarized],
+    )?;
+    Ok(())
+}
+
+pub fn get_attachment(conn: &Connection, memory_id: i64) -> Result<Option<String>> {
+    let result = conn.query_row(
+        "SELECT content FROM attachments WHERE memory_id = ?1",
+        params![memory_id],
+        |row| compress::text(row, 0),
+    );
+    match result {
+        Ok(v) => Ok(Some(v)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Unconsolidated memories that were truncated rather than summarized: (memory id, full text).
+pub fn get_unsummarized_attachments(conn: &Connection, limit: usize) -> Result<Vec<(i64, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT a.memory_id, a.content FROM attachments a
+         JOIN memories m ON m.id = a.memory_id
+         WHERE a.summarized = 0 AND m.consolidated = 0 AND m.no_llm = 0
+         ORDER BY a.memory_id
+         LIMIT ?1",
+    )?;
+    let rows = stmt.query_map(params![limit as i64], |row| Ok((row.get(0)?, compress::text(row, 1)?)))?;
+    rows.into_iter().map(|r| Ok(r?)).collect()
+}
+
+/// Replace a truncated memory's content with its summary.
+pub fn set_summarized_content(conn: &Connection, memory_id: i64, summary: &str) -> Result<()> {
+    conn.execute("UPDATE memories SET content = ?1 WHERE id = ?2", params![summary, memory_id])?;
+    conn.execute("UPDATE attachments SET summarized = 1 WHERE memory_id = ?1", params![memory_id])?;
+    Ok(())
+}
+
 // --- Entity CRUD ---
 
 pub fn upsert_entity(conn: &Connection, name: &str, entity_type: &str, description: Option<&str>) -> Result<i64> {
@@ -397,7 +984,7 @@ pub fn get_entity_by_name(conn: &Connection, name: &str) -> Result<Option<Entity
 pub fn get_all_entities(conn: &Connection) -> Result<Vec<Entity>> {
     let mut stmt = conn.prepare(
         "SELECT id, name, entity_type, description, confidence, created_at, updated_at, access_count
-         FROM entities ORDER BY access_count DESC, updated_at DESC",
+         FROM entities ORDER BY access_count DESC, updated_at DESC, id",
     )?;
     let rows = stmt.query_map([], |row| {
         Ok(Entity {
@@ -415,7 +1002,7 @@ pub fn get_all_entities(conn: &Connection) -> Result<Vec<Entity>> {
 }
 
 pub fn search_entities(conn: &Connection, query: &str, limit: usize) -> Result<Vec<Entity>> {
-    let fts_query = build_fts_query(query);
+    let fts_query = query::fts_query(query, fts_tokenizer(conn, "entities_fts")?);
     if fts_query.is_empty() {
         return Ok(vec![]);
     }
@@ -491,31 +1078,6 @@ pub fn upsert_relationship(
     }
 }
 
-pub fn get_relationships_for_entity(conn: &Connection, entity_id: i64) -> Result<Vec<Relationship>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, source_entity_id, target_entity_id, relation_type, weight, evidence_ids, confidence, created_at, updated_at
-         FROM relationships
-         WHERE source_entity_id = ?1 OR target_entity_id = ?1
-         ORDER BY weight DESC",
-    )?;
-    let rows = stmt.query_map(params![entity_id], |row| {
-        let evidence_str: String = row.get(5)?;
-        let evidence_ids: Vec<i64> = serde_json::from_str(&evidence_str).unwrap_or_default();
-        Ok(Relationship {
-            id: row.get(0)?,
-            source_entity_id: row.get(1)?,
-            target_entity_id: row.get(2)?,
-            relation_type: row.get(3)?,
-            weight: row.get(4)?,
-            evidence_ids,
-            confidence: row.get(6)?,
-            created_at: row.get(7)?,
-            updated_at: row.get(8)?,
-        })
-    })?;
-    rows.into_iter().map(|r| Ok(r?)).collect()
-}
-
 pub fn get_all_relationships(conn: &Connection) -> Result<Vec<Relationship>> {
     let mut stmt = conn.prepare(
         "SELECT id, source_entity_id, target_entity_id, relation_type, weight, evidence_ids, confidence, created_at, updated_at
@@ -547,66 +1109,136 @@ pub fn get_relationship_count(conn: &Connection) -> Result<i64> {
 
 pub fn get_all_consolidated(conn: &Connection) -> Result<Vec<ConsolidatedMemory>> {
     let mut stmt = conn.prepare(
-        "SELECT id, content, type, source_ids, confidence, created_at, updated_at, access_count
+        "SELECT id, content, type, source_ids, confidence, created_at, updated_at, access_count, refs, sensitivity, no_llm
          FROM consolidated ORDER BY updated_at DESC",
     )?;
-    let rows = stmt.query_map([], |row| {
-        let source_ids_str: String = row.get(3)?;
-        let source_ids: Vec<i64> = serde_json::from_str(&source_ids_str).unwrap_or_default();
-        Ok(ConsolidatedMemory {
-            id: row.get(0)?,
-            content: row.get(1)?,
-            r#type: row.get(2)?,
-            source_ids,
-            confidence: row.get(4)?,
-            created_at: row.get(5)?,
-            updated_at: row.get(6)?,
-            access_count: row.get(7)?,
-        })
-    })?;
+    let rows = stmt.query_map([], consolidated_from_row)?;
     rows.into_iter().map(|r| Ok(r?)).collect()
 }
 
-pub fn search_consolidated(conn: &Connection, query: &str, limit: usize) -> Result<Vec<ConsolidatedMemory>> {
-    let fts_query = build_fts_query(query);
+/// Call `f` with each consolidated memory in ID order without loading them all at once.
+pub fn each_consolidated(conn: &Connection, mut f: impl FnMut(ConsolidatedMemory) -> Result<()>) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content, type, source_ids, confidence, created_at, updated_at, access_count, refs, sensitivity, no_llm
+         FROM consolidated ORDER BY id",
+    )?;
+    for c in stmt.query_map([], consolidated_from_row)? {
+        f(c?)?;
+    }
+    Ok(())
+}
+
+pub fn get_consolidated_by_type(conn: &Connection, mem_type: &str) -> Result<Vec<ConsolidatedMemory>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content, type, source_ids, confidence, created_at, updated_at, access_count, refs, sensitivity, no_llm
+         FROM consolidated WHERE type = ?1 ORDER BY created_at ASC",
+    )?;
+    let rows = stmt.query_map(params![mem_type], consolidated_from_row)?;
+    rows.into_iter().map(|r| Ok(r?)).collect()
+}
+
+pub fn get_consolidated(conn: &Connection, id: i64) -> Result<Option<ConsolidatedMemory>> {
+    let result = conn.query_row(
+        "SELECT id, content, type, source_ids, confidence, created_at, updated_at, access_count, refs, sensitivity, no_llm
+         FROM consolidated WHERE id = ?1",
+        params![id],
+        consolidated_from_row,
+    );
+    match result {
+        Ok(c) => Ok(Some(c)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn search_consolidated(
+    conn: &Connection,
+    query: &str,
+    limit: usize,
+    ranking: &RecallConfig,
+) -> Result<Vec<ConsolidatedMemory>> {
+    Ok(search_consolidated_scored(conn, query, limit, ranking)?.into_iter().map(|(c, _)| c).collect())
+}
+
+/// FTS search of consolidated memory with each entry's `[recall]` score (including
+/// `consolidated_boost`), best first.
+pub fn search_consolidated_scored(
+    conn: &Connection,
+    query: &str,
+    limit: usize,
+    ranking: &RecallConfig,
+) -> Result<Vec<(ConsolidatedMemory, f64)>> {
+    let fts_query = query::fts_query(query, fts_tokenizer(conn, "consolidated_fts")?);
     if fts_query.is_empty() {
         return Ok(vec![]);
     }
 
     let mut stmt = conn.prepare(
-        "SELECT c.id, c.content, c.type, c.source_ids, c.confidence, c.created_at, c.updated_at, c.access_count
+        "SELECT c.id, c.content, c.type, c.source_ids, c.confidence, c.created_at, c.updated_at, c.access_count, c.refs, c.sensitivity, c.no_llm,
+                ?6 * pow(-f.rank, ?3) * pow(c.confidence, ?4)
+                    / (1.0 + CASE WHEN ?5 > 0 THEN (julianday('now') - julianday(c.updated_at)) / ?5 ELSE 0 END) AS score
          FROM consolidated_fts f
          JOIN consolidated c ON f.rowid = c.id
          WHERE consolidated_fts MATCH ?1
-         ORDER BY f.rank * c.confidence * (1.0 / (1.0 + (julianday('now') - julianday(c.updated_at)) / 30.0))
+         ORDER BY score DESC
          LIMIT ?2",
     )?;
-    let rows = stmt.query_map(params![fts_query, limit as i64], |row| {
-        let source_ids_str: String = row.get(3)?;
-        let source_ids: Vec<i64> = serde_json::from_str(&source_ids_str).unwrap_or_default();
-        Ok(ConsolidatedMemory {
-            id: row.get(0)?,
-            content: row.get(1)?,
-            r#type: row.get(2)?,
-            source_ids,
-            confidence: row.get(4)?,
-            created_at: row.get(5)?,
-            updated_at: row.get(6)?,
-            access_count: row.get(7)?,
-        })
-    })?;
+    let rows = stmt.query_map(
+        params![
+            fts_query,
+            limit as i64,
+            ranking.bm25_weight,
+            ranking.importance_weight,
+            ranking.half_life_days,
+            ranking.consolidated_boost
+        ],
+        |row| Ok((consolidated_from_row(row)?, row.get(11)?)),
+    )?;
     rows.into_iter().map(|r| Ok(r?)).collect()
 }
 
 pub fn insert_consolidated(conn: &Connection, content: &str, mem_type: &str, source_ids: &[i64], confidence: f64) -> Result<i64> {
     let source_json = serde_json::to_string(source_ids)?;
+    let refs_json = serde_json::to_string(&refs::extract_refs(content))?;
     conn.execute(
-        "INSERT INTO consolidated (content, type, source_ids, confidence) VALUES (?1, ?2, ?3, ?4)",
-        params![content, mem_type, source_json, confidence],
+        "INSERT INTO consolidated (content, type, source_ids, confidence, refs) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![content, mem_type, source_json, confidence, refs_json],
     )?;
     Ok(conn.last_insert_rowid())
 }
 
+/// Merge additional refs (e.g. from source memories) into a consolidated entry.
+pub fn add_consolidated_refs(conn: &Connection, id: i64, new_refs: &[String]) -> Result<()> {
+    if new_refs.is_empty() {
+        return Ok(());
+    }
+    let existing: String = conn.query_row("SELECT refs FROM consolidated WHERE id = ?1", params![id], |row| row.get(0))?;
+    let mut merged: Vec<String> = serde_json::from_str(&existing).unwrap_or_default();
+    for r in new_refs {
+        if !merged.contains(r) {
+            merged.push(r.clone());
+        }
+    }
+    conn.execute(
+        "UPDATE consolidated SET refs = ?1 WHERE id = ?2",
+        params![serde_json::to_string(&merged)?, id],
+    )?;
+    Ok(())
+}
+
+/// Consolidated memories referencing an issue/PR (case-insensitive exact match).
+pub fn consolidated_by_ref(conn: &Connection, reference: &str, limit: usize) -> Result<Vec<ConsolidatedMemory>> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT c.id, c.content, c.type, c.source_ids, c.confidence, c.created_at, c.updated_at, c.access_count, c.refs, c.sensitivity, c.no_llm
+         FROM consolidated c, json_each(c.refs) r
+         WHERE r.value = ?1 COLLATE NOCASE
+         ORDER BY c.updated_at DESC
+         LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![reference, limit as i64], consolidated_from_row)?;
+    rows.into_iter().map(|r| Ok(r?)).collect()
+}
+
 pub fn consolidated_content_exists(conn: &Connection, content: &str) -> Result<bool> {
     let count: i64 = conn.query_row(
         "SELECT COUNT(*) FROM consolidated WHERE content = ?1",
@@ -621,13 +1253,53 @@ pub fn get_consolidated_count(conn: &Connection) -> Result<i64> {
 }
 
 pub fn update_consolidated(conn: &Connection, id: i64, content: &str) -> Result<bool> {
+    let refs_json = serde_json::to_string(&refs::extract_refs(content))?;
     let updated = conn.execute(
-        "UPDATE consolidated SET content = ?1, updated_at = datetime('now') WHERE id = ?2",
-        params![content, id],
+        "UPDATE consolidated SET content = ?1, refs = ?2, updated_at = datetime('now') WHERE id = ?3",
+        params![content, refs_json, id],
     )?;
     Ok(updated > 0)
 }
 
+/// Credit new observations to an existing consolidated memory: merge their ids into
+/// `source_ids`, bump `access_count`, and nudge confidence up.
+pub fn reinforce_consolidated(conn: &Connection, id: i64, source_ids: &[i64]) -> Result<()> {
+    let existing: String = conn.query_row("SELECT source_ids FROM consolidated WHERE id = ?1", params![id], |row| row.get(0))?;
+    let mut merged: Vec<i64> = serde_json::from_str(&existing).unwrap_or_default();
+    for sid in source_ids {
+        if !merged.contains(sid) {
+            merged.push(*sid);
+        }
+    }
+    conn.execute(
+        "UPDATE consolidated SET source_ids = ?1, access_count = access_count + ?2,
+         confidence = MIN(1.0, confidence + 0.05), updated_at = datetime('now') WHERE id = ?3",
+        params![serde_json::to_string(&merged)?, source_ids.len() as i64, id],
+    )?;
+    Ok(())
+}
+
+/// Consolidated memories not updated in at least `days` days, with their age in days.
+pub fn get_stale_consolidated(conn: &Connection, days: u32) -> Result<Vec<(ConsolidatedMemory, f64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content, type, source_ids, confidence, created_at, updated_at, access_count, refs, sensitivity, no_llm,
+                julianday('now') - julianday(updated_at) AS age
+         FROM consolidated WHERE age >= ?1 ORDER BY updated_at ASC",
+    )?;
+    let rows = stmt.query_map(params![days], |row| Ok((consolidated_from_row(row)?, row.get(11)?)))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Mark a consolidated memory as current: bumps `updated_at` and adds `confidence_boost`
+/// (capped at 1.0) to its confidence.
+pub fn refresh_consolidated(conn: &Connection, id: i64, confidence_boost: f64) -> Result<bool> {
+    let changed = conn.execute(
+        "UPDATE consolidated SET updated_at = datetime('now'), confidence = MIN(1.0, confidence + ?2) WHERE id = ?1",
+        params![id, confidence_boost],
+    )?;
+    Ok(changed > 0)
+}
+
 pub fn remove_consolidated(conn: &Connection, ids: &[i64]) -> Result<()> {
     for id in ids {
         conn.execute("DELETE FROM consolidated WHERE id = ?1", params![id])?;
@@ -637,35 +1309,120 @@ pub fn remove_consolidated(conn: &Connection, ids: &[i64]) -> Result<()> {
 
 // --- Skills ---
 
-pub fn upsert_skill(conn: &Connection, name: &str, content: &str, source_ids: &[i64]) -> Result<()> {
+/// Insert or replace a skill. A `None` category keeps the existing one on update.
+pub fn upsert_skill(
+    conn: &Connection,
+    name: &str,
+    category: Option<&str>,
+    content: &str,
+    source_ids: &[i64],
+) -> Result<()> {
     let source_json = serde_json::to_string(source_ids)?;
     conn.execute(
-        "INSERT INTO skills (name, content, source_ids, updated_at)
-         VALUES (?1, ?2, ?3, datetime('now'))
-         ON CONFLICT(name) DO UPDATE SET content = ?2, source_ids = ?3, updated_at = datetime('now')",
-        params![name, content, source_json],
+        "INSERT INTO skills (name, content, source_ids, category, updated_at)
+         VALUES (?1, ?2, ?3, ?4, datetime('now'))
+         ON CONFLICT(name) DO UPDATE SET content = ?2, source_ids = ?3,
+             category = COALESCE(?4, category), updated_at = datetime('now')",
+        params![name, content, source_json, category],
     )?;
     Ok(())
 }
 
+/// Write a skill synced from another store by name, keeping its timestamps. Usage counts only
+/// go up, and an existing skill keeps its local `source_ids`.
+pub fn put_skill(conn: &Connection, skill: &Skill) -> Result<()> {
+    conn.execute(
+        "INSERT INTO skills (name, content, category, updated_at, use_count, last_used_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(name) DO UPDATE SET content = ?2, category = ?3, updated_at = ?4,
+             use_count = MAX(use_count, ?5),
+             last_used_at = NULLIF(MAX(COALESCE(last_used_at, ''), COALESCE(?6, '')), '')",
+        params![skill.name, skill.content, skill.category, skill.updated_at, skill.use_count, skill.last_used_at],
+    )?;
+    Ok(())
+}
+
+/// Remember which sanitized skill name an LLM-supplied name was stored under.
+pub fn record_skill_alias(conn: &Connection, alias: &str, name: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO skill_aliases (alias, name) VALUES (?1, ?2) ON CONFLICT(alias) DO UPDATE SET name = ?2",
+        params![alias, name],
+    )?;
+    Ok(())
+}
+
+pub fn resolve_skill_alias(conn: &Connection, alias: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM skill_aliases WHERE alias = ?1")?;
+    let mut rows = stmt.query(params![alias])?;
+    Ok(match rows.next()? {
+        Some(row) => Some(row.get(0)?),
+        None => None,
+    })
+}
+
+fn skill_from_row(row: &rusqlite::Row) -> rusqlite::Result<Skill> {
+    let source_ids_str: String = row.get(3)?;
+    let source_ids: Vec<i64> = serde_json::from_str(&source_ids_str).unwrap_or_default();
+    Ok(Skill {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        content: row.get(2)?,
+        source_ids,
+        updated_at: row.get(4)?,
+        category: row.get(5)?,
+        use_count: row.get(6)?,
+        last_used_at: row.get(7)?,
+        snippet: None,
+    })
+}
+
 pub fn get_all_skills(conn: &Connection) -> Result<Vec<Skill>> {
     let mut stmt = conn.prepare(
-        "SELECT id, name, content, source_ids, updated_at FROM skills ORDER BY name",
+        "SELECT id, name, content, source_ids, updated_at, category, use_count, last_used_at FROM skills
+         ORDER BY category, name",
     )?;
-    let rows = stmt.query_map([], |row| {
-        let source_ids_str: String = row.get(3)?;
-        let source_ids: Vec<i64> = serde_json::from_str(&source_ids_str).unwrap_or_default();
-        Ok(Skill {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            content: row.get(2)?,
-            source_ids,
-            updated_at: row.get(4)?,
-        })
-    })?;
+    let rows = stmt.query_map([], skill_from_row)?;
     rows.into_iter().map(|r| Ok(r?)).collect()
 }
 
+/// FTS search of skill names and bodies, best match first, each with a `snippet` of the body
+/// around the matched words.
+pub fn search_skills(conn: &Connection, query: &str, limit: usize) -> Result<Vec<Skill>> {
+    let fts_query = query::fts_query(query, fts_tokenizer(conn, "skills_fts")?);
+    if fts_query.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut stmt = conn.prepare(
+        "SELECT s.id, s.name, s.content, s.source_ids, s.updated_at, s.category, s.use_count, s.last_used_at,
+                snippet(skills_fts, 1, '', '', '…', 16)
+         FROM skills_fts f
+         JOIN skills s ON f.rowid = s.id
+         WHERE skills_fts MATCH ?1
+         ORDER BY f.rank
+         LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![fts_query, limit as i64], |row| {
+        let snippet: String = row.get(8)?;
+        Ok(Skill { snippet: Some(snippet.split_whitespace().collect::<Vec<_>>().join(" ")), ..skill_from_row(row)? })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Count a read of skill `id`: bumps `use_count` and sets `last_used_at`, leaving `updated_at`.
+pub fn record_skill_use(conn: &Connection, id: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE skills SET use_count = use_count + 1, last_used_at = datetime('now') WHERE id = ?1",
+        params![id],
+    )?;
+    Ok(())
+}
+
+/// Delete a skill and the aliases pointing at it. Returns whether it existed.
+pub fn remove_skill(conn: &Connection, name: &str) -> Result<bool> {
+    conn.execute("DELETE FROM skill_aliases WHERE name = ?1", params![name])?;
+    Ok(conn.execute("DELETE FROM skills WHERE name = ?1", params![name])? > 0)
+}
+
 // --- Meta ---
 
 pub fn set_meta(conn: &Connection, key: &str, value: &str) -> Result<()> {
@@ -676,6 +1433,12 @@ pub fn set_meta(conn: &Connection, key: &str, value: &str) -> Result<()> {
     Ok(())
 }
 
+/// Record a sleep or dream (`kind`) for `cortex timeline`.
+pub fn log_activity(conn: &Connection, kind: &str) -> Result<()> {
+    conn.execute("INSERT INTO activity (kind) VALUES (?1)", params![kind])?;
+    Ok(())
+}
+
 pub fn get_meta(conn: &Connection, key: &str) -> Result<Option<String>> {
     let result = conn.query_row(
         "SELECT value FROM meta WHERE key = ?1",
@@ -689,6 +1452,120 @@ pub fn get_meta(conn: &Connection, key: &str) -> Result<Option<String>> {
     }
 }
 
+// --- Full-text indexes ---
+
+/// FTS5 tables and their columns/options, in either database. Created in `open_raw_db` and
+/// `open_consolidated_db`; only the tokenizer changes after that.
+const FTS_TABLES: &[(&str, &str)] = &[
+    ("memories_fts", "content, type, content=memories, content_rowid=id"),
+    ("entities_fts", "name, description, content=entities, content_rowid=id"),
+    ("consolidated_fts", "content, type, content=consolidated, content_rowid=id"),
+    ("skills_fts", "name, content, content=skills, content_rowid=id"),
+];
+
+/// Tokenizer an FTS5 table was created with.
+pub fn fts_tokenizer(conn: &Connection, table: &str) -> Result<Tokenizer> {
+    let sql: String = conn.query_row(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        params![table],
+        |row| row.get(0),
+    )?;
+    Ok(Tokenizer::from_create_sql(&sql))
+}
+
+/// Recreate this database's FTS5 tables that don't use `tokenizer` and rebuild them from
+/// their content tables. Returns the tables that were rebuilt.
+pub fn set_fts_tokenizer(conn: &Connection, tokenizer: Tokenizer) -> Result<Vec<&'static str>> {
+    let mut rebuilt = Vec::new();
+    for (table, columns) in FTS_TABLES {
+        if !fts_tables(conn)?.contains(table) || fts_tokenizer(conn, table)? == tokenizer {
+            continue;
+        }
+        // The sync triggers live on the content table and survive the drop
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(&format!(
+            "DROP TABLE {table};
+            CREATE VIRTUAL TABLE {table} USING fts5({columns}, tokenize='{}');
+            INSERT INTO {table}({table}) VALUES('rebuild');",
+            tokenizer.fts_option()
+        ))?;
+        tx.commit()?;
+        rebuilt.push(*table);
+    }
+    Ok(rebuilt)
+}
+
+/// The FTS5 tables that exist in this database.
+pub fn fts_tables(conn: &Connection) -> Result<Vec<&'static str>> {
+    let mut stmt = conn.prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")?;
+    let mut tables = Vec::new();
+    for (table, _) in FTS_TABLES {
+        if stmt.exists(params![table])? {
+            tables.push(*table);
+        }
+    }
+    Ok(tables)
+}
+
+/// Whether an FTS5 index matches its content table (false after deletes or updates that
+/// bypassed the sync triggers).
+pub fn fts_in_sync(conn: &Connection, table: &str) -> Result<bool> {
+    // rank = 1 also compares the index against the content table
+    match conn.execute(&format!("INSERT INTO {table}({table}, rank) VALUES('integrity-check', 1)"), []) {
+        Ok(_) => Ok(true),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::DatabaseCorrupt => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Rebuild an FTS5 index from its content table.
+pub fn rebuild_fts(conn: &Connection, table: &str) -> Result<()> {
+    conn.execute(&format!("INSERT INTO {table}({table}) VALUES('rebuild')"), [])?;
+    Ok(())
+}
+
+// --- Sleep runs ---
+
+/// Record, in consolidated.db, the raw.db changes a sleep run still has to apply.
+pub fn insert_pending_run(conn: &Connection, run_id: &str, raw_changes: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO pending_runs (run_id, raw_changes) VALUES (?1, ?2)",
+        params![run_id, raw_changes],
+    )?;
+    Ok(())
+}
+
+pub fn get_pending_runs(conn: &Connection) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare("SELECT run_id, raw_changes FROM pending_runs ORDER BY created_at, run_id")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.into_iter().map(|r| Ok(r?)).collect()
+}
+
+pub fn delete_pending_run(conn: &Connection, run_id: &str) -> Result<()> {
+    conn.execute("DELETE FROM pending_runs WHERE run_id = ?1", params![run_id])?;
+    Ok(())
+}
+
+/// Record, in raw.db, that a sleep run's raw changes were applied.
+pub fn insert_applied_run(conn: &Connection, run_id: &str) -> Result<()> {
+    conn.execute("INSERT OR IGNORE INTO applied_runs (run_id) VALUES (?1)", params![run_id])?;
+    Ok(())
+}
+
+pub fn is_run_applied(conn: &Connection, run_id: &str) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM applied_runs WHERE run_id = ?1",
+        params![run_id],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+pub fn delete_applied_run(conn: &Connection, run_id: &str) -> Result<()> {
+    conn.execute("DELETE FROM applied_runs WHERE run_id = ?1", params![run_id])?;
+    Ok(())
+}
+
 // --- Stats ---
 
 pub fn get_stats(raw_conn: &Connection, cons_conn: &Connection) -> Result<Stats> {
@@ -699,19 +1576,92 @@ pub fn get_stats(raw_conn: &Connection, cons_conn: &Connection) -> Result<Stats>
     let entity_count: i64 = get_entity_count(raw_conn)?;
     let relationship_count: i64 = get_relationship_count(raw_conn)?;
     let last_sleep = get_meta(cons_conn, "last_sleep")?;
-    Ok(Stats { raw_count, unconsolidated_count, consolidated_count, skill_count, entity_count, relationship_count, last_sleep })
+    let avg_importance: f64 = raw_conn.query_row("SELECT COALESCE(AVG(importance), 0) FROM memories", [], |r| r.get(0))?;
+    let avg_confidence: f64 =
+        cons_conn.query_row("SELECT COALESCE(AVG(confidence), 0) FROM consolidated", [], |r| r.get(0))?;
+    let consolidation_ratio =
+        if raw_count > 0 { (raw_count - unconsolidated_count) as f64 / raw_count as f64 } else { 0.0 };
+    Ok(Stats {
+        raw_count,
+        unconsolidated_count,
+        consolidated_count,
+        skill_count,
+        entity_count,
+        relationship_count,
+        last_sleep,
+        avg_importance,
+        avg_confidence,
+        consolidation_ratio,
+    })
 }
 
 // --- Helpers ---
 
-fn build_fts_query(query: &str) -> String {
-    query
-        .split_whitespace()
-        .map(|word| {
-            let clean: String = word.chars().filter(|c| c.is_alphanumeric() || *c == '_' || *c == '-').collect();
-            if clean.is_empty() { String::new() } else { format!("{}*", clean) }
-        })
-        .filter(|s| !s.is_empty())
-        .collect::<Vec<_>>()
-        .join(" OR ")
+/// Map a row selected as `id, content, type, created_at, accessed_at, access_count,
+/// consolidated, importance, session_id, entity_ids, tags, refs, sensitivity, no_llm` into a Memory.
+fn memory_from_row(row: &rusqlite::Row) -> rusqlite::Result<Memory> {
+    let entity_ids_str: String = row.get(9)?;
+    let entity_ids: Vec<i64> = serde_json::from_str(&entity_ids_str).unwrap_or_default();
+    let tags_str: String = row.get(10)?;
+    let tags: Vec<String> = serde_json::from_str(&tags_str).unwrap_or_default();
+    let refs_str: String = row.get(11)?;
+    let refs: Vec<String> = serde_json::from_str(&refs_str).unwrap_or_default();
+    Ok(Memory {
+        id: row.get(0)?,
+        content: compress::text(row, 1)?,
+        r#type: row.get(2)?,
+        created_at: row.get(3)?,
+        accessed_at: row.get(4)?,
+        access_count: row.get(5)?,
+        consolidated: row.get::<_, i64>(6)? != 0,
+        importance: row.get(7)?,
+        session_id: row.get(8)?,
+        entity_ids,
+        tags,
+        refs,
+        sensitivity: Sensitivity::from_db(&row.get::<_, String>(12)?),
+        no_llm: row.get::<_, i64>(13)? != 0,
+    })
+}
+
+/// Map a row selected as `id, content, type, source_ids, confidence, created_at,
+/// updated_at, access_count, refs, sensitivity, no_llm` into a ConsolidatedMemory.
+fn consolidated_from_row(row: &rusqlite::Row) -> rusqlite::Result<ConsolidatedMemory> {
+    let source_ids_str: String = row.get(3)?;
+    let source_ids: Vec<i64> = serde_json::from_str(&source_ids_str).unwrap_or_default();
+    let refs_str: String = row.get(8)?;
+    let refs: Vec<String> = serde_json::from_str(&refs_str).unwrap_or_default();
+    Ok(ConsolidatedMemory {
+        id: row.get(0)?,
+        content: row.get(1)?,
+        r#type: row.get(2)?,
+        source_ids,
+        confidence: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        access_count: row.get(7)?,
+        refs,
+        sensitivity: Sensitivity::from_db(&row.get::<_, String>(9)?),
+        no_llm: row.get::<_, i64>(10)? != 0,
+    })
+}
+
+/// Populate the refs column for rows that predate it.
+fn backfill_refs(conn: &Connection, table: &str) -> Result<()> {
+    let rows: Vec<(i64, String)> = {
+        let mut stmt = conn.prepare(&format!("SELECT id, content FROM {}", table))?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .filter_map(|r| r.ok())
+            .collect()
+    };
+    for (id, content) in rows {
+        let found = refs::extract_refs(&content);
+        if !found.is_empty() {
+            conn.execute(
+                &format!("UPDATE {} SET refs = ?1 WHERE id = ?2", table),
+                params![serde_json::to_string(&found)?, id],
+            )?;
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::db;
+
+/// One problem found by `cortex doctor`.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    /// `integrity`, `fts`, `source_ids`, or `versions`
+    pub check: &'static str,
+    pub problem: String,
+    pub fixed: bool,
+}
+
+/// Check a store's databases and, with `fix`, repair what can be repaired: FTS indexes out of
+/// sync with their tables (from deletes that bypassed the triggers) are rebuilt, and
+/// consolidated `source_ids` pointing at memories that no longer exist are compacted.
+/// SQLite-level corruption is only reported.
+pub fn check(raw: &Connection, cons: &Connection, fix: bool) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    for (name, conn) in [("raw.db", raw), ("consolidated.db", cons)] {
+        let result: String = conn.query_row("PRAGMA quick_check", [], |r| r.get(0))?;
+        if result != "ok" {
+            findings.push(Finding {
+                check: "integrity",
+                problem: format!("{} failed quick_check: {} (restore from a backup)", name, result),
+                fixed: false,
+            });
+        }
+    }
+    for conn in [raw, cons] {
+        for table in db::fts_tables(conn)? {
+            if db::fts_in_sync(conn, table)? {
+                continue;
+            }
+            if fix {
+                db::rebuild_fts(conn, table)?;
+            }
+            findings.push(Finding { check: "fts", problem: format!("{} is out of sync with its table", table), fixed: fix });
+        }
+    }
+    findings.extend(check_source_ids(raw, cons, fix)?);
+    findings.extend(check_versions(cons)?);
+    Ok(findings)
+}
+
+/// Several cortex versions using one store recently, e.g. machines sharing it through git or a
+/// synced folder at different versions. Only upgrading them fixes it.
+fn check_versions(cons: &Connection) -> Result<Option<Finding>> {
+    let versions = db::binary_versions(cons)?;
+    let recent = db::recent_versions(&versions);
+    if recent.len() < 2 {
+        return Ok(None);
+    }
+    let used: Vec<String> = recent.iter().map(|(v, day)| format!("{} (last {})", v, day)).collect();
+    Ok(Some(Finding {
+        check: "versions",
+        problem: format!(
+            "mixed cortex versions share this store: {}; upgrade every machine to {}",
+            used.join(", "),
+            recent[recent.len() - 1].0
+        ),
+        fixed: false,
+    }))
+}
+
+/// Consolidated memories cite raw memory IDs (live or archived); dream insights cite other
+/// consolidated memories.
+fn check_source_ids(raw: &Connection, cons: &Connection, fix: bool) -> Result<Vec<Finding>> {
+    let raw_ids = ids(raw, "SELECT id FROM memories UNION SELECT id FROM archived_memories")?;
+    let cons_ids = ids(cons, "SELECT id FROM consolidated")?;
+    let mut findings = Vec::new();
+    let tx = cons.unchecked_transaction()?;
+    for c in db::get_all_consolidated(&tx)? {
+        let known = if c.r#type == "insight" { &cons_ids } else { &raw_ids };
+        let valid: Vec<i64> = c.source_ids.iter().copied().filter(|id| known.contains(id)).collect();
+        if valid.len() == c.source_ids.len() {
+            continue;
+        }
+        let dangling: Vec<String> =
+            c.source_ids.iter().filter(|id| !known.contains(id)).map(|id| format!("#{}", id)).collect();
+        if fix {
+            tx.execute(
+                "UPDATE consolidated SET source_ids = ?1 WHERE id = ?2",
+                params![serde_json::to_string(&valid)?, c.id],
+            )?;
+        }
+        findings.push(Finding {
+            check: "source_ids",
+            problem: format!("consolidated #{} cites missing {}", c.id, dangling.join(", ")),
+            fixed: fix,
+        });
+    }
+    tx.commit()?;
+    Ok(findings)
+}
+
+fn ids(conn: &Connection, sql: &str) -> Result<HashSet<i64>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map([], |r| r.get(0))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn repairs_desynced_fts_and_dangling_sources() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let a = db::save_memory(&raw, "Ghost memory about tokio", "observation", "s1").unwrap();
+        let b = db::save_memory(&raw, "Kept memory", "observation", "s1").unwrap();
+        let c = db::insert_consolidated(&cons, "Summary", "pattern", &[a, b], 0.7).unwrap();
+        assert!(check(&raw, &cons, false).unwrap().is_empty());
+        let i = db::insert_consolidated(&cons, "Insight", "insight", &[c, 42], 0.6).unwrap();
+
+        // A delete that bypasses the sync trigger
+        raw.execute_batch(&format!("DROP TRIGGER memories_ad; DELETE FROM memories WHERE id = {a};")).unwrap();
+
+        let found = check(&raw, &cons, false).unwrap();
+        let checks: Vec<&str> = found.iter().map(|f| f.check).collect();
+        assert_eq!(checks, vec!["fts", "source_ids", "source_ids"], "{:?}", found);
+        assert!(found.iter().all(|f| !f.fixed));
+
+        let fixed = check(&raw, &cons, true).unwrap();
+        assert_eq!(fixed.len(), 3);
+        assert!(fixed.iter().all(|f| f.fixed));
+        assert!(check(&raw, &cons, false).unwrap().is_empty());
+        assert_eq!(db::get_consolidated(&cons, c).unwrap().unwrap().source_ids, vec![b]);
+        assert_eq!(db::get_consolidated(&cons, i).unwrap().unwrap().source_ids, vec![c]);
+        assert!(db::recall_memories(&raw, "ghost", 5, &crate::config::Config::default().recall).unwrap().is_empty());
+    }
+
+    #[test]
+    fn flags_mixed_and_newer_versions() {
+        let dir = std::env::temp_dir().join(format!("cortex-doctor-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("consolidated.db");
+        let cons = db::open_consolidated_db(&path).unwrap();
+        assert!(check_versions(&cons).unwrap().is_none());
+        let mut versions = db::binary_versions(&cons).unwrap();
+        versions.insert("0.1.0".into(), chrono::Utc::now().format("%Y-%m-%d").to_string());
+        versions.insert("0.0.9".into(), "2020-01-01".into());
+        db::set_meta(&cons, "binary_versions", &serde_json::to_string(&versions).unwrap()).unwrap();
+        let finding = check_versions(&cons).unwrap().unwrap();
+        assert!(finding.problem.contains("0.1.0") && !finding.problem.contains("0.0.9"), "{}", finding.problem);
+
+        db::set_meta(&cons, "schema_version", &(db::SCHEMA_VERSION + 1).to_string()).unwrap();
+        drop(cons);
+        assert!(db::open_consolidated_db(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
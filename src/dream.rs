@@ -1,11 +1,31 @@
 use anyhow::Result;
 use rusqlite::Connection;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
 
-use crate::config::Config;
+use crate::antipattern;
+use crate::audit::{self, Manifest};
+use crate::batch;
+use crate::config::{self, Config, DreamConfig};
 use crate::db;
 use crate::llm;
-use crate::models::ConsolidationResult;
+use crate::lock::StoreLock;
+use crate::models::{ConsolidatedMemory, ConsolidationResult};
+use crate::sensitivity::Sensitivity;
+use crate::quality;
+use crate::similar::{jaccard, word_set};
 use crate::skills;
+use crate::sleep;
+use crate::snapshot;
+
+/// Days a skill must go unread and unchanged before a dream may remove it.
+const DEAD_SKILL_DAYS: i64 = 30;
+
+/// Consolidated memories a cross-project dream samples from each store.
+const SAMPLE_PER_PROJECT: usize = 40;
+
+/// Word-set Jaccard similarity at or above which a new insight refines an earlier one.
+const INSIGHT_SIMILARITY: f64 = 0.6;
 
 /// Deep reflection: cross-session pattern mining and meta-learning.
 /// Runs 2-3 LLM calls for comprehensive analysis.
@@ -15,9 +35,221 @@ pub async fn dream(
     config: &Config,
     cortex_dir: &std::path::Path,
 ) -> Result<DreamResult> {
-    let consolidated = db::get_all_consolidated(cons_conn)?;
+    let _lock = StoreLock::acquire(cortex_dir, "dream")?;
+    snapshot::before(raw_conn, cons_conn, cortex_dir, &config.snapshots, "dream")?;
+    let Some((pattern_prompt, consolidated)) = pattern_prompt(raw_conn, cons_conn)? else {
+        let skills_condensed = condense_oversized_skills(cons_conn, config).await?;
+        if skills_condensed > 0 {
+            skills::generate_skill_files(cons_conn, &cortex_dir.join("skills"))?;
+        }
+        return Ok(DreamResult { skills_condensed, ..Default::default() });
+    };
+
+    let memories = audit::consolidated_keys(consolidated.iter().map(|m| m.id));
+    let (llm_config, tool) = (config.llm.for_dream(), sleep::consolidation_tool());
+    let call = llm::call_structured(&pattern_prompt, PATTERN_SYSTEM, config, &llm_config, &tool);
+    let response = llm::audited(Manifest::new(raw_conn, "dream", memories), call).await?;
+
+    let mut result: ConsolidationResult = serde_json::from_value(response).unwrap_or_default();
+    checked_prune(&mut result, &consolidated, config)?;
+    let counts = apply_patterns(raw_conn, cons_conn, &result, &consolidated)?;
+    let (decayed, merged) = apply_prune(cons_conn, &result, &consolidated)?;
+    let skills_removed = remove_dead_skills(cons_conn, &result.skill_removals, cortex_dir)?;
+
+    let skills_condensed = condense_oversized_skills(cons_conn, config).await?;
+    finish_dream(cons_conn, cortex_dir)?;
+    Ok(DreamResult {
+        insights: counts.insights,
+        insights_refined: counts.refined,
+        skills_updated: counts.skills_updated,
+        skills_condensed,
+        skills_removed,
+        decayed,
+        merged,
+    })
+}
+
+/// Dream through the Message Batches API: submit the pattern-mining prompt and return the batch
+/// ID, or `None` if there is nothing to dream over. `cortex sleep --collect` applies the result;
+/// oversized skills are condensed by the next regular dream.
+pub async fn submit_batch(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    config: &Config,
+    cortex_dir: &std::path::Path,
+) -> Result<Option<String>> {
+    let _lock = StoreLock::acquire(cortex_dir, "dream")?;
+    if !batch::pending(raw_conn, Some(batch::DREAM))?.is_empty() {
+        anyhow::bail!("A dream batch is already pending; run `cortex sleep --collect` first.");
+    }
+    let Some((prompt, consolidated)) = pattern_prompt(raw_conn, cons_conn)? else {
+        return Ok(None);
+    };
+    let (llm_config, tool) = (config.llm.for_dream(), sleep::consolidation_tool());
+    let request = batch::Request {
+        purpose: batch::DREAM,
+        prompt: &prompt,
+        system: PATTERN_SYSTEM,
+        llm: &llm_config,
+        tool: &tool,
+        memories: vec![],
+        consolidated: consolidated.iter().map(|m| m.id).collect(),
+    };
+    Ok(Some(batch::submit(raw_conn, config, request).await?))
+}
+
+/// Apply a collected dream batch. Insights, decays, and merges only cite memories that still
+/// exist. The caller holds the store lock.
+pub(crate) fn apply_batch(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    config: &Config,
+    cortex_dir: &std::path::Path,
+    pending: &batch::Pending,
+    response: serde_json::Value,
+) -> Result<DreamResult> {
+    let consolidated: Vec<_> = db::get_all_consolidated(cons_conn)?
+        .into_iter()
+        .filter(|m| !m.no_llm && pending.consolidated.contains(&m.id))
+        .collect();
+    let mut result: ConsolidationResult = serde_json::from_value(response).unwrap_or_default();
+    result.consolidations.retain(|c| c.source_ids.iter().all(|id| consolidated.iter().any(|m| m.id == *id)));
+    checked_prune(&mut result, &consolidated, config)?;
+    let counts = apply_patterns(raw_conn, cons_conn, &result, &consolidated)?;
+    let (decayed, merged) = apply_prune(cons_conn, &result, &consolidated)?;
+    let skills_removed = remove_dead_skills(cons_conn, &result.skill_removals, cortex_dir)?;
+    finish_dream(cons_conn, cortex_dir)?;
+    Ok(DreamResult {
+        insights: counts.insights,
+        insights_refined: counts.refined,
+        skills_updated: counts.skills_updated,
+        skills_condensed: 0,
+        skills_removed,
+        decayed,
+        merged,
+    })
+}
+
+/// What a cross-project dream did.
+pub struct CrossProjectResult {
+    /// Projects that contributed memories to the prompt
+    pub projects: usize,
+    pub sampled: usize,
+    pub insights: usize,
+    pub insights_refined: usize,
+}
+
+/// `cortex dream --all-projects`: sample each registered store's consolidated memories (most
+/// recalled, then most confident), mine them for patterns that recur across projects, and
+/// record those as insights in the global store, citing the projects they draw on. Project
+/// stores are only read. Memories above a project's `[sensitivity] global_max` and `--no-llm`
+/// ones are never sampled, and an insight must draw on at least two projects.
+pub async fn dream_all_projects(
+    stores: &[PathBuf],
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    config: &Config,
+    cortex_dir: &Path,
+) -> Result<CrossProjectResult> {
+    let mut samples: Vec<(String, ConsolidatedMemory)> = Vec::new();
+    let mut projects = 0;
+    for dir in stores {
+        let project = dir.parent().and_then(|p| p.file_name()).unwrap_or(dir.as_os_str()).to_string_lossy().to_string();
+        let sample = db::open_consolidated_db(&dir.join("consolidated.db")).and_then(|cons| {
+            let global_max = config::load_config(dir)?.sensitivity.global_max;
+            let mut memories: Vec<_> = db::get_all_consolidated(&cons)?
+                .into_iter()
+                .filter(|m| !m.no_llm && m.sensitivity <= global_max)
+                .collect();
+            memories.sort_by(|a, b| b.access_count.cmp(&a.access_count).then(b.confidence.total_cmp(&a.confidence)));
+            memories.truncate(SAMPLE_PER_PROJECT);
+            Ok(memories)
+        });
+        match sample {
+            Ok(memories) if !memories.is_empty() => {
+                projects += 1;
+                samples.extend(memories.into_iter().map(|m| (project.clone(), m)));
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Warning: skipped {}: {}", dir.display(), e),
+        }
+    }
+    if projects < 2 {
+        anyhow::bail!("A cross-project dream needs memories in at least two registered projects (see `cortex projects`).");
+    }
+
+    let _lock = StoreLock::acquire(cortex_dir, "dream")?;
+    snapshot::before(raw_conn, cons_conn, cortex_dir, &config.snapshots, "dream")?;
+    let global = db::get_all_consolidated(cons_conn)?;
+    let prompt = cross_project_prompt(&samples, &global)?;
+    // Sampled IDs belong to other stores, so the audit entry lists no memories
+    let (llm_config, tool) = (config.llm.for_dream(), sleep::consolidation_tool());
+    let call = llm::call_structured(&prompt, PATTERN_SYSTEM, config, &llm_config, &tool);
+    let response = llm::audited(Manifest::new(raw_conn, "dream", vec![]), call).await?;
+    let result: ConsolidationResult = serde_json::from_value(response).unwrap_or_default();
+
+    let mut known = known_insights(&global);
+    let (mut insights, mut insights_refined) = (0, 0);
+    for c in &result.consolidations {
+        // Prompt IDs are 1-based positions in `samples`
+        let cited: Vec<&(String, ConsolidatedMemory)> =
+            c.source_ids.iter().filter_map(|i| usize::try_from(*i).ok()?.checked_sub(1).and_then(|i| samples.get(i))).collect();
+        let names: BTreeSet<&str> = cited.iter().map(|(p, _)| p.as_str()).collect();
+        if names.len() < 2 {
+            continue;
+        }
+        let content = format!("{} (projects: {})", c.content.trim(), names.into_iter().collect::<Vec<_>>().join(", "));
+        let level = cited.iter().map(|(_, m)| m.sensitivity).max().unwrap_or_default();
+        if record_insight(cons_conn, &mut known, &content, &[], c.confidence, level)? {
+            insights_refined += 1;
+        } else {
+            insights += 1;
+        }
+    }
+    finish_dream(cons_conn, cortex_dir)?;
+    Ok(CrossProjectResult { projects, sampled: samples.len(), insights, insights_refined })
+}
+
+/// The cross-project prompt: sampled memories numbered from 1 with their project, and the
+/// global store's insights so far.
+fn cross_project_prompt(samples: &[(String, ConsolidatedMemory)], global: &[ConsolidatedMemory]) -> Result<String> {
+    let memories_json = serde_json::to_string_pretty(
+        &samples
+            .iter()
+            .enumerate()
+            .map(|(i, (project, m))| serde_json::json!({
+                "id": i + 1, "project": project, "type": m.r#type, "content": m.content, "confidence": m.confidence
+            }))
+            .collect::<Vec<_>>(),
+    )?;
+    let insights: Vec<String> =
+        global.iter().filter(|m| m.r#type == "insight").map(|m| format!("- {}", m.content)).collect();
+    let insights_list = if insights.is_empty() { "(none)".to_string() } else { insights.join("\n") };
+    Ok(format!(
+        r#"Find patterns that recur across these projects: tool preferences, architecture choices, conventions, and mistakes that show up in more than one project.
+
+Memories:
+{memories_json}
+
+Insights already in the global store (don't restate these; to refine one, record it again in close wording with what's new):
+{insights_list}
+
+Record each cross-project pattern in "consolidations":
+{{"content": "the pattern, stated so it applies to any project", "type": "insight", "source_ids": [ids of the memories it draws on, from at least two projects], "confidence": 0.0-1.0}}
+
+Leave out anything that holds for only one project, and don't name hosts, credentials, or people."#
+    ))
+}
+
+const PATTERN_SYSTEM: &str = "You are a deep reflection system performing meta-analysis on learned knowledge and a knowledge graph. Record the result with the record_consolidation tool.";
+
+/// The pattern-mining prompt and the consolidated memories in it, or `None` when there are
+/// none to send.
+fn pattern_prompt(raw_conn: &Connection, cons_conn: &Connection) -> Result<Option<(String, Vec<ConsolidatedMemory>)>> {
+    // Memories built from --no-llm saves stay out of the prompt
+    let consolidated: Vec<_> = db::get_all_consolidated(cons_conn)?.into_iter().filter(|m| !m.no_llm).collect();
     if consolidated.is_empty() {
-        return Ok(DreamResult { insights: 0, skills_updated: 0 });
+        return Ok(None);
     }
 
     let cons_json = serde_json::to_string_pretty(
@@ -56,6 +288,20 @@ pub async fn dream(
             .collect::<Vec<_>>(),
     )?;
 
+    let mut skills = db::get_all_skills(cons_conn)?;
+    skills.sort_by(|a, b| b.use_count.cmp(&a.use_count).then_with(|| a.name.cmp(&b.name)));
+    let skill_lines: Vec<String> = skills
+        .iter()
+        .map(|s| match &s.last_used_at {
+            Some(at) => format!("- {} (read {} times, last {})", s.name, s.use_count, at),
+            None => format!("- {} (never read)", s.name),
+        })
+        .collect();
+    let skills_list = if skill_lines.is_empty() { "(none)".to_string() } else { skill_lines.join("\n") };
+    let insights: Vec<String> =
+        consolidated.iter().filter(|m| m.r#type == "insight").map(|m| format!("- #{}: {}", m.id, m.content)).collect();
+    let insights_list = if insights.is_empty() { "(none)".to_string() } else { insights.join("\n") };
+
     // Pass 1: Pattern mining with graph awareness
     let pattern_prompt = format!(
         r#"Analyze these consolidated memories and knowledge graph for cross-cutting patterns and insights.
@@ -69,6 +315,12 @@ Entities:
 Relationships:
 {relationships_json}
 
+Existing skills, most read first (reuse a name to extend that skill instead of creating a near-duplicate; when memories add to a skill, refresh the most read ones first):
+{skills_list}
+
+Insights from earlier dreams (don't restate these; to refine one, record it again in close wording with what's new):
+{insights_list}
+
 Identify:
 1. Recurring themes across multiple memories
 2. Higher-order patterns (patterns of patterns)
@@ -76,14 +328,16 @@ Identify:
 4. Missing relationships (inferred from patterns)
 5. Contradictory relationships
 6. Potential blind spots or areas lacking coverage
+7. Memories that say the same thing (merge them into one) or that are superseded or no longer accurate (decay them). Never merge or decay memories of type "antipattern".
+8. Skills that are never read and that the memories no longer support (remove them; only skills unread and unchanged for {DEAD_SKILL_DAYS} days are removed)
 
-Output JSON:
+Record the result with these fields:
 {{
   "consolidations": [
     {{"content": "description of insight", "type": "insight", "source_ids": [ids of related memories], "confidence": 0.0-1.0}}
   ],
   "skill_updates": [
-    {{"name": "skill-name", "content": "comprehensive markdown skill file content"}}
+    {{"name": "skill-name", "category": "testing|arch|tooling|workflow|style", "content": "comprehensive markdown skill file content"}}
   ],
   "new_entities": [
     {{"name": "EntityName", "type": "concept|pattern|technology", "description": "Short description"}}
@@ -93,19 +347,33 @@ Output JSON:
   ],
   "entity_updates": [
     {{"name": "entity_name", "description": "updated description", "confidence": 0.0-1.0}}
-  ]
-}}
-
-Output ONLY valid JSON."#
+  ],
+  "merges": [
+    {{"content": "one memory replacing them", "type": "pattern|decision|...", "source_ids": [ids of the 2+ memories it replaces], "confidence": 0.0-1.0}}
+  ],
+  "decayed": [ids of memories that are superseded or no longer accurate],
+  "skill_removals": ["names of dead skills"]
+}}"#
     );
+    Ok(Some((pattern_prompt, consolidated)))
+}
 
-    let system = "You are a deep reflection system performing meta-analysis on learned knowledge and a knowledge graph. Output ONLY valid JSON.";
-    let response = llm::call_anthropic(&pattern_prompt, system, config).await?;
-
-    let json_str = extract_json(&response);
-    let result: ConsolidationResult = serde_json::from_str(json_str)
-        .unwrap_or_default();
+/// What `apply_patterns` changed.
+struct PatternCounts {
+    insights: usize,
+    refined: usize,
+    skills_updated: usize,
+}
 
+/// Apply a pattern-mining result: graph changes, insights, and skill updates. An insight close
+/// to an earlier one (or to one from the same result) rewrites and reinforces it instead of
+/// adding a near-duplicate, so repeated dreams refine their meta-knowledge.
+fn apply_patterns(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    result: &ConsolidationResult,
+    consolidated: &[ConsolidatedMemory],
+) -> Result<PatternCounts> {
     // Apply new entities from dream
     for entity in &result.new_entities {
         db::upsert_entity(raw_conn, &entity.name, &entity.r#type, entity.description.as_deref())?;
@@ -125,52 +393,385 @@ Output ONLY valid JSON."#
         db::update_entity(raw_conn, &update.name, update.description.as_deref(), update.confidence)?;
     }
 
-    // Apply insights as new consolidated memories
-    let mut insights = 0;
+    // Apply insights as new consolidated memories, or as refinements of a near-identical one
+    let mut known = known_insights(consolidated);
+    let (mut insights, mut refined) = (0, 0);
     for c in &result.consolidations {
-        db::insert_consolidated(cons_conn, &c.content, "insight", &c.source_ids, c.confidence)?;
-        insights += 1;
+        let level = consolidated.iter().filter(|m| c.source_ids.contains(&m.id)).map(|m| m.sensitivity).max();
+        if record_insight(cons_conn, &mut known, &c.content, &c.source_ids, c.confidence, level.unwrap_or_default())? {
+            refined += 1;
+        } else {
+            insights += 1;
+        }
     }
 
     // Apply skill updates
     let mut skills_updated = 0;
     for su in &result.skill_updates {
-        db::upsert_skill(cons_conn, &su.name, &su.content, &[])?;
-        skills_updated += 1;
+        if skills::store_skill(cons_conn, &su.name, su.category.as_deref(), &su.content, &[])? {
+            skills_updated += 1;
+        }
     }
 
-    // Regenerate all skill files
-    skills::generate_skill_files(cons_conn, &cortex_dir.join("skills"))?;
+    Ok(PatternCounts { insights, refined, skills_updated })
+}
+
+/// A store's insights, with their words, for `record_insight`.
+fn known_insights(consolidated: &[ConsolidatedMemory]) -> Vec<(i64, HashSet<String>, Sensitivity)> {
+    consolidated.iter().filter(|m| m.r#type == "insight").map(|m| (m.id, word_set(&m.content), m.sensitivity)).collect()
+}
+
+/// Store an insight, or rewrite and reinforce the one in `known` whose wording is within
+/// `INSIGHT_SIMILARITY` of it. Returns whether it refined an existing one.
+fn record_insight(
+    cons_conn: &Connection,
+    known: &mut Vec<(i64, HashSet<String>, Sensitivity)>,
+    content: &str,
+    source_ids: &[i64],
+    confidence: f64,
+    level: Sensitivity,
+) -> Result<bool> {
+    let words = word_set(content);
+    if let Some((id, known_words, known_level)) = known.iter_mut().find(|(_, w, _)| jaccard(w, &words) >= INSIGHT_SIMILARITY)
+    {
+        db::update_consolidated(cons_conn, *id, content)?;
+        db::reinforce_consolidated(cons_conn, *id, source_ids)?;
+        *known_level = (*known_level).max(level);
+        db::set_consolidated_sensitivity(cons_conn, &[*id], *known_level)?;
+        *known_words = words;
+        return Ok(true);
+    }
+    let id = db::insert_consolidated(cons_conn, content, "insight", source_ids, confidence)?;
+    db::set_consolidated_sensitivity(cons_conn, &[id], level)?;
+    known.push((id, words, level));
+    Ok(false)
+}
+
+/// Hold the dream's decays and merges to sleep's gates: `[quality]` checks against the memories
+/// the dream was shown (failing the dream under `[quality] strict`), and antipatterns are never
+/// touched.
+fn checked_prune(result: &mut ConsolidationResult, consolidated: &[ConsolidatedMemory], config: &Config) -> Result<()> {
+    let shown: HashSet<i64> = consolidated.iter().filter(|m| m.r#type != antipattern::TYPE).map(|m| m.id).collect();
+    let prune = ConsolidationResult {
+        decayed: std::mem::take(&mut result.decayed),
+        merges: std::mem::take(&mut result.merges),
+        ..Default::default()
+    };
+    let (mut prune, issues) = quality::validate(prune, &HashSet::new(), &shown, &config.quality);
+    if !issues.is_empty() {
+        if config.quality.strict {
+            anyhow::bail!("{} dream prune item(s) failed quality checks: {}", issues.len(), issues.join("; "));
+        }
+        eprintln!("Warning: skipped {} dream prune item(s) that failed quality checks:", issues.len());
+        for issue in &issues {
+            eprintln!("  - {}", issue);
+        }
+    }
+    // With `[quality] enabled = false` nothing above checked the IDs
+    prune.decayed.retain(|id| shown.contains(id));
+    prune.merges.retain(|m| m.source_ids.len() >= 2 && m.source_ids.iter().all(|id| shown.contains(id)));
+    result.decayed = prune.decayed;
+    result.merges = prune.merges;
+    Ok(())
+}
+
+/// Apply a dream's checked merges and decays in one transaction. A merged memory keeps the
+/// union of its originals' sources and refs and their highest sensitivity; a merge citing a
+/// memory an earlier merge already replaced is skipped, as is decaying one. Returns the number
+/// decayed and merged.
+fn apply_prune(
+    cons_conn: &Connection,
+    result: &ConsolidationResult,
+    consolidated: &[ConsolidatedMemory],
+) -> Result<(usize, usize)> {
+    let tx = cons_conn.unchecked_transaction()?;
+    let mut replaced: HashSet<i64> = HashSet::new();
+    let mut merged = 0;
+    for m in &result.merges {
+        let originals: Vec<&ConsolidatedMemory> = consolidated.iter().filter(|c| m.source_ids.contains(&c.id)).collect();
+        if originals.len() < 2 || originals.iter().any(|c| replaced.contains(&c.id)) {
+            continue;
+        }
+        let sources: Vec<i64> = originals.iter().flat_map(|c| c.source_ids.iter().copied()).collect::<BTreeSet<_>>().into_iter().collect();
+        let refs: Vec<String> = originals.iter().flat_map(|c| c.refs.iter().cloned()).collect::<BTreeSet<_>>().into_iter().collect();
+        let id = db::insert_consolidated(&tx, &m.content, &m.r#type, &sources, m.confidence)?;
+        db::add_consolidated_refs(&tx, id, &refs)?;
+        let level = originals.iter().map(|c| c.sensitivity).max().unwrap_or_default();
+        db::set_consolidated_sensitivity(&tx, &[id], level)?;
+        let ids: Vec<i64> = originals.iter().map(|c| c.id).collect();
+        db::remove_consolidated(&tx, &ids)?;
+        replaced.extend(ids);
+        merged += 1;
+    }
+    let decayed: Vec<i64> = result.decayed.iter().copied().filter(|id| replaced.insert(*id)).collect();
+    db::remove_consolidated(&tx, &decayed)?;
+    tx.commit()?;
+    Ok((decayed.len(), merged))
+}
+
+/// Remove the skills a dream named as dead, keeping any read or changed in the last
+/// `DEAD_SKILL_DAYS` (so one this dream updated stays). Returns how many went.
+fn remove_dead_skills(cons_conn: &Connection, names: &[String], cortex_dir: &Path) -> Result<usize> {
+    if names.is_empty() {
+        return Ok(0);
+    }
+    // Stored timestamps are SQLite's `datetime('now')`
+    let cutoff = (chrono::Utc::now() - chrono::Duration::days(DEAD_SKILL_DAYS)).format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut removed = 0;
+    for skill in db::get_all_skills(cons_conn)? {
+        let last_touched = skill.last_used_at.as_deref().map_or(skill.updated_at.as_str(), |used| used.max(skill.updated_at.as_str()));
+        if names.contains(&skill.name)
+            && last_touched < cutoff.as_str()
+            && skills::remove(cons_conn, &cortex_dir.join("skills"), &skill.name)?
+        {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Why a store is due for an automatic dream under `policy`, or `None`. Due means enough
+/// memories, `min_days` since the last dream, and churn since then: `min_changes` consolidated
+/// memories added or updated, `min_contradictions` flagged at save time, or any change at all
+/// once `max_days` have passed.
+pub fn due(raw_conn: &Connection, cons_conn: &Connection, policy: &DreamConfig) -> Result<Option<String>> {
+    let count = db::get_consolidated_count(cons_conn)?;
+    if !policy.auto || count < policy.min_entries {
+        return Ok(None);
+    }
+    let Some(last) = db::get_meta(cons_conn, "last_dream")?.and_then(|l| chrono::DateTime::parse_from_rfc3339(&l).ok())
+    else {
+        return Ok(Some(format!("{} entries, never dreamed", count)));
+    };
+    let last = last.with_timezone(&chrono::Utc);
+    let days = chrono::Utc::now().signed_duration_since(last).num_days();
+    if days < policy.min_days {
+        return Ok(None);
+    }
+    // Stored timestamps are SQLite's `datetime('now')`
+    let since = last.format("%Y-%m-%d %H:%M:%S").to_string();
+    let changed: i64 =
+        cons_conn.query_row("SELECT COUNT(*) FROM consolidated WHERE updated_at >= ?1", [&since], |r| r.get(0))?;
+    let contradictions: i64 =
+        raw_conn.query_row("SELECT COUNT(*) FROM contradictions WHERE created_at >= ?1", [&since], |r| r.get(0))?;
+    Ok(if changed >= policy.min_changes {
+        Some(format!("{} memories changed since the last dream", changed))
+    } else if contradictions >= policy.min_contradictions {
+        Some(format!("{} contradictions since the last dream", contradictions))
+    } else if policy.max_days > 0 && days >= policy.max_days && changed > 0 {
+        Some(format!("{} days since the last dream", days))
+    } else {
+        None
+    })
+}
 
-    // Record dream time
+/// Regenerate skill files and record the dream time.
+fn finish_dream(cons_conn: &Connection, cortex_dir: &std::path::Path) -> Result<()> {
+    skills::generate_skill_files(cons_conn, &cortex_dir.join("skills"))?;
     db::set_meta(cons_conn, "last_dream", &chrono::Utc::now().to_rfc3339())?;
     db::set_meta(cons_conn, "last_sleep", &chrono::Utc::now().to_rfc3339())?;
-
-    Ok(DreamResult { insights, skills_updated })
+    db::log_activity(cons_conn, "dream")?;
+    Ok(())
 }
 
+#[derive(Default)]
 pub struct DreamResult {
     pub insights: usize,
+    /// Earlier insights rewritten by a near-identical new one
+    pub insights_refined: usize,
     pub skills_updated: usize,
+    /// Skills rewritten to fit `[skills] max_tokens`
+    pub skills_condensed: usize,
+    /// Dead skills removed: unread and unchanged for `DEAD_SKILL_DAYS`
+    pub skills_removed: usize,
+    /// Redundant or superseded memories removed
+    pub decayed: usize,
+    /// Memories written to replace groups that said the same thing
+    pub merged: usize,
 }
 
-fn extract_json(text: &str) -> &str {
-    if let Some(start) = text.find("```json") {
-        let content = &text[start + 7..];
-        if let Some(end) = content.rfind("```") {
-            return content[..end].trim();
+/// Ask the LLM to rewrite each skill over `[skills] max_tokens` more concisely. A skill the
+/// LLM fails on is left as-is with a warning; one that comes back still too long is truncated.
+async fn condense_oversized_skills(cons_conn: &Connection, config: &Config) -> Result<usize> {
+    let max_tokens = config.skills.max_tokens;
+    let tool = llm::OutputTool {
+        name: "record_skill",
+        description: "Record the condensed skill file.",
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": { "content": { "type": "string", "description": "Condensed markdown skill content" } },
+            "required": ["content"]
+        }),
+    };
+    let system = "You condense skill files for coding agents. Keep the most actionable guidance and the markdown structure; drop repetition and examples that restate a rule.";
+
+    let llm_config = config.llm.for_dream();
+    let mut condensed = 0;
+    for skill in db::get_all_skills(cons_conn)? {
+        let tokens = skills::estimate_tokens(&skill.content);
+        if tokens <= max_tokens {
+            continue;
         }
+        let prompt = format!(
+            "This skill file is about {tokens} tokens; rewrite it to under {max_tokens} tokens (about {} words).\n\nSkill `{}`:\n\n{}",
+            max_tokens * 3 / 4,
+            skill.name,
+            skill.content
+        );
+        let call = llm::call_structured(&prompt, system, config, &llm_config, &tool);
+        let content = match llm::audited(Manifest::new(cons_conn, "skills", vec![]), call).await {
+            Ok(v) => match v.get("content").and_then(|c| c.as_str()) {
+                Some(c) if !c.trim().is_empty() => c.to_string(),
+                _ => {
+                    eprintln!("Warning: condensing skill `{}` returned no content; left as-is.", skill.name);
+                    continue;
+                }
+            },
+            Err(e) => {
+                eprintln!("Warning: could not condense skill `{}`: {}", skill.name, e);
+                continue;
+            }
+        };
+        let content = skills::truncate_to_tokens(&content, max_tokens);
+        db::upsert_skill(cons_conn, &skill.name, None, &content, &skill.source_ids)?;
+        condensed += 1;
     }
-    if let Some(start) = text.find("```") {
-        let content = &text[start + 3..];
-        if let Some(end) = content.rfind("```") {
-            return content[..end].trim();
+    Ok(condensed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn dreams_on_churn_not_just_time() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let policy = DreamConfig { min_changes: 3, min_contradictions: 2, ..Default::default() };
+        for i in 0..5 {
+            db::insert_consolidated(&cons, &format!("Pattern {}", i), "pattern", &[], 0.8).unwrap();
         }
+        assert!(due(&raw, &cons, &policy).unwrap().unwrap().contains("never dreamed"));
+        assert_eq!(due(&raw, &cons, &DreamConfig { auto: false, ..Default::default() }).unwrap(), None);
+
+        // Dreamed two days ago, before any of today's changes
+        let two_days_ago = chrono::Utc::now() - chrono::Duration::days(2);
+        db::set_meta(&cons, "last_dream", &two_days_ago.to_rfc3339()).unwrap();
+        cons.execute("UPDATE consolidated SET updated_at = '2020-01-01 00:00:00'", []).unwrap();
+        assert_eq!(due(&raw, &cons, &policy).unwrap(), None, "no churn");
+
+        cons.execute("UPDATE consolidated SET updated_at = datetime('now') WHERE id <= 3", []).unwrap();
+        assert!(due(&raw, &cons, &policy).unwrap().unwrap().starts_with("3 memories changed"));
+        let quiet = DreamConfig { min_changes: 10, ..policy.clone() };
+        assert_eq!(due(&raw, &cons, &quiet).unwrap(), None);
+
+        let id = db::save_memory(&raw, "Deploys skip staging now", "decision", "s1").unwrap();
+        db::link_contradiction(&raw, id, 1, "opposite").unwrap();
+        db::link_contradiction(&raw, id, 2, "opposite").unwrap();
+        assert!(due(&raw, &cons, &quiet).unwrap().unwrap().starts_with("2 contradictions"));
+
+        let week_ago = chrono::Utc::now() - chrono::Duration::days(8);
+        db::set_meta(&cons, "last_dream", &week_ago.to_rfc3339()).unwrap();
+        let strict = DreamConfig { min_changes: 10, min_contradictions: 10, ..policy };
+        assert!(due(&raw, &cons, &strict).unwrap().unwrap().starts_with("8 days"));
+    }
+
+    #[test]
+    fn prunes_redundant_memories_under_gates() {
+        use crate::models::Consolidation;
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let a = db::insert_consolidated(&cons, "Tests run with nextest", "convention", &[1, 2], 0.8).unwrap();
+        let b = db::insert_consolidated(&cons, "Use cargo nextest for tests", "convention", &[3], 0.7).unwrap();
+        let stale = db::insert_consolidated(&cons, "CI runs on Travis", "decision", &[4], 0.6).unwrap();
+        let rule = db::insert_consolidated(&cons, "Never commit .env files", antipattern::TYPE, &[5], 0.9).unwrap();
+        db::add_consolidated_refs(&cons, b, &["Cargo.toml".to_string()]).unwrap();
+        let consolidated = db::get_all_consolidated(&cons).unwrap();
+
+        let merge = |ids: Vec<i64>| Consolidation {
+            content: "Tests run with cargo nextest".to_string(),
+            r#type: "convention".to_string(),
+            source_ids: ids,
+            confidence: 0.85,
+        };
+        let response = || ConsolidationResult {
+            merges: vec![merge(vec![a, b]), merge(vec![a, stale]), merge(vec![stale, 999])],
+            decayed: vec![stale, rule, a],
+            ..Default::default()
+        };
+        let mut strict = Config::default();
+        strict.quality.strict = true;
+        assert!(checked_prune(&mut response(), &consolidated, &strict).is_err(), "unknown id and antipattern");
+
+        let mut result = response();
+        checked_prune(&mut result, &consolidated, &Config::default()).unwrap();
+        assert_eq!(result.merges.len(), 2);
+        assert_eq!(result.decayed, vec![stale, a]);
+        // The second merge and decaying `a` cite a memory the first merge replaced
+        assert_eq!(apply_prune(&cons, &result, &consolidated).unwrap(), (1, 1));
+
+        let left = db::get_all_consolidated(&cons).unwrap();
+        assert_eq!(left.len(), 2);
+        assert!(left.iter().any(|m| m.id == rule));
+        let merged = left.iter().find(|m| m.id != rule).unwrap();
+        assert_eq!((merged.content.as_str(), merged.source_ids.clone()), ("Tests run with cargo nextest", vec![1, 2, 3]));
+        assert_eq!(merged.refs, vec!["Cargo.toml"]);
     }
-    if let Some(start) = text.find('{') {
-        if let Some(end) = text.rfind('}') {
-            return text[start..=end].trim();
+
+    #[test]
+    fn refines_insights_instead_of_duplicating() {
+        use crate::models::Consolidation;
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let a = db::insert_consolidated(&cons, "Retry uploads with backoff", "pattern", &[1], 0.8).unwrap();
+        let b = db::insert_consolidated(&cons, "Retry webhooks with backoff", "pattern", &[2], 0.8).unwrap();
+        let earlier = "Network calls across the codebase retry with exponential backoff";
+        let old = db::insert_consolidated(&cons, earlier, "insight", &[a], 0.7).unwrap();
+        let consolidated = db::get_all_consolidated(&cons).unwrap();
+
+        let insight = |content: &str| Consolidation {
+            content: content.to_string(),
+            r#type: "insight".to_string(),
+            source_ids: vec![a, b],
+            confidence: 0.8,
+        };
+        let result = ConsolidationResult {
+            consolidations: vec![
+                insight("Network calls across the codebase retry with exponential backoff and jitter"),
+                insight("Deploys are gated on staging sign-off"),
+                insight("Deploys are gated on staging sign-off first"),
+            ],
+            ..Default::default()
+        };
+        let counts = apply_patterns(&raw, &cons, &result, &consolidated).unwrap();
+        assert_eq!((counts.insights, counts.refined), (1, 2));
+
+        let insights: Vec<_> =
+            db::get_all_consolidated(&cons).unwrap().into_iter().filter(|m| m.r#type == "insight").collect();
+        assert_eq!(insights.len(), 2);
+        let refined = insights.iter().find(|m| m.id == old).unwrap();
+        assert!(refined.content.ends_with("and jitter"));
+        assert_eq!(refined.source_ids, vec![a, b]);
+    }
+
+    #[test]
+    fn removes_only_dead_skills() {
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let dir = std::env::temp_dir().join(format!("cortex-dead-skills-{}", std::process::id()));
+        for name in ["old-unread", "old-but-read", "fresh"] {
+            skills::store_skill(&cons, name, None, "# Steps", &[]).unwrap();
         }
+        skills::generate_skill_files(&cons, &dir.join("skills")).unwrap();
+        cons.execute("UPDATE skills SET updated_at = '2020-01-01 00:00:00' WHERE name != 'fresh'", []).unwrap();
+        let read = skills::find(&cons, "old-but-read").unwrap().unwrap();
+        db::record_skill_use(&cons, read.id).unwrap();
+
+        let names: Vec<String> = ["old-unread", "old-but-read", "fresh", "missing"].map(String::from).to_vec();
+        assert_eq!(remove_dead_skills(&cons, &names, &dir).unwrap(), 1);
+        let left: Vec<String> = db::get_all_skills(&cons).unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(left, vec!["fresh", "old-but-read"]);
+        assert_eq!(skills::find(&cons, "old-but-read").unwrap().unwrap().use_count, 1);
+        assert!(!dir.join("skills/old-unread.md").exists() && dir.join("skills/fresh.md").exists());
+        let _ = std::fs::remove_dir_all(&dir);
     }
-    text.trim()
 }
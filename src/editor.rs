@@ -0,0 +1,121 @@
+use anyhow::{Context, Result};
+use std::process::Command;
+
+/// A memory composed interactively in $EDITOR.
+pub struct EditedMemory {
+    pub content: String,
+    pub r#type: String,
+    pub tags: Vec<String>,
+    pub importance: f64,
+}
+
+const TEMPLATE_HELP: &str = "\
+# Write the memory below the --- line. Comment lines above it are ignored.
+# type: bugfix, decision, pattern, preference, observation, antipattern
+# tags: comma-separated, e.g. auth, database
+# importance: 0.0-1.0
+# Leave the content empty to abort.
+";
+
+/// Open $VISUAL/$EDITOR on a save template and parse the result.
+/// Returns None if the user left the content empty.
+pub fn edit_memory(mem_type: &str, initial_content: &str) -> Result<Option<EditedMemory>> {
+    let template = format!(
+        "{TEMPLATE_HELP}type: {mem_type}\ntags: \nimportance: 0.5\n---\n{initial_content}\n"
+    );
+    let text = open_in_editor(&template)?;
+    parse_template(&text, mem_type).map(|m| if m.content.is_empty() { None } else { Some(m) })
+}
+
+/// Write `initial` to a temp file, open it in the user's editor, and return the saved text.
+pub fn open_in_editor(initial: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    let path = std::env::temp_dir().join(format!("cortex-{}.md", uuid::Uuid::new_v4()));
+    std::fs::write(&path, initial)?;
+
+    // Run through the shell so EDITOR values with arguments (e.g. "code --wait") work
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("cortex")
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Failed to launch editor `{}`", editor));
+
+    let result = match status {
+        Ok(s) if s.success() => std::fs::read_to_string(&path).map_err(Into::into),
+        Ok(s) => Err(anyhow::anyhow!("Editor `{}` exited with {}", editor, s)),
+        Err(e) => Err(e),
+    };
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+fn parse_template(text: &str, default_type: &str) -> Result<EditedMemory> {
+    let mut memory = EditedMemory {
+        content: String::new(),
+        r#type: default_type.to_string(),
+        tags: vec![],
+        importance: 0.5,
+    };
+
+    let (header, body) = match text.split_once("\n---\n") {
+        Some((h, b)) => (h, b),
+        None => ("", text),
+    };
+
+    for line in header.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else { continue };
+        let value = value.trim();
+        match key.trim() {
+            "type" if !value.is_empty() => memory.r#type = value.to_string(),
+            "tags" => {
+                memory.tags = value
+                    .split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect();
+            }
+            "importance" if !value.is_empty() => {
+                let importance: f64 = value
+                    .parse()
+                    .with_context(|| format!("Invalid importance: {}", value))?;
+                memory.importance = importance.clamp(0.0, 1.0);
+            }
+            _ => {}
+        }
+    }
+
+    memory.content = body.trim().to_string();
+
+    Ok(memory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_save_template() {
+        let text = format!(
+            "{TEMPLATE_HELP}type: decision\ntags: auth, , database\nimportance: 1.7\n---\n\nUse argon2 for password hashes\n"
+        );
+        let m = parse_template(&text, "observation").unwrap();
+        assert_eq!((m.content.as_str(), m.r#type.as_str()), ("Use argon2 for password hashes", "decision"));
+        assert_eq!(m.tags, ["auth", "database"]);
+        assert_eq!(m.importance, 1.0);
+
+        // Without a header everything is content; a blank type keeps the default
+        let m = parse_template("just a note", "observation").unwrap();
+        assert_eq!((m.content.as_str(), m.r#type.as_str(), m.importance), ("just a note", "observation", 0.5));
+        assert_eq!(parse_template("type: \n---\nx", "pattern").unwrap().r#type, "pattern");
+        assert!(parse_template("importance: high\n---\nx", "pattern").is_err());
+    }
+}
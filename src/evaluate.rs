@@ -0,0 +1,207 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, params};
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, Manifest};
+use crate::config::Config;
+use crate::llm;
+use crate::models::{ConsolidationResult, Memory};
+
+/// Evaluated runs averaged on each side of `cortex stats`' trend.
+pub const TREND_WINDOW: usize = 10;
+
+/// The evaluator's verdict on one quick sleep (`[quality] evaluate`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct Evaluation {
+    /// Share of the observations whose information survives in the output, 0.0 to 1.0
+    pub coverage: f64,
+    /// Share of consolidations fully supported by their cited sources, 0.0 to 1.0
+    pub faithfulness: f64,
+    /// 1-based numbers of consolidations with claims their sources don't support
+    #[serde(default)]
+    pub unsupported: Vec<usize>,
+    #[serde(default)]
+    pub notes: String,
+}
+
+impl Evaluation {
+    /// Mean of coverage and faithfulness, each clamped to 0.0..=1.0.
+    pub fn score(&self) -> f64 {
+        (self.coverage.clamp(0.0, 1.0) + self.faithfulness.clamp(0.0, 1.0)) / 2.0
+    }
+}
+
+/// Scores of recent evaluated runs, for `cortex stats`.
+#[derive(Debug, Serialize)]
+pub struct Trend {
+    pub runs: i64,
+    pub last: f64,
+    /// Mean score of the last `TREND_WINDOW` runs
+    pub average: f64,
+    /// Mean score of the `TREND_WINDOW` runs before those, if there were any
+    pub previous_average: Option<f64>,
+}
+
+impl std::fmt::Display for Trend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Consolidation quality: {:.2} last run, {:.2} average", self.last, self.average)?;
+        match self.previous_average {
+            Some(prev) if self.average > prev + 0.02 => write!(f, " (up from {:.2})", prev),
+            Some(prev) if self.average < prev - 0.02 => write!(f, " (down from {:.2})", prev),
+            Some(_) => write!(f, " (steady)"),
+            None => Ok(()),
+        }?;
+        write!(f, " over {} evaluated run{}", self.runs, if self.runs == 1 { "" } else { "s" })
+    }
+}
+
+/// Ask the LLM to grade `result` against the observations it was built from. Returns `None`
+/// when the result has nothing to grade.
+pub async fn evaluate(
+    raw_conn: &Connection,
+    inputs: &[Memory],
+    result: &ConsolidationResult,
+    config: &Config,
+) -> Result<Option<Evaluation>> {
+    if inputs.is_empty() || (result.consolidations.is_empty() && result.promotions.is_empty()) {
+        return Ok(None);
+    }
+    let observations = inputs.iter().map(|m| format!("#{} ({}): {}", m.id, m.r#type, m.content)).collect::<Vec<_>>().join("\n");
+    let mut outputs: Vec<String> = result
+        .consolidations
+        .iter()
+        .enumerate()
+        .map(|(i, c)| format!("{}. [from {:?}] {}", i + 1, c.source_ids, c.content))
+        .collect();
+    outputs.extend(result.promotions.iter().map(|id| format!("- observation #{} kept as-is", id)));
+    let prompt = format!(
+        r#"A memory consolidation step turned these observations into long-term memories. Grade it.
+
+Observations:
+{observations}
+
+Output (numbered consolidations cite the observations they were merged from):
+{outputs}
+
+- coverage: share of the observations whose useful information is preserved somewhere in the output (0.0-1.0)
+- faithfulness: share of numbered consolidations whose every claim is supported by the observations they cite (0.0-1.0; 1.0 if there are none)
+- unsupported: numbers of consolidations that state anything their cited observations don't
+- notes: one sentence on the biggest problem, or empty"#,
+        outputs = outputs.join("\n"),
+    );
+    let system = "You audit the output of a memory consolidation system for lost information and hallucination. Record your grades with the record_evaluation tool.";
+    let tool = llm::OutputTool {
+        name: "record_evaluation",
+        description: "Record the consolidation grades.",
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "coverage": { "type": "number" },
+                "faithfulness": { "type": "number" },
+                "unsupported": { "type": "array", "items": { "type": "integer" } },
+                "notes": { "type": "string" }
+            },
+            "required": ["coverage", "faithfulness", "unsupported"]
+        }),
+    };
+    let manifest = Manifest::new(raw_conn, "evaluate", audit::raw_keys(inputs.iter().map(|m| m.id)));
+    let response = llm::audited(manifest, llm::call_structured(&prompt, system, config, &config.llm, &tool)).await?;
+    let evaluation = serde_json::from_value(response).context("Evaluation did not match record_evaluation")?;
+    Ok(Some(evaluation))
+}
+
+/// Store `evaluation` of a run that turned `inputs` observations into `outputs` memories.
+pub fn record(conn: &Connection, evaluation: &Evaluation, inputs: usize, outputs: usize) -> Result<()> {
+    conn.execute(
+        "INSERT INTO sleep_evaluations (inputs, outputs, coverage, faithfulness, score, unsupported, notes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            inputs as i64,
+            outputs as i64,
+            evaluation.coverage,
+            evaluation.faithfulness,
+            evaluation.score(),
+            serde_json::to_string(&evaluation.unsupported)?,
+            evaluation.notes,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Score trend over evaluated runs, or `None` before the first one.
+pub fn trend(conn: &Connection) -> Result<Option<Trend>> {
+    let mut stmt = conn.prepare("SELECT score FROM sleep_evaluations ORDER BY id DESC LIMIT ?1")?;
+    let scores: Vec<f64> =
+        stmt.query_map(params![(TREND_WINDOW * 2) as i64], |r| r.get(0))?.collect::<rusqlite::Result<_>>()?;
+    let Some(&last) = scores.first() else { return Ok(None) };
+    let runs: i64 = conn.query_row("SELECT COUNT(*) FROM sleep_evaluations", [], |r| r.get(0))?;
+    let mean = |s: &[f64]| s.iter().sum::<f64>() / s.len() as f64;
+    let (recent, previous) = scores.split_at(scores.len().min(TREND_WINDOW));
+    Ok(Some(Trend {
+        runs,
+        last,
+        average: mean(recent),
+        previous_average: (!previous.is_empty()).then(|| mean(previous)),
+    }))
+}
+
+/// Evaluate and record a quick sleep's LLM result. Best-effort: a failed evaluation never fails
+/// the sleep, which has already been applied.
+pub async fn run(raw_conn: &Connection, inputs: &[Memory], result: &ConsolidationResult, config: &Config) {
+    let evaluation = match evaluate(raw_conn, inputs, result, config).await {
+        Ok(Some(e)) => e,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("Warning: could not evaluate this sleep: {}", e);
+            return;
+        }
+    };
+    let outputs = result.consolidations.len() + result.promotions.len();
+    if let Err(e) = record(raw_conn, &evaluation, inputs.len(), outputs) {
+        eprintln!("Warning: could not record the sleep evaluation: {}", e);
+    }
+    eprintln!(
+        "Self-evaluation: {:.2} (coverage {:.2}, faithfulness {:.2}).",
+        evaluation.score(),
+        evaluation.coverage,
+        evaluation.faithfulness
+    );
+    if !evaluation.unsupported.is_empty() {
+        let numbers = evaluation.unsupported.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", ");
+        eprintln!("  Consolidations not supported by their sources: {}", numbers);
+    }
+    if !evaluation.notes.trim().is_empty() {
+        eprintln!("  {}", evaluation.notes.trim());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn evaluation(coverage: f64, faithfulness: f64) -> Evaluation {
+        Evaluation { coverage, faithfulness, unsupported: vec![], notes: String::new() }
+    }
+
+    #[test]
+    fn tracks_score_trend() {
+        let conn = db::open_raw_db(std::path::Path::new(":memory:")).unwrap();
+        assert!(trend(&conn).unwrap().is_none());
+        assert_eq!(evaluation(1.4, 0.6).score(), 0.8);
+
+        for _ in 0..TREND_WINDOW {
+            record(&conn, &evaluation(0.6, 0.6), 5, 2).unwrap();
+        }
+        let t = trend(&conn).unwrap().unwrap();
+        assert!(t.previous_average.is_none());
+        assert!(t.to_string().starts_with("Consolidation quality: 0.60 last run, 0.60 average over"));
+
+        for _ in 0..TREND_WINDOW {
+            record(&conn, &evaluation(0.9, 0.9), 5, 2).unwrap();
+        }
+        let t = trend(&conn).unwrap().unwrap();
+        assert_eq!((t.runs, t.previous_average.map(|p| (p * 100.0).round())), (20, Some(60.0)));
+        assert!(t.to_string().contains("(up from 0.60)"));
+    }
+}
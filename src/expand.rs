@@ -0,0 +1,201 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::config::{Config, Expansion};
+use crate::llm;
+use crate::models::Memory;
+
+/// Paraphrases searched besides the query itself.
+const MAX_PARAPHRASES: usize = 3;
+/// Reciprocal rank fusion constant: a result at rank r in one list scores 1 / (RRF_K + r).
+/// 60 is the usual choice; it keeps one list's top hit from outweighing agreement between lists.
+const RRF_K: f64 = 60.0;
+
+/// Groups of interchangeable developer terms for `[recall] expand = "synonyms"`. Each word
+/// belongs to at most one group.
+const SYNONYMS: &[&[&str]] = &[
+    &["error", "failure", "bug", "crash", "panic"],
+    &["fix", "repair", "patch", "resolve", "workaround"],
+    &["slow", "latency", "performance", "sluggish"],
+    &["config", "configuration", "settings", "options"],
+    &["test", "spec", "assertion"],
+    &["delete", "remove", "drop"],
+    &["add", "create", "insert"],
+    &["database", "db", "sqlite", "storage"],
+    &["auth", "authentication", "login", "credentials"],
+    &["deploy", "release", "ship", "publish"],
+    &["dependency", "crate", "package", "library"],
+    &["build", "compile", "compilation"],
+    &["async", "concurrent", "concurrency"],
+    &["cache", "caching", "memoize"],
+    &["docs", "documentation", "readme"],
+    &["function", "method", "fn"],
+    &["ci", "pipeline", "workflow"],
+    &["flaky", "intermittent", "nondeterministic"],
+    &["timeout", "hang", "stall"],
+    &["upgrade", "bump", "update"],
+    &["refactor", "rename", "restructure"],
+    &["log", "logging", "tracing"],
+    &["env", "environment"],
+    &["permission", "authorization", "access"],
+    &["token", "secret", "apikey"],
+    &["api", "endpoint", "route"],
+    &["decision", "decide", "chose", "choice"],
+];
+
+/// The query followed by its `[synonyms]` expansion, if any, and up to three paraphrases from
+/// `mode`, for `context::recall_fused`. `Llm` falls back to the synonym table when the LLM
+/// call fails.
+pub async fn queries(query: &str, mode: Expansion, config: &Config) -> Vec<String> {
+    let paraphrases = match mode {
+        Expansion::Off => vec![],
+        Expansion::Synonyms => synonyms(query),
+        Expansion::Llm => match llm::paraphrase_query(query, MAX_PARAPHRASES, config).await {
+            Ok(p) if !p.is_empty() => p,
+            _ => synonyms(query),
+        },
+    };
+    let mut queries = vec![query.to_string()];
+    let aliased = with_aliases(query, &config.synonyms);
+    let aliases = usize::from(aliased != query);
+    if aliases > 0 {
+        queries.push(aliased);
+    }
+    for p in paraphrases {
+        let p = p.trim().to_string();
+        if !p.is_empty() && !queries.iter().any(|q| q.eq_ignore_ascii_case(&p)) {
+            queries.push(p);
+        }
+        if queries.len() > MAX_PARAPHRASES + aliases {
+            break;
+        }
+    }
+    queries
+}
+
+/// Paraphrases of `query` with known words swapped for synonyms: the first paraphrase uses
+/// each word's next synonym, the second the one after, and so on. Empty when no word is known.
+pub fn synonyms(query: &str) -> Vec<String> {
+    let words: Vec<_> = query.split_whitespace().map(|w| (w, group_of(w))).collect();
+    let mut out: Vec<String> = Vec::new();
+    for shift in 1..=MAX_PARAPHRASES {
+        let variant = words
+            .iter()
+            .map(|(word, group)| match group {
+                Some((group, i)) => group[(i + shift) % group.len()],
+                None => word,
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !variant.eq_ignore_ascii_case(query.trim()) && !out.contains(&variant) {
+            out.push(variant);
+        }
+    }
+    out
+}
+
+/// The synonym group containing `word` (ignoring case, punctuation, and a plural `s`) and
+/// the word's position in it.
+fn group_of(word: &str) -> Option<(&'static [&'static str], usize)> {
+    let word = word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+    let singular = word.strip_suffix('s').unwrap_or(&word);
+    SYNONYMS.iter().find_map(|group| {
+        let i = group.iter().position(|s| *s == word).or_else(|| group.iter().position(|s| *s == singular))?;
+        Some((*group, i))
+    })
+}
+
+/// `query` with the alternatives of every word it shares with a `[synonyms]` entry appended.
+/// An entry works both ways: with `db = "database, sqlite"`, "sqlite" also brings in "db" and
+/// "database". Full-text search ORs the words, so the result matches anything the query or an
+/// alternative does. Returns `query` unchanged when no entry applies.
+pub fn with_aliases(query: &str, synonyms: &BTreeMap<String, String>) -> String {
+    let groups: Vec<Vec<String>> = synonyms
+        .iter()
+        .map(|(term, alternatives)| {
+            std::iter::once(term.as_str())
+                .chain(alternatives.split(','))
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .collect();
+    let words: Vec<String> = query
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+        .collect();
+    let mut extra: Vec<&str> = Vec::new();
+    for word in &words {
+        let singular = word.strip_suffix('s').unwrap_or(word);
+        for group in groups.iter().filter(|g| g.iter().any(|s| s == word || s == singular)) {
+            for s in group {
+                if s != word && s != singular && !words.contains(s) && !extra.contains(&s.as_str()) {
+                    extra.push(s);
+                }
+            }
+        }
+    }
+    if extra.is_empty() {
+        return query.to_string();
+    }
+    format!("{} {}", query.trim(), extra.join(" "))
+}
+
+/// Reciprocal rank fusion: each memory scores the sum of 1 / (60 + rank) over the lists it
+/// appears in. Ties keep the order memories were first seen in, so one list comes back as is.
+pub fn fuse(lists: Vec<Vec<Memory>>, limit: usize) -> Vec<Memory> {
+    let mut scores: HashMap<i64, f64> = HashMap::new();
+    let mut fused: Vec<Memory> = Vec::new();
+    for list in lists {
+        for (rank, m) in list.into_iter().enumerate() {
+            *scores.entry(m.id).or_default() += 1.0 / (RRF_K + rank as f64 + 1.0);
+            if !fused.iter().any(|f| f.id == m.id) {
+                fused.push(m);
+            }
+        }
+    }
+    // Stable sort keeps first-seen order among equal scores
+    fused.sort_by(|a, b| scores[&b.id].total_cmp(&scores[&a.id]));
+    fused.truncate(limit);
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RecallConfig;
+    use crate::context;
+    use crate::db;
+    use std::path::Path;
+
+    #[test]
+    fn synonyms_rephrase_known_words() {
+        assert_eq!(synonyms("fix login errors"), vec!["repair credentials failure", "patch auth bug", "resolve authentication crash"]);
+        assert_eq!(synonyms("Env vars?"), vec!["environment vars?"]);
+        assert!(synonyms("tokio runtime").is_empty());
+
+        let project = BTreeMap::from([("k8s".to_string(), "kubernetes, cluster".to_string())]);
+        assert_eq!(with_aliases("k8s manifests", &project), "k8s manifests kubernetes cluster");
+        assert_eq!(with_aliases("Clusters?", &project), "Clusters? k8s kubernetes");
+        assert_eq!(with_aliases("helm charts", &project), "helm charts");
+    }
+
+    #[test]
+    fn fused_recall_finds_differently_phrased_memories() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let a = db::save_memory(&raw, "Login failure when the token expires mid-request", "bugfix", "s1").unwrap();
+        let b = db::save_memory(&raw, "Auth error messages are logged at warn level", "observation", "s1").unwrap();
+        db::save_memory(&raw, "Release notes come from the changelog", "decision", "s1").unwrap();
+        let ranking = RecallConfig::default();
+
+        let plain = context::recall(&raw, None, "auth error", 10, &ranking).unwrap();
+        assert_eq!(plain.iter().map(|m| m.id).collect::<Vec<_>>(), vec![b]);
+
+        let mut queries = vec!["auth error".to_string()];
+        queries.extend(synonyms("auth error"));
+        let fused = context::recall_fused(&raw, None, &queries, 10, &ranking).unwrap();
+        // "authentication failure" and "login bug" both find a; only the original finds b
+        assert_eq!(fused.iter().map(|m| m.id).collect::<Vec<_>>(), vec![a, b]);
+        let touched: i64 = raw.query_row("SELECT access_count FROM memories WHERE id = ?1", [a], |r| r.get(0)).unwrap();
+        assert_eq!(touched, 1, "each recall counts one access, however many phrasings matched");
+    }
+}
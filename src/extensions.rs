@@ -0,0 +1,70 @@
+use rusqlite::{Connection, LoadExtensionGuard};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// SQLite extensions every connection loads, from `[db] extensions` in ~/.cortex/config.toml.
+static EXTENSIONS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+/// `~/x` from the home directory, relative paths from `base` (~/.cortex).
+fn resolve(path: &Path, base: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => dirs::home_dir().map(|h| h.join(rest)).unwrap_or_else(|| path.to_path_buf()),
+        Err(_) if path.is_relative() => base.join(path),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+fn try_load(conn: &Connection, path: &Path) -> rusqlite::Result<()> {
+    // SAFETY: loading runs the library's init code. Paths come only from the user's own global
+    // config, never from a project's committed config.toml.
+    unsafe {
+        let _guard = LoadExtensionGuard::new(conn)?;
+        conn.load_extension(path, None::<&str>)
+    }
+}
+
+/// Set the extensions connections opened from now on load. Each is tried once on a scratch
+/// connection; one that fails is reported and left out, so a missing or incompatible library
+/// never stops cortex from opening a store. Only the first call has an effect.
+pub fn configure(paths: &[PathBuf], base: &Path) {
+    let scratch = Connection::open_in_memory().ok();
+    let loaded = paths
+        .iter()
+        .map(|p| resolve(p, base))
+        .filter(|p| match scratch.as_ref().map(|c| try_load(c, p)) {
+            Some(Ok(())) => true,
+            Some(Err(e)) => {
+                eprintln!("Warning: skipping SQLite extension {}: {}", p.display(), e);
+                false
+            }
+            None => false,
+        })
+        .collect();
+    let _ = EXTENSIONS.set(loaded);
+}
+
+/// Load the configured extensions into `conn`. Failures are reported, not returned.
+pub fn load(conn: &Connection) {
+    for path in EXTENSIONS.get().map(Vec::as_slice).unwrap_or_default() {
+        if let Err(e) = try_load(conn, path) {
+            eprintln!("Warning: could not load SQLite extension {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_extension_paths() {
+        let base = Path::new("/home/me/.cortex");
+        assert_eq!(resolve(Path::new("ext/vec0.so"), base), base.join("ext/vec0.so"));
+        assert_eq!(resolve(Path::new("/opt/vec0.so"), base), PathBuf::from("/opt/vec0.so"));
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(resolve(Path::new("~/lib/vec0.so"), base), home.join("lib/vec0.so"));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(try_load(&conn, Path::new("/nonexistent/ext.so")).is_err());
+    }
+}
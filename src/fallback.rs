@@ -0,0 +1,191 @@
+use anyhow::Result;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::db;
+use crate::models::Memory;
+use crate::similar::word_set;
+
+/// Newest rows per table whose words make up the vocabulary corrections are drawn from.
+const VOCABULARY_ROWS: usize = 5000;
+
+/// Corrections offered per misspelled word.
+const CANDIDATES: usize = 3;
+
+/// What to try when recall finds nothing.
+#[derive(Debug, Default, Serialize)]
+pub struct Fallback {
+    /// The query with unknown words replaced by close words from the store
+    pub did_you_mean: Vec<String>,
+    /// Entity names close to a query word
+    pub topics: Vec<String>,
+    /// Memories containing a query word inside another word, which full-text search misses
+    pub partial: Vec<Memory>,
+}
+
+impl Fallback {
+    pub fn is_empty(&self) -> bool {
+        self.did_you_mean.is_empty() && self.topics.is_empty() && self.partial.is_empty()
+    }
+
+    /// Lines for the CLI and `cortex_recall`, after "No memories found".
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        if !self.did_you_mean.is_empty() {
+            let quoted: Vec<String> = self.did_you_mean.iter().map(|q| format!("\"{}\"", q)).collect();
+            out.push_str(&format!("Did you mean {}?\n", quoted.join(" or ")));
+        }
+        if !self.topics.is_empty() {
+            out.push_str(&format!("Related topics: {}\n", self.topics.join(", ")));
+        }
+        if !self.partial.is_empty() {
+            out.push_str("Partial matches:\n");
+            for m in &self.partial {
+                out.push_str(&format!("  [{}] #{}: {}\n", m.r#type, m.id, m.content.lines().next().unwrap_or("")));
+            }
+        }
+        out
+    }
+}
+
+/// Optimal string alignment distance: edits, counting a swap of neighbours as one.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Edits allowed for a word of `len` characters to still count as a typo of another.
+fn max_edits(len: usize) -> usize {
+    if len <= 4 { 1 } else { 2 }
+}
+
+/// How many recent memories and consolidated memories use each word.
+fn vocabulary(raw: &Connection, cons: &Connection) -> Result<HashMap<String, usize>> {
+    let mut vocab = HashMap::new();
+    for (conn, sql) in [
+        (raw, "SELECT content FROM memories WHERE trusted = 1 ORDER BY id DESC LIMIT ?1"),
+        (cons, "SELECT content FROM consolidated ORDER BY id DESC LIMIT ?1"),
+    ] {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(params![VOCABULARY_ROWS as i64], |r| r.get::<_, String>(0))?;
+        for content in rows {
+            for word in word_set(&content?) {
+                *vocab.entry(word).or_default() += 1;
+            }
+        }
+    }
+    Ok(vocab)
+}
+
+/// Known words within `max_edits` of `word`, closest and most used first.
+fn corrections(word: &str, vocab: &HashMap<String, usize>) -> Vec<String> {
+    let len = word.chars().count();
+    let max = max_edits(len);
+    let mut found: Vec<(usize, usize, &String)> = vocab
+        .iter()
+        .filter(|(w, _)| w.chars().count().abs_diff(len) <= max)
+        .filter_map(|(w, n)| Some((edit_distance(word, w), *n, w)).filter(|(d, _, _)| *d <= max))
+        .collect();
+    found.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)).then(a.2.cmp(b.2)));
+    found.into_iter().take(CANDIDATES).map(|(_, _, w)| w.clone()).collect()
+}
+
+/// Alternatives for a `query` recall found nothing for: respellings of words the store doesn't
+/// use, entity names close to the query, and memories containing a query word mid-word.
+/// Read-only, like `suggest`.
+pub fn fallback(raw: &Connection, cons: &Connection, query: &str, limit: usize) -> Result<Fallback> {
+    let words: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 3)
+        .map(|w| w.to_lowercase())
+        .collect();
+    if words.is_empty() {
+        return Ok(Fallback::default());
+    }
+    let vocab = vocabulary(raw, cons)?;
+
+    // The best respelling of every unknown word, then one variant per runner-up
+    let options: Vec<Vec<String>> = words
+        .iter()
+        .map(|w| if vocab.contains_key(w) { vec![w.clone()] } else { corrections(w, &vocab) })
+        .collect();
+    let mut did_you_mean: Vec<String> = Vec::new();
+    if words.iter().zip(&options).any(|(w, o)| !vocab.contains_key(w) && !o.is_empty()) {
+        let best: Vec<String> = options.iter().zip(&words).map(|(o, w)| o.first().unwrap_or(w).clone()).collect();
+        let mut candidates = vec![best.join(" ")];
+        for (i, o) in options.iter().enumerate() {
+            for alternative in o.iter().skip(1) {
+                let mut variant = best.clone();
+                variant[i] = alternative.clone();
+                candidates.push(variant.join(" "));
+            }
+        }
+        for q in candidates {
+            if q != words.join(" ") && !did_you_mean.contains(&q) && did_you_mean.len() < limit {
+                did_you_mean.push(q);
+            }
+        }
+    }
+
+    let mut stmt = raw.prepare("SELECT name FROM entities ORDER BY access_count DESC")?;
+    let names = stmt.query_map([], |r| r.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    let topics: Vec<String> = names
+        .into_iter()
+        .filter(|name| {
+            let name = name.to_lowercase();
+            words.iter().any(|w| {
+                (w.len() >= 4 && name.contains(w.as_str()))
+                    || name.split_whitespace().any(|part| edit_distance(part, w) <= max_edits(w.chars().count()))
+            })
+        })
+        .take(limit)
+        .collect();
+
+    let long: Vec<String> = words.iter().filter(|w| w.chars().count() >= 4).cloned().collect();
+    let partial = db::search_memories_substring(raw, &long, limit)?;
+    Ok(Fallback { did_you_mean, topics, partial })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn suggests_respellings_topics_and_partial_matches() {
+        assert_eq!(edit_distance("kuberentes", "kubernetes"), 1);
+        assert_eq!(edit_distance("deploy", "deploys"), 1);
+
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        db::save_memory(&raw, "Kubernetes manifests live in deploy/k8s", "convention", "s1").unwrap();
+        let id = db::save_memory(&raw, "Use the microservices template for new services", "pattern", "s1").unwrap();
+        raw.execute("INSERT INTO entities (name, entity_type) VALUES ('Kubernetes', 'tool')", []).unwrap();
+
+        let found = fallback(&raw, &cons, "kuberentes manifets", 5).unwrap();
+        assert_eq!(found.did_you_mean.first().map(String::as_str), Some("kubernetes manifests"));
+        assert_eq!(found.topics, vec!["Kubernetes"]);
+
+        let found = fallback(&raw, &cons, "services", 5).unwrap();
+        assert!(found.partial.iter().any(|m| m.id == id));
+        assert!(found.render().contains("Partial matches"));
+        assert!(fallback(&raw, &cons, "a b", 5).unwrap().is_empty());
+    }
+}
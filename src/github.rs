@@ -0,0 +1,274 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use crate::db;
+use crate::models::ConsolidationResult;
+
+/// The GitHub Actions run that `cortex sleep --from-ci` is executing in.
+pub struct GithubContext {
+    pub repository: String,
+    pub sha: String,
+    pub pr_number: Option<u64>,
+    pub run_url: Option<String>,
+}
+
+impl GithubContext {
+    /// Read the run from the GITHUB_* environment.
+    pub fn from_env() -> Result<Self> {
+        let sha = std::env::var("GITHUB_SHA")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("--from-ci requires a GitHub Actions environment (GITHUB_SHA is not set)."))?;
+        let repository = std::env::var("GITHUB_REPOSITORY").unwrap_or_default();
+
+        let run_url = match (std::env::var("GITHUB_SERVER_URL"), std::env::var("GITHUB_RUN_ID")) {
+            (Ok(server), Ok(run)) if !repository.is_empty() => {
+                Some(format!("{}/{}/actions/runs/{}", server, repository, run))
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            repository,
+            sha,
+            pr_number: pr_number_from_env(),
+            run_url,
+        })
+    }
+
+    pub fn short_sha(&self) -> &str {
+        self.sha.get(..7).unwrap_or(&self.sha)
+    }
+
+    /// Tags recorded on each memory consolidated by this run.
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags = vec!["ci".to_string(), format!("commit:{}", self.short_sha())];
+        if let Some(pr) = self.pr_number {
+            tags.push(format!("pr:{}", pr));
+        }
+        tags
+    }
+
+    /// The pull request as an issue-tracker ref (`owner/repo#42`).
+    pub fn pr_ref(&self) -> Option<String> {
+        let pr = self.pr_number?;
+        if self.repository.is_empty() {
+            Some(format!("#{}", pr))
+        } else {
+            Some(format!("{}#{}", self.repository, pr))
+        }
+    }
+}
+
+fn pr_number_from_env() -> Option<u64> {
+    let git_ref = std::env::var("GITHUB_REF").ok();
+    let event = || std::fs::read_to_string(std::env::var("GITHUB_EVENT_PATH").ok()?).ok();
+    pr_number(git_ref.as_deref(), event)
+}
+
+/// PR number from `refs/pull/<n>/merge`, falling back to the event payload.
+fn pr_number(git_ref: Option<&str>, event: impl FnOnce() -> Option<String>) -> Option<u64> {
+    if let Some(rest) = git_ref.and_then(|r| r.strip_prefix("refs/pull/"))
+        && let Some(n) = rest.split('/').next().and_then(|n| n.parse().ok())
+    {
+        return Some(n);
+    }
+    let event: serde_json::Value = serde_json::from_str(&event()?).ok()?;
+    event
+        .pointer("/pull_request/number")
+        .or_else(|| event.pointer("/number"))
+        .and_then(|n| n.as_u64())
+}
+
+/// Tag every unconsolidated memory with the run's commit/PR so consolidated entries inherit them.
+pub fn tag_unconsolidated(raw_conn: &Connection, ctx: &GithubContext) -> Result<usize> {
+    let memories = db::get_unconsolidated_memories(raw_conn)?;
+    let ci_tags = ctx.tags();
+    let pr_ref: Vec<String> = ctx.pr_ref().into_iter().collect();
+    for m in &memories {
+        let mut tags = m.tags.clone();
+        for t in &ci_tags {
+            if !tags.contains(t) {
+                tags.push(t.clone());
+            }
+        }
+        db::update_memory_tags(raw_conn, m.id, &tags)?;
+        db::add_memory_refs(raw_conn, m.id, &pr_ref)?;
+    }
+    Ok(memories.len())
+}
+
+/// Append a markdown summary of the sleep to $GITHUB_STEP_SUMMARY, if set.
+pub fn write_job_summary(ctx: &GithubContext, tagged: usize, result: Option<&ConsolidationResult>, removed: u64) -> Result<()> {
+    let Ok(path) = std::env::var("GITHUB_STEP_SUMMARY") else {
+        return Ok(());
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open job summary {}", path))?;
+    file.write_all(job_summary(ctx, tagged, result, removed).as_bytes())?;
+    Ok(())
+}
+
+fn job_summary(ctx: &GithubContext, tagged: usize, result: Option<&ConsolidationResult>, removed: u64) -> String {
+    let mut out = String::from("## Cortex consolidation\n\n");
+    out.push_str(&format!("Commit `{}`", ctx.short_sha()));
+    if let Some(pr) = ctx.pr_number {
+        out.push_str(&format!(" · PR #{}", pr));
+    }
+    out.push_str(&format!(" · {} memories processed\n\n", tagged));
+
+    match result {
+        Some(r) => {
+            out.push_str("| Consolidations | Promotions | Contradictions | Decayed | Skills |\n|---|---|---|---|---|\n");
+            out.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                r.consolidations.len(),
+                r.promotions.len(),
+                r.contradictions.len(),
+                r.decayed.len(),
+                r.skill_updates.len()
+            ));
+            if !r.consolidations.is_empty() {
+                out.push_str("\n### New patterns\n\n");
+                for c in &r.consolidations {
+                    let first_line = c.content.lines().next().unwrap_or("");
+                    out.push_str(&format!("- **{}** {}\n", c.r#type, first_line));
+                }
+            }
+        }
+        None => out.push_str(&format!(
+            "LLM consolidation unavailable; ran micro sleep instead ({} stale memories removed).\n",
+            removed
+        )),
+    }
+    if let Some(url) = &ctx.run_url {
+        out.push_str(&format!("\n[Workflow run]({})\n", url));
+    }
+    out
+}
+
+/// Commit the consolidated mirror (consolidated.db and skills/) if it changed.
+/// Returns false when there was nothing to commit. `cons_conn` is checkpointed first, so the
+/// committed file holds changes still sitting in its gitignored -wal file.
+pub fn commit_consolidated(project_root: &Path, cortex_dir: &Path, cons_conn: &Connection, message: &str) -> Result<bool> {
+    let busy: i64 = cons_conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |r| r.get(0))?;
+    if busy != 0 {
+        anyhow::bail!("Could not checkpoint consolidated.db while another connection was writing; nothing committed.");
+    }
+    let paths = [cortex_dir.join("consolidated.db"), cortex_dir.join("skills")];
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .current_dir(project_root)
+            .args(args)
+            .status()
+            .context("Failed to run git")
+    };
+
+    let mut add = Command::new("git");
+    add.current_dir(project_root).args(["add", "--"]).args(&paths);
+    if !add.status().context("Failed to run git")?.success() {
+        anyhow::bail!("git add failed for the consolidated mirror.");
+    }
+    if git(&["diff", "--cached", "--quiet"])?.success() {
+        return Ok(false);
+    }
+
+    // Actions checkouts have no identity configured; fall back to the bot account
+    let mut commit = Command::new("git");
+    commit.current_dir(project_root);
+    if !has_git_identity(project_root) {
+        commit.args([
+            "-c",
+            "user.name=github-actions[bot]",
+            "-c",
+            "user.email=41898282+github-actions[bot]@users.noreply.github.com",
+        ]);
+    }
+    if !commit.args(["commit", "-m", message]).status().context("Failed to run git")?.success() {
+        anyhow::bail!("git commit failed for the consolidated mirror.");
+    }
+    Ok(true)
+}
+
+fn has_git_identity(project_root: &Path) -> bool {
+    Command::new("git")
+        .current_dir(project_root)
+        .args(["config", "user.email"])
+        .output()
+        .map(|o| o.status.success() && !o.stdout.is_empty())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Consolidation;
+
+    fn context(repository: &str, pr_number: Option<u64>) -> GithubContext {
+        GithubContext { repository: repository.into(), sha: "0123456789abcdef".into(), pr_number, run_url: None }
+    }
+
+    #[test]
+    fn commits_changes_still_in_the_wal() {
+        let root = std::env::temp_dir().join(format!("cortex-gh-{}", uuid::Uuid::new_v4()));
+        let cortex_dir = root.join(".cortex");
+        std::fs::create_dir_all(cortex_dir.join("skills")).unwrap();
+        std::fs::write(cortex_dir.join("skills/deploy.md"), "# deploy\n").unwrap();
+        assert!(Command::new("git").current_dir(&root).args(["init", "-q"]).status().unwrap().success());
+        let cons = db::open_consolidated_db(&cortex_dir.join("consolidated.db")).unwrap();
+        db::insert_consolidated(&cons, "Deploys go through staging", "decision", &[], 0.9).unwrap();
+
+        assert!(commit_consolidated(&root, &cortex_dir, &cons, "Update memories").unwrap());
+        let committed = root.join("committed.db");
+        let blob = Command::new("git").current_dir(&root).args(["show", "HEAD:.cortex/consolidated.db"]).output().unwrap();
+        std::fs::write(&committed, blob.stdout).unwrap();
+        let copy = Connection::open(&committed).unwrap();
+        assert_eq!(db::get_consolidated_count(&copy).unwrap(), 1);
+        assert!(!commit_consolidated(&root, &cortex_dir, &cons, "Update memories").unwrap());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn reads_the_pr_from_the_ref_or_event() {
+        assert_eq!(pr_number(Some("refs/pull/17/merge"), || None), Some(17));
+        let event = || Some(r#"{"pull_request":{"number":23}}"#.to_string());
+        assert_eq!(pr_number(Some("refs/heads/main"), event), Some(23));
+        assert_eq!(pr_number(None, || Some(r#"{"number":5,"action":"opened"}"#.to_string())), Some(5));
+        assert_eq!(pr_number(Some("refs/pull/x/merge"), || Some("not json".to_string())), None);
+        assert_eq!(pr_number(None, || Some(r#"{"ref":"main"}"#.to_string())), None);
+    }
+
+    #[test]
+    fn tags_and_refs_name_the_commit_and_pr() {
+        let ctx = context("acme/api", Some(42));
+        assert_eq!(ctx.short_sha(), "0123456");
+        assert_eq!(ctx.tags(), ["ci", "commit:0123456", "pr:42"]);
+        assert_eq!(ctx.pr_ref().as_deref(), Some("acme/api#42"));
+        assert_eq!(context("", Some(42)).pr_ref().as_deref(), Some("#42"));
+        assert_eq!(context("acme/api", None).pr_ref(), None);
+        assert_eq!(GithubContext { sha: "abc".into(), ..context("", None) }.short_sha(), "abc");
+    }
+
+    #[test]
+    fn summarizes_the_sleep() {
+        let mut result = ConsolidationResult::default();
+        result.consolidations.push(Consolidation {
+            content: "Retry flaky uploads\nwith backoff".into(),
+            r#type: "pattern".into(),
+            source_ids: vec![1, 2],
+            confidence: 0.8,
+        });
+        let summary = job_summary(&context("acme/api", Some(42)), 3, Some(&result), 0);
+        assert!(summary.starts_with("## Cortex consolidation\n\nCommit `0123456` · PR #42 · 3 memories processed"));
+        assert!(summary.contains("| 1 | 0 | 0 | 0 | 0 |"));
+        assert!(summary.contains("- **pattern** Retry flaky uploads\n"));
+        let fallback = job_summary(&context("acme/api", None), 0, None, 2);
+        assert!(fallback.contains("ran micro sleep instead (2 stale memories removed)"));
+    }
+}
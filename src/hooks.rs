@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::Config;
+use crate::plugins::ENV_ALLOWLIST;
+
+/// Upper bound for a single hook so a hung command or endpoint can't stall cortex.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Memory events that can trigger `[hooks]`.
+#[derive(Debug, Clone, Copy)]
+pub enum HookEvent {
+    Save,
+    SleepComplete,
+    GlobalPromotion,
+}
+
+impl HookEvent {
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::Save => "on_save",
+            HookEvent::SleepComplete => "on_sleep_complete",
+            HookEvent::GlobalPromotion => "on_global_promotion",
+        }
+    }
+
+    fn targets(self, config: &Config) -> &[String] {
+        match self {
+            HookEvent::Save => &config.hooks.on_save,
+            HookEvent::SleepComplete => &config.hooks.on_sleep_complete,
+            HookEvent::GlobalPromotion => &config.hooks.on_global_promotion,
+        }
+    }
+}
+
+/// Run every hook configured for `event`. Hook failures are reported as warnings,
+/// never as errors, so automation can't break a save or sleep.
+pub async fn fire(config: &Config, event: HookEvent, data: Value) {
+    let targets = event.targets(config);
+    if targets.is_empty() {
+        return;
+    }
+
+    let mut payload = serde_json::json!({
+        "event": event.name(),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    });
+    if let (Some(obj), Value::Object(extra)) = (payload.as_object_mut(), data) {
+        obj.extend(extra);
+    }
+
+    for target in targets {
+        let result = if target.starts_with("http://") || target.starts_with("https://") {
+            post(target, &payload).await
+        } else {
+            run_command(target, event, &payload, HOOK_TIMEOUT).await
+        };
+        if let Err(e) = result {
+            eprintln!("Warning: {} hook `{}` failed: {}", event.name(), target, e);
+        }
+    }
+}
+
+async fn post(url: &str, payload: &Value) -> Result<()> {
+    let resp = reqwest::Client::new()
+        .post(url)
+        .timeout(HOOK_TIMEOUT)
+        .json(payload)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        anyhow::bail!("HTTP {}", resp.status());
+    }
+    Ok(())
+}
+
+/// Run `command` with `sh -c`, the payload on stdin, and the same minimal environment as
+/// plugins plus `CORTEX_EVENT`.
+async fn run_command(command: &str, event: HookEvent, payload: &Value, timeout: Duration) -> Result<()> {
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env_clear()
+        .envs(ENV_ALLOWLIST.iter().filter_map(|k| std::env::var(k).ok().map(|v| (*k, v))))
+        .env("CORTEX_EVENT", event.name())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("failed to spawn")?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // Commands that ignore stdin may close it early; that's not a failure
+        let _ = stdin.write_all(payload.to_string().as_bytes()).await;
+    }
+
+    let status = tokio::time::timeout(timeout, child.wait())
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out after {}s", timeout.as_secs_f64()))??;
+    if !status.success() {
+        anyhow::bail!("exited with {}", status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn commands_get_the_payload_and_time_out() {
+        let out = std::env::temp_dir().join(format!("cortex-hook-{}", uuid::Uuid::new_v4()));
+        let payload = serde_json::json!({ "event": "on_save", "id": 7 });
+        let command = format!("cat > '{0}'; echo \"$CORTEX_EVENT ${{ANTHROPIC_API_KEY:-withheld}}\" >> '{0}'", out.display());
+        run_command(&command, HookEvent::Save, &payload, HOOK_TIMEOUT).await.unwrap();
+        let written = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(written, format!("{}on_save withheld\n", payload));
+        std::fs::remove_file(&out).unwrap();
+
+        let err = run_command("sleep 5", HookEvent::Save, &payload, Duration::from_millis(100)).await.unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert!(run_command("exit 3", HookEvent::Save, &payload, HOOK_TIMEOUT).await.is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::path::{Path, PathBuf};
 
 use crate::config::Config;
@@ -13,14 +13,24 @@ fn init_cortex_dir(cortex_dir: &Path) -> Result<()> {
     let _raw = db::open_raw_db(&cortex_dir.join("raw.db"))?;
     let _cons = db::open_consolidated_db(&cortex_dir.join("consolidated.db"))?;
 
-    // Write default config
-    let config = Config::default();
-    let config_str = toml::to_string_pretty(&config)?;
-    std::fs::write(cortex_dir.join("config.toml"), config_str)?;
+    // Write default config, keeping one written ahead of the databases
+    let config_path = config_path(cortex_dir);
+    if !config_path.exists() {
+        let config = Config::default();
+        let config_str = toml::to_string_pretty(&config)?;
+        if let Some(parent) = config_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(config_path, config_str)?;
+    }
 
     Ok(())
 }
 
+/// The .gitignore entries `cortex init` adds.
+const GITIGNORE_ENTRIES: [&str; 4] =
+    [".cortex/raw.db", ".cortex/raw.db-wal", ".cortex/raw.db-shm", ".cortex/consolidation.lock"];
+
 pub fn init_cortex(base_dir: &Path) -> Result<()> {
     let cortex_dir = base_dir.join(".cortex");
     if cortex_dir.exists() {
@@ -34,12 +44,19 @@ pub fn init_cortex(base_dir: &Path) -> Result<()> {
     let gitignore = base_dir.join(".gitignore");
     if gitignore.exists() {
         let content = std::fs::read_to_string(&gitignore)?;
-        if !content.contains(".cortex/raw.db") {
-            let mut append = String::new();
-            if !content.ends_with('\n') {
-                append.push('\n');
+        let mut append = String::new();
+        if !content.contains(GITIGNORE_ENTRIES[0]) {
+            append.push_str(&GITIGNORE_ENTRIES[..3].join("\n"));
+            append.push('\n');
+        }
+        if !content.contains(GITIGNORE_ENTRIES[3]) {
+            append.push_str(GITIGNORE_ENTRIES[3]);
+            append.push('\n');
+        }
+        if !append.is_empty() {
+            if !content.is_empty() && !content.ends_with('\n') {
+                append.insert(0, '\n');
             }
-            append.push_str(".cortex/raw.db\n.cortex/raw.db-wal\n.cortex/raw.db-shm\n");
             std::fs::write(&gitignore, format!("{}{}", content, append))?;
         }
     }
@@ -48,11 +65,138 @@ pub fn init_cortex(base_dir: &Path) -> Result<()> {
     Ok(())
 }
 
-/// Return the global cortex directory path if it exists.
+/// Undo `cortex init`: delete `cortex_dir` and the .gitignore entries init added to `base_dir`.
+pub fn uninit(cortex_dir: &Path, base_dir: &Path) -> Result<()> {
+    std::fs::remove_dir_all(cortex_dir).with_context(|| format!("Failed to remove {}", cortex_dir.display()))?;
+    let gitignore = base_dir.join(".gitignore");
+    if gitignore.exists() {
+        let content = std::fs::read_to_string(&gitignore)?;
+        let kept: Vec<&str> = content.lines().filter(|l| !GITIGNORE_ENTRIES.contains(&l.trim())).collect();
+        if kept.len() < content.lines().count() {
+            let mut rewritten = kept.join("\n");
+            if !rewritten.is_empty() {
+                rewritten.push('\n');
+            }
+            std::fs::write(&gitignore, rewritten)?;
+        }
+    }
+    Ok(())
+}
+
+/// Environment variable naming the project store directory itself, overriding discovery from
+/// the working directory (e.g. a volume mounted into a container). `--dir` still wins.
+pub const DIR_ENV: &str = "CORTEX_DIR";
+
+/// Environment variable naming the global store directory, instead of ~/.cortex.
+pub const GLOBAL_DIR_ENV: &str = "CORTEX_GLOBAL_DIR";
+
+fn env_path(var: &str) -> Option<PathBuf> {
+    std::env::var_os(var).filter(|v| !v.is_empty()).map(PathBuf::from)
+}
+
+/// The project store directory from `CORTEX_DIR`, if set.
+pub fn dir_from_env() -> Option<PathBuf> {
+    env_path(DIR_ENV)
+}
+
+/// The global store split across the XDG base directories: config.toml and credentials.json
+/// in `$XDG_CONFIG_HOME/cortex`, databases and everything else in `$XDG_DATA_HOME/cortex`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Xdg {
+    pub config: PathBuf,
+    pub data: PathBuf,
+}
+
+/// An XDG base directory; relative values are invalid per the spec and ignored.
+fn xdg_base(var: &str, default: &str) -> Option<PathBuf> {
+    env_path(var).filter(|p| p.is_absolute()).or_else(|| Some(dirs::home_dir()?.join(default)))
+}
+
+pub fn xdg_dirs() -> Option<Xdg> {
+    Some(Xdg {
+        config: xdg_base("XDG_CONFIG_HOME", ".config")?.join("cortex"),
+        data: xdg_base("XDG_DATA_HOME", ".local/share")?.join("cortex"),
+    })
+}
+
+/// `~/.cortex`, the default global layout.
+pub fn legacy_global_dir() -> Option<PathBuf> {
+    Some(dirs::home_dir()?.join(".cortex"))
+}
+
+/// The XDG layout, when it's in use: `CORTEX_GLOBAL_DIR` isn't set, ~/.cortex holds no store,
+/// and either XDG cortex directory exists. Creating ~/.config/cortex opts a new install in.
+fn active_xdg() -> Option<Xdg> {
+    let legacy_store = legacy_global_dir()
+        .is_some_and(|d| d.join("config.toml").exists() || d.join("consolidated.db").exists());
+    if env_path(GLOBAL_DIR_ENV).is_some() || legacy_store {
+        return None;
+    }
+    xdg_dirs().filter(|x| x.config.exists() || x.data.exists())
+}
+
+/// Where the global store lives: `CORTEX_GLOBAL_DIR`, the XDG data directory, or ~/.cortex.
+/// The project registry and schedule logs live there too.
+pub fn global_dir_path() -> Option<PathBuf> {
+    env_path(GLOBAL_DIR_ENV).or_else(|| active_xdg().map(|x| x.data)).or_else(legacy_global_dir)
+}
+
+/// Where the global config.toml and credentials.json live: the global store directory, except
+/// under XDG.
+pub fn global_config_dir() -> Option<PathBuf> {
+    active_xdg().map(|x| x.config).or_else(global_dir_path)
+}
+
+/// The config.toml of the store in `cortex_dir`.
+pub fn config_path(cortex_dir: &Path) -> PathBuf {
+    match active_xdg() {
+        Some(xdg) if xdg.data == cortex_dir => xdg.config.join("config.toml"),
+        _ => cortex_dir.join("config.toml"),
+    }
+}
+
+/// Move the global store from `legacy` into the XDG layout. Fails without moving anything if
+/// either XDG directory already holds a store.
+pub fn migrate_to_xdg(legacy: &Path, xdg: &Xdg) -> Result<()> {
+    if !legacy.is_dir() {
+        anyhow::bail!("No global store at {} to migrate", legacy.display());
+    }
+    if xdg.config.join("config.toml").exists() || xdg.data.join("consolidated.db").exists() {
+        anyhow::bail!("A global store already exists at {} / {}", xdg.config.display(), xdg.data.display());
+    }
+    std::fs::create_dir_all(&xdg.config)?;
+    std::fs::create_dir_all(&xdg.data)?;
+    for entry in std::fs::read_dir(legacy)? {
+        let from = entry?.path();
+        let name = from.file_name().context("unnamed entry")?;
+        let to = match name.to_str() {
+            Some("config.toml" | "credentials.json") => xdg.config.join(name),
+            _ => xdg.data.join(name),
+        };
+        std::fs::rename(&from, &to)
+            .with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))?;
+    }
+    std::fs::remove_dir(legacy)?;
+    Ok(())
+}
+
+/// Initialize the store directory named by `CORTEX_DIR`. The directory may already exist as
+/// an empty mount point.
+pub fn init_dir(cortex_dir: &Path) -> Result<()> {
+    if cortex_dir.join("config.toml").exists() {
+        eprintln!("{} is already initialized", cortex_dir.display());
+        return Ok(());
+    }
+    init_cortex_dir(cortex_dir)?;
+    eprintln!("Initialized {}", cortex_dir.display());
+    Ok(())
+}
+
+/// Return the global cortex directory path if it exists. An empty directory, such as a freshly
+/// mounted volume, doesn't count.
 pub fn find_global_dir() -> Option<PathBuf> {
-    let home = dirs::home_dir()?;
-    let global_dir = home.join(".cortex");
-    if global_dir.exists() {
+    let global_dir = global_dir_path()?;
+    if global_dir.is_dir() && (config_path(&global_dir).exists() || global_dir.join("consolidated.db").exists()) {
         Some(global_dir)
     } else {
         None
@@ -61,11 +205,59 @@ pub fn find_global_dir() -> Option<PathBuf> {
 
 /// Ensure the global cortex directory exists, creating it if needed.
 pub fn ensure_global_dir() -> Result<PathBuf> {
-    let home = dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Cannot determine home directory"))?;
-    let global_dir = home.join(".cortex");
-    if !global_dir.exists() {
+    let global_dir = global_dir_path().ok_or_else(|| anyhow::anyhow!("Cannot determine home directory"))?;
+    if !global_dir.join("consolidated.db").exists() {
         init_cortex_dir(&global_dir)?;
-        eprintln!("Initialized global ~/.cortex/");
+        eprintln!("Initialized global {}", global_dir.display());
     }
     Ok(global_dir)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initializes_mounted_dir() {
+        let dir = std::env::temp_dir().join(format!("cortex-init-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        init_dir(&dir).unwrap();
+        assert!(dir.join("config.toml").exists() && dir.join("consolidated.db").exists());
+        init_dir(&dir).unwrap();
+        assert_eq!(env_path("CORTEX_TEST_UNSET_DIR"), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn migrates_to_xdg_layout() {
+        let home = std::env::temp_dir().join(format!("cortex-xdg-{}", uuid::Uuid::new_v4()));
+        let legacy = home.join(".cortex");
+        init_cortex_dir(&legacy).unwrap();
+        std::fs::write(legacy.join("credentials.json"), "{}").unwrap();
+        let xdg = Xdg { config: home.join(".config/cortex"), data: home.join(".local/share/cortex") };
+        migrate_to_xdg(&legacy, &xdg).unwrap();
+        assert!(!legacy.exists());
+        assert!(xdg.config.join("config.toml").exists() && xdg.config.join("credentials.json").exists());
+        assert!(xdg.data.join("consolidated.db").exists() && xdg.data.join("skills").is_dir());
+
+        // Nothing left to move, and an existing XDG store is never overwritten
+        assert!(migrate_to_xdg(&legacy, &xdg).is_err());
+        init_cortex_dir(&legacy).unwrap();
+        assert!(migrate_to_xdg(&legacy, &xdg).is_err());
+        assert!(legacy.join("consolidated.db").exists());
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn uninit_reverts_init() {
+        let base = std::env::temp_dir().join(format!("cortex-uninit-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&base).unwrap();
+        std::fs::write(base.join(".gitignore"), "target/").unwrap();
+        init_cortex(&base).unwrap();
+        assert!(std::fs::read_to_string(base.join(".gitignore")).unwrap().contains(".cortex/raw.db-wal"));
+        uninit(&base.join(".cortex"), &base).unwrap();
+        assert!(!base.join(".cortex").exists());
+        assert_eq!(std::fs::read_to_string(base.join(".gitignore")).unwrap(), "target/\n");
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
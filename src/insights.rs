@@ -0,0 +1,155 @@
+use anyhow::Result;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::confirm;
+use crate::db;
+
+/// A query and how often it was run.
+#[derive(Debug, Serialize)]
+pub struct QueryCount {
+    pub query: String,
+    pub count: usize,
+}
+
+/// A raw memory and how often recall returned it.
+#[derive(Debug, Serialize)]
+pub struct Recalled {
+    pub id: i64,
+    pub content: String,
+    pub count: usize,
+    /// Later saves in the same MCP session that reported it worked (see `confirm`)
+    pub confirmed: usize,
+}
+
+/// A consolidated memory none of whose sources recall returned.
+#[derive(Debug, Serialize)]
+pub struct Unrecalled {
+    pub id: i64,
+    pub r#type: String,
+    pub content: String,
+}
+
+/// `cortex insights`: what the local query log says about recall over the last `days`.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub days: u32,
+    pub queries: usize,
+    /// Queries that found nothing, most frequent first: wording the store doesn't cover
+    pub zero_hit: Vec<QueryCount>,
+    pub most_recalled: Vec<Recalled>,
+    /// Consolidated before the first logged query and not recalled since
+    pub never_recalled: Vec<Unrecalled>,
+}
+
+/// Log one recall (from `source`: `cli` or `mcp`) and the memory IDs it returned, negative for
+/// global memories. Only called with `[insights] enabled`; the log never leaves raw.db.
+pub fn record(raw: &Connection, source: &str, query: &str, hit_ids: &[i64]) -> Result<()> {
+    if query.trim().is_empty() {
+        return Ok(());
+    }
+    raw.execute(
+        "INSERT INTO query_log (source, query, hits, hit_ids) VALUES (?1, ?2, ?3, ?4)",
+        params![source, query.trim(), hit_ids.len() as i64, serde_json::to_string(hit_ids)?],
+    )?;
+    Ok(())
+}
+
+/// Delete the whole query log. Returns how many entries there were.
+pub fn clear(raw: &Connection) -> Result<usize> {
+    Ok(raw.execute("DELETE FROM query_log", [])?)
+}
+
+pub fn report(raw: &Connection, cons: &Connection, days: u32, limit: usize) -> Result<Report> {
+    let since = format!("-{} days", days);
+    let mut stmt = raw.prepare(
+        "SELECT at, query, hit_ids FROM query_log
+         WHERE at >= strftime('%Y-%m-%dT%H:%M:%SZ', 'now', ?1) ORDER BY at",
+    )?;
+    let rows = stmt
+        .query_map(params![since], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, String>(2)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut zero_hit: BTreeMap<String, usize> = BTreeMap::new();
+    let mut counts: BTreeMap<i64, usize> = BTreeMap::new();
+    for (_, query, hit_ids) in &rows {
+        let ids: Vec<i64> = serde_json::from_str(hit_ids).unwrap_or_default();
+        if ids.is_empty() {
+            *zero_hit.entry(query.to_lowercase()).or_default() += 1;
+        }
+        for id in ids.into_iter().filter(|id| *id > 0) {
+            *counts.entry(id).or_default() += 1;
+        }
+    }
+    let mut zero_hit: Vec<QueryCount> = zero_hit.into_iter().map(|(query, count)| QueryCount { query, count }).collect();
+    zero_hit.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.query.cmp(&b.query)));
+    zero_hit.truncate(limit);
+
+    let mut ranked: Vec<(i64, usize)> = counts.iter().map(|(id, n)| (*id, *n)).collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked.truncate(limit);
+    let ids: Vec<i64> = ranked.iter().map(|(id, _)| *id).collect();
+    let contents: BTreeMap<i64, String> =
+        db::get_source_memories(raw, &ids)?.into_iter().map(|(m, _)| (m.id, m.content)).collect();
+    let most_recalled = ranked
+        .into_iter()
+        .map(|(id, count)| {
+            Ok(Recalled {
+                id,
+                content: contents.get(&id).cloned().unwrap_or_else(|| "(deleted)".to_string()),
+                count,
+                confirmed: confirm::confirmed_by(raw, id)?.len(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Without a log before a memory existed, "never recalled" would only mean "too new to tell"
+    let mut never_recalled = Vec::new();
+    if let Some(first_day) = rows.first().map(|(at, _, _)| at[..10].to_string()) {
+        let recalled: HashSet<i64> = counts.keys().copied().collect();
+        for c in db::get_all_consolidated(cons)? {
+            if c.r#type == "insight" || c.created_at.get(..10).is_none_or(|day| day >= first_day.as_str()) {
+                continue;
+            }
+            if !c.source_ids.iter().any(|id| recalled.contains(id)) {
+                never_recalled.push(Unrecalled { id: c.id, r#type: c.r#type, content: c.content });
+            }
+        }
+        never_recalled.truncate(limit);
+    }
+    Ok(Report { days, queries: rows.len(), zero_hit, most_recalled, never_recalled })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn reports_zero_hits_and_recall_counts() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let a = db::save_memory(&raw, "Deploys go through staging", "decision", "s1").unwrap();
+        let b = db::save_memory(&raw, "Tests run with nextest", "convention", "s1").unwrap();
+        let old = db::insert_consolidated(&cons, "Deploys go through staging", "decision", &[a], 0.9).unwrap();
+        db::insert_consolidated(&cons, "Tests run with nextest", "convention", &[b], 0.9).unwrap();
+        cons.execute("UPDATE consolidated SET created_at = '2020-01-01 00:00:00'", []).unwrap();
+        let cold = db::insert_consolidated(&cons, "Tests use nextest", "convention", &[b], 0.9).unwrap();
+        cons.execute("UPDATE consolidated SET created_at = '2020-01-01 00:00:00' WHERE id = ?1", [cold]).unwrap();
+
+        record(&raw, "cli", "deploy", &[a, -3]).unwrap();
+        record(&raw, "mcp", "deploy staging", &[a]).unwrap();
+        record(&raw, "cli", "Kubernetes", &[]).unwrap();
+        record(&raw, "cli", "kubernetes ", &[]).unwrap();
+        record(&raw, "cli", "", &[]).unwrap();
+
+        let report = report(&raw, &cons, 30, 10).unwrap();
+        assert_eq!(report.queries, 4);
+        assert_eq!((report.zero_hit[0].query.as_str(), report.zero_hit[0].count), ("kubernetes", 2));
+        assert_eq!((report.most_recalled[0].id, report.most_recalled[0].count), (a, 2));
+        let never: Vec<i64> = report.never_recalled.iter().map(|u| u.id).collect();
+        assert!(!never.contains(&old) && never.contains(&cold), "{:?}", never);
+        assert_eq!(clear(&raw).unwrap(), 4);
+    }
+}
@@ -0,0 +1,228 @@
+use anyhow::Result;
+use serde_json::Value;
+
+/// Parse the first JSON object or array found in an LLM response.
+///
+/// Candidates are tried in order: fenced code blocks, then every balanced `{...}`/`[...]`
+/// span in the text. Each is parsed strictly first, then after `repair` (comments and
+/// trailing commas removed), so prose around the JSON and several blocks in one reply
+/// are both handled.
+pub fn parse_lenient(text: &str) -> Result<Value> {
+    let mut candidates = fenced_blocks(text);
+    candidates.extend(balanced_spans(text));
+    candidates.push(text.trim());
+
+    for candidate in &candidates {
+        if let Ok(v) = serde_json::from_str::<Value>(candidate)
+            && (v.is_object() || v.is_array())
+        {
+            return Ok(v);
+        }
+        if let Ok(v) = serde_json::from_str::<Value>(&repair(candidate))
+            && (v.is_object() || v.is_array())
+        {
+            return Ok(v);
+        }
+    }
+    let preview: String = text.chars().take(200).collect();
+    anyhow::bail!("No JSON object found in response: {}", preview)
+}
+
+/// Contents of markdown code fences (```json or bare ```), in order.
+fn fenced_blocks(text: &str) -> Vec<&str> {
+    let mut blocks = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("```") {
+        let after = &rest[start + 3..];
+        // Skip the info string ("json", "JSON", ...) up to the end of the line
+        let body_start = after.find('\n').map(|i| i + 1).unwrap_or(after.len());
+        let body = &after[body_start..];
+        let Some(end) = body.find("```") else { break };
+        blocks.push(body[..end].trim());
+        rest = &body[end + 3..];
+    }
+    blocks
+}
+
+/// Every top-level balanced `{...}` or `[...]` span, skipping brackets inside strings.
+fn balanced_spans(text: &str) -> Vec<&str> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if (bytes[i] == b'{' || bytes[i] == b'[')
+            && let Some(end) = matching_close(bytes, i)
+        {
+            spans.push(&text[i..=end]);
+            i = end + 1;
+            continue;
+        }
+        i += 1;
+    }
+    spans
+}
+
+/// Index of the bracket closing the one at `start`, or None if the text ends first.
+fn matching_close(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut stack = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &b) in bytes.iter().enumerate().skip(start) {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'{' => stack.push(b'}'),
+            b'[' => stack.push(b']'),
+            b'}' | b']' => {
+                if stack.pop() != Some(b) {
+                    return None;
+                }
+                if stack.is_empty() {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Fix the JSON5-isms models commonly produce: `//` and `/* */` comments and trailing
+/// commas before `}` or `]`. String contents are left untouched.
+pub fn repair(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if in_string {
+            out.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            i += 1;
+            continue;
+        }
+        match (c, chars.get(i + 1)) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            ('/', Some('*')) => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+                continue;
+            }
+            (',', _) => {
+                let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+                if !matches!(next, Some('}') | Some(']')) {
+                    out.push(c);
+                }
+            }
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn plain_and_fenced() {
+        assert_eq!(parse_lenient(r#"{"a": 1}"#).unwrap(), json!({"a": 1}));
+        assert_eq!(parse_lenient("Here you go:\n```json\n{\"a\": 1}\n```\nDone.").unwrap(), json!({"a": 1}));
+        assert_eq!(parse_lenient("```\n[1, 2]\n```").unwrap(), json!([1, 2]));
+    }
+
+    #[test]
+    fn prose_and_multiple_blocks() {
+        let text = r#"I considered {not json} first. Result: {"a": {"b": "}"}} and also {"c": 2}"#;
+        assert_eq!(parse_lenient(text).unwrap(), json!({"a": {"b": "}"}}));
+    }
+
+    #[test]
+    fn nested_fences_in_strings() {
+        let text = "```json\n{\"content\": \"use `x`\"}\n```";
+        assert_eq!(parse_lenient(text).unwrap(), json!({"content": "use `x`"}));
+    }
+
+    #[test]
+    fn repairs_trailing_commas_and_comments() {
+        let text = "{\n  // ids\n  \"ids\": [1, 2,],\n  /* note */ \"s\": \"a, ]\",\n}";
+        assert_eq!(parse_lenient(text).unwrap(), json!({"ids": [1, 2], "s": "a, ]"}));
+    }
+
+    #[test]
+    fn rejects_text_without_json() {
+        assert!(parse_lenient("no json here").is_err());
+        assert!(parse_lenient("{unterminated").is_err());
+    }
+
+    fn json_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            any::<bool>().prop_map(Value::from),
+            any::<i32>().prop_map(Value::from),
+            "[a-z ,{}\\[\\]\"`/]{0,12}".prop_map(Value::from),
+        ];
+        leaf.prop_recursive(3, 24, 4, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4).prop_map(Value::from),
+                prop::collection::btree_map("[a-z]{1,6}", inner, 0..4)
+                    .prop_map(|m| Value::Object(m.into_iter().collect())),
+            ]
+        })
+    }
+
+    fn json_object() -> impl Strategy<Value = Value> {
+        prop::collection::btree_map("[a-z]{1,6}", json_value(), 1..4).prop_map(|m| Value::Object(m.into_iter().collect()))
+    }
+
+    proptest! {
+        #[test]
+        fn finds_object_in_messy_output(
+            value in json_object(),
+            prefix in "[A-Za-z .:\n]{0,40}",
+            suffix in "[A-Za-z .:\n]{0,40}",
+            fenced in any::<bool>(),
+            pretty in any::<bool>(),
+        ) {
+            let body = if pretty { serde_json::to_string_pretty(&value).unwrap() } else { value.to_string() };
+            let body = if fenced { format!("```json\n{}\n```", body) } else { body };
+            let text = format!("{}{}{}", prefix, body, suffix);
+            prop_assert_eq!(parse_lenient(&text).unwrap(), value);
+        }
+
+        #[test]
+        fn repair_is_identity_on_valid_json(value in json_value()) {
+            let text = serde_json::to_string_pretty(&value).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Value>(&repair(&text)).unwrap(), value);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::Write;
+
+use crate::db;
+use crate::outcome::{self, Outcome};
+use crate::redact::Anonymizer;
+use crate::sensitivity::Sensitivity;
+
+/// `schema` field of every line. Bump when a record changes shape in a way readers would notice;
+/// added fields don't count.
+pub const SCHEMA: &str = "cortex-jsonl/1";
+
+/// One line of the export: which table the record came from, and the record as the JSON
+/// commands print it.
+#[derive(Serialize)]
+struct Line<'a, T: Serialize> {
+    schema: &'static str,
+    table: &'static str,
+    record: &'a T,
+}
+
+#[derive(Serialize)]
+struct OutcomeRecord<'a> {
+    consolidated_id: i64,
+    #[serde(flatten)]
+    outcome: &'a Outcome,
+}
+
+/// Records written per table by `export`.
+#[derive(Debug, Default, Serialize)]
+pub struct Written {
+    pub memories: usize,
+    pub entities: usize,
+    pub relationships: usize,
+    pub consolidated: usize,
+    pub skills: usize,
+    pub outcomes: usize,
+}
+
+impl Written {
+    pub fn total(&self) -> usize {
+        self.memories + self.entities + self.relationships + self.consolidated + self.skills + self.outcomes
+    }
+}
+
+struct Writer<'a, W: Write> {
+    out: &'a mut W,
+    flush: bool,
+}
+
+impl<W: Write> Writer<'_, W> {
+    fn line<T: Serialize>(&mut self, table: &'static str, record: &T) -> Result<()> {
+        serde_json::to_writer(&mut *self.out, &Line { schema: SCHEMA, table, record })?;
+        self.out.write_all(b"\n")?;
+        if self.flush {
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the store as JSON lines, one record per line, table by table: `memories`, `entities`,
+/// `relationships`, `consolidated`, `skills`, `outcomes`. Memories and consolidated memories
+/// are read row by row, so memory use doesn't grow with the store; with `flush_each`, every
+/// line is flushed as written, for pipes into tools that consume records as they arrive.
+/// Untrusted memories and memories more sensitive than `max_sensitivity` (with the outcomes of
+/// left-out consolidated memories) are skipped. With an `anonymizer`, text fields pass through
+/// it before they're written.
+pub fn export<W: Write>(
+    raw: &Connection,
+    cons: &Connection,
+    out: &mut W,
+    max_sensitivity: Sensitivity,
+    flush_each: bool,
+    mut anonymizer: Option<&mut Anonymizer>,
+) -> Result<Written> {
+    let mut w = Writer { out, flush: flush_each };
+    let mut written = Written::default();
+
+    db::each_memory(raw, |mut m| {
+        if m.sensitivity <= max_sensitivity {
+            if let Some(a) = anonymizer.as_deref_mut() {
+                a.memory(&mut m);
+            }
+            w.line("memories", &m)?;
+            written.memories += 1;
+        }
+        Ok(())
+    })?;
+    for mut e in db::get_all_entities(raw)? {
+        if let Some(a) = anonymizer.as_deref_mut() {
+            a.entity(&mut e);
+        }
+        w.line("entities", &e)?;
+        written.entities += 1;
+    }
+    for r in db::get_all_relationships(raw)? {
+        w.line("relationships", &r)?;
+        written.relationships += 1;
+    }
+    let mut exported = HashSet::new();
+    db::each_consolidated(cons, |mut c| {
+        if c.sensitivity <= max_sensitivity {
+            if let Some(a) = anonymizer.as_deref_mut() {
+                a.consolidated(&mut c);
+            }
+            w.line("consolidated", &c)?;
+            exported.insert(c.id);
+            written.consolidated += 1;
+        }
+        Ok(())
+    })?;
+    for mut s in db::get_all_skills(cons)? {
+        if let Some(a) = anonymizer.as_deref_mut() {
+            a.skill(&mut s);
+        }
+        w.line("skills", &s)?;
+        written.skills += 1;
+    }
+    for (consolidated_id, mut o) in outcome::all(cons)? {
+        if exported.contains(&consolidated_id) {
+            if let Some(a) = anonymizer.as_deref_mut() {
+                o.note = a.text(&o.note);
+            }
+            w.line("outcomes", &OutcomeRecord { consolidated_id, outcome: &o })?;
+            written.outcomes += 1;
+        }
+    }
+    w.out.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn writes_one_tagged_record_per_line() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let a = db::save_memory(&raw, "Retry S3 uploads with backoff", "pattern", "s1").unwrap();
+        let secret = db::save_memory(&raw, "The staging token is in 1Password", "observation", "s1").unwrap();
+        raw.execute("UPDATE memories SET sensitivity = 'secret' WHERE id = ?1", [secret]).unwrap();
+        let shown = db::insert_consolidated(&cons, "Retry uploads with backoff", "pattern", &[a], 0.8).unwrap();
+        let hidden = db::insert_consolidated(&cons, "Tokens live in 1Password", "convention", &[secret], 0.8).unwrap();
+        cons.execute("UPDATE consolidated SET sensitivity = 'secret' WHERE id = ?1", [hidden]).unwrap();
+        outcome::record(&cons, shown, true, "fixed the flaky upload").unwrap();
+        outcome::record(&cons, hidden, true, "").unwrap();
+
+        let mut out = Vec::new();
+        let written = export(&raw, &cons, &mut out, Sensitivity::Internal, true, None).unwrap();
+        assert_eq!((written.memories, written.consolidated, written.outcomes), (1, 1, 1));
+
+        let lines: Vec<serde_json::Value> =
+            String::from_utf8(out).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), written.total());
+        assert!(lines.iter().all(|l| l["schema"] == SCHEMA));
+        let tables: Vec<&str> = lines.iter().map(|l| l["table"].as_str().unwrap()).collect();
+        assert_eq!(tables, vec!["memories", "consolidated", "outcomes"]);
+        assert_eq!(lines[0]["record"]["id"], a);
+        assert_eq!(lines[2]["record"]["consolidated_id"], shown);
+        assert_eq!(lines[2]["record"]["worked"], true);
+    }
+}
@@ -0,0 +1,74 @@
+//! Library half of the `cortex` binary, exposed so benchmarks can drive the storage and
+//! retrieval paths directly.
+
+pub mod adr;
+pub mod antipattern;
+pub mod ask;
+pub mod audit;
+pub mod auth;
+#[cfg(feature = "aws")]
+pub mod aws;
+pub mod batch;
+pub mod bench;
+pub mod chat;
+pub mod classify;
+pub mod compress;
+pub mod config;
+pub mod confirm;
+pub mod context;
+pub mod contradiction;
+pub mod db;
+pub mod doctor;
+pub mod dream;
+pub mod editor;
+pub mod evaluate;
+pub mod expand;
+pub mod extensions;
+pub mod fallback;
+pub mod github;
+pub mod hooks;
+pub mod init;
+pub mod insights;
+pub mod json_util;
+pub mod jsonl;
+pub mod limits;
+pub mod llm;
+pub mod lock;
+pub mod mcp;
+pub mod mcp_install;
+pub mod merge;
+#[cfg(feature = "aws")]
+pub mod model_registry;
+pub mod models;
+pub mod outcome;
+pub mod oversize;
+pub mod obsidian;
+pub mod patch;
+pub mod plugins;
+pub mod projects;
+pub mod quality;
+pub mod query;
+pub mod redact;
+pub mod refs;
+pub mod remote;
+pub mod reset;
+pub mod review;
+pub mod runs;
+pub mod schedule;
+pub mod sensitivity;
+pub mod similar;
+pub mod skills;
+pub mod sleep;
+pub mod snapshot;
+pub mod stats;
+pub mod storage;
+pub mod store;
+pub mod suggest;
+pub mod templates;
+pub mod timeline;
+pub mod trust;
+pub mod update;
+pub mod wake;
+#[cfg(feature = "wasmtime")]
+pub mod wasm;
+pub mod why;
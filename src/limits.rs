@@ -0,0 +1,115 @@
+use anyhow::Result;
+use rusqlite::{Connection, params};
+
+use crate::antipattern;
+use crate::config::LimitsConfig;
+
+/// Eviction score of a consolidated memory: confidence, boosted by recalls and faded by days
+/// since its last update. Lowest goes first.
+const CONSOLIDATED_SCORE: &str =
+    "confidence * (access_count + 1.0) / (julianday('now') - julianday(updated_at) + 1.0)";
+
+/// Eviction score of a raw memory, the same one micro sleep decays by.
+const RAW_SCORE: &str = "importance * (access_count + 1.0) / (julianday('now') - julianday(accessed_at) + 1.0)";
+
+/// What `enforce` removed to bring the store under `[limits]`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Evicted {
+    pub consolidated: usize,
+    /// Archived out of raw search; `cortex why` still finds them
+    pub raw: usize,
+}
+
+/// Evict the lowest-scoring memories over `[limits]` from both databases.
+pub fn enforce(raw_conn: &Connection, cons_conn: &Connection, limits: &LimitsConfig) -> Result<Evicted> {
+    Ok(Evicted {
+        consolidated: evict_consolidated(cons_conn, limits.max_consolidated)?,
+        raw: evict_raw(raw_conn, limits.max_raw)?,
+    })
+}
+
+/// Delete the lowest-scoring consolidated memories beyond `max`, never antipatterns.
+pub fn evict_consolidated(conn: &Connection, max: usize) -> Result<usize> {
+    if max == 0 {
+        return Ok(0);
+    }
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM consolidated", [], |r| r.get(0))?;
+    let over = count - max as i64;
+    if over <= 0 {
+        return Ok(0);
+    }
+    let evicted = conn.execute(
+        &format!(
+            "DELETE FROM consolidated WHERE id IN (
+                 SELECT id FROM consolidated WHERE type != ?1
+                 ORDER BY {CONSOLIDATED_SCORE}, id LIMIT ?2)"
+        ),
+        params![antipattern::TYPE, over],
+    )?;
+    Ok(evicted)
+}
+
+/// Archive the lowest-scoring raw memories beyond `max`. Only memories already consolidated
+/// are eligible, so nothing is lost before a sleep has seen it; antipatterns always stay.
+pub fn evict_raw(conn: &Connection, max: usize) -> Result<usize> {
+    if max == 0 {
+        return Ok(0);
+    }
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM memories", [], |r| r.get(0))?;
+    let over = count - max as i64;
+    if over <= 0 {
+        return Ok(0);
+    }
+    let tx = conn.unchecked_transaction()?;
+    tx.execute_batch("CREATE TEMP TABLE IF NOT EXISTS evicted (id INTEGER PRIMARY KEY); DELETE FROM evicted;")?;
+    tx.execute(
+        &format!(
+            "INSERT INTO evicted SELECT id FROM memories WHERE consolidated = 1 AND type != ?1
+             ORDER BY {RAW_SCORE}, id LIMIT ?2"
+        ),
+        params![antipattern::TYPE, over],
+    )?;
+    tx.execute(
+        "INSERT OR REPLACE INTO archived_memories
+             (id, content, type, created_at, accessed_at, access_count, consolidated, importance,
+              session_id, entity_ids, tags, refs, sensitivity, no_llm)
+         SELECT id, content, type, created_at, accessed_at, access_count, consolidated, importance,
+                session_id, entity_ids, tags, refs, sensitivity, no_llm
+         FROM memories WHERE id IN (SELECT id FROM evicted)",
+        [],
+    )?;
+    let evicted = tx.execute("DELETE FROM memories WHERE id IN (SELECT id FROM evicted)", [])?;
+    tx.execute_batch("DROP TABLE evicted;")?;
+    tx.commit()?;
+    Ok(evicted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use std::path::Path;
+
+    #[test]
+    fn evicts_lowest_scoring_memories() {
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let weak = db::insert_consolidated(&cons, "Weak pattern", "pattern", &[], 0.2).unwrap();
+        let rule = db::insert_consolidated(&cons, "Never force-push main", antipattern::TYPE, &[], 0.1).unwrap();
+        let strong = db::insert_consolidated(&cons, "Strong decision", "decision", &[], 0.9).unwrap();
+        assert_eq!(evict_consolidated(&cons, 0).unwrap(), 0);
+        assert_eq!(evict_consolidated(&cons, 2).unwrap(), 1);
+        let left: Vec<i64> = db::get_all_consolidated(&cons).unwrap().iter().map(|c| c.id).collect();
+        assert!(left.contains(&rule) && left.contains(&strong) && !left.contains(&weak));
+
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let done = db::save_memory(&raw, "Consolidated already", "observation", "s").unwrap();
+        let pending = db::save_memory(&raw, "Not consolidated yet", "observation", "s").unwrap();
+        db::save_memory(&raw, "Also pending", "observation", "s").unwrap();
+        db::mark_consolidated(&raw, &[done]).unwrap();
+        // Only the consolidated memory may go, even though the store stays over budget
+        assert_eq!(evict_raw(&raw, 1).unwrap(), 1);
+        assert_eq!(db::get_unconsolidated_count(&raw).unwrap(), 2);
+        let archived = db::get_source_memories(&raw, &[done, pending]).unwrap();
+        assert!(archived[0].1.is_some() && archived[1].1.is_none());
+    }
+}
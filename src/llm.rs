@@ -1,38 +1,147 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::audit::{self, Manifest};
+use crate::auth;
+#[cfg(feature = "aws")]
+use crate::aws;
+use crate::config::{Config, LlmConfig};
+use crate::db;
+use crate::json_util;
+#[cfg(feature = "aws")]
+use crate::model_registry;
+
+/// Process-wide kill switch for outbound LLM calls (set by `--ci`).
+static LLM_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Disable all LLM calls for the rest of the process.
+pub fn disable_llm() {
+    LLM_DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// LLM calls left before `send` refuses (`u64::MAX` when unlimited).
+static CALLS_REMAINING: AtomicU64 = AtomicU64::new(u64::MAX);
+/// LLM calls started in this process.
+static CALLS_MADE: AtomicU64 = AtomicU64::new(0);
+
+/// Cap the number of LLM calls for the rest of the process (e.g. `sleep --all-projects
+/// --max-llm-calls`). Calls past the cap fail, so callers fall back as for a missing key.
+pub fn set_call_budget(max_calls: u64) {
+    CALLS_REMAINING.store(max_calls, Ordering::Relaxed);
+}
 
-use crate::config::Config;
+/// Whether the call budget still allows another call.
+pub fn budget_remaining() -> bool {
+    CALLS_REMAINING.load(Ordering::Relaxed) > 0
+}
+
+/// LLM calls started so far in this process, successful or not.
+pub fn calls_made() -> u64 {
+    CALLS_MADE.load(Ordering::Relaxed)
+}
+
+tokio::task_local! {
+    /// Manifest for calls made inside `audited`.
+    static MANIFEST: Manifest;
+}
+
+/// Run `f`, recording each LLM call it makes in the `llm_audit` table under `manifest`.
+pub async fn audited<F: std::future::Future>(manifest: Manifest, f: F) -> F::Output {
+    MANIFEST.scope(manifest, f).await
+}
 
 #[derive(Serialize)]
 struct MessageRequest {
     model: String,
     max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
     system: String,
     messages: Vec<Message>,
 }
 
+#[cfg(feature = "aws")]
 #[derive(Serialize)]
 struct BedrockRequest {
     anthropic_version: String,
     max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
     system: String,
     messages: Vec<Message>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 struct Message {
     role: String,
     content: String,
 }
 
+impl Message {
+    fn user(content: &str) -> Self {
+        Self { role: "user".to_string(), content: content.to_string() }
+    }
+
+    fn assistant(content: &str) -> Self {
+        Self { role: "assistant".to_string(), content: content.to_string() }
+    }
+}
+
 #[derive(Deserialize)]
 struct MessageResponse {
     content: Vec<ContentBlock>,
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize)]
+struct Usage {
+    input_tokens: i64,
+    output_tokens: i64,
 }
 
 #[derive(Deserialize)]
 struct ContentBlock {
     text: Option<String>,
+    /// Arguments of a `tool_use` block
+    input: Option<serde_json::Value>,
+}
+
+/// A tool the model is forced to call, so that its input is the structured response.
+pub struct OutputTool {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub schema: serde_json::Value,
+}
+
+impl OutputTool {
+    /// `tools` and `tool_choice` request fields.
+    fn request_fields(tool: Option<&OutputTool>) -> (Vec<serde_json::Value>, Option<serde_json::Value>) {
+        match tool {
+            Some(t) => (
+                vec![serde_json::json!({ "name": t.name, "description": t.description, "input_schema": t.schema })],
+                Some(serde_json::json!({ "type": "tool", "name": t.name })),
+            ),
+            None => (vec![], None),
+        }
+    }
 }
 
 /// Extract entities and relationships from a memory content string.
@@ -42,7 +151,7 @@ pub async fn extract_entities(content: &str, config: &Config) -> Result<crate::m
 
 Memory: "{content}"
 
-Output JSON:
+Record them with these fields:
 {{
   "entities": [
     {{"name": "EntityName", "type": "language|technology|service|pattern|concept|tool|framework", "description": "Short description"}}
@@ -57,14 +166,18 @@ Rules:
 - Use canonical names (e.g., "Rust" not "rust lang", "SQLite" not "sqlite")
 - Only extract entities that are clearly mentioned
 - If no clear entities, return empty arrays
-- Output ONLY valid JSON"#
+- Put the whole result in the tool call"#
     );
 
-    let system = "You are an entity extraction system. Extract structured entities and relationships from text. Output ONLY valid JSON.";
-    let response = call_anthropic(&prompt, system, config).await?;
+    let system = "You are an entity extraction system. Extract structured entities and relationships from text and record them with the record_entities tool.";
+    let tool = OutputTool {
+        name: "record_entities",
+        description: "Record the entities and relationships found in the memory.",
+        schema: crate::models::extraction_schema(),
+    };
+    let response = call_structured(&prompt, system, config, &config.llm, &tool).await?;
 
-    let json_str = extract_json_from_response(&response);
-    let result: crate::models::ExtractionResult = serde_json::from_str(json_str)
+    let result: crate::models::ExtractionResult = serde_json::from_value(response)
         .unwrap_or_else(|_| crate::models::ExtractionResult {
             entities: vec![],
             relationships: vec![],
@@ -72,139 +185,498 @@ Rules:
     Ok(result)
 }
 
-fn extract_json_from_response(text: &str) -> &str {
-    if let Some(start) = text.find("```json") {
-        let content = &text[start + 7..];
-        if let Some(end) = content.rfind("```") {
-            return content[..end].trim();
-        }
+/// Summarize an oversized memory in under `max_chars` characters.
+pub async fn summarize_memory(content: &str, max_chars: usize, config: &Config) -> Result<String> {
+    let prompt = format!(
+        r#"This memory is too long to store as-is. Summarize it in under {max_chars} characters.
+
+Keep what a coding agent would need later: the decision, cause, fix, or pattern; exact names of files, functions, commands, and error messages; and any issue or PR references. Drop repeated log lines and boilerplate.
+
+Memory:
+{content}"#
+    );
+    let system = "You summarize developer notes and logs for a memory system. Record the summary with the record_summary tool.";
+    let tool = OutputTool {
+        name: "record_summary",
+        description: "Record the summary of the memory.",
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": { "summary": { "type": "string" } },
+            "required": ["summary"]
+        }),
+    };
+    let response = call_structured(&prompt, system, config, &config.llm, &tool).await?;
+    match response.get("summary").and_then(|s| s.as_str()) {
+        Some(s) if !s.trim().is_empty() => Ok(s.trim().to_string()),
+        _ => anyhow::bail!("Summary response had no summary"),
     }
-    if let Some(start) = text.find("```") {
-        let content = &text[start + 3..];
-        if let Some(end) = content.rfind("```") {
-            return content[..end].trim();
+}
+
+/// Answer a question from numbered memory excerpts (`[key] ...` lines), returning the answer
+/// and the keys it cites.
+pub async fn answer_question(question: &str, memories: &str, config: &Config) -> Result<(String, Vec<String>)> {
+    let prompt = format!(
+        r#"Answer the question using only the project memories below. Each memory starts with its key in brackets.
+
+Cite every memory you rely on by its key. If the memories don't answer the question, say so plainly and cite nothing; do not guess from general knowledge. Keep the answer short and concrete.
+
+Question: {question}
+
+Memories:
+{memories}"#
+    );
+    let system = "You answer questions about a software project from its recorded memory. Record the answer with the record_answer tool.";
+    let tool = OutputTool {
+        name: "record_answer",
+        description: "Record the answer and the keys of the memories it is based on.",
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "answer": { "type": "string" },
+                "citations": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["answer", "citations"]
+        }),
+    };
+    let response = call_structured(&prompt, system, config, &config.llm, &tool).await?;
+    let answer = match response.get("answer").and_then(|s| s.as_str()) {
+        Some(s) if !s.trim().is_empty() => s.trim().to_string(),
+        _ => anyhow::bail!("Answer response had no answer"),
+    };
+    let citations = response
+        .get("citations")
+        .and_then(|c| c.as_array())
+        .map(|c| c.iter().filter_map(|k| k.as_str()).map(|k| k.trim_matches(['[', ']']).to_string()).collect())
+        .unwrap_or_default();
+    Ok((answer, citations))
+}
+
+/// Rephrase a search query `n` ways for `[recall] expand = "llm"`.
+pub async fn paraphrase_query(query: &str, n: usize, config: &Config) -> Result<Vec<String>> {
+    let prompt = format!(
+        r#"Rewrite this search query over a software project's notes {n} different ways. Use the other words a developer might have written the note with: synonyms, related technical terms, the problem instead of the fix or the other way round. Keep each rewrite a short keyword query.
+
+Query: {query}"#
+    );
+    let system = "You expand search queries for a developer memory system. Record the rewrites with the record_paraphrases tool.";
+    let tool = OutputTool {
+        name: "record_paraphrases",
+        description: "Record the rewritten queries.",
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "paraphrases": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["paraphrases"]
+        }),
+    };
+    let response = call_structured(&prompt, system, config, &config.llm, &tool).await?;
+    Ok(response
+        .get("paraphrases")
+        .and_then(|p| p.as_array())
+        .map(|p| p.iter().filter_map(|q| q.as_str()).map(|q| q.trim().to_string()).filter(|q| !q.is_empty()).take(n).collect())
+        .unwrap_or_default())
+}
+
+/// Whether a new memory contradicts an existing consolidated one; the reason if it does.
+pub async fn check_contradiction(new: &str, existing: &str, config: &Config) -> Result<Option<String>> {
+    let prompt = format!(
+        r#"A developer just saved a new memory about their project. Does it contradict the existing memory, i.e. can both not be true at the same time? A refinement, an addition, or a different topic is not a contradiction.
+
+Existing memory:
+{existing}
+
+New memory:
+{new}"#
+    );
+    let system = "You check a developer memory system for contradictions. Record the verdict with the record_verdict tool.";
+    let tool = OutputTool {
+        name: "record_verdict",
+        description: "Record whether the new memory contradicts the existing one, and why in one short sentence.",
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "contradicts": { "type": "boolean" },
+                "reason": { "type": "string" }
+            },
+            "required": ["contradicts", "reason"]
+        }),
+    };
+    let response = call_structured(&prompt, system, config, &config.llm, &tool).await?;
+    match response.get("contradicts").and_then(|c| c.as_bool()) {
+        Some(true) => {
+            let reason = response.get("reason").and_then(|r| r.as_str()).unwrap_or("").trim();
+            Ok(Some(if reason.is_empty() { "contradicts it".to_string() } else { reason.to_string() }))
         }
+        Some(false) => Ok(None),
+        None => anyhow::bail!("Verdict response had no verdict"),
     }
-    if let Some(start) = text.find('{') {
-        if let Some(end) = text.rfind('}') {
-            return text[start..=end].trim();
-        }
+}
+
+/// The memory type that fits `content` best, for saves that didn't give one.
+pub async fn classify_memory(content: &str, config: &Config) -> Result<String> {
+    let prompt = format!(
+        r#"Classify this developer memory by type:
+- bugfix: a bug, its cause, or its fix
+- decision: a choice that was made, often with the alternatives
+- pattern: a convention or recurring way things are done in the project
+- preference: how someone likes things done
+- observation: a fact that is none of the above
+
+Memory:
+{content}"#
+    );
+    let system = "You classify memories for a developer memory system. Record the type with the record_type tool.";
+    let types = ["bugfix", "decision", "pattern", "preference", "observation"];
+    let tool = OutputTool {
+        name: "record_type",
+        description: "Record the memory's type.",
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": { "type": { "type": "string", "enum": types } },
+            "required": ["type"]
+        }),
+    };
+    let response = call_structured(&prompt, system, config, &config.llm, &tool).await?;
+    match response.get("type").and_then(|t| t.as_str()).map(str::trim) {
+        Some(t) if types.contains(&t) => Ok(t.to_string()),
+        other => anyhow::bail!("Classification response had no known type: {:?}", other),
+    }
+}
+
+/// Reconstruct how a consolidated memory follows from its source memories (`cortex why --reason`).
+pub async fn reconstruct_reasoning(consolidated: &str, sources: &str, config: &Config) -> Result<String> {
+    let prompt = format!(
+        r#"A memory system consolidated the raw observations below into one memory. Explain step by step how the consolidated memory follows from them: which observations support which part, in time order, and what changed along the way.
+
+Point out any part of the consolidated memory that the observations don't support, and any observation that contradicts it. Refer to observations by their #id.
+
+Consolidated memory:
+{consolidated}
+
+Source observations:
+{sources}"#
+    );
+    let system = "You audit a developer memory system by tracing consolidated memories back to their evidence. Record the explanation with the record_reasoning tool.";
+    let tool = OutputTool {
+        name: "record_reasoning",
+        description: "Record the reasoning chain from the source observations to the consolidated memory.",
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": { "reasoning": { "type": "string" } },
+            "required": ["reasoning"]
+        }),
+    };
+    let response = call_structured(&prompt, system, config, &config.llm, &tool).await?;
+    match response.get("reasoning").and_then(|s| s.as_str()) {
+        Some(s) if !s.trim().is_empty() => Ok(s.trim().to_string()),
+        _ => anyhow::bail!("Reasoning response had no reasoning"),
     }
-    text.trim()
 }
 
-pub async fn call_anthropic(prompt: &str, system: &str, config: &Config) -> Result<String> {
+/// One turn of `cortex chat`: answer `message` from the memory excerpts and the recent
+/// conversation, and pick out new facts the user stated that are worth saving.
+pub async fn chat_reply(
+    history: &str,
+    message: &str,
+    memories: &str,
+    config: &Config,
+) -> Result<crate::models::ChatReply> {
+    let prompt = format!(
+        r#"You are chatting with a developer about their project's memory.
+
+Answer the latest message using only the project memories below and the conversation so far. Cite every memory you rely on by its key. If the memories don't cover it, say so; do not guess from general knowledge.
+
+Separately, list any new facts the developer states in the latest message (decisions, bug causes and fixes, patterns, preferences) that are not already in the memories. Phrase each as a standalone memory. List nothing for questions, chit-chat, or facts already recorded.
+
+Conversation so far:
+{history}
+
+Latest message: {message}
+
+Memories:
+{memories}"#,
+        history = if history.is_empty() { "(none)" } else { history },
+    );
+    let system = "You answer questions about a software project from its recorded memory and help curate it. Record your reply with the record_reply tool.";
+    let tool = OutputTool {
+        name: "record_reply",
+        description: "Record the answer, the keys of the memories it is based on, and new facts worth saving.",
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "answer": { "type": "string" },
+                "citations": { "type": "array", "items": { "type": "string" } },
+                "facts": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "content": { "type": "string" },
+                            "type": { "type": "string", "enum": ["bugfix", "decision", "pattern", "preference", "observation", "antipattern"] }
+                        },
+                        "required": ["content", "type"]
+                    }
+                }
+            },
+            "required": ["answer", "citations", "facts"]
+        }),
+    };
+    let response = call_structured(&prompt, system, config, &config.llm, &tool).await?;
+    let mut reply: crate::models::ChatReply =
+        serde_json::from_value(response).context("Chat response did not match record_reply")?;
+    for key in &mut reply.citations {
+        *key = key.trim_matches(['[', ']']).to_string();
+    }
+    reply.facts.retain(|f| !f.content.trim().is_empty());
+    Ok(reply)
+}
+
+/// Send one prompt using the active provider and force a call to `tool`, returning the tool
+/// input (JSON matching `tool.schema`). Falls back to scraping JSON from the text if no tool
+/// call comes back. `llm` is `config.llm`, or its dream variant.
+pub async fn call_structured(
+    prompt: &str,
+    system: &str,
+    config: &Config,
+    llm: &LlmConfig,
+    tool: &OutputTool,
+) -> Result<serde_json::Value> {
+    structured_output(send(&[Message::user(prompt)], system, config, llm, Some(tool)).await?, tool)
+}
+
+/// A second turn on a `call_structured` exchange: `first` is the model's earlier answer to
+/// `prompt`, and `feedback` asks for a revised one, recorded with `tool` again.
+pub async fn refine_structured(
+    prompt: &str,
+    first: &serde_json::Value,
+    feedback: &str,
+    system: &str,
+    config: &Config,
+    llm: &LlmConfig,
+    tool: &OutputTool,
+) -> Result<serde_json::Value> {
+    let messages = [
+        Message::user(prompt),
+        Message::assistant(&serde_json::to_string(first)?),
+        Message::user(&format!(
+            "{}\n\nRecord the complete revised result with the {} tool, keeping every item that had no problem.",
+            feedback, tool.name
+        )),
+    ];
+    structured_output(send(&messages, system, config, llm, Some(tool)).await?, tool)
+}
+
+/// The input of the forced tool call, or JSON scraped from the text.
+fn structured_output(response: MessageResponse, tool: &OutputTool) -> Result<serde_json::Value> {
+    if let Some(input) = response.content.iter().find_map(|b| b.input.clone()) {
+        return Ok(input);
+    }
+    let text = response.content.into_iter().find_map(|b| b.text).context("No text in LLM response")?;
+    json_util::parse_lenient(&text).with_context(|| format!("No {} tool call in response", tool.name))
+}
+
+async fn send(
+    messages: &[Message],
+    system: &str,
+    config: &Config,
+    llm: &LlmConfig,
+    tool: Option<&OutputTool>,
+) -> Result<MessageResponse> {
+    if LLM_DISABLED.load(Ordering::Relaxed) {
+        anyhow::bail!("LLM calls are disabled in CI mode.");
+    }
+
     // Check if we have a direct API key (non-empty)
     let api_key = std::env::var("ANTHROPIC_API_KEY").unwrap_or_default();
 
+    let model = &config.consolidation.model;
+    let prompt = &messages.iter().map(|m| m.content.as_str()).collect::<Vec<_>>().join("\n\n");
     if !api_key.is_empty() {
-        call_direct_api(prompt, system, config, &api_key).await
-    } else if resolve_aws_credentials().is_some() {
-        call_bedrock(prompt, system, config).await
-    } else {
-        anyhow::bail!(
-            "No LLM credentials found. Set ANTHROPIC_API_KEY for direct API, \
-             or AWS credentials (env vars or ~/.aws/credentials) for Bedrock. \
-             Run `cortex sleep --micro` for LLM-free consolidation."
-        )
-    }
-}
-
-/// AWS credential triple
-struct AwsCreds {
-    access_key: String,
-    secret_key: String,
-    session_token: Option<String>,
-}
-
-/// Resolve AWS credentials from env vars or ~/.aws/credentials file
-fn resolve_aws_credentials() -> Option<AwsCreds> {
-    // Try env vars first
-    if let (Ok(ak), Ok(sk)) = (
-        std::env::var("AWS_ACCESS_KEY_ID"),
-        std::env::var("AWS_SECRET_ACCESS_KEY"),
-    ) {
-        if !ak.is_empty() && !sk.is_empty() {
-            return Some(AwsCreds {
-                access_key: ak,
-                secret_key: sk,
-                session_token: std::env::var("AWS_SESSION_TOKEN").ok().filter(|s| !s.is_empty()),
-            });
-        }
+        reserve_call()?;
+        let response = call_direct_api(messages, system, config, llm, tool, DirectAuth::ApiKey(&api_key)).await;
+        return record_call("anthropic", model, system, prompt, response);
     }
+    if let Some(token) = auth::access_token().await? {
+        reserve_call()?;
+        let response = call_direct_api(messages, system, config, llm, tool, DirectAuth::OAuth(&token)).await;
+        return record_call("anthropic-oauth", model, system, prompt, response);
+    }
+    #[cfg(feature = "aws")]
+    if aws::credentials_configured() {
+        reserve_call()?;
+        let response = call_bedrock(messages, system, config, llm, tool).await;
+        let model = model_registry::bedrock_model_id(config, model, &bedrock_region()).unwrap_or_else(|_| model.clone());
+        return record_call("bedrock", &model, system, prompt, response);
+    }
+    anyhow::bail!(
+        "No LLM credentials found. Set ANTHROPIC_API_KEY or run `cortex auth login` for direct API, \
+         or AWS credentials (env vars, ~/.aws/credentials, or an SSO/assume-role profile) for Bedrock. \
+         Run `cortex sleep --micro` for LLM-free consolidation."
+    )
+}
 
-    // Try ~/.aws/credentials file
-    let home = std::env::var("HOME").ok()?;
-    let creds_path = std::path::PathBuf::from(&home).join(".aws").join("credentials");
-    let content = std::fs::read_to_string(&creds_path).ok()?;
+/// Add a sent call to the audit trail when it was made inside `audited`. Best-effort: a
+/// trail that can't be written never fails the call.
+fn record_call(
+    provider: &str,
+    model: &str,
+    system: &str,
+    prompt: &str,
+    response: Result<MessageResponse>,
+) -> Result<MessageResponse> {
+    audit_call(&audit::Call {
+        provider,
+        model,
+        system,
+        prompt,
+        usage: response.as_ref().ok().and_then(|r| r.usage.as_ref()).map(|u| (u.input_tokens, u.output_tokens)),
+        ok: response.is_ok(),
+    });
+    response
+}
 
-    let profile = std::env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string());
-    let section_header = format!("[{}]", profile);
+fn audit_call(call: &audit::Call) {
+    let Ok(Some((manifest, raw_db))) = MANIFEST.try_with(|m| m.raw_db.clone().map(|db| (m.clone(), db))) else {
+        return;
+    };
+    if let Err(e) = db::open_raw_db(&raw_db).and_then(|conn| audit::record(&conn, &manifest, call)) {
+        eprintln!("Warning: could not record LLM call in the audit trail: {}", e);
+    }
+}
 
-    let mut in_section = false;
-    let mut access_key = None;
-    let mut secret_key = None;
-    let mut session_token = None;
+/// Take one call from the budget, or fail if it is spent.
+fn reserve_call() -> Result<()> {
+    let reserved = CALLS_REMAINING.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| match n {
+        u64::MAX => Some(n),
+        _ => n.checked_sub(1),
+    });
+    if reserved.is_err() {
+        anyhow::bail!("LLM call budget exhausted.");
+    }
+    CALLS_MADE.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
 
-    for line in content.lines() {
-        let trimmed = line.trim();
-        if trimmed.starts_with('[') {
-            in_section = trimmed == section_header;
-            continue;
-        }
-        if !in_section {
-            continue;
-        }
-        // Handle both "key = value" and "export KEY=value" formats
-        let (key, value) = if let Some(rest) = trimmed.strip_prefix("export ") {
-            if let Some((k, v)) = rest.split_once('=') {
-                (k.trim().to_lowercase(), v.trim().to_string())
-            } else {
-                continue;
-            }
-        } else if let Some((k, v)) = trimmed.split_once('=') {
-            (k.trim().to_lowercase(), v.trim().to_string())
-        } else {
-            continue;
+/// Outcome of pinging one configured provider (`cortex auth check`).
+pub struct ProviderCheck {
+    pub provider: &'static str,
+    pub active: bool,
+    pub model: String,
+    pub result: Result<std::time::Duration>,
+}
+
+/// Send a tiny request through every configured provider, in the order `send`
+/// would try them. The first configured provider is the active one.
+pub async fn check_providers(config: &Config) -> Vec<ProviderCheck> {
+    const PROMPT: &str = "Reply with the single word: ok";
+    const SYSTEM: &str = "You are a connectivity check.";
+
+    let mut checks = Vec::new();
+    let mut record = |provider, model: String, result: Result<std::time::Duration>| {
+        let active = checks.is_empty();
+        checks.push(ProviderCheck { provider, active, model, result });
+    };
+
+    let api_key = std::env::var("ANTHROPIC_API_KEY").unwrap_or_default();
+    if !api_key.is_empty() {
+        let start = std::time::Instant::now();
+        let result = call_direct_api(&[Message::user(PROMPT)], SYSTEM, config, &config.llm, None, DirectAuth::ApiKey(&api_key)).await;
+        record("direct API (ANTHROPIC_API_KEY)", config.consolidation.model.clone(), result.map(|_| start.elapsed()));
+    }
+    if auth::load_tokens().is_some() {
+        let start = std::time::Instant::now();
+        let result = match auth::access_token().await {
+            Ok(Some(token)) => call_direct_api(&[Message::user(PROMPT)], SYSTEM, config, &config.llm, None, DirectAuth::OAuth(&token)).await,
+            Ok(None) => Err(anyhow::anyhow!("not logged in")),
+            Err(e) => Err(e),
         };
+        record("direct API (OAuth)", config.consolidation.model.clone(), result.map(|_| start.elapsed()));
+    }
+    #[cfg(feature = "aws")]
+    if aws::credentials_configured() {
+        let start = std::time::Instant::now();
+        let result = call_bedrock(&[Message::user(PROMPT)], SYSTEM, config, &config.llm, None).await;
+        let model = model_registry::bedrock_model_id(config, &config.consolidation.model, &bedrock_region())
+            .unwrap_or_else(|_| config.consolidation.model.clone());
+        record("Bedrock", model, result.map(|_| start.elapsed()));
+    }
+    checks
+}
 
-        match key.as_str() {
-            "aws_access_key_id" => access_key = Some(value),
-            "aws_secret_access_key" => secret_key = Some(value),
-            "aws_session_token" => session_token = Some(value),
-            _ => {}
-        }
+fn http_client(llm: &LlmConfig) -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(llm.timeout_secs))
+        .build()?)
+}
+
+/// How to authenticate to the Anthropic API directly.
+enum DirectAuth<'a> {
+    ApiKey(&'a str),
+    /// claude.ai subscription token from `cortex auth login`
+    OAuth(&'a str),
+}
+
+impl DirectAuth<'_> {
+    /// A request to `path` under the API base URL (e.g. `/v1/messages`), with credential and
+    /// version headers.
+    fn request(&self, client: &reqwest::Client, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.request_url(client, method, &format!("{}{}", anthropic_base_url(), path))
     }
 
-    match (access_key, secret_key) {
-        (Some(ak), Some(sk)) if !ak.is_empty() && !sk.is_empty() => Some(AwsCreds {
-            access_key: ak,
-            secret_key: sk,
-            session_token,
-        }),
-        _ => None,
+    fn request_url(&self, client: &reqwest::Client, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        let req = client.request(method, url);
+        let req = match self {
+            DirectAuth::ApiKey(key) => req.header("x-api-key", *key),
+            DirectAuth::OAuth(token) => req.bearer_auth(token).header("anthropic-beta", auth::OAUTH_BETA),
+        };
+        req.header("anthropic-version", "2023-06-01")
     }
 }
 
-async fn call_direct_api(prompt: &str, system: &str, config: &Config, api_key: &str) -> Result<String> {
-    let base_url = std::env::var("ANTHROPIC_BASE_URL")
-        .unwrap_or_else(|_| "https://api.anthropic.com".to_string());
+fn anthropic_base_url() -> String {
+    std::env::var("ANTHROPIC_BASE_URL").unwrap_or_else(|_| "https://api.anthropic.com".to_string())
+}
+
+/// `[llm] stop_sequences`, except when a tool call is forced: the reply is then the tool's
+/// JSON, which a stop sequence would cut short.
+fn stop_sequences(llm: &LlmConfig, tool: Option<&OutputTool>) -> Vec<String> {
+    if tool.is_some() { Vec::new() } else { llm.stop_sequences.clone() }
+}
 
-    let client = reqwest::Client::new();
-    let body = MessageRequest {
+fn message_request(
+    messages: &[Message],
+    system: &str,
+    config: &Config,
+    llm: &LlmConfig,
+    tool: Option<&OutputTool>,
+) -> MessageRequest {
+    let (tools, tool_choice) = OutputTool::request_fields(tool);
+    MessageRequest {
         model: config.consolidation.model.clone(),
-        max_tokens: 8192,
+        max_tokens: llm.max_tokens,
+        temperature: llm.temperature,
+        top_p: llm.top_p,
+        stop_sequences: stop_sequences(llm, tool),
+        tools,
+        tool_choice,
         system: system.to_string(),
-        messages: vec![Message {
-            role: "user".to_string(),
-            content: prompt.to_string(),
-        }],
-    };
+        messages: messages.to_vec(),
+    }
+}
 
-    let resp = client
-        .post(format!("{}/v1/messages", base_url))
-        .header("x-api-key", api_key)
-        .header("anthropic-version", "2023-06-01")
+async fn call_direct_api(
+    messages: &[Message],
+    system: &str,
+    config: &Config,
+    llm: &LlmConfig,
+    tool: Option<&OutputTool>,
+    auth: DirectAuth<'_>,
+) -> Result<MessageResponse> {
+    let client = http_client(llm)?;
+    let body = message_request(messages, system, config, llm, tool);
+    let resp = auth
+        .request(&client, reqwest::Method::POST, "/v1/messages")
         .header("content-type", "application/json")
         .json(&body)
         .send()
@@ -217,36 +689,188 @@ async fn call_direct_api(prompt: &str, system: &str, config: &Config, api_key: &
         anyhow::bail!("Anthropic API error ({}): {}", status, text);
     }
 
-    let response: MessageResponse = resp.json().await.context("Failed to parse Anthropic response")?;
-    response
-        .content
-        .into_iter()
-        .find_map(|b| b.text)
-        .context("No text in Anthropic response")
+    resp.json().await.context("Failed to parse Anthropic response")
+}
+
+/// A Message Batches API batch, as returned on submit and status checks.
+#[derive(Deserialize)]
+struct BatchInfo {
+    id: String,
+    /// `in_progress`, `canceling`, or `ended`
+    processing_status: String,
+    #[serde(default)]
+    results_url: Option<String>,
+}
+
+/// One line of a batch's JSONL results.
+#[derive(Deserialize)]
+struct BatchResultLine {
+    custom_id: String,
+    result: BatchResult,
+}
+
+#[derive(Deserialize)]
+struct BatchResult {
+    /// `succeeded`, `errored`, `canceled`, or `expired`
+    r#type: String,
+    message: Option<MessageResponse>,
+    error: Option<serde_json::Value>,
+}
+
+/// Where a submitted batch request stands.
+#[derive(Debug)]
+pub enum BatchOutcome {
+    /// Still processing; collect again later
+    Pending,
+    /// The tool input, as `call_structured` returns it
+    Done(serde_json::Value),
+    /// The request errored, expired, or was canceled
+    Failed(String),
+}
+
+/// The API key batch requests authenticate with. Batches go through the direct API only.
+fn batch_api_key() -> Result<String> {
+    match std::env::var("ANTHROPIC_API_KEY") {
+        Ok(key) if !key.is_empty() => Ok(key),
+        _ => anyhow::bail!("The batch API needs ANTHROPIC_API_KEY; it is not available with OAuth or Bedrock."),
+    }
 }
 
-async fn call_bedrock(prompt: &str, system: &str, config: &Config) -> Result<String> {
-    let region = std::env::var("AWS_REGION")
+/// Submit one prompt through the Message Batches API, forcing a call to `tool` as
+/// `call_structured` does. Returns the batch ID for `batch_outcome`. Batches cost half as much
+/// as regular calls and usually finish within the hour.
+pub async fn submit_batch(
+    custom_id: &str,
+    prompt: &str,
+    system: &str,
+    config: &Config,
+    llm: &LlmConfig,
+    tool: &OutputTool,
+) -> Result<String> {
+    if LLM_DISABLED.load(Ordering::Relaxed) {
+        anyhow::bail!("LLM calls are disabled in CI mode.");
+    }
+    let api_key = batch_api_key()?;
+    reserve_call()?;
+    let client = http_client(llm)?;
+    let body = serde_json::json!({
+        "requests": [{ "custom_id": custom_id, "params": message_request(&[Message::user(prompt)], system, config, llm, Some(tool)) }]
+    });
+    let result = async {
+        let resp = DirectAuth::ApiKey(&api_key)
+            .request(&client, reqwest::Method::POST, "/v1/messages/batches")
+            .header("content-type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to submit batch")?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Anthropic API error ({}): {}", status, text);
+        }
+        let batch: BatchInfo = resp.json().await.context("Failed to parse batch response")?;
+        Ok(batch.id)
+    }
+    .await;
+    audit_call(&audit::Call {
+        provider: "anthropic-batch",
+        model: &config.consolidation.model,
+        system,
+        prompt,
+        usage: None,
+        ok: result.is_ok(),
+    });
+    result
+}
+
+/// Check on batch `batch_id` and, once it has ended, fetch the result of `custom_id`.
+pub async fn batch_outcome(batch_id: &str, custom_id: &str, config: &Config, tool: &OutputTool) -> Result<BatchOutcome> {
+    let auth = batch_api_key()?;
+    let auth = DirectAuth::ApiKey(&auth);
+    let client = http_client(&config.llm)?;
+    let resp = auth
+        .request(&client, reqwest::Method::GET, &format!("/v1/messages/batches/{}", batch_id))
+        .send()
+        .await
+        .context("Failed to check batch")?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Anthropic API error ({}): {}", status, text);
+    }
+    let batch: BatchInfo = resp.json().await.context("Failed to parse batch response")?;
+    if batch.processing_status != "ended" {
+        return Ok(BatchOutcome::Pending);
+    }
+    let url = batch.results_url.context("Batch ended without a results URL")?;
+    let resp = auth
+        .request_url(&client, reqwest::Method::GET, &url)
+        .send()
+        .await
+        .context("Failed to fetch batch results")?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+        anyhow::bail!("Anthropic API error ({}): {}", status, text);
+    }
+    Ok(parse_batch_results(&resp.text().await?, custom_id, tool))
+}
+
+/// The outcome of `custom_id` in a batch's JSONL results.
+fn parse_batch_results(jsonl: &str, custom_id: &str, tool: &OutputTool) -> BatchOutcome {
+    let line = jsonl
+        .lines()
+        .filter_map(|l| serde_json::from_str::<BatchResultLine>(l).ok())
+        .find(|l| l.custom_id == custom_id);
+    let Some(line) = line else {
+        return BatchOutcome::Failed(format!("no result for `{}` in the batch", custom_id));
+    };
+    match (line.result.r#type.as_str(), line.result.message) {
+        ("succeeded", Some(message)) => match structured_output(message, tool) {
+            Ok(value) => BatchOutcome::Done(value),
+            Err(e) => BatchOutcome::Failed(e.to_string()),
+        },
+        (kind, _) => BatchOutcome::Failed(match line.result.error {
+            Some(error) => format!("{}: {}", kind, error),
+            None => kind.to_string(),
+        }),
+    }
+}
+
+#[cfg(feature = "aws")]
+fn bedrock_region() -> String {
+    std::env::var("AWS_REGION")
         .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
-        .unwrap_or_else(|_| "us-west-2".to_string());
+        .unwrap_or_else(|_| "us-west-2".to_string())
+}
 
-    let creds = resolve_aws_credentials()
-        .context("No AWS credentials found in env vars or ~/.aws/credentials")?;
-    let access_key = creds.access_key;
-    let secret_key = creds.secret_key;
-    let session_token = creds.session_token;
+#[cfg(feature = "aws")]
+async fn call_bedrock(
+    messages: &[Message],
+    system: &str,
+    config: &Config,
+    llm: &LlmConfig,
+    tool: Option<&OutputTool>,
+) -> Result<MessageResponse> {
+    let region = bedrock_region();
+
+    let creds = aws::resolve_credentials().await?;
 
     // Map model name to Bedrock model ID
-    let model_id = bedrock_model_id(&config.consolidation.model);
+    let model_id = model_registry::bedrock_model_id(config, &config.consolidation.model, &region)?;
 
+    let (tools, tool_choice) = OutputTool::request_fields(tool);
     let body = BedrockRequest {
         anthropic_version: "bedrock-2023-05-31".to_string(),
-        max_tokens: 8192,
+        max_tokens: llm.max_tokens,
+        temperature: llm.temperature,
+        top_p: llm.top_p,
+        stop_sequences: stop_sequences(llm, tool),
+        tools,
+        tool_choice,
         system: system.to_string(),
-        messages: vec![Message {
-            role: "user".to_string(),
-            content: prompt.to_string(),
-        }],
+        messages: messages.to_vec(),
     };
 
     let body_bytes = serde_json::to_vec(&body)?;
@@ -254,59 +878,30 @@ async fn call_bedrock(prompt: &str, system: &str, config: &Config) -> Result<Str
     // URL uses the raw model ID — reqwest handles encoding in the HTTP request
     let url = format!("https://{}/model/{}/invoke", host, model_id);
 
-    // AWS SigV4 signing
-    let now = chrono::Utc::now();
-    let date_stamp = now.format("%Y%m%d").to_string();
-    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
-
     // Canonical URI must use percent-encoded path segments per SigV4 spec
-    let encoded_model_id = uri_encode(&model_id);
-    let canonical_uri = format!("/model/{}/invoke", encoded_model_id);
-    let canonical_querystring = "";
-
-    let payload_hash = sha256_hex(&body_bytes);
-
-    let mut canonical_headers = format!(
-        "content-type:application/json\nhost:{}\nx-amz-date:{}\n",
-        host, amz_date
-    );
-    let mut signed_headers = "content-type;host;x-amz-date".to_string();
-
-    if let Some(ref token) = session_token {
-        canonical_headers = format!(
-            "content-type:application/json\nhost:{}\nx-amz-date:{}\nx-amz-security-token:{}\n",
-            host, amz_date, token
-        );
-        signed_headers = "content-type;host;x-amz-date;x-amz-security-token".to_string();
-    }
-
-    let canonical_request = format!(
-        "POST\n{}\n{}\n{}\n{}\n{}",
-        canonical_uri, canonical_querystring, canonical_headers, signed_headers, payload_hash
+    let canonical_uri = format!("/model/{}/invoke", aws::uri_encode(&model_id));
+    let signature = aws::sign(
+        &creds,
+        &region,
+        "bedrock",
+        &aws::Request {
+            method: "POST",
+            canonical_uri: &canonical_uri,
+            canonical_query: "",
+            headers: &[("content-type", "application/json"), ("host", &host)],
+            payload: &body_bytes,
+        },
+        chrono::Utc::now(),
     );
 
-    let credential_scope = format!("{}/{}/bedrock/aws4_request", date_stamp, region);
-    let string_to_sign = format!(
-        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
-        amz_date, credential_scope, sha256_hex(canonical_request.as_bytes())
-    );
-
-    let signing_key = get_signature_key(&secret_key, &date_stamp, &region, "bedrock");
-    let signature = hmac_sha256_hex(&signing_key, string_to_sign.as_bytes());
-
-    let authorization = format!(
-        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
-        access_key, credential_scope, signed_headers, signature
-    );
-
-    let client = reqwest::Client::new();
+    let client = http_client(llm)?;
     let mut req = client
         .post(&url)
         .header("content-type", "application/json")
-        .header("x-amz-date", &amz_date)
-        .header("authorization", &authorization);
+        .header("x-amz-date", &signature.amz_date)
+        .header("authorization", &signature.authorization);
 
-    if let Some(ref token) = session_token {
+    if let Some(ref token) = signature.security_token {
         req = req.header("x-amz-security-token", token);
     }
 
@@ -322,189 +917,43 @@ async fn call_bedrock(prompt: &str, system: &str, config: &Config) -> Result<Str
         anyhow::bail!("Bedrock API error ({}): {}", status, text);
     }
 
-    let response: MessageResponse = resp.json().await.context("Failed to parse Bedrock response")?;
-    response
-        .content
-        .into_iter()
-        .find_map(|b| b.text)
-        .context("No text in Bedrock response")
-}
-
-fn bedrock_model_id(model: &str) -> String {
-    // If it already looks like a full Bedrock inference profile ID, use as-is
-    if model.starts_with("us.anthropic.") || model.starts_with("eu.anthropic.") {
-        return model.to_string();
-    }
-    // If it's a direct model ID (anthropic.*), convert to cross-region inference profile
-    if model.starts_with("anthropic.") {
-        return format!("us.{}", model);
-    }
-    // Map common short names to cross-region inference profile IDs
-    match model {
-        "claude-haiku-4-5" | "claude-haiku-4-5-20241022" | "claude-haiku-4-5-20251001" => {
-            "us.anthropic.claude-haiku-4-5-20251001-v1:0".to_string()
-        }
-        "claude-sonnet-4-5" | "claude-sonnet-4-5-20250929" => {
-            "us.anthropic.claude-sonnet-4-5-20250929-v1:0".to_string()
-        }
-        "claude-sonnet-4" | "claude-sonnet-4-20250514" => {
-            "us.anthropic.claude-sonnet-4-20250514-v1:0".to_string()
-        }
-        "claude-3-5-haiku" | "claude-3-5-haiku-20241022" => {
-            "us.anthropic.claude-3-5-haiku-20241022-v1:0".to_string()
-        }
-        "claude-3-5-sonnet" | "claude-3-5-sonnet-20241022" => {
-            "us.anthropic.claude-3-5-sonnet-20241022-v2:0".to_string()
-        }
-        _ => format!("us.anthropic.{}-v1:0", model),
-    }
+    resp.json().await.context("Failed to parse Bedrock response")
 }
 
-// --- AWS SigV4 helpers ---
-
-/// URI-encode a path segment per AWS SigV4 rules (encode everything except unreserved chars)
-fn uri_encode(input: &str) -> String {
-    let mut encoded = String::with_capacity(input.len() * 2);
-    for byte in input.bytes() {
-        match byte {
-            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'~' | b'.' => {
-                encoded.push(byte as char);
-            }
-            _ => {
-                encoded.push_str(&format!("%{:02X}", byte));
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_batch_results_by_custom_id() {
+        let tool = OutputTool { name: "record", description: "", schema: serde_json::json!({}) };
+        let jsonl = r#"{"custom_id":"dream","result":{"type":"errored","error":{"type":"overloaded_error"}}}
+{"custom_id":"sleep","result":{"type":"succeeded","message":{"content":[{"type":"tool_use","input":{"promotions":[4]}}]}}}
+"#;
+        match parse_batch_results(jsonl, "sleep", &tool) {
+            BatchOutcome::Done(v) => assert_eq!(v["promotions"][0], 4),
+            other => panic!("unexpected {:?}", other),
         }
+        assert!(matches!(parse_batch_results(jsonl, "dream", &tool), BatchOutcome::Failed(e) if e.contains("overloaded")));
+        assert!(matches!(parse_batch_results(jsonl, "skills", &tool), BatchOutcome::Failed(_)));
     }
-    encoded
-}
 
-fn sha256_hex(data: &[u8]) -> String {
-    use std::fmt::Write;
-    let digest = sha256(data);
-    let mut s = String::with_capacity(64);
-    for byte in &digest {
-        write!(s, "{:02x}", byte).unwrap();
-    }
-    s
-}
-
-fn sha256(data: &[u8]) -> [u8; 32] {
-    // Minimal SHA-256 implementation using ring-like approach
-    // We'll use the hmac helper with a simple hash
-    // Actually, let's use a basic implementation
-    sha256_impl(data)
-}
-
-fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
-    let block_size = 64;
-    let mut ikey = vec![0x36u8; block_size];
-    let mut okey = vec![0x5cu8; block_size];
-
-    let key = if key.len() > block_size {
-        sha256(key).to_vec()
-    } else {
-        key.to_vec()
-    };
-
-    for (i, &b) in key.iter().enumerate() {
-        ikey[i] ^= b;
-        okey[i] ^= b;
-    }
-
-    let mut inner = ikey;
-    inner.extend_from_slice(data);
-    let inner_hash = sha256(&inner);
-
-    let mut outer = okey;
-    outer.extend_from_slice(&inner_hash);
-    sha256(&outer)
-}
-
-fn hmac_sha256_hex(key: &[u8], data: &[u8]) -> String {
-    let hash = hmac_sha256(key, data);
-    let mut s = String::with_capacity(64);
-    for byte in &hash {
-        use std::fmt::Write;
-        write!(s, "{:02x}", byte).unwrap();
-    }
-    s
-}
-
-fn get_signature_key(key: &str, date_stamp: &str, region: &str, service: &str) -> Vec<u8> {
-    let k_date = hmac_sha256(format!("AWS4{}", key).as_bytes(), date_stamp.as_bytes());
-    let k_region = hmac_sha256(&k_date, region.as_bytes());
-    let k_service = hmac_sha256(&k_region, service.as_bytes());
-    hmac_sha256(&k_service, b"aws4_request").to_vec()
-}
-
-// Minimal SHA-256 implementation (no external dependency)
-fn sha256_impl(data: &[u8]) -> [u8; 32] {
-    let h: [u32; 8] = [
-        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
-        0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
-    ];
-    let k: [u32; 64] = [
-        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
-        0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
-        0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
-        0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
-        0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
-        0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
-        0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
-        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
-    ];
-
-    let bit_len = (data.len() as u64) * 8;
-    let mut msg = data.to_vec();
-    msg.push(0x80);
-    while (msg.len() % 64) != 56 {
-        msg.push(0);
-    }
-    msg.extend_from_slice(&bit_len.to_be_bytes());
-
-    let mut hash = h;
-
-    for chunk in msg.chunks(64) {
-        let mut w = [0u32; 64];
-        for i in 0..16 {
-            w[i] = u32::from_be_bytes([chunk[i*4], chunk[i*4+1], chunk[i*4+2], chunk[i*4+3]]);
-        }
-        for i in 16..64 {
-            let s0 = w[i-15].rotate_right(7) ^ w[i-15].rotate_right(18) ^ (w[i-15] >> 3);
-            let s1 = w[i-2].rotate_right(17) ^ w[i-2].rotate_right(19) ^ (w[i-2] >> 10);
-            w[i] = w[i-16].wrapping_add(s0).wrapping_add(w[i-7]).wrapping_add(s1);
-        }
-
-        let (mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh) =
-            (hash[0], hash[1], hash[2], hash[3], hash[4], hash[5], hash[6], hash[7]);
-
-        for i in 0..64 {
-            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
-            let ch = (e & f) ^ ((!e) & g);
-            let temp1 = hh.wrapping_add(s1).wrapping_add(ch).wrapping_add(k[i]).wrapping_add(w[i]);
-            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
-            let maj = (a & b) ^ (a & c) ^ (b & c);
-            let temp2 = s0.wrapping_add(maj);
-
-            hh = g; g = f; f = e;
-            e = d.wrapping_add(temp1);
-            d = c; c = b; b = a;
-            a = temp1.wrapping_add(temp2);
-        }
-
-        hash[0] = hash[0].wrapping_add(a);
-        hash[1] = hash[1].wrapping_add(b);
-        hash[2] = hash[2].wrapping_add(c);
-        hash[3] = hash[3].wrapping_add(d);
-        hash[4] = hash[4].wrapping_add(e);
-        hash[5] = hash[5].wrapping_add(f);
-        hash[6] = hash[6].wrapping_add(g);
-        hash[7] = hash[7].wrapping_add(hh);
-    }
-
-    let mut result = [0u8; 32];
-    for (i, &val) in hash.iter().enumerate() {
-        result[i*4..i*4+4].copy_from_slice(&val.to_be_bytes());
+    #[test]
+    fn forced_tool_calls_drop_stop_sequences() {
+        let tool = OutputTool { name: "record", description: "", schema: serde_json::json!({}) };
+        let llm = LlmConfig { stop_sequences: vec!["}".into()], ..LlmConfig::default() };
+        let config = Config::default();
+        assert!(message_request(&[], "", &config, &llm, Some(&tool)).stop_sequences.is_empty());
+        assert_eq!(message_request(&[], "", &config, &llm, None).stop_sequences, ["}"]);
+
+        let both = LlmConfig { temperature: Some(0.2), top_p: Some(0.9), ..LlmConfig::default() };
+        assert!(both.validate().is_err());
+        let dream = LlmConfig {
+            temperature: Some(0.2),
+            dream: Some(crate::config::LlmOverrides { top_p: Some(0.9), ..Default::default() }),
+            ..LlmConfig::default()
+        };
+        assert!(dream.validate().unwrap_err().to_string().contains("[llm.dream]"));
+        assert!(LlmConfig { temperature: Some(0.2), ..LlmConfig::default() }.validate().is_ok());
     }
-    result
 }
@@ -0,0 +1,111 @@
+use anyhow::Result;
+use std::fs::{File, TryLockError};
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// Returned (inside `anyhow::Error`) when another process holds the store's lock, so callers
+/// can skip fallbacks instead of racing it.
+#[derive(Debug)]
+pub struct LockBusy {
+    pub path: PathBuf,
+    pub holder: String,
+}
+
+impl std::fmt::Display for LockBusy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Another consolidation is running on this store ({}).", self.holder)
+    }
+}
+
+impl std::error::Error for LockBusy {}
+
+/// Lock on a store (`<cortex_dir>/consolidation.lock`) held for the duration of a sleep or
+/// dream. The operating system releases it when the holder exits, however it exits, so a lock
+/// is never stale; the file only records who holds it.
+pub struct StoreLock {
+    file: File,
+}
+
+impl StoreLock {
+    pub fn acquire(cortex_dir: &Path, action: &str) -> Result<Self> {
+        let path = cortex_dir.join("consolidation.lock");
+        // Never truncated before the lock is ours, so a waiting process can't erase the holder
+        let mut file = std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let holder = describe(&std::fs::read_to_string(&path).unwrap_or_default());
+                return Err(LockBusy { path, holder }.into());
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{} {} {}", std::process::id(), chrono::Utc::now().timestamp(), action)?;
+        Ok(Self { file })
+    }
+}
+
+/// Who holds the store's lock, if anyone, e.g. `sleep by pid 123 since ...`.
+pub fn holder(cortex_dir: &Path) -> Option<String> {
+    let path = cortex_dir.join("consolidation.lock");
+    let file = File::open(&path).ok()?;
+    match file.try_lock_shared() {
+        Ok(()) => None,
+        Err(_) => Some(describe(&std::fs::read_to_string(&path).unwrap_or_default())),
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        // The file stays, so nobody can lock a path another process already has open; closing
+        // it releases the lock
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// Lock contents are `<pid> <unix time> <action>`.
+fn parse(contents: &str) -> Option<(u32, i64, &str)> {
+    let mut parts = contents.trim().splitn(3, ' ');
+    let pid = parts.next()?.parse().ok()?;
+    let started = parts.next()?.parse().ok()?;
+    Some((pid, started, parts.next().unwrap_or("consolidation")))
+}
+
+fn describe(contents: &str) -> String {
+    match parse(contents) {
+        Some((pid, started, action)) => {
+            let when = chrono::DateTime::from_timestamp(started, 0).unwrap_or_default();
+            format!("{} by pid {} since {}", action, pid, when.format("%Y-%m-%d %H:%M:%S UTC"))
+        }
+        None => "unreadable lock file".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contends_releases_and_ignores_leftover_files() {
+        let dir = std::env::temp_dir().join(format!("cortex-lock-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("consolidation.lock");
+
+        let lock = StoreLock::acquire(&dir, "sleep").unwrap();
+        let busy = StoreLock::acquire(&dir, "dream").err().unwrap();
+        assert!(busy.downcast_ref::<LockBusy>().is_some());
+        assert!(holder(&dir).unwrap().starts_with("sleep by pid"));
+        assert!(StoreLock::acquire(&dir, "dream").is_err(), "checking the holder doesn't release it");
+        drop(lock);
+        assert!(holder(&dir).is_none());
+
+        // A file left by a killed process, or an older cortex, holds nothing
+        std::fs::write(&path, format!("{} {} sleep", std::process::id(), chrono::Utc::now().timestamp())).unwrap();
+        assert!(holder(&dir).is_none());
+        let lock = StoreLock::acquire(&dir, "dream").unwrap();
+        assert!(holder(&dir).unwrap().starts_with("dream by pid"));
+        drop(lock);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,18 +1,17 @@
-mod config;
-mod context;
-mod db;
-mod dream;
-mod init;
-mod llm;
-mod mcp;
-mod models;
-mod skills;
-mod sleep;
-mod wake;
-
+#[cfg(feature = "aws")]
+use cortex::aws;
+use cortex::{
+    adr, antipattern, ask, audit, auth, batch, chat, classify, compress, config, context, contradiction, db, doctor, dream, editor, evaluate, expand, extensions, fallback, github, hooks,
+    init, insights, jsonl, llm, lock, mcp, mcp_install, merge, models, obsidian, outcome, oversize, patch, plugins, projects, quality, redact, refs, remote, reset, review, runs, schedule, sensitivity, similar, skills, sleep, snapshot, stats, store, suggest, templates,
+    timeline, trust, update, wake, why,
+};
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use audit::Manifest;
+use clap::{CommandFactory, Parser, Subcommand};
+use hooks::HookEvent;
+use sensitivity::Sensitivity;
 use std::path::PathBuf;
+use store::Store;
 
 #[derive(Parser)]
 #[command(name = "cortex", about = "Repo-local cognitive memory for AI agents")]
@@ -21,6 +20,24 @@ struct Cli {
     #[arg(long, global = true)]
     dir: Option<PathBuf>,
 
+    /// Refuse any command that would modify memory (save, sleep, dream, edit, delete)
+    #[arg(long, global = true)]
+    read_only: bool,
+
+    /// CI mode: never call LLMs, bound runtime, and emit JSON on stdout
+    #[arg(long, global = true)]
+    ci: bool,
+
+    /// Scheduled runs: refuse interactive prompts; sleep and dream write a summary to
+    /// `.cortex/runs/` and exit 0 (done), 3 (nothing to do), 4 (partial), or 1 (failed)
+    #[arg(long, global = true)]
+    unattended: bool,
+
+    /// Send save, recall, context, and sleep to a `cortex serve` store instead of local files,
+    /// e.g. https://cortex.internal/acme-repo (or set CORTEX_REMOTE; token from CORTEX_REMOTE_TOKEN)
+    #[arg(long, global = true, value_name = "URL")]
+    remote: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -30,17 +47,35 @@ enum Commands {
     /// Initialize .cortex/ in the current directory
     Init,
     /// Save a learning, decision, or pattern
-    Save {
-        /// What was learned or observed
-        content: String,
-        /// Type: bugfix, decision, pattern, preference, observation
-        #[arg(long, default_value = "observation")]
-        r#type: String,
-    },
+    #[command(visible_alias = "s")]
+    Save(SaveArgs),
     /// Search project memory
-    Recall {
-        /// Search query
-        query: String,
+    #[command(visible_alias = "r")]
+    Recall(RecallArgs),
+    /// Answer a question from project memory, citing the memories used
+    Ask {
+        /// Question, e.g. "why did we switch to tokio?"
+        question: String,
+        /// Max memories retrieved from each store
+        #[arg(long, default_value = "10")]
+        limit: usize,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Interactive chat over project memory; offers to save new facts you state
+    Chat,
+    /// Find memories similar to an existing one or to new text ("have we learned this before?")
+    Similar {
+        /// Raw memory ID (or consolidated, with --consolidated)
+        #[arg(required_unless_present = "text", conflicts_with = "text")]
+        id: Option<i64>,
+        /// Compare against this text instead, e.g. before saving it
+        #[arg(long)]
+        text: Option<String>,
+        /// The ID is a consolidated memory
+        #[arg(long, requires = "id")]
+        consolidated: bool,
         /// Max results
         #[arg(long, default_value = "10")]
         limit: usize,
@@ -48,12 +83,116 @@ enum Commands {
         #[arg(long)]
         json: bool,
     },
+    /// Type-ahead completions: memory titles and topics starting with the typed words
+    Suggest {
+        /// Words typed so far
+        prefix: String,
+        /// Max suggestions
+        #[arg(long, default_value = "10")]
+        limit: usize,
+        /// Output as JSON, with kind and score
+        #[arg(long)]
+        json: bool,
+    },
     /// Memory health statistics
     Stats {
+        /// Show global stats only
+        #[arg(long)]
+        global: bool,
+        #[command(flatten)]
+        args: StatsArgs,
+    },
+    /// Saves, sleeps, and dreams per week as an ASCII heatmap and timeline
+    Timeline {
+        /// Weeks to show, ending with the current one
+        #[arg(long, default_value = "8")]
+        weeks: u32,
         /// Output as JSON
         #[arg(long)]
         json: bool,
-        /// Show global stats only
+    },
+    /// Rebuild full-text indexes with the configured `[search] tokenizer`
+    Reindex {
+        /// Reindex the global ~/.cortex/ store instead
+        #[arg(long)]
+        global: bool,
+    },
+    /// Compress archived memories and large attachments per [storage], then reclaim the space
+    Compact {
+        /// Compact the global ~/.cortex/ store instead
+        #[arg(long)]
+        global: bool,
+    },
+    /// Write consolidated memories as a patch file, optionally only what changed since a snapshot or date, or the whole store as an Obsidian vault or JSON lines
+    Export {
+        /// `patch`, `obsidian` for interlinked notes of memories, consolidated memories, and skills, or `jsonl` for one record per line across all tables
+        #[arg(long, value_parser = ["patch", "obsidian", "jsonl"], default_value = "patch")]
+        format: String,
+        /// Flush each JSON line as it's written, for piping into other tools (jsonl only)
+        #[arg(long)]
+        stream: bool,
+        /// Replace file paths, emails, hostnames, IPs, and project, org, and user names with placeholders, for sharing examples publicly
+        #[arg(long)]
+        anonymize: bool,
+        /// Snapshot name or date (YYYY-MM-DD or RFC 3339); omit for every memory (patch only)
+        #[arg(long)]
+        since: Option<String>,
+        /// Write to this file instead of stdout; the vault directory for `--format obsidian`
+        #[arg(long, short, visible_alias = "out")]
+        output: Option<PathBuf>,
+        /// Most sensitive memories to include (defaults to [sensitivity] share_max)
+        #[arg(long)]
+        max_sensitivity: Option<Sensitivity>,
+    },
+    /// Apply a patch file written by `cortex export`
+    Import {
+        /// Patch file
+        #[arg(long)]
+        patch: PathBuf,
+    },
+    /// Merge another store's consolidated memories into this one
+    Merge {
+        /// The other store: a project directory or its .cortex directory
+        other: PathBuf,
+        /// Report what would happen without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Output the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Create, list, and restore point-in-time copies of both databases
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommands,
+        /// Use the global ~/.cortex/ store instead
+        #[arg(long, global = true)]
+        global: bool,
+    },
+    /// Check store integrity: SQLite health, FTS index sync, dangling source IDs
+    Doctor {
+        /// Repair what can be repaired (rebuild FTS indexes, drop dangling source IDs)
+        #[arg(long)]
+        fix: bool,
+        /// Check the global ~/.cortex/ store instead
+        #[arg(long)]
+        global: bool,
+    },
+    /// Review consolidated memories untouched for a while: confirm, refresh, or decay each
+    ReviewStale {
+        /// Days without an update before a memory is due for review
+        #[arg(long, default_value = "90")]
+        days: u32,
+        /// Max memories to review, riskiest first
+        #[arg(long, default_value = "20")]
+        limit: usize,
+        /// List the queue without reviewing
+        #[arg(long)]
+        list: bool,
+        /// List the queue as JSON
+        #[arg(long)]
+        json: bool,
+        /// Review the global ~/.cortex/ store instead
         #[arg(long)]
         global: bool,
     },
@@ -65,15 +204,43 @@ enum Commands {
         /// Quick sleep: LLM-powered consolidation (default)
         #[arg(long)]
         quick: bool,
+        /// Heuristic sleep: LLM-free clustering, near-duplicate merging, and promotion
+        #[arg(long, conflicts_with_all = ["micro", "quick"])]
+        heuristic: bool,
         /// Operate on global ~/.cortex/ store
         #[arg(long, short)]
         global: bool,
+        /// Running in GitHub Actions: tag memories with the PR/commit, write a job summary,
+        /// and commit the consolidated mirror if `[ci] commit` is set
+        #[arg(long, conflicts_with = "global")]
+        from_ci: bool,
+        /// Sleep every registered project store (see `cortex projects`), e.g. from a nightly job
+        #[arg(long, conflicts_with_all = ["global", "from_ci"])]
+        all_projects: bool,
+        /// Cap on LLM calls across all stores; once spent, remaining stores get a micro sleep
+        #[arg(long, requires = "all_projects")]
+        max_llm_calls: Option<u64>,
+        /// Submit the consolidation prompt through Anthropic's Message Batches API (half price,
+        /// results usually within the hour) and apply it later with --collect
+        #[arg(long, conflicts_with_all = ["micro", "heuristic", "from_ci", "all_projects"])]
+        batch_api: bool,
+        /// Apply the results of finished --batch-api submissions (sleep and dream)
+        #[arg(long, conflicts_with_all = ["micro", "heuristic", "from_ci", "all_projects", "batch_api"])]
+        collect: bool,
     },
     /// Deep reflection: cross-session pattern mining
     Dream {
         /// Operate on global ~/.cortex/ store
         #[arg(long, short)]
         global: bool,
+        /// Submit the pattern-mining prompt through Anthropic's Message Batches API and apply it
+        /// later with `cortex sleep --collect`
+        #[arg(long)]
+        batch_api: bool,
+        /// Mine memories sampled from every registered project store for cross-project patterns,
+        /// recorded in the global store
+        #[arg(long, conflicts_with_all = ["global", "batch_api"])]
+        all_projects: bool,
     },
     /// Edit a consolidated memory by ID
     Edit {
@@ -82,349 +249,2326 @@ enum Commands {
         /// New content for the memory
         content: String,
     },
+    /// Trace a consolidated memory back to the raw memories it came from
+    Why {
+        /// Consolidated memory ID (use negative IDs for global memories)
+        id: i64,
+        /// Also ask the LLM to reconstruct the reasoning from sources to memory
+        #[arg(long)]
+        reason: bool,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show a consolidated memory with its details and reported outcomes
+    Show {
+        /// Consolidated memory ID (use negative IDs for global memories)
+        id: i64,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Record whether applying a consolidated memory worked; the next sleep adjusts its confidence
+    #[command(group = clap::ArgGroup::new("verdict").required(true))]
+    Outcome {
+        /// Consolidated memory ID (use negative IDs for global memories)
+        id: i64,
+        /// Following it worked
+        #[arg(long, group = "verdict")]
+        worked: bool,
+        /// Following it didn't work
+        #[arg(long, group = "verdict")]
+        failed: bool,
+        /// What happened
+        #[arg(long, default_value = "")]
+        note: String,
+    },
+    /// Print the full text of a memory that was shortened on save
+    Attachment {
+        /// Raw memory ID
+        id: i64,
+    },
+    /// Clear raw memories, consolidated memories, or skills (snapshotted first)
+    Reset {
+        /// Clear raw memories
+        #[arg(long)]
+        raw: bool,
+        /// Clear consolidated memories
+        #[arg(long)]
+        consolidated: bool,
+        /// Clear skills
+        #[arg(long)]
+        skills: bool,
+        /// Clear all three
+        #[arg(long)]
+        all: bool,
+        /// Re-seed consolidated memories and skills from the committed copy at git HEAD
+        #[arg(long)]
+        from_git: bool,
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Remove .cortex/ and the .gitignore entries `cortex init` added
+    Uninit {
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
     /// Delete a consolidated memory by ID
     Delete {
         /// Consolidated memory ID to delete (use negative IDs for global memories)
         id: i64,
     },
     /// Session start: catch-up consolidation and context injection
-    Wake,
+    Wake {
+        /// For shell startup or direnv: a quick summary on stderr (no consolidation, no LLM)
+        /// and an export of CORTEX_SESSION_ID to eval (bash, zsh, fish)
+        #[arg(long, value_name = "SHELL")]
+        hook: Option<wake::HookShell>,
+        /// Consolidate in a detached `cortex sleep` and return context right away
+        #[arg(long, conflicts_with = "hook")]
+        background: bool,
+    },
     /// Output memory context for prompt injection
-    Context {
-        /// Compact single-line format
-        #[arg(long)]
-        compact: bool,
-        /// Optional search query to load only relevant memories
-        #[arg(short, long)]
-        query: Option<String>,
-        /// Max number of relevant memories to include (default: 15)
-        #[arg(short, long, default_value = "15")]
+    Context(ContextArgs),
+    /// Start MCP stdio server
+    Mcp {
+        #[command(subcommand)]
+        command: Option<McpCommands>,
+    },
+    /// Serve the project stores under a directory over HTTP for `--remote` clients
+    Serve {
+        /// Directory holding one project per store name (`<root>/<name>/.cortex/`)
+        root: PathBuf,
+        /// Address to listen on; anything but loopback requires CORTEX_SERVER_TOKEN
+        #[arg(long, default_value = remote::DEFAULT_ADDR)]
+        addr: String,
+        /// Print a new token for this project, which opens only its store, and exit
+        #[arg(long, value_name = "PROJECT")]
+        issue_token: Option<String>,
+    },
+    /// List quarantined memories, or approve them so they appear in recall and context; with --plugins, --hooks or --db, let this project run its own code or pick its remote
+    Trust {
+        /// Raw memory IDs to approve (lists untrusted memories when omitted)
+        ids: Vec<i64>,
+        /// Approve every untrusted memory
+        #[arg(long, conflicts_with = "ids")]
+        all: bool,
+        /// Let this project's .cortex/plugins/ run (recorded in ~/.cortex/trusted.json, never in the repo)
+        #[arg(long, conflicts_with_all = ["ids", "all"])]
+        plugins: bool,
+        /// Let this project's [hooks] commands and URLs run
+        #[arg(long, conflicts_with_all = ["ids", "all"])]
+        hooks: bool,
+        /// Let this project's [db] backend and url choose where its memories are synced
+        #[arg(long, conflicts_with_all = ["ids", "all"])]
+        db: bool,
+        /// With --plugins, --hooks or --db, withdraw the trust instead
+        #[arg(long)]
+        revoke: bool,
+        /// Output the list as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Set the sensitivity of memories (public, internal, or secret)
+    Label {
+        /// public, internal, or secret
+        sensitivity: Sensitivity,
+        /// Memory IDs (raw, or consolidated with --consolidated)
+        #[arg(required = true)]
+        ids: Vec<i64>,
+        /// The IDs are consolidated memories
+        #[arg(long)]
+        consolidated: bool,
+    },
+    /// Show which memories were sent to LLM calls, and when
+    Audit {
+        /// Only calls from the last N days
+        #[arg(long)]
+        days: Option<i64>,
+        /// Only calls that included this memory (`12` or `m12` raw, `c12` consolidated)
+        #[arg(long)]
+        memory: Option<String>,
+        /// Only calls for this purpose (save, summarize, sleep, dream, skills, contradiction, ask, chat, why)
+        #[arg(long)]
+        purpose: Option<String>,
+        /// Maximum number of calls to show
+        #[arg(long, default_value = "50")]
+        limit: usize,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show consolidations the hallucination guard rejected or down-ranked
+    Rejections {
+        /// Maximum number to show (0 for all)
+        #[arg(long, default_value = "50")]
         limit: usize,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Local recall analytics: zero-hit queries, most and never recalled memories (`[insights] enabled`)
+    Insights {
+        /// Look back this many days
+        #[arg(long, default_value = "30")]
+        days: u32,
+        /// Entries per list
+        #[arg(long, default_value = "10")]
+        limit: usize,
+        /// Delete the query log
+        #[arg(long)]
+        clear: bool,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Save, search, list, and maintain the global ~/.cortex/ store
+    Global {
+        #[command(subcommand)]
+        command: GlobalCommands,
+    },
+    /// List every known cortex store with counts, last sleep, and size
+    Projects {
+        /// Drop stores whose directory no longer exists from the registry
+        #[arg(long)]
+        prune_missing: bool,
+        /// Run catch-up consolidation in every store that has unconsolidated memories
+        #[arg(long)]
+        sleep_all: bool,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Install, inspect, or remove scheduled sleep and dream runs (launchd, systemd, or cron)
+    Schedule {
+        #[command(subcommand)]
+        command: ScheduleCommands,
+    },
+    /// Sync decisions with Architecture Decision Record files
+    Adr {
+        #[command(subcommand)]
+        command: AdrCommands,
+    },
+    /// List or export learned skills
+    Skills {
+        #[command(subcommand)]
+        command: SkillsCommands,
+    },
+    /// Manage Anthropic sign-in (claude.ai subscription OAuth)
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommands,
+    },
+    /// Update this binary to the latest GitHub release, verifying its SHA-256 checksum
+    SelfUpdate {
+        /// Only report whether a newer release is available
+        #[arg(long)]
+        check: bool,
+    },
+    /// Generate shell completion script (e.g. `cortex completions zsh > _cortex`)
+    Completions {
+        /// Target shell
+        shell: clap_complete::Shell,
+    },
+    /// Time save, recall, context, and micro sleep on throwaway synthetic stores
+    #[command(hide = true)]
+    Bench {
+        /// Store sizes to seed, comma-separated
+        #[arg(long, value_delimiter = ',', default_value = "10000,100000")]
+        sizes: Vec<usize>,
+        /// Operations timed per workload (micro sleep runs once per size)
+        #[arg(long, default_value_t = 200)]
+        iterations: usize,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
     },
-    /// Start MCP stdio server
-    Mcp,
 }
 
-fn find_cortex_dir(base: &Option<PathBuf>) -> Result<PathBuf> {
-    let base = match base {
-        Some(p) => p.clone(),
-        None => std::env::current_dir()?,
-    };
-    let cortex_dir = base.join(".cortex");
-    if !cortex_dir.exists() {
-        anyhow::bail!(
-            "No .cortex/ directory found in {}. Run `cortex init` first.",
-            base.display()
-        );
+#[derive(clap::Args)]
+struct SaveArgs {
+    /// What was learned or observed
+    #[arg(required_unless_present_any = ["edit", "template"], conflicts_with = "template")]
+    content: Option<String>,
+    /// Type: bugfix, decision, pattern, preference, observation, antipattern ("do NOT do X");
+    /// guessed from the content by [classify] mode when omitted
+    #[arg(long)]
+    r#type: Option<String>,
+    /// Compose the memory in $EDITOR (content, type, tags, importance)
+    #[arg(long)]
+    edit: bool,
+    /// Structured template from [templates] (e.g. decision); opens $EDITOR unless --field is given
+    #[arg(long)]
+    template: Option<String>,
+    /// Fill a template section non-interactively, e.g. --field choice="Use tokio" (repeatable)
+    #[arg(long, requires = "template")]
+    field: Vec<String>,
+    /// public, internal, or secret (default: [sensitivity] default); limits where it can be shared
+    #[arg(long)]
+    sensitivity: Option<Sensitivity>,
+    /// Never send this memory to an LLM: no summary or entity extraction, and sleep
+    /// consolidates it without the LLM
+    #[arg(long)]
+    no_llm: bool,
+}
+
+impl SaveArgs {
+    /// Whether saving will open `$EDITOR`.
+    fn opens_editor(&self) -> bool {
+        self.edit || (self.template.is_some() && self.field.is_empty())
     }
-    Ok(cortex_dir)
 }
 
-fn session_id() -> String {
-    uuid::Uuid::new_v4().to_string()
+#[derive(clap::Args)]
+struct RecallArgs {
+    /// Search query
+    #[arg(required_unless_present = "reference")]
+    query: Option<String>,
+    /// Max results
+    #[arg(long, default_value = "10")]
+    limit: usize,
+    /// Output as JSON
+    #[arg(long)]
+    json: bool,
+    /// Find memories referencing an issue/PR (e.g. #123, JIRA-456, or a URL)
+    #[arg(long = "ref", value_name = "REF")]
+    reference: Option<String>,
+    /// Also search paraphrases of the query and fuse the results (`[recall] expand`, or
+    /// the bundled synonym table when that is off)
+    #[arg(long, conflicts_with = "reference")]
+    expand: bool,
+    /// Leave out memories more sensitive than this (public, internal, secret)
+    #[arg(long)]
+    max_sensitivity: Option<Sensitivity>,
 }
 
-/// Open global consolidated DB if ~/.cortex/ exists.
-fn open_global_cons() -> Option<rusqlite::Connection> {
-    init::find_global_dir().and_then(|gd| {
-        db::open_consolidated_db(&gd.join("consolidated.db")).ok()
-    })
+#[derive(clap::Args)]
+struct StatsArgs {
+    /// Output as JSON
+    #[arg(long)]
+    json: bool,
+    /// Break memories down by type (raw and consolidated)
+    #[arg(long)]
+    per_type: bool,
+    /// Break raw memories down by session, most recent first
+    #[arg(long)]
+    per_session: bool,
+    /// Break raw memories down by week of creation
+    #[arg(long)]
+    per_week: bool,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+#[derive(clap::Args)]
+struct ContextArgs {
+    /// Compact single-line format
+    #[arg(long)]
+    compact: bool,
+    /// Optional search query to load only relevant memories
+    #[arg(short, long)]
+    query: Option<String>,
+    /// Max number of relevant memories to include (default: 15)
+    #[arg(short, long, default_value = "15")]
+    limit: usize,
+    /// Leave out memories more sensitive than this (public, internal, secret)
+    #[arg(long)]
+    max_sensitivity: Option<Sensitivity>,
+    /// Print the estimated token size of each section instead of the context
+    #[arg(long)]
+    stats: bool,
+    /// Only patterns and skills added or updated since this client's last fetch
+    #[arg(long, conflicts_with_all = ["compact", "query", "stats"])]
+    since_last: bool,
+    /// Name for the cursor `--since-last` reads; a full fetch with it sets the cursor too
+    /// (default with --since-last: cli)
+    #[arg(long)]
+    client: Option<String>,
+    /// Context profile from `[context.profiles]` or built in (reviewer, implementer)
+    #[arg(long)]
+    profile: Option<String>,
+    /// Wrap sections in XML tags for a system prompt, with memory IDs
+    #[arg(long, conflicts_with_all = ["compact", "stats", "since_last"])]
+    xml: bool,
+}
 
-    match cli.command {
-        Commands::Init => {
-            let base = cli.dir.unwrap_or(std::env::current_dir()?);
-            init::init_cortex(&base)?;
-        }
-        Commands::Save { content, r#type } => {
-            let cortex_dir = find_cortex_dir(&cli.dir)?;
-            let config = config::load_config(&cortex_dir)?;
-            let raw_conn = db::open_raw_db(&cortex_dir.join("raw.db"))?;
-            let sid = session_id();
-            let id = db::save_memory(&raw_conn, &content, &r#type, &sid)?;
-
-            // Try to extract entities (best-effort, don't fail save if extraction fails)
-            match llm::extract_entities(&content, &config).await {
-                Ok(extraction) => {
-                    let mut entity_ids = Vec::new();
-                    for entity in &extraction.entities {
-                        if let Ok(eid) = db::upsert_entity(&raw_conn, &entity.name, &entity.r#type, entity.description.as_deref()) {
-                            entity_ids.push(eid);
-                        }
-                    }
-                    if !entity_ids.is_empty() {
-                        let _ = db::update_memory_entities(&raw_conn, id, &entity_ids);
-                    }
-                    for rel in &extraction.relationships {
-                        let source = db::get_entity_by_name(&raw_conn, &rel.source).ok().flatten();
-                        let target = db::get_entity_by_name(&raw_conn, &rel.target).ok().flatten();
-                        if let (Some(s), Some(t)) = (source, target) {
-                            let _ = db::upsert_relationship(&raw_conn, s.id, t.id, &rel.r#type, id, rel.confidence);
-                        }
-                    }
-                    if !extraction.entities.is_empty() {
-                        eprintln!("Saved memory #{} (type: {}, {} entities extracted)", id, r#type, extraction.entities.len());
-                    } else {
-                        eprintln!("Saved memory #{} (type: {})", id, r#type);
-                    }
-                }
-                Err(_) => {
-                    eprintln!("Saved memory #{} (type: {})", id, r#type);
-                }
-            }
-
-            // Auto micro-sleep
-            let uncons = db::get_unconsolidated_count(&raw_conn)?;
-            if uncons >= config.consolidation.auto_micro_threshold as i64 {
-                let removed = sleep::micro_sleep(&raw_conn, &config)?;
-                if removed > 0 {
-                    eprintln!("Auto micro-sleep: removed {} stale memories", removed);
-                }
-            }
-        }
-        Commands::Recall { query, limit, json } => {
-            let cortex_dir = find_cortex_dir(&cli.dir)?;
-            let raw_conn = db::open_raw_db(&cortex_dir.join("raw.db"))?;
-
-            // Try entity-based recall first, then fall back to FTS
-            let mut memories = db::recall_by_entity(&raw_conn, &query, true, limit)?;
-            if memories.is_empty() {
-                memories = db::recall_memories(&raw_conn, &query, limit)?;
-            }
-
-            // Also search global consolidated DB
-            if let Some(global_cons) = open_global_cons() {
-                let global_consolidated = db::get_all_consolidated(&global_cons).unwrap_or_default();
-                let query_lower = query.to_lowercase();
-                let query_words: Vec<&str> = query_lower.split_whitespace().collect();
-                for m in global_consolidated {
-                    let content_lower = m.content.to_lowercase();
-                    if query_words.iter().any(|w| content_lower.contains(w)) {
-                        memories.push(models::Memory {
-                            id: -m.id, // negative ID to distinguish global
-                            content: format!("[global] {}", m.content),
-                            r#type: m.r#type,
-                            created_at: m.created_at,
-                            accessed_at: m.updated_at,
-                            access_count: m.access_count,
-                            consolidated: true,
-                            importance: m.confidence,
-                            session_id: None,
-                            entity_ids: vec![],
-                        });
-                    }
-                }
+#[derive(clap::Args)]
+struct ListArgs {
+    /// Only list memories of this type (e.g. preference)
+    #[arg(long)]
+    r#type: Option<String>,
+    /// Output as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Subcommand)]
+enum GlobalCommands {
+    /// Save a memory straight to the global store (consolidated by `cortex sleep --global`)
+    Save(SaveArgs),
+    /// Search global memory only
+    Recall(RecallArgs),
+    /// List consolidated global memories
+    List(ListArgs),
+    /// Output global memory context for prompt injection
+    Context(ContextArgs),
+    /// Global store health statistics
+    Stats(StatsArgs),
+    /// SQL-only dedup and decay of the global store, no LLM call
+    Prune,
+    /// Move ~/.cortex/ to ~/.config/cortex (config, credentials) and ~/.local/share/cortex (data)
+    MigrateXdg,
+}
+
+#[derive(Subcommand)]
+enum AdrCommands {
+    /// Import ADR markdown files as decision memories
+    Import {
+        /// ADR directory (defaults to [adr] dir, usually docs/adr)
+        dir: Option<PathBuf>,
+    },
+    /// Write decisions without an ADR as new numbered ADR files
+    Export {
+        /// ADR directory (defaults to [adr] dir, usually docs/adr)
+        dir: Option<PathBuf>,
+        /// Most sensitive decisions to write (defaults to [sensitivity] share_max)
+        #[arg(long)]
+        max_sensitivity: Option<Sensitivity>,
+    },
+}
+
+#[derive(Subcommand)]
+enum McpCommands {
+    /// Register `cortex mcp` for this project with an MCP client, merging into its config
+    Install {
+        /// claude-code, claude-desktop, cursor, or zed
+        #[arg(long)]
+        client: mcp_install::Client,
+        /// Print the resulting config without writing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SkillsCommands {
+    /// List skills grouped by category
+    List {
+        /// Only show skills in this category (e.g. testing)
+        #[arg(long)]
+        category: Option<String>,
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+        /// List global ~/.cortex/ skills
+        #[arg(long, short)]
+        global: bool,
+    },
+    /// Print a skill's full content and count the read towards its usage
+    Show {
+        /// Skill name or category/name path
+        name: String,
+        /// Look in the global ~/.cortex/ store
+        #[arg(long, short)]
+        global: bool,
+    },
+    /// Write skills as Claude Code skills (<dir>/<name>/SKILL.md)
+    Export {
+        /// Output directory (defaults to .claude/skills in the project root)
+        dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommands {
+    /// Snapshot raw.db and consolidated.db into .cortex/snapshots/
+    Create {
+        /// Label for the snapshot directory (letters, digits, '-', '_')
+        #[arg(long, default_value = "manual")]
+        label: String,
+    },
+    /// List snapshots, oldest first
+    List {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Replace both databases with a snapshot (the current state is snapshotted first)
+    Restore {
+        /// Snapshot name, as shown by `cortex snapshot list`
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuthCommands {
+    /// Sign in with a Claude account and store tokens in the OS keychain
+    Login,
+    /// Show which LLM credentials cortex will use
+    Status,
+    /// Remove stored OAuth tokens
+    Logout,
+    /// Ping each configured LLM provider and report latency and model availability
+    Check,
+}
+
+#[derive(Subcommand)]
+enum ScheduleCommands {
+    /// Write and activate scheduled jobs
+    Install {
+        /// Run `cortex sleep --all-projects` every night
+        #[arg(long)]
+        daily_sleep: bool,
+        /// Run `cortex dream --global` every Sunday, an hour after the sleep time
+        #[arg(long)]
+        weekly_dream: bool,
+        /// Time of the nightly sleep (HH:MM, 24-hour, local time)
+        #[arg(long, default_value = "03:00")]
+        at: String,
+        /// Cap LLM calls per nightly sleep (passed to `sleep --max-llm-calls`)
+        #[arg(long)]
+        max_llm_calls: Option<u64>,
+        /// Scheduler to use: launchd, systemd, or cron (default: detected)
+        #[arg(long)]
+        backend: Option<schedule::Backend>,
+        /// Print what would be installed without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show which jobs are installed
+    Status {
+        /// Scheduler to inspect (default: detected)
+        #[arg(long)]
+        backend: Option<schedule::Backend>,
+    },
+    /// Remove all installed cortex jobs
+    Uninstall {
+        /// Scheduler to remove jobs from (default: detected)
+        #[arg(long)]
+        backend: Option<schedule::Backend>,
+    },
+}
+
+/// The project store under `--dir` (or the current directory).
+fn find_store(base: &Option<PathBuf>) -> Result<Store> {
+    let store = match (base, init::dir_from_env()) {
+        (Some(base), _) => Store::project(base)?,
+        (None, Some(dir)) => {
+            if !dir.join("config.toml").exists() {
+                anyhow::bail!("No cortex store at {} ({}). Run `cortex init` first.", dir.display(), init::DIR_ENV);
             }
+            Store::open(dir)
+        }
+        (None, None) => Store::project(&std::env::current_dir()?)?,
+    };
+    projects::remember(store.dir());
+    Ok(store)
+}
 
-            if memories.is_empty() {
-                eprintln!("No memories found.");
-            } else if json {
-                println!("{}", serde_json::to_string_pretty(&memories)?);
-            } else {
-                for m in &memories {
-                    println!("[{}] #{}: {}", m.r#type, m.id, m.content);
-                }
+/// Run a command against a `cortex serve` store through its MCP tools. Only the commands an
+/// ephemeral environment needs are proxied; the rest work on local stores.
+async fn run_remote(client: &remote::Client, cli: Cli) -> Result<()> {
+    let (key, text) = match cli.command {
+        Commands::Save(args) => {
+            config::ensure_writable(cli.read_only, "save")?;
+            if args.edit || args.template.is_some() || args.no_llm {
+                anyhow::bail!("--edit, --template, and --no-llm aren't available with --remote");
             }
+            let mut arguments = serde_json::json!({ "content": args.content, "type": args.r#type });
+            if let Some(s) = args.sensitivity {
+                arguments["sensitivity"] = serde_json::json!(s);
+            }
+            ("result", client.call_tool("cortex_save", arguments).await?)
         }
-        Commands::Stats { json, global } => {
-            if global {
-                let global_dir = init::find_global_dir()
-                    .ok_or_else(|| anyhow::anyhow!("No global ~/.cortex/ directory found."))?;
-                let global_cons = db::open_consolidated_db(&global_dir.join("consolidated.db"))?;
-                let cons_count: i64 = global_cons.query_row("SELECT COUNT(*) FROM consolidated", [], |r| r.get(0))?;
-                let skill_count: i64 = global_cons.query_row("SELECT COUNT(*) FROM skills", [], |r| r.get(0))?;
-                let last_sleep = db::get_meta(&global_cons, "last_sleep")?;
-                if json {
-                    println!("{}", serde_json::to_string_pretty(&serde_json::json!({
-                        "global_consolidated": cons_count,
-                        "global_skills": skill_count,
-                        "global_last_sleep": last_sleep,
-                    }))?);
-                } else {
-                    println!("Global consolidated: {}", cons_count);
-                    println!("Global skills: {}", skill_count);
-                    if let Some(ref last) = last_sleep {
-                        println!("Global last sleep: {}", last);
-                    }
-                }
-            } else {
-                let cortex_dir = find_cortex_dir(&cli.dir)?;
-                let raw_conn = db::open_raw_db(&cortex_dir.join("raw.db"))?;
-                let cons_conn = db::open_consolidated_db(&cortex_dir.join("consolidated.db"))?;
-                let stats = db::get_stats(&raw_conn, &cons_conn)?;
-                if json {
-                    let mut stats_json = serde_json::to_value(&stats)?;
-                    // Add global stats if available
-                    if let Some(global_cons) = open_global_cons() {
-                        let gc: i64 = global_cons.query_row("SELECT COUNT(*) FROM consolidated", [], |r| r.get(0)).unwrap_or(0);
-                        let gs: i64 = global_cons.query_row("SELECT COUNT(*) FROM skills", [], |r| r.get(0)).unwrap_or(0);
-                        stats_json["global_consolidated"] = serde_json::json!(gc);
-                        stats_json["global_skills"] = serde_json::json!(gs);
-                    }
-                    println!("{}", serde_json::to_string_pretty(&stats_json)?);
-                } else {
-                    println!("{}", stats);
-                    // Append global stats
-                    if let Some(global_cons) = open_global_cons() {
-                        let gc: i64 = global_cons.query_row("SELECT COUNT(*) FROM consolidated", [], |r| r.get(0)).unwrap_or(0);
-                        let gs: i64 = global_cons.query_row("SELECT COUNT(*) FROM skills", [], |r| r.get(0)).unwrap_or(0);
-                        if gc > 0 || gs > 0 {
-                            println!("Global: {} consolidated, {} skills", gc, gs);
-                        }
-                    }
-                }
+        Commands::Recall(args) => {
+            if args.max_sensitivity.is_some() {
+                anyhow::bail!("--max-sensitivity isn't available with --remote");
             }
+            let arguments = serde_json::json!({
+                "query": args.query.unwrap_or_default(),
+                "limit": args.limit,
+                "ref": args.reference,
+                "expand": args.expand.then_some(true),
+            });
+            ("memories", client.call_tool("cortex_recall", arguments).await?)
         }
-        Commands::Sleep { micro, global, .. } => {
-            if global {
-                let global_dir = init::ensure_global_dir()?;
-                let config = config::load_config(&global_dir)?;
-                let raw_conn = db::open_raw_db(&global_dir.join("raw.db"))?;
-
-                if micro {
-                    let removed = sleep::micro_sleep(&raw_conn, &config)?;
-                    eprintln!("Global micro sleep complete. Removed {} stale memories.", removed);
-                } else {
-                    let cons_conn = db::open_consolidated_db(&global_dir.join("consolidated.db"))?;
-                    match sleep::quick_sleep(&raw_conn, &cons_conn, &config, &global_dir).await {
-                        Ok(result) => {
-                            eprintln!(
-                                "Global quick sleep complete. {} consolidations, {} promotions, {} decayed, {} skills updated.",
-                                result.consolidations.len(),
-                                result.promotions.len(),
-                                result.decayed.len(),
-                                result.skill_updates.len()
-                            );
-                        }
-                        Err(e) => {
-                            eprintln!("Global quick sleep failed: {}. Falling back to micro sleep.", e);
-                            let removed = sleep::micro_sleep(&raw_conn, &config)?;
-                            eprintln!("Global micro sleep complete. Removed {} stale memories.", removed);
-                        }
-                    }
-                }
-            } else {
-                let cortex_dir = find_cortex_dir(&cli.dir)?;
-                let config = config::load_config(&cortex_dir)?;
-                let raw_conn = db::open_raw_db(&cortex_dir.join("raw.db"))?;
-
-                if micro {
-                    let removed = sleep::micro_sleep(&raw_conn, &config)?;
-                    eprintln!("Micro sleep complete. Removed {} stale memories.", removed);
-                } else {
-                    let cons_conn = db::open_consolidated_db(&cortex_dir.join("consolidated.db"))?;
-                    match sleep::quick_sleep(&raw_conn, &cons_conn, &config, &cortex_dir).await {
-                        Ok(result) => {
-                            eprintln!(
-                                "Quick sleep complete. {} consolidations, {} promotions, {} decayed, {} skills updated.",
-                                result.consolidations.len(),
-                                result.promotions.len(),
-                                result.decayed.len(),
-                                result.skill_updates.len()
-                            );
-                        }
-                        Err(e) => {
-                            eprintln!("Quick sleep failed: {}. Falling back to micro sleep.", e);
-                            let removed = sleep::micro_sleep(&raw_conn, &config)?;
-                            eprintln!("Micro sleep complete. Removed {} stale memories.", removed);
-                        }
-                    }
-                }
+        Commands::Context(args) => {
+            if args.max_sensitivity.is_some() || args.since_last || args.client.is_some() {
+                anyhow::bail!("--max-sensitivity, --since-last, and --client aren't available with --remote");
             }
+            let arguments =
+                serde_json::json!({ "compact": args.compact, "query": args.query, "limit": args.limit, "profile": args.profile, "xml": args.xml });
+            ("context", client.call_tool("cortex_context", arguments).await?)
         }
-        Commands::Dream { global } => {
-            if global {
-                let global_dir = init::ensure_global_dir()?;
-                let config = config::load_config(&global_dir)?;
-                let raw_conn = db::open_raw_db(&global_dir.join("raw.db"))?;
-                let cons_conn = db::open_consolidated_db(&global_dir.join("consolidated.db"))?;
-                let result = dream::dream(&raw_conn, &cons_conn, &config, &global_dir).await?;
-                eprintln!(
-                    "Global dream complete. {} insights generated, {} skills updated.",
-                    result.insights, result.skills_updated
-                );
-            } else {
-                let cortex_dir = find_cortex_dir(&cli.dir)?;
-                let config = config::load_config(&cortex_dir)?;
-                let raw_conn = db::open_raw_db(&cortex_dir.join("raw.db"))?;
-                let cons_conn = db::open_consolidated_db(&cortex_dir.join("consolidated.db"))?;
-                let result = dream::dream(&raw_conn, &cons_conn, &config, &cortex_dir).await?;
-                eprintln!(
-                    "Dream complete. {} insights generated, {} skills updated.",
-                    result.insights, result.skills_updated
-                );
+        Commands::Sleep { micro, heuristic, global, from_ci, all_projects, batch_api, collect, .. } => {
+            config::ensure_writable(cli.read_only, "sleep")?;
+            if heuristic || global || from_ci || all_projects || batch_api || collect {
+                anyhow::bail!("Only `sleep` and `sleep --micro` are available with --remote");
             }
+            ("result", client.call_tool("cortex_sleep", serde_json::json!({ "micro": micro || cli.ci })).await?)
         }
-        Commands::Edit { id, content } => {
-            if id < 0 {
-                // Global memory (negative IDs map to positive global IDs)
-                let global_dir = init::find_global_dir()
-                    .ok_or_else(|| anyhow::anyhow!("No global ~/.cortex/ directory found."))?;
-                let global_cons = db::open_consolidated_db(&global_dir.join("consolidated.db"))?;
-                let real_id = -id;
-                if db::update_consolidated(&global_cons, real_id, &content)? {
-                    eprintln!("Updated global memory #{}", real_id);
+        _ => anyhow::bail!("Only save, recall, context, and sleep are available with --remote"),
+    };
+    if cli.ci {
+        println!("{}", serde_json::json!({ "ok": true, key: text }));
+    } else {
+        println!("{}", text);
+    }
+    Ok(())
+}
+
+/// Automatic snapshot of `store` before a destructive command (`[snapshots] auto`).
+fn snapshot_before(store: &Store, operation: &str) -> Result<()> {
+    snapshot::before(store.raw()?, store.cons()?, store.dir(), &store.config()?.snapshots, operation)
+}
+
+/// CORTEX_SESSION_ID when a shell hook exported one (`cortex wake --hook`), so saves from one
+/// terminal share a session; otherwise a new ID.
+fn session_id() -> String {
+    std::env::var("CORTEX_SESSION_ID")
+        .ok()
+        .filter(|id| !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Wall-clock budget for a single command in CI mode.
+const CI_TIMEOUT_SECS: u64 = 30;
+
+fn heuristic_summary(result: &sleep::HeuristicResult) -> String {
+    format!(
+        "{} clusters merged, {} promotions, {} existing memories reinforced.",
+        result.consolidation.consolidations.len(),
+        result.consolidation.promotions.len(),
+        result.reinforced
+    )
+}
+
+fn heuristic_json(result: &sleep::HeuristicResult) -> serde_json::Value {
+    serde_json::json!({
+        "ok": true,
+        "mode": "heuristic",
+        "consolidations": result.consolidation.consolidations.len(),
+        "promotions": result.consolidation.promotions.len(),
+        "reinforced": result.reinforced,
+    })
+}
+
+/// `cortex save` and `cortex global save`.
+async fn save_memory(store: &Store, args: SaveArgs, ci: bool, source: &str) -> Result<()> {
+    let edit = args.opens_editor();
+    let SaveArgs { content, r#type, template, field, sensitivity, no_llm, .. } = args;
+    let config = store.config()?;
+
+    let template = template.map(|name| templates::find_template(config, &name)).transpose()?;
+    let (r#type, content) = match &template {
+        Some(t) if !field.is_empty() => (Some(t.r#type.clone()), Some(templates::render(t, &field)?)),
+        Some(t) => (Some(t.r#type.clone()), Some(templates::skeleton(t))),
+        None => (r#type, content),
+    };
+
+    let (content, r#type, tags, importance) = if edit {
+        if ci {
+            anyhow::bail!("--edit is interactive and not available in CI mode.");
+        }
+        let initial = content.unwrap_or_default();
+        match editor::edit_memory(r#type.as_deref().unwrap_or(classify::DEFAULT_TYPE), &initial)? {
+            // An untouched template skeleton counts as empty
+            Some(m) if template.is_none() || m.content != initial => {
+                (m.content, Some(m.r#type), m.tags, Some(m.importance))
+            }
+            _ => {
+                eprintln!("Aborting save: empty content.");
+                return Ok(());
+            }
+        }
+    } else {
+        (content.unwrap_or_default(), r#type, vec![], None)
+    };
+
+    if no_llm {
+        // Classification, oversize summaries, entity extraction, and contradiction checks fall
+        // back as if no LLM were configured
+        llm::disable_llm();
+    }
+    let raw_conn = store.raw()?;
+    let r#type = match r#type {
+        Some(t) => t,
+        None => classify::classify(raw_conn, &content, config).await,
+    };
+    let plugins = plugins::load_plugins(store.dir()).await;
+    let (content, r#type) = plugins::process_memory(&plugins, &content, &r#type).await?;
+
+    let sid = session_id();
+    let saved = oversize::save_memory(raw_conn, &content, &r#type, &sid, source, sensitivity, config).await?;
+    let (trusted, sensitivity) = (saved.trusted, saved.sensitivity);
+    let note = saved.note(config.content.max_chars);
+    let (id, content) = (saved.id, saved.content);
+    if no_llm {
+        db::set_memory_no_llm(raw_conn, &[id])?;
+    }
+    if !tags.is_empty() {
+        db::update_memory_tags(raw_conn, id, &tags)?;
+    }
+    if let Some(importance) = importance {
+        db::update_memory_importance(raw_conn, id, importance)?;
+    }
+
+    // Try to extract entities (best-effort, don't fail save if extraction fails)
+    let extraction = llm::extract_entities(&content, config);
+    match llm::audited(Manifest::new(raw_conn, "save", audit::raw_keys([id])), extraction).await {
+        Ok(extraction) => {
+            let mut entity_ids = Vec::new();
+            for entity in &extraction.entities {
+                if let Ok(eid) = db::upsert_entity(raw_conn, &entity.name, &entity.r#type, entity.description.as_deref()) {
+                    entity_ids.push(eid);
+                }
+            }
+            if !entity_ids.is_empty() {
+                let _ = db::update_memory_entities(raw_conn, id, &entity_ids);
+            }
+            for rel in &extraction.relationships {
+                let source = db::get_entity_by_name(raw_conn, &rel.source).ok().flatten();
+                let target = db::get_entity_by_name(raw_conn, &rel.target).ok().flatten();
+                if let (Some(s), Some(t)) = (source, target) {
+                    let _ = db::upsert_relationship(raw_conn, s.id, t.id, &rel.r#type, id, rel.confidence);
+                }
+            }
+            if !extraction.entities.is_empty() {
+                eprintln!("Saved memory #{} (type: {}, {} entities extracted{})", id, r#type, extraction.entities.len(), note);
+            } else {
+                eprintln!("Saved memory #{} (type: {}{})", id, r#type, note);
+            }
+        }
+        Err(_) => {
+            eprintln!("Saved memory #{} (type: {}{})", id, r#type, note);
+        }
+    }
+    let cons_conn = store.cons()?;
+    let alerts = contradiction::check(raw_conn, cons_conn, id, &content, config).await?;
+    for a in &alerts {
+        eprintln!("Warning: contradicts consolidated #{} ({}): {}", a.consolidated_id, a.reason, a.content);
+    }
+    if !alerts.is_empty() {
+        eprintln!("Linked for the next sleep to resolve; fix whichever is wrong with `cortex edit` or `cortex delete`.");
+    }
+    if ci {
+        println!("{}", serde_json::json!({ "ok": true, "id": id, "type": r#type, "trusted": trusted, "contradictions": alerts }));
+    }
+
+    if config.adr.auto_export && r#type == "decision" && sensitivity <= config.sensitivity.share_max {
+        let adr_dir = store.root().join(&config.adr.dir);
+        let path = adr::export_memory(raw_conn, &adr_dir, id, &content)?;
+        eprintln!("Wrote {}", path.display());
+    }
+
+    hooks::fire(
+        config,
+        HookEvent::Save,
+        serde_json::json!({ "id": id, "type": r#type, "content": content, "tags": tags }),
+    )
+    .await;
+
+    // Auto micro-sleep
+    let uncons = db::get_unconsolidated_count(raw_conn)?;
+    if uncons >= config.consolidation.auto_micro_threshold as i64 {
+        let removed = sleep::auto_micro_sleep(raw_conn, config, store.dir())?;
+        if removed > 0 {
+            eprintln!("Auto micro-sleep: removed {} stale memories", removed);
+        }
+    }
+    Ok(())
+}
+
+/// `cortex recall` and `cortex global recall`: search `store`, plus `global` if given.
+async fn recall_memories(store: &Store, global: Option<&Store>, args: RecallArgs, read_only: bool, ci: bool) -> Result<()> {
+    let RecallArgs { query, limit, json, reference, expand, max_sensitivity } = args;
+    let json = json || ci;
+    let allowed = |level: Sensitivity| max_sensitivity.is_none_or(|max| level <= max);
+    let raw_conn = store.raw()?;
+
+    if let Some(reference) = reference {
+        let reference = refs::normalize_ref(&reference);
+        let cons_conn = store.cons()?;
+        let mut memories = db::recall_by_ref(raw_conn, &reference, limit)?;
+        let mut consolidated = db::consolidated_by_ref(cons_conn, &reference, limit)?;
+        memories.retain(|m| allowed(m.sensitivity));
+        consolidated.retain(|c| allowed(c.sensitivity));
+        if json {
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "ref": reference,
+                "memories": memories,
+                "consolidated": consolidated,
+            }))?);
+        } else if memories.is_empty() && consolidated.is_empty() {
+            eprintln!("No memories reference {}.", reference);
+        } else {
+            for c in &consolidated {
+                println!("[{}] consolidated #{}: {}", c.r#type, c.id, c.content);
+            }
+            for m in &memories {
+                println!("[{}] #{}: {}", m.r#type, m.id, m.content);
+            }
+        }
+        return Ok(());
+    }
+    let query = query.unwrap_or_default();
+    let config = store.config()?;
+    let mode = match config.recall.expand {
+        config::Expansion::Off if expand => config::Expansion::Synonyms,
+        mode => mode,
+    };
+    let queries = expand::queries(&query, mode, config).await;
+    let global_cons = store::global_cons(global);
+    let mut memories = context::recall_fused(raw_conn, global_cons, &queries, limit, &config.recall)?;
+    memories.retain(|m| allowed(m.sensitivity));
+    if config.insights.enabled && !read_only {
+        insights::record(raw_conn, "cli", &query, &memories.iter().map(|m| m.id).collect::<Vec<_>>())?;
+    }
+    let cons_conn = store.cons()?;
+    let conflicts =
+        antipattern::conflicts(&memories, &antipattern::load(raw_conn, cons_conn, global_cons)?);
+    let skill_hits = skills::search(cons_conn, global_cons, &query, limit)?;
+
+    // `--json` stays a plain array of memories
+    if json {
+        println!("{}", serde_json::to_string_pretty(&memories)?);
+    } else if memories.is_empty() && skill_hits.is_empty() {
+        eprintln!("No memories found.");
+    } else {
+        for m in &memories {
+            println!("[{}] #{}: {}", m.r#type, m.id, m.content);
+        }
+        for (scope, skill) in &skill_hits {
+            println!("{}", skills::recall_line(scope, skill));
+        }
+    }
+    // Suggestions go to stderr so `--json` output stays a plain array
+    if memories.is_empty() && skill_hits.is_empty() && !query.trim().is_empty() {
+        let mut alternatives = fallback::fallback(raw_conn, cons_conn, &query, limit)?;
+        alternatives.partial.retain(|m| allowed(m.sensitivity));
+        eprint!("{}", alternatives.render());
+    }
+    for c in &conflicts {
+        eprintln!("Warning: #{} conflicts with antipattern {}: {}", c.memory_id, c.antipattern, c.content);
+    }
+    Ok(())
+}
+
+/// `cortex stats` and `cortex global stats` for `store`, plus counts from `global` if given.
+fn print_stats(store: &Store, global: Option<&Store>, args: StatsArgs) -> Result<()> {
+    let StatsArgs { json, per_type, per_session, per_week } = args;
+    let raw_conn = store.raw()?;
+    let cons_conn = store.cons()?;
+    let stats = db::get_stats(raw_conn, cons_conn)?;
+    let tokenizer = store.config()?.search.tokenizer;
+    if db::fts_tokenizer(raw_conn, "memories_fts")? != tokenizer
+        || db::fts_tokenizer(cons_conn, "consolidated_fts")? != tokenizer
+    {
+        eprintln!("Warning: search indexes don't use the configured {} tokenizer; run `cortex reindex`.", tokenizer);
+    }
+    if json {
+        let mut stats_json = serde_json::to_value(&stats)?;
+        if per_type {
+            stats_json["by_type"] = serde_json::json!({
+                "memories": stats::memory_groups(raw_conn, stats::Grouping::Type)?,
+                "consolidated": stats::consolidated_groups(cons_conn)?,
+            });
+        }
+        if per_session {
+            stats_json["by_session"] =
+                serde_json::to_value(stats::memory_groups(raw_conn, stats::Grouping::Session)?)?;
+        }
+        if per_week {
+            stats_json["by_week"] = serde_json::to_value(stats::memory_groups(raw_conn, stats::Grouping::Week)?)?;
+        }
+        if let Some(trend) = evaluate::trend(raw_conn)? {
+            stats_json["quality"] = serde_json::to_value(&trend)?;
+        }
+        // Add global stats if available
+        if let Some(global_cons) = store::global_cons(global) {
+            let gc: i64 = global_cons.query_row("SELECT COUNT(*) FROM consolidated", [], |r| r.get(0)).unwrap_or(0);
+            let gs: i64 = global_cons.query_row("SELECT COUNT(*) FROM skills", [], |r| r.get(0)).unwrap_or(0);
+            stats_json["global_consolidated"] = serde_json::json!(gc);
+            stats_json["global_skills"] = serde_json::json!(gs);
+        }
+        println!("{}", serde_json::to_string_pretty(&stats_json)?);
+    } else {
+        println!("{}", stats);
+        if let Some(trend) = evaluate::trend(raw_conn)? {
+            println!("{}", trend);
+        }
+        if per_type {
+            let groups = stats::memory_groups(raw_conn, stats::Grouping::Type)?;
+            print!("\n{}", stats::format_memory_groups("Memories by type", "TYPE", &groups, usize::MAX));
+            print!("\n{}", stats::format_consolidated_groups(&stats::consolidated_groups(cons_conn)?));
+        }
+        if per_session {
+            let groups = stats::memory_groups(raw_conn, stats::Grouping::Session)?;
+            print!("\n{}", stats::format_memory_groups("Memories by session", "SESSION", &groups, 20));
+        }
+        if per_week {
+            let groups = stats::memory_groups(raw_conn, stats::Grouping::Week)?;
+            print!("\n{}", stats::format_memory_groups("Memories by week", "WEEK", &groups, 20));
+        }
+        // Append global stats
+        if let Some(global_cons) = store::global_cons(global) {
+            let gc: i64 = global_cons.query_row("SELECT COUNT(*) FROM consolidated", [], |r| r.get(0)).unwrap_or(0);
+            let gs: i64 = global_cons.query_row("SELECT COUNT(*) FROM skills", [], |r| r.get(0)).unwrap_or(0);
+            if gc > 0 || gs > 0 {
+                println!("Global: {} consolidated, {} skills", gc, gs);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `cortex context` and `cortex global context` for `store`, plus `global` if given.
+async fn print_context(store: &Store, global: Option<&Store>, args: ContextArgs, read_only: bool, ci: bool) -> Result<()> {
+    let ContextArgs { compact, query, limit, max_sensitivity, stats, since_last, client, profile, xml } = args;
+    let raw_conn = store.raw()?;
+    let cons_conn = store.cons()?;
+    let config = store.config()?;
+    let profile = profile.map(|name| context::find_profile(&config.context, &name)).transpose()?;
+    if since_last {
+        let client = client.as_deref().unwrap_or("cli");
+        let since = context::get_cursor(cons_conn, client)?;
+        let ctx =
+            context::format_delta(cons_conn, store::global_cons(global), since.as_deref(), max_sensitivity, profile.as_ref())?;
+        let cursor = if read_only { context::current_cursor(cons_conn)? } else { context::save_cursor(cons_conn, client)? };
+        if ci {
+            println!("{}", serde_json::json!({ "ok": true, "context": ctx, "since": since, "cursor": cursor }));
+        } else {
+            print!("{}", ctx);
+        }
+        return Ok(());
+    }
+    let query = query.map(|q| expand::with_aliases(&q, &config.synonyms));
+    let format = context::ContextFormat::from_flags(compact, xml);
+    let sections = plugins::context_sections(&plugins::load_plugins(store.dir()).await, format).await;
+    let mut ctx = context::format_context(
+        cons_conn,
+        raw_conn,
+        store::global_cons(global),
+        format,
+        query.as_deref(),
+        limit,
+        &config.recall,
+        max_sensitivity,
+        &context::reserve(&config.context, &sections),
+        profile.as_ref(),
+    )?;
+    context::append_sections(&mut ctx, format, &sections);
+    if stats {
+        let sizes = context::section_sizes(&ctx);
+        let total = skills::estimate_tokens(&ctx);
+        let budget = config.context.max_tokens;
+        if ci {
+            println!("{}", serde_json::json!({ "ok": true, "sections": sizes, "tokens": total, "max_tokens": budget }));
+            return Ok(());
+        }
+        println!("{:<24} {:>6} {:>7}", "SECTION", "ITEMS", "TOKENS");
+        for s in &sizes {
+            println!("{:<24} {:>6} {:>7}", s.section, s.items, s.tokens);
+        }
+        let budget = if budget > 0 { format!(" of {} ([context] max_tokens)", budget) } else { String::new() };
+        println!("{:<24} {:>6} {:>7}{}", "Total", "", total, budget);
+    } else if ci {
+        println!("{}", serde_json::json!({ "ok": true, "context": ctx }));
+    } else {
+        println!("{}", ctx);
+    }
+    if let Some(client) = client.filter(|_| !stats && !read_only) {
+        context::save_cursor(cons_conn, &client)?;
+    }
+    Ok(())
+}
+
+/// `cortex global list`: consolidated memories in `store`, most recently updated first.
+fn list_consolidated(store: &Store, args: ListArgs, ci: bool) -> Result<()> {
+    let ListArgs { r#type, json } = args;
+    let cons_conn = store.cons()?;
+    let mut memories = db::get_all_consolidated(cons_conn)?;
+    if let Some(t) = &r#type {
+        memories.retain(|m| &m.r#type == t);
+    }
+    if json || ci {
+        println!("{}", serde_json::to_string_pretty(&memories)?);
+    } else if memories.is_empty() {
+        eprintln!("No consolidated memories.");
+    } else {
+        for m in &memories {
+            let first = m.content.lines().next().unwrap_or("");
+            println!("#{} [{}] confidence {:.2}: {}", m.id, m.r#type, m.confidence, first);
+        }
+    }
+    Ok(())
+}
+
+/// `cortex global prune` and `cortex sleep --micro`: SQL-only dedup and decay of `store`.
+async fn prune_store(store: &Store, label: &str, ci: bool) -> Result<u64> {
+    let config = store.config()?;
+    let raw_conn = store.raw()?;
+    let removed = sleep::micro_sleep(raw_conn, config, store.dir())?;
+    eprintln!("{} complete. Removed {} stale memories.", label, removed);
+    hooks::fire(config, HookEvent::SleepComplete, serde_json::json!({ "mode": "micro", "removed": removed })).await;
+    if ci {
+        println!("{}", serde_json::json!({ "ok": true, "mode": "micro", "removed": removed }));
+    }
+    Ok(removed)
+}
+
+/// One store's `cortex sleep`. Quick sleep falls back to micro sleep on any failure but a
+/// held lock. Returns what ran, plus the consolidation result for the GitHub job summary.
+async fn sleep_store(
+    store: &Store,
+    micro: bool,
+    heuristic: bool,
+    ci: bool,
+    from_ci: bool,
+) -> Result<(runs::StoreRun, Option<models::ConsolidationResult>)> {
+    let config = store.config()?;
+    let raw_conn = store.raw()?;
+    let label = |mode: &str| match store.is_global() {
+        true => format!("Global {} sleep", mode),
+        false => format!("{}{} sleep", mode[..1].to_uppercase(), &mode[1..]),
+    };
+    let pending = db::get_unconsolidated_count(raw_conn)?;
+    let idle_or_done = if pending == 0 { runs::Outcome::NothingToDo } else { runs::Outcome::Done };
+
+    if micro {
+        let removed = prune_store(store, &label("micro"), ci).await?;
+        let outcome = if removed == 0 { runs::Outcome::NothingToDo } else { runs::Outcome::Done };
+        return Ok((runs::StoreRun { removed, ..runs::StoreRun::new(store.root(), "micro", outcome) }, None));
+    }
+    if heuristic {
+        let result = sleep::heuristic_sleep(raw_conn, store.cons()?, config, store.dir()).await?;
+        eprintln!("{} complete. {}", label("heuristic"), heuristic_summary(&result));
+        if ci {
+            println!("{}", heuristic_json(&result));
+        }
+        let run = runs::StoreRun {
+            consolidations: result.consolidation.consolidations.len(),
+            ..runs::StoreRun::new(store.root(), "heuristic", idle_or_done)
+        };
+        return Ok((run, Some(result.consolidation)));
+    }
+
+    match sleep::quick_sleep(raw_conn, store.cons()?, config, store.dir(), false).await {
+        Ok(result) => {
+            eprintln!(
+                "{} complete. {} consolidations, {} promotions, {} decayed, {} skills updated.",
+                label("quick"),
+                result.consolidations.len(),
+                result.promotions.len(),
+                result.decayed.len(),
+                result.skill_updates.len()
+            );
+            let run = runs::StoreRun {
+                consolidations: result.consolidations.len(),
+                ..runs::StoreRun::new(store.root(), "quick", idle_or_done)
+            };
+            Ok((run, Some(result)))
+        }
+        Err(e) if e.is::<lock::LockBusy>() => Err(e),
+        Err(e) => {
+            if from_ci {
+                // Surface as a workflow annotation (e.g. the LLM key secret is missing)
+                println!("::warning title=cortex sleep::Quick sleep failed: {}", e);
+            }
+            eprintln!("{} failed: {}. Falling back to micro sleep.", label("quick"), e);
+            let removed = sleep::micro_sleep(raw_conn, config, store.dir())?;
+            eprintln!("{} complete. Removed {} stale memories.", label("micro"), removed);
+            hooks::fire(config, HookEvent::SleepComplete, serde_json::json!({ "mode": "micro", "removed": removed })).await;
+            let run = runs::StoreRun {
+                removed,
+                error: Some(e.to_string()),
+                ..runs::StoreRun::new(store.root(), "micro", runs::Outcome::Partial)
+            };
+            Ok((run, None))
+        }
+    }
+}
+
+/// `cortex sleep --all-projects`: sleep each registered store in turn. A failing store is
+/// reported and skipped rather than stopping the run. Once the LLM call budget is spent,
+/// the remaining stores get a micro sleep.
+async fn sleep_all_projects(micro: bool, heuristic: bool, ci: bool) -> Result<Vec<runs::StoreRun>> {
+    let mut stores = Vec::new();
+    for dir in projects::known_stores()? {
+        let store = Store::open(dir);
+        eprintln!("{}:", store.root().display());
+        let micro = micro || (!heuristic && !llm::budget_remaining());
+        let mode = if micro { "micro" } else if heuristic { "heuristic" } else { "quick" };
+        let result = sleep_store(&store, micro, heuristic, false, false).await.map(|(run, _)| run);
+        let run = runs::StoreRun::from_result(store.root(), mode, result);
+        if let Some(e) = run.error.as_ref().filter(|_| run.outcome == runs::Outcome::Failed) {
+            eprintln!("Failed: {}", e);
+        }
+        stores.push(run);
+    }
+
+    let count = |outcome| stores.iter().filter(|s| s.outcome == outcome).count();
+    let consolidations: usize = stores.iter().map(|s| s.consolidations).sum();
+    let removed: u64 = stores.iter().map(|s| s.removed).sum();
+    let failed = count(runs::Outcome::Failed);
+    eprintln!(
+        "Slept {} stores ({} with nothing to do, {} failed). {} consolidations, {} removed, {} LLM calls.",
+        stores.len() - failed,
+        count(runs::Outcome::NothingToDo),
+        failed,
+        consolidations,
+        removed,
+        llm::calls_made()
+    );
+    if ci {
+        println!(
+            "{}",
+            serde_json::json!({
+                "ok": failed == 0,
+                "stores": stores,
+                "consolidations": consolidations,
+                "removed": removed,
+                "llm_calls": llm::calls_made(),
+            })
+        );
+    }
+    Ok(stores)
+}
+
+/// `sleep --batch-api` or `dream --batch-api` in one store.
+async fn submit_batch(store: &Store, purpose: &str) -> Result<runs::StoreRun> {
+    let (raw, cons, config) = (store.raw()?, store.cons()?, store.config()?);
+    let submitted = if purpose == batch::DREAM {
+        dream::submit_batch(raw, cons, config, store.dir()).await?
+    } else {
+        sleep::submit_batch(raw, cons, config, store.dir()).await?
+    };
+    let outcome = match submitted {
+        Some(id) => {
+            eprintln!("Submitted {} batch {}. Run `cortex sleep --collect` once it has finished.", purpose, id);
+            runs::Outcome::Done
+        }
+        None => {
+            eprintln!("Nothing to send.");
+            runs::Outcome::NothingToDo
+        }
+    };
+    Ok(runs::StoreRun::new(store.root(), "batch", outcome))
+}
+
+/// `sleep --collect` in one store.
+async fn collect_batches(store: &Store) -> Result<runs::StoreRun> {
+    let collected = batch::collect(store.raw()?, store.cons()?, store.config()?, store.dir()).await?;
+    eprintln!(
+        "Collected {} batches; {} still processing, {} failed.",
+        collected.applied, collected.waiting, collected.failed
+    );
+    let outcome = match (collected.applied, collected.failed) {
+        (0, 0) => runs::Outcome::NothingToDo,
+        (0, _) => runs::Outcome::Failed,
+        (_, 0) => runs::Outcome::Done,
+        _ => runs::Outcome::Partial,
+    };
+    Ok(runs::StoreRun::new(store.root(), "collect", outcome))
+}
+
+/// Under `--unattended`: write the run summary to `cortex_dir/runs/` and exit with the
+/// outcome's code (returns only when the run did some work without failures).
+fn finish_unattended(
+    cortex_dir: &std::path::Path,
+    command: &str,
+    started: chrono::DateTime<chrono::Utc>,
+    stores: Vec<runs::StoreRun>,
+) -> Result<()> {
+    for run in stores.iter().filter(|s| s.outcome == runs::Outcome::Failed) {
+        eprintln!("Error: {}", run.error.as_deref().unwrap_or("unknown"));
+    }
+    let summary = runs::RunSummary::new(command, started, stores);
+    let path = runs::write(cortex_dir, &summary)?;
+    eprintln!("Run summary written to {}", path.display());
+    match summary.outcome.exit_code() {
+        0 => Ok(()),
+        code => std::process::exit(code),
+    }
+}
+
+/// Source of a CLI save for `[trust]`: `ci`, `unattended`, or `cli`.
+fn save_source(ci: bool, unattended: bool) -> &'static str {
+    if ci {
+        "ci"
+    } else if unattended {
+        "unattended"
+    } else {
+        "cli"
+    }
+}
+
+/// Refuse commands that prompt for input when running unattended.
+fn ensure_interactive(unattended: bool, operation: &str) -> Result<()> {
+    if unattended {
+        anyhow::bail!("{} is interactive and not available with --unattended.", operation);
+    }
+    Ok(())
+}
+
+/// Ask before a destructive operation; `--yes` skips the question, and is required when no one
+/// is there to answer it.
+fn confirm(question: &str, yes: bool, cli: &Cli, operation: &str) -> Result<bool> {
+    use std::io::{BufRead, IsTerminal, Write};
+    if yes {
+        return Ok(true);
+    }
+    if cli.unattended || cli.ci || !std::io::stdin().is_terminal() {
+        anyhow::bail!("{} needs confirmation; pass --yes to run it non-interactively.", operation);
+    }
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    // Extensions run native code, so they come only from the user's own global config
+    if let Some(dir) = init::find_global_dir()
+        && let Ok(config) = config::load_config(&dir)
+    {
+        extensions::configure(&config.db.extensions, &dir);
+    }
+    if !cli.ci {
+        return run(cli).await;
+    }
+
+    // Servers run until stopped, which the watchdog below would cut short
+    let server = match &cli.command {
+        Commands::Mcp { command: None } => Some("mcp"),
+        Commands::Serve { issue_token: None, .. } => Some("serve"),
+        _ => None,
+    };
+    if let Some(name) = server {
+        let error = format!("{} runs until stopped and is not available in CI mode.", name);
+        println!("{}", serde_json::json!({ "ok": false, "error": error }));
+        std::process::exit(1);
+    }
+
+    llm::disable_llm();
+    // Watchdog thread: blocking SQLite work can't be cancelled by a tokio timeout,
+    // so bail out of the whole process if the budget is exceeded.
+    std::thread::spawn(|| {
+        std::thread::sleep(std::time::Duration::from_secs(CI_TIMEOUT_SECS));
+        println!("{}", serde_json::json!({ "ok": false, "error": format!("timed out after {}s", CI_TIMEOUT_SECS) }));
+        std::process::exit(1);
+    });
+    if let Err(e) = run(cli).await {
+        println!("{}", serde_json::json!({ "ok": false, "error": format!("{:#}", e) }));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    if let Some(url) = cli.remote.clone().or_else(|| std::env::var("CORTEX_REMOTE").ok().filter(|u| !u.is_empty())) {
+        return run_remote(&remote::Client::new(&url, session_id()), cli).await;
+    }
+    match cli.command {
+        Commands::Init => match (cli.dir, init::dir_from_env()) {
+            (None, Some(dir)) => {
+                init::init_dir(&dir)?;
+                projects::remember(&dir);
+            }
+            (base, _) => {
+                let base = base.unwrap_or(std::env::current_dir()?);
+                init::init_cortex(&base)?;
+                projects::remember(&base.join(".cortex"));
+            }
+        },
+        Commands::Save(args) => {
+            config::ensure_writable(cli.read_only, "save")?;
+            if args.opens_editor() {
+                ensure_interactive(cli.unattended, "save --edit")?;
+            }
+            save_memory(&find_store(&cli.dir)?, args, cli.ci, save_source(cli.ci, cli.unattended)).await?;
+        }
+        Commands::Recall(args) => {
+            recall_memories(&find_store(&cli.dir)?, Store::global().as_ref(), args, cli.read_only, cli.ci).await?;
+        }
+        Commands::Stats { global, mut args } => {
+            args.json |= cli.ci;
+            if global {
+                let global = Store::require_global()?;
+                let global_cons = global.cons()?;
+                let cons_count: i64 = global_cons.query_row("SELECT COUNT(*) FROM consolidated", [], |r| r.get(0))?;
+                let skill_count: i64 = global_cons.query_row("SELECT COUNT(*) FROM skills", [], |r| r.get(0))?;
+                let last_sleep = db::get_meta(global_cons, "last_sleep")?;
+                if args.json {
+                    println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                        "global_consolidated": cons_count,
+                        "global_skills": skill_count,
+                        "global_last_sleep": last_sleep,
+                    }))?);
+                } else {
+                    println!("Global consolidated: {}", cons_count);
+                    println!("Global skills: {}", skill_count);
+                    if let Some(ref last) = last_sleep {
+                        println!("Global last sleep: {}", last);
+                    }
+                }
+            } else {
+                print_stats(&find_store(&cli.dir)?, Store::global().as_ref(), args)?;
+            }
+        }
+        Commands::Timeline { weeks, json } => {
+            let store = find_store(&cli.dir)?;
+            let activity = timeline::build(store.raw()?, store.cons()?, weeks, chrono::Utc::now().date_naive())?;
+            if json || cli.ci {
+                println!("{}", serde_json::to_string_pretty(&activity)?);
+            } else {
+                print!("{}", timeline::render(&activity));
+            }
+        }
+        Commands::Reindex { global } => {
+            config::ensure_writable(cli.read_only, "reindex")?;
+            let store = if global { Store::require_global()? } else { find_store(&cli.dir)? };
+            let tokenizer = store.config()?.search.tokenizer;
+            let mut rebuilt = db::set_fts_tokenizer(store.raw()?, tokenizer)?;
+            rebuilt.extend(db::set_fts_tokenizer(store.cons()?, tokenizer)?);
+            if rebuilt.is_empty() {
+                eprintln!("Indexes already use the {} tokenizer.", tokenizer);
+            } else {
+                eprintln!("Reindexed {} with the {} tokenizer.", rebuilt.join(", "), tokenizer);
+            }
+            if cli.ci {
+                println!("{}", serde_json::json!({ "ok": true, "tokenizer": tokenizer, "rebuilt": rebuilt }));
+            }
+        }
+        Commands::Compact { global } => {
+            config::ensure_writable(cli.read_only, "compact")?;
+            let store = if global { Store::require_global()? } else { find_store(&cli.dir)? };
+            let _lock = lock::StoreLock::acquire(store.dir(), "compact")?;
+            let raw_conn = store.raw()?;
+            let size = || std::fs::metadata(store.dir().join("raw.db")).map(|m| m.len()).unwrap_or(0);
+            let before = size();
+            let compacted = compress::compact(raw_conn, &store.config()?.storage)?;
+            raw_conn.execute_batch("VACUUM;")?;
+            let after = size();
+            eprintln!(
+                "Compressed {} rows ({} -> {} bytes). raw.db: {} -> {} bytes.",
+                compacted.rows, compacted.bytes_before, compacted.bytes_after, before, after
+            );
+            if cli.ci {
+                println!(
+                    "{}",
+                    serde_json::json!({ "ok": true, "compressed": compacted.rows, "size_before": before, "size_after": after })
+                );
+            }
+        }
+        Commands::Export { format, stream, anonymize, since, output, max_sensitivity } => {
+            let store = find_store(&cli.dir)?;
+            let max = max_sensitivity.unwrap_or(store.config()?.sensitivity.share_max);
+            let mut anonymizer = anonymize.then(|| redact::Anonymizer::for_project(&store.root()));
+            if since.is_some() && format != "patch" {
+                anyhow::bail!("--since only applies to patch exports.");
+            }
+            if stream && format != "jsonl" {
+                anyhow::bail!("--stream only applies to --format jsonl.");
+            }
+            if format == "jsonl" {
+                let written = match &output {
+                    Some(path) => {
+                        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+                        jsonl::export(store.raw()?, store.cons()?, &mut file, max, stream, anonymizer.as_mut())?
+                    }
+                    None => match jsonl::export(store.raw()?, store.cons()?, &mut std::io::stdout().lock(), max, stream, anonymizer.as_mut()) {
+                        Ok(written) => written,
+                        // A reader like `head` that stops early is done, not failed
+                        Err(e) if e.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe) => {
+                            return Ok(());
+                        }
+                        Err(e) => return Err(e),
+                    },
+                };
+                if let Some(path) = &output {
+                    eprintln!("Wrote {} records to {}.", written.total(), path.display());
+                }
+                return Ok(());
+            }
+            if format == "obsidian" {
+                let Some(vault) = output else {
+                    anyhow::bail!("--format obsidian needs the vault directory: --out <vault-dir>.");
+                };
+                let exported = obsidian::export(store.raw()?, store.cons()?, &vault, max, anonymizer.as_mut())?;
+                eprintln!(
+                    "Wrote {} memories, {} consolidated memories, and {} skills to {}.",
+                    exported.memories,
+                    exported.consolidated,
+                    exported.skills,
+                    vault.join("cortex").display()
+                );
+                if cli.ci {
+                    println!("{}", serde_json::json!({ "ok": true, "exported": exported }));
+                }
+                return Ok(());
+            }
+            let mut patch = patch::export(store.cons()?, store.dir(), since.as_deref(), max)?;
+            if let Some(a) = &mut anonymizer {
+                a.patch(&mut patch);
+            }
+            let text = serde_json::to_string_pretty(&patch)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, text + "\n")?;
+                    eprintln!(
+                        "Wrote {} changed and {} deleted memories to {}.",
+                        patch.upserts.len(),
+                        patch.deletes.len(),
+                        path.display()
+                    );
+                }
+                None => println!("{}", text),
+            }
+        }
+        Commands::Import { patch: path } => {
+            config::ensure_writable(cli.read_only, "import")?;
+            let store = find_store(&cli.dir)?;
+            let patch = patch::parse(&std::fs::read_to_string(&path)?)?;
+            let _lock = lock::StoreLock::acquire(store.dir(), "import")?;
+            snapshot_before(&store, "import")?;
+            let applied = patch::apply(store.cons()?, &patch)?;
+            skills::generate_skill_files(store.cons()?, &store.dir().join("skills"))?;
+            eprintln!(
+                "Imported {}: {} added, {} updated, {} deleted, {} unchanged.",
+                path.display(),
+                applied.added,
+                applied.updated,
+                applied.deleted,
+                applied.unchanged
+            );
+            if cli.ci {
+                println!("{}", serde_json::json!({ "ok": true, "applied": applied }));
+            }
+        }
+        Commands::Merge { other, dry_run, json } => {
+            if !dry_run {
+                config::ensure_writable(cli.read_only, "merge")?;
+            }
+            let store = find_store(&cli.dir)?;
+            let other = merge::store_dir(&other)?;
+            if other.canonicalize()? == store.dir().canonicalize()? {
+                anyhow::bail!("Cannot merge a store into itself.");
+            }
+            let _lock = lock::StoreLock::acquire(store.dir(), "merge")?;
+            if !dry_run {
+                snapshot_before(&store, "merge")?;
+            }
+            let report = merge::merge(store.raw()?, store.cons()?, &other, dry_run)?;
+            if !dry_run {
+                skills::generate_skill_files(store.cons()?, &store.dir().join("skills"))?;
+            }
+            if json || cli.ci {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                for c in &report.conflicts {
+                    println!("Conflict with #{} ({}):\n  ours:   {}\n  theirs: {}", c.ours_id, c.reason, c.ours, c.theirs);
+                }
+                eprintln!(
+                    "{}{} added, {} merged into existing memories, {} conflicts{}.",
+                    if dry_run { "Dry run: " } else { "" },
+                    report.added,
+                    report.merged,
+                    report.conflicts.len(),
+                    if report.conflicts.is_empty() || dry_run { "" } else { " (queued for the next sleep to resolve)" }
+                );
+            }
+        }
+        Commands::Snapshot { command, global } => {
+            let store = if global { Store::require_global()? } else { find_store(&cli.dir)? };
+            match command {
+                SnapshotCommands::Create { label } => {
+                    let s = snapshot::create(store.raw()?, store.cons()?, store.dir(), &label)?;
+                    eprintln!("Created snapshot {} ({} bytes).", s.name, s.bytes);
+                    if cli.ci {
+                        println!("{}", serde_json::json!({ "ok": true, "snapshot": s }));
+                    }
+                }
+                SnapshotCommands::List { json } => {
+                    let snapshots = snapshot::list(store.dir())?;
+                    if json || cli.ci {
+                        println!("{}", serde_json::to_string_pretty(&snapshots)?);
+                    } else if snapshots.is_empty() {
+                        println!("No snapshots.");
+                    } else {
+                        for s in &snapshots {
+                            let kind = if s.automatic { "auto" } else { "manual" };
+                            println!("{}  {}  {:>10} bytes  {}", s.name, s.created_at, s.bytes, kind);
+                        }
+                    }
+                }
+                SnapshotCommands::Restore { name } => {
+                    config::ensure_writable(cli.read_only, "snapshot restore")?;
+                    let s = snapshot::restore(store.raw()?, store.cons()?, store.dir(), &name)?;
+                    eprintln!("Restored snapshot {} from {}.", s.name, s.created_at);
+                    if cli.ci {
+                        println!("{}", serde_json::json!({ "ok": true, "restored": s.name }));
+                    }
+                }
+            }
+        }
+        Commands::Doctor { fix, global } => {
+            if fix {
+                config::ensure_writable(cli.read_only, "doctor --fix")?;
+            }
+            let store = if global { Store::require_global()? } else { find_store(&cli.dir)? };
+            let _lock = if fix { Some(lock::StoreLock::acquire(store.dir(), "doctor --fix")?) } else { None };
+            if fix {
+                snapshot_before(&store, "doctor-fix")?;
+            }
+            let findings = doctor::check(store.raw()?, store.cons()?, fix)?;
+            let unfixed = findings.iter().filter(|f| !f.fixed).count();
+            if cli.ci {
+                println!("{}", serde_json::json!({ "ok": unfixed == 0, "findings": findings }));
+            } else if findings.is_empty() {
+                eprintln!("No problems found.");
+            } else {
+                for f in &findings {
+                    eprintln!("[{}] {}{}", f.check, f.problem, if f.fixed { " (fixed)" } else { "" });
+                }
+            }
+            if unfixed > 0 {
+                let hint = if fix { "" } else { " Run `cortex doctor --fix` to repair." };
+                anyhow::bail!("{} problem(s) not fixed.{}", unfixed, hint);
+            }
+        }
+        Commands::ReviewStale { days, limit, list, json, global } => {
+            let json = json || cli.ci;
+            let store = if global { Store::require_global()? } else { find_store(&cli.dir)? };
+            let cons_conn = store.cons()?;
+            let queue = review::stale(cons_conn, days, limit)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&queue)?);
+            } else if queue.is_empty() {
+                eprintln!("No consolidated memories untouched for {} days.", days);
+            } else if list {
+                for s in &queue {
+                    let m = &s.memory;
+                    let first = m.content.lines().next().unwrap_or("");
+                    println!("#{} [{}] {:.0}d, confidence {:.2}, risk {:.2}: {}", m.id, m.r#type, s.age_days, m.confidence, s.risk, first);
+                }
+            } else {
+                config::ensure_writable(cli.read_only, "review-stale")?;
+                ensure_interactive(cli.unattended, "review-stale")?;
+                let summary = review::run(cons_conn, &queue, &mut std::io::stdin().lock(), &mut std::io::stdout())?;
+                eprintln!(
+                    "Reviewed: {} confirmed, {} refreshed, {} decayed, {} skipped.",
+                    summary.confirmed, summary.refreshed, summary.decayed, summary.skipped
+                );
+            }
+        }
+        Commands::Sleep { micro, heuristic, global, from_ci, all_projects, max_llm_calls, batch_api, collect, .. } => {
+            config::ensure_writable(cli.read_only, "sleep")?;
+            // CI never calls an LLM, so only the SQL-only and heuristic passes are available
+            let micro = (micro || cli.ci) && !heuristic;
+            let mode = if micro { "micro" } else if heuristic { "heuristic" } else { "quick" };
+            let started = chrono::Utc::now();
+            if batch_api || collect {
+                if cli.ci {
+                    anyhow::bail!("The batch API is not available in CI mode.");
+                }
+                let store = if global { Store::ensure_global()? } else { find_store(&cli.dir)? };
+                let (mode, command, result) = if batch_api {
+                    ("batch", "sleep --batch-api", submit_batch(&store, batch::SLEEP).await)
+                } else {
+                    ("collect", "sleep --collect", collect_batches(&store).await)
+                };
+                if cli.unattended {
+                    let run = runs::StoreRun::from_result(store.root(), mode, result);
+                    finish_unattended(store.dir(), command, started, vec![run])?;
+                } else {
+                    result?;
+                }
+            } else if all_projects {
+                if let Some(max) = max_llm_calls {
+                    llm::set_call_budget(max);
+                }
+                let stores = sleep_all_projects(micro, heuristic, cli.ci).await?;
+                if cli.unattended {
+                    finish_unattended(Store::ensure_global()?.dir(), "sleep --all-projects", started, stores)?;
+                }
+            } else if global {
+                let global = Store::ensure_global()?;
+                let result = sleep_store(&global, micro, heuristic, cli.ci, false).await;
+                if cli.unattended {
+                    let run = runs::StoreRun::from_result(global.root(), mode, result.map(|(run, _)| run));
+                    finish_unattended(global.dir(), "sleep --global", started, vec![run])?;
+                } else {
+                    result?;
+                }
+            } else {
+                let store = find_store(&cli.dir)?;
+                let config = store.config()?;
+
+                let gh = if from_ci {
+                    let gh = github::GithubContext::from_env()?;
+                    let tagged = github::tag_unconsolidated(store.raw()?, &gh)?;
+                    Some((gh, tagged))
+                } else {
+                    None
+                };
+                let result = sleep_store(&store, micro, heuristic, cli.ci, from_ci).await;
+                if cli.unattended && result.is_err() {
+                    let run = runs::StoreRun::from_result(store.root(), mode, result.map(|(run, _)| run));
+                    return finish_unattended(store.dir(), "sleep", started, vec![run]);
+                }
+                let (run, consolidated) = result?;
+
+                if let Some((gh, tagged)) = gh {
+                    github::write_job_summary(&gh, tagged, consolidated.as_ref(), run.removed)?;
+                    if config.ci.commit {
+                        if github::commit_consolidated(&store.root(), store.dir(), store.cons()?, &config.ci.commit_message)? {
+                            eprintln!("Committed updated consolidated memories.");
+                        } else {
+                            eprintln!("Consolidated memories unchanged; nothing to commit.");
+                        }
+                    }
+                }
+                if cli.unattended {
+                    finish_unattended(store.dir(), "sleep", started, vec![run])?;
+                }
+            }
+        }
+        Commands::Dream { global, batch_api, all_projects } => {
+            config::ensure_writable(cli.read_only, "dream")?;
+            if cli.ci {
+                anyhow::bail!("dream requires an LLM and is not available in CI mode.");
+            }
+            let started = chrono::Utc::now();
+            if all_projects {
+                let store = Store::ensure_global()?;
+                let stores = projects::known_stores()?;
+                let result =
+                    dream::dream_all_projects(&stores, store.raw()?, store.cons()?, store.config()?, store.dir()).await;
+                if let Ok(result) = &result {
+                    eprintln!(
+                        "Cross-project dream complete. {} memories from {} projects, {} insights recorded in the global store, {} refined.",
+                        result.sampled, result.projects, result.insights, result.insights_refined
+                    );
+                }
+                if cli.unattended {
+                    let run = result.map(|_| runs::StoreRun::new(store.root(), "dream", runs::Outcome::Done));
+                    let run = runs::StoreRun::from_result(store.root(), "dream", run);
+                    return finish_unattended(store.dir(), "dream --all-projects", started, vec![run]);
+                }
+                result?;
+                return Ok(());
+            }
+            let store = if global { Store::ensure_global()? } else { find_store(&cli.dir)? };
+            if batch_api {
+                let result = submit_batch(&store, batch::DREAM).await;
+                if cli.unattended {
+                    let run = runs::StoreRun::from_result(store.root(), "batch", result);
+                    return finish_unattended(store.dir(), "dream --batch-api", started, vec![run]);
+                }
+                result?;
+                return Ok(());
+            }
+            let result = dream::dream(store.raw()?, store.cons()?, store.config()?, store.dir()).await;
+            if let Ok(result) = &result {
+                eprintln!(
+                    "{} complete. {} insights generated, {} refined, {} skills updated, {} condensed, {} removed, {} decayed, {} merged.",
+                    if global { "Global dream" } else { "Dream" },
+                    result.insights,
+                    result.insights_refined,
+                    result.skills_updated,
+                    result.skills_condensed,
+                    result.skills_removed,
+                    result.decayed,
+                    result.merged
+                );
+            }
+            if cli.unattended {
+                let run = result.map(|_| runs::StoreRun::new(store.root(), "dream", runs::Outcome::Done));
+                let command = if global { "dream --global" } else { "dream" };
+                finish_unattended(store.dir(), command, started, vec![runs::StoreRun::from_result(store.root(), "dream", run)])?;
+            } else {
+                result?;
+            }
+        }
+        Commands::Edit { id, content } => {
+            config::ensure_writable(cli.read_only, "edit")?;
+            // Global memory (negative IDs map to positive global IDs)
+            let store = if id < 0 { Store::require_global()? } else { find_store(&cli.dir)? };
+            snapshot_before(&store, "edit")?;
+            if id < 0 {
+                let real_id = -id;
+                if db::update_consolidated(store.cons()?, real_id, &content)? {
+                    eprintln!("Updated global memory #{}", real_id);
                 } else {
                     eprintln!("Global memory #{} not found.", real_id);
                 }
             } else {
-                let cortex_dir = find_cortex_dir(&cli.dir)?;
-                let cons_conn = db::open_consolidated_db(&cortex_dir.join("consolidated.db"))?;
-                if db::update_consolidated(&cons_conn, id, &content)? {
+                if db::update_consolidated(store.cons()?, id, &content)? {
                     eprintln!("Updated consolidated memory #{}", id);
                 } else {
                     eprintln!("Consolidated memory #{} not found.", id);
                 }
             }
         }
+        Commands::Ask { question, limit, json } => {
+            let store = find_store(&cli.dir)?;
+            let global = Store::global();
+            let global_cons = store::global_cons(global.as_ref());
+            let answer = ask::ask(store.raw()?, store.cons()?, global_cons, &question, limit, store.config()?).await?;
+            if json || cli.ci {
+                println!("{}", serde_json::to_string_pretty(&answer)?);
+            } else {
+                println!("{}", answer.answer);
+                if !answer.citations.is_empty() {
+                    println!("\nSources:");
+                    for s in &answer.citations {
+                        let first_line = s.content.lines().next().unwrap_or("");
+                        println!("  [{}] {} #{} ({}): {}", s.key, s.kind, s.id, s.r#type, first_line);
+                    }
+                }
+            }
+        }
+        Commands::Chat => {
+            if cli.ci {
+                anyhow::bail!("chat is interactive and not available in CI mode.");
+            }
+            ensure_interactive(cli.unattended, "chat")?;
+            let store = find_store(&cli.dir)?;
+            let global = Store::global();
+            let sid = session_id();
+            let chat = chat::Chat {
+                raw: store.raw()?,
+                cons: store.cons()?,
+                global: store::global_cons(global.as_ref()),
+                config: store.config()?,
+                dir: store.dir(),
+                session_id: &sid,
+                read_only: cli.read_only,
+            };
+            chat.run(&mut std::io::stdin().lock(), &mut std::io::stdout()).await?;
+        }
+        Commands::Similar { id, text, consolidated, limit, json } => {
+            let store = find_store(&cli.dir)?;
+            let (raw_conn, cons_conn) = (store.raw()?, store.cons()?);
+            let results = match (id, text) {
+                (Some(id), _) => {
+                    let source = if consolidated { similar::Source::Consolidated } else { similar::Source::Raw };
+                    similar::similar_to_memory(raw_conn, cons_conn, source, id, limit)?
+                }
+                (None, text) => similar::similar_to_text(raw_conn, cons_conn, &text.unwrap_or_default(), None, limit)?,
+            };
+            if json || cli.ci {
+                println!("{}", serde_json::to_string_pretty(&results)?);
+            } else if results.is_empty() {
+                eprintln!("No similar memories found.");
+            } else {
+                for m in &results {
+                    let label = match m.source {
+                        similar::Source::Raw => "",
+                        similar::Source::Consolidated => "consolidated ",
+                    };
+                    println!("[{}] {}#{} ({:.2}): {}", m.r#type, label, m.id, m.score, m.content);
+                }
+            }
+        }
+        Commands::Suggest { prefix, limit, json } => {
+            let store = find_store(&cli.dir)?;
+            let suggestions = suggest::suggest(store.raw()?, store.cons()?, &prefix, limit)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&suggestions)?);
+            } else {
+                for s in &suggestions {
+                    println!("{}", s.text);
+                }
+            }
+        }
+        Commands::Why { id, reason, json } => {
+            let store = if id < 0 { Store::require_global()? } else { find_store(&cli.dir)? };
+            let mut provenance = why::trace(store.raw()?, store.cons()?, id.abs())?;
+            if reason {
+                why::reason(store.raw()?, &mut provenance, store.config()?).await?;
+            }
+            if json || cli.ci {
+                println!("{}", serde_json::to_string_pretty(&provenance)?);
+            } else {
+                print!("{}", why::format(&provenance));
+            }
+        }
+        Commands::Show { id, json } => {
+            let store = if id < 0 { Store::require_global()? } else { find_store(&cli.dir)? };
+            let shown = outcome::show(store.cons()?, id.abs())?;
+            if json || cli.ci {
+                println!("{}", serde_json::to_string_pretty(&shown)?);
+            } else {
+                print!("{}", outcome::format(&shown));
+            }
+        }
+        Commands::Outcome { id, worked, note, .. } => {
+            config::ensure_writable(cli.read_only, "record an outcome")?;
+            let store = if id < 0 { Store::require_global()? } else { find_store(&cli.dir)? };
+            outcome::record(store.cons()?, id.abs(), worked, &note)?;
+            let shown = outcome::show(store.cons()?, id.abs())?;
+            eprintln!(
+                "Recorded: #{} {} ({} worked, {} failed). Confidence changes at the next sleep.",
+                id,
+                if worked { "worked" } else { "failed" },
+                shown.worked,
+                shown.failed
+            );
+        }
+        Commands::Attachment { id } => {
+            match db::get_attachment(find_store(&cli.dir)?.raw()?, id)? {
+                Some(full) if cli.ci => println!("{}", serde_json::json!({ "ok": true, "id": id, "content": full })),
+                Some(full) => println!("{}", full),
+                None => anyhow::bail!("Memory #{} has no attachment (it was not shortened, or has been removed).", id),
+            }
+        }
+        Commands::Reset { raw, consolidated, skills, all, from_git, yes } => {
+            config::ensure_writable(cli.read_only, "reset")?;
+            let scope = if all { reset::Scope::all() } else { reset::Scope { raw, consolidated, skills } };
+            if scope.is_empty() {
+                anyhow::bail!("Choose what to reset: --raw, --consolidated, --skills, or --all.");
+            }
+            let store = find_store(&cli.dir)?;
+            let what = scope.describe();
+            let question = format!("Clear {} in {}?", what, store.dir().display());
+            if !confirm(&question, yes, &cli, "reset")? {
+                eprintln!("Nothing reset.");
+                return Ok(());
+            }
+            let _lock = lock::StoreLock::acquire(store.dir(), "reset")?;
+            snapshot_before(&store, "reset")?;
+            reset::reset(store.dir(), scope, from_git)?;
+            let seeded = if from_git && (scope.consolidated || scope.skills) { " (re-seeded from git HEAD)" } else { "" };
+            eprintln!("Reset {}{}. `cortex snapshot list` shows the snapshot taken first.", what, seeded);
+        }
+        Commands::Uninit { yes } => {
+            config::ensure_writable(cli.read_only, "uninit")?;
+            let store = find_store(&cli.dir)?;
+            let question = format!("Delete {} and every memory in it?", store.dir().display());
+            if !confirm(&question, yes, &cli, "uninit")? {
+                eprintln!("Nothing removed.");
+                return Ok(());
+            }
+            let _lock = lock::StoreLock::acquire(store.dir(), "uninit")?;
+            init::uninit(store.dir(), &store.root())?;
+            eprintln!("Removed {}", store.dir().display());
+        }
         Commands::Delete { id } => {
+            config::ensure_writable(cli.read_only, "delete")?;
+            let store = if id < 0 { Store::require_global()? } else { find_store(&cli.dir)? };
+            snapshot_before(&store, "delete")?;
             if id < 0 {
-                let global_dir = init::find_global_dir()
-                    .ok_or_else(|| anyhow::anyhow!("No global ~/.cortex/ directory found."))?;
-                let global_cons = db::open_consolidated_db(&global_dir.join("consolidated.db"))?;
                 let real_id = -id;
-                db::remove_consolidated(&global_cons, &[real_id])?;
+                db::remove_consolidated(store.cons()?, &[real_id])?;
                 eprintln!("Deleted global memory #{}", real_id);
             } else {
-                let cortex_dir = find_cortex_dir(&cli.dir)?;
-                let cons_conn = db::open_consolidated_db(&cortex_dir.join("consolidated.db"))?;
-                db::remove_consolidated(&cons_conn, &[id])?;
+                db::remove_consolidated(store.cons()?, &[id])?;
                 eprintln!("Deleted consolidated memory #{}", id);
             }
         }
-        Commands::Wake => {
-            let cortex_dir = find_cortex_dir(&cli.dir)?;
-            let config = config::load_config(&cortex_dir)?;
-            let raw_conn = db::open_raw_db(&cortex_dir.join("raw.db"))?;
-            let cons_conn = db::open_consolidated_db(&cortex_dir.join("consolidated.db"))?;
-            let global_cons = open_global_cons();
-            let ctx = wake::wake(&raw_conn, &cons_conn, &config, &cortex_dir, global_cons.as_ref()).await?;
-            println!("{}", ctx);
-        }
-        Commands::Context { compact, query, limit } => {
-            let cortex_dir = find_cortex_dir(&cli.dir)?;
-            let raw_conn = db::open_raw_db(&cortex_dir.join("raw.db"))?;
-            let cons_conn = db::open_consolidated_db(&cortex_dir.join("consolidated.db"))?;
-            let global_cons = open_global_cons();
-            let ctx = context::format_context(
-                &cons_conn,
-                &raw_conn,
-                global_cons.as_ref(),
-                compact,
-                query.as_deref(),
+        Commands::Wake { hook: Some(shell), .. } => {
+            // Shell startup must not fail: outside a project, say nothing
+            let Ok(store) = find_store(&cli.dir) else { return Ok(()) };
+            let root = std::fs::canonicalize(store.root())?;
+            let project = root.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            eprint!("{}", wake::hook_summary(store.raw()?, store.cons()?, &project)?);
+            println!("{}", wake::export_line(shell, "CORTEX_SESSION_ID", &session_id()));
+        }
+        Commands::Wake { hook: None, background } => {
+            let store = find_store(&cli.dir)?;
+            let global = Store::global();
+            let global_cons = store::global_cons(global.as_ref());
+            let (raw_conn, cons_conn, config) = (store.raw()?, store.cons()?, store.config()?);
+            let sections = plugins::context_sections(&plugins::load_plugins(store.dir()).await, context::ContextFormat::Full).await;
+            let ctx = wake::wake(raw_conn, cons_conn, config, store.dir(), global_cons, &sections, cli.read_only, background).await?;
+            if cli.ci {
+                println!("{}", serde_json::json!({ "ok": true, "context": ctx }));
+            } else {
+                println!("{}", ctx);
+            }
+        }
+        Commands::Context(args) => {
+            let store = match find_store(&cli.dir) {
+                Ok(d) => d,
+                // A repo without memory is not a CI failure: inject nothing
+                Err(e) if cli.ci => {
+                    println!("{}", serde_json::json!({ "ok": true, "context": "", "warning": e.to_string() }));
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            };
+            print_context(&store, Store::global().as_ref(), args, cli.read_only, cli.ci).await?;
+        }
+        Commands::Mcp { command: Some(McpCommands::Install { client, dry_run }) } => {
+            let store = find_store(&cli.dir)?;
+            let root = std::fs::canonicalize(store.root())?;
+            let installed = mcp_install::install(client, &std::env::current_exe()?, &root, dry_run)?;
+            if dry_run {
+                print!("{}", installed.contents);
+            } else {
+                let verb = if installed.replaced { "Updated" } else { "Added" };
+                eprintln!("{} MCP server \"{}\" in {}", verb, installed.name, installed.path.display());
+                eprintln!("Restart {} to pick it up.", client.name());
+            }
+        }
+        Commands::Mcp { command: None } => {
+            let store = find_store(&cli.dir)?;
+            let read_only = cli.read_only || store.config()?.mcp.read_only;
+            mcp::run_mcp_server(&store, Store::global(), session_id(), read_only).await?;
+        }
+        Commands::Serve { root, addr, issue_token } => match issue_token {
+            Some(name) => {
+                println!("{}", remote::issue_token(&root, &name)?);
+                eprintln!("Saved its hash to {}; clients set CORTEX_REMOTE_TOKEN to it.", root.join("serve.toml").display());
+            }
+            None => remote::serve(&root, &addr, cli.read_only).await?,
+        },
+        Commands::Trust { ids, all, plugins, hooks, db, revoke, json } => {
+            let store = find_store(&cli.dir)?;
+            if plugins || hooks || db {
+                let path = trust::default_path().ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+                let capabilities =
+                    [(plugins, trust::Capability::Plugins), (hooks, trust::Capability::Hooks), (db, trust::Capability::Db)];
+                for (_, capability) in capabilities.into_iter().filter(|(on, _)| *on) {
+                    let changed = trust::set(&path, store.dir(), capability, !revoke)?;
+                    let verb = match (revoke, changed) {
+                        (false, true) => "Trusted",
+                        (false, false) => "Already trusted",
+                        (true, true) => "Revoked trust in",
+                        (true, false) => "Never trusted",
+                    };
+                    eprintln!("{} {} of {}.", verb, capability.name(), store.root().display());
+                }
+                if cli.ci {
+                    println!("{}", serde_json::json!({ "ok": true }));
+                }
+                return Ok(());
+            }
+            if revoke {
+                anyhow::bail!("--revoke needs --plugins, --hooks or --db.");
+            }
+            let raw_conn = store.raw()?;
+            if ids.is_empty() && !all {
+                let untrusted = db::get_untrusted_memories(raw_conn)?;
+                if json || cli.ci {
+                    println!("{}", serde_json::to_string_pretty(&untrusted)?);
+                } else if untrusted.is_empty() {
+                    println!("No untrusted memories.");
+                } else {
+                    for m in &untrusted {
+                        println!("#{} [{}] {} ({})", m.id, m.r#type, m.content, m.created_at);
+                    }
+                }
+            } else {
+                config::ensure_writable(cli.read_only, "trust")?;
+                let ids = if all { db::get_untrusted_memories(raw_conn)?.into_iter().map(|m| m.id).collect() } else { ids };
+                let approved = db::set_trusted(raw_conn, &ids, true)?;
+                eprintln!("Trusted {} memories.", approved);
+                if cli.ci {
+                    println!("{}", serde_json::json!({ "ok": true, "trusted": approved }));
+                }
+            }
+        }
+        Commands::Label { sensitivity, ids, consolidated } => {
+            config::ensure_writable(cli.read_only, "label")?;
+            let store = find_store(&cli.dir)?;
+            let labeled = if consolidated {
+                db::set_consolidated_sensitivity(store.cons()?, &ids, sensitivity)?
+            } else {
+                db::set_memory_sensitivity(store.raw()?, &ids, sensitivity)?
+            };
+            eprintln!("Labeled {} memories {}.", labeled, sensitivity);
+            if cli.ci {
+                println!("{}", serde_json::json!({ "ok": true, "labeled": labeled, "sensitivity": sensitivity }));
+            }
+        }
+        Commands::Audit { days, memory, purpose, limit, json } => {
+            let store = find_store(&cli.dir)?;
+            let filter = audit::Filter {
+                since: days.map(|d| (chrono::Utc::now() - chrono::Duration::days(d)).format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+                memory: memory.as_deref().map(audit::normalize_key),
+                purpose,
                 limit,
-            )?;
-            println!("{}", ctx);
+            };
+            let entries = audit::query(store.raw()?, &filter)?;
+            if json || cli.ci {
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else if entries.is_empty() {
+                println!("No recorded LLM calls match.");
+            } else {
+                println!("{:<6} {:<20} {:<13} {:<40} {:>8} {:>8} {:>8}  MANIFEST", "ID", "AT", "PURPOSE", "MODEL", "MEMORIES", "IN", "OUT");
+                for e in &entries {
+                    let tokens = |t: Option<i64>| t.map_or("-".to_string(), |t| t.to_string());
+                    println!(
+                        "{:<6} {:<20} {:<13} {:<40} {:>8} {:>8} {:>8}  {}{}",
+                        e.id,
+                        e.at,
+                        e.purpose,
+                        format!("{}/{}", e.provider, e.model),
+                        e.memories.len(),
+                        tokens(e.input_tokens),
+                        tokens(e.output_tokens),
+                        &e.manifest_hash[..12.min(e.manifest_hash.len())],
+                        if e.ok { "" } else { " (failed)" }
+                    );
+                }
+                let input: i64 = entries.iter().filter_map(|e| e.input_tokens).sum();
+                let output: i64 = entries.iter().filter_map(|e| e.output_tokens).sum();
+                println!("\n{} calls, {} input tokens, {} output tokens", entries.len(), input, output);
+            }
         }
-        Commands::Mcp => {
-            let cortex_dir = find_cortex_dir(&cli.dir)?;
-            let sid = session_id();
-            let global_dir = init::find_global_dir();
-            mcp::run_mcp_server(cortex_dir, sid, global_dir).await?;
+        Commands::Rejections { limit, json } => {
+            let store = find_store(&cli.dir)?;
+            let logged = quality::guard_log(store.raw()?, limit)?;
+            if json || cli.ci {
+                println!("{}", serde_json::to_string_pretty(&logged)?);
+            } else if logged.is_empty() {
+                println!("The hallucination guard has not caught anything.");
+            } else {
+                for l in &logged {
+                    println!("#{} {} {} [{}] (from {:?}): {}", l.id, l.at, l.action, l.r#type, l.source_ids, l.content);
+                    println!("    {}", l.reason);
+                }
+            }
+        }
+        Commands::Insights { days, limit, clear, json } => {
+            let store = find_store(&cli.dir)?;
+            if clear {
+                config::ensure_writable(cli.read_only, "insights --clear")?;
+                eprintln!("Deleted {} logged queries.", insights::clear(store.raw()?)?);
+                return Ok(());
+            }
+            if !store.config()?.insights.enabled {
+                eprintln!("Query logging is off; set [insights] enabled = true in .cortex/config.toml to collect data.");
+            }
+            let report = insights::report(store.raw()?, store.cons()?, days, limit)?;
+            if json || cli.ci {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{} recalls in the last {} days", report.queries, days);
+                println!("\nZero-hit queries:");
+                for q in &report.zero_hit {
+                    println!("  {:>4}x  {}", q.count, q.query);
+                }
+                println!("\nMost recalled:");
+                for r in &report.most_recalled {
+                    let confirmed = if r.confirmed > 0 { format!(" (confirmed {}x)", r.confirmed) } else { String::new() };
+                    println!("  {:>4}x  #{}: {}{}", r.count, r.id, r.content, confirmed);
+                }
+                println!("\nNever recalled (consolidated):");
+                for u in &report.never_recalled {
+                    println!("  [{}] #{}: {}", u.r#type, u.id, u.content);
+                }
+            }
+        }
+        Commands::Global { command } => match command {
+            GlobalCommands::Save(args) => {
+                config::ensure_writable(cli.read_only, "global save")?;
+                if args.opens_editor() {
+                    ensure_interactive(cli.unattended, "global save --edit")?;
+                }
+                save_memory(&Store::ensure_global()?, args, cli.ci, save_source(cli.ci, cli.unattended)).await?;
+            }
+            GlobalCommands::Recall(args) => recall_memories(&Store::require_global()?, None, args, cli.read_only, cli.ci).await?,
+            GlobalCommands::List(args) => list_consolidated(&Store::require_global()?, args, cli.ci)?,
+            GlobalCommands::Context(args) => print_context(&Store::require_global()?, None, args, cli.read_only, cli.ci).await?,
+            GlobalCommands::Stats(mut args) => {
+                args.json |= cli.ci;
+                print_stats(&Store::require_global()?, None, args)?;
+            }
+            GlobalCommands::Prune => {
+                config::ensure_writable(cli.read_only, "global prune")?;
+                prune_store(&Store::require_global()?, "Global prune", cli.ci).await?;
+            }
+            GlobalCommands::MigrateXdg => {
+                config::ensure_writable(cli.read_only, "global migrate-xdg")?;
+                if std::env::var_os(init::GLOBAL_DIR_ENV).is_some() {
+                    anyhow::bail!("{} is set, so the global store isn't in ~/.cortex/", init::GLOBAL_DIR_ENV);
+                }
+                let (Some(legacy), Some(xdg)) = (init::legacy_global_dir(), init::xdg_dirs()) else {
+                    anyhow::bail!("Cannot determine home directory");
+                };
+                if let Some(holder) = lock::holder(&legacy) {
+                    anyhow::bail!("{} is locked ({}); try again when it finishes", legacy.display(), holder);
+                }
+                init::migrate_to_xdg(&legacy, &xdg)?;
+                eprintln!("Moved {} to {} and {}", legacy.display(), xdg.config.display(), xdg.data.display());
+                if schedule::status(schedule::Backend::detect()).is_ok_and(|jobs| jobs.iter().any(|(_, found)| found.is_some())) {
+                    eprintln!("Run `cortex schedule install` again so scheduled runs log to the new location.");
+                }
+            }
+        },
+        Commands::Projects { prune_missing, sleep_all, json } => {
+            if prune_missing && let Some(registry) = projects::default_path() {
+                for dir in projects::prune_missing(&registry)? {
+                    eprintln!("Removed {}", dir.display());
+                }
+            }
+            let dirs = projects::known_stores()?;
+
+            if sleep_all {
+                config::ensure_writable(cli.read_only, "projects --sleep-all")?;
+                for dir in &dirs {
+                    let store = Store::open(dir.clone());
+                    eprintln!("{}:", store.root().display());
+                    let result = async {
+                        wake::catch_up(store.raw()?, store.cons()?, store.config()?, store.dir()).await
+                    }
+                    .await;
+                    if let Err(e) = result {
+                        eprintln!("Warning: catch-up failed for {}: {}", store.root().display(), e);
+                    }
+                }
+            }
+
+            let mut summaries = Vec::new();
+            for dir in &dirs {
+                match projects::summarize(dir) {
+                    Ok(s) => summaries.push(s),
+                    Err(e) => eprintln!("Warning: could not read {}: {}", dir.display(), e),
+                }
+            }
+            if json || cli.ci {
+                println!("{}", serde_json::to_string_pretty(&summaries)?);
+            } else if summaries.is_empty() {
+                println!("No projects registered yet. Stores are added when cortex opens them.");
+            } else {
+                println!("{:<8} {:<8} {:<8} {:<20} {:>9}  PATH", "MEMORIES", "PENDING", "CONS", "LAST SLEEP", "SIZE");
+                for s in &summaries {
+                    let last_sleep = s.last_sleep.as_deref().map(|t| t.get(..19).unwrap_or(t)).unwrap_or("never");
+                    println!(
+                        "{:<8} {:<8} {:<8} {:<20} {:>9}  {}",
+                        s.memories,
+                        s.unconsolidated,
+                        s.consolidated,
+                        last_sleep,
+                        projects::format_size(s.size),
+                        s.path.display()
+                    );
+                }
+            }
+        }
+        Commands::Schedule { command } => match command {
+            ScheduleCommands::Install { daily_sleep, weekly_dream, at, max_llm_calls, backend, dry_run } => {
+                if !daily_sleep && !weekly_dream {
+                    anyhow::bail!("Nothing to install: pass --daily-sleep and/or --weekly-dream.");
+                }
+                let backend = backend.unwrap_or_else(schedule::Backend::detect);
+                let (hour, minute) = schedule::parse_time(&at)?;
+                let mut jobs = Vec::new();
+                if daily_sleep {
+                    jobs.push(schedule::sleep_job(hour, minute, max_llm_calls));
+                }
+                if weekly_dream {
+                    jobs.push(schedule::dream_job(hour, minute));
+                }
+                let exe = std::env::current_exe()?;
+
+                if dry_run {
+                    for artifact in schedule::render(backend, &exe, &jobs)? {
+                        match artifact.path {
+                            Some(path) => println!("# {}\n{}", path.display(), artifact.contents),
+                            None => println!("# crontab\n{}\n", artifact.contents),
+                        }
+                    }
+                } else {
+                    schedule::install(backend, &exe, &jobs)?;
+                    let names: Vec<&str> = jobs.iter().map(|j| j.name).collect();
+                    eprintln!("Installed {} with {}.", names.join(" and "), backend.name());
+                }
+            }
+            ScheduleCommands::Status { backend } => {
+                let backend = backend.unwrap_or_else(schedule::Backend::detect);
+                println!("Scheduler: {}", backend.name());
+                for (name, installed) in schedule::status(backend)? {
+                    println!("  {}: {}", name, installed.as_deref().unwrap_or("not installed"));
+                }
+            }
+            ScheduleCommands::Uninstall { backend } => {
+                let backend = backend.unwrap_or_else(schedule::Backend::detect);
+                let removed = schedule::uninstall(backend)?;
+                if removed.is_empty() {
+                    eprintln!("No cortex jobs installed with {}.", backend.name());
+                } else {
+                    eprintln!("Removed {} from {}.", removed.join(" and "), backend.name());
+                }
+            }
+        },
+        Commands::Adr { command } => {
+            let store = find_store(&cli.dir)?;
+            let config = store.config()?;
+            let raw_conn = store.raw()?;
+            match command {
+                AdrCommands::Import { dir } => {
+                    config::ensure_writable(cli.read_only, "import ADRs")?;
+                    let adr_dir = dir.unwrap_or_else(|| store.root().join(&config.adr.dir));
+                    let imported = adr::import(raw_conn, store.cons()?, &adr_dir, &session_id())?;
+                    eprintln!("Imported {} ADRs from {}", imported, adr_dir.display());
+                }
+                AdrCommands::Export { dir, max_sensitivity } => {
+                    let adr_dir = dir.unwrap_or_else(|| store.root().join(&config.adr.dir));
+                    let max = max_sensitivity.unwrap_or(config.sensitivity.share_max);
+                    let written = adr::export(raw_conn, store.cons()?, &adr_dir, max)?;
+                    for path in &written {
+                        eprintln!("Wrote {}", path.display());
+                    }
+                    eprintln!("Exported {} decisions to {}", written.len(), adr_dir.display());
+                }
+            }
+        }
+        Commands::Skills { command } => match command {
+            SkillsCommands::List { category, json, global } => {
+                let store = if global { Store::ensure_global()? } else { find_store(&cli.dir)? };
+                let skills: Vec<_> = db::get_all_skills(store.cons()?)?
+                    .into_iter()
+                    .filter(|s| category.is_none() || s.category == category)
+                    .collect();
+
+                if json || cli.ci {
+                    println!("{}", serde_json::to_string_pretty(&skills)?);
+                } else if skills.is_empty() {
+                    eprintln!("No skills found.");
+                } else {
+                    let mut current = None;
+                    for s in &skills {
+                        if current != Some(&s.category) {
+                            println!("{}/", s.category.as_deref().unwrap_or("(uncategorized)"));
+                            current = Some(&s.category);
+                        }
+                        let used = match &s.last_used_at {
+                            Some(at) => format!("used {}x, last {}", s.use_count, at),
+                            None => "never used".to_string(),
+                        };
+                        println!("  {} ({} lines, updated {}, {})", s.name, s.content.lines().count(), s.updated_at, used);
+                    }
+                }
+            }
+            SkillsCommands::Show { name, global } => {
+                let store = if global { Store::ensure_global()? } else { find_store(&cli.dir)? };
+                let skill = skills::find(store.cons()?, &name)?
+                    .ok_or_else(|| anyhow::anyhow!("Skill '{}' not found. See `cortex skills list`.", name))?;
+                if !cli.read_only {
+                    db::record_skill_use(store.cons()?, skill.id)?;
+                }
+                println!("{}", skill.content.trim_end());
+            }
+            SkillsCommands::Export { dir } => {
+                let store = find_store(&cli.dir)?;
+                let dir = dir.unwrap_or_else(|| store.root().join(".claude").join("skills"));
+                let written = skills::export_claude(store.cons()?, &dir, store.config()?.skills.max_tokens)?;
+                for path in &written {
+                    eprintln!("Wrote {}", path.display());
+                }
+                eprintln!("Exported {} skills to {}", written.len(), dir.display());
+            }
+        },
+        Commands::Auth { command } => match command {
+            AuthCommands::Login => {
+                if cli.ci {
+                    anyhow::bail!("auth login is interactive and not available in CI mode.");
+                }
+                ensure_interactive(cli.unattended, "auth login")?;
+                let tokens = auth::login().await?;
+                let expires = chrono::DateTime::from_timestamp(tokens.expires_at, 0).unwrap_or_default();
+                eprintln!("Logged in. Tokens stored in {} (access token refreshes automatically; current one expires {}).", auth::storage_location(), expires);
+            }
+            AuthCommands::Status => {
+                let api_key = std::env::var("ANTHROPIC_API_KEY").is_ok_and(|k| !k.is_empty());
+                let oauth = auth::load_tokens();
+                #[cfg(feature = "aws")]
+                let bedrock = aws::credentials_configured();
+                #[cfg(not(feature = "aws"))]
+                let bedrock = false;
+
+                println!("ANTHROPIC_API_KEY: {}", if api_key { "set" } else { "not set" });
+                match &oauth {
+                    Some(t) => {
+                        let expires = chrono::DateTime::from_timestamp(t.expires_at, 0).unwrap_or_default();
+                        println!("OAuth: logged in ({}; access token expires {})", auth::storage_location(), expires);
+                    }
+                    None => println!("OAuth: not logged in"),
+                }
+                println!("AWS Bedrock: {}", if bedrock { "credentials configured" } else { "not configured" });
+                let active = if api_key {
+                    "direct API (API key)"
+                } else if oauth.is_some() {
+                    "direct API (OAuth)"
+                } else if bedrock {
+                    "Bedrock"
+                } else {
+                    "none (only micro sleep is available)"
+                };
+                println!("Active: {}", active);
+            }
+            AuthCommands::Check => {
+                if cli.ci {
+                    anyhow::bail!("auth check calls the LLM and is not available in CI mode.");
+                }
+                let config = match find_store(&cli.dir) {
+                    Ok(store) => store.config()?.clone(),
+                    Err(_) => config::Config::default(),
+                };
+                let checks = llm::check_providers(&config).await;
+                if checks.is_empty() {
+                    anyhow::bail!(
+                        "No LLM provider configured. Set ANTHROPIC_API_KEY, run `cortex auth login`, or configure AWS credentials for Bedrock."
+                    );
+                }
+                for check in &checks {
+                    let marker = if check.active { " (active)" } else { "" };
+                    match &check.result {
+                        Ok(latency) => println!(
+                            "ok      {}{}: {} responded in {} ms",
+                            check.provider,
+                            marker,
+                            check.model,
+                            latency.as_millis()
+                        ),
+                        Err(e) => println!("FAILED  {}{}: {}: {:#}", check.provider, marker, check.model, e),
+                    }
+                }
+                if checks.first().is_some_and(|c| c.result.is_err()) {
+                    anyhow::bail!("The active provider failed; quick sleep and dream will fall back or fail.");
+                }
+            }
+            AuthCommands::Logout => {
+                if auth::logout() {
+                    eprintln!("Removed stored OAuth tokens.");
+                } else {
+                    eprintln!("No stored OAuth tokens.");
+                }
+            }
+        },
+        Commands::SelfUpdate { check } => match update::self_update(check).await? {
+            update::Outcome::UpToDate(version) => eprintln!("cortex {} is the latest release.", version),
+            update::Outcome::Available(tag) => eprintln!("cortex {} is available; run `cortex self-update`.", tag),
+            update::Outcome::Updated { from, to } => eprintln!("Updated cortex {} to {}.", from, to),
+        },
+        Commands::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "cortex", &mut std::io::stdout());
+        }
+        Commands::Bench { sizes, iterations, json } => {
+            let timings = cortex::bench::run(&sizes, iterations)?;
+            if json || cli.ci {
+                let rows: Vec<_> = timings
+                    .iter()
+                    .map(|t| serde_json::json!({
+                        "workload": t.name, "size": t.size, "iterations": t.iterations,
+                        "total_ms": t.total.as_secs_f64() * 1000.0, "per_op_us": t.per_op().as_secs_f64() * 1e6
+                    }))
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&rows)?);
+            } else {
+                println!("{:<12} {:>8} {:>6} {:>12}", "workload", "size", "ops", "per op");
+                for t in &timings {
+                    println!("{:<12} {:>8} {:>6} {:>12.3?}", t.name, t.size, t.iterations, t.per_op());
+                }
+            }
         }
     }
 
@@ -1,16 +1,45 @@
 use anyhow::Result;
+use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::BTreeMap;
 use std::io::{self, BufRead, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-use crate::config;
+use crate::antipattern;
+use crate::audit::{self, Manifest};
+use crate::classify;
+use crate::confirm;
+use crate::config::{self, Config, Expansion};
 use crate::context;
+use crate::contradiction;
 use crate::db;
-use crate::init;
+use crate::expand;
+use crate::fallback;
+use crate::hooks::{self, HookEvent};
+use crate::insights;
 use crate::llm;
-use crate::models;
+use crate::models::ExtractionResult;
+use crate::oversize;
+use crate::plugins::{self, Plugin};
+use crate::refs;
+use crate::similar;
+use crate::skills;
 use crate::sleep;
+use crate::store::Store;
+use crate::suggest;
+
+/// How requests on one connection are handled.
+pub struct Session<'a> {
+    pub id: &'a str,
+    pub read_only: bool,
+    /// Serving one tenant of `cortex serve`: the global store is off limits, so projects on the
+    /// same server can't read or write each other's memories through it
+    pub isolated: bool,
+    pub plugins: &'a [Plugin],
+}
 
 #[derive(Deserialize)]
 struct JsonRpcRequest {
@@ -38,53 +67,68 @@ struct JsonRpcError {
     message: String,
 }
 
-pub async fn run_mcp_server(cortex_dir: PathBuf, session_id: String, global_dir: Option<PathBuf>) -> Result<()> {
+pub async fn run_mcp_server(store: &Store, global: Option<Store>, session_id: String, read_only: bool) -> Result<()> {
     let stdin = io::stdin();
     let stdout = io::stdout();
     let mut stdout = stdout.lock();
+    let plugins = plugins::load_plugins(store.dir()).await;
+    let session = Session { id: &session_id, read_only, isolated: false, plugins: &plugins };
 
     for line in stdin.lock().lines() {
         let line = line?;
         if line.trim().is_empty() {
             continue;
         }
+        let resp = respond(&line, store, global.as_ref(), &session).await?;
+        writeln!(stdout, "{}", resp)?;
+        stdout.flush()?;
+    }
 
-        let req: JsonRpcRequest = match serde_json::from_str(&line) {
-            Ok(r) => r,
-            Err(e) => {
-                let resp = JsonRpcResponse {
-                    jsonrpc: "2.0".into(),
-                    id: Value::Null,
-                    result: None,
-                    error: Some(JsonRpcError { code: -32700, message: e.to_string() }),
-                };
-                writeln!(stdout, "{}", serde_json::to_string(&resp)?)?;
-                stdout.flush()?;
-                continue;
-            }
-        };
-
-        let id = req.id.clone().unwrap_or(Value::Null);
-        let result = handle_request(&req, &cortex_dir, &session_id, &global_dir).await;
+    Ok(())
+}
 
-        let resp = match result {
-            Ok(val) => JsonRpcResponse { jsonrpc: "2.0".into(), id, result: Some(val), error: None },
-            Err(e) => JsonRpcResponse {
+/// Handle one JSON-RPC request and return the serialized response. Shared by the stdio server
+/// and `cortex serve`.
+pub async fn respond(
+    line: &str,
+    store: &Store,
+    global: Option<&Store>,
+    session: &Session<'_>,
+) -> Result<String> {
+    let req: JsonRpcRequest = match serde_json::from_str(line) {
+        Ok(r) => r,
+        Err(e) => {
+            let resp = JsonRpcResponse {
                 jsonrpc: "2.0".into(),
-                id,
+                id: Value::Null,
                 result: None,
-                error: Some(JsonRpcError { code: -32603, message: e.to_string() }),
-            },
-        };
+                error: Some(JsonRpcError { code: -32700, message: e.to_string() }),
+            };
+            return Ok(serde_json::to_string(&resp)?);
+        }
+    };
 
-        writeln!(stdout, "{}", serde_json::to_string(&resp)?)?;
-        stdout.flush()?;
-    }
+    let id = req.id.clone().unwrap_or(Value::Null);
+    let result = handle_request(&req, store, global, session).await;
 
-    Ok(())
+    let resp = match result {
+        Ok(val) => JsonRpcResponse { jsonrpc: "2.0".into(), id, result: Some(val), error: None },
+        Err(e) => JsonRpcResponse {
+            jsonrpc: "2.0".into(),
+            id,
+            result: None,
+            error: Some(JsonRpcError { code: -32603, message: e.to_string() }),
+        },
+    };
+    Ok(serde_json::to_string(&resp)?)
 }
 
-async fn handle_request(req: &JsonRpcRequest, cortex_dir: &PathBuf, session_id: &str, global_dir: &Option<PathBuf>) -> Result<Value> {
+async fn handle_request(
+    req: &JsonRpcRequest,
+    store: &Store,
+    global: Option<&Store>,
+    session: &Session<'_>,
+) -> Result<Value> {
     match req.method.as_str() {
         "initialize" => Ok(serde_json::json!({
             "protocolVersion": "2024-11-05",
@@ -95,8 +139,8 @@ async fn handle_request(req: &JsonRpcRequest, cortex_dir: &PathBuf, session_id:
             }
         })),
         "notifications/initialized" => Ok(Value::Null),
-        "tools/list" => Ok(serde_json::json!({
-            "tools": [
+        "tools/list" => {
+            let mut tools = serde_json::json!([
                 {
                     "name": "cortex_save",
                     "description": "Save a learning, decision, or pattern to project memory. Automatically extracts entities and relationships. Use global=true for cross-project knowledge like personal preferences.",
@@ -104,8 +148,9 @@ async fn handle_request(req: &JsonRpcRequest, cortex_dir: &PathBuf, session_id:
                         "type": "object",
                         "properties": {
                             "content": { "type": "string", "description": "What was learned or observed" },
-                            "type": { "type": "string", "description": "Type: bugfix, decision, pattern, preference, observation", "default": "observation" },
-                            "global": { "type": "boolean", "description": "Save to global ~/.cortex/ instead of project (for cross-project knowledge)", "default": false }
+                            "type": { "type": "string", "description": "Type: bugfix, decision, pattern, preference, observation, antipattern (a \"do NOT do X\" rule, never merged away and shown under Avoid in context). Omit to have it guessed from the content" },
+                            "global": { "type": "boolean", "description": "Save to global ~/.cortex/ instead of project (for cross-project knowledge)", "default": false },
+                            "sensitivity": { "type": "string", "enum": ["public", "internal", "secret"], "description": "Where the memory may be shared: secret never leaves this machine's raw.db (default from config)" }
                         },
                         "required": ["content"]
                     }
@@ -117,9 +162,35 @@ async fn handle_request(req: &JsonRpcRequest, cortex_dir: &PathBuf, session_id:
                         "type": "object",
                         "properties": {
                             "query": { "type": "string", "description": "Search query" },
-                            "limit": { "type": "integer", "description": "Max results (default 10)" }
+                            "limit": { "type": "integer", "description": "Max results (default 10)" },
+                            "ref": { "type": "string", "description": "Find memories referencing an issue/PR instead of searching (e.g. #123, JIRA-456, or a URL)" },
+                            "expand": { "type": "boolean", "description": "Also search paraphrases of the query and fuse the results; helps when a memory may be worded differently (default: [recall] expand)" }
+                        }
+                    }
+                },
+                {
+                    "name": "cortex_similar",
+                    "description": "Find project memories most similar to a memory or to new text. Use before cortex_save to check whether something has already been learned. Scores range from 0 to 1.",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": {
+                            "text": { "type": "string", "description": "Text to compare against, e.g. a learning about to be saved" },
+                            "id": { "type": "integer", "description": "Compare against this memory instead of text" },
+                            "consolidated": { "type": "boolean", "description": "The id is a consolidated memory (default: raw memory)", "default": false },
+                            "limit": { "type": "integer", "description": "Max results (default 5)" }
+                        }
+                    }
+                },
+                {
+                    "name": "cortex_suggest",
+                    "description": "Type-ahead completions for editor plugins: project memory titles and topics whose words start with the typed prefix, most frequent first. Does not count as an access.",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": {
+                            "prefix": { "type": "string", "description": "Words typed so far" },
+                            "limit": { "type": "integer", "description": "Max suggestions (default 10)" }
                         },
-                        "required": ["query"]
+                        "required": ["prefix"]
                     }
                 },
                 {
@@ -129,8 +200,22 @@ async fn handle_request(req: &JsonRpcRequest, cortex_dir: &PathBuf, session_id:
                         "type": "object",
                         "properties": {
                             "compact": { "type": "boolean", "description": "Return compact single-line format", "default": false },
+                            "xml": { "type": "boolean", "description": "Wrap sections in XML tags (<project_memory>, <rules>, <patterns>, <skills>) with memory ids to cite or ask to delete", "default": false },
                             "query": { "type": "string", "description": "Optional search query to load only relevant memories. If omitted, loads all memories." },
-                            "limit": { "type": "integer", "description": "Max number of relevant memories to include (default: 15)", "default": 15 }
+                            "limit": { "type": "integer", "description": "Max number of relevant memories to include (default: 15)", "default": 15 },
+                            "since_last": { "type": "boolean", "description": "Only patterns and skills added or updated since this session's last since_last call", "default": false },
+                            "since": { "type": "string", "description": "Only patterns and skills added or updated since this cursor, from the Cursor line of an earlier delta" },
+                            "profile": { "type": "string", "description": "Context profile for the kind of agent: reviewer (decisions and antipatterns), implementer (patterns and skills), or one from [context.profiles]" }
+                        }
+                    }
+                },
+                {
+                    "name": "cortex_skill",
+                    "description": "Get the full content of a learned skill. Context lists skills by name only; pull one when a task needs its steps. Omit name to list the skills that spell out procedures (numbered steps or commands), project and global.",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string", "description": "Skill name or category/name path, as listed under Skills in context" }
                         }
                     }
                 },
@@ -149,12 +234,16 @@ async fn handle_request(req: &JsonRpcRequest, cortex_dir: &PathBuf, session_id:
                     "description": "Get memory health statistics including entity counts, relationship counts, and global memory counts",
                     "inputSchema": { "type": "object", "properties": {} }
                 }
-            ]
-        })),
+            ]);
+            if let Value::Array(list) = &mut tools {
+                list.extend(plugins::tool_definitions(session.plugins));
+            }
+            Ok(serde_json::json!({ "tools": tools }))
+        }
         "tools/call" => {
             let tool_name = req.params.get("name").and_then(|v| v.as_str()).unwrap_or("");
             let args = req.params.get("arguments").cloned().unwrap_or(serde_json::json!({}));
-            let text = call_tool(tool_name, &args, cortex_dir, session_id, global_dir).await?;
+            let text = call_tool(tool_name, &args, store, global, session).await?;
             Ok(serde_json::json!({
                 "content": [{ "type": "text", "text": text }]
             }))
@@ -163,124 +252,277 @@ async fn handle_request(req: &JsonRpcRequest, cortex_dir: &PathBuf, session_id:
     }
 }
 
-async fn call_tool(name: &str, args: &Value, cortex_dir: &PathBuf, session_id: &str, global_dir: &Option<PathBuf>) -> Result<String> {
+async fn call_tool(
+    name: &str,
+    args: &Value,
+    store: &Store,
+    global: Option<&Store>,
+    session: &Session<'_>,
+) -> Result<String> {
     match name {
         "cortex_save" => {
+            config::ensure_writable(session.read_only, "save")?;
             let content = args.get("content").and_then(|v| v.as_str()).unwrap_or("");
-            let mem_type = args.get("type").and_then(|v| v.as_str()).unwrap_or("observation");
+            let mem_type = args.get("type").and_then(|v| v.as_str()).map(str::to_string);
             let global = args.get("global").and_then(|v| v.as_bool()).unwrap_or(false);
+            let sensitivity = args.get("sensitivity").and_then(|v| v.as_str()).map(str::parse).transpose()?;
 
             if global {
-                let gd = init::ensure_global_dir()?;
-                let raw_conn = db::open_raw_db(&gd.join("raw.db"))?;
-                let id = db::save_memory(&raw_conn, content, mem_type, session_id)?;
-                Ok(format!("Saved global memory #{} (type: {})", id, mem_type))
+                if session.isolated {
+                    anyhow::bail!("Global memory isn't available on this server");
+                }
+                let global = Store::ensure_global()?;
+                let config = global.config()?;
+                let mem_type = match mem_type {
+                    Some(t) => t,
+                    None => classify::classify(global.raw()?, content, config).await,
+                };
+                let saved = oversize::save_memory(global.raw()?, content, &mem_type, session.id, "mcp", sensitivity, config).await?;
+                Ok(format!("Saved global memory #{} (type: {}{})", saved.id, mem_type, saved.note(config.content.max_chars)))
             } else {
-                let raw_conn = db::open_raw_db(&cortex_dir.join("raw.db"))?;
-                let config = config::load_config(cortex_dir)?;
-                let id = db::save_memory(&raw_conn, content, mem_type, session_id)?;
+                let raw_conn = store.raw()?;
+                let config = store.config()?;
+                let mem_type = match mem_type {
+                    Some(t) => t,
+                    None => classify::classify(raw_conn, content, config).await,
+                };
+                let (content, mem_type) = plugins::process_memory(session.plugins, content, &mem_type).await?;
+                let mem_type = mem_type.as_str();
+                let saved = oversize::save_memory(raw_conn, &content, mem_type, session.id, "mcp", sensitivity, config).await?;
+                let note = saved.note(config.content.max_chars);
+                let (id, content) = (saved.id, saved.content.as_str());
 
                 // Try to extract entities (best-effort)
-                let entity_msg = match llm::extract_entities(content, &config).await {
+                let extraction = llm::extract_entities(content, config);
+                let entity_msg = match llm::audited(Manifest::new(raw_conn, "save", audit::raw_keys([id])), extraction).await {
                     Ok(extraction) => {
-                        let mut entity_ids = Vec::new();
-                        for entity in &extraction.entities {
-                            if let Ok(eid) = db::upsert_entity(&raw_conn, &entity.name, &entity.r#type, entity.description.as_deref()) {
-                                entity_ids.push(eid);
-                            }
-                        }
-                        if !entity_ids.is_empty() {
-                            let _ = db::update_memory_entities(&raw_conn, id, &entity_ids);
-                        }
-                        for rel in &extraction.relationships {
-                            let source = db::get_entity_by_name(&raw_conn, &rel.source).ok().flatten();
-                            let target = db::get_entity_by_name(&raw_conn, &rel.target).ok().flatten();
-                            if let (Some(s), Some(t)) = (source, target) {
-                                let _ = db::upsert_relationship(&raw_conn, s.id, t.id, &rel.r#type, id, rel.confidence);
-                            }
-                        }
-                        if extraction.entities.is_empty() {
-                            String::new()
-                        } else {
-                            format!(", {} entities extracted", extraction.entities.len())
-                        }
+                        let count = extraction.entities.len();
+                        let _ = store.call(move |c| link_entities(&c.raw, id, &extraction)).await;
+                        if count == 0 { String::new() } else { format!(", {} entities extracted", count) }
                     }
                     Err(_) => String::new(),
                 };
 
-                let uncons = db::get_unconsolidated_count(&raw_conn)?;
-                if uncons >= config.consolidation.auto_micro_threshold as i64 {
-                    let _ = sleep::micro_sleep(&raw_conn, &config);
-                }
+                let alerts = contradiction::check(raw_conn, store.cons()?, id, content, config).await?;
+                let confirmed = confirm::confirm(raw_conn, session.id, id, content, config.recall.confirm_boost)?;
+
+                hooks::fire(
+                    config,
+                    HookEvent::Save,
+                    serde_json::json!({ "id": id, "type": mem_type, "content": content, "tags": [] }),
+                )
+                .await;
+
+                auto_micro_sleep(store.dir(), config);
 
-                Ok(format!("Saved memory #{} (type: {}{})", id, mem_type, entity_msg))
+                let mut out = format!("Saved memory #{} (type: {}{}{})", id, mem_type, entity_msg, note);
+                if !confirmed.is_empty() {
+                    let ids: Vec<String> = confirmed.iter().map(|id| format!("#{}", id)).collect();
+                    out.push_str(&format!("\nConfirms recalled memory {}; its importance was raised.", ids.join(", ")));
+                }
+                for a in &alerts {
+                    out.push_str(&format!(
+                        "\nWarning: this contradicts consolidated memory #{} ({}): {}",
+                        a.consolidated_id, a.reason, a.content
+                    ));
+                }
+                if !alerts.is_empty() {
+                    out.push_str("\nBoth are kept and linked for the next sleep; tell the user if the new memory may be wrong.");
+                }
+                Ok(out)
             }
         }
         "cortex_recall" => {
             let query = args.get("query").and_then(|v| v.as_str()).unwrap_or("");
             let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(10) as usize;
-            let raw_conn = db::open_raw_db(&cortex_dir.join("raw.db"))?;
 
-            // Try entity-based recall first, then fall back to FTS
-            let mut memories = db::recall_by_entity(&raw_conn, query, true, limit)?;
-            if memories.is_empty() {
-                memories = db::recall_memories(&raw_conn, query, limit)?;
+            if let Some(reference) = args.get("ref").and_then(|v| v.as_str()) {
+                let reference = refs::normalize_ref(reference);
+                let found = reference.clone();
+                let (memories, consolidated) = store
+                    .call(move |c| {
+                        Ok((db::recall_by_ref(&c.raw, &found, limit)?, db::consolidated_by_ref(&c.cons, &found, limit)?))
+                    })
+                    .await?;
+                if memories.is_empty() && consolidated.is_empty() {
+                    return Ok(format!("No memories reference {}.", reference));
+                }
+                return Ok(serde_json::to_string_pretty(&serde_json::json!({
+                    "ref": reference,
+                    "memories": memories,
+                    "consolidated": consolidated,
+                }))?);
             }
 
-            // Also search global consolidated DB
-            if let Some(gd) = global_dir {
-                if let Ok(global_cons) = db::open_consolidated_db(&gd.join("consolidated.db")) {
-                    let global_consolidated = db::get_all_consolidated(&global_cons).unwrap_or_default();
-                    let query_lower = query.to_lowercase();
-                    let query_words: Vec<&str> = query_lower.split_whitespace().collect();
-                    for m in global_consolidated {
-                        let content_lower = m.content.to_lowercase();
-                        if query_words.iter().any(|w| content_lower.contains(w)) {
-                            memories.push(models::Memory {
-                                id: -m.id,
-                                content: format!("[global] {}", m.content),
-                                r#type: m.r#type,
-                                created_at: m.created_at,
-                                accessed_at: m.updated_at,
-                                access_count: m.access_count,
-                                consolidated: true,
-                                importance: m.confidence,
-                                session_id: None,
-                                entity_ids: vec![],
-                            });
-                        }
+            let config = store.config()?;
+            let mode = match (args.get("expand").and_then(|v| v.as_bool()), config.recall.expand) {
+                (Some(false), _) => Expansion::Off,
+                (Some(true), Expansion::Off) => Expansion::Synonyms,
+                (_, mode) => mode,
+            };
+            let queries = expand::queries(query, mode, config).await;
+            let ranking = config.recall.clone();
+            let logged = (config.insights.enabled && !session.read_only).then(|| query.to_string());
+            let session_id = session.id.to_string();
+            let read_only = session.read_only;
+            let searched = query.to_string();
+            let (memories, conflicts, skill_hits) = store
+                .call_with_global(global, move |c, g| {
+                    let global_cons = g.map(|g| &g.cons);
+                    let memories = context::recall_fused(&c.raw, global_cons, &queries, limit, &ranking)?;
+                    let skill_hits = skills::search(&c.cons, global_cons, &searched, limit)?;
+                    if !read_only {
+                        confirm::record_recalls(&c.raw, &session_id, &memories.iter().map(|m| m.id).collect::<Vec<_>>())?;
                     }
+                    if let Some(query) = logged {
+                        insights::record(&c.raw, "mcp", &query, &memories.iter().map(|m| m.id).collect::<Vec<_>>())?;
+                    }
+                    let rules = antipattern::load(&c.raw, &c.cons, global_cons)?;
+                    let conflicts = antipattern::conflicts(&memories, &rules);
+                    Ok((memories, conflicts, skill_hits))
+                })
+                .await?;
+
+            let mut skills_found = String::new();
+            if !skill_hits.is_empty() {
+                skills_found.push_str("Matching skills (full content with cortex_skill):\n");
+                for (scope, skill) in &skill_hits {
+                    skills_found.push_str(&format!("{}\n", skills::recall_line(scope, skill)));
                 }
             }
-
+            if memories.is_empty() && !skills_found.is_empty() {
+                return Ok(format!("No memories found matching that query.\n\n{}", skills_found).trim_end().to_string());
+            }
             if memories.is_empty() {
-                Ok("No memories found matching that query.".to_string())
+                let query = query.to_string();
+                let alternatives = store.call(move |c| fallback::fallback(&c.raw, &c.cons, &query, limit)).await?;
+                return Ok(format!("No memories found matching that query.\n{}", alternatives.render()).trim_end().to_string());
+            }
+            let mut out = serde_json::to_string_pretty(&memories)?;
+            if !skills_found.is_empty() {
+                out.push_str(&format!("\n\n{}", skills_found.trim_end()));
+            }
+            for c in conflicts {
+                out.push_str(&format!(
+                    "\n\nWarning: #{} conflicts with antipattern {}: {}",
+                    c.memory_id, c.antipattern, c.content
+                ));
+            }
+            Ok(out)
+        }
+        "cortex_similar" => {
+            let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(5) as usize;
+            let source = if args.get("consolidated").and_then(|v| v.as_bool()).unwrap_or(false) {
+                similar::Source::Consolidated
             } else {
-                Ok(serde_json::to_string_pretty(&memories)?)
+                similar::Source::Raw
+            };
+            let id = args.get("id").and_then(|v| v.as_i64());
+            let text = args.get("text").and_then(|v| v.as_str()).map(str::to_string);
+            let results = match (id, text) {
+                (Some(id), _) => store.call(move |c| similar::similar_to_memory(&c.raw, &c.cons, source, id, limit)).await?,
+                (None, Some(text)) => {
+                    store.call(move |c| similar::similar_to_text(&c.raw, &c.cons, &text, None, limit)).await?
+                }
+                (None, None) => anyhow::bail!("cortex_similar needs `text` or `id`"),
+            };
+            if results.is_empty() {
+                return Ok("No similar memories found.".to_string());
             }
+            Ok(serde_json::to_string_pretty(&results)?)
+        }
+        "cortex_suggest" => {
+            let prefix = args.get("prefix").and_then(|v| v.as_str()).unwrap_or("").to_string();
+            let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(10) as usize;
+            let suggestions = store.call(move |c| suggest::suggest(&c.raw, &c.cons, &prefix, limit)).await?;
+            Ok(serde_json::to_string_pretty(&suggestions)?)
+        }
+        "cortex_skill" => {
+            let name = args.get("name").and_then(|v| v.as_str()).map(str::to_string);
+            let read_only = session.read_only;
+            store
+                .call_with_global(global, move |c, g| {
+                    let stores: Vec<(&str, &Connection)> =
+                        std::iter::once(("", &c.cons)).chain(g.map(|g| ("global ", &g.cons))).collect();
+                    if let Some(name) = name {
+                        for (scope, cons) in stores {
+                            if let Some(skill) = skills::find(cons, &name)? {
+                                if !read_only {
+                                    db::record_skill_use(cons, skill.id)?;
+                                }
+                                return Ok(format!("# {}skill {}\n\n{}", scope, skill.path(), skill.content));
+                            }
+                        }
+                        anyhow::bail!("Unknown skill '{}'. Call cortex_skill without a name to list procedures.", name);
+                    }
+                    let mut out = String::new();
+                    for (scope, cons) in stores {
+                        for skill in db::get_all_skills(cons)?.into_iter().filter(|s| skills::has_steps(&s.content)) {
+                            out.push_str(&format!("- {}{}: {} lines\n", scope, skill.path(), skill.content.lines().count()));
+                        }
+                    }
+                    Ok(if out.is_empty() { "No skills with steps yet.".to_string() } else { format!("Procedures:\n{}", out) })
+                })
+                .await
         }
         "cortex_context" => {
             let compact = args.get("compact").and_then(|v| v.as_bool()).unwrap_or(false);
-            let query = args.get("query").and_then(|v| v.as_str());
+            let format = context::ContextFormat::from_flags(compact, args.get("xml").and_then(|v| v.as_bool()).unwrap_or(false));
+            let config = store.config()?;
+            let query = args.get("query").and_then(|v| v.as_str()).map(|q| expand::with_aliases(q, &config.synonyms));
             let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(15) as usize;
-            let raw_conn = db::open_raw_db(&cortex_dir.join("raw.db"))?;
-            let cons_conn = db::open_consolidated_db(&cortex_dir.join("consolidated.db"))?;
-            let global_cons = global_dir.as_ref().and_then(|gd| {
-                db::open_consolidated_db(&gd.join("consolidated.db")).ok()
-            });
-            context::format_context(&cons_conn, &raw_conn, global_cons.as_ref(), compact, query, limit)
+            let ranking = config.recall.clone();
+            let settings = config.context.clone();
+            let since_last = args.get("since_last").and_then(|v| v.as_bool()).unwrap_or(false);
+            let since = args.get("since").and_then(|v| v.as_str()).map(str::to_string);
+            let client = format!("mcp-{}", session.id);
+            let profile = match args.get("profile").and_then(|v| v.as_str()) {
+                Some(name) => Some(context::find_profile(&settings, name)?),
+                None => None,
+            };
+            if since_last || since.is_some() {
+                // Only a `since_last` fetch moves the session's cursor; a client with its own keeps it
+                let save = since.is_none() && !session.read_only;
+                return store
+                    .call_with_global(global, move |c, g| {
+                        let since = match since {
+                            Some(cursor) => Some(cursor),
+                            None => context::get_cursor(&c.cons, &client)?,
+                        };
+                        let mut delta =
+                            context::format_delta(&c.cons, g.map(|g| &g.cons), since.as_deref(), None, profile.as_ref())?;
+                        let cursor =
+                            if save { context::save_cursor(&c.cons, &client)? } else { context::current_cursor(&c.cons)? };
+                        delta.push_str(&format!("Cursor: {}\n", cursor));
+                        Ok(delta)
+                    })
+                    .await;
+            }
+            let sections = plugins::context_sections(session.plugins, format).await;
+            let settings = context::reserve(&settings, &sections);
+            let mut ctx = store
+                .call_with_global(global, move |c, g| {
+                    let global_cons = g.map(|g| &g.cons);
+                    context::format_context(&c.cons, &c.raw, global_cons, format, query.as_deref(), limit, &ranking, None, &settings, profile.as_ref())
+                })
+                .await?;
+            context::append_sections(&mut ctx, format, &sections);
+            Ok(ctx)
         }
         "cortex_sleep" => {
+            config::ensure_writable(session.read_only, "sleep")?;
             let micro = args.get("micro").and_then(|v| v.as_bool()).unwrap_or(false);
-            let raw_conn = db::open_raw_db(&cortex_dir.join("raw.db"))?;
-            let config = config::load_config(cortex_dir)?;
+            let config = store.config()?;
 
             if micro {
-                let removed = sleep::micro_sleep(&raw_conn, &config)?;
+                let (sleep_config, dir) = (config.clone(), store.dir().to_path_buf());
+                let removed = store.call(move |c| sleep::micro_sleep(&c.raw, &sleep_config, &dir)).await?;
+                hooks::fire(config, HookEvent::SleepComplete, serde_json::json!({ "mode": "micro", "removed": removed })).await;
                 Ok(format!("Micro sleep complete. Removed {} stale memories.", removed))
             } else {
-                let cons_conn = db::open_consolidated_db(&cortex_dir.join("consolidated.db"))?;
-                let result = sleep::quick_sleep(&raw_conn, &cons_conn, &config, cortex_dir).await?;
+                // Quick sleep interleaves LLM calls with its DB work, so it stays on the
+                // store's own connections
+                let result = sleep::quick_sleep(store.raw()?, store.cons()?, config, store.dir(), session.isolated).await?;
                 let mut msg = format!(
                     "Quick sleep complete. {} consolidations, {} promotions, {} decayed, {} skills updated.",
                     result.consolidations.len(), result.promotions.len(), result.decayed.len(), result.skill_updates.len()
@@ -298,23 +540,115 @@ async fn call_tool(name: &str, args: &Value, cortex_dir: &PathBuf, session_id: &
             }
         }
         "cortex_stats" => {
-            let raw_conn = db::open_raw_db(&cortex_dir.join("raw.db"))?;
-            let cons_conn = db::open_consolidated_db(&cortex_dir.join("consolidated.db"))?;
-            let stats = db::get_stats(&raw_conn, &cons_conn)?;
-            let mut stats_json = serde_json::to_value(&stats)?;
+            let stats_json = store
+                .call_with_global(global, |c, g| {
+                    let mut stats_json = serde_json::to_value(db::get_stats(&c.raw, &c.cons)?)?;
+                    // Add global stats if available
+                    if let Some(g) = g {
+                        let gc: i64 = g.cons.query_row("SELECT COUNT(*) FROM consolidated", [], |r| r.get(0)).unwrap_or(0);
+                        let gs: i64 = g.cons.query_row("SELECT COUNT(*) FROM skills", [], |r| r.get(0)).unwrap_or(0);
+                        stats_json["global_consolidated"] = serde_json::json!(gc);
+                        stats_json["global_skills"] = serde_json::json!(gs);
+                    }
+                    Ok(stats_json)
+                })
+                .await?;
+            Ok(serde_json::to_string_pretty(&stats_json)?)
+        }
+        _ => match plugins::call_tool(session.plugins, name, args).await {
+            Some(result) => result,
+            None => anyhow::bail!("Unknown tool: {}", name),
+        },
+    }
+}
 
-            // Add global stats if available
-            if let Some(gd) = global_dir {
-                if let Ok(global_cons) = db::open_consolidated_db(&gd.join("consolidated.db")) {
-                    let gc: i64 = global_cons.query_row("SELECT COUNT(*) FROM consolidated", [], |r| r.get(0)).unwrap_or(0);
-                    let gs: i64 = global_cons.query_row("SELECT COUNT(*) FROM skills", [], |r| r.get(0)).unwrap_or(0);
-                    stats_json["global_consolidated"] = serde_json::json!(gc);
-                    stats_json["global_skills"] = serde_json::json!(gs);
-                }
+/// When each store last started an automatic micro sleep.
+static LAST_AUTO_SLEEP: Mutex<BTreeMap<PathBuf, Instant>> = Mutex::new(BTreeMap::new());
+
+/// Whether the store in `dir` may start an automatic micro sleep now, at most once per
+/// `interval`. Records the start when it may.
+fn auto_sleep_due(dir: &Path, interval: Duration) -> bool {
+    let mut last = LAST_AUTO_SLEEP.lock().unwrap_or_else(|e| e.into_inner());
+    if last.get(dir).is_some_and(|t| t.elapsed() < interval) {
+        return false;
+    }
+    last.insert(dir.to_path_buf(), Instant::now());
+    true
+}
+
+/// Micro sleep once `auto_micro_threshold` memories are pending, debounced by
+/// `auto_micro_interval_mins`. Runs on the blocking pool with its own connection, so a burst of
+/// saves neither waits on it nor triggers one each.
+fn auto_micro_sleep(dir: &Path, config: &Config) {
+    let (dir, config) = (dir.to_path_buf(), config.clone());
+    tokio::task::spawn_blocking(move || {
+        let result = db::open_raw_db(&dir.join("raw.db")).and_then(|raw| {
+            let pending = db::get_unconsolidated_count(&raw)?;
+            let interval = Duration::from_secs(config.consolidation.auto_micro_interval_mins * 60);
+            if pending >= config.consolidation.auto_micro_threshold as i64 && auto_sleep_due(&dir, interval) {
+                sleep::auto_micro_sleep(&raw, &config, &dir)?;
             }
+            Ok(())
+        });
+        if let Err(e) = result {
+            eprintln!("Warning: automatic micro sleep failed: {:#}", e);
+        }
+    });
+}
 
-            Ok(serde_json::to_string_pretty(&stats_json)?)
+/// Store extracted entities and relationships for raw memory `id` (best-effort per item).
+fn link_entities(raw: &Connection, id: i64, extraction: &ExtractionResult) -> Result<()> {
+    let mut entity_ids = Vec::new();
+    for entity in &extraction.entities {
+        if let Ok(eid) = db::upsert_entity(raw, &entity.name, &entity.r#type, entity.description.as_deref()) {
+            entity_ids.push(eid);
+        }
+    }
+    if !entity_ids.is_empty() {
+        db::update_memory_entities(raw, id, &entity_ids)?;
+    }
+    for rel in &extraction.relationships {
+        let source = db::get_entity_by_name(raw, &rel.source).ok().flatten();
+        let target = db::get_entity_by_name(raw, &rel.target).ok().flatten();
+        if let (Some(s), Some(t)) = (source, target) {
+            let _ = db::upsert_relationship(raw, s.id, t.id, &rel.r#type, id, rel.confidence);
         }
-        _ => anyhow::bail!("Unknown tool: {}", name),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debounces_auto_sleep_per_store() {
+        let dir = std::env::temp_dir().join(format!("cortex-mcp-{}", uuid::Uuid::new_v4()));
+        let hour = Duration::from_secs(3600);
+        assert!(auto_sleep_due(&dir, hour));
+        assert!(!auto_sleep_due(&dir, hour));
+        assert!(auto_sleep_due(&dir.join("other"), hour));
+        assert!(auto_sleep_due(&dir, Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn only_writable_since_last_calls_move_the_cursor() {
+        let dir = std::env::temp_dir().join(format!("cortex-mcp-{}", uuid::Uuid::new_v4()));
+        crate::init::init_cortex(&dir).unwrap();
+        let store = Store::open(dir.join(".cortex"));
+        let cursor = |s: &Store| context::get_cursor(s.cons().unwrap(), "mcp-s1").unwrap();
+        let read_only = Session { id: "s1", read_only: true, isolated: false, plugins: &[] };
+        let since_last = serde_json::json!({ "since_last": true });
+        call_tool("cortex_context", &serde_json::json!({}), &store, None, &read_only).await.unwrap();
+        let delta = call_tool("cortex_context", &since_last, &store, None, &read_only).await.unwrap();
+        assert!(delta.contains("Cursor: "));
+        assert_eq!(cursor(&store), None);
+
+        let session = Session { read_only: false, ..read_only };
+        call_tool("cortex_context", &serde_json::json!({}), &store, None, &session).await.unwrap();
+        assert_eq!(cursor(&store), None, "full context leaves the cursor alone");
+        call_tool("cortex_context", &since_last, &store, None, &session).await.unwrap();
+        assert!(cursor(&store).is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,201 @@
+use anyhow::{Context, Result};
+use serde_json::{Map, Value, json};
+use std::path::{Path, PathBuf};
+
+/// An MCP client `cortex mcp install` can register the server with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Client {
+    /// Local scope in `~/.claude.json`: this project only, not committed
+    ClaudeCode,
+    /// The app-wide `claude_desktop_config.json`
+    ClaudeDesktop,
+    /// `.cursor/mcp.json` in the project
+    Cursor,
+    /// `.zed/settings.json` in the project
+    Zed,
+}
+
+impl Client {
+    pub fn name(self) -> &'static str {
+        match self {
+            Client::ClaudeCode => "claude-code",
+            Client::ClaudeDesktop => "claude-desktop",
+            Client::Cursor => "cursor",
+            Client::Zed => "zed",
+        }
+    }
+}
+
+impl std::str::FromStr for Client {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "claude-code" => Ok(Client::ClaudeCode),
+            "claude-desktop" => Ok(Client::ClaudeDesktop),
+            "cursor" => Ok(Client::Cursor),
+            "zed" => Ok(Client::Zed),
+            _ => anyhow::bail!("Unknown MCP client '{}' (expected claude-code, claude-desktop, cursor, or zed)", s),
+        }
+    }
+}
+
+/// What `install` wrote, or would write.
+#[derive(Debug)]
+pub struct Installed {
+    pub path: PathBuf,
+    pub name: String,
+    /// An entry with the same name was already there
+    pub replaced: bool,
+    pub contents: String,
+}
+
+/// The config file `client` reads its servers from for the project at `root`.
+pub fn config_path(client: Client, root: &Path) -> Result<PathBuf> {
+    Ok(match client {
+        Client::ClaudeCode => dirs::home_dir().context("Cannot determine home directory")?.join(".claude.json"),
+        Client::ClaudeDesktop => dirs::config_dir()
+            .context("Cannot determine the config directory")?
+            .join("Claude")
+            .join("claude_desktop_config.json"),
+        Client::Cursor => root.join(".cursor").join("mcp.json"),
+        Client::Zed => root.join(".zed").join("settings.json"),
+    })
+}
+
+/// The object keys leading to the client's server map.
+fn servers_path(client: Client, root: &Path) -> Vec<String> {
+    match client {
+        Client::ClaudeCode => vec!["projects".into(), root.display().to_string(), "mcpServers".into()],
+        Client::Zed => vec!["context_servers".into()],
+        Client::ClaudeDesktop | Client::Cursor => vec!["mcpServers".into()],
+    }
+}
+
+/// `cortex`, except in Claude Desktop's single app-wide config, where each project gets its own
+/// `cortex-<project>` entry.
+pub fn server_name(client: Client, root: &Path) -> String {
+    match (client, root.file_name()) {
+        (Client::ClaudeDesktop, Some(name)) => format!("cortex-{}", name.to_string_lossy()),
+        _ => "cortex".to_string(),
+    }
+}
+
+/// The server entry: this binary, run against the project at `root` whatever directory the
+/// client starts it in.
+pub fn entry(client: Client, exe: &Path, root: &Path) -> Value {
+    let args = json!(["--dir", root.display().to_string(), "mcp"]);
+    let command = exe.display().to_string();
+    match client {
+        Client::Zed => json!({ "source": "custom", "command": command, "args": args, "env": {} }),
+        Client::ClaudeCode => json!({ "type": "stdio", "command": command, "args": args, "env": {} }),
+        Client::ClaudeDesktop | Client::Cursor => json!({ "command": command, "args": args, "env": {} }),
+    }
+}
+
+/// Merge the entry into `existing` config text, keeping everything else. Fails on files that
+/// aren't plain JSON objects (Zed allows comments, for one) rather than rewriting them.
+fn merge(existing: &str, path: &[String], name: &str, entry: Value) -> Result<(String, bool)> {
+    let mut config: Value = if existing.trim().is_empty() { json!({}) } else { serde_json::from_str(existing)? };
+    let mut servers = &mut config;
+    for key in path {
+        servers = servers
+            .as_object_mut()
+            .context("expected a JSON object")?
+            .entry(key.as_str())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    let replaced = servers.as_object_mut().context("expected a JSON object")?.insert(name.to_string(), entry).is_some();
+    Ok((serde_json::to_string_pretty(&config)? + "\n", replaced))
+}
+
+/// Register `exe mcp` for the project at `root` with `client`, merging into its config file.
+/// With `dry_run`, nothing is written.
+pub fn install(client: Client, exe: &Path, root: &Path, dry_run: bool) -> Result<Installed> {
+    let path = config_path(client, root)?;
+    let name = server_name(client, root);
+    let entry = entry(client, exe, root);
+    let existing = if path.exists() { std::fs::read_to_string(&path)? } else { String::new() };
+    let (contents, replaced) = merge(&existing, &servers_path(client, root), &name, entry.clone()).with_context(|| {
+        format!(
+            "Couldn't update {}; add this under \"{}\" by hand:\n\"{}\": {}",
+            path.display(),
+            servers_path(client, root).join("\" → \""),
+            name,
+            serde_json::to_string_pretty(&entry).unwrap_or_default()
+        )
+    })?;
+    if !dry_run {
+        write_replacing(&path, &contents)?;
+    }
+    Ok(Installed { path, name, replaced, contents })
+}
+
+/// Write `contents` to a temp file beside `path` and rename it over `path`, so the client
+/// never reads a half-written config (~/.claude.json holds all of Claude Code's settings). A
+/// symlinked config is written through, and the file keeps its permissions.
+fn write_replacing(path: &Path, contents: &str) -> Result<()> {
+    let target = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = target.parent().context("The config file has no parent directory")?;
+    std::fs::create_dir_all(dir)?;
+    let file_name = target.file_name().context("The config path has no file name")?.to_string_lossy();
+    let staged = dir.join(format!(".{}.cortex-{}", file_name, std::process::id()));
+    std::fs::write(&staged, contents)?;
+    let result = std::fs::metadata(&target)
+        .and_then(|meta| std::fs::set_permissions(&staged, meta.permissions()))
+        .or_else(|e| if e.kind() == std::io::ErrorKind::NotFound { Ok(()) } else { Err(e) })
+        .and_then(|()| std::fs::rename(&staged, &target));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&staged);
+    }
+    Ok(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_into_existing_config() {
+        let root = Path::new("/src/acme");
+        let exe = Path::new("/usr/local/bin/cortex");
+        let existing = r#"{"tipsHistory": {}, "numStartups": 3, "projects": {"/src/acme": {"mcpServers": {"github": {"command": "gh"}}}}}"#;
+        let path = servers_path(Client::ClaudeCode, root);
+        let (merged, replaced) = merge(existing, &path, "cortex", entry(Client::ClaudeCode, exe, root)).unwrap();
+        assert!(!replaced);
+        let config: Value = serde_json::from_str(&merged).unwrap();
+        assert_eq!(config["numStartups"], 3);
+        // Keys stay in the file's order, so the rewrite is a small diff
+        assert!(merged.find("tipsHistory").unwrap() < merged.find("numStartups").unwrap());
+        let servers = &config["projects"]["/src/acme"]["mcpServers"];
+        assert_eq!(servers["github"]["command"], "gh");
+        assert_eq!(servers["cortex"]["args"], json!(["--dir", "/src/acme", "mcp"]));
+
+        let (again, replaced) = merge(&merged, &path, "cortex", entry(Client::ClaudeCode, exe, root)).unwrap();
+        assert!(replaced && again == merged);
+        assert_eq!(server_name(Client::ClaudeDesktop, root), "cortex-acme");
+        let zed = merge("", &servers_path(Client::Zed, root), "cortex", entry(Client::Zed, exe, root)).unwrap().0;
+        assert!(zed.contains("\"context_servers\""));
+        assert!(merge("// comment\n{}", &["context_servers".to_string()], "cortex", json!({})).is_err());
+    }
+
+    #[test]
+    fn replaces_configs_whole() {
+        let dir = std::env::temp_dir().join(format!("cortex-mcp-install-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("nested/.claude.json");
+        write_replacing(&path, "{}\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+            let link = dir.join("link.json");
+            std::os::unix::fs::symlink(&path, &link).unwrap();
+            write_replacing(&link, "{\"a\": 1}\n").unwrap();
+            assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with('{'));
+        assert_eq!(std::fs::read_dir(dir.join("nested")).unwrap().count(), 1, "no temp file left behind");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags, params};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::contradiction;
+use crate::db;
+use crate::models::ConsolidatedMemory;
+use crate::similar::{jaccard, word_set};
+
+/// Word-set Jaccard similarity at or above which memories of the same type are merged.
+const SIMILARITY_THRESHOLD: f64 = 0.6;
+
+/// Session ID of raw memories created for conflicts, so the next sleep resolves them.
+const SESSION: &str = "merge";
+
+/// A memory from the other store that contradicts one of ours. It is saved as a raw memory
+/// flagged against ours, so the next sleep decides between them.
+#[derive(Debug, Serialize)]
+pub struct Conflict {
+    pub ours_id: i64,
+    pub ours: String,
+    pub theirs: String,
+    pub reason: String,
+    /// The raw memory holding `theirs`
+    pub memory_id: i64,
+}
+
+/// What `merge` did.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub added: usize,
+    /// Same or near-identical to one of ours; confidence and refs folded in
+    pub merged: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// The `.cortex` directory at `path`: either the directory itself or a project containing it.
+pub fn store_dir(path: &Path) -> Result<PathBuf> {
+    let dir = if path.join(".cortex").is_dir() { path.join(".cortex") } else { path.to_path_buf() };
+    if !dir.join("consolidated.db").exists() {
+        anyhow::bail!("No cortex store at {} (expected a consolidated.db)", path.display());
+    }
+    Ok(dir)
+}
+
+/// Lowercased words, single-spaced, so formatting differences don't hide duplicates.
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Merge the consolidated memories of the store at `other_dir` into ours. Exact and
+/// near-duplicates keep our text with the higher confidence, the union of refs, and the more
+/// restrictive sensitivity; contradictions are reported and queued for the next sleep; the rest
+/// are added. With `dry_run`, nothing is written. Merging the same store twice adds nothing.
+pub fn merge(raw_conn: &Connection, cons_conn: &Connection, other_dir: &Path, dry_run: bool) -> Result<Report> {
+    let path = other_dir.join("consolidated.db");
+    let other = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut theirs = db::get_all_consolidated(&other)?;
+    theirs.sort_by_key(|m| m.id);
+
+    let raw_tx = raw_conn.unchecked_transaction()?;
+    let tx = cons_conn.unchecked_transaction()?;
+    let mut ours = db::get_all_consolidated(&tx)?;
+    let mut report = Report::default();
+    for t in &theirs {
+        let normalized = normalize(&t.content);
+        if let Some(i) = ours.iter().position(|o| normalize(&o.content) == normalized) {
+            fold(&tx, &mut ours[i], t)?;
+            report.merged += 1;
+            continue;
+        }
+        // Checked before near-duplicates: "X is enabled" and "X is disabled" share most words
+        if let Some((o, reason)) =
+            ours.iter().find_map(|o| contradiction::heuristic_conflict(&t.content, &o.content).map(|r| (o, r)))
+        {
+            let memory_id = match queued(&raw_tx, &t.content)? {
+                Some(id) => id,
+                None => {
+                    let id = db::save_memory(&raw_tx, &t.content, &t.r#type, SESSION)?;
+                    db::set_memory_sensitivity(&raw_tx, &[id], t.sensitivity)?;
+                    if t.no_llm {
+                        db::set_memory_no_llm(&raw_tx, &[id])?;
+                    }
+                    db::link_contradiction(&raw_tx, id, o.id, &reason)?;
+                    id
+                }
+            };
+            report.conflicts.push(Conflict {
+                ours_id: o.id,
+                ours: o.content.clone(),
+                theirs: t.content.clone(),
+                reason,
+                memory_id,
+            });
+            continue;
+        }
+        let words = word_set(&t.content);
+        if let Some(i) = ours
+            .iter()
+            .position(|o| o.r#type == t.r#type && jaccard(&words, &word_set(&o.content)) >= SIMILARITY_THRESHOLD)
+        {
+            fold(&tx, &mut ours[i], t)?;
+            report.merged += 1;
+            continue;
+        }
+        let id = db::insert_consolidated(&tx, &t.content, &t.r#type, &[], t.confidence)?;
+        db::add_consolidated_refs(&tx, id, &t.refs)?;
+        db::set_consolidated_sensitivity(&tx, &[id], t.sensitivity)?;
+        if t.no_llm {
+            db::set_consolidated_no_llm(&tx, &[id])?;
+        }
+        ours.push(db::get_consolidated(&tx, id)?.context("inserted memory is missing")?);
+        report.added += 1;
+    }
+    if !dry_run {
+        raw_tx.commit()?;
+        tx.commit()?;
+    }
+    Ok(report)
+}
+
+/// A conflict an earlier merge already queued, still waiting for a sleep.
+fn queued(raw_conn: &Connection, content: &str) -> Result<Option<i64>> {
+    let id = raw_conn.query_row(
+        "SELECT id FROM memories WHERE content = ?1 AND session_id = ?2 AND consolidated = 0 LIMIT 1",
+        params![content, SESSION],
+        |r| r.get(0),
+    );
+    match id {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Fold `theirs` into `ours`, a duplicate of it, in place and in the database.
+fn fold(conn: &Connection, ours: &mut ConsolidatedMemory, theirs: &ConsolidatedMemory) -> Result<()> {
+    let confidence = ours.confidence.max(theirs.confidence);
+    let sensitivity = ours.sensitivity.max(theirs.sensitivity);
+    if confidence > ours.confidence {
+        conn.execute(
+            "UPDATE consolidated SET confidence = ?1, updated_at = datetime('now') WHERE id = ?2",
+            params![confidence, ours.id],
+        )?;
+    }
+    if theirs.refs.iter().any(|r| !ours.refs.contains(r)) {
+        db::add_consolidated_refs(conn, ours.id, &theirs.refs)?;
+    }
+    if sensitivity > ours.sensitivity {
+        db::set_consolidated_sensitivity(conn, &[ours.id], sensitivity)?;
+    }
+    if theirs.no_llm && !ours.no_llm {
+        db::set_consolidated_no_llm(conn, &[ours.id])?;
+    }
+    *ours = db::get_consolidated(conn, ours.id)?.context("merged memory is missing")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensitivity::Sensitivity;
+
+    #[test]
+    fn merges_duplicates_and_flags_conflicts() {
+        let dir = std::env::temp_dir().join(format!("cortex-merge-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join(".cortex")).unwrap();
+        let other = db::open_consolidated_db(&dir.join(".cortex/consolidated.db")).unwrap();
+        db::insert_consolidated(&other, "Run   migrations with sqlx", "decision", &[], 0.95).unwrap();
+        let secret = db::insert_consolidated(&other, "Release builds use thin LTO for speed", "pattern", &[], 0.7).unwrap();
+        db::set_consolidated_sensitivity(&other, &[secret], Sensitivity::Secret).unwrap();
+        db::insert_consolidated(&other, "Incremental compilation is disabled in CI builds", "decision", &[], 0.8).unwrap();
+        db::insert_consolidated(&other, "Docs are built with mdbook", "convention", &[], 0.6).unwrap();
+
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let sqlx = db::insert_consolidated(&cons, "run migrations with sqlx", "decision", &[], 0.8).unwrap();
+        let lto = db::insert_consolidated(&cons, "Release builds use thin LTO for build speed", "pattern", &[], 0.9).unwrap();
+        let ci = db::insert_consolidated(&cons, "Incremental compilation is enabled in CI builds", "decision", &[], 0.8).unwrap();
+
+        let other_dir = store_dir(&dir).unwrap();
+        let dry = merge(&raw, &cons, &other_dir, true).unwrap();
+        assert_eq!((dry.added, dry.merged, dry.conflicts.len()), (1, 2, 1));
+        assert_eq!(db::get_consolidated_count(&cons).unwrap(), 3);
+
+        let report = merge(&raw, &cons, &other_dir, false).unwrap();
+        assert_eq!((report.added, report.merged), (1, 2));
+        assert_eq!(report.conflicts[0].ours_id, ci);
+        assert_eq!(db::get_consolidated(&cons, sqlx).unwrap().unwrap().confidence, 0.95);
+        let lto = db::get_consolidated(&cons, lto).unwrap().unwrap();
+        assert_eq!((lto.confidence, lto.sensitivity), (0.9, Sensitivity::Secret));
+        assert_eq!(db::get_contradictions(&raw).unwrap().len(), 1);
+
+        // Merging again changes nothing and re-reports the queued conflict
+        let again = merge(&raw, &cons, &other_dir, false).unwrap();
+        assert_eq!((again.added, again.merged, again.conflicts.len()), (0, 3, 1));
+        assert_eq!(db::get_consolidated_count(&cons).unwrap(), 4);
+        assert_eq!(db::get_contradictions(&raw).unwrap().len(), 1);
+        assert!(store_dir(&std::env::temp_dir().join("no-such-cortex")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+use crate::config::{Config, ModelAlias};
+
+/// Built-in short names, overridable and extendable by `[models.<alias>]` in config.toml.
+fn builtin_aliases() -> BTreeMap<String, ModelAlias> {
+    let entries: &[(&[&str], &str)] = &[
+        (
+            &["claude-haiku-4-5", "claude-haiku-4-5-20241022", "claude-haiku-4-5-20251001"],
+            "anthropic.claude-haiku-4-5-20251001-v1:0",
+        ),
+        (&["claude-sonnet-4-5", "claude-sonnet-4-5-20250929"], "anthropic.claude-sonnet-4-5-20250929-v1:0"),
+        (&["claude-sonnet-4", "claude-sonnet-4-20250514"], "anthropic.claude-sonnet-4-20250514-v1:0"),
+        (&["claude-3-5-haiku", "claude-3-5-haiku-20241022"], "anthropic.claude-3-5-haiku-20241022-v1:0"),
+        (&["claude-3-5-sonnet", "claude-3-5-sonnet-20241022"], "anthropic.claude-3-5-sonnet-20241022-v2:0"),
+    ];
+    let mut aliases = BTreeMap::new();
+    for (names, bedrock) in entries {
+        for name in *names {
+            aliases.insert(
+                name.to_string(),
+                ModelAlias {
+                    bedrock: bedrock.to_string(),
+                    regions: BTreeMap::new(),
+                },
+            );
+        }
+    }
+    aliases
+}
+
+/// All aliases known to this store: built-ins merged with configured ones.
+pub fn all_aliases(config: &Config) -> BTreeMap<String, ModelAlias> {
+    let mut aliases = builtin_aliases();
+    aliases.extend(config.models.clone());
+    aliases
+}
+
+/// Cross-region inference profile prefix for an AWS region (`eu-west-1` → `eu`).
+pub fn region_prefix(region: &str) -> &'static str {
+    if region.starts_with("us-gov-") {
+        return "us-gov";
+    }
+    match region.split('-').next().unwrap_or("") {
+        "eu" => "eu",
+        "ap" => "apac",
+        _ => "us",
+    }
+}
+
+/// Resolve a configured model name to a Bedrock model ID for `region`.
+/// Full IDs (`anthropic.*`, `<prefix>.anthropic.*`, or ARNs) pass through, with a bare
+/// `anthropic.*` ID given the region's inference profile prefix.
+pub fn bedrock_model_id(config: &Config, model: &str, region: &str) -> Result<String> {
+    if model.starts_with("arn:") || model.split_once(".anthropic.").is_some() {
+        return Ok(model.to_string());
+    }
+    let prefix = region_prefix(region);
+    if model.starts_with("anthropic.") {
+        return Ok(format!("{}.{}", prefix, model));
+    }
+
+    let aliases = all_aliases(config);
+    let alias = aliases.get(model).ok_or_else(|| {
+        let known: Vec<&str> = aliases.keys().map(|k| k.as_str()).collect();
+        anyhow::anyhow!(
+            "No Bedrock mapping for model '{}'. Known aliases: {}. Add one under [models.\"{}\"] in config.toml.",
+            model,
+            known.join(", "),
+            model
+        )
+    })?;
+    match alias.regions.get(prefix) {
+        Some(id) => Ok(id.clone()),
+        None if !alias.bedrock.is_empty() => Ok(format!("{}.{}", prefix, alias.bedrock)),
+        None => anyhow::bail!("Model '{}' has no Bedrock profile for {} regions.", model, prefix),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_prefixes() {
+        assert_eq!(region_prefix("us-west-2"), "us");
+        assert_eq!(region_prefix("eu-central-1"), "eu");
+        assert_eq!(region_prefix("ap-northeast-1"), "apac");
+        assert_eq!(region_prefix("us-gov-west-1"), "us-gov");
+    }
+
+    #[test]
+    fn resolves_aliases_per_region() {
+        let mut config = Config::default();
+        config.models.insert(
+            "custom".into(),
+            ModelAlias {
+                bedrock: "anthropic.custom-v1:0".into(),
+                regions: BTreeMap::from([("apac".into(), "apac.anthropic.custom-v2:0".into())]),
+            },
+        );
+        assert_eq!(
+            bedrock_model_id(&config, "claude-sonnet-4-5", "eu-west-1").unwrap(),
+            "eu.anthropic.claude-sonnet-4-5-20250929-v1:0"
+        );
+        assert_eq!(bedrock_model_id(&config, "custom", "us-east-1").unwrap(), "us.anthropic.custom-v1:0");
+        assert_eq!(bedrock_model_id(&config, "custom", "ap-south-1").unwrap(), "apac.anthropic.custom-v2:0");
+        assert_eq!(
+            bedrock_model_id(&config, "eu.anthropic.x-v1:0", "us-east-1").unwrap(),
+            "eu.anthropic.x-v1:0"
+        );
+        let err = bedrock_model_id(&config, "mystery", "us-east-1").unwrap_err().to_string();
+        assert!(err.contains("Known aliases:") && err.contains("custom"));
+    }
+}
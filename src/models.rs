@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::sensitivity::Sensitivity;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Memory {
     pub id: i64,
@@ -13,6 +15,15 @@ pub struct Memory {
     pub session_id: Option<String>,
     #[serde(default)]
     pub entity_ids: Vec<i64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub refs: Vec<String>,
+    #[serde(default)]
+    pub sensitivity: Sensitivity,
+    /// Never sent to an LLM (`cortex save --no-llm`, or built from such memories)
+    #[serde(default)]
+    pub no_llm: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -67,6 +78,25 @@ pub struct ExtractionResult {
     pub relationships: Vec<ExtractedRelationship>,
 }
 
+/// One `cortex chat` reply: the answer plus any new facts the user stated.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatReply {
+    pub answer: String,
+    #[serde(default)]
+    pub citations: Vec<String>,
+    #[serde(default)]
+    pub facts: Vec<StatedFact>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatedFact {
+    pub content: String,
+    #[serde(default = "default_fact_type")]
+    pub r#type: String,
+}
+
+fn default_fact_type() -> String { "observation".to_string() }
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConsolidatedMemory {
     pub id: i64,
@@ -77,6 +107,13 @@ pub struct ConsolidatedMemory {
     pub created_at: String,
     pub updated_at: String,
     pub access_count: i64,
+    #[serde(default)]
+    pub refs: Vec<String>,
+    #[serde(default)]
+    pub sensitivity: Sensitivity,
+    /// Never sent to an LLM (`cortex save --no-llm`, or built from such memories)
+    #[serde(default)]
+    pub no_llm: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -86,6 +123,26 @@ pub struct Skill {
     pub content: String,
     pub source_ids: Vec<i64>,
     pub updated_at: String,
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Reads through `cortex_skill` or `cortex skills show`
+    #[serde(default)]
+    pub use_count: i64,
+    #[serde(default)]
+    pub last_used_at: Option<String>,
+    /// Body excerpt around the query when found by `db::search_skills`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+}
+
+impl Skill {
+    /// `category/name`, or just `name` when uncategorized.
+    pub fn path(&self) -> String {
+        match &self.category {
+            Some(c) => format!("{}/{}", c, self.name),
+            None => self.name.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,6 +154,15 @@ pub struct Stats {
     pub entity_count: i64,
     pub relationship_count: i64,
     pub last_sleep: Option<String>,
+    /// Average raw memory importance (0 with no memories)
+    #[serde(default)]
+    pub avg_importance: f64,
+    /// Average consolidated memory confidence (0 with none)
+    #[serde(default)]
+    pub avg_confidence: f64,
+    /// Share of raw memories already consolidated, 0.0 to 1.0
+    #[serde(default)]
+    pub consolidation_ratio: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -118,6 +184,9 @@ pub struct Contradiction {
 pub struct SkillUpdate {
     pub name: String,
     pub content: String,
+    /// Subdirectory of skills/ to file the skill under, e.g. `testing`
+    #[serde(default)]
+    pub category: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -125,9 +194,12 @@ pub struct GlobalPromotion {
     pub content: String,
     pub r#type: String,
     pub confidence: f64,
+    /// Recent observations the promotion draws on (checked against `[sensitivity] global_max`)
+    #[serde(default)]
+    pub source_ids: Vec<i64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ConsolidationResult {
     #[serde(default)]
     pub consolidations: Vec<Consolidation>,
@@ -147,6 +219,12 @@ pub struct ConsolidationResult {
     pub new_relationships: Vec<ExtractedRelationship>,
     #[serde(default)]
     pub entity_updates: Vec<EntityUpdate>,
+    /// Dream only: consolidated memories (`source_ids`) that say the same thing, replaced by one
+    #[serde(default)]
+    pub merges: Vec<Consolidation>,
+    /// Dream only: names of skills nobody uses that the memories no longer support
+    #[serde(default)]
+    pub skill_removals: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -164,6 +242,13 @@ impl std::fmt::Display for Stats {
         writeln!(f, "Consolidated: {}", self.consolidated_count)?;
         writeln!(f, "Entities: {} ({} relationships)", self.entity_count, self.relationship_count)?;
         writeln!(f, "Skills: {}", self.skill_count)?;
+        writeln!(
+            f,
+            "Average importance: {:.2} | average confidence: {:.2} | {:.0}% consolidated",
+            self.avg_importance,
+            self.avg_confidence,
+            self.consolidation_ratio * 100.0
+        )?;
         if let Some(ref last) = self.last_sleep {
             write!(f, "Last sleep: {}", last)?;
         } else {
@@ -172,3 +257,118 @@ impl std::fmt::Display for Stats {
         Ok(())
     }
 }
+
+// --- JSON Schemas for structured (tool-use) LLM output ---
+
+fn array_of(items: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({ "type": "array", "items": items })
+}
+
+fn object(properties: serde_json::Value, required: &[&str]) -> serde_json::Value {
+    serde_json::json!({ "type": "object", "properties": properties, "required": required })
+}
+
+fn entity_schema() -> serde_json::Value {
+    object(
+        serde_json::json!({
+            "name": { "type": "string" },
+            "type": { "type": "string", "description": "language|technology|service|pattern|concept|tool|framework" },
+            "description": { "type": "string" }
+        }),
+        &["name", "type"],
+    )
+}
+
+fn relationship_schema() -> serde_json::Value {
+    object(
+        serde_json::json!({
+            "source": { "type": "string" },
+            "target": { "type": "string" },
+            "type": { "type": "string", "description": "uses|implements|related_to|alternative_to|caused_by|used_for" },
+            "confidence": { "type": "number", "minimum": 0, "maximum": 1 }
+        }),
+        &["source", "target", "type"],
+    )
+}
+
+/// Schema for `ExtractionResult`.
+pub fn extraction_schema() -> serde_json::Value {
+    object(
+        serde_json::json!({
+            "entities": array_of(entity_schema()),
+            "relationships": array_of(relationship_schema())
+        }),
+        &["entities", "relationships"],
+    )
+}
+
+/// Schema for `ConsolidationResult`. Every field is optional, matching its serde defaults.
+pub fn consolidation_schema() -> serde_json::Value {
+    let confidence = serde_json::json!({ "type": "number", "minimum": 0, "maximum": 1 });
+    let ids = array_of(serde_json::json!({ "type": "integer" }));
+    object(
+        serde_json::json!({
+            "consolidations": array_of(object(
+                serde_json::json!({
+                    "content": { "type": "string" },
+                    "type": { "type": "string" },
+                    "source_ids": ids,
+                    "confidence": confidence
+                }),
+                &["content", "type", "source_ids", "confidence"],
+            )),
+            "contradictions": array_of(object(
+                serde_json::json!({
+                    "old_id": { "type": "integer" },
+                    "new_id": { "type": "integer" },
+                    "resolution": { "type": "string", "enum": ["keep_new", "keep_old", "merge"] }
+                }),
+                &["old_id", "new_id", "resolution"],
+            )),
+            "promotions": ids,
+            "decayed": ids,
+            "skill_updates": array_of(object(
+                serde_json::json!({
+                    "name": { "type": "string" },
+                    "content": { "type": "string" },
+                    "category": { "type": "string", "description": "testing|arch|tooling|workflow|style, or another short kebab-case group" }
+                }),
+                &["name", "content"],
+            )),
+            "skill_removals": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Names of unused skills the memories no longer support"
+            },
+            "global_promotions": array_of(object(
+                serde_json::json!({
+                    "content": { "type": "string" },
+                    "type": { "type": "string" },
+                    "confidence": confidence,
+                    "source_ids": { "type": "array", "items": { "type": "integer" } }
+                }),
+                &["content", "type", "confidence"],
+            )),
+            "new_entities": array_of(entity_schema()),
+            "new_relationships": array_of(relationship_schema()),
+            "entity_updates": array_of(object(
+                serde_json::json!({
+                    "name": { "type": "string" },
+                    "description": { "type": "string" },
+                    "confidence": confidence
+                }),
+                &["name"],
+            )),
+            "merges": array_of(object(
+                serde_json::json!({
+                    "content": { "type": "string" },
+                    "type": { "type": "string" },
+                    "source_ids": { "type": "array", "items": { "type": "integer" }, "description": "IDs of the 2+ long-term memories this one replaces" },
+                    "confidence": confidence
+                }),
+                &["content", "type", "source_ids", "confidence"],
+            ))
+        }),
+        &[],
+    )
+}
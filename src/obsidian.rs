@@ -0,0 +1,252 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::db;
+use crate::models::{ConsolidatedMemory, Memory, Skill};
+use crate::redact::Anonymizer;
+use crate::sensitivity::Sensitivity;
+
+/// Frontmatter line marking a note as written by the export, so a re-export replaces it.
+const MARKER: &str = "generator: cortex";
+
+/// Folder under the vault that holds the exported notes.
+const FOLDER: &str = "cortex";
+
+/// What `export` wrote.
+#[derive(Debug, Default, Serialize)]
+pub struct Exported {
+    pub memories: usize,
+    pub consolidated: usize,
+    pub skills: usize,
+    /// Notes from an earlier export that were removed because their memory is gone
+    pub removed: usize,
+}
+
+/// Write memories, consolidated memories, and skills as interlinked notes under
+/// `<vault>/cortex/`: wikilinks run from each consolidated memory to its sources and back, and
+/// entities become `topic/...` tags. Notes from an earlier export are replaced; other files are
+/// left alone. Memories more sensitive than `max_sensitivity` and untrusted ones are skipped;
+/// with an `anonymizer`, text and entity names pass through it first.
+pub fn export(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    vault: &Path,
+    max_sensitivity: Sensitivity,
+    anonymizer: Option<&mut Anonymizer>,
+) -> Result<Exported> {
+    let root = vault.join(FOLDER);
+    let mut exported = Exported { removed: clear_generated(&root)?, ..Default::default() };
+
+    let mut entities = db::get_all_entities(raw_conn)?;
+    let mut memories: Vec<Memory> =
+        db::get_trusted_memories(raw_conn)?.into_iter().filter(|m| m.sensitivity <= max_sensitivity).collect();
+    let mut consolidated: Vec<ConsolidatedMemory> =
+        db::get_all_consolidated(cons_conn)?.into_iter().filter(|c| c.sensitivity <= max_sensitivity).collect();
+    let mut skills = db::get_all_skills(cons_conn)?;
+    if let Some(a) = anonymizer {
+        entities.iter_mut().for_each(|e| a.entity(e));
+        memories.iter_mut().for_each(|m| a.memory(m));
+        consolidated.iter_mut().for_each(|c| a.consolidated(c));
+        skills.iter_mut().for_each(|s| a.skill(s));
+    }
+    let entities: HashMap<i64, String> = entities.into_iter().map(|e| (e.id, e.name)).collect();
+
+    let memory_ids: BTreeSet<i64> = memories.iter().map(|m| m.id).collect();
+    let consolidated_ids: BTreeSet<i64> = consolidated.iter().map(|c| c.id).collect();
+    let topics = |m: &Memory| -> BTreeSet<String> {
+        let mut tags: BTreeSet<String> =
+            m.entity_ids.iter().filter_map(|id| entities.get(id)).map(|name| format!("topic/{}", tag(name))).collect();
+        tags.extend(m.tags.iter().map(|t| tag(t)).filter(|t| !t.is_empty()));
+        tags
+    };
+    let memory_topics: BTreeMap<i64, BTreeSet<String>> = memories.iter().map(|m| (m.id, topics(m))).collect();
+
+    // Links: raw sources for most consolidated memories, other consolidated memories for insights
+    let mut derived: BTreeMap<(bool, i64), Vec<i64>> = BTreeMap::new();
+    for c in &consolidated {
+        for id in &c.source_ids {
+            derived.entry((c.r#type == "insight", *id)).or_default().push(c.id);
+        }
+    }
+
+    std::fs::create_dir_all(root.join("memories"))?;
+    for m in &memories {
+        let mut body = format!("{}\n", m.content.trim_end());
+        let links: Vec<String> = derived.get(&(false, m.id)).into_iter().flatten().map(|id| consolidated_link(*id)).collect();
+        push_links(&mut body, "Consolidated into", &links);
+        let mut tags = vec!["cortex/memory".to_string(), format!("type/{}", tag(&m.r#type))];
+        tags.extend(memory_topics[&m.id].iter().cloned());
+        let fields = [
+            ("id", m.id.to_string()),
+            ("type", m.r#type.clone()),
+            ("created", m.created_at.clone()),
+            ("importance", format!("{:.2}", m.importance)),
+        ];
+        write_note(&root.join("memories").join(format!("Memory {}.md", m.id)), &fields, &m.content, &tags, &body)?;
+        exported.memories += 1;
+    }
+
+    std::fs::create_dir_all(root.join("consolidated"))?;
+    for c in &consolidated {
+        let insight = c.r#type == "insight";
+        let mut body = format!("{}\n", c.content.trim_end());
+        let sources: Vec<String> = if insight {
+            c.source_ids.iter().filter(|id| consolidated_ids.contains(id)).map(|id| consolidated_link(*id)).collect()
+        } else {
+            c.source_ids.iter().filter(|id| memory_ids.contains(id)).map(|id| format!("[[Memory {}]]", id)).collect()
+        };
+        push_links(&mut body, "Sources", &sources);
+        let insights: Vec<String> = derived.get(&(true, c.id)).into_iter().flatten().map(|id| consolidated_link(*id)).collect();
+        push_links(&mut body, "Insights drawing on this", &insights);
+        let mut tags = vec!["cortex/consolidated".to_string(), format!("type/{}", tag(&c.r#type))];
+        if !insight {
+            let topics: BTreeSet<String> = c.source_ids.iter().filter_map(|id| memory_topics.get(id)).flatten().cloned().collect();
+            tags.extend(topics);
+        }
+        let fields = [
+            ("id", c.id.to_string()),
+            ("type", c.r#type.clone()),
+            ("updated", c.updated_at.clone()),
+            ("confidence", format!("{:.2}", c.confidence)),
+        ];
+        write_note(&root.join("consolidated").join(format!("Consolidated {}.md", c.id)), &fields, &c.content, &tags, &body)?;
+        exported.consolidated += 1;
+    }
+
+    std::fs::create_dir_all(root.join("skills"))?;
+    for s in &skills {
+        let mut body = format!("{}\n", s.content.trim_end());
+        let sources: Vec<String> =
+            s.source_ids.iter().filter(|id| memory_ids.contains(id)).map(|id| format!("[[Memory {}]]", id)).collect();
+        push_links(&mut body, "Sources", &sources);
+        let mut tags = vec!["cortex/skill".to_string()];
+        tags.extend(s.category.as_deref().map(|c| format!("skill/{}", tag(c))));
+        let fields = [("updated", s.updated_at.clone())];
+        write_note(&root.join("skills").join(format!("{}.md", skill_note(s))), &fields, &s.name, &tags, &body)?;
+        exported.skills += 1;
+    }
+
+    write_index(&root, &consolidated, &skills)?;
+    Ok(exported)
+}
+
+fn consolidated_link(id: i64) -> String {
+    format!("[[Consolidated {}]]", id)
+}
+
+fn skill_note(s: &Skill) -> String {
+    format!("Skill {}", s.name.replace(['/', '\\'], "-"))
+}
+
+/// An Obsidian tag: lowercase, with anything but letters, digits, `/`, and `_` turned into `-`.
+fn tag(text: &str) -> String {
+    let mapped: String = text
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '/' || c == '_' { c } else { '-' })
+        .collect();
+    mapped.split('-').filter(|s| !s.is_empty()).collect::<Vec<_>>().join("-")
+}
+
+fn push_links(body: &mut String, heading: &str, links: &[String]) {
+    if links.is_empty() {
+        return;
+    }
+    body.push_str(&format!("\n## {}\n\n", heading));
+    for link in links {
+        body.push_str(&format!("- {}\n", link));
+    }
+}
+
+/// Write a note with frontmatter: the marker, `fields`, the first line of `title_from` as an
+/// alias (so the note can be found by its wording), and `tags`.
+fn write_note(path: &Path, fields: &[(&str, String)], title_from: &str, tags: &[String], body: &str) -> Result<()> {
+    let mut note = format!("---\n{}\n", MARKER);
+    for (key, value) in fields {
+        // Numbers stay bare so Obsidian properties sort them as numbers; text is quoted YAML
+        let value = if value.parse::<f64>().is_ok() { value.clone() } else { serde_json::to_string(value)? };
+        note.push_str(&format!("{}: {}\n", key, value));
+    }
+    let title: String = title_from.lines().next().unwrap_or("").trim().chars().take(80).collect();
+    if !title.is_empty() {
+        note.push_str(&format!("aliases: [{}]\n", serde_json::to_string(&title)?));
+    }
+    note.push_str(&format!("tags: [{}]\n---\n\n{}", tags.join(", "), body));
+    std::fs::write(path, note)?;
+    Ok(())
+}
+
+/// `cortex/Cortex.md`: links to every skill and consolidated memory, grouped by type.
+fn write_index(root: &Path, consolidated: &[ConsolidatedMemory], skills: &[Skill]) -> Result<()> {
+    let mut body = String::from("# Cortex memory\n");
+    let skill_links: Vec<String> = skills.iter().map(|s| format!("[[{}]]", skill_note(s))).collect();
+    push_links(&mut body, "Skills", &skill_links);
+    let mut by_type: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for c in consolidated {
+        let first = c.content.lines().next().unwrap_or("");
+        by_type.entry(&c.r#type).or_default().push(format!("{}: {}", consolidated_link(c.id), first));
+    }
+    for (r#type, links) in &by_type {
+        push_links(&mut body, r#type, links);
+    }
+    write_note(&root.join("Cortex.md"), &[], "", &["cortex".to_string()], &body)
+}
+
+/// Delete the notes an earlier export wrote under `root`. Returns how many there were.
+fn clear_generated(root: &Path) -> Result<usize> {
+    let mut removed = 0;
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "md")
+                && std::fs::read_to_string(&path).is_ok_and(|text| text.starts_with(&format!("---\n{}\n", MARKER)))
+            {
+                std::fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_linked_notes_and_replaces_earlier_exports() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let a = db::save_memory(&raw, "Retry S3 uploads with backoff", "pattern", "s1").unwrap();
+        let secret = db::save_memory(&raw, "The staging token is in 1Password", "observation", "s1").unwrap();
+        raw.execute("UPDATE memories SET sensitivity = 'secret' WHERE id = ?1", [secret]).unwrap();
+        raw.execute("INSERT INTO entities (name, entity_type) VALUES ('Amazon S3', 'tool')", []).unwrap();
+        raw.execute("UPDATE memories SET entity_ids = '[1]' WHERE id = ?1", [a]).unwrap();
+        let p = db::insert_consolidated(&cons, "Retry uploads with backoff", "pattern", &[a, secret], 0.8).unwrap();
+        let i = db::insert_consolidated(&cons, "Network calls here need retries", "insight", &[p], 0.6).unwrap();
+
+        let vault = std::env::temp_dir().join(format!("cortex-obsidian-{}", uuid::Uuid::new_v4()));
+        let stale = vault.join("cortex/memories/Memory 99.md");
+        std::fs::create_dir_all(stale.parent().unwrap()).unwrap();
+        std::fs::write(&stale, "---\ngenerator: cortex\n---\n").unwrap();
+        std::fs::write(vault.join("cortex/memories/mine.md"), "my own note").unwrap();
+
+        let exported = export(&raw, &cons, &vault, Sensitivity::Internal, None).unwrap();
+        assert_eq!((exported.memories, exported.consolidated, exported.removed), (1, 2, 1));
+        assert!(!stale.exists() && vault.join("cortex/memories/mine.md").exists());
+        let memory = std::fs::read_to_string(vault.join(format!("cortex/memories/Memory {}.md", a))).unwrap();
+        assert!(memory.contains("topic/amazon-s3") && memory.contains(&format!("[[Consolidated {}]]", p)), "{}", memory);
+        let pattern = std::fs::read_to_string(vault.join(format!("cortex/consolidated/Consolidated {}.md", p))).unwrap();
+        assert!(pattern.contains(&format!("[[Memory {}]]", a)) && !pattern.contains(&format!("[[Memory {}]]", secret)));
+        assert!(pattern.contains(&format!("[[Consolidated {}]]", i)), "{}", pattern);
+        assert!(vault.join("cortex/Cortex.md").exists());
+        std::fs::remove_dir_all(&vault).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::Result;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+
+use crate::db;
+use crate::models::ConsolidatedMemory;
+
+/// Confidence a sleep adds per outcome that worked, as much as a reinforcing observation.
+const WORKED_STEP: f64 = 0.05;
+/// Confidence a sleep removes per outcome that failed: advice that misled once costs more than
+/// advice that helped once earns.
+const FAILED_STEP: f64 = 0.1;
+
+/// One report of applying a consolidated memory.
+#[derive(Debug, Clone, Serialize)]
+pub struct Outcome {
+    pub worked: bool,
+    pub note: String,
+    pub created_at: String,
+}
+
+/// `cortex show`: a consolidated memory and how applying it went.
+#[derive(Debug, Serialize)]
+pub struct Shown {
+    pub consolidated: ConsolidatedMemory,
+    pub worked: usize,
+    pub failed: usize,
+    /// Share of outcomes that worked; `None` until there is one
+    pub success_rate: Option<f64>,
+    pub outcomes: Vec<Outcome>,
+}
+
+/// Record that applying consolidated memory `id` worked or failed. Confidence changes at the
+/// next sleep.
+pub fn record(cons: &Connection, id: i64, worked: bool, note: &str) -> Result<()> {
+    if db::get_consolidated(cons, id)?.is_none() {
+        anyhow::bail!("Consolidated memory #{} not found.", id);
+    }
+    cons.execute(
+        "INSERT INTO outcomes (consolidated_id, worked, note) VALUES (?1, ?2, ?3)",
+        params![id, worked, note.trim()],
+    )?;
+    Ok(())
+}
+
+pub fn outcomes(cons: &Connection, id: i64) -> Result<Vec<Outcome>> {
+    let mut stmt =
+        cons.prepare("SELECT worked, note, created_at FROM outcomes WHERE consolidated_id = ?1 ORDER BY id")?;
+    let rows = stmt.query_map(params![id], |r| Ok(Outcome { worked: r.get(0)?, note: r.get(1)?, created_at: r.get(2)? }))?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+/// Every outcome with the ID of the consolidated memory it reports on, oldest first.
+pub fn all(cons: &Connection) -> Result<Vec<(i64, Outcome)>> {
+    let mut stmt = cons.prepare("SELECT consolidated_id, worked, note, created_at FROM outcomes ORDER BY id")?;
+    let rows = stmt.query_map([], |r| {
+        Ok((r.get(0)?, Outcome { worked: r.get(1)?, note: r.get(2)?, created_at: r.get(3)? }))
+    })?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+pub fn show(cons: &Connection, id: i64) -> Result<Shown> {
+    let consolidated =
+        db::get_consolidated(cons, id)?.ok_or_else(|| anyhow::anyhow!("Consolidated memory #{} not found.", id))?;
+    let outcomes = outcomes(cons, id)?;
+    let worked = outcomes.iter().filter(|o| o.worked).count();
+    let failed = outcomes.len() - worked;
+    let success_rate = (!outcomes.is_empty()).then(|| worked as f64 / outcomes.len() as f64);
+    Ok(Shown { consolidated, worked, failed, success_rate, outcomes })
+}
+
+/// Fold outcomes recorded since the last sleep into confidence: up `WORKED_STEP` per success,
+/// down `FAILED_STEP` per failure, kept within 0.0 to 1.0. Returns how many memories changed.
+pub fn apply(cons: &Connection) -> Result<usize> {
+    let tx = cons.unchecked_transaction()?;
+    let pending: Vec<(i64, i64, i64)> = {
+        let mut stmt = tx.prepare(
+            "SELECT consolidated_id, SUM(worked), SUM(1 - worked) FROM outcomes WHERE applied = 0 GROUP BY consolidated_id",
+        )?;
+        stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?.collect::<rusqlite::Result<_>>()?
+    };
+    let mut changed = 0;
+    for (id, worked, failed) in pending {
+        let delta = worked as f64 * WORKED_STEP - failed as f64 * FAILED_STEP;
+        changed += tx.execute(
+            "UPDATE consolidated SET confidence = MAX(0.0, MIN(1.0, confidence + ?1)) WHERE id = ?2",
+            params![delta, id],
+        )?;
+    }
+    tx.execute("UPDATE outcomes SET applied = 1 WHERE applied = 0", [])?;
+    tx.commit()?;
+    Ok(changed)
+}
+
+pub fn format(s: &Shown) -> String {
+    let c = &s.consolidated;
+    let mut out = format!(
+        "Consolidated #{} ({}, confidence {:.2}, {} sources, recalled {}x, {}):\n  {}\n",
+        c.id,
+        c.r#type,
+        c.confidence,
+        c.source_ids.len(),
+        c.access_count,
+        c.sensitivity,
+        c.content.replace('\n', "\n  ")
+    );
+    if !c.refs.is_empty() {
+        out.push_str(&format!("Refs: {}\n", c.refs.join(", ")));
+    }
+    out.push_str(&format!("Created {}, updated {}\n", c.created_at, c.updated_at));
+    match s.success_rate {
+        Some(rate) => out.push_str(&format!(
+            "\nOutcomes: {} worked, {} failed ({:.0}% success)\n",
+            s.worked,
+            s.failed,
+            rate * 100.0
+        )),
+        None => out.push_str("\nNo outcomes recorded; add one with `cortex outcome <id> --worked` or `--failed`.\n"),
+    }
+    for o in &s.outcomes {
+        let verdict = if o.worked { "worked" } else { "failed" };
+        let note = if o.note.is_empty() { String::new() } else { format!(": {}", o.note) };
+        out.push_str(&format!("  {} {}{}\n", o.created_at, verdict, note));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn outcomes_adjust_confidence_once() {
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let id = db::insert_consolidated(&cons, "Retry uploads with backoff", "pattern", &[], 0.5).unwrap();
+        record(&cons, id, true, "fixed the flaky upload").unwrap();
+        record(&cons, id, true, "").unwrap();
+        record(&cons, id, false, "backoff hid a real outage").unwrap();
+        assert!(record(&cons, id + 1, true, "").is_err());
+
+        let shown = show(&cons, id).unwrap();
+        assert_eq!((shown.worked, shown.failed), (2, 1));
+        assert!((shown.success_rate.unwrap() - 2.0 / 3.0).abs() < 1e-9);
+        assert!(format(&shown).contains("67% success"));
+
+        assert_eq!(apply(&cons).unwrap(), 1);
+        assert_eq!(apply(&cons).unwrap(), 0, "outcomes count once");
+        let confidence = db::get_consolidated(&cons, id).unwrap().unwrap().confidence;
+        assert!((confidence - 0.5).abs() < 1e-9, "+0.05 +0.05 -0.1: {}", confidence);
+    }
+}
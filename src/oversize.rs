@@ -0,0 +1,225 @@
+use anyhow::Result;
+use rusqlite::Connection;
+
+use crate::audit::{self, Manifest};
+use crate::config::{Config, Oversize};
+use crate::db;
+use crate::llm;
+use crate::refs;
+use crate::sensitivity::Sensitivity;
+
+/// Unsummarized memories handled per sleep, to bound the extra LLM calls.
+const SUMMARIES_PER_SLEEP: usize = 20;
+
+/// How an oversized memory was shortened before storing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shortened {
+    Truncated,
+    Summarized,
+}
+
+pub struct SavedMemory {
+    pub id: i64,
+    /// Content as stored (shortened if it was over `[content] max_chars`)
+    pub content: String,
+    pub shortened: Option<Shortened>,
+    /// False when `[trust]` quarantines the save's source
+    pub trusted: bool,
+    pub sensitivity: Sensitivity,
+}
+
+impl SavedMemory {
+    /// Note for the user when the content was shortened, e.g. appended to "Saved memory #1".
+    pub fn note(&self, max_chars: usize) -> String {
+        let mut note = self.shortened_note(max_chars);
+        if !self.trusted {
+            note.push_str(&format!("; untrusted until the next sleep or `cortex trust {}`", self.id));
+        }
+        note
+    }
+
+    fn shortened_note(&self, max_chars: usize) -> String {
+        match self.shortened {
+            Some(Shortened::Truncated) => format!(
+                "; truncated to {} chars, full text in `cortex attachment {}`",
+                max_chars, self.id
+            ),
+            Some(Shortened::Summarized) => format!("; summarized, full text in `cortex attachment {}`", self.id),
+            None => String::new(),
+        }
+    }
+}
+
+/// Save a raw memory, applying `[content]` limits. Content over `max_chars` is summarized
+/// (when `oversize = "summarize"` and an LLM is reachable) or truncated, and the full text is
+/// kept in the `attachments` table. References are detected on the full text. `source` (e.g.
+/// `cli`, `mcp`) decides whether the memory starts out trusted, per `[trust]`. Without an
+/// explicit `sensitivity` the memory gets `[sensitivity] default`.
+pub async fn save_memory(
+    conn: &Connection,
+    content: &str,
+    mem_type: &str,
+    session_id: &str,
+    source: &str,
+    sensitivity: Option<Sensitivity>,
+    config: &Config,
+) -> Result<SavedMemory> {
+    let mut saved = store(conn, content, mem_type, session_id, config).await?;
+    saved.sensitivity = sensitivity.unwrap_or(config.sensitivity.default);
+    if saved.sensitivity != Sensitivity::default() {
+        db::set_memory_sensitivity(conn, &[saved.id], saved.sensitivity)?;
+    }
+    saved.trusted = config.trust.trusts(source);
+    if !saved.trusted {
+        db::set_trusted(conn, &[saved.id], false)?;
+    }
+    Ok(saved)
+}
+
+async fn store(conn: &Connection, content: &str, mem_type: &str, session_id: &str, config: &Config) -> Result<SavedMemory> {
+    let max_chars = config.content.max_chars;
+    if max_chars == 0 || content.chars().count() <= max_chars {
+        let id = db::save_memory(conn, content, mem_type, session_id)?;
+        let content = content.to_string();
+        return Ok(SavedMemory { id, content, shortened: None, trusted: true, sensitivity: Sensitivity::default() });
+    }
+
+    // Stored truncated first, so the summary call can be audited against the memory's ID
+    let truncated = truncate(content, max_chars);
+    let id = db::save_memory(conn, &truncated, mem_type, session_id)?;
+    db::insert_attachment(conn, id, content, false)?;
+    db::add_memory_refs(conn, id, &refs::extract_refs(content))?;
+    let mut saved = SavedMemory {
+        id,
+        content: truncated,
+        shortened: Some(Shortened::Truncated),
+        trusted: true,
+        sensitivity: Sensitivity::default(),
+    };
+
+    if config.content.oversize == Oversize::Summarize {
+        let summary = llm::summarize_memory(content, max_chars, config);
+        match llm::audited(Manifest::new(conn, "summarize", audit::raw_keys([id])), summary).await {
+            Ok(s) => {
+                let s = truncate(&s, max_chars);
+                db::set_summarized_content(conn, id, &s)?;
+                saved.content = s;
+                saved.shortened = Some(Shortened::Summarized);
+            }
+            Err(e) => eprintln!("Warning: could not summarize oversized memory ({}); truncating instead.", e),
+        }
+    }
+    Ok(saved)
+}
+
+/// With `oversize = "summarize"`, replace memories that were truncated (because no LLM was
+/// reachable at save time) with summaries before they are consolidated. Best-effort: stops at
+/// the first failure and leaves the rest truncated. Returns how many were summarized.
+pub async fn summarize_pending(conn: &Connection, config: &Config) -> usize {
+    if config.content.oversize != Oversize::Summarize || config.content.max_chars == 0 {
+        return 0;
+    }
+    let pending = match db::get_unsummarized_attachments(conn, SUMMARIES_PER_SLEEP) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Warning: could not read attachments: {}", e);
+            return 0;
+        }
+    };
+    let mut summarized = 0;
+    for (id, full) in pending {
+        let summary = llm::summarize_memory(&full, config.content.max_chars, config);
+        match llm::audited(Manifest::new(conn, "summarize", audit::raw_keys([id])), summary).await {
+            Ok(s) => {
+                if let Err(e) = db::set_summarized_content(conn, id, &truncate(&s, config.content.max_chars)) {
+                    eprintln!("Warning: could not store summary for memory #{}: {}", id, e);
+                    break;
+                }
+                summarized += 1;
+            }
+            Err(e) => {
+                eprintln!("Warning: could not summarize memory #{}: {}", id, e);
+                break;
+            }
+        }
+    }
+    summarized
+}
+
+/// Cut `content` to at most `max_chars` characters including a trailing marker, preferring a
+/// line or word boundary near the end.
+pub fn truncate(content: &str, max_chars: usize) -> String {
+    let total = content.chars().count();
+    if total <= max_chars {
+        return content.to_string();
+    }
+    let marker = format!("\n\n[truncated from {} chars]", total);
+    let budget = max_chars.saturating_sub(marker.chars().count());
+    let end = content.char_indices().nth(budget).map(|(i, _)| i).unwrap_or(content.len());
+    let head = &content[..end];
+    // Back up to a newline or space if one is close, so words and log lines stay whole
+    let cut = head
+        .rfind('\n')
+        .filter(|&i| i >= end * 4 / 5)
+        .or_else(|| head.rfind(' ').filter(|&i| i >= end * 4 / 5))
+        .unwrap_or(end);
+    format!("{}{}", head[..cut].trim_end(), marker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncates_within_budget_at_boundaries() {
+        let text = "word ".repeat(100);
+        let out = truncate(&text, 120);
+        assert!(out.chars().count() <= 120);
+        assert!(out.ends_with("[truncated from 500 chars]"));
+        assert!(out.starts_with("word word"));
+        assert!(!out.contains("wor\n"));
+
+        let cjk = "東京タワー".repeat(50);
+        let out = truncate(&cjk, 60);
+        assert!(out.chars().count() <= 60);
+        assert_eq!(truncate("short", 60), "short");
+    }
+
+    #[tokio::test]
+    async fn keeps_full_text_as_attachment() {
+        let conn = db::open_raw_db(std::path::Path::new(":memory:")).unwrap();
+        let mut config = Config::default();
+        config.content.max_chars = 100;
+        let long = format!("{} see #42", "log line\n".repeat(50));
+
+        let saved = save_memory(&conn, &long, "observation", "s1", "cli", None, &config).await.unwrap();
+        assert_eq!(saved.shortened, Some(Shortened::Truncated));
+        assert!(saved.content.chars().count() <= 100);
+        assert_eq!(db::get_attachment(&conn, saved.id).unwrap().as_deref(), Some(long.as_str()));
+        assert_eq!(db::recall_by_ref(&conn, "#42", 5).unwrap().len(), 1);
+
+        let short = save_memory(&conn, "fits", "observation", "s1", "cli", None, &config).await.unwrap();
+        assert_eq!(short.shortened, None);
+        assert!(db::get_attachment(&conn, short.id).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn quarantines_untrusted_sources() {
+        let conn = db::open_raw_db(std::path::Path::new(":memory:")).unwrap();
+        let config = Config::default();
+        let ranking = &config.recall;
+        let ci = save_memory(&conn, "Flaky test in the parser suite", "observation", "s1", "ci", None, &config).await.unwrap();
+        let cli = save_memory(&conn, "Parser suite runs in parallel", "observation", "s1", "cli", None, &config).await.unwrap();
+        assert!(!ci.trusted && cli.trusted);
+        assert!(ci.note(100).contains(&format!("`cortex trust {}`", ci.id)));
+
+        let found = |conn| db::search_memories_scored(conn, "parser suite", 10, ranking).unwrap().into_iter().map(|(m, _)| m.id).collect::<Vec<_>>();
+        assert_eq!(found(&conn), vec![cli.id]);
+        assert_eq!(db::get_untrusted_memories(&conn).unwrap().len(), 1);
+
+        // Sleep consolidating a memory promotes it
+        db::mark_consolidated(&conn, &[ci.id]).unwrap();
+        assert!(db::get_untrusted_memories(&conn).unwrap().is_empty());
+        assert_eq!(found(&conn).len(), 2);
+    }
+}
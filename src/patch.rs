@@ -0,0 +1,310 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags, params};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::db;
+use crate::models::ConsolidatedMemory;
+use crate::sensitivity::Sensitivity;
+use crate::snapshot;
+
+/// `format` field of a patch file.
+const FORMAT: &str = "cortex-patch";
+const VERSION: u32 = 1;
+
+/// Consolidated memories changed since a baseline, keyed by content rather than ID so another
+/// store can apply them (`cortex export --since`, `cortex import --patch`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Patch {
+    pub format: String,
+    pub version: u32,
+    /// The snapshot or date the patch was taken against; empty for a full export
+    #[serde(default)]
+    pub since: String,
+    pub created_at: String,
+    /// Added or changed memories
+    #[serde(default)]
+    pub upserts: Vec<Entry>,
+    /// Content of memories deleted since the baseline
+    #[serde(default)]
+    pub deletes: Vec<String>,
+}
+
+impl Patch {
+    /// An empty patch taken against `since`.
+    pub fn new(since: &str) -> Self {
+        Self {
+            format: FORMAT.into(),
+            version: VERSION,
+            since: since.into(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub content: String,
+    pub r#type: String,
+    pub confidence: f64,
+    #[serde(default)]
+    pub refs: Vec<String>,
+    #[serde(default)]
+    pub sensitivity: Sensitivity,
+    /// Never sent to an LLM; a receiver never clears the flag
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_llm: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+    /// Recalls counted by the sender; a receiver keeps the higher count
+    #[serde(default)]
+    pub access_count: i64,
+    /// Content this entry had at the baseline, when it was edited since
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaces: Option<String>,
+}
+
+impl Entry {
+    pub(crate) fn new(m: &ConsolidatedMemory, replaces: Option<String>) -> Self {
+        Self {
+            content: m.content.clone(),
+            r#type: m.r#type.clone(),
+            confidence: m.confidence,
+            refs: m.refs.clone(),
+            sensitivity: m.sensitivity,
+            no_llm: m.no_llm,
+            created_at: Some(m.created_at.clone()),
+            updated_at: Some(m.updated_at.clone()),
+            access_count: m.access_count,
+            replaces,
+        }
+    }
+}
+
+/// What applying a patch did.
+#[derive(Debug, Default, Serialize)]
+pub struct Applied {
+    pub added: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    /// Upserts already present unchanged, or deletes of memories this store doesn't have
+    pub unchanged: usize,
+}
+
+/// Where a `--since` value points.
+enum Baseline {
+    /// consolidated.db of a snapshot
+    Snapshot(std::path::PathBuf),
+    /// `updated_at` cutoff (`%Y-%m-%d %H:%M:%S` UTC); deletions can't be seen
+    Date(String),
+}
+
+/// Resolve `since`: a snapshot name, or a date (`YYYY-MM-DD` or RFC 3339). A date uses the newest
+/// snapshot taken at or before it when there is one, so deletions are included.
+fn baseline(cortex_dir: &Path, since: &str) -> Result<Baseline> {
+    let snapshots = snapshot::list(cortex_dir)?;
+    if let Some(s) = snapshots.iter().find(|s| s.name == since) {
+        return Ok(Baseline::Snapshot(cortex_dir.join(snapshot::DIR).join(&s.name).join("consolidated.db")));
+    }
+    let cutoff = chrono::DateTime::parse_from_rfc3339(since)
+        .map(|d| d.with_timezone(&chrono::Utc))
+        .or_else(|_| {
+            chrono::NaiveDate::parse_from_str(since, "%Y-%m-%d").map(|d| d.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        })
+        .map_err(|_| anyhow::anyhow!("--since must be a snapshot name (see `cortex snapshot list`) or a date"))?;
+    let stamp = cutoff.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    if let Some(s) = snapshots.iter().rev().find(|s| s.created_at <= stamp) {
+        return Ok(Baseline::Snapshot(cortex_dir.join(snapshot::DIR).join(&s.name).join("consolidated.db")));
+    }
+    Ok(Baseline::Date(cutoff.format("%Y-%m-%d %H:%M:%S").to_string()))
+}
+
+/// Memories at or below `max` sensitivity that changed since `since` in the store at
+/// `cortex_dir`, or all of them without `since`.
+pub fn export(cons_conn: &Connection, cortex_dir: &Path, since: Option<&str>, max: Sensitivity) -> Result<Patch> {
+    let current: Vec<ConsolidatedMemory> =
+        db::get_all_consolidated(cons_conn)?.into_iter().filter(|m| m.sensitivity <= max).collect();
+    let mut patch = Patch::new(since.unwrap_or_default());
+    let Some(since) = since else {
+        patch.upserts = current.iter().map(|m| Entry::new(m, None)).collect();
+        return Ok(patch);
+    };
+    match baseline(cortex_dir, since)? {
+        Baseline::Snapshot(path) => {
+            let old = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            (patch.upserts, patch.deletes) = diff(db::get_all_consolidated(&old)?, &current, max);
+        }
+        Baseline::Date(cutoff) => {
+            eprintln!("No snapshot predates {}; the patch has no deletions.", since);
+            patch.upserts = current.iter().filter(|m| m.updated_at >= cutoff).map(|m| Entry::new(m, None)).collect();
+        }
+    }
+    Ok(patch)
+}
+
+/// Upserts and deletes turning `old` into `current` (already filtered to `max`), matched by ID.
+pub(crate) fn diff(
+    old: Vec<ConsolidatedMemory>,
+    current: &[ConsolidatedMemory],
+    max: Sensitivity,
+) -> (Vec<Entry>, Vec<String>) {
+    let old: HashMap<i64, ConsolidatedMemory> = old.into_iter().map(|m| (m.id, m)).collect();
+    let mut upserts = vec![];
+    for m in current {
+        match old.get(&m.id) {
+            None => upserts.push(Entry::new(m, None)),
+            Some(o) if changed(o, m) => upserts.push(Entry::new(m, (o.content != m.content).then(|| o.content.clone()))),
+            Some(_) => {}
+        }
+    }
+    // Memories made more sensitive since count as deleted for the receiver
+    let kept: HashMap<i64, &ConsolidatedMemory> = current.iter().map(|m| (m.id, m)).collect();
+    let mut deletes: Vec<&ConsolidatedMemory> =
+        old.values().filter(|o| o.sensitivity <= max && !kept.contains_key(&o.id)).collect();
+    deletes.sort_by_key(|o| o.id);
+    (upserts, deletes.into_iter().map(|o| o.content.clone()).collect())
+}
+
+pub(crate) fn changed(old: &ConsolidatedMemory, new: &ConsolidatedMemory) -> bool {
+    old.content != new.content
+        || old.r#type != new.r#type
+        || old.sensitivity != new.sensitivity
+        || old.no_llm != new.no_llm
+        || old.access_count != new.access_count
+        || (old.confidence - new.confidence).abs() > 0.01
+}
+
+/// Parse a patch file's contents.
+pub fn parse(text: &str) -> Result<Patch> {
+    let patch: Patch = serde_json::from_str(text).context("Not a cortex patch file")?;
+    if patch.format != FORMAT {
+        anyhow::bail!("Not a cortex patch file (format '{}')", patch.format);
+    }
+    if patch.version > VERSION {
+        anyhow::bail!("Patch version {} is newer than this cortex supports ({})", patch.version, VERSION);
+    }
+    Ok(patch)
+}
+
+fn find_by_content(conn: &Connection, content: &str) -> Result<Option<i64>> {
+    let id = conn.query_row("SELECT id FROM consolidated WHERE content = ?1 ORDER BY id LIMIT 1", params![content], |r| r.get(0));
+    match id {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Apply `patch` to a consolidated store in one transaction. Upserts match existing memories by
+/// their baseline content, then their content; deletes match by content.
+pub fn apply(cons_conn: &Connection, patch: &Patch) -> Result<Applied> {
+    let tx = cons_conn.unchecked_transaction()?;
+    let mut applied = Applied::default();
+    for e in &patch.upserts {
+        let target = match e.replaces.as_deref() {
+            Some(old) => find_by_content(&tx, old)?.or(find_by_content(&tx, &e.content)?),
+            None => find_by_content(&tx, &e.content)?,
+        };
+        let Some(id) = target else {
+            // Sources are raw.db IDs of the sending store, so a new memory starts without any
+            let id = db::insert_consolidated(&tx, &e.content, &e.r#type, &[], e.confidence)?;
+            tx.execute(
+                "UPDATE consolidated SET no_llm = ?1, access_count = ?2, created_at = COALESCE(?3, created_at),
+                     updated_at = COALESCE(?4, updated_at) WHERE id = ?5",
+                params![e.no_llm, e.access_count, e.created_at, e.updated_at, id],
+            )?;
+            db::add_consolidated_refs(&tx, id, &e.refs)?;
+            db::set_consolidated_sensitivity(&tx, &[id], e.sensitivity)?;
+            applied.added += 1;
+            continue;
+        };
+        let current = db::get_consolidated(&tx, id)?.context("memory vanished mid-import")?;
+        if !changed(&current, &as_memory(e, &current)) {
+            applied.unchanged += 1;
+            continue;
+        }
+        tx.execute(
+            "UPDATE consolidated SET content = ?1, type = ?2, confidence = ?3, no_llm = MAX(no_llm, ?4),
+                 access_count = MAX(access_count, ?5), updated_at = COALESCE(?6, datetime('now')) WHERE id = ?7",
+            params![e.content, e.r#type, e.confidence, e.no_llm, e.access_count, e.updated_at, id],
+        )?;
+        db::add_consolidated_refs(&tx, id, &e.refs)?;
+        db::set_consolidated_sensitivity(&tx, &[id], e.sensitivity)?;
+        applied.updated += 1;
+    }
+    for content in &patch.deletes {
+        match find_by_content(&tx, content)? {
+            Some(id) => {
+                db::remove_consolidated(&tx, &[id])?;
+                applied.deleted += 1;
+            }
+            None => applied.unchanged += 1,
+        }
+    }
+    tx.commit()?;
+    Ok(applied)
+}
+
+/// `e` as a memory in place of `current`, to compare with `changed`.
+fn as_memory(e: &Entry, current: &ConsolidatedMemory) -> ConsolidatedMemory {
+    ConsolidatedMemory {
+        content: e.content.clone(),
+        r#type: e.r#type.clone(),
+        confidence: e.confidence,
+        sensitivity: e.sensitivity,
+        no_llm: current.no_llm || e.no_llm,
+        access_count: current.access_count.max(e.access_count),
+        ..current.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_and_applies_changes_since_a_snapshot() {
+        let dir = std::env::temp_dir().join(format!("cortex-patch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let raw = db::open_raw_db(&dir.join("raw.db")).unwrap();
+        let cons = db::open_consolidated_db(&dir.join("consolidated.db")).unwrap();
+        let edited = db::insert_consolidated(&cons, "Use sqlx for migrations", "decision", &[], 0.9).unwrap();
+        let dropped = db::insert_consolidated(&cons, "Deploy on Fridays", "pattern", &[], 0.5).unwrap();
+        db::insert_consolidated(&cons, "Tests run with nextest", "convention", &[], 0.7).unwrap();
+
+        // A teammate's store starts from the same memories
+        let theirs = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let full = export(&cons, &dir, None, Sensitivity::Internal).unwrap();
+        assert_eq!(apply(&theirs, &full).unwrap().added, 3);
+
+        let base = snapshot::create(&raw, &cons, &dir, "shared").unwrap();
+        db::update_consolidated(&cons, edited, "Use sqlx for migrations and queries").unwrap();
+        db::remove_consolidated(&cons, &[dropped]).unwrap();
+        let secret = db::insert_consolidated(&cons, "Staging password rotation", "convention", &[], 0.8).unwrap();
+        db::set_consolidated_sensitivity(&cons, &[secret], Sensitivity::Secret).unwrap();
+        db::insert_consolidated(&cons, "Prefer tracing over log", "convention", &[], 0.8).unwrap();
+
+        let patch = export(&cons, &dir, Some(&base.name), Sensitivity::Internal).unwrap();
+        assert_eq!(patch.upserts.len(), 2);
+        assert_eq!(patch.deletes, ["Deploy on Fridays"]);
+        let text = serde_json::to_string(&patch).unwrap();
+        let applied = apply(&theirs, &parse(&text).unwrap()).unwrap();
+        assert_eq!((applied.added, applied.updated, applied.deleted), (1, 1, 1));
+        let contents: Vec<String> = db::get_all_consolidated(&theirs).unwrap().into_iter().map(|m| m.content).collect();
+        assert!(contents.contains(&"Use sqlx for migrations and queries".to_string()));
+        assert_eq!(contents.len(), 3);
+
+        // Applying twice changes nothing
+        let again = apply(&theirs, &parse(&text).unwrap()).unwrap();
+        assert_eq!((again.added, again.updated, again.deleted, again.unchanged), (0, 0, 0, 3));
+        assert!(parse(r#"{"format":"other","version":1,"created_at":""}"#).is_err());
+        let dated = export(&cons, &dir, Some("2000-01-01"), Sensitivity::Internal).unwrap();
+        assert!(dated.deletes.is_empty() && dated.upserts.len() == 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
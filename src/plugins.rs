@@ -0,0 +1,300 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::context::{ContextFormat, xml_escape};
+use crate::trust::{self, Capability};
+
+/// Budget for a single plugin request, including `describe` at load time.
+const PLUGIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Responses larger than this are rejected rather than buffered.
+const MAX_RESPONSE_BYTES: u64 = 1024 * 1024;
+/// Environment passed through to plugins and hook commands; everything else (API keys, tokens)
+/// is withheld.
+pub(crate) const ENV_ALLOWLIST: &[&str] = &["PATH", "HOME", "LANG", "TMPDIR"];
+
+/// An executable (or, with the `wasmtime` feature, a `.wasm` transform) in
+/// `.cortex/plugins/` that passed validation at load time.
+pub struct Plugin {
+    pub name: String,
+    runtime: Runtime,
+    pub tools: Vec<PluginTool>,
+    pub processor: bool,
+    pub context: bool,
+}
+
+enum Runtime {
+    Process { path: PathBuf, project_root: PathBuf },
+    #[cfg(feature = "wasmtime")]
+    Wasm(crate::wasm::WasmTransform),
+}
+
+/// An MCP tool contributed by a plugin.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginTool {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename = "inputSchema", default = "default_schema")]
+    pub input_schema: Value,
+}
+
+/// Reply to `{"method": "describe"}`.
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    tools: Vec<PluginTool>,
+    #[serde(default)]
+    processor: bool,
+    #[serde(default)]
+    context: bool,
+}
+
+fn default_schema() -> Value {
+    serde_json::json!({ "type": "object", "properties": {} })
+}
+
+/// Load and validate every plugin in `<cortex_dir>/plugins/`. Invalid plugins are
+/// skipped with a warning so one broken executable can't take cortex down. A project's
+/// plugins come with its repo, so they only load after `cortex trust --plugins`; the global
+/// store's always do.
+pub async fn load_plugins(cortex_dir: &Path) -> Vec<Plugin> {
+    let dir = cortex_dir.join("plugins");
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return vec![];
+    };
+    if !trust::is_trusted(cortex_dir, Capability::Plugins) {
+        if entries.filter_map(|e| e.ok()).any(|e| !e.file_name().to_string_lossy().starts_with('.')) {
+            eprintln!(
+                "Warning: not loading plugins from {}; run `cortex trust --plugins` in this project to allow them",
+                dir.display()
+            );
+        }
+        return vec![];
+    }
+    let project_root = cortex_dir.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+
+    let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    paths.sort();
+
+    let mut plugins: Vec<Plugin> = Vec::new();
+    for path in paths {
+        match load_plugin(&path, &project_root, &plugins).await {
+            Ok(Some(plugin)) => plugins.push(plugin),
+            Ok(None) => {}
+            Err(e) => eprintln!("Warning: skipping plugin {}: {:#}", path.display(), e),
+        }
+    }
+    plugins
+}
+
+async fn load_plugin(path: &Path, project_root: &Path, loaded: &[Plugin]) -> Result<Option<Plugin>> {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(n) if !n.starts_with('.') => n.split('.').next().unwrap_or(n).to_string(),
+        _ => return Ok(None),
+    };
+    if path.extension().and_then(|e| e.to_str()) == Some("wasm") {
+        return load_wasm_plugin(name, path).map(Some);
+    }
+    validate_executable(path)?;
+
+    let mut plugin = Plugin {
+        name,
+        runtime: Runtime::Process {
+            path: path.to_path_buf(),
+            project_root: project_root.to_path_buf(),
+        },
+        tools: vec![],
+        processor: false,
+        context: false,
+    };
+    let manifest: Manifest = serde_json::from_value(plugin.request(serde_json::json!({ "method": "describe" })).await?)
+        .context("invalid describe response")?;
+
+    for tool in &manifest.tools {
+        if tool.name.starts_with("cortex_") {
+            anyhow::bail!("tool `{}` uses the reserved cortex_ prefix", tool.name);
+        }
+        if loaded.iter().any(|p| p.tools.iter().any(|t| t.name == tool.name)) {
+            anyhow::bail!("tool `{}` is already provided by another plugin", tool.name);
+        }
+    }
+    plugin.tools = manifest.tools;
+    plugin.processor = manifest.processor;
+    plugin.context = manifest.context;
+    Ok(Some(plugin))
+}
+
+/// WASM modules only act as save-time transforms.
+#[cfg(feature = "wasmtime")]
+fn load_wasm_plugin(name: String, path: &Path) -> Result<Plugin> {
+    let meta = std::fs::symlink_metadata(path)?;
+    if !meta.file_type().is_file() {
+        anyhow::bail!("not a regular file (symlinks and directories are not loaded)");
+    }
+    Ok(Plugin {
+        name,
+        runtime: Runtime::Wasm(crate::wasm::WasmTransform::load(path)?),
+        tools: vec![],
+        processor: true,
+        context: false,
+    })
+}
+
+#[cfg(not(feature = "wasmtime"))]
+fn load_wasm_plugin(_name: String, _path: &Path) -> Result<Plugin> {
+    anyhow::bail!("WASM plugins require cortex built with `--features wasmtime`")
+}
+
+/// Only plain, executable files that other users can't modify are run.
+fn validate_executable(path: &Path) -> Result<()> {
+    let meta = std::fs::symlink_metadata(path)?;
+    if !meta.file_type().is_file() {
+        anyhow::bail!("not a regular file (symlinks and directories are not loaded)");
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = meta.permissions().mode();
+        if mode & 0o111 == 0 {
+            anyhow::bail!("not executable");
+        }
+        if mode & 0o022 != 0 {
+            anyhow::bail!("group- or world-writable (mode {:o})", mode & 0o777);
+        }
+    }
+    Ok(())
+}
+
+impl Plugin {
+    /// Send one JSON request on stdin and read one JSON reply from stdout.
+    async fn request(&self, req: Value) -> Result<Value> {
+        let (path, project_root) = match &self.runtime {
+            Runtime::Process { path, project_root } => (path, project_root),
+            #[cfg(feature = "wasmtime")]
+            Runtime::Wasm(_) => anyhow::bail!("WASM plugins only support save-time transforms"),
+        };
+        let mut child = tokio::process::Command::new(path)
+            .current_dir(project_root)
+            .env_clear()
+            .envs(ENV_ALLOWLIST.iter().filter_map(|k| std::env::var(k).ok().map(|v| (*k, v))))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .context("failed to start")?;
+
+        let mut stdin = child.stdin.take().context("no stdin")?;
+        let mut stdout = child.stdout.take().context("no stdout")?.take(MAX_RESPONSE_BYTES + 1);
+
+        let exchange = async {
+            stdin.write_all(format!("{}\n", req).as_bytes()).await?;
+            drop(stdin);
+            let mut buf = Vec::new();
+            stdout.read_to_end(&mut buf).await?;
+            let status = child.wait().await?;
+            Ok::<_, std::io::Error>((buf, status))
+        };
+        let (buf, status) = tokio::time::timeout(PLUGIN_TIMEOUT, exchange)
+            .await
+            .map_err(|_| anyhow::anyhow!("timed out after {}s", PLUGIN_TIMEOUT.as_secs()))??;
+
+        if buf.len() as u64 > MAX_RESPONSE_BYTES {
+            anyhow::bail!("response exceeds {} bytes", MAX_RESPONSE_BYTES);
+        }
+        if !status.success() {
+            anyhow::bail!("exited with {}", status);
+        }
+        let resp: Value = serde_json::from_slice(&buf).context("response is not valid JSON")?;
+        if let Some(err) = resp.get("error").and_then(|e| e.as_str()) {
+            anyhow::bail!("{}", err);
+        }
+        Ok(resp)
+    }
+}
+
+/// Run save-time processors in load order. Each may rewrite the content and type.
+pub async fn process_memory(plugins: &[Plugin], content: &str, mem_type: &str) -> Result<(String, String)> {
+    let mut content = content.to_string();
+    let mut mem_type = mem_type.to_string();
+    for plugin in plugins.iter().filter(|p| p.processor) {
+        #[cfg(feature = "wasmtime")]
+        if let Runtime::Wasm(wasm) = &plugin.runtime {
+            (content, mem_type) = wasm
+                .transform(&content, &mem_type)
+                .with_context(|| format!("plugin `{}` failed to transform memory", plugin.name))?;
+            continue;
+        }
+        let resp = plugin
+            .request(serde_json::json!({ "method": "process", "memory": { "content": content, "type": mem_type } }))
+            .await
+            .with_context(|| format!("plugin `{}` failed to process memory", plugin.name))?;
+        if let Some(c) = resp.get("content").and_then(|v| v.as_str()) {
+            content = c.to_string();
+        }
+        if let Some(t) = resp.get("type").and_then(|v| v.as_str()) {
+            mem_type = t.to_string();
+        }
+    }
+    Ok((content, mem_type))
+}
+
+/// Extra context sections contributed by plugins, formatted to follow `format_context` output
+/// (see `context::append_sections`); in XML, each is a `<plugin>` tag.
+pub async fn context_sections(plugins: &[Plugin], format: ContextFormat) -> String {
+    let mut out = String::new();
+    for plugin in plugins.iter().filter(|p| p.context) {
+        let resp = match plugin.request(serde_json::json!({ "method": "context" })).await {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("Warning: plugin `{}` context failed: {:#}", plugin.name, e);
+                continue;
+            }
+        };
+        let body = resp.get("content").and_then(|v| v.as_str()).unwrap_or("").trim();
+        if body.is_empty() {
+            continue;
+        }
+        let title = resp.get("title").and_then(|v| v.as_str()).unwrap_or(&plugin.name);
+        match format {
+            ContextFormat::Compact => {
+                out.push_str(&format!(". {}: {}", title, body.split_whitespace().collect::<Vec<_>>().join(" ")))
+            }
+            ContextFormat::Full => out.push_str(&format!("\n### {}\n{}\n", title, body)),
+            ContextFormat::Xml => out.push_str(&format!(
+                "<plugin name=\"{}\" title=\"{}\">{}</plugin>\n",
+                xml_escape(&plugin.name),
+                xml_escape(title),
+                xml_escape(body)
+            )),
+        }
+    }
+    out
+}
+
+/// Tool definitions for MCP `tools/list`.
+pub fn tool_definitions(plugins: &[Plugin]) -> Vec<Value> {
+    plugins
+        .iter()
+        .flat_map(|p| &p.tools)
+        .map(|t| serde_json::json!({ "name": t.name, "description": t.description, "inputSchema": t.input_schema }))
+        .collect()
+}
+
+/// Dispatch an MCP tool call to the plugin that declared it, if any.
+pub async fn call_tool(plugins: &[Plugin], name: &str, args: &Value) -> Option<Result<String>> {
+    let plugin = plugins.iter().find(|p| p.tools.iter().any(|t| t.name == name))?;
+    let result = plugin
+        .request(serde_json::json!({ "method": "call_tool", "name": name, "arguments": args }))
+        .await
+        .map(|resp| match resp.get("text").and_then(|v| v.as_str()) {
+            Some(text) => text.to_string(),
+            None => resp.to_string(),
+        });
+    Some(result)
+}
@@ -0,0 +1,169 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::db;
+use crate::init;
+use crate::store::Store;
+
+/// Registry of project stores, kept next to the global store.
+const REGISTRY_FILE: &str = "projects.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Registry {
+    /// `.cortex` directories, in the order they were first seen
+    pub stores: Vec<PathBuf>,
+}
+
+/// One row of `cortex projects`.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    /// The project directory (parent of `.cortex/`)
+    pub path: PathBuf,
+    pub memories: i64,
+    pub unconsolidated: i64,
+    pub consolidated: i64,
+    pub last_sleep: Option<String>,
+    /// Bytes on disk under `.cortex/`
+    pub size: u64,
+}
+
+/// `~/.cortex/projects.json` (or under `CORTEX_GLOBAL_DIR`).
+pub fn default_path() -> Option<PathBuf> {
+    init::global_dir_path().map(|d| d.join(REGISTRY_FILE))
+}
+
+/// Load a registry; a missing file is an empty registry.
+pub fn load(path: &Path) -> Result<Registry> {
+    match std::fs::read_to_string(path) {
+        Ok(s) => Ok(serde_json::from_str(&s)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Registry::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save(path: &Path, registry: &Registry) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(registry)?)?;
+    Ok(())
+}
+
+/// Add `cortex_dir` to the registry at `path` unless it is already listed. Returns whether it
+/// was added.
+pub fn register(path: &Path, cortex_dir: &Path) -> Result<bool> {
+    let cortex_dir = cortex_dir.canonicalize().unwrap_or_else(|_| cortex_dir.to_path_buf());
+    let mut registry = load(path)?;
+    if registry.stores.contains(&cortex_dir) {
+        return Ok(false);
+    }
+    registry.stores.push(cortex_dir);
+    save(path, &registry)?;
+    Ok(true)
+}
+
+/// Record a project store in `~/.cortex/projects.json`. Best-effort: a registry that can't be
+/// written never fails the command that opened the store.
+pub fn remember(cortex_dir: &Path) {
+    if let Some(path) = default_path()
+        && let Err(e) = register(&path, cortex_dir)
+    {
+        eprintln!("Warning: could not update {}: {}", path.display(), e);
+    }
+}
+
+/// Registered stores whose `.cortex/` still exists, from `~/.cortex/projects.json`.
+pub fn known_stores() -> Result<Vec<PathBuf>> {
+    let path = default_path().ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+    Ok(load(&path)?.stores.into_iter().filter(|d| d.is_dir()).collect())
+}
+
+/// Drop stores whose `.cortex/` no longer exists. Returns the removed entries.
+pub fn prune_missing(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut registry = load(path)?;
+    let (kept, missing): (Vec<_>, Vec<_>) = registry.stores.into_iter().partition(|d| d.is_dir());
+    registry.stores = kept;
+    if !missing.is_empty() {
+        save(path, &registry)?;
+    }
+    Ok(missing)
+}
+
+/// Counts, last sleep, and size for the store in `cortex_dir`.
+pub fn summarize(cortex_dir: &Path) -> Result<Summary> {
+    let store = Store::open(cortex_dir.to_path_buf());
+    let stats = db::get_stats(store.raw()?, store.cons()?)?;
+    Ok(Summary {
+        path: store.root(),
+        memories: stats.raw_count,
+        unconsolidated: stats.unconsolidated_count,
+        consolidated: stats.consolidated_count,
+        last_sleep: stats.last_sleep,
+        size: dir_size(cortex_dir),
+    })
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else { return 0 };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| match e.metadata() {
+            Ok(m) if m.is_dir() => dir_size(&e.path()),
+            Ok(m) => m.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// `1536` -> `1.5 KB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", size, UNITS[unit]) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_once_and_prunes_deleted_stores() {
+        let tmp = std::env::temp_dir().join(format!("cortex-projects-{}", uuid::Uuid::new_v4()));
+        let registry = tmp.join("home").join(REGISTRY_FILE);
+        let (a, b) = (tmp.join("a").join(".cortex"), tmp.join("b").join(".cortex"));
+        std::fs::create_dir_all(&a).unwrap();
+        std::fs::create_dir_all(&b).unwrap();
+
+        assert!(load(&registry).unwrap().stores.is_empty());
+        assert!(register(&registry, &a).unwrap());
+        assert!(register(&registry, &b).unwrap());
+        assert!(!register(&registry, &a).unwrap());
+        assert_eq!(load(&registry).unwrap().stores.len(), 2);
+
+        db::save_memory(Store::open(a.clone()).raw().unwrap(), "Kept project", "observation", "s1").unwrap();
+        let summary = summarize(&a).unwrap();
+        assert_eq!((summary.memories, summary.unconsolidated, summary.consolidated), (1, 1, 0));
+        assert!(summary.size > 0);
+
+        std::fs::remove_dir_all(tmp.join("b")).unwrap();
+        let missing = prune_missing(&registry).unwrap();
+        assert_eq!(missing.len(), 1);
+        assert!(missing[0].ends_with("b/.cortex"));
+        assert_eq!(load(&registry).unwrap().stores, vec![a.canonicalize().unwrap()]);
+
+        std::fs::remove_dir_all(&tmp).unwrap();
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MB");
+    }
+}
@@ -0,0 +1,394 @@
+use anyhow::Result;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::config::{QualityConfig, Unsupported};
+use crate::models::{ConsolidationResult, Memory};
+use crate::similar::word_set;
+
+/// Words too common to show that a consolidation came from its sources.
+const FILLER: &[&str] = &[
+    "the", "and", "for", "with", "this", "that", "are", "was", "were", "from", "into", "when", "not", "but", "all",
+    "use", "uses", "used", "should", "must", "can", "will", "have", "has", "its", "our", "your", "they", "them",
+];
+
+/// Drop the parts of an LLM consolidation result that fail the `[quality]` gates.
+///
+/// `recent_ids` are the unprocessed observations sent to the model and `existing_ids` the
+/// consolidated memories it was shown. Returns the result to apply and one line per rejected
+/// item.
+pub fn validate(
+    result: ConsolidationResult,
+    recent_ids: &HashSet<i64>,
+    existing_ids: &HashSet<i64>,
+    config: &QualityConfig,
+) -> (ConsolidationResult, Vec<String>) {
+    if !config.enabled {
+        return (result, vec![]);
+    }
+    let mut issues = Vec::new();
+    let content_ok = |content: &str| {
+        let len = content.trim().chars().count();
+        if len < config.min_content_chars {
+            Some(format!("content shorter than {} chars", config.min_content_chars))
+        } else if len > config.max_content_chars {
+            Some(format!("content longer than {} chars", config.max_content_chars))
+        } else {
+            None
+        }
+    };
+
+    let mut out = ConsolidationResult::default();
+
+    for (i, c) in result.consolidations.into_iter().enumerate() {
+        let problem = if c.source_ids.is_empty() {
+            Some("no source_ids".to_string())
+        } else if let Some(id) = c.source_ids.iter().find(|id| !recent_ids.contains(id)) {
+            Some(format!("source id {} is not a recent observation", id))
+        } else if c.r#type.trim().is_empty() {
+            Some("empty type".to_string())
+        } else {
+            content_ok(&c.content)
+        };
+        match problem {
+            Some(p) => issues.push(format!("consolidation {}: {}", i + 1, p)),
+            None => out.consolidations.push(c),
+        }
+    }
+
+    for c in result.contradictions {
+        if !existing_ids.contains(&c.old_id) {
+            issues.push(format!("contradiction: old id {} is not a long-term memory", c.old_id));
+        } else if !recent_ids.contains(&c.new_id) {
+            issues.push(format!("contradiction: new id {} is not a recent observation", c.new_id));
+        } else {
+            out.contradictions.push(c);
+        }
+    }
+
+    for id in result.promotions {
+        if recent_ids.contains(&id) {
+            out.promotions.push(id);
+        } else {
+            issues.push(format!("promotion: id {} is not a recent observation", id));
+        }
+    }
+
+    for id in result.decayed {
+        if existing_ids.contains(&id) {
+            out.decayed.push(id);
+        } else {
+            issues.push(format!("decay: id {} is not a long-term memory", id));
+        }
+    }
+
+    for s in result.skill_updates {
+        if s.name.trim().is_empty() {
+            issues.push("skill update: empty name".to_string());
+        } else if s.content.trim().is_empty() {
+            issues.push(format!("skill `{}`: empty content", s.name));
+        } else if s.content.chars().count() > config.max_skill_chars {
+            issues.push(format!("skill `{}`: content longer than {} chars", s.name, config.max_skill_chars));
+        } else {
+            out.skill_updates.push(s);
+        }
+    }
+
+    for (i, g) in result.global_promotions.into_iter().enumerate() {
+        match content_ok(&g.content) {
+            Some(p) => issues.push(format!("global promotion {}: {}", i + 1, p)),
+            None => out.global_promotions.push(g),
+        }
+    }
+
+    for (i, m) in result.merges.into_iter().enumerate() {
+        let problem = if m.source_ids.len() < 2 {
+            Some("fewer than 2 memories to merge".to_string())
+        } else if let Some(id) = m.source_ids.iter().find(|id| !existing_ids.contains(id)) {
+            Some(format!("id {} is not a long-term memory", id))
+        } else {
+            content_ok(&m.content)
+        };
+        match problem {
+            Some(p) => issues.push(format!("merge {}: {}", i + 1, p)),
+            None => out.merges.push(m),
+        }
+    }
+
+    for e in result.new_entities {
+        if e.name.trim().is_empty() {
+            issues.push("new entity: empty name".to_string());
+        } else {
+            out.new_entities.push(e);
+        }
+    }
+    for r in result.new_relationships {
+        if r.source.trim().is_empty() || r.target.trim().is_empty() {
+            issues.push(format!("relationship `{}`: missing source or target", r.r#type));
+        } else {
+            out.new_relationships.push(r);
+        }
+    }
+    for u in result.entity_updates {
+        if u.name.trim().is_empty() {
+            issues.push("entity update: empty name".to_string());
+        } else {
+            out.entity_updates.push(u);
+        }
+    }
+
+    (out, issues)
+}
+
+/// Contradictions in `result` that need a second look: resolutions other than `keep_new`,
+/// `keep_old`, or `merge`, and contradictions flagged at save time (`(memory_id,
+/// consolidated_id, reason)`) against recent observations that the result leaves unresolved.
+pub fn unresolved_contradictions(
+    result: &ConsolidationResult,
+    flagged: &[(i64, i64, String)],
+    recent_ids: &HashSet<i64>,
+) -> Vec<String> {
+    let mut out: Vec<String> = result
+        .contradictions
+        .iter()
+        .filter(|c| !RESOLUTIONS.contains(&c.resolution.trim()))
+        .map(|c| {
+            format!(
+                "contradiction between long-term #{} and observation #{}: resolution `{}` is not keep_new, keep_old, or merge",
+                c.old_id, c.new_id, c.resolution
+            )
+        })
+        .collect();
+    for (memory_id, consolidated_id, reason) in flagged {
+        let resolved = result.contradictions.iter().any(|c| c.new_id == *memory_id && c.old_id == *consolidated_id);
+        if recent_ids.contains(memory_id) && !resolved {
+            out.push(format!(
+                "observation #{} was flagged as contradicting long-term #{} ({}) but has no contradiction entry",
+                memory_id, consolidated_id, reason
+            ));
+        }
+    }
+    out
+}
+
+const RESOLUTIONS: [&str; 3] = ["keep_new", "keep_old", "merge"];
+
+/// A consolidation the hallucination guard rejected or down-ranked.
+#[derive(Debug, Clone, Serialize)]
+pub struct Caught {
+    /// 1-based position among the result's consolidations
+    pub index: usize,
+    pub content: String,
+    pub r#type: String,
+    pub source_ids: Vec<i64>,
+    /// Share of its words found in the cited observations
+    pub overlap: f64,
+    pub reason: String,
+    /// `rejected` or `downranked`
+    pub action: &'static str,
+}
+
+/// Hallucination guard: every consolidation must cite observations in `sources`, and at least
+/// `min_source_overlap` of its words must appear in them. Consolidations citing unknown IDs are
+/// always removed; low-overlap ones are removed, or kept at half confidence with
+/// `on_unsupported = "downrank"`. Returns what was caught.
+pub fn guard(result: &mut ConsolidationResult, sources: &[Memory], config: &QualityConfig) -> Vec<Caught> {
+    if config.min_source_overlap <= 0.0 {
+        return vec![];
+    }
+    let mut caught = Vec::new();
+    let mut kept = Vec::new();
+    for (i, mut c) in std::mem::take(&mut result.consolidations).into_iter().enumerate() {
+        let cited: Vec<&Memory> = sources.iter().filter(|m| c.source_ids.contains(&m.id)).collect();
+        let overlap = source_overlap(&c.content, &cited);
+        let (reason, action) = if let Some(id) = c.source_ids.iter().find(|id| !cited.iter().any(|m| m.id == **id)) {
+            (format!("cites #{}, which is not an observation in this sleep", id), "rejected")
+        } else if overlap < config.min_source_overlap {
+            let reason = format!(
+                "only {:.0}% of its words appear in its sources (minimum {:.0}%)",
+                overlap * 100.0,
+                config.min_source_overlap * 100.0
+            );
+            match config.on_unsupported {
+                Unsupported::Reject => (reason, "rejected"),
+                Unsupported::Downrank => (reason, "downranked"),
+            }
+        } else {
+            kept.push(c);
+            continue;
+        };
+        caught.push(Caught {
+            index: i + 1,
+            content: c.content.clone(),
+            r#type: c.r#type.clone(),
+            source_ids: c.source_ids.clone(),
+            overlap,
+            reason,
+            action,
+        });
+        if action == "downranked" {
+            c.confidence /= 2.0;
+            kept.push(c);
+        }
+    }
+    result.consolidations = kept;
+    caught
+}
+
+/// One row of `guard_log`, for `cortex rejections`.
+#[derive(Debug, Serialize)]
+pub struct Logged {
+    pub id: i64,
+    pub at: String,
+    pub action: String,
+    pub r#type: String,
+    pub content: String,
+    pub source_ids: Vec<i64>,
+    pub overlap: f64,
+    pub reason: String,
+}
+
+/// Record what the guard caught in raw.db's `guard_log`.
+pub fn log(conn: &Connection, caught: &[Caught]) -> Result<()> {
+    for c in caught {
+        conn.execute(
+            "INSERT INTO guard_log (action, type, content, source_ids, overlap, reason) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![c.action, c.r#type, c.content, serde_json::to_string(&c.source_ids)?, c.overlap, c.reason],
+        )?;
+    }
+    Ok(())
+}
+
+/// Logged guard catches, newest first (`limit` 0 for all).
+pub fn guard_log(conn: &Connection, limit: usize) -> Result<Vec<Logged>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, at, action, type, content, source_ids, overlap, reason FROM guard_log ORDER BY id DESC LIMIT ?1",
+    )?;
+    let limit = if limit == 0 { -1 } else { limit as i64 };
+    let rows = stmt.query_map(params![limit], |row| {
+        let source_ids: String = row.get(5)?;
+        Ok(Logged {
+            id: row.get(0)?,
+            at: row.get(1)?,
+            action: row.get(2)?,
+            r#type: row.get(3)?,
+            content: row.get(4)?,
+            source_ids: serde_json::from_str(&source_ids).unwrap_or_default(),
+            overlap: row.get(6)?,
+            reason: row.get(7)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Share of `content`'s significant words that appear in any of `sources` (1.0 if it has none).
+fn source_overlap(content: &str, sources: &[&Memory]) -> f64 {
+    let words: HashSet<String> = word_set(content).into_iter().filter(|w| !FILLER.contains(&w.as_str())).collect();
+    if words.is_empty() {
+        return 1.0;
+    }
+    let source_words: HashSet<String> = sources.iter().flat_map(|m| word_set(&m.content)).collect();
+    words.iter().filter(|w| source_words.contains(*w)).count() as f64 / words.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Consolidation, Contradiction, SkillUpdate};
+
+    fn result() -> ConsolidationResult {
+        ConsolidationResult {
+            consolidations: vec![
+                Consolidation {
+                    content: "Use tokio for async IO".into(),
+                    r#type: "pattern".into(),
+                    source_ids: vec![1, 2],
+                    confidence: 0.8,
+                },
+                Consolidation {
+                    content: "Hallucinated sources".into(),
+                    r#type: "pattern".into(),
+                    source_ids: vec![1, 99],
+                    confidence: 0.8,
+                },
+            ],
+            promotions: vec![2, 42],
+            decayed: vec![10, 11],
+            skill_updates: vec![SkillUpdate { name: " ".into(), content: "x".into(), category: None }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn drops_invalid_pieces() {
+        let (out, issues) = validate(result(), &HashSet::from([1, 2]), &HashSet::from([10]), &QualityConfig::default());
+        assert_eq!(out.consolidations.len(), 1);
+        assert_eq!(out.promotions, vec![2]);
+        assert_eq!(out.decayed, vec![10]);
+        assert!(out.skill_updates.is_empty());
+        assert_eq!(issues.len(), 4);
+        assert!(issues[0].contains("source id 99"));
+    }
+
+    fn memory(id: i64, content: &str) -> Memory {
+        Memory {
+            id,
+            content: content.into(),
+            r#type: "observation".into(),
+            created_at: String::new(),
+            accessed_at: String::new(),
+            access_count: 0,
+            consolidated: false,
+            importance: 0.5,
+            session_id: None,
+            entity_ids: vec![],
+            tags: vec![],
+            refs: vec![],
+            sensitivity: Default::default(),
+            no_llm: false,
+        }
+    }
+
+    #[test]
+    fn guards_against_unsupported_consolidations() {
+        let sources = [memory(1, "Use tokio for async IO in the daemon"), memory(2, "The daemon uses tokio runtime")];
+        let mut result = result();
+        result.consolidations.push(Consolidation {
+            content: "Deploy with Kubernetes and Helm charts".into(),
+            r#type: "pattern".into(),
+            source_ids: vec![2],
+            confidence: 0.8,
+        });
+
+        let caught = guard(&mut result.clone(), &sources, &QualityConfig::default());
+        assert_eq!(caught.iter().map(|c| (c.index, c.action)).collect::<Vec<_>>(), [(2, "rejected"), (3, "rejected")]);
+        assert!(caught[0].reason.contains("#99"));
+
+        let config = QualityConfig { on_unsupported: Unsupported::Downrank, ..Default::default() };
+        let caught = guard(&mut result, &sources, &config);
+        assert_eq!(caught[1].action, "downranked");
+        assert_eq!(result.consolidations.len(), 2);
+        assert_eq!(result.consolidations[1].confidence, 0.4);
+        assert_eq!(result.consolidations[0].content, "Use tokio for async IO");
+
+        let conn = crate::db::open_raw_db(std::path::Path::new(":memory:")).unwrap();
+        log(&conn, &caught).unwrap();
+        let logged = guard_log(&conn, 1).unwrap();
+        assert_eq!((logged[0].action.as_str(), logged[0].source_ids.as_slice()), ("downranked", &[2][..]));
+    }
+
+    #[test]
+    fn finds_unresolved_contradictions() {
+        let mut result = result();
+        result.contradictions = vec![
+            Contradiction { old_id: 10, new_id: 1, resolution: "keep_new".into() },
+            Contradiction { old_id: 11, new_id: 2, resolution: "unclear".into() },
+        ];
+        let flagged = vec![(1, 10, "disabled vs enabled".into()), (2, 12, "never vs always".into()), (7, 10, "x".into())];
+        let found = unresolved_contradictions(&result, &flagged, &HashSet::from([1, 2]));
+        assert_eq!(found.len(), 2);
+        assert!(found[0].contains("`unclear`"));
+        assert!(found[1].contains("observation #2") && found[1].contains("long-term #12"));
+    }
+}
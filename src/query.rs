@@ -0,0 +1,241 @@
+use serde::{Deserialize, Serialize};
+
+/// Most terms kept from one query; pasted logs otherwise turn into huge OR expressions.
+const MAX_TERMS: usize = 32;
+
+/// FTS5 tokenizer for a store's full-text indexes (`[search] tokenizer`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tokenizer {
+    /// unicode61 with English Porter stemming
+    #[default]
+    Porter,
+    /// Unicode word splitting without stemming, for non-English text
+    Unicode61,
+    /// Substring matching on 3-character sequences, for CJK and compound words
+    Trigram,
+}
+
+impl Tokenizer {
+    /// Value of the FTS5 `tokenize` option.
+    pub fn fts_option(self) -> &'static str {
+        match self {
+            Tokenizer::Porter => "porter unicode61",
+            Tokenizer::Unicode61 => "unicode61",
+            Tokenizer::Trigram => "trigram",
+        }
+    }
+
+    /// Tokenizer named in an FTS5 table's `CREATE VIRTUAL TABLE` statement.
+    pub fn from_create_sql(sql: &str) -> Self {
+        let sql = sql.to_lowercase();
+        let option = sql.split("tokenize").nth(1).unwrap_or("");
+        if option.contains("trigram") {
+            Tokenizer::Trigram
+        } else if option.contains("porter") {
+            Tokenizer::Porter
+        } else {
+            Tokenizer::Unicode61
+        }
+    }
+}
+
+impl std::fmt::Display for Tokenizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Tokenizer::Porter => "porter",
+            Tokenizer::Unicode61 => "unicode61",
+            Tokenizer::Trigram => "trigram",
+        })
+    }
+}
+
+/// Turn free text into an FTS5 `MATCH` expression for a table using `tokenizer`: each word
+/// becomes a quoted term and the terms are OR'd. Returns an empty string when nothing
+/// searchable is left.
+///
+/// For porter and unicode61, words are split the way `unicode61` splits them (letters and
+/// numbers, including CJK, are kept; everything else separates), a word with several parts
+/// like `foo-bar` or `snake_case` becomes one phrase, and terms match as prefixes. For
+/// trigram, which matches substrings, words are kept whole apart from surrounding
+/// punctuation, and words under 3 characters are dropped because trigram cannot match them.
+/// Every term is quoted, so FTS5 operators and syntax in the input (`AND`, `NEAR(`, `col:`,
+/// `^`, `"`) are searched as text instead of causing syntax errors.
+pub fn fts_query(input: &str, tokenizer: Tokenizer) -> String {
+    terms(input, tokenizer).join(" OR ")
+}
+
+/// Like `fts_query`, but every term must match: for type-ahead, where each word narrows
+/// the results and the last one is still being typed.
+pub fn fts_prefix_query(input: &str, tokenizer: Tokenizer) -> String {
+    terms(input, tokenizer).join(" AND ")
+}
+
+fn terms(input: &str, tokenizer: Tokenizer) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for word in input.split_whitespace() {
+        let term = match tokenizer {
+            Tokenizer::Trigram => {
+                let word = word.trim_matches(|c: char| !is_token_char(c));
+                if word.chars().count() < 3 {
+                    continue;
+                }
+                quote(word)
+            }
+            Tokenizer::Porter | Tokenizer::Unicode61 => {
+                let parts: Vec<&str> = word.split(|c: char| !is_token_char(c)).filter(|p| !p.is_empty()).collect();
+                if parts.is_empty() {
+                    continue;
+                }
+                format!("{}*", quote(&parts.join(" ")))
+            }
+        };
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+        if terms.len() == MAX_TERMS {
+            break;
+        }
+    }
+    terms
+}
+
+/// Characters the `unicode61` tokenizer keeps inside tokens (Unicode letters and numbers).
+fn is_token_char(c: char) -> bool {
+    c.is_alphanumeric()
+}
+
+/// Quote an FTS5 string, doubling embedded quotes.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rusqlite::Connection;
+
+    const P: Tokenizer = Tokenizer::Porter;
+
+    fn table(tokenize: &str) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(&format!("CREATE VIRTUAL TABLE t USING fts5(content, tokenize='{}');", tokenize))
+            .unwrap();
+        for row in [
+            "Use tokio for async IO",
+            "snake_case names for Rust functions",
+            "Prefer foo-bar over bar foo",
+            "東京タワー 観光 メモ",
+            "Schlüsselwörter für Straßennamen",
+            "C++ and C# interop notes",
+        ] {
+            conn.execute("INSERT INTO t (content) VALUES (?1)", [row]).unwrap();
+        }
+        conn
+    }
+
+    fn search(conn: &Connection, input: &str) -> rusqlite::Result<Vec<String>> {
+        let sql: String = conn.query_row("SELECT sql FROM sqlite_master WHERE name = 't'", [], |r| r.get(0))?;
+        let q = fts_query(input, Tokenizer::from_create_sql(&sql));
+        if q.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut stmt = conn.prepare("SELECT content FROM t WHERE t MATCH ?1 ORDER BY rank")?;
+        stmt.query_map([q], |r| r.get(0))?.collect()
+    }
+
+    #[test]
+    fn builds_quoted_prefix_terms() {
+        assert_eq!(fts_query("tokio async", P), r#""tokio"* OR "async"*"#);
+        assert_eq!(fts_query("foo-bar snake_case", P), r#""foo bar"* OR "snake case"*"#);
+        assert_eq!(fts_query("  --- ?? ", P), "");
+        assert_eq!(fts_query("rust rust", P), r#""rust"*"#);
+        assert_eq!(fts_query(&"word ".repeat(100), P).matches(" OR ").count(), 0);
+        let many: String = (0..100).map(|i| format!("w{} ", i)).collect();
+        assert_eq!(fts_query(&many, P).matches(" OR ").count(), MAX_TERMS - 1);
+        assert_eq!(fts_prefix_query("rust err", P), r#""rust"* AND "err"*"#);
+    }
+
+    #[test]
+    fn corpus_never_errors_and_finds_expected_rows() {
+        let corpus: &[(&str, Option<&str>)] = &[
+            ("tokio", Some("Use tokio for async IO")),
+            ("TOKIO", Some("Use tokio for async IO")),
+            ("snake_case", Some("snake_case names for Rust functions")),
+            ("foo-bar", Some("Prefer foo-bar over bar foo")),
+            ("東京", Some("東京タワー 観光 メモ")),
+            ("観光", Some("東京タワー 観光 メモ")),
+            ("c++", Some("C++ and C# interop notes")),
+            ("AND", Some("C++ and C# interop notes")),
+            ("OR NOT", None),
+            ("NEAR(tokio async)", Some("Use tokio for async IO")),
+            ("content:tokio", None),
+            ("^tokio", Some("Use tokio for async IO")),
+            ("\"unterminated", None),
+            ("a\"b\"\"c", None),
+            ("*", None),
+            ("-tokio +async", Some("Use tokio for async IO")),
+            ("(((", None),
+            ("🦀 rust", Some("snake_case names for Rust functions")),
+            ("\u{200b}\u{0}\t\n", None),
+            ("مرحبا שלום", None),
+        ];
+        for tokenize in ["porter unicode61", "unicode61", "trigram"] {
+            let conn = table(tokenize);
+            for (input, expected) in corpus {
+                let rows = search(&conn, input).unwrap_or_else(|e| panic!("{:?} ({}): {}", input, tokenize, e));
+                if let Some(row) = expected
+                    && tokenize != "trigram"
+                {
+                    assert!(rows.iter().any(|r| r == row), "{:?} ({}) missed {:?}", input, tokenize, row);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn matches_prefix_of_compound_word() {
+        let conn = table("unicode61");
+        assert!(!search(&conn, "schlüssel").unwrap().is_empty());
+    }
+
+    #[test]
+    fn trigram_matches_substrings() {
+        assert_eq!(fts_query("(foo-bar) 東京 東京タ", Tokenizer::Trigram), r#""foo-bar" OR "東京タ""#);
+        let conn = table("trigram");
+        assert_eq!(search(&conn, "wörter").unwrap(), vec!["Schlüsselwörter für Straßennamen"]);
+        assert_eq!(search(&conn, "東京タワー").unwrap(), vec!["東京タワー 観光 メモ"]);
+        assert_eq!(search(&conn, "foo-bar").unwrap(), vec!["Prefer foo-bar over bar foo"]);
+        assert!(search(&conn, "東京").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_tokenizer_from_schema() {
+        for t in [Tokenizer::Porter, Tokenizer::Unicode61, Tokenizer::Trigram] {
+            let sql = format!("CREATE VIRTUAL TABLE x USING fts5(content, content=m, tokenize='{}')", t.fts_option());
+            assert_eq!(Tokenizer::from_create_sql(&sql), t);
+        }
+        assert_eq!(Tokenizer::from_create_sql("CREATE VIRTUAL TABLE x USING fts5(content)"), Tokenizer::Unicode61);
+    }
+
+    proptest! {
+        #[test]
+        fn never_produces_invalid_fts(input in "\\PC{0,60}") {
+            let conn = table("porter unicode61");
+            prop_assert!(search(&conn, &input).is_ok(), "query {:?} from {:?}", fts_query(&input, Tokenizer::Porter), input);
+        }
+
+        #[test]
+        fn never_produces_invalid_fts_from_syntax(input in "[a-zA-Z0-9 \"*:^(){}+\\-.,_]{0,40}") {
+            let conn = table("porter unicode61");
+            prop_assert!(search(&conn, &input).is_ok(), "query {:?} from {:?}", fts_query(&input, Tokenizer::Porter), input);
+        }
+
+        #[test]
+        fn never_produces_invalid_trigram_fts(input in "\\PC{0,60}") {
+            let conn = table("trigram");
+            prop_assert!(search(&conn, &input).is_ok(), "query {:?} from {:?}", fts_query(&input, Tokenizer::Trigram), input);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::models::{ConsolidatedMemory, Entity, Memory, Skill};
+use crate::patch::Patch;
+use crate::projects;
+
+/// Top-level domains that make a dotted word a hostname; file extensions like `.rs` don't.
+const TLDS: &str = "com|net|org|io|dev|app|co|ai|cloud|internal|local|corp|lan|intra|edu|gov";
+
+/// Identifiers shorter than this are left alone: they'd replace ordinary words.
+const MIN_TERM: usize = 3;
+
+/// Emails, URL hosts, hostnames, IPv4 addresses, and absolute paths (`pre` keeps the character
+/// before a path, so `src/db.rs` inside a sentence isn't one).
+fn pattern() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(&format!(
+            r#"(?xi)
+            (?P<email>[\w.+-]+@(?:[a-z0-9-]+\.)+[a-z]{{2,}})
+            | (?P<scheme>\b[a-z][a-z0-9+.-]*://)(?P<urlhost>[^\s/:?\#)>\]'"`]+)
+            | (?P<host>\b(?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+(?:{})\b)
+            | (?P<ip>\b\d{{1,3}}(?:\.\d{{1,3}}){{3}}\b)
+            | (?P<pre>^|[\s"'`(\[=:,])(?P<path>(?:~|[a-z]:)?[/\\](?:[\w.-]+[/\\])+[\w.-]*)
+            "#,
+            TLDS
+        ))
+        .expect("valid anonymize regex")
+    })
+}
+
+/// Replaces identifying text with placeholders for `cortex export --anonymize`: project, org,
+/// and user names by fixed stand-ins, then emails, hosts, IPs, and absolute paths by numbered
+/// ones under reserved example names (`user-1@example.com`, `host-1.example.com`, `192.0.2.1`).
+/// The same original always gets the same placeholder, so exported memories still read
+/// consistently.
+pub struct Anonymizer {
+    terms: Option<Regex>,
+    replacements: HashMap<String, String>,
+    seen: HashMap<(&'static str, String), String>,
+    counts: HashMap<&'static str, usize>,
+}
+
+impl Anonymizer {
+    /// `terms` pairs an identifier with its stand-in; matching is whole-word and
+    /// case-insensitive, and earlier pairs win.
+    pub fn new(terms: Vec<(String, String)>) -> Self {
+        let mut replacements = HashMap::new();
+        for (term, stand_in) in terms {
+            if term.chars().count() >= MIN_TERM {
+                replacements.entry(term.to_lowercase()).or_insert(stand_in);
+            }
+        }
+        let mut words: Vec<&String> = replacements.keys().collect();
+        words.sort_by_key(|w| std::cmp::Reverse(w.len()));
+        let terms = (!words.is_empty()).then(|| {
+            let alternatives: Vec<String> = words.iter().map(|w| regex::escape(w)).collect();
+            Regex::new(&format!(r"(?i)\b(?:{})\b", alternatives.join("|"))).expect("escaped terms")
+        });
+        Self { terms, replacements, seen: HashMap::new(), counts: HashMap::new() }
+    }
+
+    /// Identifiers of the project at `root`: its directory and git remote repository
+    /// (`my-project`), the remote's owner (`my-org`), other registered projects (`project-1`...),
+    /// and the user's login name (`user`).
+    pub fn for_project(root: &Path) -> Self {
+        let mut terms = Vec::new();
+        let name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().into_owned());
+        terms.extend(name(root).map(|n| (n, "my-project".to_string())));
+        if let Some((owner, repo)) = git_remote(root) {
+            terms.push((repo, "my-project".to_string()));
+            terms.push((owner, "my-org".to_string()));
+        }
+        let home = dirs::home_dir();
+        terms.extend(home.as_deref().and_then(name).map(|n| (n, "user".to_string())));
+        terms.extend(std::env::var("USER").ok().map(|n| (n, "user".to_string())));
+        let others = projects::known_stores().unwrap_or_default();
+        let others = others.iter().filter_map(|dir| dir.parent()).filter(|p| Some(*p) != home.as_deref());
+        for (i, project) in others.filter_map(name).enumerate() {
+            terms.push((project, format!("project-{}", i + 1)));
+        }
+        Self::new(terms)
+    }
+
+    pub fn text(&mut self, text: &str) -> String {
+        let text = match &self.terms {
+            Some(re) => re.replace_all(text, |c: &Captures| self.replacements[&c[0].to_lowercase()].clone()).into_owned(),
+            None => text.to_string(),
+        };
+        pattern()
+            .replace_all(&text, |c: &Captures| {
+                if let Some(m) = c.name("email") {
+                    self.placeholder("email", m.as_str())
+                } else if let Some(m) = c.name("urlhost") {
+                    format!("{}{}", &c["scheme"], self.placeholder("host", m.as_str()))
+                } else if let Some(m) = c.name("host") {
+                    self.placeholder("host", m.as_str())
+                } else if let Some(m) = c.name("ip") {
+                    self.placeholder("ip", m.as_str())
+                } else {
+                    format!("{}{}", &c["pre"], self.placeholder("path", &c["path"]))
+                }
+            })
+            .into_owned()
+    }
+
+    fn placeholder(&mut self, kind: &'static str, original: &str) -> String {
+        let key = (kind, original.to_lowercase());
+        if let Some(p) = self.seen.get(&key) {
+            return p.clone();
+        }
+        let n = self.counts.entry(kind).or_default();
+        *n += 1;
+        let p = match kind {
+            "email" => format!("user-{}@example.com", n),
+            "host" => format!("host-{}.example.com", n),
+            "ip" => format!("192.0.2.{}", (*n - 1) % 254 + 1),
+            _ => format!("/path/to/file-{}", n),
+        };
+        self.seen.insert(key, p.clone());
+        p
+    }
+
+    fn all(&mut self, texts: &mut [String]) {
+        for t in texts {
+            *t = self.text(t);
+        }
+    }
+
+    pub fn memory(&mut self, m: &mut Memory) {
+        m.content = self.text(&m.content);
+        self.all(&mut m.tags);
+        self.all(&mut m.refs);
+    }
+
+    pub fn consolidated(&mut self, c: &mut ConsolidatedMemory) {
+        c.content = self.text(&c.content);
+        self.all(&mut c.refs);
+    }
+
+    pub fn entity(&mut self, e: &mut Entity) {
+        e.name = self.text(&e.name);
+        e.description = e.description.as_deref().map(|d| self.text(d));
+    }
+
+    pub fn skill(&mut self, s: &mut Skill) {
+        s.name = self.text(&s.name);
+        s.content = self.text(&s.content);
+        s.snippet = s.snippet.as_deref().map(|t| self.text(t));
+    }
+
+    pub fn patch(&mut self, patch: &mut Patch) {
+        for e in &mut patch.upserts {
+            e.content = self.text(&e.content);
+            self.all(&mut e.refs);
+            e.replaces = e.replaces.as_deref().map(|t| self.text(t));
+        }
+        self.all(&mut patch.deletes);
+    }
+}
+
+/// Owner and repository of `origin`, from `git@host:owner/repo.git` or
+/// `https://host/owner/repo`.
+fn git_remote(root: &Path) -> Option<(String, String)> {
+    let out = Command::new("git").current_dir(root).args(["remote", "get-url", "origin"]).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let url = String::from_utf8(out.stdout).ok()?;
+    let mut parts = url.trim().trim_end_matches('/').trim_end_matches(".git").rsplit(['/', ':']);
+    let repo = parts.next()?.to_string();
+    let owner = parts.next()?.to_string();
+    Some((owner, repo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_identifiers_consistently() {
+        let mut a = Anonymizer::new(vec![
+            ("acme-api".to_string(), "my-project".to_string()),
+            ("jdoe".to_string(), "user".to_string()),
+            ("ab".to_string(), "too-short".to_string()),
+        ]);
+        let text = a.text(
+            "jdoe@acme.io saw ACME-API fail on build01.acme.internal (10.0.4.12); logs in /home/jdoe/acme-api/logs/out.log, see https://ci.acme.io:8080/runs/7",
+        );
+        assert_eq!(
+            text,
+            "user-1@example.com saw my-project fail on host-1.example.com (192.0.2.1); logs in /path/to/file-1, see https://host-2.example.com:8080/runs/7"
+        );
+        assert_eq!(a.text("Email jdoe@acme.io about src/db.rs and ab"), "Email user-1@example.com about src/db.rs and ab");
+    }
+}
@@ -0,0 +1,81 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Issue/PR references: `#123`, `owner/repo#123`, `JIRA-456`, and issue/PR/ticket URLs.
+fn ref_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?x)
+            (?P<url>https?://[^\s)>\]]+/(?:issues|pull|pulls|merge_requests|browse|tickets?)/[^\s)>\]]+)
+            | (?:^|[^\w/\#])(?P<gh>(?:[\w.-]+/[\w.-]+)?\#\d+)\b
+            | \b(?P<jira>[A-Z][A-Z0-9]{1,9}-\d+)\b
+            ",
+        )
+        .expect("valid ref regex")
+    })
+}
+
+/// Extract tracker references from memory content, in order of first appearance, deduplicated.
+pub fn extract_refs(content: &str) -> Vec<String> {
+    let mut refs: Vec<String> = Vec::new();
+    for caps in ref_regex().captures_iter(content) {
+        let found = caps
+            .name("url")
+            .or_else(|| caps.name("gh"))
+            .or_else(|| caps.name("jira"))
+            .map(|m| m.as_str().trim_end_matches(['.', ',', ';', ':']).to_string());
+        if let Some(r) = found
+            && !refs.iter().any(|e| e.eq_ignore_ascii_case(&r))
+        {
+            refs.push(r);
+        }
+    }
+    refs
+}
+
+/// Normalize a user-supplied ref for lookup (`123` → `#123`).
+pub fn normalize_ref(reference: &str) -> String {
+    let reference = reference.trim();
+    if reference.chars().all(|c| c.is_ascii_digit()) {
+        format!("#{}", reference)
+    } else {
+        reference.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn extracts_tracker_refs() {
+        let content = "Fixed in #42 (see acme/api#7, PROJ-123). Upstream: https://github.com/acme/api/issues/9. \
+                       Again #42, proj-123 and PROJ-123; not a#5, path/#6 or #anchor.";
+        assert_eq!(extract_refs(content), ["#42", "acme/api#7", "PROJ-123", "https://github.com/acme/api/issues/9"]);
+        assert_eq!(extract_refs("https://tracker.example.com/browse/OPS-1, done"), ["https://tracker.example.com/browse/OPS-1"]);
+        assert!(extract_refs("Version 1.2-3 ships on Monday").is_empty());
+    }
+
+    #[test]
+    fn normalizes_lookups() {
+        assert_eq!(normalize_ref(" 123 "), "#123");
+        assert_eq!(normalize_ref("#123"), "#123");
+        assert_eq!(normalize_ref("acme/api#7"), "acme/api#7");
+    }
+
+    #[test]
+    fn resolves_refs_to_memories() {
+        let conn = db::open_raw_db(std::path::Path::new(":memory:")).unwrap();
+        let content = "Retry budget fixed in #42 for PROJ-9";
+        let id = db::save_memory(&conn, content, "bugfix", "s").unwrap();
+        db::add_memory_refs(&conn, id, &extract_refs(content)).unwrap();
+        db::save_memory(&conn, "Unrelated note about #420", "observation", "s").unwrap();
+
+        let found = db::recall_by_ref(&conn, &normalize_ref("42"), 10).unwrap();
+        assert_eq!(found.iter().map(|m| m.id).collect::<Vec<_>>(), [id]);
+        assert_eq!(db::recall_by_ref(&conn, &normalize_ref("proj-9"), 10).unwrap().len(), 1);
+        assert!(db::recall_by_ref(&conn, &normalize_ref("4"), 10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,575 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use rusqlite::{Connection, OpenFlags};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tokio::sync::OnceCell;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::db;
+use crate::init;
+use crate::lock;
+use crate::mcp::{self, Session};
+use crate::plugins::{self, Plugin};
+use crate::store::Store;
+
+/// Address `cortex serve` listens on by default.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:7433";
+
+/// Bearer token `cortex serve` requires, and the one `--remote` clients send.
+pub const SERVER_TOKEN_ENV: &str = "CORTEX_SERVER_TOKEN";
+pub const CLIENT_TOKEN_ENV: &str = "CORTEX_REMOTE_TOKEN";
+
+/// Header carrying the client's session ID, so one CLI run's saves share a session.
+const SESSION_HEADER: &str = "x-cortex-session";
+
+/// Header naming the project for requests to `/` rather than `/<name>`.
+const PROJECT_HEADER: &str = "x-cortex-project";
+
+/// Per-project settings of a `cortex serve` root.
+const SERVE_CONFIG: &str = "serve.toml";
+
+/// Largest request body the server reads.
+const MAX_BODY: usize = 4 << 20;
+
+/// Longest request or header line, and most headers, the server reads.
+const MAX_LINE: usize = 8 << 10;
+const MAX_HEADERS: usize = 100;
+
+/// Time a client gets to send its request; the tool call itself is not limited.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time a client waits for a response; quick sleeps make several LLM calls.
+const CALL_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// A remote cortex server's project store, e.g. `https://cortex.internal/acme-repo`.
+pub struct Client {
+    url: String,
+    token: Option<String>,
+    session_id: String,
+}
+
+impl Client {
+    /// Reads the token from `CORTEX_REMOTE_TOKEN`.
+    pub fn new(url: &str, session_id: String) -> Self {
+        let token = std::env::var(CLIENT_TOKEN_ENV).ok().filter(|t| !t.is_empty());
+        Self { url: url.trim_end_matches('/').to_string(), token, session_id }
+    }
+
+    /// Call an MCP tool on the server and return its text result.
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<String> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": { "name": name, "arguments": arguments },
+        });
+        let mut req = reqwest::Client::new()
+            .post(&self.url)
+            .timeout(CALL_TIMEOUT)
+            .header(SESSION_HEADER, &self.session_id)
+            .json(&body);
+        if let Some(token) = &self.token {
+            req = req.bearer_auth(token);
+        }
+        let resp = req.send().await.with_context(|| format!("Could not reach {}", self.url))?;
+        match resp.status().as_u16() {
+            200 => {}
+            401 => anyhow::bail!("{} rejected the request: set {} to the server's token", self.url, CLIENT_TOKEN_ENV),
+            status => anyhow::bail!("{} returned HTTP {}: {}", self.url, status, resp.text().await.unwrap_or_default()),
+        }
+        let resp: Value = resp.json().await.context("Malformed response from the cortex server")?;
+        if let Some(message) = resp["error"]["message"].as_str() {
+            anyhow::bail!("{}", message);
+        }
+        resp["result"]["content"][0]["text"]
+            .as_str()
+            .map(str::to_string)
+            .context("Malformed response from the cortex server")
+    }
+}
+
+/// `<root>/serve.toml`: the projects a server knows by name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ServeConfig {
+    #[serde(default)]
+    pub projects: BTreeMap<String, Tenant>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Tenant {
+    /// Project directory served under this name instead of `<root>/<name>`, e.g. a repo checkout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// SHA-256 of the project's own token (`cortex serve --issue-token`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_sha256: Option<String>,
+}
+
+impl ServeConfig {
+    /// Read on every request, so issued tokens and new paths apply without a restart.
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = root.join(SERVE_CONFIG);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        toml::from_str(&std::fs::read_to_string(&path)?).with_context(|| format!("Invalid {}", path.display()))
+    }
+
+    fn save(&self, root: &Path) -> Result<()> {
+        std::fs::create_dir_all(root)?;
+        std::fs::write(root.join(SERVE_CONFIG), toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+fn sha256_hex(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Generate a token for project `name`, store its hash in `serve.toml`, and return it.
+/// Replaces any earlier token for the project.
+pub fn issue_token(root: &Path, name: &str) -> Result<String> {
+    if !valid_name(name) {
+        anyhow::bail!("Project names may only use letters, digits, '-', '_', and '.'");
+    }
+    let token = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+    let mut config = ServeConfig::load(root)?;
+    config.projects.entry(name.to_string()).or_default().token_sha256 = Some(sha256_hex(&token));
+    config.save(root)?;
+    Ok(token)
+}
+
+/// Each tenant's plugins, loaded on its first request and kept until the server restarts:
+/// loading asks every plugin to `describe` itself, too slow to repeat per request.
+#[derive(Default)]
+struct PluginCache(Mutex<HashMap<PathBuf, Arc<TenantPlugins>>>);
+
+type TenantPlugins = OnceCell<Arc<Vec<Plugin>>>;
+
+impl PluginCache {
+    async fn get(&self, cortex_dir: &Path) -> Arc<Vec<Plugin>> {
+        let cell = self.0.lock().unwrap_or_else(|e| e.into_inner()).entry(cortex_dir.to_path_buf()).or_default().clone();
+        cell.get_or_init(|| async { Arc::new(plugins::load_plugins(cortex_dir).await) }).await.clone()
+    }
+}
+
+/// Whether `presented` may use a project: its own token if it has one, or the server token.
+/// A project with neither is open (only possible on a loopback address).
+fn authorized(tenant: Option<&Tenant>, server_token: Option<&str>, presented: Option<&str>) -> bool {
+    let project = tenant.and_then(|t| t.token_sha256.as_deref());
+    if project.is_none() && server_token.is_none() {
+        return true;
+    }
+    presented.is_some_and(|p| {
+        server_token.is_some_and(|t| same(p, t)) || project.is_some_and(|hash| same(&sha256_hex(p), hash))
+    })
+}
+
+/// Whether a request's `Host` names the loopback interface. An open project only answers these,
+/// so a web page can't reach it through a rebound DNS name.
+fn loopback_host(host: Option<&str>) -> bool {
+    let Some(host) = host else { return false };
+    let name = match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost") || name.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Compare secrets in constant time, so response timing doesn't reveal how much of a guess
+/// was right.
+fn same(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+/// Serve project stores over HTTP: `POST /<name>` (or `POST /` with an `X-Cortex-Project`
+/// header) takes the same JSON-RPC requests as `cortex mcp`, run against the project's
+/// `serve.toml` path or `<root>/<name>/.cortex/` (created on first use). Each project sees only
+/// its own store: the global store is off limits, and a project token opens only its project.
+/// Requires `CORTEX_SERVER_TOKEN` unless bound to a loopback address, and then answers only
+/// requests to a loopback `Host`. Each connection gets its own task; each project's plugins are
+/// loaded once, on its first request.
+pub async fn serve(root: &Path, addr: &str, read_only: bool) -> Result<()> {
+    let token = std::env::var(SERVER_TOKEN_ENV).ok().filter(|t| !t.is_empty());
+    let listener = TcpListener::bind(addr).await.with_context(|| format!("Could not listen on {}", addr))?;
+    let local = listener.local_addr()?;
+    if token.is_none() && !local.ip().is_loopback() {
+        anyhow::bail!("Set {} before serving on a non-loopback address", SERVER_TOKEN_ENV);
+    }
+    eprintln!("Serving cortex stores in {} on http://{}", root.display(), local);
+    serve_on(listener, root, token.as_deref(), read_only).await
+}
+
+async fn serve_on(listener: TcpListener, root: &Path, token: Option<&str>, read_only: bool) -> Result<()> {
+    let root: Rc<Path> = root.into();
+    let token: Option<Rc<str>> = token.map(Rc::from);
+    let plugins = Rc::new(PluginCache::default());
+    // Stores aren't `Send`, so connection tasks share this thread; a slow client or tool call
+    // only holds up its own task, and SQLite work already runs on the blocking pool
+    let tasks = tokio::task::LocalSet::new();
+    tasks
+        .run_until(async move {
+            loop {
+                let (stream, _) = listener.accept().await?;
+                let (root, token, plugins) = (root.clone(), token.clone(), plugins.clone());
+                tokio::task::spawn_local(async move { reply(stream, &root, token.as_deref(), read_only, &plugins).await });
+            }
+        })
+        .await
+}
+
+/// Handle one connection and write its response.
+async fn reply(mut stream: TcpStream, root: &Path, token: Option<&str>, read_only: bool, plugins: &PluginCache) {
+    let (status, body) = match handle(&mut stream, root, token, read_only, plugins).await {
+        Ok(response) => response,
+        Err(e) => (400, e.to_string()),
+    };
+    let reason = match status {
+        200 => "OK",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        415 => "Unsupported Media Type",
+        503 => "Service Unavailable",
+        _ => "Bad Request",
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        eprintln!("Warning: could not send response: {}", e);
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+async fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    read_line(&mut reader, &mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default().to_string(), parts.next().unwrap_or_default().to_string());
+    let mut headers = HashMap::new();
+    for read in 0.. {
+        if read_line(&mut reader, &mut line).await? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if read == MAX_HEADERS {
+            anyhow::bail!("More than {} headers", MAX_HEADERS);
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    let len: usize = headers.get("content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+    if len > MAX_BODY {
+        anyhow::bail!("Request body over {} bytes", MAX_BODY);
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).await?;
+    Ok(Request { method, path, headers, body: String::from_utf8(body)? })
+}
+
+/// Read one line into `line`, refusing lines over `MAX_LINE` bytes.
+async fn read_line(reader: &mut (impl AsyncBufRead + Unpin), line: &mut String) -> Result<usize> {
+    line.clear();
+    let read = reader.take(MAX_LINE as u64 + 1).read_line(line).await?;
+    if read > MAX_LINE {
+        anyhow::bail!("Request line over {} bytes", MAX_LINE);
+    }
+    Ok(read)
+}
+
+/// Status and body for one request.
+async fn handle(
+    stream: &mut TcpStream,
+    root: &Path,
+    token: Option<&str>,
+    read_only: bool,
+    plugins: &PluginCache,
+) -> Result<(u16, String)> {
+    let req = tokio::time::timeout(READ_TIMEOUT, read_request(stream)).await.context("Timed out reading the request")??;
+    let presented = req.headers.get("authorization").and_then(|h| h.strip_prefix("Bearer "));
+    match (req.method.as_str(), req.path.as_str()) {
+        ("GET", "/healthz") => return Ok((200, json!({ "ok": true }).to_string())),
+        ("GET", "/readyz") => {
+            let projects = readiness(root);
+            let ready = projects.as_ref().is_ok_and(|p| p.iter().all(|h| h.ok));
+            // Project details only for the server token, so probes can't enumerate projects
+            let admin = token.is_none_or(|t| presented.is_some_and(|p| same(p, t)));
+            let body = match projects {
+                Ok(projects) if admin => json!({ "ready": ready, "projects": projects }),
+                Err(e) if admin => json!({ "ready": false, "error": format!("{:#}", e) }),
+                _ => json!({ "ready": ready }),
+            };
+            return Ok((if ready { 200 } else { 503 }, body.to_string()));
+        }
+        ("POST", _) => {}
+        _ => return Ok((405, "Send JSON-RPC requests with POST".into())),
+    }
+    // Browsers can't send this cross-site without a preflight the server never answers
+    let json = req.headers.get("content-type").is_some_and(|t| t.to_ascii_lowercase().starts_with("application/json"));
+    if !json {
+        return Ok((415, "Send JSON-RPC requests as application/json".into()));
+    }
+    let name = match req.path.trim_matches('/') {
+        "" => req.headers.get(PROJECT_HEADER).map(String::as_str).unwrap_or_default(),
+        name => name,
+    };
+    if !valid_name(name) {
+        return Ok((404, format!("No project named '{}'", name)));
+    }
+    let config = ServeConfig::load(root)?;
+    let tenant = config.projects.get(name);
+    if !authorized(tenant, token, presented) {
+        return Ok((401, "Missing or wrong bearer token".into()));
+    }
+    let open = token.is_none() && tenant.is_none_or(|t| t.token_sha256.is_none());
+    if open && !loopback_host(req.headers.get("host").map(String::as_str)) {
+        return Ok((403, "Without a token, only requests to a loopback host are served".into()));
+    }
+    let base = match tenant.and_then(|t| t.path.clone()) {
+        Some(path) if !path.join(".cortex").exists() => return Ok((404, format!("No .cortex/ in {}", path.display()))),
+        Some(path) => path,
+        None => root.join(name),
+    };
+    if !base.join(".cortex").exists() {
+        init::init_cortex(&base)?;
+    }
+    let store = Store::open(base.join(".cortex"));
+    let plugins = plugins.get(store.dir()).await;
+    let session_id = req.headers.get(SESSION_HEADER).cloned().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let session = Session {
+        id: &session_id,
+        read_only: read_only || store.config()?.mcp.read_only,
+        isolated: true,
+        plugins: &plugins,
+    };
+    let body = mcp::respond(&req.body, &store, None, &session).await?;
+    Ok((200, body))
+}
+
+/// Readiness of one served project (`GET /readyz`).
+#[derive(Debug, Serialize)]
+pub struct ProjectHealth {
+    pub name: String,
+    /// Both databases open and answer a query
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Migrations the next request will apply, as `table.column`
+    pub pending_migrations: Vec<String>,
+    /// Holder of the consolidation lock while a sleep or dream runs
+    pub lock: Option<String>,
+    pub last_sleep: Option<String>,
+}
+
+/// Check every project the server at `root` serves, read-only: those in `serve.toml` and
+/// each `<root>/<name>/.cortex/`.
+pub fn readiness(root: &Path) -> Result<Vec<ProjectHealth>> {
+    let mut dirs: BTreeMap<String, PathBuf> = ServeConfig::load(root)?
+        .projects
+        .into_iter()
+        .map(|(name, t)| {
+            let dir = t.path.unwrap_or_else(|| root.join(&name)).join(".cortex");
+            (name, dir)
+        })
+        .collect();
+    for entry in std::fs::read_dir(root).with_context(|| format!("Cannot read {}", root.display()))? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string();
+        if valid_name(&name) && path.join(".cortex").is_dir() {
+            dirs.entry(name).or_insert_with(|| path.join(".cortex"));
+        }
+    }
+    Ok(dirs
+        .into_iter()
+        .map(|(name, dir)| {
+            let lock = lock::holder(&dir);
+            match inspect(&dir) {
+                Ok((pending_migrations, last_sleep)) => {
+                    ProjectHealth { name, ok: true, error: None, pending_migrations, lock, last_sleep }
+                }
+                Err(e) => ProjectHealth {
+                    name,
+                    ok: false,
+                    error: Some(format!("{:#}", e)),
+                    pending_migrations: vec![],
+                    lock,
+                    last_sleep: None,
+                },
+            }
+        })
+        .collect())
+}
+
+/// Pending migrations and last sleep of the store in `dir`, opening its databases read-only.
+/// A missing raw.db is fine: it is created on first use, as in a fresh clone.
+fn inspect(dir: &Path) -> Result<(Vec<String>, Option<String>)> {
+    let cons_path = dir.join("consolidated.db");
+    if !cons_path.exists() {
+        anyhow::bail!("No consolidated.db in {}", dir.display());
+    }
+    let mut pending = vec![];
+    let mut last_sleep = None;
+    for (file, path) in [("raw.db", dir.join("raw.db")), ("consolidated.db", cons_path)] {
+        if !path.exists() {
+            continue;
+        }
+        let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Cannot open {}", file))?;
+        conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |r| r.get::<_, i64>(0))
+            .with_context(|| format!("Cannot read {}", file))?;
+        pending.extend(db::pending_migrations(&conn));
+        if file == "consolidated.db" {
+            last_sleep = db::get_meta(&conn, "last_sleep").unwrap_or_default();
+        }
+    }
+    Ok((pending, last_sleep))
+}
+
+/// Store names are a single path segment: letters, digits, `-`, `_`, and `.`, not leading.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn proxies_tool_calls_to_served_stores() {
+        let root = std::env::temp_dir().join(format!("cortex-remote-{}", uuid::Uuid::new_v4()));
+        init::init_cortex(&root.join("acme")).unwrap();
+        let raw = db::open_raw_db(&root.join("acme/.cortex/raw.db")).unwrap();
+        db::save_memory(&raw, "Deploys go through the staging cluster first", "decision", "s1").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let client = Client { url: format!("{}/acme", base), token: Some("t0ken".into()), session_id: "s2".into() };
+        let anonymous = Client { token: None, ..Client::new(&format!("{}/acme", base), "s3".into()) };
+        let escape = Client { url: format!("{}/..", base), token: Some("t0ken".into()), session_id: "s4".into() };
+        let addr = listener.local_addr().unwrap();
+        tokio::select! {
+            _ = serve_on(listener, &root, Some("t0ken"), false) => panic!("server stopped"),
+            _ = async {
+                // A client that connects and sends nothing doesn't hold up the others
+                let _idle = TcpStream::connect(addr).await.unwrap();
+                let call = client.call_tool("cortex_recall", json!({ "query": "staging" }));
+                let found = tokio::time::timeout(Duration::from_secs(10), call).await.expect("request was queued").unwrap();
+                assert!(found.contains("staging cluster"));
+                let err = anonymous.call_tool("cortex_stats", json!({})).await.unwrap_err();
+                assert!(err.to_string().contains(CLIENT_TOKEN_ENV));
+                assert!(escape.call_tool("cortex_stats", json!({})).await.is_err());
+                let err = client.call_tool("cortex_nope", json!({})).await.unwrap_err();
+                assert!(err.to_string().contains("Unknown tool"));
+                let err = client.call_tool("cortex_save", json!({ "content": "x", "global": true })).await.unwrap_err();
+                assert!(err.to_string().contains("Global memory"));
+
+                // A project token opens its own project and no other
+                let token = issue_token(&root, "beta").unwrap();
+                let beta = Client { url: format!("{}/beta", base), token: Some(token.clone()), session_id: "s5".into() };
+                let stats = beta.call_tool("cortex_stats", json!({})).await.unwrap();
+                assert!(stats.contains("\"raw_count\": 0"));
+                let acme = Client { url: format!("{}/acme", base), token: Some(token), session_id: "s6".into() };
+                assert!(acme.call_tool("cortex_stats", json!({})).await.is_err());
+            } => {}
+        }
+        let ready = readiness(&root).unwrap();
+        assert_eq!(ready.iter().map(|h| h.name.as_str()).collect::<Vec<_>>(), ["acme", "beta"]);
+        assert!(ready.iter().all(|h| h.ok && h.pending_migrations.is_empty() && h.lock.is_none()));
+        let old = Connection::open_in_memory().unwrap();
+        old.execute_batch("CREATE TABLE skills (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        assert_eq!(db::pending_migrations(&old), ["skills.category", "skills.use_count", "skills.last_used_at"]);
+        assert!(authorized(None, None, None));
+        assert!(!authorized(None, Some("t0ken"), Some("wrong")));
+        assert!(!authorized(None, Some("t0ken"), Some("t0ke")));
+        let tenant = Tenant { path: None, token_sha256: Some(sha256_hex("pr0ject")) };
+        assert!(authorized(Some(&tenant), Some("t0ken"), Some("pr0ject")));
+        assert!(authorized(Some(&tenant), Some("t0ken"), Some("t0ken")));
+        assert!(!authorized(Some(&tenant), None, None));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn open_servers_refuse_cross_site_requests() {
+        async fn status(addr: std::net::SocketAddr, head: &str) -> String {
+            let body = r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#;
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let request = format!("POST /acme HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}", head, body.len(), body);
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response.lines().next().unwrap_or_default().to_string()
+        }
+
+        let root = std::env::temp_dir().join(format!("cortex-remote-{}", uuid::Uuid::new_v4()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let json = "Content-Type: application/json\r\n";
+        tokio::select! {
+            _ = serve_on(listener, &root, None, false) => panic!("server stopped"),
+            _ = async {
+                let local = format!("Host: {}\r\n{}", addr, json);
+                assert_eq!(status(addr, &local).await, "HTTP/1.1 200 OK");
+                assert_eq!(status(addr, &format!("Host: {}\r\nContent-Type: text/plain\r\n", addr)).await, "HTTP/1.1 415 Unsupported Media Type");
+                assert_eq!(status(addr, &format!("Host: rebound.example:7433\r\n{}", json)).await, "HTTP/1.1 403 Forbidden");
+                assert_eq!(status(addr, json).await, "HTTP/1.1 403 Forbidden");
+                let long = format!("{}X-Pad: {}\r\n", local, "a".repeat(MAX_LINE));
+                assert_eq!(status(addr, &long).await, "HTTP/1.1 400 Bad Request");
+                let many = format!("{}{}", local, "X-Pad: a\r\n".repeat(MAX_HEADERS));
+                assert_eq!(status(addr, &many).await, "HTTP/1.1 400 Bad Request");
+            } => {}
+        }
+        assert!(loopback_host(Some("localhost")) && loopback_host(Some("[::1]:7433")));
+        assert!(!loopback_host(Some("127.0.0.1.nip.io")) && !loopback_host(None));
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[tokio::test]
+    async fn served_sleeps_stay_in_their_store() {
+        let root = std::env::temp_dir().join(format!("cortex-remote-{}", uuid::Uuid::new_v4()));
+        init::init_cortex(&root.join("acme")).unwrap();
+        let raw = db::open_raw_db(&root.join("acme/.cortex/raw.db")).unwrap();
+        let a = db::save_memory(&raw, "I prefer tabs over spaces in every project", "preference", "s1").unwrap();
+        let b = db::save_memory(&raw, "I prefer tabs over spaces in every project I own", "preference", "s1").unwrap();
+        db::set_memory_no_llm(&raw, &[a, b]).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = Client {
+            url: format!("http://{}/acme", listener.local_addr().unwrap()),
+            token: Some("t0ken".into()),
+            session_id: "s2".into(),
+        };
+        tokio::select! {
+            _ = serve_on(listener, &root, Some("t0ken"), false) => panic!("server stopped"),
+            _ = async {
+                let slept = client.call_tool("cortex_sleep", json!({})).await.unwrap();
+                assert!(slept.contains("1 consolidations"), "{}", slept);
+                assert!(!slept.contains("global"), "{}", slept);
+            } => {}
+        }
+        assert_eq!(db::get_unconsolidated_count(&raw).unwrap(), 0);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, DatabaseName};
+use std::path::Path;
+use std::process::Command;
+
+use crate::db;
+
+/// Which parts of a store `reset` clears.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Scope {
+    pub raw: bool,
+    pub consolidated: bool,
+    pub skills: bool,
+}
+
+impl Scope {
+    pub fn all() -> Self {
+        Self { raw: true, consolidated: true, skills: true }
+    }
+
+    pub fn is_empty(self) -> bool {
+        !(self.raw || self.consolidated || self.skills)
+    }
+
+    /// The files a reset touches, e.g. `raw.db, skills/`.
+    pub fn describe(self) -> String {
+        [(self.raw, "raw.db"), (self.consolidated, "consolidated.db"), (self.skills, "skills/")]
+            .iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Replace the database at `live` with the one at `from` in place, as `snapshot restore` does,
+/// so connections other processes hold see the new contents.
+fn replace(live: &Path, from: &Path) -> Result<()> {
+    let mut conn = Connection::open(live)?;
+    conn.restore(DatabaseName::Main, from, None::<fn(rusqlite::backup::Progress)>)
+        .with_context(|| format!("Failed to reset {}", live.display()))
+}
+
+fn git(cortex_dir: &Path, args: &[&str]) -> Result<std::process::Output> {
+    let output = Command::new("git").current_dir(cortex_dir).args(args).output().context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output)
+}
+
+/// Clear the chosen parts of the store in `cortex_dir`. With `from_git`, consolidated.db and
+/// skills/ are re-seeded from the committed mirror at HEAD instead of left empty.
+pub fn reset(cortex_dir: &Path, scope: Scope, from_git: bool) -> Result<()> {
+    let scratch = std::env::temp_dir().join(format!("cortex-reset-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&scratch)?;
+    let result = reset_with(cortex_dir, scope, from_git, &scratch);
+    let _ = std::fs::remove_dir_all(&scratch);
+    result
+}
+
+fn reset_with(cortex_dir: &Path, scope: Scope, from_git: bool, scratch: &Path) -> Result<()> {
+    if scope.raw {
+        let fresh = scratch.join("raw.db");
+        drop(db::open_raw_db(&fresh)?);
+        replace(&cortex_dir.join("raw.db"), &fresh)?;
+    }
+    if scope.consolidated {
+        let fresh = scratch.join("consolidated.db");
+        if from_git {
+            std::fs::write(&fresh, git(cortex_dir, &["show", "HEAD:./consolidated.db"])?.stdout)?;
+        }
+        // Opening also brings a committed copy from an older cortex up to date
+        drop(db::open_consolidated_db(&fresh)?);
+        replace(&cortex_dir.join("consolidated.db"), &fresh)?;
+    }
+    if scope.skills {
+        let skills = cortex_dir.join("skills");
+        if skills.exists() {
+            std::fs::remove_dir_all(&skills)?;
+        }
+        std::fs::create_dir_all(&skills)?;
+        if from_git {
+            git(cortex_dir, &["checkout", "HEAD", "--", "skills"])?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resets_chosen_stores() {
+        let dir = std::env::temp_dir().join(format!("cortex-reset-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("skills")).unwrap();
+        std::fs::write(dir.join("skills/deploy.md"), "# Deploy").unwrap();
+        let raw = db::open_raw_db(&dir.join("raw.db")).unwrap();
+        let cons = db::open_consolidated_db(&dir.join("consolidated.db")).unwrap();
+        db::save_memory(&raw, "Deploys go through staging", "observation", "s1").unwrap();
+        db::insert_consolidated(&cons, "Deploys go through staging", "decision", &[], 0.9).unwrap();
+
+        let scope = Scope { raw: true, ..Default::default() };
+        assert_eq!(scope.describe(), "raw.db");
+        reset(&dir, scope, false).unwrap();
+        let raw_count: i64 = raw.query_row("SELECT COUNT(*) FROM memories", [], |r| r.get(0)).unwrap();
+        assert_eq!(raw_count, 0);
+        assert_eq!(db::get_consolidated_count(&cons).unwrap(), 1);
+
+        reset(&dir, Scope::all(), false).unwrap();
+        assert_eq!(db::get_consolidated_count(&cons).unwrap(), 0);
+        assert!(dir.join("skills").is_dir() && !dir.join("skills/deploy.md").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::io::{BufRead, Write};
+
+use crate::chat::prompt;
+use crate::db;
+use crate::models::ConsolidatedMemory;
+
+/// Confidence added when a reviewer confirms a memory is still accurate.
+const CONFIRM_BOOST: f64 = 0.1;
+
+/// A consolidated memory due for review (`cortex review-stale`).
+#[derive(Debug, Serialize)]
+pub struct StaleMemory {
+    #[serde(flatten)]
+    pub memory: ConsolidatedMemory,
+    /// Days since it was last updated
+    pub age_days: f64,
+    /// How likely it is to be outdated; see `stale`
+    pub risk: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Still accurate: refresh it and raise its confidence
+    Confirm,
+    /// Still relevant: reset its age only
+    Refresh,
+    /// Outdated: remove it
+    Decay,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub confirmed: usize,
+    pub refreshed: usize,
+    pub decayed: usize,
+    pub skipped: usize,
+}
+
+/// Consolidated memories untouched for `days` days, riskiest first. Risk grows with age
+/// (in units of `days`) and shrinks with confidence and with how often the memory is used:
+/// `age / days × (1.5 − confidence) / (1 + ln(1 + access_count))`.
+pub fn stale(cons: &Connection, days: u32, limit: usize) -> Result<Vec<StaleMemory>> {
+    let window = f64::from(days.max(1));
+    let mut out: Vec<StaleMemory> = db::get_stale_consolidated(cons, days)?
+        .into_iter()
+        .map(|(memory, age_days)| {
+            let risk = age_days / window * (1.5 - memory.confidence) / (1.0 + (memory.access_count as f64).ln_1p());
+            StaleMemory { memory, age_days, risk }
+        })
+        .collect();
+    out.sort_by(|a, b| b.risk.total_cmp(&a.risk));
+    out.truncate(limit);
+    Ok(out)
+}
+
+pub fn apply(cons: &Connection, id: i64, action: Action) -> Result<()> {
+    match action {
+        Action::Confirm => db::refresh_consolidated(cons, id, CONFIRM_BOOST).map(|_| ()),
+        Action::Refresh => db::refresh_consolidated(cons, id, 0.0).map(|_| ()),
+        Action::Decay => db::remove_consolidated(cons, &[id]),
+    }
+}
+
+/// Walk the queue, asking for an action on each memory until the queue or `input` runs out.
+pub fn run(cons: &Connection, queue: &[StaleMemory], input: &mut impl BufRead, out: &mut impl Write) -> Result<Summary> {
+    let mut summary = Summary::default();
+    for (i, s) in queue.iter().enumerate() {
+        let m = &s.memory;
+        writeln!(
+            out,
+            "\n[{}/{}] consolidated #{} ({}, confidence {:.2}, untouched {:.0} days, risk {:.2})",
+            i + 1,
+            queue.len(),
+            m.id,
+            m.r#type,
+            m.confidence,
+            s.age_days,
+            s.risk
+        )?;
+        for line in m.content.lines() {
+            writeln!(out, "  {}", line)?;
+        }
+        let action = loop {
+            let Some(answer) = prompt(input, out, "[c]onfirm  [r]efresh  [d]ecay  [s]kip  [q]uit > ")? else {
+                return Ok(summary);
+            };
+            match answer.trim().to_lowercase().as_str() {
+                "c" | "confirm" => break Some(Action::Confirm),
+                "r" | "refresh" => break Some(Action::Refresh),
+                "d" | "decay" => break Some(Action::Decay),
+                "s" | "skip" | "" => break None,
+                "q" | "quit" => return Ok(summary),
+                _ => writeln!(out, "Answer c, r, d, s, or q.")?,
+            }
+        };
+        match action {
+            Some(a) => {
+                apply(cons, m.id, a)?;
+                match a {
+                    Action::Confirm => summary.confirmed += 1,
+                    Action::Refresh => summary.refreshed += 1,
+                    Action::Decay => summary.decayed += 1,
+                }
+            }
+            None => summary.skipped += 1,
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn ranks_stale_memories_and_applies_answers() {
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let sure = db::insert_consolidated(&cons, "Builds use sccache", "pattern", &[], 0.9).unwrap();
+        let shaky = db::insert_consolidated(&cons, "Staging runs Postgres 12", "observation", &[], 0.4).unwrap();
+        let old = db::insert_consolidated(&cons, "Deploys go through Jenkins", "decision", &[], 0.4).unwrap();
+        db::insert_consolidated(&cons, "Fresh memory", "pattern", &[], 0.4).unwrap();
+        for (id, days) in [(sure, 100), (shaky, 100), (old, 300)] {
+            cons.execute(&format!("UPDATE consolidated SET updated_at = datetime('now', '-{days} days') WHERE id = ?1"), [id])
+                .unwrap();
+        }
+
+        let queue = stale(&cons, 90, 10).unwrap();
+        assert_eq!(queue.iter().map(|s| s.memory.id).collect::<Vec<_>>(), vec![old, shaky, sure]);
+
+        let mut out = Vec::new();
+        let summary = run(&cons, &queue, &mut "x\nd\nc\n".as_bytes(), &mut out).unwrap();
+        assert_eq!(summary, Summary { confirmed: 1, decayed: 1, ..Default::default() });
+        assert!(String::from_utf8(out).unwrap().contains("Answer c, r, d, s, or q."));
+        assert!(db::get_consolidated(&cons, old).unwrap().is_none());
+        let confirmed = db::get_consolidated(&cons, shaky).unwrap().unwrap();
+        assert!((confirmed.confidence - 0.5).abs() < 1e-9);
+        assert_eq!(stale(&cons, 90, 10).unwrap().iter().map(|s| s.memory.id).collect::<Vec<_>>(), vec![sure]);
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::llm;
+
+/// How an unattended run ended, reported in its summary and exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Done,
+    NothingToDo,
+    /// Some work fell back or failed, the rest succeeded
+    Partial,
+    Failed,
+}
+
+impl Outcome {
+    /// Process exit code under `--unattended`. 2 is left to clap's usage errors.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Done => 0,
+            Outcome::Failed => 1,
+            Outcome::NothingToDo => 3,
+            Outcome::Partial => 4,
+        }
+    }
+}
+
+/// What a run did in one store.
+#[derive(Debug, Serialize)]
+pub struct StoreRun {
+    /// The project directory (the home directory for the global store)
+    pub path: PathBuf,
+    /// `quick`, `heuristic`, `micro`, `dream`, `batch`, or `collect`
+    pub mode: String,
+    pub outcome: Outcome,
+    pub consolidations: usize,
+    pub removed: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl StoreRun {
+    pub fn new(path: PathBuf, mode: &str, outcome: Outcome) -> Self {
+        Self { path, mode: mode.to_string(), outcome, consolidations: 0, removed: 0, error: None }
+    }
+
+    /// The run itself, or a failed entry recording the error.
+    pub fn from_result(path: PathBuf, mode: &str, result: Result<StoreRun>) -> Self {
+        result.unwrap_or_else(|e| Self { error: Some(format!("{:#}", e)), ..Self::new(path, mode, Outcome::Failed) })
+    }
+}
+
+/// Written to `.cortex/runs/<timestamp>.json` after an unattended run.
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    pub command: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub outcome: Outcome,
+    pub llm_calls: u64,
+    pub stores: Vec<StoreRun>,
+}
+
+impl RunSummary {
+    pub fn new(command: &str, started_at: DateTime<Utc>, stores: Vec<StoreRun>) -> Self {
+        Self {
+            command: command.to_string(),
+            started_at,
+            finished_at: Utc::now(),
+            outcome: overall(&stores),
+            llm_calls: llm::calls_made(),
+            stores,
+        }
+    }
+}
+
+/// Failed only if every store failed; partial if any store failed or fell back.
+pub fn overall(stores: &[StoreRun]) -> Outcome {
+    let failed = stores.iter().filter(|s| s.outcome == Outcome::Failed).count();
+    if stores.is_empty() || stores.iter().all(|s| s.outcome == Outcome::NothingToDo) {
+        Outcome::NothingToDo
+    } else if failed == stores.len() {
+        Outcome::Failed
+    } else if failed > 0 || stores.iter().any(|s| s.outcome == Outcome::Partial) {
+        Outcome::Partial
+    } else {
+        Outcome::Done
+    }
+}
+
+/// Write `summary` under `cortex_dir/runs/`, returning the file's path.
+pub fn write(cortex_dir: &Path, summary: &RunSummary) -> Result<PathBuf> {
+    let dir = cortex_dir.join("runs");
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.json", summary.started_at.format("%Y%m%dT%H%M%S%.3fZ")));
+    std::fs::write(&path, serde_json::to_string_pretty(summary)?)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(outcome: Outcome) -> StoreRun {
+        StoreRun::new(PathBuf::from("/p"), "quick", outcome)
+    }
+
+    #[test]
+    fn combines_store_outcomes() {
+        use Outcome::*;
+        assert_eq!(overall(&[]), NothingToDo);
+        assert_eq!(overall(&[run(NothingToDo), run(NothingToDo)]), NothingToDo);
+        assert_eq!(overall(&[run(Done), run(NothingToDo)]), Done);
+        assert_eq!(overall(&[run(Done), run(Partial)]), Partial);
+        assert_eq!(overall(&[run(Done), run(Failed)]), Partial);
+        assert_eq!(overall(&[run(NothingToDo), run(Failed)]), Partial);
+        assert_eq!(overall(&[run(Failed), run(Failed)]), Failed);
+        assert_eq!((Done.exit_code(), Failed.exit_code(), NothingToDo.exit_code(), Partial.exit_code()), (0, 1, 3, 4));
+    }
+
+    #[test]
+    fn writes_summary_with_errors() {
+        let dir = std::env::temp_dir().join(format!("cortex-runs-{}", uuid::Uuid::new_v4()));
+        let failed = StoreRun::from_result(PathBuf::from("/p"), "quick", Err(anyhow::anyhow!("store is locked")));
+        let summary = RunSummary::new("sleep", Utc::now(), vec![failed]);
+
+        let path = write(&dir, &summary).unwrap();
+        assert!(path.starts_with(dir.join("runs")));
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["outcome"], "failed");
+        assert_eq!(json["stores"][0]["error"], "store is locked");
+        assert_eq!(json["stores"][0]["mode"], "quick");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
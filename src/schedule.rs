@@ -0,0 +1,417 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Every job `cortex schedule` knows how to install.
+pub const JOB_NAMES: [&str; 2] = ["sleep", "dream"];
+
+/// Marks crontab lines owned by cortex so reinstalling and uninstalling leave the rest alone.
+const CRON_TAG: &str = "# cortex-schedule:";
+
+/// Where scheduled jobs are installed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// `~/Library/LaunchAgents` plists (macOS)
+    Launchd,
+    /// systemd user timers in `~/.config/systemd/user`
+    Systemd,
+    /// The user's crontab
+    Cron,
+}
+
+impl Backend {
+    /// launchd on macOS, systemd when booted with it, otherwise cron.
+    pub fn detect() -> Self {
+        if cfg!(target_os = "macos") {
+            Backend::Launchd
+        } else if Path::new("/run/systemd/system").is_dir() {
+            Backend::Systemd
+        } else {
+            Backend::Cron
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Launchd => "launchd",
+            Backend::Systemd => "systemd",
+            Backend::Cron => "cron",
+        }
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "launchd" => Ok(Backend::Launchd),
+            "systemd" => Ok(Backend::Systemd),
+            "cron" => Ok(Backend::Cron),
+            _ => anyhow::bail!("Unknown scheduler '{}' (expected launchd, systemd, or cron)", s),
+        }
+    }
+}
+
+/// One scheduled cortex invocation.
+#[derive(Debug, Clone)]
+pub struct Job {
+    /// One of `JOB_NAMES`; used in file names, labels, and crontab tags
+    pub name: &'static str,
+    /// Arguments after the cortex executable
+    pub args: Vec<String>,
+    /// Day of week for weekly jobs (0 = Sunday); `None` runs daily
+    pub weekday: Option<u8>,
+    pub hour: u8,
+    pub minute: u8,
+}
+
+/// Nightly `cortex --unattended sleep --all-projects` at `hour:minute`.
+pub fn sleep_job(hour: u8, minute: u8, max_llm_calls: Option<u64>) -> Job {
+    let mut args = vec!["--unattended".to_string(), "sleep".to_string(), "--all-projects".to_string()];
+    if let Some(max) = max_llm_calls {
+        args.extend(["--max-llm-calls".to_string(), max.to_string()]);
+    }
+    Job { name: "sleep", args, weekday: None, hour, minute }
+}
+
+/// Sunday `cortex --unattended dream --global`, an hour after the nightly sleep so the two don't overlap.
+pub fn dream_job(hour: u8, minute: u8) -> Job {
+    let args = vec!["--unattended".to_string(), "dream".to_string(), "--global".to_string()];
+    Job { name: "dream", args, weekday: Some(0), hour: (hour + 1) % 24, minute }
+}
+
+/// `"03:30"` -> `(3, 30)`.
+pub fn parse_time(s: &str) -> Result<(u8, u8)> {
+    let parsed = s.split_once(':').and_then(|(h, m)| Some((h.parse::<u8>().ok()?, m.parse::<u8>().ok()?)));
+    match parsed {
+        Some((h, m)) if h < 24 && m < 60 => Ok((h, m)),
+        _ => anyhow::bail!("Invalid time '{}' (expected HH:MM, 24-hour)", s),
+    }
+}
+
+/// A file or crontab entry to write for a job.
+pub struct Artifact {
+    /// Destination file, or `None` for a crontab line
+    pub path: Option<PathBuf>,
+    pub contents: String,
+}
+
+/// What `install` would write for `jobs`, without touching anything.
+pub fn render(backend: Backend, exe: &Path, jobs: &[Job]) -> Result<Vec<Artifact>> {
+    let mut artifacts = Vec::new();
+    for job in jobs {
+        match backend {
+            Backend::Launchd => artifacts.push(Artifact {
+                path: Some(launchd_path(job.name)?),
+                contents: launchd_plist(job, exe, &log_path(job.name)?),
+            }),
+            Backend::Systemd => {
+                let (service, timer) = systemd_units(job, exe);
+                artifacts.push(Artifact { path: Some(systemd_path(job.name, "service")?), contents: service });
+                artifacts.push(Artifact { path: Some(systemd_path(job.name, "timer")?), contents: timer });
+            }
+            Backend::Cron => artifacts.push(Artifact { path: None, contents: cron_line(job, exe, &log_path(job.name)?) }),
+        }
+    }
+    Ok(artifacts)
+}
+
+/// Write and activate `jobs`, replacing any earlier install of the same jobs.
+pub fn install(backend: Backend, exe: &Path, jobs: &[Job]) -> Result<()> {
+    if let Some(dir) = log_path("sleep")?.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    match backend {
+        Backend::Launchd => {
+            for (job, artifact) in jobs.iter().zip(render(backend, exe, jobs)?) {
+                let path = artifact.path.expect("launchd jobs are files");
+                write_file(&path, &artifact.contents)?;
+                // Reloading is how launchd picks up a changed plist; the unload fails harmlessly if it wasn't loaded
+                let _ = run("launchctl", &["unload", &path.to_string_lossy()]);
+                run("launchctl", &["load", "-w", &path.to_string_lossy()])
+                    .with_context(|| format!("Failed to load {} job", job.name))?;
+            }
+        }
+        Backend::Systemd => {
+            for artifact in render(backend, exe, jobs)? {
+                write_file(&artifact.path.expect("systemd units are files"), &artifact.contents)?;
+            }
+            run("systemctl", &["--user", "daemon-reload"])?;
+            for job in jobs {
+                run("systemctl", &["--user", "enable", "--now", &format!("cortex-{}.timer", job.name)])?;
+            }
+        }
+        Backend::Cron => {
+            let names: Vec<&str> = jobs.iter().map(|j| j.name).collect();
+            let mut crontab = strip_cron(&read_crontab()?, &names);
+            for artifact in render(backend, exe, jobs)? {
+                crontab.push_str(&artifact.contents);
+                crontab.push('\n');
+            }
+            write_crontab(&crontab)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove every installed cortex job. Returns the names that were removed.
+pub fn uninstall(backend: Backend) -> Result<Vec<&'static str>> {
+    let installed: Vec<&'static str> = status(backend)?.into_iter().filter(|(_, s)| s.is_some()).map(|(n, _)| n).collect();
+    match backend {
+        Backend::Launchd => {
+            for name in &installed {
+                let path = launchd_path(name)?;
+                let _ = run("launchctl", &["unload", "-w", &path.to_string_lossy()]);
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Backend::Systemd => {
+            for name in &installed {
+                let _ = run("systemctl", &["--user", "disable", "--now", &format!("cortex-{}.timer", name)]);
+                for kind in ["timer", "service"] {
+                    let path = systemd_path(name, kind)?;
+                    if path.exists() {
+                        std::fs::remove_file(path)?;
+                    }
+                }
+            }
+            if !installed.is_empty() {
+                run("systemctl", &["--user", "daemon-reload"])?;
+            }
+        }
+        Backend::Cron => {
+            if !installed.is_empty() {
+                write_crontab(&strip_cron(&read_crontab()?, &JOB_NAMES))?;
+            }
+        }
+    }
+    Ok(installed)
+}
+
+/// For each job name, where it is installed (a file path or the crontab line), if it is.
+pub fn status(backend: Backend) -> Result<Vec<(&'static str, Option<String>)>> {
+    let crontab = if backend == Backend::Cron { read_crontab()? } else { String::new() };
+    JOB_NAMES
+        .iter()
+        .map(|&name| {
+            let found = match backend {
+                Backend::Launchd => Some(launchd_path(name)?).filter(|p| p.exists()).map(|p| p.display().to_string()),
+                Backend::Systemd => Some(systemd_path(name, "timer")?).filter(|p| p.exists()).map(|p| p.display().to_string()),
+                Backend::Cron => {
+                    let tag = format!("{}{}", CRON_TAG, name);
+                    crontab.lines().find(|l| l.ends_with(&tag)).map(|l| l.to_string())
+                }
+            };
+            Ok((name, found))
+        })
+        .collect()
+}
+
+fn launchd_plist(job: &Job, exe: &Path, log: &Path) -> String {
+    let mut args = format!("        <string>{}</string>\n", xml_escape(&exe.to_string_lossy()));
+    for arg in &job.args {
+        args.push_str(&format!("        <string>{}</string>\n", xml_escape(arg)));
+    }
+    let weekday = job.weekday.map(|d| format!("        <key>Weekday</key>\n        <integer>{}</integer>\n", d)).unwrap_or_default();
+    let log = xml_escape(&log.to_string_lossy());
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{args}    </array>
+    <key>StartCalendarInterval</key>
+    <dict>
+{weekday}        <key>Hour</key>
+        <integer>{hour}</integer>
+        <key>Minute</key>
+        <integer>{minute}</integer>
+    </dict>
+    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#,
+        label = launchd_label(job.name),
+        hour = job.hour,
+        minute = job.minute,
+    )
+}
+
+fn systemd_units(job: &Job, exe: &Path) -> (String, String) {
+    let command = std::iter::once(format!("\"{}\"", exe.display())).chain(job.args.iter().cloned()).collect::<Vec<_>>().join(" ");
+    let service = format!(
+        "[Unit]\nDescription=cortex {}\n\n[Service]\nType=oneshot\nExecStart={}\n",
+        job.args.join(" "),
+        command
+    );
+    let day = match job.weekday {
+        Some(d) => format!("{} ", ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"][d as usize % 7]),
+        None => String::new(),
+    };
+    let timer = format!(
+        "[Unit]\nDescription=Scheduled cortex {}\n\n[Timer]\nOnCalendar={}*-*-* {:02}:{:02}:00\nPersistent=true\n\n[Install]\nWantedBy=timers.target\n",
+        job.name, day, job.hour, job.minute
+    );
+    (service, timer)
+}
+
+fn cron_line(job: &Job, exe: &Path, log: &Path) -> String {
+    let weekday = job.weekday.map(|d| d.to_string()).unwrap_or_else(|| "*".to_string());
+    format!(
+        "{} {} * * {} {} {} >> {} 2>&1 {}{}",
+        job.minute,
+        job.hour,
+        weekday,
+        shell_quote(&exe.to_string_lossy()),
+        job.args.join(" "),
+        shell_quote(&log.to_string_lossy()),
+        CRON_TAG,
+        job.name
+    )
+}
+
+/// `crontab` without the lines for the given jobs.
+fn strip_cron(crontab: &str, names: &[&str]) -> String {
+    crontab
+        .lines()
+        .filter(|l| !names.iter().any(|n| l.ends_with(&format!("{}{}", CRON_TAG, n))))
+        .map(|l| format!("{}\n", l))
+        .collect()
+}
+
+fn read_crontab() -> Result<String> {
+    let out = Command::new("crontab").arg("-l").output().context("Failed to run crontab")?;
+    crontab_listing(out.status.success(), &out.stdout, &out.stderr)
+}
+
+/// The table `crontab -l` printed. It exits non-zero when the user has no crontab yet, which
+/// reads as empty; any other failure is an error, so the user's table is never replaced by ours.
+fn crontab_listing(success: bool, stdout: &[u8], stderr: &[u8]) -> Result<String> {
+    if success {
+        return Ok(String::from_utf8_lossy(stdout).into_owned());
+    }
+    let err = String::from_utf8_lossy(stderr);
+    if err.contains("no crontab for") {
+        return Ok(String::new());
+    }
+    anyhow::bail!("crontab -l failed: {}", err.trim())
+}
+
+fn write_crontab(contents: &str) -> Result<()> {
+    let mut child = Command::new("crontab").arg("-").stdin(Stdio::piped()).spawn().context("Failed to run crontab")?;
+    child.stdin.take().context("crontab stdin")?.write_all(contents.as_bytes())?;
+    if !child.wait()?.success() {
+        anyhow::bail!("crontab rejected the updated table");
+    }
+    Ok(())
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let out = Command::new(program).args(args).output().with_context(|| format!("Failed to run {}", program))?;
+    if !out.status.success() {
+        anyhow::bail!("{} {} failed: {}", program, args.join(" "), String::from_utf8_lossy(&out.stderr).trim());
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn home() -> Result<PathBuf> {
+    dirs::home_dir().context("Could not determine home directory")
+}
+
+fn launchd_label(name: &str) -> String {
+    format!("dev.cortex.{}", name)
+}
+
+fn launchd_path(name: &str) -> Result<PathBuf> {
+    Ok(home()?.join("Library/LaunchAgents").join(format!("{}.plist", launchd_label(name))))
+}
+
+fn systemd_path(name: &str, kind: &str) -> Result<PathBuf> {
+    Ok(home()?.join(".config/systemd/user").join(format!("cortex-{}.{}", name, kind)))
+}
+
+/// Output of launchd and cron runs (systemd keeps it in the journal).
+fn log_path(name: &str) -> Result<PathBuf> {
+    Ok(crate::init::global_dir_path().context("Could not determine home directory")?.join("logs").join(format!("{}.log", name)))
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_missing_crontab_reads_as_empty() {
+        assert_eq!(crontab_listing(true, b"0 3 * * * backup\n", b"").unwrap(), "0 3 * * * backup\n");
+        assert_eq!(crontab_listing(false, b"", b"no crontab for me\n").unwrap(), "");
+        assert_eq!(crontab_listing(false, b"", b"crontab: no crontab for me\n").unwrap(), "");
+        let err = crontab_listing(false, b"", b"You (me) are not allowed to use this program (crontab)\n").unwrap_err();
+        assert!(err.to_string().contains("not allowed"));
+    }
+
+    #[test]
+    fn renders_jobs_for_each_backend() {
+        let exe = Path::new("/opt/cortex & co/cortex");
+        let log = Path::new("/home/me/.cortex/logs/sleep.log");
+        let sleep = sleep_job(3, 30, Some(20));
+        let dream = dream_job(23, 0);
+        assert_eq!((dream.weekday, dream.hour), (Some(0), 0));
+
+        let plist = launchd_plist(&sleep, exe, log);
+        assert!(plist.contains("<string>/opt/cortex &amp; co/cortex</string>"));
+        assert!(plist.contains("<string>--unattended</string>\n        <string>sleep</string>"));
+        assert!(plist.contains("<string>--max-llm-calls</string>\n        <string>20</string>"));
+        assert!(plist.contains("<integer>30</integer>"));
+        assert!(!plist.contains("Weekday"));
+        assert!(launchd_plist(&dream, exe, log).contains("<key>Weekday</key>\n        <integer>0</integer>"));
+
+        let (service, timer) = systemd_units(&sleep, exe);
+        assert!(service.contains("ExecStart=\"/opt/cortex & co/cortex\" --unattended sleep --all-projects --max-llm-calls 20"));
+        assert!(timer.contains("OnCalendar=*-*-* 03:30:00"));
+        assert!(systemd_units(&dream, exe).1.contains("OnCalendar=Sun *-*-* 00:00:00"));
+
+        assert_eq!(
+            cron_line(&dream, Path::new("/usr/bin/cortex"), log),
+            "0 0 * * 0 '/usr/bin/cortex' --unattended dream --global >> '/home/me/.cortex/logs/sleep.log' 2>&1 # cortex-schedule:dream"
+        );
+    }
+
+    #[test]
+    fn strips_only_cortex_cron_lines() {
+        let crontab = "MAILTO=me\n0 1 * * * backup\n0 3 * * * cortex sleep # cortex-schedule:sleep\n0 4 * * 0 cortex dream # cortex-schedule:dream\n";
+        assert_eq!(strip_cron(crontab, &["sleep"]), "MAILTO=me\n0 1 * * * backup\n0 4 * * 0 cortex dream # cortex-schedule:dream\n");
+        assert_eq!(strip_cron(crontab, &JOB_NAMES), "MAILTO=me\n0 1 * * * backup\n");
+    }
+
+    #[test]
+    fn parses_times_and_backends() {
+        assert_eq!(parse_time("03:05").unwrap(), (3, 5));
+        assert!(parse_time("24:00").is_err());
+        assert!(parse_time("3pm").is_err());
+        assert_eq!("systemd".parse::<Backend>().unwrap(), Backend::Systemd);
+        assert!("anacron".parse::<Backend>().is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+/// How far a memory may travel. Levels are ordered: `public < internal < secret`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sensitivity {
+    /// Fine to share anywhere, including the global store used by open-source repos
+    Public,
+    /// Stays within this project and its team (committed consolidated.db, ADRs)
+    #[default]
+    Internal,
+    /// Never leaves this machine's raw.db: not consolidated, exported, or promoted
+    Secret,
+}
+
+impl Sensitivity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Sensitivity::Public => "public",
+            Sensitivity::Internal => "internal",
+            Sensitivity::Secret => "secret",
+        }
+    }
+
+    /// Column value to level; unknown values count as the default.
+    pub fn from_db(s: &str) -> Self {
+        s.parse().unwrap_or_default()
+    }
+}
+
+impl std::str::FromStr for Sensitivity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "public" => Ok(Sensitivity::Public),
+            "internal" => Ok(Sensitivity::Internal),
+            "secret" => Ok(Sensitivity::Secret),
+            _ => anyhow::bail!("Unknown sensitivity '{}' (expected public, internal, or secret)", s),
+        }
+    }
+}
+
+impl std::fmt::Display for Sensitivity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_and_parses_levels() {
+        assert!(Sensitivity::Public < Sensitivity::Internal && Sensitivity::Internal < Sensitivity::Secret);
+        assert_eq!("secret".parse::<Sensitivity>().unwrap(), Sensitivity::Secret);
+        assert!("confidential".parse::<Sensitivity>().is_err());
+        assert_eq!(Sensitivity::from_db("bogus"), Sensitivity::Internal);
+        assert_eq!([Sensitivity::Public, Sensitivity::Secret].into_iter().max(), Some(Sensitivity::Secret));
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::db;
+use crate::query;
+
+/// FTS candidates read per store before re-scoring by word overlap.
+const CANDIDATES: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Raw,
+    Consolidated,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimilarMemory {
+    pub id: i64,
+    pub source: Source,
+    pub r#type: String,
+    pub content: String,
+    /// 0.0 to 1.0; see `similar_to_text`
+    pub score: f64,
+}
+
+/// Memories most similar to `text`, from raw and consolidated memory, best first.
+///
+/// Hybrid scoring: FTS (any shared word) picks candidates, which are scored as
+/// 0.5 × word-set Jaccard + 0.3 × the share of `text`'s words they contain + 0.2 × BM25
+/// relative to the best candidate in the same store. Word overlap keeps the score comparable
+/// across queries; BM25 breaks ties towards rarer shared words. Access counts are not bumped.
+pub fn similar_to_text(
+    raw: &Connection,
+    cons: &Connection,
+    text: &str,
+    exclude: Option<(Source, i64)>,
+    limit: usize,
+) -> Result<Vec<SimilarMemory>> {
+    let words = word_set(text);
+    let mut results = candidates(raw, "memories_fts", "memories", Source::Raw, text, &words)?;
+    results.extend(candidates(cons, "consolidated_fts", "consolidated", Source::Consolidated, text, &words)?);
+    results.retain(|m| Some((m.source, m.id)) != exclude);
+    results.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.id.cmp(&b.id)));
+    results.truncate(limit);
+    Ok(results)
+}
+
+/// Memories most similar to an existing one, excluding itself. Errors if it doesn't exist.
+pub fn similar_to_memory(
+    raw: &Connection,
+    cons: &Connection,
+    source: Source,
+    id: i64,
+    limit: usize,
+) -> Result<Vec<SimilarMemory>> {
+    let (conn, table, label) = match source {
+        Source::Raw => (raw, "memories", "Memory"),
+        Source::Consolidated => (cons, "consolidated", "Consolidated memory"),
+    };
+    let content: Option<String> = conn
+        .query_row(&format!("SELECT content FROM {table} WHERE id = ?1"), [id], |r| r.get(0))
+        .optional()?;
+    let content = content.ok_or_else(|| anyhow::anyhow!("{} #{} not found.", label, id))?;
+    similar_to_text(raw, cons, &content, Some((source, id)), limit)
+}
+
+fn candidates(
+    conn: &Connection,
+    fts: &str,
+    table: &str,
+    source: Source,
+    text: &str,
+    words: &HashSet<String>,
+) -> Result<Vec<SimilarMemory>> {
+    let q = query::fts_query(text, db::fts_tokenizer(conn, fts)?);
+    if q.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut stmt = conn.prepare(&format!(
+        "SELECT t.id, t.type, t.content, f.rank FROM {fts} f JOIN {table} t ON f.rowid = t.id
+         WHERE {fts} MATCH ?1 ORDER BY f.rank LIMIT ?2"
+    ))?;
+    let rows: Vec<(i64, String, String, f64)> = stmt
+        .query_map(params![q, CANDIDATES as i64], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    // FTS5 ranks are negative, lower is better
+    let best = rows.iter().map(|r| r.3).fold(0.0, f64::min);
+    Ok(rows
+        .into_iter()
+        .map(|(id, r#type, content, rank)| {
+            let other = word_set(&content);
+            let contained = if words.is_empty() { 0.0 } else { words.intersection(&other).count() as f64 / words.len() as f64 };
+            let bm25 = if best < 0.0 { rank / best } else { 0.0 };
+            let score = 0.5 * jaccard(words, &other) + 0.3 * contained + 0.2 * bm25;
+            SimilarMemory { id, source, r#type, content, score }
+        })
+        .collect())
+}
+
+/// Lowercased words of 3+ characters.
+pub(crate) fn word_set(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() >= 3)
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+pub(crate) fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / a.union(b).count() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn ranks_near_duplicates_first_and_excludes_self() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let a = db::save_memory(&raw, "Use tokio spawn_blocking for sqlite calls in handlers", "pattern", "s1").unwrap();
+        let b = db::save_memory(&raw, "sqlite calls in async handlers need spawn_blocking", "pattern", "s1").unwrap();
+        db::save_memory(&raw, "Release notes are generated from the changelog", "decision", "s1").unwrap();
+        let c = db::insert_consolidated(&cons, "Wrap sqlite calls in spawn_blocking inside tokio handlers", "pattern", &[a], 0.8)
+            .unwrap();
+
+        let out = similar_to_memory(&raw, &cons, Source::Raw, a, 10).unwrap();
+        assert!(out.iter().all(|m| !(m.source == Source::Raw && m.id == a)));
+        let found: Vec<(Source, i64)> = out.iter().map(|m| (m.source, m.id)).collect();
+        assert_eq!(found.len(), 2, "unrelated memory shares no words: {:?}", found);
+        assert!(found.contains(&(Source::Raw, b)) && found.contains(&(Source::Consolidated, c)));
+        assert!(out.iter().all(|m| m.score > 0.0 && m.score <= 1.0));
+
+        let exact = similar_to_text(&raw, &cons, "Release notes are generated from the changelog", None, 1).unwrap();
+        assert!(exact[0].score > 0.99);
+        assert!(similar_to_memory(&raw, &cons, Source::Consolidated, 99, 5).is_err());
+        assert!(similar_to_text(&raw, &cons, "?!", None, 5).unwrap().is_empty());
+    }
+}
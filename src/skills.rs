@@ -1,27 +1,472 @@
 use anyhow::Result;
 use rusqlite::Connection;
+use std::collections::HashSet;
 use std::path::Path;
 
 use crate::db;
+use crate::models::Skill;
 
+/// Longest skill name (and file stem) accepted.
+const MAX_NAME_LEN: usize = 64;
+/// Lists the files `generate_skill_files` or `export_claude` wrote into a directory, one per
+/// line, so the next run can remove ones it no longer writes without touching hand-written files.
+const MANIFEST: &str = ".cortex-manifest";
+/// Names that are unusable as file names on some platforms.
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8", "com9", "lpt1",
+    "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Turn an LLM-supplied skill name into a safe kebab-case file stem.
+/// Names containing path separators, `..`, or control characters are rejected outright
+/// rather than rewritten, as are names that are empty or reserved once normalized.
+pub fn sanitize_name(raw: &str) -> Result<String> {
+    if raw.contains(['/', '\\']) || raw.contains("..") || raw.chars().any(|c| c.is_control()) {
+        anyhow::bail!("skill name {:?} contains a path separator or control character", raw);
+    }
+    let mut name = String::new();
+    for c in raw.trim().chars().flat_map(|c| c.to_lowercase()) {
+        if c.is_ascii_alphanumeric() {
+            name.push(c);
+        } else if !name.is_empty() && !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    name.truncate(MAX_NAME_LEN);
+    let name = name.trim_end_matches('-').to_string();
+    if name.is_empty() {
+        anyhow::bail!("skill name {:?} has no usable characters", raw);
+    }
+    if RESERVED_NAMES.contains(&name.as_str()) {
+        anyhow::bail!("skill name {:?} is reserved", raw);
+    }
+    Ok(name)
+}
+
+/// Store an LLM skill update under its sanitized name. If no skill has that name but one
+/// has a near-identical name (`error-handling` vs `error-handling-patterns`), the update is
+/// merged into that skill instead, and the raw name is remembered so later updates go to the
+/// same place. Invalid names are skipped with a warning; an invalid category leaves the
+/// skill uncategorized.
+pub fn store_skill(
+    cons_conn: &Connection,
+    raw_name: &str,
+    category: Option<&str>,
+    content: &str,
+    source_ids: &[i64],
+) -> Result<bool> {
+    let sanitized = match sanitize_name(raw_name) {
+        Ok(name) => name,
+        Err(e) => {
+            eprintln!("Warning: skipping skill update: {}", e);
+            return Ok(false);
+        }
+    };
+    let existing = db::get_all_skills(cons_conn)?;
+    let name = match db::resolve_skill_alias(cons_conn, raw_name)? {
+        Some(name) => name,
+        None if existing.iter().any(|s| s.name == sanitized) => sanitized.clone(),
+        None => match find_similar(&existing, &sanitized) {
+            Some(similar) => {
+                eprintln!("Merging skill `{}` into existing skill `{}`.", sanitized, similar.name);
+                similar.name.clone()
+            }
+            None => sanitized.clone(),
+        },
+    };
+    if name != raw_name {
+        db::record_skill_alias(cons_conn, raw_name, &name)?;
+    }
+
+    // An update aimed at a differently named skill adds to it rather than replacing it
+    let content = match existing.iter().find(|s| s.name == name) {
+        Some(target) if name != sanitized => merge_content(&target.content, content),
+        _ => content.to_string(),
+    };
+    let category = category.filter(|c| !c.trim().is_empty()).and_then(|c| match sanitize_name(c) {
+        Ok(c) => Some(c),
+        Err(e) => {
+            eprintln!("Warning: ignoring category for skill `{}`: {}", name, e);
+            None
+        }
+    });
+    db::upsert_skill(cons_conn, &name, category.as_deref(), &content, source_ids)?;
+    Ok(true)
+}
+
+/// Words that don't distinguish one skill from another.
+const GENERIC_NAME_WORDS: &[&str] = &[
+    "pattern", "patterns", "guide", "guidelines", "tips", "best", "practices", "notes", "conventions", "skill",
+    "skills", "overview",
+];
+
+/// Minimum overlap of significant name words for two skills to count as the same topic.
+const NAME_SIMILARITY_THRESHOLD: f64 = 0.75;
+
+/// An existing skill whose name means the same thing as `name`, ignoring generic words.
+fn find_similar<'a>(existing: &'a [crate::models::Skill], name: &str) -> Option<&'a crate::models::Skill> {
+    let words = significant_words(name);
+    if words.is_empty() {
+        return None;
+    }
+    existing
+        .iter()
+        .map(|s| {
+            let other = significant_words(&s.name);
+            let shared = words.iter().filter(|w| other.contains(w)).count();
+            let union = words.len() + other.len() - shared;
+            (s, shared as f64 / union.max(1) as f64)
+        })
+        .filter(|(_, score)| *score >= NAME_SIMILARITY_THRESHOLD)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(s, _)| s)
+}
+
+fn significant_words(name: &str) -> Vec<&str> {
+    let mut words: Vec<&str> = name.split('-').filter(|w| !w.is_empty() && !GENERIC_NAME_WORDS.contains(w)).collect();
+    words.sort_unstable();
+    words.dedup();
+    words
+}
+
+/// Whether a skill spells out a procedure: a fenced code block, or at least two numbered steps.
+pub fn has_steps(content: &str) -> bool {
+    let numbered = content
+        .lines()
+        .map(str::trim_start)
+        .filter(|l| {
+            let digits = l.chars().take_while(char::is_ascii_digit).count();
+            digits > 0 && (l[digits..].starts_with(". ") || l[digits..].starts_with(") "))
+        })
+        .count();
+    numbered >= 2 || content.lines().any(|l| l.trim_start().starts_with("```"))
+}
+
+/// The skill `name` refers to: its name, its `category/name` path, or a name an update was
+/// stored under.
+pub fn find(cons_conn: &Connection, name: &str) -> Result<Option<Skill>> {
+    let name = name.trim().trim_end_matches(".md");
+    let skills = db::get_all_skills(cons_conn)?;
+    if let Some(skill) = skills.iter().find(|s| s.name == name || s.path() == name) {
+        return Ok(Some(skill.clone()));
+    }
+    let resolved = match db::resolve_skill_alias(cons_conn, name)? {
+        Some(resolved) => resolved,
+        None => match sanitize_name(name.rsplit('/').next().unwrap_or(name)) {
+            Ok(sanitized) => sanitized,
+            Err(_) => return Ok(None),
+        },
+    };
+    Ok(skills.into_iter().find(|s| s.name == resolved))
+}
+
+/// Skills whose name or body match `query`, the project's first, each with its scope: `""`,
+/// or `"global "` for the global store's.
+pub fn search(
+    cons_conn: &Connection,
+    global_cons: Option<&Connection>,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<(&'static str, Skill)>> {
+    let mut found: Vec<(&'static str, Skill)> = db::search_skills(cons_conn, query, limit)?.into_iter().map(|s| ("", s)).collect();
+    if let Some(gc) = global_cons {
+        found.extend(db::search_skills(gc, query, limit)?.into_iter().map(|s| ("global ", s)));
+    }
+    Ok(found)
+}
+
+/// A skill recall found, as `[skill] path: snippet`.
+pub fn recall_line(scope: &str, skill: &Skill) -> String {
+    format!("[{}skill] {}: {}", scope, skill.path(), skill.snippet.as_deref().unwrap_or_default())
+}
+
+/// Delete skill `name` and its file under `skills_dir`. Returns whether it existed.
+pub fn remove(cons_conn: &Connection, skills_dir: &Path, name: &str) -> Result<bool> {
+    let Some(skill) = db::get_all_skills(cons_conn)?.into_iter().find(|s| s.name == name) else {
+        return Ok(false);
+    };
+    db::remove_skill(cons_conn, name)?;
+    if let Ok(stem) = sanitize_name(&skill.name) {
+        let _ = std::fs::remove_file(skills_dir.join(format!("{}.md", stem)));
+        if let Some(category) = skill.category.as_deref().and_then(|c| sanitize_name(c).ok()) {
+            let _ = std::fs::remove_file(skills_dir.join(category).join(format!("{}.md", stem)));
+        }
+    }
+    Ok(true)
+}
+
+/// LLM-free merge: keep `old`, then append the paragraphs of `new` it doesn't already contain.
+pub fn merge_content(old: &str, new: &str) -> String {
+    let normalize = |p: &str| p.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let known: std::collections::HashSet<String> = old.split("\n\n").map(normalize).collect();
+    let mut merged = old.trim_end().to_string();
+    for paragraph in new.split("\n\n") {
+        if paragraph.trim().is_empty() || known.contains(&normalize(paragraph)) {
+            continue;
+        }
+        merged.push_str("\n\n");
+        merged.push_str(paragraph.trim_end());
+    }
+    merged
+}
+
+/// Write each skill to `<skills_dir>/[<category>/]<name>.md`. Files written by an earlier run
+/// for skills since renamed, recategorized or deleted are removed. Skills whose names clash
+/// once sanitized get a numeric suffix rather than overwriting each other.
 pub fn generate_skill_files(cons_conn: &Connection, skills_dir: &Path) -> Result<Vec<String>> {
     std::fs::create_dir_all(skills_dir)?;
     let skills = db::get_all_skills(cons_conn)?;
     let mut written = Vec::new();
+    let mut taken = HashSet::new();
 
     for skill in &skills {
-        let filename = format!("{}.md", skill.name);
-        let path = skills_dir.join(&filename);
-        let content = format_skill_markdown(&skill.name, &skill.content);
-        std::fs::write(&path, content)?;
+        // Rows written before names were sanitized may still hold unsafe names
+        let name = match sanitize_name(&skill.name) {
+            Ok(name) => name,
+            Err(e) => {
+                eprintln!("Warning: not writing skill file: {}", e);
+                continue;
+            }
+        };
+        let category = skill.category.as_deref().and_then(|c| sanitize_name(c).ok());
+        let prefix = category.as_ref().map(|c| format!("{}/", c)).unwrap_or_default();
+        let unique = unclaimed(&mut taken, &name, |n| format!("{}{}.md", prefix, n));
+        let filename = format!("{}{}.md", prefix, unique);
+        if unique != name {
+            eprintln!("Warning: skill '{}' clashes with another skill's file name; writing {}", skill.name, filename);
+        }
+        if let Some(c) = &category {
+            std::fs::create_dir_all(skills_dir.join(c))?;
+        }
+        let content = format_skill_markdown(&name, category.as_deref(), &skill.content);
+        std::fs::write(skills_dir.join(&filename), content)?;
         written.push(filename);
     }
 
+    prune_stale(skills_dir, &written)?;
     Ok(written)
 }
 
-fn format_skill_markdown(name: &str, content: &str) -> String {
+/// `name`, or the first of `name-2`, `name-3`, ... whose `path` isn't `taken` yet. Paths are
+/// compared lowercased, since the files may land on a case-insensitive file system.
+fn unclaimed(taken: &mut HashSet<String>, name: &str, path: impl Fn(&str) -> String) -> String {
+    let mut candidate = name.to_string();
+    let mut n = 1;
+    while !taken.insert(path(&candidate).to_lowercase()) {
+        n += 1;
+        candidate = format!("{}-{}", name, n);
+    }
+    candidate
+}
+
+/// Remove the files `dir`'s manifest lists that this run didn't write (paths relative to
+/// `dir`), with any directory that leaves empty, then record `written` as the new manifest.
+fn prune_stale(dir: &Path, written: &[String]) -> Result<()> {
+    let manifest = dir.join(MANIFEST);
+    let previous = std::fs::read_to_string(&manifest).unwrap_or_default();
+    for stale in previous.lines().filter(|f| !f.is_empty() && !written.iter().any(|w| w == f)) {
+        // Only plain relative paths, in case the manifest was edited
+        if stale.contains("..") || stale.starts_with('/') {
+            continue;
+        }
+        let path = dir.join(stale);
+        let _ = std::fs::remove_file(&path);
+        if let Some(parent) = path.parent().filter(|d| *d != dir) {
+            // Only succeeds once the directory is empty
+            let _ = std::fs::remove_dir(parent);
+        }
+    }
+    std::fs::write(&manifest, written.iter().map(|f| format!("{}\n", f)).collect::<String>())?;
+    Ok(())
+}
+
+fn format_skill_markdown(name: &str, category: Option<&str>, content: &str) -> String {
+    let category = category.map(|c| format!("category: {c}\n")).unwrap_or_default();
     format!(
-        "---\nname: {name}\ndescription: Learned patterns for {name}\n---\n\n{content}\n"
+        "---\nname: {name}\ndescription: Learned patterns for {name}\n{category}---\n\n{content}\n"
     )
 }
+
+/// Rough token count (about four characters per token), good enough for size budgets.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+/// Cut `text` to about `max_tokens`, on a line boundary where possible, with a marker.
+pub fn truncate_to_tokens(text: &str, max_tokens: usize) -> String {
+    if estimate_tokens(text) <= max_tokens {
+        return text.to_string();
+    }
+    let mut end = (max_tokens * 4).min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let cut = text[..end].rfind('\n').unwrap_or(end);
+    format!("{}\n\n_(truncated; `cortex dream` condenses oversized skills)_", text[..cut].trim_end())
+}
+
+/// Export skills as Claude Code skills: `<dir>/<name>/SKILL.md`, one directory per skill
+/// (Claude Code does not nest skills), with the category kept in the description. Skills
+/// over `max_tokens` are truncated so a runaway skill can't flood the agent's context. As with
+/// `generate_skill_files`, clashing names get a numeric suffix and skills exported by an
+/// earlier run that no longer exist are removed.
+pub fn export_claude(cons_conn: &Connection, dir: &Path, max_tokens: usize) -> Result<Vec<std::path::PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    let mut exported = Vec::new();
+    let mut taken = HashSet::new();
+    for skill in db::get_all_skills(cons_conn)? {
+        let Ok(sanitized) = sanitize_name(&skill.name) else { continue };
+        let name = unclaimed(&mut taken, &sanitized, |n| n.to_string());
+        if name != sanitized {
+            eprintln!("Warning: skill '{}' clashes with another skill's name; exporting it as {}", skill.name, name);
+        }
+        let category = skill.category.as_deref().and_then(|c| sanitize_name(c).ok());
+        let description = match &category {
+            Some(c) => format!("Learned {} patterns for {} (from cortex memory)", c, name),
+            None => format!("Learned patterns for {} (from cortex memory)", name),
+        };
+        let skill_dir = dir.join(&name);
+        std::fs::create_dir_all(&skill_dir)?;
+        let path = skill_dir.join("SKILL.md");
+        std::fs::write(
+            &path,
+            format!(
+                "---\nname: {}\ndescription: {}\n---\n\n{}\n",
+                name,
+                description,
+                truncate_to_tokens(&skill.content, max_tokens)
+            ),
+        )?;
+        written.push(path);
+        exported.push(format!("{}/SKILL.md", name));
+    }
+    prune_stale(dir, &exported)?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_to_kebab_case() {
+        assert_eq!(sanitize_name("Rust Error Handling").unwrap(), "rust-error-handling");
+        assert_eq!(sanitize_name("  async_io: tokio!  ").unwrap(), "async-io-tokio");
+        assert_eq!(sanitize_name(&"a".repeat(100)).unwrap().len(), MAX_NAME_LEN);
+    }
+
+    #[test]
+    fn matches_near_identical_names() {
+        let skill = |name: &str| crate::models::Skill {
+            id: 0,
+            name: name.into(),
+            content: String::new(),
+            source_ids: vec![],
+            updated_at: String::new(),
+            category: None,
+            use_count: 0,
+            last_used_at: None,
+            snippet: None,
+        };
+        let existing = vec![skill("error-handling"), skill("rust-testing"), skill("sqlite-patterns")];
+        assert_eq!(find_similar(&existing, "error-handling-patterns").unwrap().name, "error-handling");
+        assert_eq!(find_similar(&existing, "sqlite").unwrap().name, "sqlite-patterns");
+        assert!(find_similar(&existing, "rust-error-handling-in-cli").is_none());
+        assert!(find_similar(&existing, "best-practices").is_none());
+    }
+
+    #[test]
+    fn merge_appends_new_paragraphs() {
+        let old = "# Errors\n\nUse anyhow.";
+        let new = "# Errors\n\nuse  anyhow.\n\nAdd context to IO errors.";
+        assert_eq!(merge_content(old, new), "# Errors\n\nUse anyhow.\n\nAdd context to IO errors.");
+    }
+
+    #[test]
+    fn truncates_on_line_boundary() {
+        let text = "line one\nline two\nline three";
+        assert_eq!(truncate_to_tokens(text, 100), text);
+        let cut = truncate_to_tokens(text, 4);
+        assert!(cut.starts_with("line one\n\n_(truncated"), "{}", cut);
+    }
+
+    #[test]
+    fn finds_procedures_by_name_or_path() {
+        assert!(has_steps("# Release\n\n1. Bump the version\n2. Tag it\n"));
+        assert!(has_steps("Run:\n\n```sh\ncargo test\n```"));
+        assert!(!has_steps("# Errors\n\nUse anyhow. 2024 was the year we switched.\n- 1 crate per feature"));
+
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        store_skill(&cons, "Release Process", Some("workflow"), "1. Bump\n2. Tag", &[]).unwrap();
+        for name in ["release-process", "workflow/release-process", "Release Process", "release-process.md"] {
+            assert_eq!(find(&cons, name).unwrap().map(|s| s.name), Some("release-process".to_string()), "{}", name);
+        }
+        assert!(find(&cons, "deploys").unwrap().is_none());
+    }
+
+    #[test]
+    fn recall_searches_skill_bodies() {
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let body = "# Releases\n\n1. Bump the version in Cargo.toml\n2. Push a signed tag to trigger the publish workflow";
+        store_skill(&cons, "release-process", Some("workflow"), body, &[]).unwrap();
+        store_skill(&cons, "error-handling", None, "Use anyhow with context on IO errors", &[]).unwrap();
+
+        let found = search(&cons, None, "signed tag", 5).unwrap();
+        assert_eq!(found.len(), 1);
+        let line = recall_line(found[0].0, &found[0].1);
+        assert!(line.starts_with("[skill] workflow/release-process: ") && line.contains("signed tag"), "{}", line);
+
+        store_skill(&cons, "error-handling", None, "Wrap errors with thiserror", &[]).unwrap();
+        db::record_skill_use(&cons, found[0].1.id).unwrap();
+        assert!(db::search_skills(&cons, "anyhow", 5).unwrap().is_empty(), "updates reindex");
+        assert_eq!(db::search_skills(&cons, "thiserror", 5).unwrap().len(), 1);
+        remove(&cons, Path::new("/nonexistent"), "release-process").unwrap();
+        assert!(db::search_skills(&cons, "signed tag", 5).unwrap().is_empty());
+        assert!(db::fts_in_sync(&cons, "skills_fts").unwrap());
+    }
+
+    #[test]
+    fn rejects_unsafe_names() {
+        for name in ["../etc/passwd", "a/b", "a\\b", "..", "", "---", "CON", "tab\there"] {
+            assert!(sanitize_name(name).is_err(), "{:?} should be rejected", name);
+        }
+    }
+
+    #[test]
+    fn skill_files_follow_renames_and_never_collide() {
+        let dir = std::env::temp_dir().join(format!("cortex-skills-{}", uuid::Uuid::new_v4()));
+        let skills_dir = dir.join("skills");
+        std::fs::create_dir_all(&skills_dir).unwrap();
+        let conn = db::open_consolidated_db(&dir.join("consolidated.db")).unwrap();
+        db::upsert_skill(&conn, "deploy", Some("ops"), "1. Build", &[]).unwrap();
+        db::upsert_skill(&conn, "Deploy", Some("Ops"), "1. Ship", &[]).unwrap();
+        db::upsert_skill(&conn, "triage", None, "1. Label", &[]).unwrap();
+        std::fs::write(skills_dir.join("notes.md"), "hand-written").unwrap();
+
+        let mut written = generate_skill_files(&conn, &skills_dir).unwrap();
+        written.sort();
+        assert_eq!(written, ["ops/deploy-2.md", "ops/deploy.md", "triage.md"]);
+
+        // A category change and a deletion leave no stale files behind
+        conn.execute("UPDATE skills SET category = 'release' WHERE name = 'deploy'", []).unwrap();
+        db::remove_skill(&conn, "Deploy").unwrap();
+        assert_eq!(generate_skill_files(&conn, &skills_dir).unwrap(), ["triage.md", "release/deploy.md"]);
+        assert!(!skills_dir.join("ops").exists());
+        assert!(skills_dir.join("release/deploy.md").exists());
+        assert_eq!(std::fs::read_to_string(skills_dir.join("notes.md")).unwrap(), "hand-written");
+
+        // Claude Code exports get the same treatment, one directory per skill
+        let claude = dir.join("claude");
+        db::upsert_skill(&conn, "Deploy", None, "1. Ship", &[]).unwrap();
+        std::fs::create_dir_all(claude.join("mine")).unwrap();
+        std::fs::write(claude.join("mine/SKILL.md"), "hand-written").unwrap();
+        assert_eq!(export_claude(&conn, &claude, 1000).unwrap().len(), 3);
+        assert!(claude.join("deploy-2/SKILL.md").exists());
+        db::remove_skill(&conn, "Deploy").unwrap();
+        export_claude(&conn, &claude, 1000).unwrap();
+        assert!(!claude.join("deploy-2").exists() && claude.join("deploy/SKILL.md").exists());
+        assert!(claude.join("mine/SKILL.md").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
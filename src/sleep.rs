@@ -1,19 +1,38 @@
 use anyhow::Result;
 use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
 
+use crate::antipattern;
+use crate::audit::{self, Manifest};
+use crate::batch;
+use crate::compress;
 use crate::config;
 use crate::config::Config;
 use crate::db;
 use crate::dream;
+use crate::evaluate;
+use crate::hooks::{self, HookEvent};
 use crate::init;
+use crate::limits;
 use crate::llm;
-use crate::models::ConsolidationResult;
+use crate::lock::{LockBusy, StoreLock};
+use crate::models::{ConsolidatedMemory, ConsolidationResult, EntityUpdate, ExtractedEntity, ExtractedRelationship, Memory};
+use crate::outcome;
+use crate::oversize;
+use crate::quality;
+use crate::sensitivity::Sensitivity;
+use crate::similar::{jaccard, word_set};
 use crate::skills;
+use crate::templates;
 
 /// Micro sleep: pure SQL operations, no LLM call.
 /// Dedup exact matches, update decay scores, delete below threshold.
-pub fn micro_sleep(raw_conn: &Connection, config: &Config) -> Result<u64> {
+pub fn micro_sleep(raw_conn: &Connection, config: &Config, cortex_dir: &std::path::Path) -> Result<u64> {
+    let _lock = StoreLock::acquire(cortex_dir, "micro sleep")?;
     let mut removed = 0u64;
+    // One transaction for all deletes; committing each separately dominates on large stores
+    let tx = raw_conn.unchecked_transaction()?;
+    let raw_conn: &Connection = &tx;
 
     // Dedup exact content matches (keep the one with highest access_count)
     let dupes: Vec<i64> = {
@@ -33,76 +52,221 @@ pub fn micro_sleep(raw_conn: &Connection, config: &Config) -> Result<u64> {
     }
 
     // Decay: compute score = importance * (access_count + 1) / (days_since_access + 1)
-    // Delete memories below threshold that are already consolidated
+    // Archive and delete memories below threshold that are already consolidated
     let threshold = config.consolidation.decay_threshold;
-    let decayed: Vec<i64> = {
-        let mut stmt = raw_conn.prepare(
-            "SELECT id FROM memories
-             WHERE consolidated = 1
-             AND (importance * (access_count + 1.0) / (julianday('now') - julianday(accessed_at) + 1.0)) < ?1",
-        )?;
-        let rows: Vec<i64> = stmt.query_map(rusqlite::params![threshold], |row| row.get(0))?
-            .filter_map(|r| r.ok())
-            .collect();
-        rows
-    };
-    for id in &decayed {
-        db::delete_memory(raw_conn, *id)?;
-        removed += 1;
-    }
+    let decayed = "consolidated = 1
+         AND (importance * (access_count + 1.0) / (julianday('now') - julianday(accessed_at) + 1.0)) < ?1";
+    raw_conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO archived_memories
+                 (id, content, type, created_at, accessed_at, access_count, consolidated, importance,
+                  session_id, entity_ids, tags, refs, sensitivity, no_llm)
+             SELECT id, content, type, created_at, accessed_at, access_count, consolidated, importance,
+                    session_id, entity_ids, tags, refs, sensitivity, no_llm
+             FROM memories WHERE {decayed}"
+        ),
+        rusqlite::params![threshold],
+    )?;
+    removed += raw_conn.execute(&format!("DELETE FROM memories WHERE {decayed}"), rusqlite::params![threshold])? as u64;
+    compress::compact(raw_conn, &config.storage)?;
 
+    tx.commit()?;
     Ok(removed)
 }
 
-/// Quick sleep: gather unprocessed memories, call LLM for consolidation, apply results.
+/// The micro sleep that follows saves once `auto_micro_threshold` memories are pending, skipped
+/// while another consolidation holds the store.
+pub fn auto_micro_sleep(raw_conn: &Connection, config: &Config, cortex_dir: &std::path::Path) -> Result<u64> {
+    match micro_sleep(raw_conn, config, cortex_dir) {
+        Err(e) if e.is::<LockBusy>() => Ok(0),
+        result => result,
+    }
+}
+
+/// Quick sleep: gather unprocessed memories, call LLM for consolidation, apply results. Then,
+/// with the store unlocked again, a dream if `[dream]` says one is due. An `isolated` store (a
+/// tenant of `cortex serve`) promotes nothing to the global store.
 pub async fn quick_sleep(
     raw_conn: &Connection,
     cons_conn: &Connection,
     config: &Config,
     cortex_dir: &std::path::Path,
+    isolated: bool,
+) -> Result<ConsolidationResult> {
+    let result = quick_consolidation(raw_conn, cons_conn, config, cortex_dir, isolated).await?;
+    auto_dream(raw_conn, cons_conn, config, cortex_dir, "dream").await;
+    Ok(result)
+}
+
+async fn quick_consolidation(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    config: &Config,
+    cortex_dir: &std::path::Path,
+    isolated: bool,
 ) -> Result<ConsolidationResult> {
-    let unprocessed = db::get_unconsolidated_memories(raw_conn)?;
+    let _lock = StoreLock::acquire(cortex_dir, "quick sleep")?;
+    recover_pending_runs(raw_conn, cons_conn)?;
+    apply_outcomes(cons_conn)?;
+    let summarized = oversize::summarize_pending(raw_conn, config).await;
+    if summarized > 0 {
+        eprintln!("Summarized {} oversized memories.", summarized);
+    }
+    let unprocessed = withhold_sensitive(raw_conn, config)?;
     if unprocessed.is_empty() {
         return Ok(ConsolidationResult::default());
     }
 
     let existing = db::get_all_consolidated(cons_conn)?;
-    let entities = db::get_all_entities(raw_conn)?;
-    let relationships = db::get_all_relationships(raw_conn)?;
-    let prompt = build_consolidation_prompt(&unprocessed, &existing, &entities, &relationships);
 
-    let system = "You are a memory consolidation system. Analyze observations and output ONLY valid JSON.";
-    let response = llm::call_anthropic(&prompt, system, config).await?;
+    // Memories saved with --no-llm (and what was built from them) never reach the prompt;
+    // they take the heuristic path
+    let (local, shared): (Vec<_>, Vec<_>) = unprocessed.iter().cloned().partition(|m| m.no_llm);
+    let mut llm_result = if shared.is_empty() {
+        ConsolidationResult::default()
+    } else {
+        let sendable: Vec<_> = existing.iter().filter(|c| !c.no_llm).cloned().collect();
+        llm_consolidation(raw_conn, cons_conn, config, &shared, &sendable, !isolated).await?
+    };
+    if isolated {
+        llm_result.global_promotions.clear();
+    }
+    let (local_result, reinforced) = cluster(&local, &existing);
+    let mut result = llm_result.clone();
+    result.consolidations.extend(local_result.consolidations);
+    result.promotions.extend(local_result.promotions);
+
+    apply_consolidation(raw_conn, cons_conn, &result, &unprocessed, &reinforced)?;
+    enforce_limits(raw_conn, cons_conn, config)?;
+    if config.quality.evaluate {
+        evaluate::run(raw_conn, &shared, &llm_result, config).await;
+    }
+    finish_sleep(cons_conn, config, cortex_dir, &result, &unprocessed, "quick").await?;
+    Ok(result)
+}
+
+/// Quick sleep through the Message Batches API: submit the consolidation prompt and return the
+/// batch ID, or `None` if there is nothing to send. `cortex sleep --collect` applies the result.
+pub async fn submit_batch(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    config: &Config,
+    cortex_dir: &std::path::Path,
+) -> Result<Option<String>> {
+    let _lock = StoreLock::acquire(cortex_dir, "quick sleep")?;
+    recover_pending_runs(raw_conn, cons_conn)?;
+    if !batch::pending(raw_conn, Some(batch::SLEEP))?.is_empty() {
+        anyhow::bail!("A sleep batch is already pending; run `cortex sleep --collect` first.");
+    }
+    // --no-llm memories are left for the next regular sleep to consolidate locally
+    let shared: Vec<_> = withhold_sensitive(raw_conn, config)?.into_iter().filter(|m| !m.no_llm).collect();
+    if shared.is_empty() {
+        return Ok(None);
+    }
+    let existing: Vec<_> = db::get_all_consolidated(cons_conn)?.into_iter().filter(|c| !c.no_llm).collect();
+    let prompt = consolidation_prompt(raw_conn, cons_conn, config, &shared, &existing, true)?;
+    let tool = consolidation_tool();
+    let request = batch::Request {
+        purpose: batch::SLEEP,
+        prompt: &prompt,
+        system: CONSOLIDATION_SYSTEM,
+        llm: &config.llm,
+        tool: &tool,
+        memories: shared.iter().map(|m| m.id).collect(),
+        consolidated: existing.iter().map(|c| c.id).collect(),
+    };
+    Ok(Some(batch::submit(raw_conn, config, request).await?))
+}
+
+/// Apply a collected sleep batch to the memories it was built from that are still
+/// unconsolidated (a regular sleep may have taken some since). The caller holds the store lock.
+pub(crate) async fn apply_batch(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    config: &Config,
+    cortex_dir: &std::path::Path,
+    pending: &batch::Pending,
+    response: serde_json::Value,
+) -> Result<ConsolidationResult> {
+    let unprocessed: Vec<_> =
+        withhold_sensitive(raw_conn, config)?.into_iter().filter(|m| pending.memories.contains(&m.id)).collect();
+    if unprocessed.is_empty() {
+        return Ok(ConsolidationResult::default());
+    }
+    let existing: Vec<_> =
+        db::get_all_consolidated(cons_conn)?.into_iter().filter(|c| pending.consolidated.contains(&c.id)).collect();
+    let result = checked_result(raw_conn, response, &unprocessed, &existing, config)?;
+    apply_consolidation(raw_conn, cons_conn, &result, &unprocessed, &[])?;
+    enforce_limits(raw_conn, cons_conn, config)?;
+    finish_sleep(cons_conn, config, cortex_dir, &result, &unprocessed, "batch").await?;
+    Ok(result)
+}
 
-    // Extract JSON from response (handle markdown code blocks)
-    let json_str = extract_json(&response);
-    let result: ConsolidationResult = serde_json::from_str(json_str)
-        .map_err(|e| anyhow::anyhow!("Failed to parse consolidation JSON: {}. Response: {}", e, &response))?;
+/// Adjust confidence by the `cortex outcome` reports since the last sleep, before anything is
+/// consolidated or evicted by it.
+fn apply_outcomes(cons_conn: &Connection) -> Result<()> {
+    let adjusted = outcome::apply(cons_conn)?;
+    if adjusted > 0 {
+        eprintln!("Adjusted confidence of {} memories from reported outcomes.", adjusted);
+    }
+    Ok(())
+}
 
-    apply_consolidation(raw_conn, cons_conn, &result, &unprocessed)?;
+/// Bring the store back under `[limits]` after a consolidation is applied.
+fn enforce_limits(raw_conn: &Connection, cons_conn: &Connection, config: &Config) -> Result<()> {
+    let evicted = limits::enforce(raw_conn, cons_conn, &config.limits)?;
+    if evicted != limits::Evicted::default() {
+        eprintln!(
+            "Evicted {} consolidated and archived {} raw memories to stay within [limits].",
+            evicted.consolidated, evicted.raw
+        );
+    }
+    if evicted.raw > 0 {
+        compress::compact(raw_conn, &config.storage)?;
+    }
+    Ok(())
+}
 
+/// After a consolidation is applied: global promotions, skill files, `last_sleep`, and the
+/// `on_sleep_complete` hook.
+async fn finish_sleep(
+    cons_conn: &Connection,
+    config: &Config,
+    cortex_dir: &std::path::Path,
+    result: &ConsolidationResult,
+    unprocessed: &[Memory],
+    mode: &str,
+) -> Result<()> {
     // Apply global promotions to ~/.cortex/
     if !result.global_promotions.is_empty() {
         match init::ensure_global_dir() {
             Ok(global_dir) => {
                 let global_cons = db::open_consolidated_db(&global_dir.join("consolidated.db"))?;
-                let mut promoted = 0;
+                let mut promoted = Vec::new();
                 for gp in &result.global_promotions {
-                    // Skip duplicates
-                    if db::consolidated_content_exists(&global_cons, &gp.content)? {
+                    // Skip duplicates, and anything drawn from memories too sensitive to leave the project
+                    let level = highest_sensitivity(unprocessed, &gp.source_ids);
+                    if level > config.sensitivity.global_max
+                        || db::consolidated_content_exists(&global_cons, &gp.content)?
+                    {
                         continue;
                     }
-                    db::insert_consolidated(&global_cons, &gp.content, &gp.r#type, &[], gp.confidence)?;
-                    promoted += 1;
+                    let id = db::insert_consolidated(&global_cons, &gp.content, &gp.r#type, &[], gp.confidence)?;
+                    db::set_consolidated_sensitivity(&global_cons, &[id], level)?;
+                    promoted.push(serde_json::json!({ "id": id, "content": gp.content, "type": gp.r#type }));
                 }
-                if promoted > 0 {
+                if !promoted.is_empty() {
                     skills::generate_skill_files(&global_cons, &global_dir.join("skills"))?;
                     db::set_meta(&global_cons, "last_sleep", &chrono::Utc::now().to_rfc3339())?;
-                    eprintln!("Promoted {} new memories to global store.", promoted);
+                    eprintln!("Promoted {} new memories to global store.", promoted.len());
+                    hooks::fire(config, HookEvent::GlobalPromotion, serde_json::json!({ "memories": promoted })).await;
                 }
 
-                // Auto global dream: if 5+ entries and last dream was 7+ days ago (or never)
-                auto_global_dream(&global_dir, &global_cons).await;
+                // Auto global dream, by the global store's `[dream]` policy
+                let global_config = config::load_config(&global_dir).unwrap_or_default();
+                if let Ok(global_raw) = db::open_raw_db(&global_dir.join("raw.db")) {
+                    auto_dream(&global_raw, &global_cons, &global_config, &global_dir, "global dream").await;
+                }
             }
             Err(e) => {
                 eprintln!("Warning: could not write global promotions: {}", e);
@@ -115,20 +279,340 @@ pub async fn quick_sleep(
 
     // Record sleep time
     db::set_meta(cons_conn, "last_sleep", &chrono::Utc::now().to_rfc3339())?;
+    db::log_activity(cons_conn, "sleep")?;
+
+    hooks::fire(
+        config,
+        HookEvent::SleepComplete,
+        serde_json::json!({
+            "mode": mode,
+            "consolidations": result.consolidations.len(),
+            "promotions": result.promotions.len(),
+            "global_promotions": result.global_promotions.len(),
+            "decayed": result.decayed.len(),
+            "skills_updated": result.skill_updates.len(),
+        }),
+    )
+    .await;
+
+    Ok(())
+}
+
+const CONSOLIDATION_SYSTEM: &str = "You are a memory consolidation system. Analyze observations and record the result with the record_consolidation tool.";
+
+/// One LLM consolidation call over `unprocessed`, checked by `quality`. With `[quality]
+/// refine`, a result with problems gets one more turn to fix them.
+async fn llm_consolidation(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    config: &Config,
+    unprocessed: &[Memory],
+    existing: &[ConsolidatedMemory],
+    global: bool,
+) -> Result<ConsolidationResult> {
+    let prompt = consolidation_prompt(raw_conn, cons_conn, config, unprocessed, existing, global)?;
+    let mut memories = audit::raw_keys(unprocessed.iter().map(|m| m.id));
+    memories.extend(audit::consolidated_keys(existing.iter().map(|m| m.id)));
+    let tool = consolidation_tool();
+    let manifest = Manifest::new(raw_conn, "sleep", memories);
+    let call = llm::call_structured(&prompt, CONSOLIDATION_SYSTEM, config, &config.llm, &tool);
+    let mut response = llm::audited(manifest.clone(), call).await?;
+
+    if config.quality.refine {
+        let problems = refinement_problems(raw_conn, &response, unprocessed, existing, config)?;
+        if !problems.is_empty() {
+            eprintln!("Asking for a refinement pass on {} problem(s).", problems.len());
+            let feedback = format!(
+                "Your result has these problems:\n{}",
+                problems.iter().map(|p| format!("- {}", p)).collect::<Vec<_>>().join("\n")
+            );
+            let call = llm::refine_structured(&prompt, &response, &feedback, CONSOLIDATION_SYSTEM, config, &config.llm, &tool);
+            match llm::audited(manifest, call).await {
+                Ok(refined) => response = refined,
+                Err(e) => eprintln!("Warning: refinement pass failed ({}); applying the first result.", e),
+            }
+        }
+    }
+    checked_result(raw_conn, response, unprocessed, existing, config)
+}
+
+/// What a refinement turn should fix: items failing the quality gates or the hallucination guard,
+/// contradictions with an unclear resolution or left unresolved, or a response that doesn't parse
+/// at all.
+fn refinement_problems(
+    raw_conn: &Connection,
+    response: &serde_json::Value,
+    unprocessed: &[Memory],
+    existing: &[ConsolidatedMemory],
+    config: &Config,
+) -> Result<Vec<String>> {
+    let result: ConsolidationResult = match serde_json::from_value(response.clone()) {
+        Ok(r) => r,
+        Err(e) => return Ok(vec![format!("the result does not match the record_consolidation schema: {}", e)]),
+    };
+    let recent_ids = unprocessed.iter().map(|m| m.id).collect();
+    let existing_ids = existing.iter().map(|m| m.id).collect();
+    let flagged = db::get_contradictions(raw_conn)?;
+    let contradictions = quality::unresolved_contradictions(&result, &flagged, &recent_ids);
+    let (mut result, mut problems) = quality::validate(result, &recent_ids, &existing_ids, &config.quality);
+    problems.extend(contradictions);
+    for caught in quality::guard(&mut result, unprocessed, &config.quality) {
+        problems.push(format!("consolidation \"{}\": {}", caught.content, caught.reason));
+    }
+    Ok(problems)
+}
+
+/// The consolidation prompt over `unprocessed`, with the store's graph, skills, and flagged
+/// contradictions. Without `global` it asks for no global promotions.
+fn consolidation_prompt(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    config: &Config,
+    unprocessed: &[Memory],
+    existing: &[ConsolidatedMemory],
+    global: bool,
+) -> Result<String> {
+    let entities = db::get_all_entities(raw_conn)?;
+    let relationships = db::get_all_relationships(raw_conn)?;
+    let template_hint = templates::consolidation_hint(config);
+    let skill_names: Vec<String> = db::get_all_skills(cons_conn)?.into_iter().map(|s| s.name).collect();
+    let flagged = db::get_contradictions(raw_conn)?;
+    Ok(build_consolidation_prompt(
+        unprocessed,
+        existing,
+        &entities,
+        &relationships,
+        &template_hint,
+        &skill_names,
+        &flagged,
+        global,
+    ))
+}
+
+/// Parse a `record_consolidation` response and drop items that fail the quality gates (or fail
+/// outright under `[quality] strict`), then run the hallucination guard, logging what it catches.
+fn checked_result(
+    raw_conn: &Connection,
+    response: serde_json::Value,
+    unprocessed: &[Memory],
+    existing: &[ConsolidatedMemory],
+    config: &Config,
+) -> Result<ConsolidationResult> {
+    let result: ConsolidationResult = serde_json::from_value(response.clone())
+        .map_err(|e| anyhow::anyhow!("Failed to parse consolidation JSON: {}. Response: {}", e, response))?;
+
+    let recent_ids = unprocessed.iter().map(|m| m.id).collect();
+    let existing_ids = existing.iter().map(|m| m.id).collect();
+    let (mut result, issues) = quality::validate(result, &recent_ids, &existing_ids, &config.quality);
+    if !issues.is_empty() {
+        if config.quality.strict {
+            // Leave the observations unprocessed so the next sleep retries them
+            anyhow::bail!("{} consolidation item(s) failed quality checks: {}", issues.len(), issues.join("; "));
+        }
+        eprintln!("Warning: skipped {} consolidation item(s) that failed quality checks:", issues.len());
+        for issue in &issues {
+            eprintln!("  - {}", issue);
+        }
+    }
+
+    let caught = quality::guard(&mut result, unprocessed, &config.quality);
+    if !caught.is_empty() {
+        quality::log(raw_conn, &caught)?;
+        let rejected = caught.iter().filter(|c| c.action == "rejected").count();
+        if rejected > 0 && config.quality.strict {
+            anyhow::bail!("{} consolidation(s) not supported by their sources; see `cortex rejections`", rejected);
+        }
+        eprintln!(
+            "Warning: hallucination guard rejected {} and down-ranked {} consolidation(s); see `cortex rejections`.",
+            rejected,
+            caught.len() - rejected
+        );
+    }
 
     Ok(result)
 }
 
+/// Tool whose input schema is `ConsolidationResult`; shared with dream.
+pub fn consolidation_tool() -> llm::OutputTool {
+    llm::OutputTool {
+        name: "record_consolidation",
+        description: "Record the consolidation result.",
+        schema: crate::models::consolidation_schema(),
+    }
+}
+
+/// Word-set Jaccard similarity at or above which two memories count as near-duplicates.
+const SIMILARITY_THRESHOLD: f64 = 0.6;
+/// Singletons at or above this importance are promoted as-is.
+const PROMOTE_IMPORTANCE: f64 = 0.7;
+/// Singletons recalled at least this often are promoted as-is.
+const PROMOTE_ACCESS_COUNT: i64 = 3;
+
+/// Heuristic sleep: LLM-free consolidation. Clusters unprocessed memories of the same type
+/// by word overlap, merges each cluster into one consolidated memory (or reinforces an
+/// existing near-identical one), and promotes antipatterns and high-importance or frequently
+/// recalled singletons.
+pub async fn heuristic_sleep(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    config: &Config,
+    cortex_dir: &std::path::Path,
+) -> Result<HeuristicResult> {
+    let _lock = StoreLock::acquire(cortex_dir, "heuristic sleep")?;
+    recover_pending_runs(raw_conn, cons_conn)?;
+    apply_outcomes(cons_conn)?;
+    let unprocessed = withhold_sensitive(raw_conn, config)?;
+    if unprocessed.is_empty() {
+        return Ok(HeuristicResult::default());
+    }
+    let existing = db::get_all_consolidated(cons_conn)?;
+    let (consolidation, reinforced) = cluster(&unprocessed, &existing);
+
+    apply_consolidation(raw_conn, cons_conn, &consolidation, &unprocessed, &reinforced)?;
+    enforce_limits(raw_conn, cons_conn, config)?;
+    let result = HeuristicResult { consolidation, reinforced: reinforced.len() };
+    skills::generate_skill_files(cons_conn, &cortex_dir.join("skills"))?;
+    db::set_meta(cons_conn, "last_sleep", &chrono::Utc::now().to_rfc3339())?;
+    db::log_activity(cons_conn, "sleep")?;
+
+    hooks::fire(
+        config,
+        HookEvent::SleepComplete,
+        serde_json::json!({
+            "mode": "heuristic",
+            "consolidations": result.consolidation.consolidations.len(),
+            "promotions": result.consolidation.promotions.len(),
+            "reinforced": result.reinforced,
+        }),
+    )
+    .await;
+
+    Ok(result)
+}
+
+/// Unconsolidated memories that may be written to consolidated.db. Those above `[sensitivity]
+/// share_max` are marked processed (so they can decay) but stay in raw.db only.
+fn withhold_sensitive(raw_conn: &Connection, config: &Config) -> Result<Vec<Memory>> {
+    let (shared, withheld): (Vec<_>, Vec<_>) = db::get_unconsolidated_memories(raw_conn)?
+        .into_iter()
+        .partition(|m| m.sensitivity <= config.sensitivity.share_max);
+    if !withheld.is_empty() {
+        db::mark_withheld(raw_conn, &withheld.iter().map(|m| m.id).collect::<Vec<_>>())?;
+        eprintln!(
+            "Kept {} memories above the '{}' sensitivity out of consolidation.",
+            withheld.len(),
+            config.sensitivity.share_max
+        );
+    }
+    Ok(shared)
+}
+
+/// The most sensitive of the cited memories, or of the whole batch when none are cited.
+fn highest_sensitivity(memories: &[Memory], ids: &[i64]) -> Sensitivity {
+    memories
+        .iter()
+        .filter(|m| ids.is_empty() || ids.contains(&m.id))
+        .map(|m| m.sensitivity)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Group `unprocessed` by word overlap: clusters become merges (or reinforce a near-identical
+/// existing memory, returned as `(existing id, source ids)`), and notable singletons promotions.
+fn cluster(unprocessed: &[Memory], existing: &[ConsolidatedMemory]) -> (ConsolidationResult, Vec<(i64, Vec<i64>)>) {
+    let existing_words: Vec<_> = existing.iter().map(|m| word_set(&m.content)).collect();
+
+    // Greedy single-pass clustering; the first member of a cluster is its seed
+    let words: Vec<_> = unprocessed.iter().map(|m| word_set(&m.content)).collect();
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    for (i, m) in unprocessed.iter().enumerate() {
+        // Antipatterns are never merged, even with each other
+        let home = clusters.iter_mut().find(|c| {
+            m.r#type != antipattern::TYPE
+                && unprocessed[c[0]].r#type == m.r#type
+                && jaccard(&words[c[0]], &words[i]) >= SIMILARITY_THRESHOLD
+        });
+        match home {
+            Some(c) => c.push(i),
+            None => clusters.push(vec![i]),
+        }
+    }
+
+    let mut result = ConsolidationResult::default();
+    let mut reinforced = Vec::new();
+    for cluster in &clusters {
+        let members: Vec<&Memory> = cluster.iter().map(|&i| &unprocessed[i]).collect();
+        let source_ids: Vec<i64> = members.iter().map(|m| m.id).collect();
+
+        // Already known: credit the existing memory instead of adding a duplicate
+        let known = existing
+            .iter()
+            .zip(&existing_words)
+            .find(|(e, w)| e.r#type == members[0].r#type && jaccard(w, &words[cluster[0]]) >= SIMILARITY_THRESHOLD);
+        if let Some((e, _)) = known {
+            reinforced.push((e.id, source_ids));
+            continue;
+        }
+
+        if members.len() > 1 {
+            // Keep the most important (then longest) phrasing; confidence grows with support
+            let best = members
+                .iter()
+                .max_by(|a, b| a.importance.total_cmp(&b.importance).then(a.content.len().cmp(&b.content.len())))
+                .expect("cluster is non-empty");
+            let avg = members.iter().map(|m| m.importance).sum::<f64>() / members.len() as f64;
+            result.consolidations.push(crate::models::Consolidation {
+                content: best.content.clone(),
+                r#type: best.r#type.clone(),
+                source_ids,
+                confidence: (avg + 0.1 * (members.len() - 1) as f64).min(1.0),
+            });
+        } else if members[0].r#type == antipattern::TYPE
+            || members[0].importance >= PROMOTE_IMPORTANCE
+            || members[0].access_count >= PROMOTE_ACCESS_COUNT
+        {
+            result.promotions.push(members[0].id);
+        }
+    }
+
+    (result, reinforced)
+}
+
+#[derive(Debug, Default)]
+pub struct HeuristicResult {
+    /// Merges and promotions, in the same shape quick sleep produces
+    pub consolidation: ConsolidationResult,
+    /// Existing consolidated memories credited with new observations
+    pub reinforced: usize,
+}
+
+#[allow(clippy::too_many_arguments)]
 fn build_consolidation_prompt(
     unprocessed: &[crate::models::Memory],
     existing: &[crate::models::ConsolidatedMemory],
     entities: &[crate::models::Entity],
     relationships: &[crate::models::Relationship],
+    template_hint: &str,
+    skill_names: &[String],
+    flagged: &[(i64, i64, String)],
+    global: bool,
 ) -> String {
     let recent_json = serde_json::to_string_pretty(
         &unprocessed
             .iter()
-            .map(|m| serde_json::json!({"id": m.id, "content": m.content, "type": m.r#type, "created_at": m.created_at, "entity_ids": m.entity_ids}))
+            .map(|m| {
+                let mut json = serde_json::json!({"id": m.id, "content": m.content, "type": m.r#type, "created_at": m.created_at, "entity_ids": m.entity_ids});
+                // Contradictions found at save time (see `contradiction`)
+                let contradicts: Vec<_> = flagged
+                    .iter()
+                    .filter(|(id, _, _)| *id == m.id)
+                    .map(|(_, cid, reason)| serde_json::json!({"id": cid, "reason": reason}))
+                    .collect();
+                if !contradicts.is_empty() {
+                    json["contradicts"] = serde_json::Value::Array(contradicts);
+                }
+                json
+            })
             .collect::<Vec<_>>(),
     )
     .unwrap_or_default();
@@ -172,6 +656,18 @@ fn build_consolidation_prompt(
         .unwrap_or_default()
     };
 
+    let skills_list = if skill_names.is_empty() { "(none)".to_string() } else { skill_names.join(", ") };
+    let (global_field, global_rule) = if global {
+        (
+            r#"- "global_promotions": array of {"content": "description", "type": "preference|pattern", "source_ids": [recent observation ids it is drawn from], "confidence": 0.0-1.0}
+  Identify user-level knowledge that applies across ALL projects: personal identity (name, role),
+  tool preferences, coding style, workflow habits, language preferences. NOT project-specific patterns."#,
+            "\n- Put cross-project personal preferences and identity in global_promotions, not consolidations",
+        )
+    } else {
+        (r#"- "global_promotions": always an empty array; this store shares nothing with a global one"#, "")
+    };
+
     format!(
         r#"Given these recent observations, existing long-term memories, and the current knowledge graph, consolidate them.
 
@@ -187,15 +683,19 @@ Current Entities:
 Current Relationships:
 {relationships_json}
 
+Structured templates (observations written with these markdown section headings):
+{template_hint}
+
+Existing skills:
+{skills_list}
+
 Output a JSON object with these fields:
 - "consolidations": array of {{"content": "merged abstract pattern", "type": "pattern|bugfix|decision|preference", "source_ids": [list of recent observation ids merged], "confidence": 0.0-1.0}}
 - "contradictions": array of {{"old_id": existing_memory_id, "new_id": recent_observation_id, "resolution": "keep_new|keep_old|merge"}}
 - "promotions": array of recent observation IDs that should be promoted to long-term as-is (high value, unique)
 - "decayed": array of existing long-term memory IDs that are superseded or no longer relevant
-- "skill_updates": array of {{"name": "skill-name-kebab-case", "content": "markdown content describing the learned skill/pattern"}}
-- "global_promotions": array of {{"content": "description", "type": "preference|pattern", "confidence": 0.0-1.0}}
-  Identify user-level knowledge that applies across ALL projects: personal identity (name, role),
-  tool preferences, coding style, workflow habits, language preferences. NOT project-specific patterns.
+- "skill_updates": array of {{"name": "skill-name-kebab-case", "category": "testing|arch|tooling|workflow|style", "content": "markdown content describing the learned skill/pattern"}}
+{global_field}
 - "new_entities": array of {{"name": "EntityName", "type": "language|technology|service|pattern|concept|tool|framework", "description": "Short description"}}
   New entities discovered in the observations that aren't in the current graph.
 - "new_relationships": array of {{"source": "entity_name", "target": "entity_name", "type": "uses|implements|related_to|alternative_to|caused_by|used_for", "confidence": 0.0-1.0}}
@@ -205,149 +705,342 @@ Output a JSON object with these fields:
 
 Rules:
 - Merge similar observations into single consolidated patterns
-- Detect contradictions between old and new knowledge
+- Never merge, rewrite, or decay memories of type "antipattern" ("do NOT do X" rules); they are kept as written
+- Detect contradictions between old and new knowledge; observations with "contradicts" were flagged against those long-term memories when saved, so resolve each of those
 - Promote unique high-value observations directly
 - Decay superseded long-term memories
 - Generate skill files for recurring patterns (3+ related observations)
-- Put cross-project personal preferences and identity in global_promotions, not consolidations
+- To extend an existing skill, reuse its exact name rather than creating a similarly named one{global_rule}
 - Discover new entities and relationships from the observations
 - Use canonical entity names (e.g., "Rust" not "rust lang")
-- Output ONLY valid JSON, no explanation"#
+- When merging templated observations, keep their markdown section structure in the consolidated content
+- Put the whole result in the tool call, no explanation"#
     )
 }
 
+/// The raw.db half of a sleep run, stored in consolidated.db until it has been applied.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RawChanges {
+    consolidated_ids: Vec<i64>,
+    #[serde(default)]
+    new_entities: Vec<ExtractedEntity>,
+    #[serde(default)]
+    new_relationships: Vec<ExtractedRelationship>,
+    #[serde(default)]
+    entity_updates: Vec<EntityUpdate>,
+}
+
+/// Apply a consolidation result so that a crash at any point is safe to retry.
+///
+/// consolidated.db changes are committed in one transaction together with a `pending_runs`
+/// row describing the raw.db changes. Those are then committed in one raw.db transaction that
+/// also records the run id in `applied_runs`, and the pending row is cleared. A run that dies
+/// in between is finished by `recover_pending_runs` on the next sleep, without consolidating
+/// its observations twice.
 fn apply_consolidation(
     raw_conn: &Connection,
     cons_conn: &Connection,
     result: &ConsolidationResult,
     unprocessed: &[crate::models::Memory],
+    reinforced: &[(i64, Vec<i64>)],
 ) -> Result<()> {
-    // Apply new entities from consolidation
-    for entity in &result.new_entities {
-        db::upsert_entity(raw_conn, &entity.name, &entity.r#type, entity.description.as_deref())?;
-    }
-
-    // Apply new relationships from consolidation
-    for rel in &result.new_relationships {
-        let source = db::get_entity_by_name(raw_conn, &rel.source)?;
-        let target = db::get_entity_by_name(raw_conn, &rel.target)?;
-        if let (Some(s), Some(t)) = (source, target) {
-            // Use 0 as evidence_id for consolidation-discovered relationships
-            db::upsert_relationship(raw_conn, s.id, t.id, &rel.r#type, 0, rel.confidence)?;
+    let source_refs = |ids: &[i64]| -> Vec<String> {
+        unprocessed
+            .iter()
+            .filter(|m| ids.contains(&m.id))
+            .flat_map(|m| m.refs.iter().cloned())
+            .collect()
+    };
+    let any_no_llm = |ids: &[i64]| unprocessed.iter().any(|m| m.no_llm && ids.contains(&m.id));
+    let run_id = format!("{}-{}", chrono::Utc::now().format("%Y%m%dT%H%M%S%.6f"), std::process::id());
+    let changes = RawChanges {
+        consolidated_ids: unprocessed.iter().map(|m| m.id).collect(),
+        new_entities: result.new_entities.clone(),
+        new_relationships: result.new_relationships.clone(),
+        entity_updates: result.entity_updates.clone(),
+    };
+
+    let tx = cons_conn.unchecked_transaction()?;
+
+    // Credit existing memories that the new observations repeat
+    // Consolidated memories are as sensitive as the most sensitive observation behind them
+    for (id, source_ids) in reinforced {
+        db::reinforce_consolidated(&tx, *id, source_ids)?;
+        db::add_consolidated_refs(&tx, *id, &source_refs(source_ids))?;
+        let current = db::get_consolidated(&tx, *id)?.map(|c| c.sensitivity).unwrap_or_default();
+        let level = highest_sensitivity(unprocessed, source_ids);
+        if level > current {
+            db::set_consolidated_sensitivity(&tx, &[*id], level)?;
+        }
+        if any_no_llm(source_ids) {
+            db::set_consolidated_no_llm(&tx, &[*id])?;
         }
     }
 
-    // Apply entity updates
-    for update in &result.entity_updates {
-        db::update_entity(raw_conn, &update.name, update.description.as_deref(), update.confidence)?;
-    }
+    // Antipatterns are kept word for word: they are left out of merges and always promoted
+    let antipatterns: Vec<i64> =
+        unprocessed.iter().filter(|m| m.r#type == antipattern::TYPE).map(|m| m.id).collect();
 
-    // Apply consolidations
+    // Apply consolidations, carrying tracker references over from the merged observations
     for c in &result.consolidations {
-        db::insert_consolidated(cons_conn, &c.content, &c.r#type, &c.source_ids, c.confidence)?;
+        let source_ids: Vec<i64> = c.source_ids.iter().copied().filter(|id| !antipatterns.contains(id)).collect();
+        if source_ids.is_empty() && !c.source_ids.is_empty() {
+            continue;
+        }
+        let id = db::insert_consolidated(&tx, &c.content, &c.r#type, &source_ids, c.confidence)?;
+        db::add_consolidated_refs(&tx, id, &source_refs(&source_ids))?;
+        db::set_consolidated_sensitivity(&tx, &[id], highest_sensitivity(unprocessed, &source_ids))?;
+        if any_no_llm(&source_ids) {
+            db::set_consolidated_no_llm(&tx, &[id])?;
+        }
     }
 
     // Apply promotions (copy raw memory to consolidated)
-    for raw_id in &result.promotions {
+    let mut promotions = result.promotions.clone();
+    for id in &antipatterns {
+        if !promotions.contains(id) && !reinforced.iter().any(|(_, ids)| ids.contains(id)) {
+            promotions.push(*id);
+        }
+    }
+    for raw_id in &promotions {
         if let Some(m) = unprocessed.iter().find(|m| m.id == *raw_id) {
-            db::insert_consolidated(cons_conn, &m.content, &m.r#type, &[m.id], m.importance)?;
+            let id = db::insert_consolidated(&tx, &m.content, &m.r#type, &[m.id], m.importance)?;
+            db::set_consolidated_sensitivity(&tx, &[id], m.sensitivity)?;
+            if m.no_llm {
+                db::set_consolidated_no_llm(&tx, &[id])?;
+            }
         }
     }
 
-    // Apply decayed (remove from consolidated)
-    db::remove_consolidated(cons_conn, &result.decayed)?;
+    // Apply decayed (remove from consolidated), except antipatterns
+    let mut decayed = Vec::new();
+    for id in &result.decayed {
+        if db::get_consolidated(&tx, *id)?.is_none_or(|c| c.r#type != antipattern::TYPE) {
+            decayed.push(*id);
+        }
+    }
+    db::remove_consolidated(&tx, &decayed)?;
 
     // Apply skill updates
     for su in &result.skill_updates {
-        db::upsert_skill(cons_conn, &su.name, &su.content, &[])?;
+        skills::store_skill(&tx, &su.name, su.category.as_deref(), &su.content, &[])?;
     }
 
-    // Mark all unprocessed as consolidated
-    let ids: Vec<i64> = unprocessed.iter().map(|m| m.id).collect();
-    db::mark_consolidated(raw_conn, &ids)?;
+    db::insert_pending_run(&tx, &run_id, &serde_json::to_string(&changes)?)?;
+    tx.commit()?;
 
-    Ok(())
+    finish_run(raw_conn, cons_conn, &run_id, &changes)
 }
 
-fn extract_json(text: &str) -> &str {
-    // Try to find JSON in markdown code blocks.
-    // Use rfind for the closing fence since skill content may contain nested code blocks.
-    if let Some(start) = text.find("```json") {
-        let content = &text[start + 7..];
-        if let Some(end) = content.rfind("```") {
-            return content[..end].trim();
+/// Apply a run's raw.db changes unless `applied_runs` says they already were, then clear it
+/// from `pending_runs`.
+fn finish_run(raw_conn: &Connection, cons_conn: &Connection, run_id: &str, changes: &RawChanges) -> Result<()> {
+    if !db::is_run_applied(raw_conn, run_id)? {
+        let tx = raw_conn.unchecked_transaction()?;
+        for entity in &changes.new_entities {
+            db::upsert_entity(&tx, &entity.name, &entity.r#type, entity.description.as_deref())?;
         }
-    }
-    if let Some(start) = text.find("```") {
-        let content = &text[start + 3..];
-        if let Some(end) = content.rfind("```") {
-            return content[..end].trim();
+        for rel in &changes.new_relationships {
+            let source = db::get_entity_by_name(&tx, &rel.source)?;
+            let target = db::get_entity_by_name(&tx, &rel.target)?;
+            if let (Some(s), Some(t)) = (source, target) {
+                // Use 0 as evidence_id for consolidation-discovered relationships
+                db::upsert_relationship(&tx, s.id, t.id, &rel.r#type, 0, rel.confidence)?;
+            }
         }
-    }
-    // Try to find a JSON object directly
-    if let Some(start) = text.find('{') {
-        if let Some(end) = text.rfind('}') {
-            return text[start..=end].trim();
+        for update in &changes.entity_updates {
+            db::update_entity(&tx, &update.name, update.description.as_deref(), update.confidence)?;
         }
+        db::mark_consolidated(&tx, &changes.consolidated_ids)?;
+        db::insert_applied_run(&tx, run_id)?;
+        tx.commit()?;
     }
-    text.trim()
+    db::delete_pending_run(cons_conn, run_id)?;
+    db::delete_applied_run(raw_conn, run_id)
 }
 
-/// Auto-trigger global dream if enough entries exist and it hasn't been done recently.
-async fn auto_global_dream(global_dir: &std::path::Path, global_cons: &rusqlite::Connection) {
-    let count = db::get_consolidated_count(global_cons).unwrap_or(0);
-    if count < 5 {
-        return;
-    }
-
-    let should_dream = match db::get_meta(global_cons, "last_dream") {
-        Ok(Some(last)) => {
-            if let Ok(last_dt) = chrono::DateTime::parse_from_rfc3339(&last) {
-                let last_utc = last_dt.with_timezone(&chrono::Utc);
-                let days = chrono::Utc::now().signed_duration_since(last_utc).num_days();
-                days >= 1
-            } else {
-                true
-            }
-        }
-        _ => true, // never dreamed
-    };
-
-    if !should_dream {
-        return;
+/// Finish sleep runs interrupted after their consolidated.db commit. Returns how many were
+/// completed; must run before unconsolidated memories are read, or they would be sent again.
+pub fn recover_pending_runs(raw_conn: &Connection, cons_conn: &Connection) -> Result<usize> {
+    let pending = db::get_pending_runs(cons_conn)?;
+    for (run_id, raw_changes) in &pending {
+        let changes: RawChanges = serde_json::from_str(raw_changes)?;
+        finish_run(raw_conn, cons_conn, run_id, &changes)?;
     }
+    if !pending.is_empty() {
+        eprintln!("Finished {} interrupted sleep run(s).", pending.len());
+    }
+    Ok(pending.len())
+}
 
-    eprintln!("Auto-running global dream ({} entries, overdue)...", count);
-    let global_config = config::load_config(global_dir).unwrap_or_default();
-    let global_raw = match db::open_raw_db(&global_dir.join("raw.db")) {
-        Ok(c) => c,
-        Err(_) => return,
-    };
-    match dream::dream(&global_raw, global_cons, &global_config, global_dir).await {
-        Ok(result) => {
-            eprintln!(
-                "Global dream complete. {} insights, {} skills updated.",
-                result.insights, result.skills_updated
-            );
-        }
+/// Dream over a store (`label` names it in messages) if `[dream]` says one is due. Failures
+/// are reported, not returned: the sleep before it already succeeded.
+async fn auto_dream(raw_conn: &Connection, cons_conn: &Connection, config: &Config, cortex_dir: &std::path::Path, label: &str) {
+    let reason = match dream::due(raw_conn, cons_conn, &config.dream) {
+        Ok(Some(reason)) => reason,
+        Ok(None) => return,
         Err(e) => {
-            eprintln!("Global dream failed: {}", e);
+            eprintln!("Warning: could not check whether a {} is due: {}", label, e);
+            return;
         }
+    };
+    eprintln!("Auto-running {} ({})...", label, reason);
+    match dream::dream(raw_conn, cons_conn, config, cortex_dir).await {
+        Ok(result) => eprintln!(
+            "Auto {} complete. {} insights, {} refined, {} skills updated, {} removed, {} decayed, {} merged.",
+            label,
+            result.insights,
+            result.insights_refined,
+            result.skills_updated,
+            result.skills_removed,
+            result.decayed,
+            result.merged
+        ),
+        Err(e) => eprintln!("Auto {} failed: {}", label, e),
     }
 }
 
-impl Default for ConsolidationResult {
-    fn default() -> Self {
-        Self {
-            consolidations: vec![],
-            contradictions: vec![],
-            promotions: vec![],
-            decayed: vec![],
-            skill_updates: vec![],
-            global_promotions: vec![],
-            new_entities: vec![],
-            new_relationships: vec![],
-            entity_updates: vec![],
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn open() -> (Connection, Connection) {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        (raw, cons)
+    }
+
+    fn pending(ids: Vec<i64>) -> RawChanges {
+        RawChanges {
+            consolidated_ids: ids,
+            new_entities: vec![ExtractedEntity {
+                name: "tokio".into(),
+                r#type: "technology".into(),
+                description: None,
+            }],
+            ..Default::default()
         }
     }
+
+    #[test]
+    fn recovers_interrupted_run_once() {
+        let (raw, cons) = open();
+        let id = db::save_memory(&raw, "Use tokio for async IO", "pattern", "s1").unwrap();
+        db::insert_pending_run(&cons, "run-1", &serde_json::to_string(&pending(vec![id])).unwrap()).unwrap();
+
+        assert_eq!(recover_pending_runs(&raw, &cons).unwrap(), 1);
+        assert_eq!(db::get_unconsolidated_count(&raw).unwrap(), 0);
+        assert_eq!(db::get_entity_count(&raw).unwrap(), 1);
+        assert!(db::get_pending_runs(&cons).unwrap().is_empty());
+        assert_eq!(recover_pending_runs(&raw, &cons).unwrap(), 0);
+    }
+
+    #[test]
+    fn skips_raw_changes_already_applied() {
+        let (raw, cons) = open();
+        let id = db::save_memory(&raw, "Use tokio for async IO", "pattern", "s1").unwrap();
+        db::mark_consolidated(&raw, &[id]).unwrap();
+        db::insert_applied_run(&raw, "run-1").unwrap();
+        db::insert_pending_run(&cons, "run-1", &serde_json::to_string(&pending(vec![id])).unwrap()).unwrap();
+
+        assert_eq!(recover_pending_runs(&raw, &cons).unwrap(), 1);
+        assert_eq!(db::get_entity_count(&raw).unwrap(), 0);
+        assert!(!db::is_run_applied(&raw, "run-1").unwrap());
+    }
+
+    #[test]
+    fn antipatterns_are_never_merged_or_decayed() {
+        let (raw, cons) = open();
+        let old = db::insert_consolidated(&cons, "Do NOT retry non-idempotent requests", antipattern::TYPE, &[], 0.9)
+            .unwrap();
+        let a = db::save_memory(&raw, "Never log request bodies", antipattern::TYPE, "s1").unwrap();
+        let b = db::save_memory(&raw, "Request bodies are logged at debug level", "observation", "s1").unwrap();
+        let unprocessed = db::get_unconsolidated_memories(&raw).unwrap();
+        let result = ConsolidationResult {
+            consolidations: vec![crate::models::Consolidation {
+                content: "Request bodies get logged".into(),
+                r#type: "pattern".into(),
+                source_ids: vec![a, b],
+                confidence: 0.6,
+            }],
+            decayed: vec![old],
+            ..Default::default()
+        };
+        apply_consolidation(&raw, &cons, &result, &unprocessed, &[]).unwrap();
+
+        let all = db::get_all_consolidated(&cons).unwrap();
+        let merged = all.iter().find(|c| c.r#type == "pattern").unwrap();
+        assert_eq!(merged.source_ids, vec![b]);
+        let kept: Vec<String> =
+            db::get_consolidated_by_type(&cons, antipattern::TYPE).unwrap().into_iter().map(|c| c.content).collect();
+        assert_eq!(kept, vec!["Do NOT retry non-idempotent requests", "Never log request bodies"]);
+        assert_eq!(db::get_unconsolidated_count(&raw).unwrap(), 0);
+    }
+
+    #[test]
+    fn keeps_sensitive_memories_out_of_consolidation() {
+        let (raw, cons) = open();
+        let public = db::save_memory(&raw, "Run cargo fmt before pushing", "pattern", "s1").unwrap();
+        let internal = db::save_memory(&raw, "Run cargo fmt before every push", "pattern", "s1").unwrap();
+        let secret = db::save_memory(&raw, "The staging password is hunter2", "observation", "s1").unwrap();
+        db::set_memory_sensitivity(&raw, &[public], Sensitivity::Public).unwrap();
+        db::set_memory_sensitivity(&raw, &[secret], Sensitivity::Secret).unwrap();
+
+        let unprocessed = withhold_sensitive(&raw, &Config::default()).unwrap();
+        assert_eq!(unprocessed.iter().map(|m| m.id).collect::<Vec<_>>(), vec![public, internal]);
+        // Only the secret memory is marked processed; it never reaches consolidated.db
+        assert_eq!(db::get_unconsolidated_count(&raw).unwrap(), 2);
+        assert_eq!(highest_sensitivity(&unprocessed, &[public]), Sensitivity::Public);
+        assert_eq!(highest_sensitivity(&unprocessed, &[]), Sensitivity::Internal);
+
+        let result = ConsolidationResult {
+            consolidations: vec![crate::models::Consolidation {
+                content: "Format with cargo fmt before pushing".into(),
+                r#type: "pattern".into(),
+                source_ids: vec![public, internal],
+                confidence: 0.8,
+            }],
+            promotions: vec![public],
+            ..Default::default()
+        };
+        apply_consolidation(&raw, &cons, &result, &unprocessed, &[]).unwrap();
+        let levels: Vec<_> = db::get_all_consolidated(&cons).unwrap().into_iter().map(|c| (c.content, c.sensitivity)).collect();
+        assert!(levels.contains(&("Format with cargo fmt before pushing".into(), Sensitivity::Internal)));
+        assert!(levels.contains(&("Run cargo fmt before pushing".into(), Sensitivity::Public)));
+        assert!(!levels.iter().any(|(c, _)| c.contains("hunter2")));
+    }
+
+    #[test]
+    fn isolated_prompts_ask_for_no_global_promotions() {
+        let (raw, cons) = open();
+        db::save_memory(&raw, "I prefer tabs over spaces", "preference", "s1").unwrap();
+        let unprocessed = db::get_unconsolidated_memories(&raw).unwrap();
+        let config = Config::default();
+        let shared = consolidation_prompt(&raw, &cons, &config, &unprocessed, &[], true).unwrap();
+        assert!(shared.contains("Put cross-project personal preferences"));
+        let isolated = consolidation_prompt(&raw, &cons, &config, &unprocessed, &[], false).unwrap();
+        assert!(isolated.contains("\"global_promotions\": always an empty array"));
+        assert!(!isolated.contains("cross-project") && !isolated.contains("ALL projects"));
+    }
+
+    #[test]
+    fn no_llm_memories_consolidate_locally() {
+        let (raw, cons) = open();
+        let a = db::save_memory(&raw, "Customer Acme runs the on-prem build on Windows", "observation", "s1").unwrap();
+        let b = db::save_memory(&raw, "Customer Acme runs the on-prem build on Windows 11", "observation", "s1").unwrap();
+        db::save_memory(&raw, "Tests use nextest", "pattern", "s1").unwrap();
+        db::set_memory_no_llm(&raw, &[a, b]).unwrap();
+
+        let unprocessed = db::get_unconsolidated_memories(&raw).unwrap();
+        let local: Vec<_> = unprocessed.iter().filter(|m| m.no_llm).cloned().collect();
+        let (result, reinforced) = cluster(&local, &[]);
+        assert_eq!(result.consolidations.len(), 1);
+        apply_consolidation(&raw, &cons, &result, &unprocessed, &reinforced).unwrap();
+
+        let merged = db::get_all_consolidated(&cons).unwrap();
+        assert_eq!(merged.len(), 1);
+        assert!(merged[0].no_llm);
+        assert_eq!(db::get_unconsolidated_count(&raw).unwrap(), 0);
+    }
 }
@@ -0,0 +1,174 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, DatabaseName};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::config::SnapshotConfig;
+use crate::lock::StoreLock;
+
+/// Subdirectory of `.cortex/` holding one directory per snapshot.
+pub const DIR: &str = "snapshots";
+
+/// Database files a snapshot holds.
+const FILES: [&str; 2] = ["raw.db", "consolidated.db"];
+
+/// Label prefix of snapshots taken automatically; only these are pruned to `[snapshots] keep`.
+const AUTO_PREFIX: &str = "before-";
+
+/// A snapshot directory, `<UTC timestamp>-<label>`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    pub name: String,
+    pub created_at: String,
+    pub label: String,
+    /// Taken before a dream or destructive command rather than by `cortex snapshot create`
+    pub automatic: bool,
+    /// Combined size of both databases
+    pub bytes: u64,
+}
+
+impl Snapshot {
+    fn from_dir(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_string();
+        let (stamp, label) = name.split_once('-')?;
+        let created_at = chrono::NaiveDateTime::parse_from_str(stamp, "%Y%m%dT%H%M%SZ").ok()?;
+        let bytes = FILES.iter().filter_map(|f| std::fs::metadata(path.join(f)).ok()).map(|m| m.len()).sum();
+        Some(Self {
+            created_at: created_at.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            automatic: label.starts_with(AUTO_PREFIX),
+            label: label.to_string(),
+            name,
+            bytes,
+        })
+    }
+}
+
+/// Copy both databases into a new snapshot with SQLite's backup API, so the copies are
+/// consistent even while other processes write.
+pub fn create(raw_conn: &Connection, cons_conn: &Connection, cortex_dir: &Path, label: &str) -> Result<Snapshot> {
+    if label.is_empty() || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        anyhow::bail!("Snapshot labels may only use letters, digits, '-', and '_'");
+    }
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+    let mut path = cortex_dir.join(DIR).join(format!("{}-{}", stamp, label));
+    // Two snapshots in the same second with the same label get a suffix
+    for n in 2.. {
+        if !path.exists() {
+            break;
+        }
+        path = cortex_dir.join(DIR).join(format!("{}-{}-{}", stamp, label, n));
+    }
+    std::fs::create_dir_all(&path)?;
+    // Snapshots include raw.db, which must never be committed, even in repos initialized
+    // before snapshots existed
+    let ignore = cortex_dir.join(DIR).join(".gitignore");
+    if !ignore.exists() {
+        std::fs::write(&ignore, "*\n")?;
+    }
+    for (conn, file) in [raw_conn, cons_conn].into_iter().zip(FILES) {
+        conn.backup(DatabaseName::Main, path.join(file), None)
+            .with_context(|| format!("Failed to snapshot {}", file))?;
+    }
+    Snapshot::from_dir(&path).context("Snapshot directory has an unexpected name")
+}
+
+/// Snapshot before `operation` if `[snapshots] auto` is on, then prune old automatic ones.
+/// In-memory stores are skipped.
+pub fn before(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    cortex_dir: &Path,
+    config: &SnapshotConfig,
+    operation: &str,
+) -> Result<()> {
+    if !config.auto || cons_conn.path().is_none_or(|p| p.is_empty()) {
+        return Ok(());
+    }
+    create(raw_conn, cons_conn, cortex_dir, &format!("{}{}", AUTO_PREFIX, operation))
+        .with_context(|| format!("Could not snapshot the store before {} (set [snapshots] auto = false to skip)", operation))?;
+    prune(cortex_dir, config.keep)?;
+    Ok(())
+}
+
+/// Snapshots in `cortex_dir`, oldest first.
+pub fn list(cortex_dir: &Path) -> Result<Vec<Snapshot>> {
+    let dir = cortex_dir.join(DIR);
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut snapshots: Vec<Snapshot> = std::fs::read_dir(&dir)?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| Snapshot::from_dir(&e.path()))
+        .collect();
+    snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(snapshots)
+}
+
+/// Delete all but the newest `keep` automatic snapshots (0 keeps them all). Returns how many
+/// were deleted.
+pub fn prune(cortex_dir: &Path, keep: usize) -> Result<usize> {
+    if keep == 0 {
+        return Ok(0);
+    }
+    let automatic: Vec<Snapshot> = list(cortex_dir)?.into_iter().filter(|s| s.automatic).collect();
+    let excess = automatic.len().saturating_sub(keep);
+    for s in &automatic[..excess] {
+        std::fs::remove_dir_all(cortex_dir.join(DIR).join(&s.name))?;
+    }
+    Ok(excess)
+}
+
+/// Replace the store's databases with snapshot `name`, after snapshotting the current state as
+/// `before-restore`. Holds the store lock throughout.
+pub fn restore(raw_conn: &Connection, cons_conn: &Connection, cortex_dir: &Path, name: &str) -> Result<Snapshot> {
+    let path: PathBuf = cortex_dir.join(DIR).join(name);
+    let snapshot = Snapshot::from_dir(&path)
+        .filter(|_| path.is_dir())
+        .ok_or_else(|| anyhow::anyhow!("No snapshot named '{}' (see `cortex snapshot list`)", name))?;
+    for file in FILES {
+        if !path.join(file).exists() {
+            anyhow::bail!("Snapshot '{}' is missing {}", name, file);
+        }
+    }
+    let _lock = StoreLock::acquire(cortex_dir, "snapshot restore")?;
+    create(raw_conn, cons_conn, cortex_dir, &format!("{}restore", AUTO_PREFIX))?;
+    for file in FILES {
+        let mut live = Connection::open(cortex_dir.join(file))?;
+        live.restore(DatabaseName::Main, path.join(file), None::<fn(rusqlite::backup::Progress)>)
+            .with_context(|| format!("Failed to restore {}", file))?;
+    }
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn creates_prunes_and_restores_snapshots() {
+        let dir = std::env::temp_dir().join(format!("cortex-snapshot-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let raw = db::open_raw_db(&dir.join("raw.db")).unwrap();
+        let cons = db::open_consolidated_db(&dir.join("consolidated.db")).unwrap();
+        let kept = db::insert_consolidated(&cons, "Use sqlx for migrations", "decision", &[], 0.9).unwrap();
+
+        let manual = create(&raw, &cons, &dir, "pre-upgrade").unwrap();
+        assert!(!manual.automatic && manual.bytes > 0);
+        assert!(create(&raw, &cons, &dir, "bad label").is_err());
+        let config = SnapshotConfig { auto: true, keep: 1 };
+        before(&raw, &cons, &dir, &config, "delete").unwrap();
+        before(&raw, &cons, &dir, &config, "delete").unwrap();
+        let all = list(&dir).unwrap();
+        assert_eq!(all.iter().filter(|s| s.automatic).count(), 1);
+        assert!(all.iter().any(|s| s.name == manual.name));
+
+        db::remove_consolidated(&cons, &[kept]).unwrap();
+        restore(&raw, &cons, &dir, &manual.name).unwrap();
+        assert_eq!(db::get_all_consolidated(&cons).unwrap().len(), 1);
+        assert!(list(&dir).unwrap().iter().any(|s| s.label == "before-restore"));
+        assert!(restore(&raw, &cons, &dir, "missing").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
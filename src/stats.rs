@@ -0,0 +1,122 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+
+/// ISO week of `created_at`, e.g. `2025-W07`: a week belongs to the year of its Thursday and
+/// is numbered by that Thursday's day of year.
+const ISO_WEEK: &str = "strftime('%Y', created_at, 'weekday 0', '-3 days') || '-W' || \
+    printf('%02d', (strftime('%j', created_at, 'weekday 0', '-3 days') - 1) / 7 + 1)";
+
+/// How `cortex stats --per-*` groups raw memories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grouping {
+    Type,
+    /// Most recently active session first
+    Session,
+    /// ISO week of creation (`2025-W07`), newest first
+    Week,
+}
+
+/// Raw memories in one group.
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryGroup {
+    pub key: String,
+    pub memories: i64,
+    pub unconsolidated: i64,
+    pub avg_importance: f64,
+}
+
+/// Consolidated memories of one type.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsolidatedGroup {
+    pub r#type: String,
+    pub count: i64,
+    pub avg_confidence: f64,
+}
+
+pub fn memory_groups(raw: &Connection, grouping: Grouping) -> Result<Vec<MemoryGroup>> {
+    let (key, order) = match grouping {
+        Grouping::Type => ("type", "COUNT(*) DESC, key"),
+        Grouping::Session => ("COALESCE(session_id, '(none)')", "MAX(created_at) DESC"),
+        Grouping::Week => (ISO_WEEK, "key DESC"),
+    };
+    let mut stmt = raw.prepare(&format!(
+        "SELECT {key} AS key, COUNT(*), SUM(consolidated = 0), AVG(importance)
+         FROM memories GROUP BY key ORDER BY {order}"
+    ))?;
+    let rows = stmt.query_map([], |r| {
+        Ok(MemoryGroup { key: r.get(0)?, memories: r.get(1)?, unconsolidated: r.get(2)?, avg_importance: r.get(3)? })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+pub fn consolidated_groups(cons: &Connection) -> Result<Vec<ConsolidatedGroup>> {
+    let mut stmt = cons.prepare(
+        "SELECT type, COUNT(*), AVG(confidence) FROM consolidated GROUP BY type ORDER BY COUNT(*) DESC, type",
+    )?;
+    let rows = stmt.query_map([], |r| Ok(ConsolidatedGroup { r#type: r.get(0)?, count: r.get(1)?, avg_confidence: r.get(2)? }))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Text table of memory groups under `title`, with `key` as the first column's header.
+/// Shows at most `limit` rows and says how many were left out.
+pub fn format_memory_groups(title: &str, key: &str, groups: &[MemoryGroup], limit: usize) -> String {
+    let width = groups.iter().take(limit).map(|g| g.key.chars().count()).max().unwrap_or(0).max(key.len());
+    let mut out = format!("{}\n  {:<width$}  {:>8}  {:>14}  {:>14}\n", title, key, "MEMORIES", "UNCONSOLIDATED", "AVG IMPORTANCE");
+    for g in groups.iter().take(limit) {
+        out.push_str(&format!(
+            "  {:<width$}  {:>8}  {:>14}  {:>14.2}\n",
+            g.key, g.memories, g.unconsolidated, g.avg_importance
+        ));
+    }
+    if groups.len() > limit {
+        out.push_str(&format!("  ... {} more (see --json)\n", groups.len() - limit));
+    }
+    out
+}
+
+pub fn format_consolidated_groups(groups: &[ConsolidatedGroup]) -> String {
+    let width = groups.iter().map(|g| g.r#type.len()).max().unwrap_or(0).max(4);
+    let mut out = format!("Consolidated by type\n  {:<width$}  {:>8}  {:>14}\n", "TYPE", "COUNT", "AVG CONFIDENCE");
+    if groups.is_empty() {
+        out.push_str("  (none)\n");
+    }
+    for g in groups {
+        out.push_str(&format!("  {:<width$}  {:>8}  {:>14.2}\n", g.r#type, g.count, g.avg_confidence));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use std::path::Path;
+
+    #[test]
+    fn groups_memories_by_type_session_and_week() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let a = db::save_memory(&raw, "one", "observation", "s1").unwrap();
+        db::save_memory(&raw, "two", "observation", "s2").unwrap();
+        db::save_memory(&raw, "three", "decision", "s2").unwrap();
+        db::mark_consolidated(&raw, &[a]).unwrap();
+        db::update_memory_importance(&raw, a, 0.9).unwrap();
+        raw.execute("UPDATE memories SET created_at = '2025-01-01 10:00:00' WHERE id = ?1", [a]).unwrap();
+        db::insert_consolidated(&cons, "x", "pattern", &[a], 0.8).unwrap();
+
+        let by_type = memory_groups(&raw, Grouping::Type).unwrap();
+        assert_eq!((by_type[0].key.as_str(), by_type[0].memories, by_type[0].unconsolidated), ("observation", 2, 1));
+        assert!((by_type[0].avg_importance - 0.7).abs() < 1e-9);
+        let sessions: Vec<String> = memory_groups(&raw, Grouping::Session).unwrap().into_iter().map(|g| g.key).collect();
+        assert_eq!(sessions, vec!["s2", "s1"]);
+        let weeks = memory_groups(&raw, Grouping::Week).unwrap();
+        assert_eq!(weeks.last().unwrap().key, "2025-W01");
+        assert_eq!(consolidated_groups(&cons).unwrap()[0].count, 1);
+
+        let table = format_memory_groups("By session", "SESSION", &memory_groups(&raw, Grouping::Session).unwrap(), 1);
+        assert!(table.contains("... 1 more"), "{}", table);
+        let stats = db::get_stats(&raw, &cons).unwrap();
+        assert!((stats.consolidation_ratio - 1.0 / 3.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,589 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::{Backend, Config};
+use crate::db;
+use crate::models::{ConsolidatedMemory, Skill};
+use crate::patch::{self, Applied, Entry, Patch};
+use crate::sensitivity::Sensitivity;
+
+/// Meta key naming the remote the local consolidated.db last synced with.
+const SYNCED_KEY: &str = "replica_of";
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tables holding consolidated memories and skills on a remote backend, keyed by content and
+/// name like patches. `source_ids` stay behind: they are IDs in each machine's own raw.db.
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS cortex_memories (
+    content TEXT PRIMARY KEY,
+    type TEXT NOT NULL,
+    confidence REAL NOT NULL,
+    refs TEXT NOT NULL DEFAULT '[]',
+    sensitivity TEXT NOT NULL DEFAULT 'internal',
+    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+    no_llm INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL DEFAULT '',
+    access_count INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS cortex_skills (
+    name TEXT PRIMARY KEY,
+    content TEXT NOT NULL,
+    category TEXT,
+    updated_at TEXT NOT NULL,
+    use_count INTEGER NOT NULL DEFAULT 0,
+    last_used_at TEXT
+)";
+
+/// `cortex_memories` columns added after the table first shipped, for remotes created before.
+const ADDED_COLUMNS: [(&str, &str); 3] = [
+    ("no_llm", "INTEGER NOT NULL DEFAULT 0"),
+    ("created_at", "TEXT NOT NULL DEFAULT ''"),
+    ("access_count", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+/// The far end of a `Replica`. This is not a storage layer for commands, which always work on
+/// the local SQLite databases through `db`; it is only what a replica syncs with.
+pub(crate) trait Remote {
+    /// Where the memories live, for messages and to recognize the remote across runs
+    fn describe(&self) -> String;
+    /// Every consolidated memory
+    fn memories(&self) -> Result<Vec<Entry>>;
+    /// Apply `patch` atomically
+    fn apply(&self, patch: &Patch) -> Result<Applied>;
+    /// Every skill
+    fn skills(&self) -> Result<Vec<Skill>>;
+    /// Write `upserts` by name and delete the skills named in `deletes`, atomically
+    fn apply_skills(&self, upserts: &[Skill], deletes: &[String]) -> Result<()>;
+}
+
+impl Remote for Connection {
+    fn describe(&self) -> String {
+        self.path().filter(|p| !p.is_empty()).unwrap_or(":memory:").to_string()
+    }
+
+    fn memories(&self) -> Result<Vec<Entry>> {
+        Ok(db::get_all_consolidated(self)?.iter().map(|m| Entry::new(m, None)).collect())
+    }
+
+    fn apply(&self, patch: &Patch) -> Result<Applied> {
+        patch::apply(self, patch)
+    }
+
+    fn skills(&self) -> Result<Vec<Skill>> {
+        db::get_all_skills(self)
+    }
+
+    fn apply_skills(&self, upserts: &[Skill], deletes: &[String]) -> Result<()> {
+        let tx = self.unchecked_transaction()?;
+        for skill in upserts {
+            db::put_skill(&tx, skill)?;
+        }
+        for name in deletes {
+            db::remove_skill(&tx, name)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// A libsql server or Turso database, spoken to over the Hrana HTTP protocol.
+pub struct Libsql {
+    url: String,
+    token: Option<String>,
+    /// Set once the remote tables are known to exist with every column
+    migrated: OnceLock<()>,
+}
+
+impl Libsql {
+    pub fn new(url: &str, token: Option<String>) -> Self {
+        // Turso hands out libsql:// URLs; its HTTP API is the same host over https
+        let url = match url.strip_prefix("libsql://") {
+            Some(host) => format!("https://{}", host),
+            None => url.to_string(),
+        };
+        Self { url: url.trim_end_matches('/').to_string(), token, migrated: OnceLock::new() }
+    }
+
+    /// Create the remote tables, or add columns missing from an older `cortex_memories`.
+    fn migrate(&self) -> Result<()> {
+        if self.migrated.get().is_some() {
+            return Ok(());
+        }
+        let responses = self.pipeline(vec![
+            json!({ "type": "sequence", "sql": SCHEMA }),
+            json!({ "type": "execute", "stmt": stmt("SELECT name FROM pragma_table_info('cortex_memories')", vec![]) }),
+        ])?;
+        let rows = responses[1]["result"]["rows"].as_array().context("Malformed libsql response")?;
+        let columns: HashSet<String> = rows.iter().map(|row| cell_text(&row[0])).collect();
+        let alters: Vec<Value> = ADDED_COLUMNS
+            .iter()
+            .filter(|(name, _)| !columns.contains(*name))
+            .map(|(name, def)| stmt(&format!("ALTER TABLE cortex_memories ADD COLUMN {} {}", name, def), vec![]))
+            .collect();
+        if !alters.is_empty() {
+            self.transaction(alters)?;
+        }
+        let _ = self.migrated.set(());
+        Ok(())
+    }
+
+    /// Run one query and return its rows.
+    fn rows(&self, sql: &str) -> Result<Vec<Value>> {
+        self.migrate()?;
+        let responses = self.pipeline(vec![json!({ "type": "execute", "stmt": stmt(sql, vec![]) })])?;
+        responses[0]["result"]["rows"].as_array().cloned().context("Malformed libsql response")
+    }
+
+    /// Send Hrana `requests` on a fresh stream and return each one's response.
+    fn pipeline(&self, mut requests: Vec<Value>) -> Result<Vec<Value>> {
+        requests.push(json!({ "type": "close" }));
+        let body = json!({ "baton": null, "requests": requests });
+        let url = format!("{}/v2/pipeline", self.url);
+        // A private runtime on its own thread, so this works the same from CLI commands, async
+        // tasks, and the blocking pool
+        let resp: Value = std::thread::scope(|s| {
+            s.spawn(|| -> Result<Value> {
+                let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+                rt.block_on(async {
+                    let mut req = reqwest::Client::new().post(&url).timeout(TIMEOUT).json(&body);
+                    if let Some(token) = &self.token {
+                        req = req.bearer_auth(token);
+                    }
+                    let resp = req.send().await?;
+                    let status = resp.status();
+                    if !status.is_success() {
+                        anyhow::bail!("HTTP {}: {}", status, resp.text().await.unwrap_or_default());
+                    }
+                    Ok(resp.json().await?)
+                })
+            })
+            .join()
+            .map_err(|_| anyhow::anyhow!("libsql request thread panicked"))?
+        })
+        .with_context(|| format!("Request to {} failed", self.url))?;
+        let results = resp["results"].as_array().context("Malformed libsql response")?;
+        results
+            .iter()
+            .map(|r| match r["type"].as_str() {
+                Some("ok") => Ok(r["response"].clone()),
+                _ => anyhow::bail!("libsql error: {}", r["error"]["message"].as_str().unwrap_or("unknown")),
+            })
+            .collect()
+    }
+
+    /// Run `statements` in one transaction: each step only runs if the one before succeeded,
+    /// and a failure rolls everything back.
+    fn transaction(&self, statements: Vec<Value>) -> Result<()> {
+        let mut steps: Vec<Value> = std::iter::once(stmt("BEGIN", vec![]))
+            .chain(statements)
+            .chain([stmt("COMMIT", vec![])])
+            .enumerate()
+            .map(|(i, s)| match i {
+                0 => json!({ "stmt": s }),
+                _ => json!({ "stmt": s, "condition": { "type": "ok", "step": i - 1 } }),
+            })
+            .collect();
+        let commit = steps.len() - 1;
+        steps.push(json!({
+            "stmt": stmt("ROLLBACK", vec![]),
+            "condition": { "type": "not", "cond": { "type": "ok", "step": commit } },
+        }));
+        let responses = self.pipeline(vec![json!({ "type": "batch", "batch": { "steps": steps } })])?;
+        let errors = responses[0]["result"]["step_errors"].as_array().cloned().unwrap_or_default();
+        if let Some(e) = errors.iter().find(|e| !e.is_null()) {
+            anyhow::bail!("libsql error: {}", e["message"].as_str().unwrap_or("unknown"));
+        }
+        Ok(())
+    }
+}
+
+fn stmt(sql: &str, args: Vec<Value>) -> Value {
+    json!({ "sql": sql, "args": args })
+}
+
+fn text(s: &str) -> Value {
+    json!({ "type": "text", "value": s })
+}
+
+fn integer(n: i64) -> Value {
+    // Hrana sends integers as strings, so they survive JSON's doubles
+    json!({ "type": "integer", "value": n.to_string() })
+}
+
+fn nullable_text(s: Option<&str>) -> Value {
+    s.map(text).unwrap_or(json!({ "type": "null" }))
+}
+
+/// A Hrana result cell as text; integers arrive as strings, floats as numbers.
+fn cell_text(v: &Value) -> String {
+    match &v["value"] {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+fn cell_i64(v: &Value) -> i64 {
+    match &v["value"] {
+        Value::String(s) => s.parse().unwrap_or_default(),
+        Value::Number(n) => n.as_i64().unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+/// A nullable text cell; empty text counts as missing.
+fn cell_opt(v: &Value) -> Option<String> {
+    Some(cell_text(v)).filter(|s| !s.is_empty())
+}
+
+fn cell_f64(v: &Value) -> f64 {
+    match &v["value"] {
+        Value::Number(n) => n.as_f64().unwrap_or_default(),
+        Value::String(s) => s.parse().unwrap_or_default(),
+        _ => 0.0,
+    }
+}
+
+impl Remote for Libsql {
+    fn describe(&self) -> String {
+        self.url.clone()
+    }
+
+    fn memories(&self) -> Result<Vec<Entry>> {
+        let rows = self.rows(
+            "SELECT content, type, confidence, refs, sensitivity, no_llm, created_at, updated_at, access_count
+             FROM cortex_memories ORDER BY rowid",
+        )?;
+        Ok(rows
+            .iter()
+            .map(|row| Entry {
+                content: cell_text(&row[0]),
+                r#type: cell_text(&row[1]),
+                confidence: cell_f64(&row[2]),
+                refs: serde_json::from_str(&cell_text(&row[3])).unwrap_or_default(),
+                sensitivity: Sensitivity::from_db(&cell_text(&row[4])),
+                no_llm: cell_i64(&row[5]) != 0,
+                created_at: cell_opt(&row[6]),
+                updated_at: cell_opt(&row[7]),
+                access_count: cell_i64(&row[8]),
+                replaces: None,
+            })
+            .collect())
+    }
+
+    /// The same matching as `patch::apply`: upserts by their baseline content, then their
+    /// content; deletes by content.
+    fn apply(&self, patch: &Patch) -> Result<Applied> {
+        let mut current: HashMap<String, Entry> = self.memories()?.into_iter().map(|e| (e.content.clone(), e)).collect();
+        let mut statements = vec![];
+        let mut applied = Applied::default();
+        for e in &patch.upserts {
+            let target = e
+                .replaces
+                .as_deref()
+                .filter(|old| current.contains_key(*old))
+                .or(current.contains_key(&e.content).then_some(e.content.as_str()))
+                .map(str::to_string);
+            let mut new = Entry { replaces: None, ..e.clone() };
+            match target.and_then(|t| current.remove(&t)) {
+                None => applied.added += 1,
+                Some(old) if !differs(&old, e) => {
+                    applied.unchanged += 1;
+                    current.insert(old.content.clone(), old);
+                    continue;
+                }
+                Some(old) => {
+                    statements.push(stmt("DELETE FROM cortex_memories WHERE content = ?", vec![text(&old.content)]));
+                    new.refs.extend(old.refs.into_iter().filter(|r| !e.refs.contains(r)));
+                    new.no_llm |= old.no_llm;
+                    new.access_count = new.access_count.max(old.access_count);
+                    new.created_at = old.created_at.or(new.created_at);
+                    applied.updated += 1;
+                }
+            }
+            statements.push(stmt(
+                "INSERT OR REPLACE INTO cortex_memories
+                     (content, type, confidence, refs, sensitivity, no_llm, created_at, updated_at, access_count)
+                 VALUES (?, ?, ?, ?, ?, ?, COALESCE(?, datetime('now')), COALESCE(?, datetime('now')), ?)",
+                vec![
+                    text(&new.content),
+                    text(&new.r#type),
+                    json!({ "type": "float", "value": new.confidence }),
+                    text(&serde_json::to_string(&new.refs)?),
+                    text(new.sensitivity.as_str()),
+                    integer(new.no_llm as i64),
+                    nullable_text(new.created_at.as_deref()),
+                    nullable_text(new.updated_at.as_deref()),
+                    integer(new.access_count),
+                ],
+            ));
+            current.insert(new.content.clone(), new);
+        }
+        for content in &patch.deletes {
+            if current.remove(content).is_some() {
+                statements.push(stmt("DELETE FROM cortex_memories WHERE content = ?", vec![text(content)]));
+                applied.deleted += 1;
+            } else {
+                applied.unchanged += 1;
+            }
+        }
+        if !statements.is_empty() {
+            self.transaction(statements)?;
+        }
+        Ok(applied)
+    }
+
+    fn skills(&self) -> Result<Vec<Skill>> {
+        let rows = self.rows("SELECT name, content, category, updated_at, use_count, last_used_at FROM cortex_skills ORDER BY name")?;
+        Ok(rows
+            .iter()
+            .map(|row| Skill {
+                id: 0,
+                name: cell_text(&row[0]),
+                content: cell_text(&row[1]),
+                source_ids: vec![],
+                updated_at: cell_text(&row[3]),
+                category: cell_opt(&row[2]),
+                use_count: cell_i64(&row[4]),
+                last_used_at: cell_opt(&row[5]),
+                snippet: None,
+            })
+            .collect())
+    }
+
+    /// The same merge as `db::put_skill`: usage counts only go up.
+    fn apply_skills(&self, upserts: &[Skill], deletes: &[String]) -> Result<()> {
+        self.migrate()?;
+        let mut statements: Vec<Value> = upserts
+            .iter()
+            .map(|s| {
+                stmt(
+                    "INSERT INTO cortex_skills (name, content, category, updated_at, use_count, last_used_at)
+                     VALUES (?, ?, ?, ?, ?, ?)
+                     ON CONFLICT(name) DO UPDATE SET content = excluded.content, category = excluded.category,
+                         updated_at = excluded.updated_at, use_count = MAX(use_count, excluded.use_count),
+                         last_used_at = NULLIF(MAX(COALESCE(last_used_at, ''), COALESCE(excluded.last_used_at, '')), '')",
+                    vec![
+                        text(&s.name),
+                        text(&s.content),
+                        nullable_text(s.category.as_deref()),
+                        text(&s.updated_at),
+                        integer(s.use_count),
+                        nullable_text(s.last_used_at.as_deref()),
+                    ],
+                )
+            })
+            .collect();
+        statements.extend(deletes.iter().map(|name| stmt("DELETE FROM cortex_skills WHERE name = ?", vec![text(name)])));
+        if statements.is_empty() {
+            return Ok(());
+        }
+        self.transaction(statements)
+    }
+}
+
+/// Whether applying `new` over `old` changes anything `patch::apply` would compare.
+fn differs(old: &Entry, new: &Entry) -> bool {
+    old.content != new.content
+        || old.r#type != new.r#type
+        || old.sensitivity != new.sensitivity
+        || old.no_llm != new.no_llm
+        || old.access_count != new.access_count
+        || (old.confidence - new.confidence).abs() > 0.01
+}
+
+/// Whether a skill's synced columns differ.
+fn skill_differs(old: &Skill, new: &Skill) -> bool {
+    old.content != new.content
+        || old.category != new.category
+        || old.use_count != new.use_count
+        || old.last_used_at != new.last_used_at
+}
+
+/// Skills to write and names to delete turning `old` into `new`, matched by name.
+fn skill_diff(old: &[Skill], new: &[Skill]) -> (Vec<Skill>, Vec<String>) {
+    let old_by_name: HashMap<&str, &Skill> = old.iter().map(|s| (s.name.as_str(), s)).collect();
+    let new_names: HashSet<&str> = new.iter().map(|s| s.name.as_str()).collect();
+    let upserts =
+        new.iter().filter(|s| old_by_name.get(s.name.as_str()).is_none_or(|o| skill_differs(o, s))).cloned().collect();
+    let deletes = old.iter().filter(|s| !new_names.contains(s.name.as_str())).map(|s| s.name.clone()).collect();
+    (upserts, deletes)
+}
+
+/// The local consolidated.db standing in for a remote backend: the remote's memories and
+/// skills are pulled in when a store opens, and local changes since are pushed back when it
+/// closes. Long-lived stores can `pull` again to pick up others' changes. Only memories at or
+/// below `[sensitivity] share_max` travel either way; skills are built from those alone.
+/// Every column travels except `source_ids`, which name observations in the local raw.db.
+pub struct Replica {
+    remote: Box<dyn Remote + Send>,
+    max: Sensitivity,
+    /// Shareable local memories as of the last pull or push, to diff against on push
+    baseline: Vec<ConsolidatedMemory>,
+    /// Local skills as of the last pull or push
+    skills: Vec<Skill>,
+}
+
+impl Replica {
+    /// Pull the remote `[db] backend` into `local`, or `None` for plain SQLite.
+    pub fn open(local: &Connection, config: &Config) -> Result<Option<Self>> {
+        let remote: Box<dyn Remote + Send> = match config.db.backend {
+            Backend::Sqlite => return Ok(None),
+            Backend::Libsql => {
+                if config.db.url.is_empty() {
+                    anyhow::bail!("[db] backend = \"libsql\" needs a url");
+                }
+                let token = std::env::var(&config.db.auth_token_env).ok().filter(|t| !t.is_empty());
+                Box::new(Libsql::new(&config.db.url, token))
+            }
+        };
+        Self::join(local, remote, config.sensitivity.share_max).map(Some)
+    }
+
+    fn join(local: &Connection, remote: Box<dyn Remote + Send>, max: Sensitivity) -> Result<Self> {
+        let name = remote.describe();
+        // A store joining a remote seeds it first, so its memories aren't deleted as missing
+        if db::get_meta(local, SYNCED_KEY)?.as_deref() != Some(name.as_str()) {
+            let mut seed = Patch::new("");
+            seed.upserts = shareable(local, max)?.iter().map(|m| Entry::new(m, None)).collect();
+            remote.apply(&seed).with_context(|| format!("Could not seed {}", name))?;
+            remote.apply_skills(&db::get_all_skills(local)?, &[]).with_context(|| format!("Could not seed {}", name))?;
+        }
+        let mut replica = Self { remote, max, baseline: vec![], skills: vec![] };
+        replica.fetch(local)?;
+        db::set_meta(local, SYNCED_KEY, &name)?;
+        Ok(replica)
+    }
+
+    /// Push local changes, then pull the remote's, so memories added locally since the last
+    /// push aren't deleted as missing.
+    pub fn pull(&mut self, local: &Connection) -> Result<()> {
+        self.push(local)?;
+        self.fetch(local)
+    }
+
+    /// Make `local`'s shareable memories and its skills match the remote's.
+    fn fetch(&mut self, local: &Connection) -> Result<()> {
+        let name = self.remote.describe();
+        let theirs = self.remote.memories().with_context(|| format!("Could not pull from {}", name))?;
+        let their_skills = self.remote.skills().with_context(|| format!("Could not pull from {}", name))?;
+        let present: HashSet<&str> = theirs.iter().map(|e| e.content.as_str()).collect();
+        let mut patch = Patch::new(&name);
+        patch.deletes = shareable(local, self.max)?
+            .into_iter()
+            .filter(|m| !present.contains(m.content.as_str()))
+            .map(|m| m.content)
+            .collect();
+        patch.upserts = theirs;
+        patch::apply(local, &patch)?;
+        let (upserts, deletes) = skill_diff(&db::get_all_skills(local)?, &their_skills);
+        local.apply_skills(&upserts, &deletes)?;
+        self.baseline = shareable(local, self.max)?;
+        self.skills = db::get_all_skills(local)?;
+        Ok(())
+    }
+
+    /// Push memories and skills added, changed, or deleted in `local` since the last pull or
+    /// push. Returns what happened to the memories.
+    pub fn push(&mut self, local: &Connection) -> Result<Applied> {
+        let name = self.remote.describe();
+        let skills = db::get_all_skills(local)?;
+        let (upserts, deletes) = skill_diff(&self.skills, &skills);
+        if !(upserts.is_empty() && deletes.is_empty()) {
+            self.remote.apply_skills(&upserts, &deletes).with_context(|| format!("Could not push to {}", name))?;
+            self.skills = skills;
+        }
+        let current = shareable(local, self.max)?;
+        let mut patch = Patch::new("");
+        (patch.upserts, patch.deletes) = patch::diff(self.baseline.clone(), &current, self.max);
+        if patch.upserts.is_empty() && patch.deletes.is_empty() {
+            return Ok(Applied::default());
+        }
+        let applied = self.remote.apply(&patch).with_context(|| format!("Could not push to {}", name))?;
+        self.baseline = current;
+        Ok(applied)
+    }
+}
+
+fn shareable(conn: &Connection, max: Sensitivity) -> Result<Vec<ConsolidatedMemory>> {
+    Ok(db::get_all_consolidated(conn)?.into_iter().filter(|m| m.sensitivity <= max).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn replica_pulls_and_pushes() {
+        let dir = std::env::temp_dir().join(format!("cortex-storage-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let remote_path = dir.join("remote.db");
+        let remote = db::open_consolidated_db(&remote_path).unwrap();
+        let mdbook = db::insert_consolidated(&remote, "Docs are built with mdbook", "convention", &[], 0.7).unwrap();
+        remote
+            .execute("UPDATE consolidated SET created_at = '2025-01-02 03:04:05', access_count = 4 WHERE id = ?1", [mdbook])
+            .unwrap();
+        db::set_consolidated_no_llm(&remote, &[mdbook]).unwrap();
+        db::upsert_skill(&remote, "release", Some("ops"), "Tag, then publish", &[]).unwrap();
+
+        let local = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        db::insert_consolidated(&local, "Run migrations with sqlx", "decision", &[], 0.9).unwrap();
+        let secret = db::insert_consolidated(&local, "Staging password is in 1Password", "convention", &[], 0.9).unwrap();
+        db::set_consolidated_sensitivity(&local, &[secret], Sensitivity::Secret).unwrap();
+
+        // Joining seeds the remote with shareable memories, then pulls the rest
+        let mut replica = Replica::join(&local, Box::new(db::open_consolidated_db(&remote_path).unwrap()), Sensitivity::Internal).unwrap();
+        assert_eq!(db::get_consolidated_count(&local).unwrap(), 3);
+        assert_eq!(remote.memories().unwrap().len(), 2);
+        // Every column but the local sources comes along
+        let pulled = db::get_all_consolidated(&local).unwrap().into_iter().find(|m| m.content.contains("mdbook")).unwrap();
+        assert!(pulled.no_llm);
+        assert_eq!((pulled.created_at.as_str(), pulled.access_count), ("2025-01-02 03:04:05", 4));
+        let skills = db::get_all_skills(&local).unwrap();
+        assert_eq!((skills[0].name.as_str(), skills[0].category.as_deref()), ("release", Some("ops")));
+
+        let sqlx = db::get_all_consolidated(&local).unwrap().into_iter().find(|m| m.content.contains("sqlx")).unwrap();
+        db::remove_consolidated(&local, &[sqlx.id]).unwrap();
+        db::insert_consolidated(&local, "CI caches the cargo registry", "pattern", &[], 0.8).unwrap();
+        db::record_skill_use(&local, skills[0].id).unwrap();
+        db::upsert_skill(&local, "triage", None, "Label, then assign", &[]).unwrap();
+        let pushed = replica.push(&local).unwrap();
+        assert_eq!((pushed.added, pushed.deleted), (1, 1));
+        assert_eq!(replica.push(&local).unwrap().added, 0);
+        let contents: Vec<String> = remote.memories().unwrap().into_iter().map(|e| e.content).collect();
+        assert_eq!(contents, ["Docs are built with mdbook", "CI caches the cargo registry"]);
+        let remote_skills = db::get_all_skills(&remote).unwrap();
+        assert_eq!(remote_skills.len(), 2);
+        assert_eq!(remote_skills.iter().find(|s| s.name == "release").unwrap().use_count, 1);
+
+        // A memory deleted remotely disappears locally on the next pull, while one added locally
+        // since the last push goes up first; the secret stays
+        db::remove_consolidated(&remote, &[1]).unwrap();
+        db::remove_skill(&remote, "release").unwrap();
+        db::insert_consolidated(&local, "Releases are tagged from main", "convention", &[], 0.8).unwrap();
+        replica.pull(&local).unwrap();
+        let local_contents: Vec<String> = db::get_all_consolidated(&local).unwrap().into_iter().map(|m| m.content).collect();
+        assert_eq!(local_contents.len(), 3);
+        assert!(local_contents.iter().any(|c| c.contains("1Password")));
+        assert!(local_contents.iter().any(|c| c.contains("tagged from main")));
+        assert_eq!(remote.memories().unwrap().len(), 2);
+        let names: Vec<String> = db::get_all_skills(&local).unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["triage"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_hrana_cells() {
+        assert_eq!(Libsql::new("libsql://team.turso.io/", None).url, "https://team.turso.io");
+        assert_eq!(cell_text(&json!({ "type": "integer", "value": "42" })), "42");
+        assert_eq!(cell_f64(&json!({ "type": "float", "value": 0.75 })), 0.75);
+        assert_eq!(cell_f64(&json!({ "type": "integer", "value": "1" })), 1.0);
+        assert_eq!(cell_i64(&integer(7)), 7);
+        assert_eq!(cell_opt(&json!({ "type": "null" })), None);
+    }
+}
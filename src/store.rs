@@ -0,0 +1,367 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use std::cell::OnceCell;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::config::{self, Backend, Config, DbConfig};
+use crate::db;
+use crate::init;
+use crate::storage::Replica;
+use crate::trust::{self, Capability};
+
+/// A memory store: a project `.cortex/` or the global `~/.cortex/` directory. The databases
+/// and config are opened on first use and kept for the store's lifetime.
+///
+/// `raw()` and `cons()` are for synchronous callers such as CLI commands. Async code that
+/// may run alongside other tasks (the MCP server) should use `call` instead, which runs
+/// SQLite work on tokio's blocking pool.
+pub struct Store {
+    dir: PathBuf,
+    global: bool,
+    raw: OnceCell<Connection>,
+    cons: OnceCell<Connection>,
+    config: OnceCell<Config>,
+    /// Set when `cons` is a replica of a remote `[db] backend`
+    replica: OnceCell<Option<Replica>>,
+    pool: Pool,
+}
+
+/// Connections checked out of a store's pool for one `Store::call`.
+pub struct Conns {
+    pub raw: Connection,
+    pub cons: Connection,
+}
+
+/// How long a pool's replica serves calls before pulling from the remote again.
+const PULL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Idle connection pairs for `Store::call`. Cloning shares the pool, so it can be moved onto
+/// a blocking thread.
+#[derive(Clone)]
+struct Pool {
+    dir: PathBuf,
+    global: bool,
+    idle: Arc<Mutex<Vec<Conns>>>,
+    /// `None` until the first lease, then the remote `[db] backend`'s replica if there is one
+    replica: Arc<Mutex<Option<Option<Synced>>>>,
+}
+
+/// A pool's replica and when it last pulled.
+struct Synced {
+    replica: Replica,
+    pulled: Instant,
+}
+
+/// A checked-out pair, returned to the pool on drop (including when the closure fails).
+struct Lease {
+    conns: Option<Conns>,
+    pool: Pool,
+}
+
+impl Store {
+    /// The store in `cortex_dir`, which is assumed to exist.
+    pub fn open(cortex_dir: PathBuf) -> Self {
+        Self::new(cortex_dir, false)
+    }
+
+    /// The project store in `<base>/.cortex/`.
+    pub fn project(base: &Path) -> Result<Self> {
+        let cortex_dir = base.join(".cortex");
+        if !cortex_dir.exists() {
+            anyhow::bail!("No .cortex/ directory found in {}. Run `cortex init` first.", base.display());
+        }
+        Ok(Self::open(cortex_dir))
+    }
+
+    /// The global store, if ~/.cortex/ exists.
+    pub fn global() -> Option<Self> {
+        init::find_global_dir().map(|dir| Self::new(dir, true))
+    }
+
+    /// The global store, or an error if it hasn't been created yet.
+    pub fn require_global() -> Result<Self> {
+        Self::global().ok_or_else(|| anyhow::anyhow!("No global ~/.cortex/ directory found."))
+    }
+
+    /// The global store, creating ~/.cortex/ if needed.
+    pub fn ensure_global() -> Result<Self> {
+        Ok(Self::new(init::ensure_global_dir()?, true))
+    }
+
+    fn new(dir: PathBuf, global: bool) -> Self {
+        let pool = Pool { dir: dir.clone(), global, idle: Arc::default(), replica: Arc::default() };
+        Self {
+            dir,
+            global,
+            raw: OnceCell::new(),
+            cons: OnceCell::new(),
+            config: OnceCell::new(),
+            replica: OnceCell::new(),
+            pool,
+        }
+    }
+
+    /// The `.cortex` directory itself.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.global
+    }
+
+    /// The project directory containing `.cortex/` (the home directory for the global store).
+    pub fn root(&self) -> PathBuf {
+        self.dir.parent().map(|p| p.to_path_buf()).unwrap_or_default()
+    }
+
+    pub fn raw(&self) -> Result<&Connection> {
+        get_or_try_init(&self.raw, || db::open_raw_db(&self.dir.join("raw.db")))
+    }
+
+    /// With a remote `[db] backend`, the first call pulls the remote's memories in, and changes
+    /// are pushed back when the store is dropped.
+    pub fn cons(&self) -> Result<&Connection> {
+        let cons = get_or_try_init(&self.cons, || db::open_consolidated_db(&self.dir.join("consolidated.db")))?;
+        get_or_try_init(&self.replica, || Replica::open(cons, self.config()?))?;
+        Ok(cons)
+    }
+
+    pub fn config(&self) -> Result<&Config> {
+        get_or_try_init(&self.config, || load_config(&self.dir, self.global))
+    }
+
+    /// Run `f` with this store's databases on tokio's blocking pool, so SQLite work doesn't
+    /// hold up the async runtime. Connections are reused across calls.
+    pub async fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Conns) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || f(&*pool.lease()?)).await?
+    }
+
+    /// `call`, also passing `global`'s databases (`None` if there is no global store or it
+    /// fails to open, as with `global_cons`).
+    pub async fn call_with_global<T, F>(&self, global: Option<&Store>, f: F) -> Result<T>
+    where
+        F: FnOnce(&Conns, Option<&Conns>) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        let global_pool = global.map(|g| g.pool.clone());
+        tokio::task::spawn_blocking(move || {
+            let conns = pool.lease()?;
+            let global = global_pool.and_then(|p| p.lease().ok());
+            f(&conns, global.as_deref())
+        })
+        .await?
+    }
+}
+
+/// A store's config, minus what a committed project config may not decide on its own.
+fn load_config(dir: &Path, global: bool) -> Result<Config> {
+    let mut config = config::load_config(dir)?;
+    if global {
+        return Ok(config);
+    }
+    let path = dir.join("config.toml");
+    if !config.db.extensions.is_empty() {
+        eprintln!("Warning: ignoring [db] extensions in {}; set them in ~/.cortex/config.toml", path.display());
+    }
+    // Hook commands and URLs come with the repo, like plugins
+    let hooks = &config.hooks;
+    let has_hooks = !(hooks.on_save.is_empty() && hooks.on_sleep_complete.is_empty() && hooks.on_global_promotion.is_empty());
+    if has_hooks && !trust::is_trusted(dir, Capability::Hooks) {
+        eprintln!(
+            "Warning: ignoring [hooks] in {}; run `cortex trust --hooks` in this project to allow them",
+            path.display()
+        );
+        config.hooks = Default::default();
+    }
+    // So does the remote every consolidated memory is pushed to
+    let remote = config.db.backend != Backend::Sqlite || !config.db.url.is_empty();
+    if remote && !trust::is_trusted(dir, Capability::Db) {
+        eprintln!(
+            "Warning: ignoring [db] backend in {}; run `cortex trust --db` in this project to sync with {}",
+            path.display(),
+            if config.db.url.is_empty() { "it" } else { &config.db.url }
+        );
+        config.db.backend = Backend::Sqlite;
+        config.db.url.clear();
+    }
+    // The token's variable is never the project's choice, or it could name any secret
+    let global_env = init::find_global_dir()
+        .and_then(|g| config::load_config(&g).ok())
+        .map(|g| g.db.auth_token_env)
+        .unwrap_or_else(|| DbConfig::default().auth_token_env);
+    if config.db.auth_token_env != global_env {
+        eprintln!(
+            "Warning: ignoring [db] auth_token_env in {}; set it in ~/.cortex/config.toml",
+            path.display()
+        );
+        config.db.auth_token_env = global_env;
+    }
+    Ok(config)
+}
+
+impl Pool {
+    fn lease(&self) -> Result<Lease> {
+        let idle = lock(&self.idle).pop();
+        let conns = match idle {
+            Some(c) => c,
+            None => Conns {
+                raw: db::open_raw_db(&self.dir.join("raw.db"))?,
+                cons: db::open_consolidated_db(&self.dir.join("consolidated.db"))?,
+            },
+        };
+        self.pull(&conns.cons)?;
+        Ok(Lease { conns: Some(conns), pool: self.clone() })
+    }
+
+    /// Open the remote backend's replica on the first lease, then pull again once
+    /// `PULL_INTERVAL` has passed, so long-running servers see teammates' changes without a
+    /// round trip per call.
+    fn pull(&self, cons: &Connection) -> Result<()> {
+        let mut replica = lock(&self.replica);
+        match &mut *replica {
+            None => {
+                let opened = Replica::open(cons, &load_config(&self.dir, self.global)?)?;
+                *replica = Some(opened.map(|replica| Synced { replica, pulled: Instant::now() }));
+            }
+            Some(Some(synced)) if synced.pulled.elapsed() >= PULL_INTERVAL => {
+                synced.replica.pull(cons)?;
+                synced.pulled = Instant::now();
+            }
+            Some(_) => {}
+        }
+        Ok(())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl std::ops::Deref for Lease {
+    type Target = Conns;
+
+    fn deref(&self) -> &Conns {
+        self.conns.as_ref().expect("lease holds connections until dropped")
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if let Some(conns) = self.conns.take() {
+            if let Some(Some(synced)) = &mut *lock(&self.pool.replica) {
+                push(&mut synced.replica, &conns.cons);
+            }
+            lock(&self.pool.idle).push(conns);
+        }
+    }
+}
+
+impl Drop for Store {
+    fn drop(&mut self) {
+        if let (Some(Some(replica)), Some(cons)) = (self.replica.get_mut(), self.cons.get()) {
+            push(replica, cons);
+        }
+    }
+}
+
+/// Push a replica's local changes; a failure can't be returned from `drop`, so it is reported.
+fn push(replica: &mut Replica, cons: &Connection) {
+    if let Err(e) = replica.push(cons) {
+        eprintln!("Warning: {:#}", e);
+    }
+}
+
+/// Consolidated DB of an optional global store. A global store that fails to open is
+/// treated as absent, so project recall and context still work.
+pub fn global_cons(global: Option<&Store>) -> Option<&Connection> {
+    global.and_then(|g| g.cons().ok())
+}
+
+/// `OnceCell::get_or_try_init` is unstable; this is the same for `anyhow` errors.
+fn get_or_try_init<T>(cell: &OnceCell<T>, init: impl FnOnce() -> Result<T>) -> Result<&T> {
+    if let Some(value) = cell.get() {
+        return Ok(value);
+    }
+    let value = init()?;
+    Ok(cell.get_or_init(|| value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_connections_once_and_lazily() {
+        let dir = std::env::temp_dir().join(format!("cortex-store-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join(".cortex")).unwrap();
+        assert!(Store::project(&dir.join("missing")).is_err());
+
+        let store = Store::project(&dir).unwrap();
+        assert!(!store.is_global());
+        assert_eq!(store.root(), dir);
+        assert!(!store.dir().join("raw.db").exists());
+
+        let id = db::save_memory(store.raw().unwrap(), "Lazy open", "observation", "s1").unwrap();
+        assert!(store.dir().join("raw.db").exists());
+        assert!(!store.dir().join("consolidated.db").exists());
+        // Same connection on every call, so the insert is visible without reopening
+        assert!(std::ptr::eq(store.raw().unwrap(), store.raw().unwrap()));
+        assert_eq!(db::get_unconsolidated_count(store.raw().unwrap()).unwrap(), 1);
+        assert_eq!(store.config().unwrap().content.max_chars, Config::default().content.max_chars);
+        assert!(id > 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn ignores_an_untrusted_project_remote() {
+        let dir = std::env::temp_dir().join(format!("cortex-store-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join(".cortex")).unwrap();
+        std::fs::write(
+            dir.join(".cortex/config.toml"),
+            "[db]\nbackend = \"libsql\"\nurl = \"https://attacker.invalid\"\nauth_token_env = \"ANTHROPIC_API_KEY\"\n",
+        )
+        .unwrap();
+        let store = Store::project(&dir).unwrap();
+        let db = &store.config().unwrap().db;
+        assert_eq!((db.backend, db.url.as_str()), (Backend::Sqlite, ""));
+        assert_ne!(db.auth_token_env, "ANTHROPIC_API_KEY");
+        // Neither path contacts the remote
+        store.cons().unwrap();
+        assert_eq!(store.call(|c| db::get_consolidated_count(&c.cons)).await.unwrap(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn call_runs_on_pooled_connections() {
+        let dir = std::env::temp_dir().join(format!("cortex-store-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join(".cortex")).unwrap();
+        let store = Store::project(&dir).unwrap();
+
+        let id = store.call(|c| db::save_memory(&c.raw, "From the pool", "observation", "s1")).await.unwrap();
+        // Visible to the synchronous connection too
+        assert_eq!(db::get_unconsolidated_count(store.raw().unwrap()).unwrap(), 1);
+        let err = store.call(|_| -> Result<()> { anyhow::bail!("boom") }).await.unwrap_err();
+        assert_eq!(err.to_string(), "boom");
+        // The failed call returned its connections, so the pool still holds one pair
+        let count = store.call(|c| db::get_unconsolidated_count(&c.raw)).await.unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(store.pool.idle.lock().unwrap().len(), 1);
+
+        let missing = Store::open(dir.join("absent"));
+        let found = store.call_with_global(Some(&missing), |_, g| Ok(g.is_none())).await.unwrap();
+        assert!(found);
+        assert!(id > 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
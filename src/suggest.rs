@@ -0,0 +1,144 @@
+use anyhow::Result;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::db;
+use crate::query::{self, Tokenizer};
+
+/// Matching rows read per table before grouping; enough to weight frequent titles.
+const CANDIDATES: usize = 200;
+/// Longest title shown for a memory (its first line).
+const TITLE_CHARS: usize = 80;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Suggestion {
+    pub text: String,
+    /// `topic` (an entity name) or `memory` (the first line of a memory)
+    pub kind: &'static str,
+    pub score: f64,
+}
+
+/// Type-ahead completions for `prefix`: entity names and memory titles whose words start
+/// with the typed words, most frequent and most used first. Read-only: access counts are
+/// not bumped, since suggestions are requested on every keystroke.
+pub fn suggest(raw: &Connection, cons: &Connection, prefix: &str, limit: usize) -> Result<Vec<Suggestion>> {
+    let prefix = prefix.trim();
+    if prefix.is_empty() || limit == 0 {
+        return Ok(vec![]);
+    }
+
+    let mut suggestions = topics(raw, prefix)?;
+    let mut titles: HashMap<String, f64> = HashMap::new();
+    for (content, access_count) in matching(raw, "memories_fts", "memories", prefix)?
+        .into_iter()
+        .chain(matching(cons, "consolidated_fts", "consolidated", prefix)?)
+    {
+        if let Some(t) = title(&content) {
+            *titles.entry(t).or_default() += 1.0 + access_count as f64;
+        }
+    }
+    suggestions.extend(titles.into_iter().map(|(text, score)| Suggestion { text, kind: "memory", score }));
+
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.text.cmp(&b.text)));
+    let mut seen = std::collections::HashSet::new();
+    suggestions.retain(|s| seen.insert(s.text.to_lowercase()));
+    suggestions.truncate(limit);
+    Ok(suggestions)
+}
+
+/// Entities with a name (or a word in it) starting with `prefix`, weighted by use and by how
+/// connected they are. Entity tables are small, so a LIKE scan is fine and avoids stemming.
+fn topics(raw: &Connection, prefix: &str) -> Result<Vec<Suggestion>> {
+    let escaped = prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    let mut stmt = raw.prepare(
+        "SELECT e.name, e.access_count,
+                (SELECT COUNT(*) FROM relationships r WHERE r.source_entity_id = e.id OR r.target_entity_id = e.id)
+         FROM entities e
+         WHERE e.name LIKE ?1 || '%' ESCAPE '\\' OR e.name LIKE '% ' || ?1 || '%' ESCAPE '\\'
+         LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![escaped, CANDIDATES as i64], |r| {
+        let name: String = r.get(0)?;
+        let access_count: i64 = r.get(1)?;
+        let links: i64 = r.get(2)?;
+        Ok(Suggestion { text: name, kind: "topic", score: 1.0 + access_count as f64 + links as f64 })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Content and access count of rows in `table` matching every word of `prefix` as a prefix.
+///
+/// Porter indexes stems, so a partly typed word can run past its stem and match nothing
+/// (`consolidat` is not a prefix of the stem `consolid`). For porter, an empty result is
+/// retried with the last word shortened by up to 3 characters.
+fn matching(conn: &Connection, fts: &str, table: &str, prefix: &str) -> Result<Vec<(String, i64)>> {
+    let tokenizer = db::fts_tokenizer(conn, fts)?;
+    let mut input = prefix.to_string();
+    for _ in 0..4 {
+        let q = query::fts_prefix_query(&input, tokenizer);
+        if q.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut stmt = conn.prepare(&format!(
+            "SELECT t.content, t.access_count FROM {fts} f JOIN {table} t ON f.rowid = t.id
+             WHERE {fts} MATCH ?1 ORDER BY f.rank LIMIT ?2"
+        ))?;
+        let rows: Vec<(String, i64)> = stmt
+            .query_map(params![q, CANDIDATES as i64], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        if !rows.is_empty() || tokenizer != Tokenizer::Porter {
+            return Ok(rows);
+        }
+        let last = input.split_whitespace().last().unwrap_or("");
+        if last.chars().count() <= 3 {
+            return Ok(rows);
+        }
+        input.pop();
+    }
+    Ok(vec![])
+}
+
+/// First non-empty line of a memory, cut to `TITLE_CHARS`.
+fn title(content: &str) -> Option<String> {
+    let line = content.lines().map(str::trim).find(|l| !l.is_empty())?;
+    if line.chars().count() <= TITLE_CHARS {
+        return Some(line.to_string());
+    }
+    let cut: String = line.chars().take(TITLE_CHARS - 1).collect();
+    Some(format!("{}…", cut.trim_end()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn suggests_frequent_titles_and_topics() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        for _ in 0..3 {
+            db::save_memory(&raw, "Consolidation runs at session end\nmore detail", "observation", "s1").unwrap();
+        }
+        db::save_memory(&raw, "Consolidated view of the store", "observation", "s1").unwrap();
+        db::save_memory(&raw, "Unrelated note about tokio", "observation", "s1").unwrap();
+        raw.execute("INSERT INTO entities (name, entity_type) VALUES ('consolidation engine', 'concept')", [])
+            .unwrap();
+
+        let out = suggest(&raw, &cons, "consolidat", 10).unwrap();
+        let texts: Vec<&str> = out.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts[0], "Consolidation runs at session end");
+        assert!(texts.contains(&"consolidation engine"));
+        assert!(!texts.iter().any(|t| t.contains("tokio")));
+        assert!(suggest(&raw, &cons, "  ", 10).unwrap().is_empty());
+        assert_eq!(suggest(&raw, &cons, "consolidation end", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn titles_are_first_line_and_bounded() {
+        assert_eq!(title("\n  first line \nsecond").as_deref(), Some("first line"));
+        assert_eq!(title(&"x".repeat(200)).unwrap().chars().count(), TITLE_CHARS);
+        assert_eq!(title("   \n"), None);
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+use crate::config::{Config, TemplateConfig};
+
+/// Built-in templates, overridable by `[templates.<name>]` in config.toml.
+fn builtin_templates() -> BTreeMap<String, TemplateConfig> {
+    let mut templates = BTreeMap::new();
+    templates.insert(
+        "decision".to_string(),
+        TemplateConfig {
+            r#type: "decision".to_string(),
+            sections: vec!["Context".into(), "Options".into(), "Choice".into(), "Consequences".into()],
+        },
+    );
+    templates.insert(
+        "bugfix".to_string(),
+        TemplateConfig {
+            r#type: "bugfix".to_string(),
+            sections: vec!["Symptom".into(), "Root Cause".into(), "Fix".into()],
+        },
+    );
+    templates
+}
+
+/// All templates available to this store: built-ins merged with configured ones.
+pub fn all_templates(config: &Config) -> BTreeMap<String, TemplateConfig> {
+    let mut templates = builtin_templates();
+    templates.extend(config.templates.clone());
+    templates
+}
+
+pub fn find_template(config: &Config, name: &str) -> Result<TemplateConfig> {
+    let templates = all_templates(config);
+    templates.get(name).cloned().ok_or_else(|| {
+        let known: Vec<&str> = templates.keys().map(|k| k.as_str()).collect();
+        anyhow::anyhow!("Unknown template '{}'. Available: {}", name, known.join(", "))
+    })
+}
+
+/// Empty markdown skeleton for the editor.
+pub fn skeleton(template: &TemplateConfig) -> String {
+    template
+        .sections
+        .iter()
+        .map(|s| format!("## {}\n\n", s))
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+/// Render `key=value` fields into the template's markdown sections.
+/// Keys match section names case-insensitively, with `_` or `-` standing in for spaces.
+pub fn render(template: &TemplateConfig, fields: &[String]) -> Result<String> {
+    let mut values: BTreeMap<String, String> = BTreeMap::new();
+    for field in fields {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid --field '{}': expected key=value", field))?;
+        let key = normalize(key);
+        if !template.sections.iter().any(|s| normalize(s) == key) {
+            anyhow::bail!(
+                "Unknown section '{}'. Template sections: {}",
+                key,
+                template.sections.join(", ")
+            );
+        }
+        values.insert(key, value.trim().to_string());
+    }
+
+    let out: Vec<String> = template
+        .sections
+        .iter()
+        .filter_map(|s| values.get(&normalize(s)).map(|v| format!("## {}\n{}", s, v)))
+        .collect();
+    Ok(out.join("\n\n"))
+}
+
+/// One-line-per-template hint for the consolidation prompt.
+pub fn consolidation_hint(config: &Config) -> String {
+    all_templates(config)
+        .values()
+        .map(|t| format!("- `{}` observations may use markdown sections: {}", t.r#type, t.sections.join(", ")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase().replace(['_', '-'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_fields_into_sections() {
+        let mut config = Config::default();
+        config.templates.insert(
+            "incident".into(),
+            TemplateConfig { r#type: "bugfix".into(), sections: vec!["Impact".into(), "Follow-up Items".into()] },
+        );
+        let bugfix = find_template(&config, "bugfix").unwrap();
+        assert_eq!(skeleton(&bugfix), "## Symptom\n\n## Root Cause\n\n## Fix");
+        let fields = ["fix=Pin the TLS crate ".to_string(), "ROOT_CAUSE=Upstream bump".to_string()];
+        assert_eq!(render(&bugfix, &fields).unwrap(), "## Root Cause\nUpstream bump\n\n## Fix\nPin the TLS crate");
+
+        let incident = find_template(&config, "incident").unwrap();
+        assert_eq!(render(&incident, &["follow-up_items=Add an alert".into()]).unwrap(), "## Follow-up Items\nAdd an alert");
+        assert!(render(&incident, &["impact".into()]).is_err());
+        assert!(render(&incident, &["cause=x".into()]).unwrap_err().to_string().contains("Impact, Follow-up Items"));
+        let unknown = find_template(&config, "retro").unwrap_err().to_string();
+        assert!(unknown.contains("bugfix, decision, incident"));
+        assert!(consolidation_hint(&config).contains("- `bugfix` observations may use markdown sections: Impact"));
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::Result;
+use chrono::{Datelike, Duration, NaiveDate};
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Widest bar in the per-week table.
+const BAR_WIDTH: i64 = 30;
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// One week (Monday to Sunday, UTC) of activity.
+#[derive(Debug, Clone, Serialize)]
+pub struct Week {
+    /// Monday, `YYYY-MM-DD`
+    pub start: String,
+    pub saves: i64,
+    /// Distinct sessions that saved anything
+    pub sessions: usize,
+    pub sleeps: i64,
+    pub dreams: i64,
+    /// Saves per day, Monday first; `None` for days still to come
+    pub days: Vec<Option<i64>>,
+}
+
+/// The last `weeks` weeks up to and including the one containing `today`, oldest first.
+/// Saves are raw memories still in raw.db; sleeps and dreams come from the activity log,
+/// which quick sleep, heuristic sleep, and dream write to.
+pub fn build(raw: &Connection, cons: &Connection, weeks: u32, today: NaiveDate) -> Result<Vec<Week>> {
+    let weeks = weeks.max(1);
+    let first = today - Duration::days(i64::from(today.weekday().num_days_from_monday()) + 7 * i64::from(weeks - 1));
+    let mut out: Vec<Week> = (0..weeks)
+        .map(|w| {
+            let start = first + Duration::days(7 * i64::from(w));
+            Week {
+                start: start.format("%Y-%m-%d").to_string(),
+                saves: 0,
+                sessions: 0,
+                sleeps: 0,
+                dreams: 0,
+                days: (0..7).map(|d| (start + Duration::days(d) <= today).then_some(0)).collect(),
+            }
+        })
+        .collect();
+    let slot = |date: &str| -> Option<(usize, usize)> {
+        let offset = (NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()? - first).num_days();
+        (0..7 * i64::from(weeks)).contains(&offset).then_some(((offset / 7) as usize, (offset % 7) as usize))
+    };
+    let since = first.format("%Y-%m-%d").to_string();
+
+    let mut sessions: Vec<HashSet<String>> = vec![HashSet::new(); out.len()];
+    let mut stmt = raw.prepare("SELECT date(created_at), session_id FROM memories WHERE created_at >= ?1")?;
+    let rows = stmt.query_map(params![since], |r| Ok((r.get::<_, String>(0)?, r.get::<_, Option<String>>(1)?)))?;
+    for row in rows {
+        let (date, session) = row?;
+        if let Some((w, d)) = slot(&date) {
+            out[w].saves += 1;
+            if let Some(count) = out[w].days[d].as_mut() {
+                *count += 1;
+            }
+            if let Some(session) = session {
+                sessions[w].insert(session);
+            }
+        }
+    }
+    for (week, s) in out.iter_mut().zip(&sessions) {
+        week.sessions = s.len();
+    }
+
+    let mut stmt = cons.prepare("SELECT date(created_at), kind FROM activity WHERE created_at >= ?1")?;
+    let rows = stmt.query_map(params![since], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?;
+    for row in rows {
+        let (date, kind) = row?;
+        if let Some((w, _)) = slot(&date) {
+            match kind.as_str() {
+                "sleep" => out[w].sleeps += 1,
+                "dream" => out[w].dreams += 1,
+                _ => {}
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// ASCII heatmap of saves per day (one column per week), then a row per week with counts
+/// and a bar, then how many weeks had no saves at all.
+pub fn render(weeks: &[Week]) -> String {
+    let mut out = format!(
+        "Saves per day, {} weeks from {} (one column per week, oldest first)\n",
+        weeks.len(),
+        weeks.first().map(|w| w.start.as_str()).unwrap_or("")
+    );
+    for (d, name) in WEEKDAYS.iter().enumerate() {
+        let cells: Vec<&str> = weeks.iter().map(|w| w.days[d].map(shade).unwrap_or(" ")).collect();
+        out.push_str(&format!("  {}  {}\n", name, cells.join(" ").trim_end()));
+    }
+    out.push_str("       . none  - 1  + 2-3  * 4-7  # 8+\n\n");
+
+    let max = weeks.iter().map(|w| w.saves).max().unwrap_or(0).max(1);
+    out.push_str(&format!("  {:<10}  {:>5}  {:>8}  {:>6}  {:>6}\n", "WEEK OF", "SAVES", "SESSIONS", "SLEEPS", "DREAMS"));
+    for w in weeks {
+        // Any save gets at least one mark
+        let bar = "#".repeat(((w.saves * BAR_WIDTH + max - 1) / max) as usize);
+        out.push_str(
+            format!("  {:<10}  {:>5}  {:>8}  {:>6}  {:>6}  {}", w.start, w.saves, w.sessions, w.sleeps, w.dreams, bar)
+                .trim_end(),
+        );
+        out.push('\n');
+    }
+    let idle = weeks.iter().filter(|w| w.saves == 0).count();
+    if idle > 0 {
+        out.push_str(&format!("\n{} of {} weeks had no saves.\n", idle, weeks.len()));
+    }
+    out
+}
+
+fn shade(saves: i64) -> &'static str {
+    match saves {
+        0 => ".",
+        1 => "-",
+        2..=3 => "+",
+        4..=7 => "*",
+        _ => "#",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use std::path::Path;
+
+    #[test]
+    fn buckets_saves_sleeps_and_dreams_by_week() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        // Wednesday
+        let today = NaiveDate::from_ymd_opt(2025, 3, 12).unwrap();
+        let saves = [
+            ("s1", "2025-03-10 09:00:00"),
+            ("s2", "2025-03-10 17:00:00"),
+            ("s2", "2025-03-12 08:00:00"),
+            ("s3", "2025-02-25 12:00:00"),
+            ("s4", "2025-01-01 12:00:00"),
+        ];
+        for (session, at) in saves {
+            let id = db::save_memory(&raw, "note", "observation", session).unwrap();
+            raw.execute("UPDATE memories SET created_at = ?1 WHERE id = ?2", params![at, id]).unwrap();
+        }
+        cons.execute(
+            "INSERT INTO activity (kind, created_at) VALUES ('sleep', '2025-03-11 10:00:00'), ('dream', '2025-02-26 10:00:00')",
+            [],
+        )
+        .unwrap();
+
+        let weeks = build(&raw, &cons, 3, today).unwrap();
+        assert_eq!(weeks.iter().map(|w| w.start.as_str()).collect::<Vec<_>>(), vec!["2025-02-24", "2025-03-03", "2025-03-10"]);
+        assert_eq!((weeks[0].saves, weeks[0].sessions, weeks[0].dreams), (1, 1, 1));
+        assert_eq!((weeks[2].saves, weeks[2].sessions, weeks[2].sleeps), (3, 2, 1));
+        assert_eq!(weeks[2].days, vec![Some(2), Some(0), Some(1), None, None, None, None]);
+
+        let text = render(&weeks);
+        assert!(text.contains("  Mon  . . +\n"), "{}", text);
+        assert!(text.contains("  Tue  - . .\n"), "{}", text);
+        assert!(text.contains("  Thu  . .\n"), "{}", text);
+        assert!(text.contains("1 of 3 weeks had no saves."), "{}", text);
+    }
+}
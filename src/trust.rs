@@ -0,0 +1,120 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::init;
+
+/// Projects allowed to run their own code, kept next to the global store so a cloned repo
+/// can never grant itself trust.
+const TRUST_FILE: &str = "trusted.json";
+
+/// Project-controlled things cortex acts on by itself: `.cortex/plugins/` executables,
+/// `[hooks]` commands, and the `[db]` remote memories are sent to, in a project's config.toml.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Plugins,
+    Hooks,
+    Db,
+}
+
+impl Capability {
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Plugins => "plugins",
+            Capability::Hooks => "hooks",
+            Capability::Db => "db",
+        }
+    }
+}
+
+/// `.cortex` directories the user trusted with `cortex trust --plugins`, `--hooks` or `--db`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Trusted {
+    #[serde(default)]
+    plugins: Vec<PathBuf>,
+    #[serde(default)]
+    hooks: Vec<PathBuf>,
+    #[serde(default)]
+    db: Vec<PathBuf>,
+}
+
+impl Trusted {
+    fn list(&mut self, capability: Capability) -> &mut Vec<PathBuf> {
+        match capability {
+            Capability::Plugins => &mut self.plugins,
+            Capability::Hooks => &mut self.hooks,
+            Capability::Db => &mut self.db,
+        }
+    }
+}
+
+/// `~/.cortex/trusted.json` (or under `CORTEX_GLOBAL_DIR`).
+pub fn default_path() -> Option<PathBuf> {
+    init::global_dir_path().map(|d| d.join(TRUST_FILE))
+}
+
+fn canonical(dir: &Path) -> PathBuf {
+    dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf())
+}
+
+fn load(path: &Path) -> Trusted {
+    std::fs::read_to_string(path).ok().and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default()
+}
+
+/// Whether the store in `cortex_dir` may run its own `capability`. The global store always
+/// may: it lives in the user's home, not in a repo.
+pub fn is_trusted(cortex_dir: &Path, capability: Capability) -> bool {
+    if init::global_dir_path().is_some_and(|g| canonical(&g) == canonical(cortex_dir)) {
+        return true;
+    }
+    default_path().is_some_and(|path| is_trusted_in(&path, cortex_dir, capability))
+}
+
+fn is_trusted_in(path: &Path, cortex_dir: &Path, capability: Capability) -> bool {
+    load(path).list(capability).contains(&canonical(cortex_dir))
+}
+
+/// Grant or revoke `capability` for the store in `cortex_dir` in the trust file at `path`.
+/// Returns whether anything changed.
+pub fn set(path: &Path, cortex_dir: &Path, capability: Capability, trusted: bool) -> Result<bool> {
+    let dir = canonical(cortex_dir);
+    let mut all = load(path);
+    let list = all.list(capability);
+    let listed = list.contains(&dir);
+    match (trusted, listed) {
+        (true, false) => list.push(dir),
+        (false, true) => list.retain(|d| *d != dir),
+        _ => return Ok(false),
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&all)?)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trust_is_per_store_and_capability() {
+        let dir = std::env::temp_dir().join(format!("cortex-trust-{}", uuid::Uuid::new_v4()));
+        let store = dir.join("repo/.cortex");
+        std::fs::create_dir_all(&store).unwrap();
+        let file = dir.join("global/trusted.json");
+
+        assert!(!is_trusted_in(&file, &store, Capability::Plugins));
+        assert!(set(&file, &store, Capability::Plugins, true).unwrap());
+        assert!(!set(&file, &store, Capability::Plugins, true).unwrap(), "already trusted");
+        assert!(is_trusted_in(&file, &store, Capability::Plugins));
+        assert!(!is_trusted_in(&file, &store, Capability::Hooks), "plugins don't imply hooks");
+        assert!(!is_trusted_in(&file, &store, Capability::Db), "nor a remote");
+        assert!(set(&file, &store, Capability::Db, true).unwrap());
+        assert!(is_trusted_in(&file, &store, Capability::Db));
+        assert!(!is_trusted_in(&file, &dir.join("other/.cortex"), Capability::Plugins));
+        assert!(set(&file, &store, Capability::Plugins, false).unwrap());
+        assert!(!is_trusted_in(&file, &store, Capability::Plugins));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Latest-release endpoint of the GitHub repo the release workflow publishes to.
+const RELEASES_URL: &str = "https://api.github.com/repos/thrashr888/cortex/releases/latest";
+
+/// Overrides `RELEASES_URL`, for mirrors and GitHub Enterprise.
+const RELEASES_URL_ENV: &str = "CORTEX_RELEASES_URL";
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Result<&str> {
+        self.assets
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| a.browser_download_url.as_str())
+            .with_context(|| format!("Release {} has no {}", self.tag_name, name))
+    }
+}
+
+/// What `self_update` found or did.
+#[derive(Debug)]
+pub enum Outcome {
+    UpToDate(String),
+    /// A newer release exists; `--check` doesn't install it
+    Available(String),
+    Updated { from: String, to: String },
+}
+
+/// The release archive for this platform, as named by the release workflow.
+pub fn asset_name() -> Result<String> {
+    let os = match std::env::consts::OS {
+        "linux" => "linux",
+        "macos" => "darwin",
+        other => anyhow::bail!("No release binaries for {}; build from source instead", other),
+    };
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        other => anyhow::bail!("No release binaries for {}; build from source instead", other),
+    };
+    Ok(format!("cortex-{}-{}.tar.gz", os, arch))
+}
+
+/// `v1.2.3` or `1.2.3`; pre-release and build suffixes are ignored.
+pub(crate) fn parse_version(s: &str) -> Option<(u64, u64, u64)> {
+    let core = s.trim_start_matches('v').split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|p| p.parse().ok());
+    Some((parts.next()??, parts.next().unwrap_or(Some(0))?, parts.next().unwrap_or(Some(0))?))
+}
+
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    matches!((parse_version(latest), parse_version(current)), (Some(l), Some(c)) if l > c)
+}
+
+/// Check `data` against a `shasum -a 256` line (`<hex>  <file>`).
+fn verify(data: &[u8], checksum_file: &str) -> Result<()> {
+    let expected = checksum_file.split_whitespace().next().context("Empty checksum file")?;
+    let actual = format!("{:x}", Sha256::digest(data));
+    if !actual.eq_ignore_ascii_case(expected) {
+        anyhow::bail!("Checksum mismatch: expected {}, downloaded file has {}", expected, actual);
+    }
+    Ok(())
+}
+
+/// How the binary at `exe` is managed, if by a package manager that should update it instead.
+fn managed_by(exe: &Path) -> Option<&'static str> {
+    let path = exe.to_string_lossy();
+    if path.contains("/Cellar/") || path.contains("/homebrew/") || path.contains("/linuxbrew/") {
+        Some("brew upgrade cortex")
+    } else if path.contains("/.cargo/bin/") {
+        Some("cargo install cortex --force")
+    } else {
+        None
+    }
+}
+
+async fn get(client: &reqwest::Client, url: &str) -> Result<reqwest::Response> {
+    let resp = client
+        .get(url)
+        .header("User-Agent", concat!("cortex/", env!("CARGO_PKG_VERSION")))
+        .timeout(TIMEOUT)
+        .send()
+        .await
+        .with_context(|| format!("Failed to fetch {}", url))?;
+    if !resp.status().is_success() {
+        anyhow::bail!("{} returned {}", url, resp.status());
+    }
+    Ok(resp)
+}
+
+pub async fn latest(client: &reqwest::Client) -> Result<Release> {
+    let url = std::env::var(RELEASES_URL_ENV).unwrap_or_else(|_| RELEASES_URL.to_string());
+    Ok(get(client, &url).await?.json().await?)
+}
+
+/// Unpack the `cortex` binary from a release archive into `dir`. Uses the system `tar`, which
+/// macOS and Linux both ship.
+fn unpack(archive: &[u8], dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("cortex.tar.gz");
+    std::fs::write(&path, archive)?;
+    let status = std::process::Command::new("tar")
+        .args(["-xzf", &path.to_string_lossy(), "-C", &dir.to_string_lossy(), "cortex"])
+        .status()
+        .context("Failed to run tar")?;
+    if !status.success() {
+        anyhow::bail!("Couldn't unpack the cortex binary from the release archive");
+    }
+    Ok(dir.join("cortex"))
+}
+
+/// Put `new` in place of `exe`: copy it next to `exe`, then rename over it, so the swap is
+/// atomic and a failed update leaves the old binary working.
+fn replace(exe: &Path, new: &Path) -> Result<()> {
+    let dir = exe.parent().context("The cortex binary has no parent directory")?;
+    let staged = dir.join(format!(".cortex-update-{}", std::process::id()));
+    std::fs::copy(new, &staged).with_context(|| format!("Can't write to {} (try sudo?)", dir.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))?;
+    }
+    std::fs::rename(&staged, exe).inspect_err(|_| {
+        let _ = std::fs::remove_file(&staged);
+    })?;
+    Ok(())
+}
+
+/// Update the running binary to the latest GitHub release, after checking the archive against
+/// the release's published SHA-256. With `check_only`, just report whether one is available.
+pub async fn self_update(check_only: bool) -> Result<Outcome> {
+    let current = env!("CARGO_PKG_VERSION").to_string();
+    let client = reqwest::Client::new();
+    let release = latest(&client).await?;
+    if !is_newer(&release.tag_name, &current) {
+        return Ok(Outcome::UpToDate(current));
+    }
+    if check_only {
+        return Ok(Outcome::Available(release.tag_name));
+    }
+
+    let exe = std::env::current_exe()?.canonicalize()?;
+    if let Some(command) = managed_by(&exe) {
+        anyhow::bail!("{} is managed by a package manager; run `{}` instead", exe.display(), command);
+    }
+    let name = asset_name()?;
+    let archive = get(&client, release.asset(&name)?).await?.bytes().await?;
+    let checksum = get(&client, release.asset(&format!("{}.sha256", name))?).await?.text().await?;
+    verify(&archive, &checksum)?;
+
+    let scratch = std::env::temp_dir().join(format!("cortex-update-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&scratch)?;
+    let result = unpack(&archive, &scratch).and_then(|new| replace(&exe, &new));
+    let _ = std::fs::remove_dir_all(&scratch);
+    result?;
+    Ok(Outcome::Updated { from: current, to: release.tag_name })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_versions_and_checksums() {
+        assert!(is_newer("v0.5.0", "0.4.0"));
+        assert!(is_newer("v0.4.10", "0.4.9"));
+        assert!(!is_newer("v0.4.0", "0.4.0"));
+        assert!(!is_newer("v0.3.9-rc1", "0.4.0"));
+        assert!(!is_newer("nightly", "0.4.0"));
+
+        let sum = format!("{:x}  cortex-linux-amd64.tar.gz\n", Sha256::digest(b"archive"));
+        assert!(verify(b"archive", &sum).is_ok());
+        assert!(verify(b"tampered", &sum).is_err());
+        assert_eq!(managed_by(Path::new("/opt/homebrew/Cellar/cortex/0.4.0/bin/cortex")), Some("brew upgrade cortex"));
+        assert_eq!(managed_by(Path::new("/usr/local/bin/cortex")), None);
+    }
+}
@@ -1,31 +1,175 @@
 use anyhow::Result;
 use rusqlite::Connection;
 
+use crate::antipattern;
 use crate::config::Config;
 use crate::context;
 use crate::db;
+use crate::lock::LockBusy;
 use crate::sleep;
 
+/// Session start: reindex if needed, catch up on a backlog of at least `[wake] min_backlog`
+/// (within `[wake] max_seconds`, or in a detached `cortex sleep` with `background`), then
+/// return context, ending with plugin `sections` within `[context] max_tokens`.
+#[allow(clippy::too_many_arguments)]
 pub async fn wake(
     raw_conn: &Connection,
     cons_conn: &Connection,
     config: &Config,
     cortex_dir: &std::path::Path,
     global_cons_conn: Option<&Connection>,
+    sections: &str,
+    read_only: bool,
+    background: bool,
 ) -> Result<String> {
-    let uncons = db::get_unconsolidated_count(raw_conn)?;
+    // Bring the indexes in line with `[search] tokenizer`, e.g. a fresh raw.db in a new clone
+    if !read_only {
+        let tokenizer = config.search.tokenizer;
+        let mut rebuilt = db::set_fts_tokenizer(raw_conn, tokenizer)?;
+        rebuilt.extend(db::set_fts_tokenizer(cons_conn, tokenizer)?);
+        if !rebuilt.is_empty() {
+            eprintln!("Reindexed {} with the {} tokenizer.", rebuilt.join(", "), tokenizer);
+        }
+    }
 
-    if uncons > 0 {
-        eprintln!("Found {} unconsolidated memories, running catch-up...", uncons);
-        // Try quick sleep, fall back to micro if no API key
-        match sleep::quick_sleep(raw_conn, cons_conn, config, cortex_dir).await {
-            Ok(_) => eprintln!("Catch-up consolidation complete."),
-            Err(e) => {
-                eprintln!("Quick sleep failed ({}), running micro sleep...", e);
-                sleep::micro_sleep(raw_conn, config)?;
+    // Read-only mode serves context as-is and leaves the backlog for a writable run
+    if !read_only {
+        let waiting = db::get_unconsolidated_count(raw_conn)?;
+        if waiting > 0 && waiting < config.wake.min_backlog {
+            eprintln!("{} unconsolidated memories; catch-up waits for {} ([wake] min_backlog).", waiting, config.wake.min_backlog);
+        } else if waiting > 0 && background {
+            spawn_sleep(cortex_dir)?;
+            eprintln!("Consolidating {} memories in the background.", waiting);
+        } else if config.wake.max_seconds > 0 {
+            let budget = std::time::Duration::from_secs(config.wake.max_seconds);
+            if tokio::time::timeout(budget, catch_up(raw_conn, cons_conn, config, cortex_dir)).await.is_err() {
+                eprintln!("Catch-up passed {}s ([wake] max_seconds); the next sleep picks it up.", config.wake.max_seconds);
             }
+        } else {
+            catch_up(raw_conn, cons_conn, config, cortex_dir).await?;
+        }
+    }
+
+    let settings = context::reserve(&config.context, sections);
+    let mut ctx =
+        context::format_context(cons_conn, raw_conn, global_cons_conn, context::ContextFormat::Full, None, 20, &config.recall, None, &settings, None)?;
+    ctx.push_str(sections);
+    Ok(ctx)
+}
+
+/// Consolidate any unconsolidated memories: quick sleep, or micro sleep if that fails (e.g.
+/// no API key). Skipped while another consolidation holds the store's lock. Returns how many
+/// memories were waiting.
+pub async fn catch_up(
+    raw_conn: &Connection,
+    cons_conn: &Connection,
+    config: &Config,
+    cortex_dir: &std::path::Path,
+) -> Result<i64> {
+    let uncons = db::get_unconsolidated_count(raw_conn)?;
+    if uncons == 0 {
+        return Ok(0);
+    }
+    eprintln!("Found {} unconsolidated memories, running catch-up...", uncons);
+    match sleep::quick_sleep(raw_conn, cons_conn, config, cortex_dir, false).await {
+        Ok(_) => eprintln!("Catch-up consolidation complete."),
+        Err(e) if e.is::<LockBusy>() => eprintln!("Skipping catch-up: {}", e),
+        Err(e) => {
+            eprintln!("Quick sleep failed ({}), running micro sleep...", e);
+            sleep::micro_sleep(raw_conn, config, cortex_dir)?;
         }
     }
+    Ok(uncons)
+}
+
+/// Start `cortex sleep` on the store at `cortex_dir` without waiting for it. It runs in its own
+/// process group, so a hook runner that kills wake's group on exit leaves it alone.
+fn spawn_sleep(cortex_dir: &std::path::Path) -> Result<()> {
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .arg("sleep")
+        .env(crate::init::DIR_ENV, cortex_dir)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    command.spawn()?;
+    Ok(())
+}
 
-    context::format_context(cons_conn, raw_conn, global_cons_conn, false, None, 20)
+/// A shell `cortex wake --hook` writes for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl std::str::FromStr for HookShell {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "bash" => Ok(HookShell::Bash),
+            "zsh" => Ok(HookShell::Zsh),
+            "fish" => Ok(HookShell::Fish),
+            _ => anyhow::bail!("Unknown shell '{}' (expected bash, zsh, or fish)", s),
+        }
+    }
+}
+
+/// The line that sets `name` to `value` in `shell`'s environment. `value` is a session ID,
+/// which never needs quoting.
+pub fn export_line(shell: HookShell, name: &str, value: &str) -> String {
+    match shell {
+        HookShell::Bash | HookShell::Zsh => format!("export {}={}", name, value),
+        HookShell::Fish => format!("set -gx {} {}", name, value),
+    }
+}
+
+/// `cortex wake --hook`: two or three lines on the state of the store, from SQL alone so a new
+/// terminal doesn't wait on consolidation or an LLM.
+pub fn hook_summary(raw_conn: &Connection, cons_conn: &Connection, project: &str) -> Result<String> {
+    let stats = db::get_stats(raw_conn, cons_conn)?;
+    let mut out = format!(
+        "cortex: {} memories, {} consolidated, {} skills in {}\n",
+        stats.raw_count, stats.consolidated_count, stats.skill_count, project
+    );
+    match (stats.unconsolidated_count, stats.last_sleep.as_deref()) {
+        (0, Some(last)) => out.push_str(&format!("Last sleep {}\n", last.get(..10).unwrap_or(last))),
+        (0, None) => out.push_str("Nothing saved yet; agents save with `cortex save` or cortex_save\n"),
+        (waiting, _) => out.push_str(&format!("{} memories waiting for `cortex sleep`\n", waiting)),
+    }
+    let avoid = antipattern::load(raw_conn, cons_conn, None)?;
+    if !avoid.is_empty() {
+        out.push_str(&format!("{} rules to avoid; `cortex context` lists them\n", avoid.len()));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn hook_summarizes_and_exports() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        db::save_memory(&raw, "Deploys go through staging", "decision", "s1").unwrap();
+        db::insert_consolidated(&cons, "Never force-push main", antipattern::TYPE, &[], 0.9).unwrap();
+        let summary = hook_summary(&raw, &cons, "api").unwrap();
+        assert_eq!(
+            summary,
+            "cortex: 1 memories, 1 consolidated, 0 skills in api\n1 memories waiting for `cortex sleep`\n1 rules to avoid; `cortex context` lists them\n"
+        );
+
+        assert_eq!(export_line("zsh".parse().unwrap(), "CORTEX_SESSION_ID", "abc"), "export CORTEX_SESSION_ID=abc");
+        assert_eq!(export_line("fish".parse().unwrap(), "CORTEX_SESSION_ID", "abc"), "set -gx CORTEX_SESSION_ID abc");
+        assert!("tcsh".parse::<HookShell>().is_err());
+    }
 }
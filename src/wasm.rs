@@ -0,0 +1,120 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use wasmtime::{Config as EngineConfig, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// Instructions a single `transform` call may execute before it is aborted.
+const FUEL_PER_CALL: u64 = 500_000_000;
+/// Linear memory cap per call.
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+/// A `.wasm` save-time transform.
+///
+/// Modules import nothing (no WASI, so no filesystem, network, or clock) and export:
+/// - `memory`
+/// - `alloc(len: i32) -> i32`: reserve `len` bytes for the input
+/// - `transform(ptr: i32, len: i32) -> i64`: read the input JSON `{"content", "type"}` and
+///   return the output JSON's location packed as `(ptr << 32) | len`
+pub struct WasmTransform {
+    engine: Engine,
+    module: Module,
+}
+
+struct State {
+    limits: StoreLimits,
+}
+
+impl WasmTransform {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut config = EngineConfig::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+        let module = Module::from_file(&engine, path)?;
+
+        if let Some(import) = module.imports().next() {
+            anyhow::bail!("imports `{}::{}`; transforms must be self-contained", import.module(), import.name());
+        }
+        for export in ["memory", "alloc", "transform"] {
+            if module.get_export(export).is_none() {
+                anyhow::bail!("missing export `{}`", export);
+            }
+        }
+        Ok(Self { engine, module })
+    }
+
+    /// Run the transform on one memory. Each call gets a fresh instance.
+    pub fn transform(&self, content: &str, mem_type: &str) -> Result<(String, String)> {
+        let mut store = Store::new(
+            &self.engine,
+            State {
+                limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).build(),
+            },
+        );
+        store.limiter(|s| &mut s.limits);
+        store.set_fuel(FUEL_PER_CALL)?;
+
+        let instance = Instance::new(&mut store, &self.module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .context("`memory` is not a linear memory")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let transform = instance.get_typed_func::<(i32, i32), i64>(&mut store, "transform")?;
+
+        let input = serde_json::json!({ "content": content, "type": mem_type }).to_string();
+        let len = i32::try_from(input.len()).context("memory too large for a WASM transform")?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as u32 as usize, input.as_bytes())?;
+
+        let packed = transform.call(&mut store, (ptr, len))? as u64;
+        let (out_ptr, out_len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        // Read in place: the length comes from the guest, so it is checked before anything is copied
+        let out = out_ptr
+            .checked_add(out_len)
+            .and_then(|end| memory.data(&store).get(out_ptr..end))
+            .context("transform returned an out-of-bounds result")?;
+
+        let output: serde_json::Value = serde_json::from_slice(out).context("transform output is not valid JSON")?;
+        if let Some(err) = output.get("error").and_then(|e| e.as_str()) {
+            anyhow::bail!("{}", err);
+        }
+        let content = output.get("content").and_then(|v| v.as_str()).unwrap_or(content);
+        let mem_type = output.get("type").and_then(|v| v.as_str()).unwrap_or(mem_type);
+        Ok((content.to_string(), mem_type.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A transform whose `transform` body is `body`; the memory holds `{"content":"[redacted]"}`
+    /// at offset 0 (24 bytes).
+    fn module(body: &str) -> Result<(String, String)> {
+        let wat = format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (data (i32.const 0) "{{\"content\":\"[redacted]\"}}")
+                (func (export "alloc") (param i32) (result i32) i32.const 1024)
+                (func (export "transform") (param i32 i32) (result i64) {}))"#,
+            body
+        );
+        let path = std::env::temp_dir().join(format!("cortex-wasm-{}.wasm", uuid::Uuid::new_v4()));
+        std::fs::write(&path, wat::parse_str(&wat).unwrap()).unwrap();
+        let transform = WasmTransform::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        transform?.transform("token=abc123", "observation")
+    }
+
+    #[test]
+    fn runs_transforms_within_bounds_and_fuel() {
+        assert_eq!(module("i64.const 24").unwrap(), ("[redacted]".to_string(), "observation".to_string()));
+
+        // A length far past the 64 KiB of guest memory is refused without allocating it
+        let err = module("i64.const 0xffffffff").unwrap_err();
+        assert!(err.to_string().contains("out-of-bounds"), "{:#}", err);
+        let err = module("i64.const 0x0000fff0_00000020").unwrap_err();
+        assert!(err.to_string().contains("out-of-bounds"), "{:#}", err);
+
+        let err = module("(loop (br 0)) i64.const 0").unwrap_err();
+        assert!(format!("{:?}", err).contains("fuel"), "{:?}", err);
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::audit::{self, Manifest};
+use crate::config::Config;
+use crate::db;
+use crate::llm;
+use crate::models::{ConsolidatedMemory, Memory};
+
+#[derive(Debug, Serialize)]
+pub struct SourceMemory {
+    #[serde(flatten)]
+    pub memory: Memory,
+    /// Set when micro sleep has archived the memory out of raw search
+    pub archived_at: Option<String>,
+}
+
+/// Where a consolidated memory came from (`cortex why`).
+#[derive(Debug, Serialize)]
+pub struct Provenance {
+    pub consolidated: ConsolidatedMemory,
+    /// For dream insights, which are built from other consolidated memories: those memories
+    pub derived_from: Vec<ConsolidatedMemory>,
+    /// Raw memories the entry (or the entries it derives from) was consolidated from
+    pub sources: Vec<SourceMemory>,
+    /// Source IDs no longer in either store
+    pub missing: Vec<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+}
+
+/// Walk a consolidated memory's `source_ids` back to raw memories, including archived ones.
+/// Dream insights cite consolidated memories, so their sources are followed one level further.
+pub fn trace(raw: &Connection, cons: &Connection, id: i64) -> Result<Provenance> {
+    let consolidated =
+        db::get_consolidated(cons, id)?.ok_or_else(|| anyhow::anyhow!("Consolidated memory #{} not found.", id))?;
+
+    let mut missing = Vec::new();
+    let mut derived_from = Vec::new();
+    let mut raw_ids = Vec::new();
+    if consolidated.r#type == "insight" {
+        for &cid in &consolidated.source_ids {
+            match db::get_consolidated(cons, cid)? {
+                Some(c) => {
+                    raw_ids.extend(&c.source_ids);
+                    derived_from.push(c);
+                }
+                None => missing.push(cid),
+            }
+        }
+    } else {
+        raw_ids.extend(&consolidated.source_ids);
+    }
+    raw_ids.sort_unstable();
+    raw_ids.dedup();
+
+    let sources: Vec<SourceMemory> = db::get_source_memories(raw, &raw_ids)?
+        .into_iter()
+        .map(|(memory, archived_at)| SourceMemory { memory, archived_at })
+        .collect();
+    missing.extend(raw_ids.iter().filter(|id| !sources.iter().any(|s| s.memory.id == **id)));
+    Ok(Provenance { consolidated, derived_from, sources, missing, reasoning: None })
+}
+
+/// Ask the LLM to reconstruct how the sources led to the consolidated memory.
+pub async fn reason(raw: &Connection, provenance: &mut Provenance, config: &Config) -> Result<()> {
+    if provenance.consolidated.no_llm {
+        anyhow::bail!("Consolidated #{} was built from --no-llm memories and is not sent to the LLM.", provenance.consolidated.id);
+    }
+    if provenance.sources.is_empty() {
+        anyhow::bail!("No source memories left to reason from.");
+    }
+    let sent: Vec<&SourceMemory> = provenance.sources.iter().filter(|s| !s.memory.no_llm).collect();
+    let mut memories = audit::consolidated_keys([provenance.consolidated.id]);
+    memories.extend(audit::raw_keys(sent.iter().map(|s| s.memory.id)));
+    let sources = sent
+        .iter()
+        .map(|s| {
+            format!(
+                "#{} ({}, {}, session {}): {}",
+                s.memory.id,
+                s.memory.r#type,
+                s.memory.created_at,
+                s.memory.session_id.as_deref().unwrap_or("-"),
+                s.memory.content
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let call = llm::reconstruct_reasoning(&provenance.consolidated.content, &sources, config);
+    provenance.reasoning = Some(llm::audited(Manifest::new(raw, "why", memories), call).await?);
+    Ok(())
+}
+
+/// Human-readable report for the terminal.
+pub fn format(p: &Provenance) -> String {
+    let c = &p.consolidated;
+    let mut out = format!(
+        "Consolidated #{} ({}, confidence {:.2}, created {}, updated {}):\n  {}\n",
+        c.id, c.r#type, c.confidence, c.created_at, c.updated_at, c.content
+    );
+    if !p.derived_from.is_empty() {
+        out.push_str("\nDerived from:\n");
+        for d in &p.derived_from {
+            out.push_str(&format!("  consolidated #{} ({}): {}\n", d.id, d.r#type, first_line(&d.content)));
+        }
+    }
+    out.push_str(&format!("\nSources ({}):\n", p.sources.len()));
+    for s in &p.sources {
+        let m = &s.memory;
+        let session = m.session_id.as_deref().map(|s| s.get(..8).unwrap_or(s)).unwrap_or("-");
+        let archived = s.archived_at.as_deref().map(|a| format!(", archived {}", a)).unwrap_or_default();
+        out.push_str(&format!("  #{} [{}] {} session {}{}\n", m.id, m.r#type, m.created_at, session, archived));
+        out.push_str(&format!("      {}\n", first_line(&m.content)));
+    }
+    if !p.missing.is_empty() {
+        let ids: Vec<String> = p.missing.iter().map(|id| format!("#{}", id)).collect();
+        out.push_str(&format!("\nNo longer stored: {}\n", ids.join(", ")));
+    }
+    if let Some(r) = &p.reasoning {
+        out.push_str(&format!("\nReasoning:\n{}\n", r));
+    }
+    out
+}
+
+fn first_line(content: &str) -> &str {
+    content.lines().next().unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn traces_live_archived_and_missing_sources() {
+        let raw = db::open_raw_db(Path::new(":memory:")).unwrap();
+        let cons = db::open_consolidated_db(Path::new(":memory:")).unwrap();
+        let a = db::save_memory(&raw, "Flaky test traced to shared tmp dir", "bugfix", "session-a").unwrap();
+        let b = db::save_memory(&raw, "Tests now use tempfile per case", "decision", "session-b").unwrap();
+        db::mark_consolidated(&raw, &[a, b]).unwrap();
+        let c = db::insert_consolidated(&cons, "Give each test its own temp dir", "pattern", &[a, b, 99], 0.8).unwrap();
+        let insight = db::insert_consolidated(&cons, "Test isolation matters here", "insight", &[c, 77], 0.6).unwrap();
+
+        // Decay archives a
+        raw.execute("UPDATE memories SET importance = 0, accessed_at = datetime('now', '-400 days') WHERE id = ?1", [a])
+            .unwrap();
+        let dir = std::env::temp_dir().join(format!("cortex-why-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        crate::sleep::micro_sleep(&raw, &Config::default(), &dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let p = trace(&raw, &cons, c).unwrap();
+        let ids: Vec<(i64, bool)> = p.sources.iter().map(|s| (s.memory.id, s.archived_at.is_some())).collect();
+        assert_eq!(ids, vec![(a, true), (b, false)]);
+        assert_eq!(p.missing, vec![99]);
+        assert!(format(&p).contains("session session-"));
+
+        let p = trace(&raw, &cons, insight).unwrap();
+        assert_eq!(p.derived_from.len(), 1);
+        assert_eq!(p.sources.len(), 2);
+        assert_eq!(p.missing, vec![77, 99]);
+        assert!(trace(&raw, &cons, 500).is_err());
+    }
+}